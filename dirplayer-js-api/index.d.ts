@@ -12,6 +12,46 @@ type JsBridgeBreakpoint = {
   script_name: string,
   handler_name: string,
   bytecode_index: number,
+  condition: string | null,
+}
+
+type JsBridgeWatch = {
+  expression: string,
+  datum_ref?: DatumRef,
+  error?: string,
+}
+
+type JsBridgeHandlerCoverage = {
+  handler: string,
+  bytecodeCount: number,
+  executedBytecodeCount: number,
+  executedRanges: [number, number][],
+}
+
+type JsBridgeScriptCoverage = {
+  script: string,
+  castLib: number,
+  castMember: number,
+  handlerCount: number,
+  executedHandlerCount: number,
+  bytecodeCount: number,
+  executedBytecodeCount: number,
+  handlers: JsBridgeHandlerCoverage[],
+}
+
+type JsBridgePropertyDescription = {
+  name: string,
+  format?: string,
+  comment?: string,
+  default?: string,
+  range?: string,
+  value?: string,
+}
+
+type JsBridgeBehaviorDescription = {
+  scriptInstance: ScriptInstanceId,
+  scriptMemberRef: ICastMemberRef,
+  properties: JsBridgePropertyDescription[],
 }
 
 type JsBridgeChunk = {
@@ -19,6 +59,12 @@ type JsBridgeChunk = {
   fourcc: string,
 }
 
+type FrameDigest = {
+  frame: number,
+  label: string | null,
+  changedGlobals: Record<string, string>,
+}
+
 type TVmCallbacks = {
   onMovieLoaded: Function,
   onMovieChunkListChanged: (chunks: Partial<Record<number, JsBridgeChunk>>) => void,
@@ -31,6 +77,9 @@ type TVmCallbacks = {
   onScriptError: (data: OnScriptErrorData) => void,
   onScopeListChanged: Function,
   onBreakpointListChanged: (data: JsBridgeBreakpoint[]) => void,
+  onWatchListChanged: (data: JsBridgeWatch[]) => void,
+  onCoverageSummaryChanged: (scripts: JsBridgeScriptCoverage[]) => void,
+  onBehaviorDescriptionsChanged: (spriteNum: number, behaviors: JsBridgeBehaviorDescription[]) => void,
   onScriptErrorCleared: Function,
   onGlobalListChanged: (globals: Map<string, JsBridgeDatum>) => void,
   onDebugMessage: (message: string) => void,
@@ -41,6 +90,17 @@ type TVmCallbacks = {
   onScriptInstanceSnapshot: (scriptInstanceRef: ScriptInstanceId, scriptInstance: JsBridgeDatum) => void,
   onChannelChanged: (channelNumber: number, channelData: ScoreSpriteSnapshot) => void,
   onChannelDisplayNameChanged: (channelNumber: number, displayName: string) => void,
+  onGoToNetPage: (url: string, target: string) => void,
+  onExternalEvent: (event: string) => void,
+  onFrameDigest: (digest: FrameDigest) => void,
+  onWindowOpened: (name: string, title: string, width: number, height: number) => void,
+  onWindowClosed: (name: string) => void,
+  onSoundChannelChanged: (channel: number, volume: number, pan: number, rampMs: number) => void,
+  onSoundMasterVolumeChanged: (gain: number) => void,
+  onSoundPlay: (channel: number, samples: Uint8Array, sampleRate: number, channelCount: number, bitsPerSample: number, offsetMs: number, playCount: number) => void,
+  onSoundStop: (channel: number) => void,
+  onSoundBreakLoop: (channel: number) => void,
+  onStageCursorChanged: (cursor: string) => void,
 }
 declare let vmCallbacks: TVmCallbacks | undefined;
 
@@ -47,6 +47,18 @@ export function onBreakpointListChanged(breakpointList) {
   vmCallbacks.onBreakpointListChanged(breakpointList)
 }
 
+export function onWatchListChanged(watchList) {
+  vmCallbacks.onWatchListChanged(watchList)
+}
+
+export function onCoverageSummaryChanged(scripts) {
+  vmCallbacks.onCoverageSummaryChanged(scripts)
+}
+
+export function onBehaviorDescriptionsChanged(spriteNum, behaviors) {
+  vmCallbacks.onBehaviorDescriptionsChanged(spriteNum, behaviors)
+}
+
 export function onScriptErrorCleared() {
   vmCallbacks.onScriptErrorCleared()
 }
@@ -79,6 +91,50 @@ export function onScriptInstanceSnapshot(instanceId, snapshot) {
   vmCallbacks.onScriptInstanceSnapshot(instanceId, snapshot)
 }
 
+export function onGoToNetPage(url, target) {
+  vmCallbacks.onGoToNetPage(url, target)
+}
+
+export function onExternalEvent(event) {
+  vmCallbacks.onExternalEvent(event)
+}
+
+export function onFrameDigest(digest) {
+  vmCallbacks.onFrameDigest(digest)
+}
+
+export function onWindowOpened(name, title, width, height) {
+  vmCallbacks.onWindowOpened(name, title, width, height)
+}
+
+export function onWindowClosed(name) {
+  vmCallbacks.onWindowClosed(name)
+}
+
+export function onSoundChannelChanged(channel, volume, pan, rampMs) {
+  vmCallbacks.onSoundChannelChanged(channel, volume, pan, rampMs)
+}
+
+export function onSoundMasterVolumeChanged(gain) {
+  vmCallbacks.onSoundMasterVolumeChanged(gain)
+}
+
+export function onSoundPlay(channel, samples, sampleRate, channelCount, bitsPerSample, offsetMs, playCount) {
+  vmCallbacks.onSoundPlay(channel, samples, sampleRate, channelCount, bitsPerSample, offsetMs, playCount)
+}
+
+export function onSoundStop(channel) {
+  vmCallbacks.onSoundStop(channel)
+}
+
+export function onSoundBreakLoop(channel) {
+  vmCallbacks.onSoundBreakLoop(channel)
+}
+
+export function onStageCursorChanged(cursor) {
+  vmCallbacks.onStageCursorChanged(cursor)
+}
+
 export function onChannelChanged(channel, value) {
   vmCallbacks.onChannelChanged(channel, value)
 }
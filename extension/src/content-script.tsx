@@ -1,7 +1,7 @@
 import React from 'react';
 import ReactDOM from 'react-dom/client';
 import init, { set_system_font_path } from 'vm-rust'
-import { getCaseInsensitiveValue } from './utils';
+import { getCaseInsensitiveValue, parseKeyCapture } from './utils';
 
 import EmbedPlayer from '../../src/components/EmbedPlayer';
 import { initVmCallbacks } from '../../src/vm/callbacks';
@@ -75,6 +75,7 @@ function replaceDirEmbed(element: HTMLEmbedElement) {
   }
 
   console.log('External params:', externalParams);
+  const keyCapture = parseKeyCapture(element.getAttribute('keycapture'));
   
   const newElement = document.createElement('div');
   element.replaceWith(newElement);
@@ -85,7 +86,7 @@ function replaceDirEmbed(element: HTMLEmbedElement) {
   root.render(
     <React.StrictMode>
       <StoreProvider store={store}>
-        <EmbedPlayer width={width} height={height} src={src} externalParams={externalParams} />
+        <EmbedPlayer width={width} height={height} src={src} externalParams={externalParams} keyCapture={keyCapture} />
       </StoreProvider>
     </React.StrictMode>
   );
@@ -109,6 +110,7 @@ function replaceDirObject(element: HTMLObjectElement, params: Partial<Record<str
 
   console.log('Params:', params);
   console.log('External params:', externalParams);
+  const keyCapture = parseKeyCapture(getCaseInsensitiveValue(params, 'keycapture'));
   
   const newElement = document.createElement('div');
   element.replaceWith(newElement);
@@ -119,7 +121,7 @@ function replaceDirObject(element: HTMLObjectElement, params: Partial<Record<str
   root.render(
     <React.StrictMode>
       <StoreProvider store={store}>
-        <EmbedPlayer width={width} height={height} src={src} externalParams={externalParams} />
+        <EmbedPlayer width={width} height={height} src={src} externalParams={externalParams} keyCapture={keyCapture} />
       </StoreProvider>
     </React.StrictMode>
   );
@@ -6,3 +6,20 @@ export function getCaseInsensitiveValue(obj: Record<string, any>, key: string):
   }
   return undefined;
 }
+
+/**
+ * Parses a `keycapture` embed parameter such as `shortcuts,-space,ctrl+s`. Names are
+ * captured unless prefixed with `-`, in which case they're left to the browser.
+ */
+export function parseKeyCapture(value: string | null | undefined): Record<string, boolean> {
+  const keyCapture: Record<string, boolean> = {};
+  for (const entry of (value || '').split(',')) {
+    const name = entry.trim();
+    if (name.startsWith('-')) {
+      keyCapture[name.substring(1)] = false;
+    } else if (name) {
+      keyCapture[name] = true;
+    }
+  }
+  return keyCapture;
+}
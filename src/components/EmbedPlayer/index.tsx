@@ -1,22 +1,82 @@
-import { useEffect, useMemo } from 'react';
+import { useEffect, useMemo, useState } from 'react';
 import { RootState } from '../../store';
 import { useSelector } from 'react-redux'
-import { load_movie_file, play, set_base_path, set_external_params } from 'vm-rust';
+import { add_net_rewrite_rule, add_search_path, load_movie_file, play, set_base_path, set_external_params, set_key_capture, set_key_combination_captured } from 'vm-rust';
 import { getFullPathFromOrigin, getBasePath } from '../../utils/path';
-import Stage from '../../views/Stage';
+import Stage, { StageScaleMode } from '../../views/Stage';
+import { createVmCallbacks } from '../../vm/callbacks';
+import { isWorkerModeSupported, VmWorkerClient } from '../../vm/workerClient';
 
 type EmbedPlayerProps = {
   width: string
   height: string
   src: string
   externalParams?: Record<string, string>
+  /** Runs the player in a Web Worker when the browser supports OffscreenCanvas. */
+  useWorker?: boolean
+  /** Maps `host` or `host:port` to the address actually used, e.g. a `wss://` proxy for a Multiuser server. */
+  netRewriteRules?: Record<string, string>
+  /** Folders where external casts and files are looked for, like `the searchPath`, e.g. `@:casts`. */
+  searchPaths?: string[]
+  /**
+   * Whether key presses are kept from the browser, by category (`navigation`, `space`,
+   * `shortcuts`, `functionKeys`) or by combination such as `ctrl+s`.
+   */
+  keyCapture?: Record<string, boolean>
+  /** How the movie is fitted into the player, `none` by default. */
+  scaleMode?: StageScaleMode
 };
 
-export default function EmbedPlayer({width, height, src, externalParams}: EmbedPlayerProps) {
-  const isVmReady = useSelector<RootState>(state => state.vm.isReady);
+const KEY_CAPTURE_CATEGORIES = ['navigation', 'space', 'shortcuts', 'functionKeys'];
+
+export default function EmbedPlayer({width, height, src, externalParams, useWorker, netRewriteRules, searchPaths, keyCapture, scaleMode}: EmbedPlayerProps) {
+  const isWorkerMode = !!useWorker && isWorkerModeSupported();
+  const isLocalVmReady = useSelector<RootState>(state => state.vm.isReady);
+  const [workerClient, setWorkerClient] = useState<VmWorkerClient>();
+  const isVmReady = isWorkerMode ? !!workerClient : !!isLocalVmReady;
+
+  useEffect(() => {
+    if (!isWorkerMode) {
+      return;
+    }
+    const client = new VmWorkerClient(createVmCallbacks(), getFullPathFromOrigin('charmap-system.png'));
+    client.ready.then(() => setWorkerClient(client));
+    return () => client.terminate();
+  }, [isWorkerMode]);
+
+  useEffect(() => {
+    // Key capture is decided on the page, so it's set here even when the player runs in a worker
+    if (!isLocalVmReady) {
+      return;
+    }
+    Object.entries(keyCapture || {}).forEach(([key, captured]) => {
+      if (KEY_CAPTURE_CATEGORIES.includes(key)) {
+        set_key_capture(key, captured);
+      } else {
+        set_key_combination_captured(key, captured);
+      }
+    });
+  }, [isLocalVmReady, keyCapture]);
+
   useEffect(() => {
     async function loadMovie() {
       const fullPath = getFullPathFromOrigin(src);
+      const rewriteRules = Object.entries(netRewriteRules || {});
+      if (workerClient) {
+        for (const [from, to] of rewriteRules) {
+          await workerClient.call('add_net_rewrite_rule', from, to);
+        }
+        for (const path of searchPaths || []) {
+          await workerClient.call('add_search_path', path);
+        }
+        await workerClient.call('set_base_path', getBasePath(fullPath));
+        await workerClient.call('set_external_params', externalParams || {});
+        await workerClient.call('load_movie_file', fullPath);
+        await workerClient.call('play');
+        return;
+      }
+      rewriteRules.forEach(([from, to]) => add_net_rewrite_rule(from, to));
+      (searchPaths || []).forEach(path => add_search_path(path));
       set_base_path(getBasePath(fullPath));
       set_external_params(externalParams || {});
       await load_movie_file(fullPath);
@@ -25,7 +85,7 @@ export default function EmbedPlayer({width, height, src, externalParams}: EmbedP
     if (isVmReady) {
       loadMovie().catch(e => console.error('Failed to load movie', e))
     }
-  }, [isVmReady]) // TODO: Update player when src/params change
+  }, [isVmReady, workerClient]) // TODO: Update player when src/params change
 
   const [widthValue, heightValue] = useMemo(() => {
     const widthInt = parseInt(width);
@@ -37,6 +97,6 @@ export default function EmbedPlayer({width, height, src, externalParams}: EmbedP
     }
   }, [width, height]);
   return <div style={{width: widthValue, height: heightValue}}>
-    {!!isVmReady && <Stage />}
+    {isVmReady && <Stage workerClient={workerClient} scaleMode={scaleMode} />}
   </div>
 }
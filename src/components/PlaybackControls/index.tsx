@@ -1,13 +1,60 @@
 import { FontAwesomeIcon } from '@fortawesome/react-fontawesome'
-import { faPlay, faStop, faRotateBack } from '@fortawesome/free-solid-svg-icons'
+import { faPlay, faStop, faRotateBack, faCircleDot, faFileExport, faSitemap, faStopwatch, faFire } from '@fortawesome/free-solid-svg-icons'
+import { useState } from 'react'
 import IconButton from '../IconButton'
 import styles from './styles.module.css'
-import { play, stop, reset } from 'vm-rust'
+import { play, stop, reset, set_coverage_enabled, get_coverage_report, dump_movie_structure, set_profiling_enabled, get_profile_json } from 'vm-rust'
+
+function downloadText(text: string, type: string, fileName: string) {
+  const url = URL.createObjectURL(new Blob([text], { type }));
+  const link = document.createElement('a');
+  link.href = url;
+  link.download = fileName;
+  link.click();
+  URL.revokeObjectURL(url);
+}
+
+function downloadCoverageReport() {
+  const report = get_coverage_report();
+  if (report) {
+    downloadText(report, 'application/json', 'coverage.json');
+  }
+}
+
+function downloadProfile() {
+  const profile = get_profile_json();
+  if (profile) {
+    downloadText(profile, 'application/json', 'profile.json');
+  }
+}
+
+function downloadMovieStructure() {
+  const dump = dump_movie_structure();
+  if (dump) {
+    downloadText(dump, 'text/plain', 'movie-structure.txt');
+  }
+}
 
 export default function PlaybackControls() {
+  const [isRecordingCoverage, setIsRecordingCoverage] = useState(false);
+  const toggleCoverage = () => {
+    set_coverage_enabled(!isRecordingCoverage);
+    setIsRecordingCoverage(!isRecordingCoverage);
+  };
+  const [isProfiling, setIsProfiling] = useState(false);
+  const toggleProfiling = () => {
+    set_profiling_enabled(!isProfiling);
+    setIsProfiling(!isProfiling);
+  };
+
   return <div className={styles.container}>
     <IconButton icon={faPlay} onClick={() => { play() }} />
     <IconButton icon={faStop} onClick={() => { stop() }} />
     <IconButton icon={faRotateBack} onClick={() => { reset() }} />
+    <IconButton icon={faCircleDot} onClick={toggleCoverage} />
+    {isRecordingCoverage && <IconButton icon={faFileExport} onClick={downloadCoverageReport} />}
+    <IconButton icon={faStopwatch} onClick={toggleProfiling} />
+    {isProfiling && <IconButton icon={faFire} onClick={downloadProfile} />}
+    <IconButton icon={faSitemap} onClick={downloadMovieStructure} />
   </div>
 }
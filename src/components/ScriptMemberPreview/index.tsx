@@ -3,9 +3,9 @@ import { ICastMemberIdentifier, IScriptMemberSnapshot, MemberSnapshot } from "..
 import styles from "./styles.module.css";
 import { useAppSelector } from "../../store/hooks";
 import { selectBreakpoints } from "../../store/vmSlice";
-import { toggle_breakpoint } from "vm-rust";
+import { set_breakpoint_condition, toggle_breakpoint } from "vm-rust";
 import { useState } from "react";
-import { ICastMemberRef } from "dirplayer-js-api";
+import { ICastMemberRef, JsBridgeBreakpoint } from "dirplayer-js-api";
 
 interface IScriptMemberPreviewProps {
   memberId: ICastMemberIdentifier,
@@ -19,14 +19,16 @@ type BytecodeLineProps = {
   isHighlighted: boolean;
   isInBackground: boolean;
   text: string;
-  hasBreakpoint: boolean;
+  breakpoint?: JsBridgeBreakpoint;
   onBreakpointClick: () => void;
+  onBreakpointConditionClick: () => void;
 };
 function BytecodeLine({
   text,
   isHighlighted,
   onBreakpointClick,
-  hasBreakpoint,
+  onBreakpointConditionClick,
+  breakpoint,
   isInBackground,
 }: BytecodeLineProps) {
   return (
@@ -34,9 +36,14 @@ function BytecodeLine({
       <button
         className={classNames(
           styles.breakpointColumn,
-          hasBreakpoint && styles.hasBreakpoint
+          breakpoint && (breakpoint.condition ? styles.hasConditionalBreakpoint : styles.hasBreakpoint)
         )}
+        title={breakpoint?.condition ?? undefined}
         onClick={onBreakpointClick}
+        onContextMenu={(e) => {
+          e.preventDefault();
+          onBreakpointConditionClick();
+        }}
       ></button>
       <p
         className={classNames([
@@ -62,6 +69,7 @@ export default function ScriptMemberPreview({
   const [expandedHandlerNames, setExpandedHandlerNames] = useState<string[]>(
     []
   );
+  const [showBytecode, setShowBytecode] = useState(false);
 
   const onToggleHandler = (handlerName: string) => {
     if (expandedHandlerNames.includes(handlerName)) {
@@ -73,8 +81,35 @@ export default function ScriptMemberPreview({
     }
   };
 
+  const findBreakpoint = (handlerName: string, start: number, end: number) =>
+    breakpoints.find(
+      (bp) =>
+        bp.script_name === snapshot.name &&
+        bp.handler_name === handlerName &&
+        bp.bytecode_index >= start &&
+        bp.bytecode_index < end
+    );
+
+  const editBreakpointCondition = (breakpoint: JsBridgeBreakpoint | undefined) => {
+    if (!breakpoint) {
+      return;
+    }
+    const condition = window.prompt("Pause only when", breakpoint.condition ?? "");
+    if (condition !== null) {
+      set_breakpoint_condition(breakpoint.script_name, breakpoint.handler_name, breakpoint.bytecode_index, condition);
+    }
+  };
+
   return (
     <div className={styles.scriptContainer}>
+      <button className={styles.viewToggle} onClick={() => setShowBytecode(!showBytecode)}>
+        {showBytecode ? "Show Lingo" : "Show bytecode"}
+      </button>
+      {snapshot.script.reconstructed && (
+        <p className={styles.reconstructedNotice}>
+          Decompiled from bytecode, the source isn't stored in this movie
+        </p>
+      )}
       {snapshot.script.handlers.map((handler) => {
         const isExpanded = expandedHandlerNames.includes(handler.name);
         const isHandlerHighlighted = highlightedHandlerName === handler.name;
@@ -94,9 +129,34 @@ export default function ScriptMemberPreview({
               on {handler.name} {handler.args.join(", ")}
             </button>
             {isExpanded &&
+              !showBytecode &&
+              handler.lines.map((line, i) => {
+                // Breakpoints on a line pause at its first bytecode
+                const breakpoint = findBreakpoint(handler.name, line.bytecode_start, line.bytecode_end);
+                const isInLine = (index: number) => index >= line.bytecode_start && index < line.bytecode_end;
+                return (
+                  <BytecodeLine
+                    breakpoint={breakpoint}
+                    text={"  ".repeat(line.indent + 1) + line.text}
+                    key={i}
+                    isHighlighted={
+                      isHandlerHighlighted && highlightedBytecodeIndex !== undefined && isInLine(highlightedBytecodeIndex)
+                    }
+                    isInBackground={backgroundScopes.some(([name, idx, scriptMemRef]) => name === handler.name && isInLine(idx) && memberId.castNumber === scriptMemRef[0] && memberId.memberNumber === scriptMemRef[1])}
+                    onBreakpointClick={() => {
+                      if (line.bytecode_start < line.bytecode_end) {
+                        toggle_breakpoint(snapshot.name, handler.name, breakpoint?.bytecode_index ?? line.bytecode_start);
+                      }
+                    }}
+                    onBreakpointConditionClick={() => editBreakpointCondition(breakpoint)}
+                  />
+                );
+              })}
+            {isExpanded &&
+              showBytecode &&
               handler.bytecode.map((bytecode, i) => (
                 <BytecodeLine
-                  hasBreakpoint={breakpoints.some(
+                  breakpoint={breakpoints.find(
                     (bp) =>
                       bp.script_name === snapshot.name &&
                       bp.handler_name === handler.name &&
@@ -111,6 +171,7 @@ export default function ScriptMemberPreview({
                   onBreakpointClick={() =>
                     toggle_breakpoint(snapshot.name, handler.name, i)
                   }
+                  onBreakpointConditionClick={() => editBreakpointCondition(findBreakpoint(handler.name, i, i + 1))}
                 />
               ))}
             {isExpanded && <p className={styles.handlerName}>end</p>}
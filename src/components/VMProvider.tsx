@@ -8,7 +8,7 @@ import {
 import init from "vm-rust";
 import { initVmCallbacks } from "../vm/callbacks";
 import { JsBridgeBreakpoint } from "dirplayer-js-api";
-import { add_breakpoint, set_system_font_path } from 'vm-rust'
+import { add_breakpoint, set_breakpoint_condition, set_system_font_path } from 'vm-rust'
 import { getFullPathFromOrigin } from "../utils/path";
 
 interface VMProviderProps {
@@ -60,6 +60,9 @@ export default function VMProvider({ children }: VMProviderProps) {
           const breakpoints: JsBridgeBreakpoint[] = JSON.parse(savedBreakpoints);
           for (const bp of breakpoints) {
             add_breakpoint(bp.script_name, bp.handler_name, bp.bytecode_index);
+            if (bp.condition) {
+              set_breakpoint_condition(bp.script_name, bp.handler_name, bp.bytecode_index, bp.condition);
+            }
           }
         }
       });
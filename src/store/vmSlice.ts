@@ -1,6 +1,6 @@
 import { PayloadAction, createSlice } from "@reduxjs/toolkit";
 import { CastSnapshot, DatumRef, ICastMemberIdentifier, IVMScope, JsBridgeDatum, MemberSnapshot, ScoreSnapshot, ScoreSpriteSnapshot, ScriptInstanceId } from "../vm";
-import { ICastMemberRef, JsBridgeBreakpoint, JsBridgeChunk } from "dirplayer-js-api";
+import { ICastMemberRef, JsBridgeBehaviorDescription, JsBridgeBreakpoint, JsBridgeChunk, JsBridgeScriptCoverage, JsBridgeWatch } from "dirplayer-js-api";
 
 export type TMemberSubscription = {
   memberRef: ICastMemberIdentifier,
@@ -16,6 +16,10 @@ interface VMSliceState {
   scopes: IVMScope[],
   scriptError?: string
   breakpoints: JsBridgeBreakpoint[],
+  watches: JsBridgeWatch[],
+  coverageSummary: JsBridgeScriptCoverage[],
+  behaviorDescriptions: Record<number, JsBridgeBehaviorDescription[]>,
+  consoleLines: string[],
   globals: Record<string, DatumRef>,
   timeoutHandles: Record<string, NodeJS.Timer>,
   datumSnapshots: Record<DatumRef, JsBridgeDatum>,
@@ -26,6 +30,8 @@ interface VMSliceState {
   movieChunkList: Partial<Record<number, JsBridgeChunk>>,
 }
 
+const MAX_CONSOLE_LINES = 500;
+
 const initialState: VMSliceState = {
   isReady: false,
   castNames: [],
@@ -33,6 +39,10 @@ const initialState: VMSliceState = {
   currentFrame: 1,
   scopes: [],
   breakpoints: [],
+  watches: [],
+  coverageSummary: [],
+  behaviorDescriptions: {},
+  consoleLines: [],
   globals: {},
   timeoutHandles: {},
   datumSnapshots: {},
@@ -152,6 +162,33 @@ const vmSlice = createSlice({
         breakpoints: action.payload,
       }
     },
+    watchListChanged: (state, action: PayloadAction<JsBridgeWatch[]>) => {
+      return {
+        ...state,
+        watches: action.payload,
+      }
+    },
+    coverageSummaryChanged: (state, action: PayloadAction<JsBridgeScriptCoverage[]>) => {
+      return {
+        ...state,
+        coverageSummary: action.payload,
+      }
+    },
+    behaviorDescriptionsChanged: (state, action: PayloadAction<{ spriteNum: number, behaviors: JsBridgeBehaviorDescription[] }>) => {
+      return {
+        ...state,
+        behaviorDescriptions: {
+          ...state.behaviorDescriptions,
+          [action.payload.spriteNum]: action.payload.behaviors,
+        },
+      }
+    },
+    consoleLineAdded: (state, action: PayloadAction<string>) => {
+      return {
+        ...state,
+        consoleLines: [...state.consoleLines, action.payload].slice(-MAX_CONSOLE_LINES),
+      }
+    },
     globalsChanged: (state, action: PayloadAction<Record<string, DatumRef>>) => {
       return {
         ...state,
@@ -245,7 +282,11 @@ export const selectCurrentScope = (state: VMSliceState) => state.scopes.at(state
 export const selectScriptError = (state: VMSliceState) => state.scriptError
 export const selectBreakpoints = (state: VMSliceState, scriptName?: string) => state.breakpoints.filter(b => !scriptName || b.script_name === scriptName)
 export const selectGlobals = (state: VMSliceState) => state.globals
+export const selectWatches = (state: VMSliceState) => state.watches
+export const selectConsoleLines = (state: VMSliceState) => state.consoleLines
+export const selectCoverageSummary = (state: VMSliceState) => state.coverageSummary
+export const selectBehaviorDescriptions = (state: VMSliceState, spriteNum?: number) => spriteNum !== undefined ? state.behaviorDescriptions[spriteNum] : undefined
 
 // Action creators are generated for each case reducer function
-export const { ready, castListChanged, castLibNameChanged, castMemberListChanged, scoreChanged, frameChanged, scopeListChanged, onScriptError, breakpointListChanged, scriptErrorCleared, globalsChanged, setTimeoutHandle, removeTimeoutHandle, datumSnapshot, scriptInstanceSnapshot, channelChanged, memberSubscribed, memberUnsubscribed, castMemberChanged, channelDisplayNameChanged, movieLoaded, movieChunkListChanged } = vmSlice.actions
+export const { ready, castListChanged, castLibNameChanged, castMemberListChanged, scoreChanged, frameChanged, scopeListChanged, onScriptError, breakpointListChanged, watchListChanged, coverageSummaryChanged, behaviorDescriptionsChanged, consoleLineAdded, scriptErrorCleared, globalsChanged, setTimeoutHandle, removeTimeoutHandle, datumSnapshot, scriptInstanceSnapshot, channelChanged, memberSubscribed, memberUnsubscribed, castMemberChanged, channelDisplayNameChanged, movieLoaded, movieChunkListChanged } = vmSlice.actions
 export default vmSlice.reducer
@@ -1,13 +1,20 @@
 import { useEffect, useState } from "react";
 import { useAppDispatch, useAppSelector } from "../../store/hooks";
-import { selectGlobals, selectScopes } from "../../store/vmSlice";
+import { consoleLineAdded, selectConsoleLines, selectCoverageSummary, selectGlobals, selectScopes, selectWatches } from "../../store/vmSlice";
 import styles from "./styles.module.css";
 import IconButton from "../../components/IconButton";
-import { faPlay, faWarning } from "@fortawesome/free-solid-svg-icons";
+import { faPlay, faRotateRight, faStepBackward, faStepForward, faWarning } from "@fortawesome/free-solid-svg-icons";
 import {
   resume_breakpoint,
+  add_watch_expression,
+  eval_lingo,
+  remove_watch_expression,
+  request_coverage_summary,
+  step_bytecode,
+  step_back_bytecode,
   request_datum,
   request_script_instance_snapshot,
+  set_global,
   trigger_alert_hook,
 } from "vm-rust";
 import TabView from "../../components/TabView";
@@ -157,6 +164,13 @@ export default function DebugInspector({}: DebugInspectorProps) {
   const selectedScope =
     selectedScopeIndex !== undefined ? scopes[selectedScopeIndex] : undefined;
   const globals = useAppSelector((state) => selectGlobals(state.vm));
+  const watches = useAppSelector((state) => selectWatches(state.vm));
+  const [newWatchExpression, setNewWatchExpression] = useState("");
+  const consoleLines = useAppSelector((state) => selectConsoleLines(state.vm));
+  const [consoleInput, setConsoleInput] = useState("");
+  const [globalAssignment, setGlobalAssignment] = useState("");
+  const [globalError, setGlobalError] = useState<string>();
+  const coverageSummary = useAppSelector((state) => selectCoverageSummary(state.vm));
 
   const onSelectScope = (index: number) => {
     setSelectedScopeIndex(index);
@@ -173,6 +187,18 @@ export default function DebugInspector({}: DebugInspectorProps) {
             resume_breakpoint();
           }}
         />
+        <IconButton
+          icon={faStepBackward}
+          onClick={() => {
+            step_back_bytecode();
+          }}
+        />
+        <IconButton
+          icon={faStepForward}
+          onClick={() => {
+            step_bytecode();
+          }}
+        />
         <IconButton
           icon={faWarning}
           onClick={() => {
@@ -263,6 +289,123 @@ export default function DebugInspector({}: DebugInspectorProps) {
               )}
             />
           </ListView>
+          <form
+            onSubmit={async (e) => {
+              e.preventDefault();
+              const match = globalAssignment.match(/^\s*(\w+)\s*=(.*)$/);
+              if (!match) {
+                return;
+              }
+              try {
+                await set_global(match[1], match[2].trim());
+                setGlobalAssignment("");
+                setGlobalError(undefined);
+              } catch (err) {
+                setGlobalError(String(err));
+              }
+            }}
+          >
+            <input
+              type="text"
+              placeholder="name = value"
+              value={globalAssignment}
+              onChange={(e) => setGlobalAssignment(e.target.value)}
+            />
+            {globalError && <div>{globalError}</div>}
+          </form>
+        </TabView.Tab>
+        <TabView.Tab tabKey="watches" title="Watches">
+          <ListView>
+            {watches.map((watch) => (
+              <div
+                key={watch.expression}
+                onContextMenu={(e) => {
+                  e.preventDefault();
+                  remove_watch_expression(watch.expression);
+                }}
+              >
+                {watch.datum_ref !== undefined ? (
+                  <DatumDebugListItems
+                    label={watch.expression}
+                    datumRef={{ type: "datum", datumRef: watch.datum_ref }}
+                  />
+                ) : (
+                  <ListView.Item>
+                    {watch.expression}: {watch.error}
+                  </ListView.Item>
+                )}
+              </div>
+            ))}
+          </ListView>
+          <form
+            onSubmit={(e) => {
+              e.preventDefault();
+              if (newWatchExpression.trim()) {
+                add_watch_expression(newWatchExpression.trim());
+                setNewWatchExpression("");
+              }
+            }}
+          >
+            <input
+              type="text"
+              placeholder="Add watch expression"
+              value={newWatchExpression}
+              onChange={(e) => setNewWatchExpression(e.target.value)}
+            />
+          </form>
+        </TabView.Tab>
+        <TabView.Tab tabKey="message" title="Message">
+          <ListView>
+            {consoleLines.map((line, i) => (
+              <ListView.Item key={i}>{line}</ListView.Item>
+            ))}
+          </ListView>
+          <form
+            onSubmit={async (e) => {
+              e.preventDefault();
+              const source = consoleInput.trim();
+              if (!source) {
+                return;
+              }
+              setConsoleInput("");
+              dispatch(consoleLineAdded(source));
+              const output = await eval_lingo(source);
+              // put already printed its value, unless it failed
+              if (output && (output.startsWith("Script error:") || !/^put\b/i.test(source))) {
+                dispatch(consoleLineAdded(`-- ${output}`));
+              }
+            }}
+          >
+            <input
+              type="text"
+              placeholder="Lingo"
+              value={consoleInput}
+              onChange={(e) => setConsoleInput(e.target.value)}
+            />
+          </form>
+        </TabView.Tab>
+        <TabView.Tab tabKey="coverage" title="Coverage">
+          <IconButton
+            icon={faRotateRight}
+            onClick={() => {
+              request_coverage_summary();
+            }}
+          />
+          <ListView>
+            {coverageSummary.map((script) => (
+              <ListView.Item
+                key={script.castLib + "-" + script.castMember}
+                onClick={() => dispatch(onMemberSelected([script.castLib, script.castMember]))}
+              >
+                {script.script || `member ${script.castMember} of castLib ${script.castLib}`}:{" "}
+                {script.executedHandlerCount}/{script.handlerCount} handlers,{" "}
+                {script.bytecodeCount > 0
+                  ? Math.round((100 * script.executedBytecodeCount) / script.bytecodeCount)
+                  : 0}
+                % bytecode
+              </ListView.Item>
+            ))}
+          </ListView>
         </TabView.Tab>
       </TabView>
     </div>
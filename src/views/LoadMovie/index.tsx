@@ -1,6 +1,6 @@
 import { useCallback, useState } from 'react';
 import styles from './styles.module.css';
-import { load_movie_file, play, set_base_path } from 'vm-rust';
+import { load_movie_file, play, set_base_path, set_safe_mode, set_smooth_scaling } from 'vm-rust';
 import { useMountEffect } from '../../utils/hooks';
 import { isDebugSession } from '../../utils/debug';
 import { getBasePath, getFullPathFromOrigin } from '../../utils/path';
@@ -10,12 +10,16 @@ export default function LoadMovie() {
   const [movieUrl, setMovieUrl] = useState<string>(defaultMovieUrl || '');
   const [isLoading, setIsLoading] = useState<boolean>(false);
   const [autoPlay, setAutoPlay] = useState<boolean>(process.env.REACT_APP_MOVIE_AUTO_PLAY === 'true');
+  const [safeMode, setSafeMode] = useState<boolean>(false);
+  const [smoothScaling, setSmoothScaling] = useState<boolean>(false);
   const loadMovieFile = useCallback(async (fullPath: string) => {
     try {
       setIsLoading(true);
       set_base_path(getBasePath(fullPath));
+      set_safe_mode(safeMode);
+      set_smooth_scaling(smoothScaling);
       await load_movie_file(fullPath);
-      if (autoPlay) {
+      if (autoPlay && !safeMode) {
         play();
       }
     } catch (e) {
@@ -23,7 +27,7 @@ export default function LoadMovie() {
     } finally {
       setIsLoading(false);
     }
-  }, [autoPlay]);
+  }, [autoPlay, safeMode, smoothScaling]);
   const onLoadClick = useCallback(async () => {
     await loadMovieFile(movieUrl);
   }, [movieUrl, loadMovieFile]);
@@ -60,6 +64,30 @@ export default function LoadMovie() {
         />
         <label htmlFor="autoPlay">Auto-play</label>
       </div>
+      <div className={styles.checkboxContainer}>
+        <input 
+          type="checkbox" 
+          id="safeMode" 
+          name="safeMode" 
+          className={styles.checkbox} 
+          disabled={isLoading} 
+          checked={safeMode}
+          onChange={e => setSafeMode(e.currentTarget.checked)}
+        />
+        <label htmlFor="safeMode">Safe mode (don't run scripts)</label>
+      </div>
+      <div className={styles.checkboxContainer}>
+        <input 
+          type="checkbox" 
+          id="smoothScaling" 
+          name="smoothScaling" 
+          className={styles.checkbox} 
+          disabled={isLoading} 
+          checked={smoothScaling}
+          onChange={e => setSmoothScaling(e.currentTarget.checked)}
+        />
+        <label htmlFor="smoothScaling">Smooth stretched sprites</label>
+      </div>
       <div className={styles.divider}></div>
       <button className={styles.button} onClick={onLoadClick} disabled={isLoading}>Load</button>
     </div>
@@ -4,8 +4,53 @@ import styles from "./styles.module.css";
 import { TSelectedObject } from "../../store/uiSlice";
 import { useSelectedObjects } from "../../hooks/selection";
 import { useAppSelector } from "../../store/hooks";
+import { selectBehaviorDescriptions } from "../../store/vmSlice";
 import { JsBridgeChunk } from "dirplayer-js-api";
-import { ComponentProps } from "react";
+import { ComponentProps, useEffect, useState } from "react";
+import { request_behavior_descriptions, set_member_prop, set_sprite_prop } from "vm-rust";
+
+type PropertyEditorProps = {
+  onSubmit: (propName: string, value: string) => Promise<void>;
+};
+
+/** Sets a property through the player, with the value typed as a Lingo literal. */
+function PropertyEditor({ onSubmit }: PropertyEditorProps) {
+  const [propName, setPropName] = useState("");
+  const [value, setValue] = useState("");
+  const [error, setError] = useState<string>();
+  return (
+    <form
+      className={styles.editor}
+      onSubmit={async (e) => {
+        e.preventDefault();
+        if (!propName.trim()) {
+          return;
+        }
+        try {
+          await onSubmit(propName.trim(), value);
+          setError(undefined);
+        } catch (err) {
+          setError(String(err));
+        }
+      }}
+    >
+      <input
+        type="text"
+        placeholder="Property"
+        value={propName}
+        onChange={(e) => setPropName(e.target.value)}
+      />
+      <input
+        type="text"
+        placeholder='Value, e.g. 10 or "text"'
+        value={value}
+        onChange={(e) => setValue(e.target.value)}
+      />
+      <button type="submit">Set</button>
+      {error && <div className={styles.error}>{error}</div>}
+    </form>
+  );
+}
 
 interface PropertyInspectorProps {
   selectedObject?: TSelectedObject;
@@ -14,8 +59,15 @@ interface PropertyInspectorProps {
 export default function PropertyInspector({
   selectedObject,
 }: PropertyInspectorProps) {
-  const { scoreBehaviorRef, selectedSprite, member } = useSelectedObjects();
+  const { scoreBehaviorRef, selectedSprite, member, memberRef } = useSelectedObjects();
   const movieChunks = useAppSelector((state) => state.vm.movieChunkList);
+  const selectedSpriteNumber = selectedObject?.type === "sprite" ? selectedObject.spriteNumber : undefined;
+  const behaviorDescriptions = useAppSelector((state) => selectBehaviorDescriptions(state.vm, selectedSpriteNumber));
+  useEffect(() => {
+    if (selectedSpriteNumber !== undefined) {
+      request_behavior_descriptions(selectedSpriteNumber);
+    }
+  }, [selectedSpriteNumber]);
   const getChunkItemString: ComponentProps<typeof JSONTree>['getItemString'] = (type, data, itemType, itemString, keyPath) => {
     let chunk = data as JsBridgeChunk;
     return <span>{chunk.fourcc}</span>;
@@ -50,11 +102,28 @@ export default function PropertyInspector({
         {selectedObject?.type === "sprite" && (
           <TabView.Tab tabKey="sprite" title="Sprite">
             <JSONTree keyPath={["sprite"]} data={{ ...selectedSprite }} />
+            <PropertyEditor
+              onSubmit={(propName, value) =>
+                set_sprite_prop(selectedObject.spriteNumber, propName, value)
+              }
+            />
+          </TabView.Tab>
+        )}
+        {selectedObject?.type === "sprite" && (
+          <TabView.Tab tabKey="behaviors" title="Behaviors">
+            <JSONTree keyPath={["behaviors"]} data={behaviorDescriptions || []} />
           </TabView.Tab>
         )}
         {member && (
           <TabView.Tab tabKey="member" title="Member">
             <JSONTree keyPath={["member"]} data={member} />
+            {memberRef && (
+              <PropertyEditor
+                onSubmit={(propName, value) =>
+                  set_member_prop(memberRef[0], memberRef[1], propName, value)
+                }
+              />
+            )}
           </TabView.Tab>
         )}
         <TabView.Tab tabKey="movie" title="Movie">
@@ -3,7 +3,7 @@ import { useAppDispatch, useAppSelector } from "../../store/hooks";
 import { selectCurrentFrame, selectScoreSnapshot } from "../../store/vmSlice";
 import styles from "./styles.module.css";
 import classNames from "classnames";
-import { player_set_debug_selected_channel, subscribe_to_channel_names, unsubscribe_from_channel_names } from "vm-rust";
+import { player_set_debug_channel_muted, player_set_debug_selected_channel, player_set_debug_solo_channel, subscribe_to_channel_names, unsubscribe_from_channel_names } from "vm-rust";
 import { channelSelected, scoreBehaviorSelected } from "../../store/uiSlice";
 import { useEffect, useState } from "react";
 import { getScoreFrameBehaviorRef } from "../../utils/score";
@@ -18,6 +18,8 @@ export default function ScoreInspector() {
   const selectedChannel = selectedObject?.type === "sprite" && selectedObject.spriteNumber;
   const dispatch = useAppDispatch();
   const [isShowingChannels, setIsShowingChannels] = useState(false);
+  const [mutedChannels, setMutedChannels] = useState<number[]>([]);
+  const [soloChannel, setSoloChannel] = useState<number | undefined>(undefined);
 
   useEffect(() => {
     if (isShowingChannels) {
@@ -31,6 +33,18 @@ export default function ScoreInspector() {
     dispatch(channelSelected(channel));
   };
 
+  const onToggleMute = (channel: number) => {
+    const isMuted = mutedChannels.includes(channel);
+    player_set_debug_channel_muted(channel, !isMuted);
+    setMutedChannels(isMuted ? mutedChannels.filter((x) => x !== channel) : [...mutedChannels, channel]);
+  };
+
+  const onToggleSolo = (channel: number) => {
+    const newSoloChannel = soloChannel === channel ? undefined : channel;
+    player_set_debug_solo_channel(newSoloChannel);
+    setSoloChannel(newSoloChannel);
+  };
+
   const onSelectBehavior = (behavior: any) => {
     dispatch(scoreBehaviorSelected({ frameNumber: behavior }));
   };
@@ -77,16 +91,31 @@ export default function ScoreInspector() {
             (channel) => {
               let sprite = channelSnapshots[channel];
               return (
-                <button
+                <div
                   key={channel}
                   className={classNames([
                     styles.channelRow,
                     selectedChannel === channel && styles.selected,
                   ])}
-                  onClick={() => onSelectChannel(channel)}
                 >
-                  ({channel}) {sprite?.displayName}
-                </button>
+                  <button className={styles.channelName} onClick={() => onSelectChannel(channel)}>
+                    ({channel}) {sprite?.displayName}
+                  </button>
+                  <button
+                    title="Mute"
+                    className={classNames(styles.channelToggle, mutedChannels.includes(channel) && styles.active)}
+                    onClick={() => onToggleMute(channel)}
+                  >
+                    M
+                  </button>
+                  <button
+                    title="Solo"
+                    className={classNames(styles.channelToggle, soloChannel === channel && styles.active)}
+                    onClick={() => onToggleSolo(channel)}
+                  >
+                    S
+                  </button>
+                </div>
               );
             }
           )}
@@ -1,40 +1,143 @@
 import { useMeasure } from "@uidotdev/usehooks";
-import { useCallback, useEffect, useRef } from "react";
+import { useCallback, useEffect, useMemo, useRef } from "react";
 import {
   set_stage_size,
+  set_stage_scale_mode,
+  set_device_pixel_ratio,
   player_create_canvas,
   mouse_move,
   mouse_down,
   mouse_up,
+  mouse_wheel,
+  touch_start,
+  touch_move,
+  touch_end,
   key_down,
   key_up,
+  should_capture_key,
 } from "vm-rust";
+import { VmWorkerClient } from "../../vm/workerClient";
 
 import styles from "./styles.module.css";
 
+type StageInput = {
+  createCanvas: (container: HTMLElement) => void,
+  setStageSize: (width: number, height: number) => void,
+  setScaleMode: (mode: StageScaleMode) => void,
+  setDevicePixelRatio: (ratio: number) => void,
+  mouseMove: (x: number, y: number) => void,
+  mouseDown: (x: number, y: number) => void,
+  mouseUp: (x: number, y: number) => void,
+  mouseWheel: (deltaX: number, deltaY: number) => void,
+  touchStart: (id: number, x: number, y: number) => void,
+  touchMove: (id: number, x: number, y: number) => void,
+  touchEnd: (id: number, x: number, y: number) => void,
+  keyDown: (key: string, code: number) => void,
+  keyUp: (key: string, code: number) => void,
+};
+
+const localStageInput: StageInput = {
+  createCanvas: () => player_create_canvas(),
+  setStageSize: set_stage_size,
+  setScaleMode: set_stage_scale_mode,
+  setDevicePixelRatio: set_device_pixel_ratio,
+  mouseMove: mouse_move,
+  mouseDown: mouse_down,
+  mouseUp: mouse_up,
+  mouseWheel: mouse_wheel,
+  touchStart: touch_start,
+  touchMove: touch_move,
+  touchEnd: touch_end,
+  keyDown: key_down,
+  keyUp: key_up,
+};
+
+function createWorkerStageInput(client: VmWorkerClient): StageInput {
+  return {
+    createCanvas: (container) => {
+      const canvas = document.createElement("canvas");
+      container.appendChild(canvas);
+      client.attachCanvas(canvas);
+    },
+    setStageSize: (width, height) => client.call("set_stage_size", width, height),
+    setScaleMode: (mode) => client.call("set_stage_scale_mode", mode),
+    setDevicePixelRatio: (ratio) => client.call("set_device_pixel_ratio", ratio),
+    mouseMove: (x, y) => client.call("mouse_move", x, y),
+    mouseDown: (x, y) => client.call("mouse_down", x, y),
+    mouseUp: (x, y) => client.call("mouse_up", x, y),
+    mouseWheel: (deltaX, deltaY) => client.call("mouse_wheel", deltaX, deltaY),
+    touchStart: (id, x, y) => client.call("touch_start", id, x, y),
+    touchMove: (id, x, y) => client.call("touch_move", id, x, y),
+    touchEnd: (id, x, y) => client.call("touch_end", id, x, y),
+    keyDown: (key, code) => client.call("key_down", key, code),
+    keyUp: (key, code) => client.call("key_up", key, code),
+  };
+}
+
 type MouseEventName = "move" | "down" | "up";
-function onMouseEvent(name: MouseEventName, e: React.MouseEvent) {
+function onMouseEvent(input: StageInput, name: MouseEventName, e: React.PointerEvent) {
+  // Touches arrive as touch events, which the player turns into mouse input itself
+  if (e.pointerType === "touch") {
+    return;
+  }
   const rect = e.currentTarget.getBoundingClientRect();
   const x = e.clientX - rect.left;
   const y = e.clientY - rect.top;
   
   switch (name) {
     case "move":
-      mouse_move(x, y);
+      input.mouseMove(x, y);
       break;
     case "down":
-      mouse_down(x, y);
+      input.mouseDown(x, y);
       break;
     case "up":
-      mouse_up(x, y);
+      input.mouseUp(x, y);
       break;
   }
 }
 
-export default function Stage() {
+type TouchEventName = "start" | "move" | "end";
+function onTouchEvent(input: StageInput, name: TouchEventName, e: React.TouchEvent) {
+  const rect = e.currentTarget.getBoundingClientRect();
+  for (const touch of Array.from(e.changedTouches)) {
+    const x = touch.clientX - rect.left;
+    const y = touch.clientY - rect.top;
+    switch (name) {
+      case "start":
+        input.touchStart(touch.identifier, x, y);
+        break;
+      case "move":
+        input.touchMove(touch.identifier, x, y);
+        break;
+      case "end":
+        input.touchEnd(touch.identifier, x, y);
+        break;
+    }
+  }
+}
+
+/**
+ * How the movie is fitted into the stage: at its own size, as large as it fits with
+ * letterboxing, as large as it fits by whole multiples, or stretched to fill it.
+ */
+export type StageScaleMode = "none" | "fit" | "integer" | "stretch";
+
+type StageProps = {
+  /** When set, the stage is rendered by a player running in a worker. */
+  workerClient?: VmWorkerClient,
+  scaleMode?: StageScaleMode,
+};
+
+export default function Stage({ workerClient, scaleMode = "none" }: StageProps) {
   const [ref, { width, height }] = useMeasure();
   const isStageCanvasCreated = useRef(false);
   const containerRef = useRef<HTMLDivElement | null>(null);
+  const canvasContainerRef = useRef<HTMLDivElement | null>(null);
+  const input = useMemo(
+    () => workerClient ? createWorkerStageInput(workerClient) : localStageInput,
+    [workerClient]
+  );
 
   const onContainerRef = useCallback(
     (element: HTMLDivElement | null) => {
@@ -48,30 +151,60 @@ export default function Stage() {
   );
 
   useEffect(() => {
-    if (width && height && !isStageCanvasCreated.current) {
+    if (width && height && !isStageCanvasCreated.current && canvasContainerRef.current) {
       isStageCanvasCreated.current = true;
-      player_create_canvas();
+      input.createCanvas(canvasContainerRef.current);
     }
-  }, [width, height]);
+  }, [width, height, input]);
+
+  useEffect(() => {
+    if (!width || !height) return;
+    // The renderer scales the movie itself and backs the canvas at the display's resolution
+    input.setDevicePixelRatio(window.devicePixelRatio || 1);
+    input.setStageSize(width, height);
+  }, [width, height, input]);
+
+  useEffect(() => {
+    const element = canvasContainerRef.current;
+    if (!element) return;
+    // Registered by hand, since React listens to wheel events passively and can't keep the page from scrolling
+    const onWheel = (e: WheelEvent) => {
+      e.preventDefault();
+      // Lines and pages are turned into pixels, the unit the player expects
+      const scale = e.deltaMode === WheelEvent.DOM_DELTA_LINE ? 40 : e.deltaMode === WheelEvent.DOM_DELTA_PAGE ? 800 : 1;
+      input.mouseWheel(e.deltaX * scale, e.deltaY * scale);
+    };
+    element.addEventListener("wheel", onWheel, { passive: false });
+    return () => element.removeEventListener("wheel", onWheel);
+  }, [input]);
 
   useEffect(() => {
     if (!width || !height) return;
-    set_stage_size(width, height);
-  }, [width, height]);
+    input.setScaleMode(scaleMode);
+  }, [width, height, scaleMode, input]);
 
   return (
     <div className={styles.container} ref={onContainerRef}>
       <div
         tabIndex={0}
         id="stage_canvas_container"
-        onPointerMove={(e) => onMouseEvent('move', e)}
-        onPointerDown={(e) => onMouseEvent('down', e)}
-        onPointerUp={(e) => onMouseEvent('up', e)}
+        className={styles.canvasContainer}
+        ref={canvasContainerRef}
+        onPointerMove={(e) => onMouseEvent(input, 'move', e)}
+        onPointerDown={(e) => onMouseEvent(input, 'down', e)}
+        onPointerUp={(e) => onMouseEvent(input, 'up', e)}
+        onTouchStart={(e) => onTouchEvent(input, 'start', e)}
+        onTouchMove={(e) => onTouchEvent(input, 'move', e)}
+        onTouchEnd={(e) => onTouchEvent(input, 'end', e)}
+        onTouchCancel={(e) => onTouchEvent(input, 'end', e)}
         onKeyDown={e => {
-          e.preventDefault();
-          key_down(e.key, e.keyCode)
+          // The capture policy runs on the page so it can answer synchronously, even in worker mode
+          if (should_capture_key(e.key, e.ctrlKey, e.altKey, e.shiftKey, e.metaKey)) {
+            e.preventDefault();
+          }
+          input.keyDown(e.key, e.keyCode)
         }}
-        onKeyUp={e => key_up(e.key, e.keyCode)}
+        onKeyUp={e => input.keyUp(e.key, e.keyCode)}
       ></div>
     </div>
   );
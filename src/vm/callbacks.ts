@@ -1,13 +1,20 @@
-import { ICastMemberRef, JsBridgeBreakpoint, JsBridgeChunk, OnScriptErrorData, registerVmCallbacks } from "dirplayer-js-api";
+import { FrameDigest, ICastMemberRef, JsBridgeBehaviorDescription, JsBridgeBreakpoint, JsBridgeChunk, JsBridgeScriptCoverage, JsBridgeWatch, OnScriptErrorData, registerVmCallbacks } from "dirplayer-js-api";
 import store from "../store";
-import { breakpointListChanged, castLibNameChanged, castListChanged, castMemberChanged, castMemberListChanged, channelChanged, channelDisplayNameChanged, datumSnapshot, frameChanged, globalsChanged, movieChunkListChanged, movieLoaded, onScriptError, removeTimeoutHandle, scopeListChanged, scoreChanged, scriptErrorCleared, scriptInstanceSnapshot, setTimeoutHandle } from "../store/vmSlice";
+import { behaviorDescriptionsChanged, breakpointListChanged, castLibNameChanged, castListChanged, castMemberChanged, castMemberListChanged, channelChanged, consoleLineAdded, channelDisplayNameChanged, coverageSummaryChanged, datumSnapshot, frameChanged, globalsChanged, movieChunkListChanged, movieLoaded, onScriptError, removeTimeoutHandle, scopeListChanged, scoreChanged, scriptErrorCleared, scriptInstanceSnapshot, setTimeoutHandle, watchListChanged } from "../store/vmSlice";
 import { OnMovieLoadedCallbackData, trigger_timeout } from 'vm-rust'
 import { DatumRef, IVMScope, JsBridgeDatum, MemberSnapshot, ScoreSnapshot, ScoreSpriteSnapshot } from ".";
 import { onMemberSelected } from "../store/uiSlice";
 import { isUIShown } from "../utils/debug";
+import { breakSoundChannelLoop, playSoundChannel, setSoundChannelMix, setSoundMasterGain, stopSoundChannel } from "./sound";
+
+type TVmCallbacks = Parameters<typeof registerVmCallbacks>[0];
 
 export function initVmCallbacks() {
-  registerVmCallbacks({
+  registerVmCallbacks(createVmCallbacks());
+}
+
+export function createVmCallbacks(): TVmCallbacks {
+  return {
     onMovieLoaded: (result: OnMovieLoadedCallbackData) => {
       console.log('onMovieLoaded called!', result.version, result.test_val)
       store.dispatch(movieLoaded());
@@ -52,6 +59,15 @@ export function initVmCallbacks() {
       store.dispatch(breakpointListChanged(breakpoints))
       window.localStorage.setItem('breakpoints', JSON.stringify(breakpoints))
     },
+    onWatchListChanged: (watches: JsBridgeWatch[]) => {
+      store.dispatch(watchListChanged(watches))
+    },
+    onCoverageSummaryChanged: (scripts: JsBridgeScriptCoverage[]) => {
+      store.dispatch(coverageSummaryChanged(scripts))
+    },
+    onBehaviorDescriptionsChanged: (spriteNum: number, behaviors: JsBridgeBehaviorDescription[]) => {
+      store.dispatch(behaviorDescriptionsChanged({ spriteNum, behaviors }))
+    },
     onScriptErrorCleared: () => {
       store.dispatch(scriptErrorCleared())
     },
@@ -60,6 +76,7 @@ export function initVmCallbacks() {
     },
     onDebugMessage: (message: string) => {
       console.log("-- ", message);
+      store.dispatch(consoleLineAdded(message))
     },
     onScheduleTimeout: (timeoutName: string, periodMs: number) => {
       const handle = setInterval(() => {
@@ -93,6 +110,46 @@ export function initVmCallbacks() {
     },
     onChannelDisplayNameChanged: (channelNumber: number, displayName: string) => {
       store.dispatch(channelDisplayNameChanged({ channelNumber, displayName }));
-    }
-  });
+    },
+    onGoToNetPage: (url: string, target: string) => {
+      const event = new CustomEvent('dirplayer:gotoNetPage', { detail: { url, target }, cancelable: true });
+      if (window.dispatchEvent(event) && !url.toLowerCase().startsWith('javascript:')) {
+        window.open(url, target || '_self');
+      }
+    },
+    onExternalEvent: (event: string) => {
+      window.dispatchEvent(new CustomEvent('dirplayer:externalEvent', { detail: event }));
+    },
+    onFrameDigest: (digest: FrameDigest) => {
+      window.dispatchEvent(new CustomEvent('dirplayer:frame', { detail: digest }));
+    },
+    onWindowOpened: (name: string, title: string, width: number, height: number) => {
+      // The host page provides a canvas for the window with player_set_window_canvas
+      window.dispatchEvent(new CustomEvent('dirplayer:windowOpened', { detail: { name, title, width, height } }));
+    },
+    onWindowClosed: (name: string) => {
+      window.dispatchEvent(new CustomEvent('dirplayer:windowClosed', { detail: { name } }));
+    },
+    onSoundChannelChanged: (channel: number, volume: number, pan: number, rampMs: number) => {
+      setSoundChannelMix(channel, volume, pan, rampMs);
+    },
+    onSoundMasterVolumeChanged: (gain: number) => {
+      setSoundMasterGain(gain);
+    },
+    onSoundPlay: (channel: number, samples: Uint8Array, sampleRate: number, channelCount: number, bitsPerSample: number, offsetMs: number, playCount: number) => {
+      playSoundChannel(channel, samples, sampleRate, channelCount, bitsPerSample, offsetMs, playCount);
+    },
+    onSoundStop: (channel: number) => {
+      stopSoundChannel(channel);
+    },
+    onSoundBreakLoop: (channel: number) => {
+      breakSoundChannelLoop(channel);
+    },
+    onStageCursorChanged: (cursor: string) => {
+      const container = document.getElementById('stage_canvas_container');
+      if (container) {
+        container.style.cursor = cursor;
+      }
+    },
+  };
 }
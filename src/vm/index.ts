@@ -124,12 +124,14 @@ export interface IPaletteMemberSnapshot {
 
 export interface IScriptSnapshot {
   handlers: IHandlerSnapshot[]
+  reconstructed: boolean
 }
 
 export interface IHandlerSnapshot {
   name: string
   args: string[],
   bytecode: IBytecodeSnapshot[],
+  lines: IDecompiledLineSnapshot[],
 }
 
 export interface IBytecodeSnapshot {
@@ -137,6 +139,13 @@ export interface IBytecodeSnapshot {
   text: string
 }
 
+export interface IDecompiledLineSnapshot {
+  text: string
+  indent: number
+  bytecode_start: number
+  bytecode_end: number
+}
+
 export interface IUnknownMemberSnapshot {
   type: 'unknown'
 }
@@ -0,0 +1,119 @@
+type SoundChannelNodes = {
+  gain: GainNode,
+  panner: StereoPannerNode,
+};
+
+let audioContext: AudioContext | undefined;
+let masterGain: GainNode | undefined;
+const channelNodes: Record<number, SoundChannelNodes> = {};
+const channelSources: Record<number, AudioBufferSourceNode> = {};
+
+function getMasterGain(): GainNode {
+  if (!audioContext) {
+    audioContext = new AudioContext();
+  }
+  if (!masterGain) {
+    masterGain = audioContext.createGain();
+    masterGain.connect(audioContext.destination);
+  }
+  return masterGain;
+}
+
+/**
+ * The input of a sound channel. Sounds played in the channel connect here so they
+ * follow the channel's volume and pan.
+ */
+export function getSoundChannelInput(channel: number): GainNode {
+  const master = getMasterGain();
+  if (!channelNodes[channel]) {
+    const gain = audioContext!.createGain();
+    const panner = audioContext!.createStereoPanner();
+    gain.connect(panner);
+    panner.connect(master);
+    channelNodes[channel] = { gain, panner };
+  }
+  return channelNodes[channel].gain;
+}
+
+/** Applies a channel volume (0-255) and pan (-100 to 100), ramping over rampMs. */
+export function setSoundChannelMix(channel: number, volume: number, pan: number, rampMs: number) {
+  const gain = getSoundChannelInput(channel);
+  const { panner } = channelNodes[channel];
+  const now = audioContext!.currentTime;
+  gain.gain.cancelScheduledValues(now);
+  if (rampMs > 0) {
+    gain.gain.setValueAtTime(gain.gain.value, now);
+    gain.gain.linearRampToValueAtTime(volume / 255, now + rampMs / 1000);
+  } else {
+    gain.gain.setValueAtTime(volume / 255, now);
+  }
+  panner.pan.setValueAtTime(pan / 100, now);
+}
+
+/**
+ * Applies the gain of all channels, which is 0 while muted or while the soundEnabled is off.
+ * The context keeps running while silent, so sounds stay in time with the player.
+ */
+export function setSoundMasterGain(gain: number) {
+  const master = getMasterGain();
+  master.gain.setValueAtTime(gain, audioContext!.currentTime);
+  if (gain > 0 && audioContext!.state === 'suspended') {
+    audioContext!.resume();
+  }
+}
+
+/** Decodes 8-bit unsigned or 16-bit little-endian signed samples, with interleaved channels. */
+function createSoundBuffer(samples: Uint8Array, sampleRate: number, channelCount: number, bitsPerSample: number): AudioBuffer {
+  const bytesPerSample = bitsPerSample / 8;
+  const frameCount = Math.floor(samples.length / (bytesPerSample * channelCount));
+  const buffer = audioContext!.createBuffer(channelCount, Math.max(frameCount, 1), sampleRate);
+  const view = new DataView(samples.buffer, samples.byteOffset, samples.byteLength);
+  for (let channel = 0; channel < channelCount; channel++) {
+    const data = buffer.getChannelData(channel);
+    for (let frame = 0; frame < frameCount; frame++) {
+      const offset = (frame * channelCount + channel) * bytesPerSample;
+      data[frame] = bitsPerSample === 16 ? view.getInt16(offset, true) / 32768 : (samples[offset] - 128) / 128;
+    }
+  }
+  return buffer;
+}
+
+/** Plays a sound in a channel, offsetMs into it, playCount times in a row. */
+export function playSoundChannel(channel: number, samples: Uint8Array, sampleRate: number, channelCount: number, bitsPerSample: number, offsetMs: number, playCount: number) {
+  stopSoundChannel(channel);
+  const input = getSoundChannelInput(channel);
+  const source = audioContext!.createBufferSource();
+  source.buffer = createSoundBuffer(samples, sampleRate, channelCount, bitsPerSample);
+  source.connect(input);
+  const offset = Math.max(offsetMs, 0) / 1000;
+  if (playCount > 1) {
+    source.loop = true;
+    source.start(0, offset);
+    source.stop(audioContext!.currentTime + source.buffer.duration * playCount - offset);
+  } else {
+    source.start(0, offset);
+  }
+  source.onended = () => {
+    if (channelSources[channel] === source) {
+      delete channelSources[channel];
+    }
+  };
+  channelSources[channel] = source;
+}
+
+export function stopSoundChannel(channel: number) {
+  const source = channelSources[channel];
+  if (source) {
+    delete channelSources[channel];
+    source.stop();
+    source.disconnect();
+  }
+}
+
+/** Lets the sound in a channel play to the end of the loop it's in. */
+export function breakSoundChannelLoop(channel: number) {
+  const source = channelSources[channel];
+  if (source) {
+    source.loop = false;
+  }
+}
@@ -0,0 +1,106 @@
+import { registerVmCallbacks } from "dirplayer-js-api";
+import init, * as vm from "vm-rust";
+import { OnMovieLoadedCallbackData, player_create_offscreen_canvas, set_system_font_path, trigger_timeout } from "vm-rust";
+import { VmWorkerRequest, VmWorkerResponse } from "./workerProtocol";
+
+type WorkerScope = {
+  postMessage: (message: VmWorkerResponse) => void,
+  onmessage: ((event: MessageEvent<VmWorkerRequest>) => void) | null,
+};
+const workerScope = globalThis as unknown as WorkerScope;
+
+function post(message: VmWorkerResponse) {
+  workerScope.postMessage(message);
+}
+
+function forward(name: string) {
+  return (...args: unknown[]) => post({ type: 'callback', name, args });
+}
+
+// Timeouts are scheduled here so they keep firing without a round trip to the page
+const timeoutHandles: Record<string, ReturnType<typeof setInterval>> = {};
+
+registerVmCallbacks({
+  onMovieLoaded: (result: OnMovieLoadedCallbackData) => {
+    // Bindgen classes can't be cloned across threads
+    post({ type: 'callback', name: 'onMovieLoaded', args: [{ version: result.version, test_val: result.test_val }] });
+  },
+  onMovieChunkListChanged: forward('onMovieChunkListChanged'),
+  onCastListChanged: forward('onCastListChanged'),
+  onCastLibNameChanged: forward('onCastLibNameChanged'),
+  onCastMemberListChanged: forward('onCastMemberListChanged'),
+  onCastMemberChanged: forward('onCastMemberChanged'),
+  onScoreChanged: forward('onScoreChanged'),
+  onFrameChanged: forward('onFrameChanged'),
+  onScriptError: forward('onScriptError'),
+  onScopeListChanged: forward('onScopeListChanged'),
+  onBreakpointListChanged: forward('onBreakpointListChanged'),
+  onWatchListChanged: forward('onWatchListChanged'),
+  onCoverageSummaryChanged: forward('onCoverageSummaryChanged'),
+  onBehaviorDescriptionsChanged: forward('onBehaviorDescriptionsChanged'),
+  onScriptErrorCleared: forward('onScriptErrorCleared'),
+  onGlobalListChanged: forward('onGlobalListChanged'),
+  onDebugMessage: forward('onDebugMessage'),
+  onScheduleTimeout: (timeoutName: string, periodMs: number) => {
+    timeoutHandles[timeoutName] = setInterval(() => {
+      trigger_timeout(timeoutName)
+    }, periodMs);
+  },
+  onClearTimeout: (timeoutName: string) => {
+    clearInterval(timeoutHandles[timeoutName]);
+    delete timeoutHandles[timeoutName];
+  },
+  onClearAllTimeouts: () => {
+    Object.keys(timeoutHandles).forEach((key) => {
+      clearInterval(timeoutHandles[key]);
+      delete timeoutHandles[key];
+    });
+  },
+  onDatumSnapshot: forward('onDatumSnapshot'),
+  onScriptInstanceSnapshot: forward('onScriptInstanceSnapshot'),
+  onChannelChanged: forward('onChannelChanged'),
+  onChannelDisplayNameChanged: forward('onChannelDisplayNameChanged'),
+  onGoToNetPage: forward('onGoToNetPage'),
+  onExternalEvent: forward('onExternalEvent'),
+  onFrameDigest: forward('onFrameDigest'),
+  onWindowOpened: forward('onWindowOpened'),
+  onWindowClosed: forward('onWindowClosed'),
+  onSoundChannelChanged: forward('onSoundChannelChanged'),
+  onSoundMasterVolumeChanged: forward('onSoundMasterVolumeChanged'),
+  onSoundPlay: forward('onSoundPlay'),
+  onSoundStop: forward('onSoundStop'),
+  onSoundBreakLoop: forward('onSoundBreakLoop'),
+  onStageCursorChanged: forward('onStageCursorChanged'),
+});
+
+async function handleCall(id: number, name: string, args: unknown[]) {
+  const fn = (vm as Record<string, unknown>)[name];
+  if (typeof fn !== 'function') {
+    post({ type: 'result', id, error: `Unknown player function ${name}` });
+    return;
+  }
+  try {
+    const result = await fn(...args);
+    post({ type: 'result', id, result });
+  } catch (e) {
+    post({ type: 'result', id, error: String(e) });
+  }
+}
+
+workerScope.onmessage = (event) => {
+  const request = event.data;
+  switch (request.type) {
+    case 'init':
+      init({}).then(() => {
+        set_system_font_path(request.systemFontPath);
+        post({ type: 'ready' });
+      });
+      break;
+    case 'attachCanvas':
+      player_create_offscreen_canvas(request.canvas);
+      break;
+    case 'call':
+      handleCall(request.id, request.name, request.args);
+      break;
+  }
+};
@@ -0,0 +1,84 @@
+import { registerVmCallbacks } from "dirplayer-js-api";
+import { VmWorkerRequest, VmWorkerResponse } from "./workerProtocol";
+
+type TVmCallbacks = Parameters<typeof registerVmCallbacks>[0];
+
+type PendingCall = {
+  resolve: (result: unknown) => void,
+  reject: (error: Error) => void,
+};
+
+export function isWorkerModeSupported() {
+  return typeof Worker !== 'undefined'
+    && typeof OffscreenCanvas !== 'undefined'
+    && 'transferControlToOffscreen' in HTMLCanvasElement.prototype;
+}
+
+/**
+ * Runs the player in a dedicated worker so that parsing and script execution don't
+ * block the page. The stage canvas is transferred to the worker, and player functions
+ * and callbacks are relayed through messages.
+ */
+export class VmWorkerClient {
+  readonly ready: Promise<void>;
+  private worker: Worker;
+  private nextCallId = 1;
+  private pendingCalls = new Map<number, PendingCall>();
+
+  constructor(callbacks: TVmCallbacks, systemFontPath: string) {
+    this.worker = new Worker(new URL('./worker.ts', import.meta.url), { type: 'module' });
+    this.ready = new Promise((resolve) => {
+      this.worker.onmessage = (event: MessageEvent<VmWorkerResponse>) => {
+        const response = event.data;
+        switch (response.type) {
+          case 'ready':
+            resolve();
+            break;
+          case 'result':
+            this.onCallResult(response.id, response.result, response.error);
+            break;
+          case 'callback':
+            (callbacks as Record<string, Function>)[response.name]?.(...response.args);
+            break;
+        }
+      };
+    });
+    this.post({ type: 'init', systemFontPath });
+  }
+
+  call<T = unknown>(name: string, ...args: unknown[]): Promise<T> {
+    const id = this.nextCallId++;
+    return new Promise<T>((resolve, reject) => {
+      this.pendingCalls.set(id, { resolve: resolve as (result: unknown) => void, reject });
+      this.post({ type: 'call', id, name, args });
+    });
+  }
+
+  attachCanvas(canvas: HTMLCanvasElement) {
+    const offscreenCanvas = canvas.transferControlToOffscreen();
+    this.post({ type: 'attachCanvas', canvas: offscreenCanvas }, [offscreenCanvas]);
+  }
+
+  terminate() {
+    this.worker.terminate();
+    this.pendingCalls.forEach(({ reject }) => reject(new Error('Player worker terminated')));
+    this.pendingCalls.clear();
+  }
+
+  private onCallResult(id: number, result: unknown, error?: string) {
+    const pendingCall = this.pendingCalls.get(id);
+    if (!pendingCall) {
+      return;
+    }
+    this.pendingCalls.delete(id);
+    if (error !== undefined) {
+      pendingCall.reject(new Error(error));
+    } else {
+      pendingCall.resolve(result);
+    }
+  }
+
+  private post(message: VmWorkerRequest, transfer: Transferable[] = []) {
+    this.worker.postMessage(message, transfer);
+  }
+}
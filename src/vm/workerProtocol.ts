@@ -0,0 +1,11 @@
+// Messages exchanged between the page and a player running in a dedicated worker.
+
+export type VmWorkerRequest =
+  | { type: 'init', systemFontPath: string }
+  | { type: 'attachCanvas', canvas: OffscreenCanvas }
+  | { type: 'call', id: number, name: string, args: unknown[] };
+
+export type VmWorkerResponse =
+  | { type: 'ready' }
+  | { type: 'result', id: number, result?: unknown, error?: string }
+  | { type: 'callback', name: string, args: unknown[] };
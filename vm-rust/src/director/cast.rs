@@ -5,12 +5,14 @@ use itertools::Itertools;
 
 use crate::{director::{file::get_children_of_chunk, utils::fourcc_to_string}, console_warn};
 
-use super::{chunks::{cast_member::CastMemberDef, key_table::KeyTableChunk, ChunkContainer, script::ScriptChunk}, file::{get_cast_member_chunk, get_script_context_chunk, get_script_context_key_entry_for_cast, get_script_names_chunk, get_script_chunk, get_chunk}, rifx::RIFXReaderContext, utils::FOURCC, lingo::script::ScriptContext};
+use super::{chunks::{cast_member::CastMemberDef, key_table::KeyTableChunk, ChunkContainer, script::ScriptChunk}, file::{get_cast_member_chunk, is_member_downloaded, get_script_context_chunk, get_script_context_key_entry_for_cast, get_script_names_chunk, get_script_chunk, get_chunk}, rifx::RIFXReaderContext, utils::FOURCC, lingo::script::ScriptContext};
 
 pub struct CastDef {
   pub id: u32,
   pub name: String,
   pub members: HashMap<u32, CastMemberDef>,
+  /// Section ids of members whose media hasn't been downloaded yet, by member number.
+  pub pending_members: HashMap<u32, u32>,
   pub lctx: Option<ScriptContext>,
   pub capital_x: bool,
   pub dir_version: u16,
@@ -34,38 +36,21 @@ impl CastDef {
     let capital_x = lctx_entry.is_some() && lctx_entry.unwrap().fourcc == FOURCC("LctX");
 
     let mut members: HashMap<u32, CastMemberDef> = HashMap::new();
+    let mut pending_members: HashMap<u32, u32> = HashMap::new();
     for i in 0..member_ids.len() {
       let section_id = member_ids[i];
       if section_id <= 0 {
         continue;
       }
-      let member_id = i as u16 + min_member;
-      let member = get_cast_member_chunk(
-        reader, 
-        chunk_container,
-        rifx,
-        section_id
-      );
-      let children_entries = get_children_of_chunk(&section_id, key_table);
-      let children = children_entries.iter()
-        .map(|x| {
-          let child = get_chunk(reader, chunk_container, rifx, x.fourcc, x.section_id);
-          if child.is_ok() { 
-            Some(child.unwrap()) 
-          } else {
-            // warn!("Could not read child chunk of type {}", fourcc_to_string(x.fourcc));
-            None
-          }
-        })
-        .collect_vec();
-
-      // log_i(format_args!("Member {member_id} name: \"{}\" chunk: {section_id} children: {}", member.member_info.name, children.len()).to_string().as_str());
-      let member_def = CastMemberDef {
-        chunk: member,
-        children
-      };
-
-      members.insert(member_id as u32, member_def);
+      let member_id = (i as u16 + min_member) as u32;
+      if !is_member_downloaded(chunk_container, rifx, key_table, reader.length, section_id) {
+        pending_members.insert(member_id, section_id);
+        continue;
+      }
+      match Self::read_member(&name, member_id, section_id, reader, chunk_container, rifx, key_table) {
+        Ok(member_def) => { members.insert(member_id, member_def); }
+        Err(err) => rifx.warn(format!("Could not read member {member_id} in cast {name}: {err}")),
+      }
     }
 
     let mut scripts: HashMap<u32, ScriptChunk> = HashMap::new();
@@ -91,6 +76,7 @@ impl CastDef {
       id, 
       name: name, 
       members: members, 
+      pending_members,
       lctx: lctx.map(|_| ScriptContext {
         scripts,
         names: script_names.map_or(Vec::new(), |x| x.names)
@@ -99,4 +85,41 @@ impl CastDef {
       dir_version: rifx.dir_version,
     });
   }
+
+  pub fn read_member(
+    cast_name: &str,
+    member_id: u32,
+    section_id: u32,
+    reader: &mut BinaryReader,
+    chunk_container: &mut ChunkContainer,
+    rifx: &mut RIFXReaderContext,
+    key_table: &KeyTableChunk,
+  ) -> Result<CastMemberDef, String> {
+    let member = get_cast_member_chunk(
+      reader, 
+      chunk_container,
+      rifx,
+      section_id
+    )?;
+    let children_entries = get_children_of_chunk(&section_id, key_table);
+    let children = children_entries.iter()
+      .map(|x| {
+        let child = get_chunk(reader, chunk_container, rifx, x.fourcc, x.section_id);
+        match child {
+          Ok(child) => Some(child),
+          Err(err) => {
+            rifx.warn(format!("Could not read {} chunk {} of member {member_id} in cast {cast_name}: {err}", fourcc_to_string(x.fourcc), x.section_id));
+            None
+          }
+        }
+      })
+      .collect_vec();
+
+    // log_i(format_args!("Member {member_id} name: \"{}\" chunk: {section_id} children: {}", member.member_info.name, children.len()).to_string().as_str());
+    Ok(CastMemberDef {
+      section_id,
+      chunk: member,
+      children
+    })
+  }
 }
@@ -1,13 +1,15 @@
-use binary_reader::BinaryReader;
+use std::rc::Rc;
 
+/// The compressed pixels of a bitmap member, shared with the bitmap manager so they
+/// aren't copied again when the member is created.
 pub struct BitmapChunk {
-  pub data: Vec<u8>,
+  pub data: Rc<[u8]>,
 }
 
 impl BitmapChunk {
-  pub fn read(reader: &mut BinaryReader) -> Result<BitmapChunk, String> {
+  pub fn read(view: Vec<u8>) -> Result<BitmapChunk, String> {
     Ok(BitmapChunk {
-      data: reader.data.clone(),
+      data: Rc::from(view),
     })
   }
 }
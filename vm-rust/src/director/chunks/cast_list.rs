@@ -1,6 +1,6 @@
 use binary_reader::{BinaryReader, Endian};
 
-use crate::io::list_readers::{read_u16, read_pascal_string};
+use crate::io::{list_readers::{read_u16, read_pascal_string}, text_encoding::TextEncoding};
 
 use super::list::BasicListChunk;
 
@@ -35,6 +35,7 @@ impl CastListChunk {
     header: CastListChunkHeader, 
     offset_table: &Vec<usize>,
     item_endian: Endian,
+    encoding: TextEncoding,
   ) -> Result<Vec<CastListEntry>, String> {
     let item_bufs = BasicListChunk::read_items(
       reader, 
@@ -55,13 +56,15 @@ impl CastListChunk {
           &item_bufs, 
           (i * header.items_per_cast + 1) as usize, 
           item_endian,
+          encoding,
         );
       }
       if header.items_per_cast >= 2 {
         file_path = read_pascal_string(
           &item_bufs, 
           (i * header.items_per_cast + 2) as usize,
-          item_endian
+          item_endian,
+          encoding,
         );
       }
       if header.items_per_cast >= 3 {
@@ -97,14 +100,14 @@ impl CastListChunk {
 }
 
 impl CastListChunk {
-  pub fn from_reader(reader: &mut BinaryReader, dir_version: u16, item_endian: Endian) -> Result<CastListChunk, String> {
+  pub fn from_reader(reader: &mut BinaryReader, dir_version: u16, item_endian: Endian, encoding: TextEncoding) -> Result<CastListChunk, String> {
     reader.set_endian(Endian::Big);
 
     let header = Self::read_header(reader, dir_version).unwrap();
     let offset_table = BasicListChunk::read_offset_table(reader, dir_version, header.data_offset).unwrap();
     //let item_endian = reader.endian;
 
-    let items = Self::read_items(reader, dir_version, header, &offset_table, item_endian).unwrap();
+    let items = Self::read_items(reader, dir_version, header, &offset_table, item_endian, encoding).unwrap();
     return Ok(CastListChunk {
       entries: items
     })
@@ -1,6 +1,6 @@
 use binary_reader::{BinaryReader, Endian};
 
-use crate::director::{chunks::cast_member_info::CastMemberInfoChunk, enums::{BitmapInfo, FilmLoopInfo, MemberType, ScriptType, ShapeInfo}};
+use crate::{director::{chunks::cast_member_info::CastMemberInfoChunk, enums::{BitmapInfo, FieldInfo, FilmLoopInfo, MemberType, ScriptType, ShapeInfo}}, io::text_encoding::TextEncoding};
 
 use super::Chunk;
 
@@ -11,13 +11,15 @@ pub struct CastMemberChunk {
 }
 
 pub struct CastMemberDef {
+  /// Id of the CASt chunk, which owns the member's other chunks.
+  pub section_id: u32,
   pub chunk: CastMemberChunk,
   pub children: Vec<Option<Chunk>>,
 }
 
 impl CastMemberChunk {
   #[allow(unused_variables, unused_assignments)]
-  pub fn from_reader(reader: &mut BinaryReader, dir_version: u16) -> Result<CastMemberChunk, String> {
+  pub fn from_reader(reader: &mut BinaryReader, dir_version: u16, encoding: TextEncoding) -> Result<CastMemberChunk, String> {
     reader.endian = Endian::Big;
 
     let mut info: Option<CastMemberInfoChunk> = None;
@@ -39,7 +41,7 @@ impl CastMemberChunk {
         let mut info_reader = BinaryReader::from_u8(reader.read_bytes(info_len).unwrap());
         info_reader.set_endian(reader.endian);
 
-        info = Some(CastMemberInfoChunk::read(&mut info_reader, dir_version).unwrap());
+        info = Some(CastMemberInfoChunk::read(&mut info_reader, dir_version, encoding).unwrap());
       }
 
       // specific data
@@ -68,7 +70,7 @@ impl CastMemberChunk {
       let mut info_reader = BinaryReader::from_u8(reader.read_bytes(info_len).unwrap());
       info_reader.set_endian(reader.endian);
       if info_len != 0 {
-        info = Some(CastMemberInfoChunk::read(&mut info_reader, dir_version).unwrap());
+        info = Some(CastMemberInfoChunk::read(&mut info_reader, dir_version, encoding).unwrap());
       }
     }
 
@@ -91,6 +93,12 @@ impl CastMemberChunk {
           ShapeInfo::from(specific_data.as_slice())
         );
       }
+      MemberType::Text => {
+        specific_data_parsed = match FieldInfo::read(specific_data.as_slice()) {
+          Some(field_info) => CastMemberSpecificData::Field(field_info),
+          None => CastMemberSpecificData::None,
+        };
+      }
       // a few cast member types may share the same memory format
       // including film loop, movie, digital video, and xtra
       // according to More Director Movie File Unofficial Documentation:
@@ -119,6 +127,7 @@ pub enum CastMemberSpecificData {
   Bitmap(BitmapInfo),
   Shape(ShapeInfo),
   FilmLoop(FilmLoopInfo),
+  Field(FieldInfo),
   None
 }
 
@@ -147,6 +156,14 @@ impl CastMemberSpecificData {
     }
   }
 
+  pub fn field_info(&self) -> Option<&FieldInfo> {
+    if let CastMemberSpecificData::Field(field_info) = self {
+      Some(field_info)
+    } else {
+      None
+    }
+  }
+
   pub fn film_loop_info(&self) -> Option<&FilmLoopInfo> {
     if let CastMemberSpecificData::FilmLoop(film_loop_info) = self {
       Some(film_loop_info)
@@ -1,6 +1,6 @@
 use binary_reader::BinaryReader;
 
-use crate::io::list_readers::{read_string, read_pascal_string};
+use crate::io::{list_readers::{read_string, read_pascal_string}, text_encoding::TextEncoding};
 
 use super::list::BasicListChunk;
 
@@ -20,13 +20,13 @@ pub struct CastMemberInfoChunk {
 }
 
 impl CastMemberInfoChunk {
-  pub fn read(reader: &mut BinaryReader, dir_version: u16) -> Result<CastMemberInfoChunk, String> {
+  pub fn read(reader: &mut BinaryReader, dir_version: u16, encoding: TextEncoding) -> Result<CastMemberInfoChunk, String> {
     let header = Self::read_header(reader, dir_version).unwrap();
     let offset_table = BasicListChunk::read_offset_table(reader, dir_version, header.data_offset).unwrap();
     let item_bufs = BasicListChunk::read_items(reader, dir_version, header.data_offset, &offset_table).unwrap();
 
-    let script_src_text = read_string(&item_bufs, 0);
-    let name = read_pascal_string(&item_bufs, 1, reader.endian);
+    let script_src_text = read_string(&item_bufs, 0, encoding);
+    let name = read_pascal_string(&item_bufs, 1, reader.endian, encoding);
     // TODO Workaround: Increase table len to have at least one entry for decompilation results
 
     return Ok(
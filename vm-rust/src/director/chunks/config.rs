@@ -5,6 +5,7 @@ use crate::{
 };
 
 
+#[derive(Clone)]
 pub struct ConfigChunk {
   /*  0 */ pub len: u16,
 	/*  2 */ pub file_version: u16,
@@ -158,6 +159,45 @@ impl ConfigChunk {
     return Ok(config);
   }
 
+  /// Writes the chunk back in the big-endian layout it's read from.
+  pub fn write(&self) -> Vec<u8> {
+    let is_d7 = human_version(self.director_version) >= 700;
+    let mut out = Vec::with_capacity(self.len as usize);
+    for value in [self.len, self.file_version, self.movie_top, self.movie_left, self.movie_bottom, self.movie_right, self.min_member, self.max_member] {
+      out.extend_from_slice(&value.to_be_bytes());
+    }
+    out.extend_from_slice(&[self.field9, self.field10]);
+    if is_d7 {
+      out.extend_from_slice(&[self.d7_stage_color_g, self.d7_stage_color_b]);
+    } else {
+      out.extend_from_slice(&self.pre_d77field11.to_be_bytes());
+    }
+    for value in [self.comment_font, self.comment_size, self.comment_style] {
+      out.extend_from_slice(&value.to_be_bytes());
+    }
+    if is_d7 {
+      out.extend_from_slice(&[self.d7_stage_color_is_rgb, self.d7_stage_color_r]);
+    } else {
+      out.extend_from_slice(&self.pre_d7_stage_color.to_be_bytes());
+    }
+    out.extend_from_slice(&self.bit_depth.to_be_bytes());
+    out.extend_from_slice(&[self.field17, self.field18]);
+    out.extend_from_slice(&self.field19.to_be_bytes());
+    out.extend_from_slice(&self.director_version.to_be_bytes());
+    out.extend_from_slice(&self.field21.to_be_bytes());
+    for value in [self.field22, self.field23, self.field24] {
+      out.extend_from_slice(&value.to_be_bytes());
+    }
+    out.extend_from_slice(&[self.field25, self.field26]);
+    for value in [self.frame_rate, self.platform, self.protection] {
+      out.extend_from_slice(&value.to_be_bytes());
+    }
+    out.extend_from_slice(&self.field29.to_be_bytes());
+    out.extend_from_slice(&self.checksum.to_be_bytes());
+    out.extend_from_slice(&self.remnants);
+    out
+  }
+
   pub fn compute_checksum(&self, dir_endian: Endian) -> u32 {
     let ver = human_version(self.director_version);
 
@@ -33,6 +33,10 @@ pub struct Bytecode {
 }
 
 impl Bytecode {
+  pub fn new(opcode: OpCode, obj: i64, pos: usize) -> Bytecode {
+    Bytecode { opcode, obj, pos, owner_loop: u32::MAX }
+  }
+
   pub fn pos_to_str(pos: usize) -> String {
     format_args!("[{}]", pos).to_string()
   }
@@ -105,6 +109,35 @@ pub struct HandlerDef {
   pub global_name_ids: Vec<u16>,
 }
 
+impl HandlerDef {
+  /// Encodes the bytecode the way it's read. Each argument keeps the width it had, which
+  /// the distance to the next bytecode tells, so that jumps still land where they did.
+  pub fn write_bytecode(&self) -> Vec<u8> {
+    let mut out = vec![];
+    for (index, bytecode) in self.bytecode_array.iter().enumerate() {
+      let op_id = num::ToPrimitive::to_u8(&bytecode.opcode).unwrap();
+      let arg_len = match self.bytecode_array.get(index + 1) {
+        Some(next) => next.pos - bytecode.pos - 1,
+        None if op_id < 0x40 => 0,
+        None => 4,
+      };
+      match arg_len {
+        0 => out.push(op_id),
+        1 => out.extend_from_slice(&[op_id, bytecode.obj as u8]),
+        2 => {
+          out.push(op_id + 0x40);
+          out.extend_from_slice(&(bytecode.obj as u16).to_be_bytes());
+        }
+        _ => {
+          out.push(op_id + 0x80);
+          out.extend_from_slice(&(bytecode.obj as u32).to_be_bytes());
+        }
+      }
+    }
+    out
+  }
+}
+
 impl HandlerRecord {
   #[allow(unused_variables)]
   pub fn read_record(
@@ -1,7 +1,7 @@
 use binary_reader::BinaryReader;
 use num_derive::FromPrimitive;
 
-use crate::{io::reader::DirectorExt, director::lingo::datum::Datum};
+use crate::{io::{reader::DirectorExt, text_encoding::TextEncoding}, director::lingo::datum::Datum};
 
 pub struct LiteralStoreRecord {
   pub literal_type: LiteralType,
@@ -24,9 +24,9 @@ pub enum LiteralType {
 
 impl LiteralStore {
   #[allow(dead_code)]
-  pub fn from_reader(reader: &mut BinaryReader, dir_version: u16, start_offset: usize) -> Result<LiteralStore, String> {
+  pub fn from_reader(reader: &mut BinaryReader, dir_version: u16, start_offset: usize, encoding: TextEncoding) -> Result<LiteralStore, String> {
     let record = Self::read_record(reader, dir_version).unwrap();
-    let data = Self::read_data(reader, &record, start_offset).unwrap();
+    let data = Self::read_data(reader, &record, start_offset, encoding).unwrap();
     return Ok(LiteralStore { 
       record,
       data,
@@ -55,6 +55,7 @@ impl LiteralStore {
     reader: &mut BinaryReader,
     record: &LiteralStoreRecord,
     start_offset: usize,
+    encoding: TextEncoding,
   ) -> Result<Datum, String> {
     let value: Datum;
     match record.literal_type {
@@ -64,7 +65,7 @@ impl LiteralStore {
         let length = reader.read_u32().unwrap() as usize;
         match record.literal_type {
           LiteralType::String => { 
-            value = Datum::String(reader.read_string(length - 1).unwrap());
+            value = Datum::String(reader.read_encoded_string(length - 1, encoding).unwrap());
           }
           LiteralType::Float => {
             let float_val = if length == 8 {
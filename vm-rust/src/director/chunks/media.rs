@@ -0,0 +1,14 @@
+use std::rc::Rc;
+
+/// The media of a member stored as a file of its own, like an imported JPEG or GIF.
+pub struct MediaChunk {
+  pub data: Rc<[u8]>,
+}
+
+impl MediaChunk {
+  pub fn read(view: Vec<u8>) -> Result<MediaChunk, String> {
+    Ok(MediaChunk {
+      data: Rc::from(view),
+    })
+  }
+}
@@ -14,9 +14,11 @@ pub mod handler;
 pub mod score;
 pub mod text;
 pub mod bitmap;
+pub mod media;
 pub mod palette;
+pub mod sound;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use binary_reader::{Endian, BinaryReader};
 use config::ConfigChunk;
@@ -24,7 +26,7 @@ use imap::InitialMapChunk;
 use key_table::KeyTableChunk;
 use score::FrameLabelsChunk;
 
-use self::{bitmap::BitmapChunk, cast::CastChunk, cast_list::CastListChunk, cast_member::CastMemberChunk, lctx::ScriptContextChunk, palette::PaletteChunk, score::ScoreChunk, script::ScriptChunk, script_names::ScriptNamesChunk, text::TextChunk};
+use self::{bitmap::BitmapChunk, media::MediaChunk, cast::CastChunk, cast_list::CastListChunk, cast_member::CastMemberChunk, lctx::ScriptContextChunk, palette::PaletteChunk, score::ScoreChunk, script::ScriptChunk, script_names::ScriptNamesChunk, sound::SoundChunk, text::TextChunk};
 use super::{guid::MoaID, utils::{fourcc_to_string, FOURCC}, rifx::RIFXReaderContext};
 
 pub struct CastInfoChunkProps {
@@ -50,7 +52,9 @@ pub enum Chunk {
   Score(ScoreChunk),
   Text(TextChunk),
   Bitmap(BitmapChunk),
+  Media(MediaChunk),
   Palette(PaletteChunk),
+  Sound(SoundChunk),
 }
 
 impl Chunk {
@@ -68,6 +72,13 @@ impl Chunk {
     }
   }
 
+  pub fn as_media(&self) -> Option<&MediaChunk> {
+    match self {
+      Self::Media(data) => { Some(data) }
+      _ => { None }
+    }
+  }
+
   pub fn as_palette(&self) -> Option<&PaletteChunk> {
     match self {
       Self::Palette(data) => { Some(data) }
@@ -75,6 +86,13 @@ impl Chunk {
     }
   }
 
+  pub fn as_sound(&self) -> Option<&SoundChunk> {
+    match self {
+      Self::Sound(data) => { Some(data) }
+      _ => { None }
+    }
+  }
+
   pub fn as_score(&self) -> Option<&ScoreChunk> {
     match self {
       Self::Score(data) => { Some(data) }
@@ -95,7 +113,22 @@ pub struct ChunkInfo {
 pub struct ChunkContainer {
   pub deserialized_chunks: HashMap<u32, Chunk>,
   pub chunk_info: HashMap<u32, ChunkInfo>,
+  /// Decompressed chunks that can't be read again from the file, which are the ones
+  /// in the initial load segment and the streamed ones. Media chunks are dropped once
+  /// they're read, since their members keep the data.
   pub cached_chunk_views: HashMap<u32, Vec<u8>>,
+  pub read_chunk_ids: HashSet<u32>,
+}
+
+impl ChunkContainer {
+  pub fn cached_bytes(&self) -> usize {
+    self.cached_chunk_views.values().map(|view| view.len()).sum()
+  }
+}
+
+/// Whether the chunk holds the media of a member, which is only read once.
+pub fn is_media_chunk(fourcc: u32) -> bool {
+  ["BITD", "ediM", "snd ", "sndS", "ALFA"].iter().any(|media_fourcc| FOURCC(media_fourcc) == fourcc)
 }
 
 #[allow(dead_code)]
@@ -113,10 +146,16 @@ pub fn make_chunk(
   endian: Endian,
   rifx: &mut RIFXReaderContext,
   fourcc: u32, 
-  view: &Vec<u8>,
+  view: Vec<u8>,
 ) -> Result<Chunk, String> {
+  if fourcc == FOURCC("BITD") {
+    // Bitmaps keep the view as is, without copying it into a reader
+    return Ok(Chunk::Bitmap(BitmapChunk::read(view)?));
+  } else if fourcc == FOURCC("ediM") {
+    return Ok(Chunk::Media(MediaChunk::read(view)?));
+  }
   let version = rifx.dir_version;
-  let mut chunk_reader = BinaryReader::from_vec(view);
+  let mut chunk_reader = BinaryReader::from_vec(&view);
   chunk_reader.set_endian(endian);
 
   match fourcc_to_string(fourcc).as_str() {
@@ -140,7 +179,7 @@ pub fn make_chunk(
     "CASt" => {
       return Ok(
         Chunk::CastMember(
-          CastMemberChunk::from_reader(&mut chunk_reader, version).unwrap()
+          CastMemberChunk::from_reader(&mut chunk_reader, version, rifx.text_encoding).unwrap()
         )
       );
     }
@@ -169,7 +208,7 @@ pub fn make_chunk(
     "Lscr" => {
       return Ok(
         Chunk::Script(
-          ScriptChunk::from_reader(&mut chunk_reader, version, rifx.lctx_capital_x).unwrap()
+          ScriptChunk::from_reader(&mut chunk_reader, version, rifx.lctx_capital_x, rifx.text_encoding).unwrap()
         )
       );
     }
@@ -186,7 +225,7 @@ pub fn make_chunk(
     "MCsL" => {
       return Ok(
         Chunk::CastList(
-          CastListChunk::from_reader(&mut chunk_reader, version, endian).unwrap()
+          CastListChunk::from_reader(&mut chunk_reader, version, endian, rifx.text_encoding).unwrap()
         )
       )
       //res = CastListChunk(dir: this);
@@ -208,25 +247,19 @@ pub fn make_chunk(
     "VWLB" => {
       return Ok(
           Chunk::FrameLabels(
-            FrameLabelsChunk::from_reader(&mut chunk_reader, version).unwrap()
+            FrameLabelsChunk::from_reader(&mut chunk_reader, version, rifx.text_encoding).unwrap()
         )
       )
     }
     "STXT" => {
       return Ok(
         Chunk::Text(
-          TextChunk::read(&mut chunk_reader).unwrap()
-        )
-      )
-    }
-    "BITD" => {
-      return Ok(
-        Chunk::Bitmap(
-          BitmapChunk::read(&mut chunk_reader).unwrap()
+          TextChunk::read(&mut chunk_reader, rifx.text_encoding).unwrap()
         )
       )
     }
     "CLUT" => Ok(Chunk::Palette(palette::PaletteChunk::from_reader(&mut chunk_reader, version).unwrap())),
+    "snd " => Ok(Chunk::Sound(SoundChunk::from_reader(&mut chunk_reader, version)?)),
     _ => {
       return Err(format_args!("Could not deserialize '{}' chunk", fourcc_to_string(fourcc)).to_string());
     }
@@ -2,7 +2,7 @@ use binary_reader::{BinaryReader, Endian};
 use itertools::Itertools;
 use log::error;
 
-use crate::{io::{list_readers::read_u16, reader::DirectorExt}, utils::log_i};
+use crate::{io::{list_readers::read_u16, reader::DirectorExt, text_encoding::TextEncoding}, utils::log_i};
 
 #[allow(dead_code)]
 pub struct ScoreFrameDelta {
@@ -35,10 +35,14 @@ pub struct ScoreFrameChannelData {
   pub pos_x: u16,
   pub height: u16,
   pub width: u16,
+  /// Sprite flags from Director 5 on, 0x80 for moveable and 0x40 for editable.
+  pub color_code: u8,
+  /// The transparency of the sprite scaled to 255, so 0 is fully opaque.
+  pub blend_amount: u8,
 }
 
 impl ScoreFrameChannelData {
-  pub fn read(reader: &mut BinaryReader) -> ScoreFrameChannelData {
+  pub fn read(reader: &mut BinaryReader, record_size: u16) -> ScoreFrameChannelData {
     let sprite_type = reader.read_u8().unwrap();
     let ink = reader.read_u8().unwrap();
     let fore_color = reader.read_u8().unwrap();
@@ -51,8 +55,15 @@ impl ScoreFrameChannelData {
     let pos_x = reader.read_u16().unwrap();
     let height = reader.read_u16().unwrap();
     let width = reader.read_u16().unwrap();
+    let color_code = if record_size > 20 { reader.read_u8().unwrap() } else { 0 };
+    let blend_amount = if record_size > 21 { reader.read_u8().unwrap() } else { 0 };
 
-    ScoreFrameChannelData { sprite_type, ink, fore_color, back_color, cast_lib, cast_member, unk1, unk2, pos_y, pos_x, height, width }
+    ScoreFrameChannelData { sprite_type, ink, fore_color, back_color, cast_lib, cast_member, unk1, unk2, pos_y, pos_x, height, width, color_code, blend_amount }
+  }
+
+  /// `the blend` of the sprite, from 0 to 100.
+  pub fn blend(&self) -> i32 {
+    100 - (self.blend_amount as i32 * 100 + 127) / 255
   }
 }
 
@@ -127,7 +138,7 @@ impl ScoreFrameData {
       for frame_index in 0..header.frame_count {
         for channel_index in 0..header.num_channels {
           let pos = channel_reader.pos;
-          let data = ScoreFrameChannelData::read(&mut channel_reader);
+          let data = ScoreFrameChannelData::read(&mut channel_reader, header.sprite_record_size);
           channel_reader.jmp(pos + header.sprite_record_size as usize);
           if data != ScoreFrameChannelData::default() {
             log_i(format_args!("frame_index={frame_index} channel_index={channel_index} sprite_type={} ink={} fore_color={} back_color={} pos_y={} pos_x={} height={} width={}", data.sprite_type, data.ink, data.fore_color, data.back_color, data.pos_y, data.pos_x, data.height, data.width).to_string().as_str());
@@ -224,6 +235,15 @@ impl FrameIntervalSecondary {
       unk0: reader.read_u32().unwrap(),
     }
   }
+
+  /// Reads every behavior attached to a sprite span, in the order they were attached.
+  pub fn read_all(reader: &mut BinaryReader) -> Vec<Self> {
+    let mut result = vec![];
+    while reader.length - reader.pos >= 8 {
+      result.push(Self::read(reader));
+    }
+    result
+  }
 }
 
 #[derive(Clone)]
@@ -241,7 +261,10 @@ pub struct ScoreChunk {
   pub header: ScoreChunkHeader,
   pub entries: Vec<Vec<u8>>,
   pub frame_interval_primaries: Vec<FrameIntervalPrimary>,
-  pub frame_interval_secondaries: Vec<Option<FrameIntervalSecondary>>,
+  /// The behaviors attached to each span
+  pub frame_interval_secondaries: Vec<Vec<FrameIntervalSecondary>>,
+  /// Serialized behavior initializer parameters, if any
+  pub frame_interval_tertiaries: Vec<Option<Vec<u8>>>,
   pub frame_data: ScoreFrameData,
 }
 
@@ -269,6 +292,7 @@ impl ScoreChunk {
     let frame_interval_entries = entries.split_off(3);
     let mut frame_interval_primaries = vec![];
     let mut frame_interval_secondaries = vec![];
+    let mut frame_interval_tertiaries = vec![];
 
     for i in (0..frame_interval_entries.len()).step_by(3) {
       let primary_entry = &frame_interval_entries[i];
@@ -282,14 +306,10 @@ impl ScoreChunk {
         error!("Failed to read FrameIntervalPrimary at index {}", i);
         break;
       }
-      let secondary_entry = &frame_interval_entries[i+1];
-      if secondary_entry.is_empty() {
-        frame_interval_secondaries.push(None);
-      } else {
-        let mut secondary_reader = BinaryReader::from_u8(secondary_entry);
-        frame_interval_secondaries.push(Some(FrameIntervalSecondary::read(&mut secondary_reader)));
-      }
-      // TODO tertiary
+      let mut secondary_reader = BinaryReader::from_u8(&frame_interval_entries[i+1]);
+      frame_interval_secondaries.push(FrameIntervalSecondary::read_all(&mut secondary_reader));
+      let tertiary_entry = frame_interval_entries.get(i+2).filter(|entry| !entry.is_empty());
+      frame_interval_tertiaries.push(tertiary_entry.cloned());
     }
 
     Ok(ScoreChunk {
@@ -297,6 +317,7 @@ impl ScoreChunk {
       entries,
       frame_interval_primaries,
       frame_interval_secondaries,
+      frame_interval_tertiaries,
       frame_data,
     })
   }
@@ -325,7 +346,7 @@ pub struct FrameLabelsChunk {
 }
 
 impl FrameLabelsChunk {
-  pub fn from_reader(reader: &mut BinaryReader, _dir_version: u16) -> Result<FrameLabelsChunk, String> {
+  pub fn from_reader(reader: &mut BinaryReader, _dir_version: u16, encoding: TextEncoding) -> Result<FrameLabelsChunk, String> {
     reader.set_endian(binary_reader::Endian::Big);
 
     let labels_count = reader.read_u16().unwrap() as usize;
@@ -346,7 +367,7 @@ impl FrameLabelsChunk {
           } else {
               labels_size - label_offset
           };
-          let label_str = reader.read_string(label_len).unwrap();
+          let label_str = reader.read_encoded_string(label_len, encoding).unwrap();
           // info!("label: {}", label_str);
           FrameLabel {
               frame_num: frame_num as i32,
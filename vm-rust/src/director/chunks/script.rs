@@ -1,7 +1,7 @@
 use binary_reader::BinaryReader;
 use itertools::Itertools;
 
-use crate::director::{chunks::literal::LiteralStore, lingo::datum::Datum};
+use crate::{director::{chunks::literal::{LiteralStore, LiteralType}, lingo::datum::Datum}, io::text_encoding::TextEncoding};
 
 use super::handler::{HandlerDef, HandlerRecord};
 
@@ -14,7 +14,7 @@ pub struct ScriptChunk {
 
 impl ScriptChunk {
   #[allow(unused_variables)]
-  pub fn from_reader(reader: &mut BinaryReader, dir_version: u16, capital_x: bool) -> Result<ScriptChunk, String> {
+  pub fn from_reader(reader: &mut BinaryReader, dir_version: u16, capital_x: bool, encoding: TextEncoding) -> Result<ScriptChunk, String> {
     // Lingo scripts are always big endian regardless of file endianness
     reader.set_endian(binary_reader::Endian::Big);
 
@@ -75,7 +75,7 @@ impl ScriptChunk {
 
     let literals = literal_records
       .iter()
-      .map(|record| LiteralStore::read_data(reader, record, literals_data_offset).unwrap())
+      .map(|record| LiteralStore::read_data(reader, record, literals_data_offset, encoding).unwrap())
       .collect_vec();
 
     return Ok(ScriptChunk { 
@@ -84,9 +84,146 @@ impl ScriptChunk {
       property_name_ids,
     });
   }
+
+  /// Writes the chunk back in the layout it's read from, with the handlers first and
+  /// the literals after them.
+  pub fn write(&self, script_number: u16, dir_version: u16, capital_x: bool, encoding: TextEncoding) -> Vec<u8> {
+    let record_len = if capital_x { 46 } else { 42 };
+    let properties_offset = SCRIPT_HEADER_LEN;
+    let handlers_offset = properties_offset + self.property_name_ids.len() * 2;
+    let handler_data_offset = handlers_offset + self.handlers.len() * record_len;
+
+    let mut handler_records = vec![];
+    let mut handler_data = vec![];
+    for handler in &self.handlers {
+      let bytecode = handler.write_bytecode();
+      let compiled_offset = handler_data_offset + handler_data.len();
+      handler_data.extend_from_slice(&bytecode);
+      let argument_offset = write_varnames_table(&mut handler_data, handler_data_offset, &handler.argument_name_ids);
+      let locals_offset = write_varnames_table(&mut handler_data, handler_data_offset, &handler.local_name_ids);
+      let globals_offset = write_varnames_table(&mut handler_data, handler_data_offset, &handler.global_name_ids);
+
+      handler_records.extend_from_slice(&handler.name_id.to_be_bytes());
+      handler_records.extend_from_slice(&0u16.to_be_bytes());
+      handler_records.extend_from_slice(&(bytecode.len() as u32).to_be_bytes());
+      handler_records.extend_from_slice(&(compiled_offset as u32).to_be_bytes());
+      for (count, offset) in [
+        (handler.argument_name_ids.len(), argument_offset),
+        (handler.local_name_ids.len(), locals_offset),
+        (handler.global_name_ids.len(), globals_offset),
+      ] {
+        handler_records.extend_from_slice(&(count as u16).to_be_bytes());
+        handler_records.extend_from_slice(&(offset as u32).to_be_bytes());
+      }
+      // No line table
+      handler_records.extend_from_slice(&[0; 12]);
+      if capital_x {
+        handler_records.extend_from_slice(&0u32.to_be_bytes());
+      }
+    }
+
+    let literals_offset = handler_data_offset + handler_data.len();
+    let mut literal_records = vec![];
+    let mut literal_data = vec![];
+    for literal in &self.literals {
+      let (literal_type, value) = match literal {
+        Datum::Int(value) => (LiteralType::Int, *value as u32),
+        Datum::String(value) => {
+          let offset = literal_data.len() as u32;
+          let bytes = encoding.encode(value);
+          literal_data.extend_from_slice(&(bytes.len() as u32 + 1).to_be_bytes());
+          literal_data.extend_from_slice(&bytes);
+          literal_data.push(0);
+          (LiteralType::String, offset)
+        }
+        Datum::Float(value) => {
+          let offset = literal_data.len() as u32;
+          literal_data.extend_from_slice(&10u32.to_be_bytes());
+          literal_data.extend_from_slice(&apple_float_80_bytes(*value as f64));
+          (LiteralType::Float, offset)
+        }
+        _ => {
+          let offset = literal_data.len() as u32;
+          literal_data.extend_from_slice(&0u32.to_be_bytes());
+          (LiteralType::Invalid, offset)
+        }
+      };
+      if dir_version >= 500 {
+        literal_records.extend_from_slice(&(literal_type as u32).to_be_bytes());
+      } else {
+        literal_records.extend_from_slice(&(literal_type as u16).to_be_bytes());
+      }
+      literal_records.extend_from_slice(&value.to_be_bytes());
+    }
+    let literals_data_offset = literals_offset + literal_records.len();
+    let total_length = (literals_data_offset + literal_data.len()) as u32;
+
+    let mut out = Vec::with_capacity(total_length as usize);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&total_length.to_be_bytes());
+    out.extend_from_slice(&total_length.to_be_bytes());
+    out.extend_from_slice(&(SCRIPT_HEADER_LEN as u16).to_be_bytes());
+    out.extend_from_slice(&script_number.to_be_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.resize(38, 0);
+    // Flags, then the cast and factory name, which aren't kept
+    out.extend_from_slice(&[0; 12]);
+    // No handler vectors
+    out.extend_from_slice(&[0; 10]);
+    for (count, offset) in [
+      (self.property_name_ids.len(), properties_offset),
+      // Globals declared outside the handlers aren't kept
+      (0, properties_offset),
+      (self.handlers.len(), handlers_offset),
+      (self.literals.len(), literals_offset),
+    ] {
+      out.extend_from_slice(&(count as u16).to_be_bytes());
+      out.extend_from_slice(&(offset as u32).to_be_bytes());
+    }
+    out.extend_from_slice(&(literal_data.len() as u32).to_be_bytes());
+    out.extend_from_slice(&(literals_data_offset as u32).to_be_bytes());
+    for name_id in &self.property_name_ids {
+      out.extend_from_slice(&name_id.to_be_bytes());
+    }
+    out.extend_from_slice(&handler_records);
+    out.extend_from_slice(&handler_data);
+    out.extend_from_slice(&literal_records);
+    out.extend_from_slice(&literal_data);
+    out
+  }
 }
 
+const SCRIPT_HEADER_LEN: usize = 92;
+
 fn read_varnames_table(reader: &mut BinaryReader, count: usize, offset: usize) -> Vec<u16> {
   reader.jmp(offset);
   return (0..count).map(|_| reader.read_u16().unwrap()).collect();
 }
+
+/// Appends a table of name ids and returns where it starts, counting from the chunk start.
+fn write_varnames_table(out: &mut Vec<u8>, base_offset: usize, name_ids: &[u16]) -> usize {
+  let offset = base_offset + out.len();
+  for name_id in name_ids {
+    out.extend_from_slice(&name_id.to_be_bytes());
+  }
+  offset
+}
+
+/// The 80 bit SANE extended float `read_apple_float_80` reads.
+fn apple_float_80_bytes(value: f64) -> [u8; 10] {
+  let bits = value.to_bits();
+  let sign = ((bits >> 48) & 0x8000) as u16;
+  let exponent = ((bits >> 52) & 0x7ff) as u16;
+  let fraction = bits & 0xfffffffffffff;
+  let mut out = [0; 10];
+  if exponent == 0 {
+    // Zero, and doubles too small for the reader to bring back
+    out[..2].copy_from_slice(&sign.to_be_bytes());
+    return out;
+  }
+  let exponent = if exponent == 0x7ff { 0x7fff } else { exponent + 0x3fff - 0x3ff };
+  out[..2].copy_from_slice(&(sign | exponent).to_be_bytes());
+  // The integer bit is explicit in the extended format
+  out[2..].copy_from_slice(&((1 << 63) | (fraction << 11)).to_be_bytes());
+  out
+}
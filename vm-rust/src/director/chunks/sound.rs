@@ -0,0 +1,97 @@
+use std::rc::Rc;
+
+use binary_reader::BinaryReader;
+
+const BUFFER_CMD: u16 = 0x8051;
+const STANDARD_SOUND_HEADER: u8 = 0x00;
+const EXTENDED_SOUND_HEADER: u8 = 0xFF;
+
+/// The samples of a sound member, stored as a Mac `snd ` resource.
+#[derive(Clone)]
+pub struct SoundChunk {
+  pub sample_rate: u32,
+  pub channel_count: u16,
+  pub bits_per_sample: u16,
+  /// Unsigned bytes for 8-bit sounds and little-endian signed words for 16-bit ones,
+  /// with the channels interleaved.
+  pub samples: Rc<[u8]>,
+}
+
+impl SoundChunk {
+  /// How long the sound plays once.
+  pub fn duration_ms(&self) -> i64 {
+    let frame_size = self.channel_count as usize * (self.bits_per_sample as usize / 8);
+    if frame_size == 0 || self.sample_rate == 0 {
+      return 0;
+    }
+    (self.samples.len() / frame_size) as i64 * 1000 / self.sample_rate as i64
+  }
+
+  pub fn from_reader(reader: &mut BinaryReader, _: u16) -> Result<SoundChunk, String> {
+    reader.set_endian(binary_reader::Endian::Big);
+    let truncated = |_| "Sound data ends early".to_string();
+
+    let format = reader.read_u16().map_err(truncated)?;
+    match format {
+      1 => {
+        let data_format_count = reader.read_u16().map_err(truncated)?;
+        reader.read_bytes(data_format_count as usize * 6).map_err(truncated)?;
+      }
+      2 => {
+        reader.read_u16().map_err(truncated)?;
+      }
+      _ => return Err(format!("Unknown sound resource format {}", format)),
+    }
+
+    // The buffer command points to the header of the sampled sound
+    let command_count = reader.read_u16().map_err(truncated)?;
+    let mut header_offset = None;
+    for _ in 0..command_count {
+      let command = reader.read_u16().map_err(truncated)?;
+      reader.read_u16().map_err(truncated)?;
+      let param = reader.read_u32().map_err(truncated)?;
+      if command & 0x7FFF == BUFFER_CMD & 0x7FFF {
+        header_offset = Some(param as usize);
+      }
+    }
+    let header_offset = header_offset.ok_or_else(|| "Sound has no sampled sound".to_string())?;
+
+    reader.jmp(header_offset);
+    reader.read_u32().map_err(truncated)?;
+    let length_or_channels = reader.read_u32().map_err(truncated)?;
+    let sample_rate = reader.read_u32().map_err(truncated)? >> 16;
+    reader.read_bytes(8).map_err(truncated)?;
+    let encoding = reader.read_u8().map_err(truncated)?;
+    reader.read_u8().map_err(truncated)?;
+
+    let (channel_count, bits_per_sample, len) = match encoding {
+      STANDARD_SOUND_HEADER => (1, 8, length_or_channels as usize),
+      EXTENDED_SOUND_HEADER => {
+        let frame_count = reader.read_u32().map_err(truncated)? as usize;
+        reader.read_bytes(22).map_err(truncated)?;
+        let bits_per_sample = reader.read_u16().map_err(truncated)?;
+        reader.read_bytes(14).map_err(truncated)?;
+        let channel_count = length_or_channels as u16;
+        (channel_count, bits_per_sample, frame_count * channel_count as usize * (bits_per_sample as usize / 8))
+      }
+      _ => return Err("Compressed sounds are not supported".to_string()),
+    };
+    if bits_per_sample != 8 && bits_per_sample != 16 {
+      return Err(format!("{}-bit sounds are not supported", bits_per_sample));
+    }
+
+    let len = len.min(reader.length.saturating_sub(reader.pos));
+    let data = reader.read_bytes(len).map_err(truncated)?;
+    let samples = if bits_per_sample == 16 {
+      data.chunks_exact(2).flat_map(|sample| [sample[1], sample[0]]).collect()
+    } else {
+      data.to_vec()
+    };
+    Ok(SoundChunk {
+      sample_rate,
+      channel_count,
+      bits_per_sample,
+      samples: Rc::from(samples),
+    })
+  }
+}
@@ -1,17 +1,19 @@
 use binary_reader::BinaryReader;
 
-use crate::io::reader::DirectorExt;
+use crate::io::{reader::DirectorExt, text_encoding::TextEncoding};
 
 pub struct TextChunk {
   pub offset: usize,
   pub text_length: usize,
   pub data_length: usize,
   pub text: String,
+  /// The encoding the text was decoded with.
+  pub encoding: TextEncoding,
   pub data: Vec<u8>,
 }
 
 impl TextChunk {
-  pub fn read(reader: &mut BinaryReader) -> Result<TextChunk, String> {
+  pub fn read(reader: &mut BinaryReader, encoding: TextEncoding) -> Result<TextChunk, String> {
     reader.set_endian(binary_reader::Endian::Big);
 
     let offset = reader.read_u32().unwrap() as usize;
@@ -26,7 +28,8 @@ impl TextChunk {
       offset,
       text_length,
       data_length,
-      text: reader.read_string(text_length).unwrap(),
+      text: reader.read_encoded_string(text_length, encoding).unwrap(),
+      encoding,
       data: reader.read_bytes(data_length).unwrap().to_vec(),
     })
   }
@@ -0,0 +1,104 @@
+use std::{collections::HashMap, fmt::Write};
+
+use itertools::Itertools;
+
+use super::{file::DirectorFile, guid::{MoaID, FONTMAP_COMPRESSION_GUID, NULL_COMPRESSION_GUID, SND_COMPRESSION_GUID, ZLIB_COMPRESSION_GUID, ZLIB_COMPRESSION_GUID2}, utils::fourcc_to_string};
+
+fn compression_name(compression_id: &MoaID) -> String {
+  if *compression_id == ZLIB_COMPRESSION_GUID || *compression_id == ZLIB_COMPRESSION_GUID2 {
+    "zlib".to_owned()
+  } else if *compression_id == SND_COMPRESSION_GUID {
+    "sound".to_owned()
+  } else if *compression_id == FONTMAP_COMPRESSION_GUID {
+    "fontmap".to_owned()
+  } else if *compression_id == NULL_COMPRESSION_GUID {
+    "none".to_owned()
+  } else {
+    compression_id.to_string()
+  }
+}
+
+/// A readable tree of the chunks of a file, the member or cast owning each of them
+/// and the problems found while reading it, meant to be attached to issue reports.
+pub fn dump_director_file(file: &DirectorFile) -> String {
+  let mut owners: HashMap<u32, String> = HashMap::new();
+  for cast in &file.casts {
+    owners.insert(cast.id, format!("cast \"{}\"", cast.name));
+    for (number, member) in &cast.members {
+      let name = member.chunk.member_info.as_ref().map_or("", |info| info.name.as_str());
+      owners.insert(member.section_id, format!("member {} \"{}\" of cast \"{}\"", number, name, cast.name));
+    }
+  }
+  let chunk_owners: HashMap<u32, u32> = file.key_table.entries.iter()
+    .take(file.key_table.used_count as usize)
+    .map(|entry| (entry.section_id, entry.cast_id))
+    .collect();
+  let describe_chunk = |id: u32| {
+    owners.get(&id).cloned().unwrap_or_else(|| {
+      match file.chunk_container.chunk_info.get(&id) {
+        Some(info) => format!("{} #{}", fourcc_to_string(info.fourcc), id),
+        None => format!("#{}", id),
+      }
+    })
+  };
+
+  let mut dump = String::new();
+  let _ = writeln!(dump, "File {} (Director {})", file.file_name, file.version);
+  if file.after_burned {
+    let _ = writeln!(dump, "Afterburned, ILS body at {}", file.ils_body_offset);
+  }
+
+  let _ = writeln!(dump, "Chunks ({}):", file.chunk_container.chunk_info.len());
+  for (id, info) in file.chunk_container.chunk_info.iter().sorted_by_key(|(id, _)| **id) {
+    let _ = write!(
+      dump,
+      "  #{:<6} {:<4} {} bytes ({} uncompressed) at {}, {}",
+      id,
+      fourcc_to_string(info.fourcc),
+      info.len,
+      info.uncompressed_len,
+      info.offset,
+      compression_name(&info.compression_id),
+    );
+    if let Some(owner_id) = chunk_owners.get(id) {
+      let _ = write!(dump, ", owned by {}", describe_chunk(*owner_id));
+    } else if owners.contains_key(id) {
+      let _ = write!(dump, ", {}", describe_chunk(*id));
+    }
+    if !file.chunk_container.cached_chunk_views.contains_key(id) && !file.chunk_container.read_chunk_ids.contains(id) {
+      let _ = write!(dump, ", not read");
+    }
+    dump.push('\n');
+  }
+
+  let _ = writeln!(dump, "Casts ({}):", file.casts.len());
+  for cast in &file.casts {
+    let entry = file.cast_entries.iter().find(|entry| entry.id == cast.id);
+    let _ = write!(dump, "  {} ({}): {} members", cast.name, cast.id, cast.members.len());
+    if let Some(entry) = entry {
+      let _ = write!(dump, " in {}..{}", entry.min_member, entry.max_member);
+      if !entry.file_path.is_empty() {
+        let _ = write!(dump, ", file {}", entry.file_path);
+      }
+    }
+    if let Some(lctx) = &cast.lctx {
+      let _ = write!(dump, ", {} scripts", lctx.scripts.len());
+    }
+    let unread_children = cast.members.values()
+      .map(|member| member.children.iter().filter(|child| child.is_none()).count())
+      .sum::<usize>();
+    if !cast.pending_members.is_empty() {
+      let _ = write!(dump, ", {} members still downloading", cast.pending_members.len());
+    }
+    if unread_children > 0 {
+      let _ = write!(dump, ", {} unreadable member chunks", unread_children);
+    }
+    dump.push('\n');
+  }
+
+  let _ = writeln!(dump, "Warnings ({}):", file.warnings.len());
+  for warning in &file.warnings {
+    let _ = writeln!(dump, "  {}", warning);
+  }
+  dump
+}
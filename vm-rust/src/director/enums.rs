@@ -51,6 +51,8 @@ pub struct BitmapInfo {
 	pub reg_y: i16,
 	pub bit_depth: u8,
 	pub palette_id: i16,
+	/// Bytes in each row of the image as it was saved, padding included.
+	pub pitch: u16,
 }
 
 #[derive(Clone)]
@@ -77,8 +79,8 @@ impl From<&[u8]> for BitmapInfo {
 		let mut reader = BinaryReader::from_u8(bytes);
 		reader.set_endian(binary_reader::Endian::Big);
 
-		reader.read_u8().unwrap();
-		reader.read_u8().unwrap(); // Logo -> 16
+		// The top bits are flags
+		let pitch = reader.read_u16().unwrap() & 0x3FFF;
 		reader.read_u32().unwrap();
 		let height = reader.read_u16().unwrap();
 		let width = reader.read_u16().unwrap();
@@ -107,6 +109,7 @@ impl From<&[u8]> for BitmapInfo {
 			reg_y,
 			bit_depth,
 			palette_id,
+			pitch,
 		}
 	}
 }
@@ -192,4 +195,91 @@ impl From<&[u8]> for FilmLoopInfo {
 			loops,
 		}
 	}
-}
\ No newline at end of file
+}
+/// How the box of a field fits its text, the `boxType`.
+#[derive(Clone, Copy)]
+pub enum FieldBoxType {
+	Adjust,
+	Scroll,
+	Fixed,
+	Limit,
+}
+
+impl FieldBoxType {
+	pub fn symbol_string(&self) -> &'static str {
+		match self {
+			FieldBoxType::Adjust => "adjust",
+			FieldBoxType::Scroll => "scroll",
+			FieldBoxType::Fixed => "fixed",
+			FieldBoxType::Limit => "limit",
+		}
+	}
+}
+
+#[derive(Clone)]
+#[allow(dead_code)]
+pub struct FieldInfo {
+	pub border: u8,
+	pub margin: u8,
+	pub box_drop_shadow: u8,
+	pub box_type: FieldBoxType,
+	/// 0 for left, 1 for center and -1 for right.
+	pub alignment: i16,
+	pub scroll_top: u16,
+	/// The rect of the box as top, left, bottom, right.
+	pub rect: (i16, i16, i16, i16),
+	pub max_height: u16,
+	pub text_drop_shadow: u8,
+	/// 1 for editable, 2 for autoTab and 4 for no word wrap.
+	pub flags: u8,
+}
+
+impl FieldInfo {
+	/// Reads the specific data of a field from Director 4 on, or None when it's too short.
+	pub fn read(bytes: &[u8]) -> Option<FieldInfo> {
+		let mut reader = BinaryReader::from_u8(bytes);
+		reader.set_endian(binary_reader::Endian::Big);
+
+		let border = reader.read_u8().ok()?;
+		let margin = reader.read_u8().ok()?;
+		let box_drop_shadow = reader.read_u8().ok()?;
+		let box_type = match reader.read_u8().ok()? {
+			1 => FieldBoxType::Scroll,
+			2 => FieldBoxType::Fixed,
+			3 => FieldBoxType::Limit,
+			_ => FieldBoxType::Adjust,
+		};
+		let alignment = reader.read_i16().ok()?;
+		// The background color as three 16-bit components
+		reader.read_bytes(6).ok()?;
+		let scroll_top = reader.read_u16().ok()?;
+		let top = reader.read_i16().ok()?;
+		let left = reader.read_i16().ok()?;
+		let bottom = reader.read_i16().ok()?;
+		let right = reader.read_i16().ok()?;
+		let max_height = reader.read_u16().ok()?;
+		let text_drop_shadow = reader.read_u8().ok()?;
+		let flags = reader.read_u8().ok()?;
+
+		Some(FieldInfo {
+			border,
+			margin,
+			box_drop_shadow,
+			box_type,
+			alignment,
+			scroll_top,
+			rect: (top, left, bottom, right),
+			max_height,
+			text_drop_shadow,
+			flags,
+		})
+	}
+
+	pub fn width(&self) -> u16 {
+		(self.rect.3 as i32 - self.rect.1 as i32).max(0) as u16
+	}
+
+	pub fn height(&self) -> u16 {
+		(self.rect.2 as i32 - self.rect.0 as i32).max(0) as u16
+	}
+}
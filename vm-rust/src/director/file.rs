@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
 use binary_reader::BinaryReader;
@@ -8,6 +8,7 @@ use url::Url;
 use crate::console_warn;
 use crate::director::rifx::RIFXReaderContext;
 use crate::io::reader::DirectorExt;
+use crate::io::text_encoding::TextEncoding;
 use crate::director::utils::*;
 use crate::director::guid::*;
 use crate::director::chunks::key_table::KeyTableChunk;
@@ -23,8 +24,10 @@ use super::chunks::cast::CastChunk;
 use super::chunks::cast_list::CastListChunk;
 use super::chunks::cast_list::CastListEntry;
 use super::chunks::cast_member::CastMemberChunk;
+use super::chunks::cast_member::CastMemberDef;
 use super::chunks::key_table::KeyTableEntry;
 use super::chunks::lctx::ScriptContextChunk;
+use super::chunks::is_media_chunk;
 use super::chunks::make_chunk;
 use super::chunks::score::ScoreChunk;
 use super::chunks::script::ScriptChunk;
@@ -33,7 +36,7 @@ use super::chunks::script_names::ScriptNamesChunk;
 pub struct DirectorFile {
   pub base_path: Url,
   pub file_name: String,
-  //pub endian: Endian,
+  pub endian: binary_reader::Endian,
   pub version: u16,
   pub cast_entries: Vec<CastListEntry>,
   pub casts: Vec<CastDef>,
@@ -41,6 +44,13 @@ pub struct DirectorFile {
   pub score: Option<ScoreChunk>,
   pub frame_labels: Option<FrameLabelsChunk>,
   pub chunk_container: ChunkContainer,
+  pub key_table: KeyTableChunk,
+  pub after_burned: bool,
+  pub ils_body_offset: usize,
+  /// The encoding text, script strings and names were decoded with.
+  pub text_encoding: TextEncoding,
+  /// Problems found while reading the file that didn't stop it from loading.
+  pub warnings: Vec<String>,
 }
 
 // macro_rules! console_log {
@@ -55,13 +65,15 @@ impl DirectorFile {
     file_name: String,
     base_path: Url,
     reader: &mut BinaryReader,
+    text_encoding: Option<TextEncoding>,
   ) -> Result<DirectorFile, String> {
     reader.set_endian(binary_reader::Endian::Big);
 
     let mut chunk_container = ChunkContainer {
       cached_chunk_views: HashMap::new(),
       chunk_info: HashMap::new(),
-      deserialized_chunks: HashMap::new()
+      deserialized_chunks: HashMap::new(),
+      read_chunk_ids: HashSet::new(),
     };
 
     let meta_fourcc = reader.read_u32().unwrap();
@@ -74,17 +86,23 @@ impl DirectorFile {
     let codec = reader.read_u32().unwrap();
     let mut after_burned = false;
     let mut ils_body_offset: usize = 0;
+    let mut warnings = vec![];
 
     if codec == FOURCC("MV93") || codec == FOURCC("MC95") {
-      // read memory map
-      return Err("read mmap not implemented".to_owned());
+      read_memory_map(reader, &mut chunk_container.chunk_info)?;
     } else if codec == FOURCC("FGDM") || codec == FOURCC("FGDC") {
       after_burned = true;
-      ils_body_offset = read_after_burner_map(
+      read_after_burner_map(
         reader, 
-        &mut chunk_container.cached_chunk_views, 
-        &mut chunk_container.chunk_info
-      ).unwrap();
+        &mut chunk_container.chunk_info,
+        &mut warnings,
+      )?;
+      ils_body_offset = read_initial_load_segment(
+        reader,
+        &mut chunk_container.cached_chunk_views,
+        &chunk_container.chunk_info,
+        &mut warnings,
+      )?;
     } else {
       return Err("Invalid codec".to_owned());
     }
@@ -94,6 +112,8 @@ impl DirectorFile {
       ils_body_offset: ils_body_offset,
       dir_version: 0,
       lctx_capital_x: false,
+      text_encoding: TextEncoding::MacRoman,
+      warnings,
     };
 
     let key_table = read_key_table(
@@ -109,6 +129,7 @@ impl DirectorFile {
     ).unwrap();
 
     rifx.dir_version = human_version(config.director_version);
+    rifx.text_encoding = text_encoding.unwrap_or_else(|| TextEncoding::from_platform(config.platform));
     let dot_syntax = rifx.dir_version >= 700;
 
     // info!("width={}, height={}", config.movie_right - config.movie_left, config.movie_bottom - config.movie_top);
@@ -135,6 +156,7 @@ impl DirectorFile {
     return Ok(DirectorFile { 
       base_path, 
       file_name, 
+      endian: reader.endian,
       version: rifx.dir_version,
       casts,
       cast_entries,
@@ -142,6 +164,11 @@ impl DirectorFile {
       score,
       frame_labels,
       chunk_container,
+      key_table,
+      after_burned,
+      ils_body_offset,
+      text_encoding: rifx.text_encoding,
+      warnings: rifx.warnings,
     });
   }
 }
@@ -185,6 +212,9 @@ fn read_casts(
           key_table,
           &cast_entry.id
         );
+        if cast.is_none() {
+          rifx.warn(format!("Cast {} ({}) has no CAS* chunk", cast_entry.name, cast_entry.id));
+        }
         if let Some(cast) = cast {
           // TODO cast.populate(castEntry.name, castEntry.id, castEntry.minMember);
           // info!("Cast {} member count: {}", cast_entry.name, cast.member_ids.len());
@@ -274,18 +304,18 @@ pub fn get_cast_member_chunk(
   chunk_container: &mut ChunkContainer,
   rifx: &mut RIFXReaderContext,
   section_id: u32
-) -> CastMemberChunk {
+) -> Result<CastMemberChunk, String> {
   let chunk = get_chunk(
     reader, 
     chunk_container,
     rifx,
     FOURCC("CASt"), 
     section_id,
-  ).unwrap();
+  )?;
   if let Chunk::CastMember(member_chunk) = chunk {
-    return member_chunk;
+    Ok(member_chunk)
   } else {
-    panic!("Not a cast member chunk");
+    Err(format!("Chunk {section_id} is not a cast member chunk"))
   }
 }
 
@@ -478,9 +508,9 @@ fn read_config(
 
 fn read_after_burner_map(
   reader: &mut BinaryReader,
-  cached_chunk_views: &mut HashMap<u32, Vec<u8>>,
-  chunk_info: &mut HashMap<u32, ChunkInfo>
-) -> Result<usize, String> {
+  chunk_info: &mut HashMap<u32, ChunkInfo>,
+  warnings: &mut Vec<String>,
+) -> Result<(), String> {
   let start: usize;
   let end: usize;
 
@@ -530,7 +560,9 @@ fn read_after_burner_map(
   // }
 
   if fcdr_reader.pos != fcdr_reader.length {
-    warn!("readAfterburnerMap(): Fcdr has uncompressed length {} but read {} bytes", fcdr_reader.length, fcdr_reader.pos);
+    let message = format!("readAfterburnerMap(): Fcdr has uncompressed length {} but read {} bytes", fcdr_reader.length, fcdr_reader.pos);
+    warn!("{}", message);
+    warnings.push(message);
   }
 
   // info!("Fcdr: {} compression types", compression_type_count);
@@ -553,7 +585,9 @@ fn read_after_burner_map(
 
   let abmp_uncomp = reader.read_zlib_bytes(abmp_end - reader.pos).unwrap();
   if abmp_uncomp.len() != abmp_uncomp_length as usize {
-    warn!("ABMP: Expected uncompressed length {} but got length {}", abmp_uncomp_length, abmp_uncomp.len());
+    let message = format!("ABMP: Expected uncompressed length {} but got length {}", abmp_uncomp_length, abmp_uncomp.len());
+    warn!("{}", message);
+    warnings.push(message);
   }
   let mut abmp_reader = BinaryReader::from_vec(&abmp_uncomp);
   abmp_reader.set_endian(reader.endian);
@@ -591,8 +625,59 @@ fn read_after_burner_map(
     };
     chunk_info.insert(res_id, info);
   }
+  Ok(())
+}
+
+/// Reads the memory map of an uncompressed file, which the initial map points to and
+/// which holds the offset of each chunk's header.
+fn read_memory_map(reader: &mut BinaryReader, chunk_info: &mut HashMap<u32, ChunkInfo>) -> Result<(), String> {
+  let truncated = |_| "Memory map ends early".to_string();
+  if reader.read_u32().map_err(truncated)? != FOURCC("imap") {
+    return Err("imap expected but not found".to_owned());
+  }
+  reader.read_u32().map_err(truncated)?;
+  reader.read_u32().map_err(truncated)?;
+  let mmap_offset = reader.read_u32().map_err(truncated)? as usize;
 
-  // Initial load segment
+  reader.jmp(mmap_offset);
+  if reader.read_u32().map_err(truncated)? != FOURCC("mmap") {
+    return Err("mmap expected but not found".to_owned());
+  }
+  reader.read_u32().map_err(truncated)?;
+  let header_len = reader.read_u16().map_err(truncated)? as usize;
+  let entry_len = reader.read_u16().map_err(truncated)? as usize;
+  reader.read_u32().map_err(truncated)?;
+  let entry_count = reader.read_u32().map_err(truncated)?;
+
+  let entries_offset = mmap_offset + 8 + header_len;
+  for id in 0..entry_count {
+    reader.jmp(entries_offset + id as usize * entry_len);
+    let fourcc = reader.read_u32().map_err(truncated)?;
+    let len = reader.read_u32().map_err(truncated)? as usize;
+    let offset = reader.read_u32().map_err(truncated)? as usize;
+    if fourcc == FOURCC("free") || fourcc == FOURCC("junk") {
+      continue;
+    }
+    chunk_info.insert(id, ChunkInfo {
+      id,
+      fourcc,
+      len,
+      uncompressed_len: len,
+      offset,
+      compression_id: NULL_COMPRESSION_GUID,
+    });
+  }
+  Ok(())
+}
+
+/// Reads the initial load segment (ILS), which holds the chunks needed to start the
+/// movie, and returns the offset that the offsets of the other chunks are relative to.
+fn read_initial_load_segment(
+  reader: &mut BinaryReader,
+  cached_chunk_views: &mut HashMap<u32, Vec<u8>>,
+  chunk_info: &HashMap<u32, ChunkInfo>,
+  warnings: &mut Vec<String>,
+) -> Result<usize, String> {
   if !chunk_info.contains_key(&2) {
     return Err("readAfterburnerMap(): Map has no entry for ILS".to_owned());
   }
@@ -607,7 +692,9 @@ fn read_after_burner_map(
 
   let ils_uncomp = reader.read_zlib_bytes(ils_info.len).unwrap();
   if ils_uncomp.len() != ils_info.uncompressed_len {
-    warn!("ILS: Expected uncompressed length {} but got length {}", ils_info.uncompressed_len, ils_uncomp.len());
+    let message = format!("ILS: Expected uncompressed length {} but got length {}", ils_info.uncompressed_len, ils_uncomp.len());
+    warn!("{}", message);
+    warnings.push(message);
   }
 
   let mut ils_reader = BinaryReader::from_vec(&ils_uncomp);
@@ -713,20 +800,154 @@ fn read_chunk_data(reader: &mut BinaryReader, fourcc: u32, len: u32) -> Result<V
   return Ok(reader.read_bytes(use_len as usize).unwrap().to_vec());
 }
 
-pub fn read_director_file_bytes(bytes: &Vec<u8>, file_name: &str, base_path: &str) -> Result<DirectorFile, String> {
+/// Chunks holding member media. A streamed movie can start before these arrive.
+const STREAMED_CHUNK_FOURCCS: [&str; 7] = ["BITD", "ALFA", "Thum", "snd ", "sndH", "sndS", "ediM"];
+
+fn read_slice_var_int(bytes: &[u8], pos: &mut usize) -> Option<usize> {
+  let mut val: usize = 0;
+  loop {
+    let b = *bytes.get(*pos)?;
+    *pos += 1;
+    val = (val << 7) | (b & 0x7f) as usize;
+    if b >> 7 == 0 {
+      return Some(val);
+    }
+  }
+}
+
+/// How many bytes of an afterburned movie are needed before it can be read, which
+/// is everything but the media of its members. None while that isn't known yet
+/// because the chunk map or initial load segment is still downloading, or if the
+/// file can't be streamed.
+pub fn get_initial_load_size(bytes: &[u8]) -> Option<usize> {
+  // Skip the header, then the Fver, Fcdr and ABMP sections, each a fourcc followed
+  // by a length, to find where the FGEI section starts
+  let mut pos = 12;
+  for _ in 0..3 {
+    pos += 4;
+    let len = read_slice_var_int(bytes, &mut pos)?;
+    pos += len;
+  }
+  pos += 4;
+  read_slice_var_int(bytes, &mut pos)?;
+  if bytes.len() < pos {
+    return None;
+  }
+
+  let mut reader = BinaryReader::from_u8(&bytes[..pos]);
+  if reader.read_u32().ok()? == FOURCC("XFIR") {
+    reader.set_endian(binary_reader::Endian::Little);
+  }
+  reader.read_u32().ok()?;
+  let codec = reader.read_u32().ok()?;
+  if codec != FOURCC("FGDM") && codec != FOURCC("FGDC") {
+    return None;
+  }
+  let mut chunk_info = HashMap::new();
+  read_after_burner_map(&mut reader, &mut chunk_info, &mut vec![]).ok()?;
+  let ils_len = chunk_info.get(&2)?.len;
+  if bytes.len() < pos + ils_len {
+    return Some(pos + ils_len);
+  }
+
+  let mut ils_reader = BinaryReader::from_u8(&bytes[..pos + ils_len]);
+  ils_reader.set_endian(reader.endian);
+  ils_reader.jmp(reader.pos);
+  let mut cached_chunk_views = HashMap::new();
+  let ils_body_offset = read_initial_load_segment(&mut ils_reader, &mut cached_chunk_views, &chunk_info, &mut vec![]).ok()?;
+  let streamed_fourccs = STREAMED_CHUNK_FOURCCS.map(FOURCC);
+  let required_end = chunk_info.values()
+    .filter(|info| !cached_chunk_views.contains_key(&info.id) && !streamed_fourccs.contains(&info.fourcc))
+    .filter_map(|info| info.offset.checked_add(ils_body_offset + info.len))
+    .max()
+    .unwrap_or(0);
+  Some(required_end.max(ils_body_offset + ils_len))
+}
+
+impl DirectorFile {
+  /// Reads the members of a streamed movie whose media has been downloaded since the
+  /// last call, given the data of the file downloaded so far. Returns the id of the
+  /// cast, the member number and the member of each. Members whose chunks haven't
+  /// arrived yet stay pending.
+  pub fn read_streamed_members(&mut self, bytes: &[u8]) -> Vec<(u32, u32, CastMemberDef)> {
+    let mut reader = BinaryReader::from_u8(bytes);
+    reader.set_endian(self.endian);
+    let mut members = vec![];
+    let chunk_container = &mut self.chunk_container;
+    let key_table = &self.key_table;
+    for cast in self.casts.iter_mut() {
+      let mut rifx = RIFXReaderContext {
+        after_burned: self.after_burned,
+        ils_body_offset: self.ils_body_offset,
+        dir_version: self.version,
+        lctx_capital_x: cast.capital_x,
+        text_encoding: self.text_encoding,
+        warnings: vec![],
+      };
+      let downloaded_members = cast.pending_members.iter()
+        .filter(|(_, section_id)| is_member_downloaded(chunk_container, &rifx, key_table, bytes.len(), **section_id))
+        .map(|(member_id, section_id)| (*member_id, *section_id))
+        .sorted()
+        .collect_vec();
+      for (member_id, section_id) in downloaded_members {
+        cast.pending_members.remove(&member_id);
+        for entry in get_children_of_chunk(&section_id, key_table) {
+          if let Err(err) = cache_streamed_chunk(bytes, chunk_container, &mut rifx, entry.section_id) {
+            rifx.warn(format!("Could not read streamed chunk {}: {err}", entry.section_id));
+          }
+        }
+        match CastDef::read_member(&cast.name, member_id, section_id, &mut reader, chunk_container, &mut rifx, key_table) {
+          Ok(member_def) => members.push((cast.id, member_id, member_def)),
+          Err(err) => rifx.warn(format!("Could not read streamed member {member_id} in cast {}: {err}", cast.name)),
+        }
+      }
+      self.warnings.append(&mut rifx.warnings);
+    }
+    members
+  }
+
+  pub fn is_member_pending(&self, cast_id: u32, member_id: u32) -> bool {
+    self.casts.iter().any(|cast| cast.id == cast_id && cast.pending_members.contains_key(&member_id))
+  }
+}
+
+/// Decodes a downloaded chunk of a streamed movie into the chunk cache, so it can be
+/// read without a reader over the whole file.
+fn cache_streamed_chunk(bytes: &[u8], chunk_container: &mut ChunkContainer, rifx: &mut RIFXReaderContext, id: u32) -> Result<(), String> {
+  if chunk_container.cached_chunk_views.contains_key(&id) || chunk_container.read_chunk_ids.contains(&id) {
+    return Ok(());
+  }
+  let info = match chunk_container.chunk_info.get(&id) {
+    Some(info) => info,
+    None => return Ok(()),
+  };
+  let start = info.offset + rifx.ils_body_offset;
+  let data = start.checked_add(info.len)
+    .and_then(|end| bytes.get(start..end))
+    .ok_or_else(|| format!("Chunk {id} is not downloaded yet"))?;
+  let mut reader = BinaryReader::from_u8(data);
+  let data = read_after_burned_chunk_data(&mut reader, rifx, info)?;
+  chunk_container.cached_chunk_views.insert(id, data);
+  Ok(())
+}
+
+/// Reads a movie or cast file, decoding its text with `text_encoding` instead of the
+/// encoding of the platform it was authored on when given.
+pub fn read_director_file_bytes(bytes: &Vec<u8>, file_name: &str, base_path: &str, text_encoding: Option<TextEncoding>) -> Result<DirectorFile, String> {
   let mut reader = binary_reader::BinaryReader::from_vec(bytes);
   
   return DirectorFile::read(
     file_name.to_owned(),
     Url::from_str(base_path).unwrap(),
     &mut reader,
+    text_encoding,
   );
 }
 
 fn get_chunk_data(
   reader: &mut BinaryReader,
   chunk_container: &mut ChunkContainer,
-  rifx: &RIFXReaderContext,
+  rifx: &mut RIFXReaderContext,
   fourcc: u32, 
   id: u32,
 ) -> Result<Vec<u8>, String>{
@@ -746,47 +967,77 @@ fn get_chunk_data(
         );
       }
 
-      if chunk_container.cached_chunk_views.contains_key(&id) {
-        return Ok(chunk_container.cached_chunk_views.get(&id).unwrap().to_vec());
+      let data = if is_media_chunk(fourcc) && chunk_container.cached_chunk_views.contains_key(&id) {
+        chunk_container.cached_chunk_views.remove(&id).unwrap()
+      } else if let Some(view) = chunk_container.cached_chunk_views.get(&id) {
+        view.to_vec()
       } else if rifx.after_burned {
-        reader.jmp(info.offset + rifx.ils_body_offset);
-        if info.len == 0 && info.uncompressed_len == 0 {
-          chunk_container.cached_chunk_views.insert(id, reader.read_bytes(info.len).unwrap().to_vec());
-        } else if compression_implemented(&info.compression_id) {
-          let mut uncomp_buf: Option<Vec<u8>> = None;
-          if info.compression_id == ZLIB_COMPRESSION_GUID || info.compression_id == ZLIB_COMPRESSION_GUID2 {
-            uncomp_buf = Some(reader.read_zlib_bytes(info.len).unwrap());
-          } else if info.compression_id == SND_COMPRESSION_GUID {
-            // TODO line 406-409
-            return Err("TODO".to_owned());
-          }
-          if uncomp_buf.is_none() {
-            return Err(format!("Chunk ${id}: Could not decompress").to_string());
-          }
-          let uncomp_buf = uncomp_buf.unwrap();
-          if uncomp_buf.len() != info.uncompressed_len {
-            return Err(format_args!("Chunk ${id}: Expected uncompressed length {} but got length {}", info.uncompressed_len, uncomp_buf.len()).to_string());
-          }
-          chunk_container.cached_chunk_views.insert(id, uncomp_buf.to_vec());
-        } else if info.compression_id == FONTMAP_COMPRESSION_GUID {
-          return Err("TODO".to_owned());
-        } else {
-          if info.compression_id != NULL_COMPRESSION_GUID {
-            warn!("Unhandled compression type {}", info.compression_id)
-          }
-          chunk_container.cached_chunk_views.insert(id, reader.read_bytes(info.len).unwrap().to_vec());
+        if !is_chunk_downloaded(chunk_container, rifx, reader.length, id) {
+          return Err(format!("Chunk {id} is not downloaded yet"));
         }
+        reader.jmp(info.offset + rifx.ils_body_offset);
+        read_after_burned_chunk_data(reader, rifx, info)?
       } else {
         reader.jmp(info.offset);
-        chunk_container.cached_chunk_views.insert(id, read_chunk_data(reader, fourcc, id).unwrap());
-      }
-
-      return Ok(chunk_container.cached_chunk_views.get(&id).unwrap().to_vec());
+        read_chunk_data(reader, fourcc, info.len as u32)?
+      };
+      chunk_container.read_chunk_ids.insert(id);
+      Ok(data)
     }
     None => Err(format_args!("Could not find chunk {} ${id}", fourcc_to_string(fourcc)).to_string())
   }
 }
 
+/// Reads a chunk of an afterburned file at the position of the reader.
+fn read_after_burned_chunk_data(reader: &mut BinaryReader, rifx: &mut RIFXReaderContext, info: &ChunkInfo) -> Result<Vec<u8>, String> {
+  let id = info.id;
+  if info.len == 0 && info.uncompressed_len == 0 {
+    Ok(vec![])
+  } else if compression_implemented(&info.compression_id) {
+    let mut uncomp_buf: Option<Vec<u8>> = None;
+    if info.compression_id == ZLIB_COMPRESSION_GUID || info.compression_id == ZLIB_COMPRESSION_GUID2 {
+      uncomp_buf = Some(reader.read_zlib_bytes(info.len).unwrap());
+    } else if info.compression_id == SND_COMPRESSION_GUID {
+      // TODO line 406-409
+      return Err("TODO".to_owned());
+    }
+    if uncomp_buf.is_none() {
+      return Err(format!("Chunk ${id}: Could not decompress").to_string());
+    }
+    let uncomp_buf = uncomp_buf.unwrap();
+    if uncomp_buf.len() != info.uncompressed_len {
+      return Err(format_args!("Chunk ${id}: Expected uncompressed length {} but got length {}", info.uncompressed_len, uncomp_buf.len()).to_string());
+    }
+    Ok(uncomp_buf)
+  } else if info.compression_id == FONTMAP_COMPRESSION_GUID {
+    Err("TODO".to_owned())
+  } else {
+    if info.compression_id != NULL_COMPRESSION_GUID {
+      rifx.warn(format!("Chunk {id}: Unhandled compression type {}", info.compression_id));
+    }
+    Ok(reader.read_bytes(info.len).unwrap().to_vec())
+  }
+}
+
+/// Whether the data of a chunk is in the first `available_len` bytes of the file,
+/// which for a streamed movie is the part downloaded so far.
+pub fn is_chunk_downloaded(chunk_container: &ChunkContainer, rifx: &RIFXReaderContext, available_len: usize, id: u32) -> bool {
+  if !rifx.after_burned || chunk_container.cached_chunk_views.contains_key(&id) || chunk_container.read_chunk_ids.contains(&id) {
+    return true;
+  }
+  match chunk_container.chunk_info.get(&id) {
+    Some(info) => info.offset.checked_add(rifx.ils_body_offset + info.len).is_some_and(|end| end <= available_len),
+    None => true,
+  }
+}
+
+/// Whether the member chunk and all of its children are downloaded.
+pub fn is_member_downloaded(chunk_container: &ChunkContainer, rifx: &RIFXReaderContext, key_table: &KeyTableChunk, available_len: usize, section_id: u32) -> bool {
+  is_chunk_downloaded(chunk_container, rifx, available_len, section_id)
+    && get_children_of_chunk(&section_id, key_table).iter()
+      .all(|entry| is_chunk_downloaded(chunk_container, rifx, available_len, entry.section_id))
+}
+
 pub fn get_chunk(
   reader: &mut BinaryReader,
   // endian: Endian,
@@ -811,7 +1062,7 @@ pub fn get_chunk(
       reader.endian, 
       rifx, 
       fourcc, 
-      &chunk_view
+      chunk_view
     );
     return chunk;
   } else {
@@ -0,0 +1,978 @@
+use fxhash::FxHashMap;
+use pest::iterators::Pair;
+
+use crate::{director::chunks::{handler::{Bytecode, HandlerDef}, script::ScriptChunk}, player::eval::{parse_script, Rule}};
+
+use super::{datum::Datum, opcode::OpCode, script::ScriptContext};
+
+#[derive(Clone)]
+enum Expr {
+  Int(i32),
+  Float(f32),
+  String(String),
+  Symbol(String),
+  Void,
+  Var(String),
+  TopLevel(String),
+  The(String),
+  Prop(Box<Expr>, String),
+  Index(Box<Expr>, Box<Expr>),
+  Call(String, Vec<Expr>),
+  ObjCall(Box<Expr>, String, Vec<Expr>),
+  List(Vec<Expr>),
+  PropList(Vec<(Expr, Expr)>),
+  Binary(OpCode, Box<Expr>, Box<Expr>),
+  Unary(OpCode, Box<Expr>),
+  /// A chunk like `char 1 to 3 of x`, with the chunk type from char to line as 0 to 3.
+  Chunk(usize, Box<Expr>, Option<Box<Expr>>, Box<Expr>),
+}
+
+enum Stmt {
+  Assign(Expr, Expr),
+  Call(Expr),
+  Put(Expr),
+  Return(Option<Expr>),
+  Exit,
+  ExitRepeat,
+  NextRepeat,
+  If(Expr, Vec<Stmt>, Vec<Stmt>),
+  RepeatWhile(Expr, Vec<Stmt>),
+  RepeatWith(String, Expr, Expr, bool, Vec<Stmt>),
+  RepeatIn(String, Expr, Vec<Stmt>),
+  Case(Expr, Vec<(Vec<Expr>, Vec<Stmt>)>, Option<Vec<Stmt>>),
+}
+
+struct HandlerSource {
+  name: String,
+  args: Vec<String>,
+  globals: Vec<String>,
+  body: Vec<Stmt>,
+}
+
+struct ScriptSource {
+  properties: Vec<String>,
+  globals: Vec<String>,
+  handlers: Vec<HandlerSource>,
+}
+
+/// Compiles the source of a script into a chunk the interpreter can run, adding the
+/// names it uses to the script context of the cast. `variable_multiplier` is the one
+/// of that cast.
+pub fn compile_script(source: &str, lctx: &mut ScriptContext, variable_multiplier: u32) -> Result<ScriptChunk, String> {
+  let script = parse_script(source).map_err(|err| err.message)?;
+  let ScriptSource { properties, globals, handlers } = build_script(script)?;
+
+  let mut names = lctx.names.clone();
+  let handler_names = handlers.iter().map(|handler| handler.name.clone()).collect::<Vec<_>>();
+  let mut literals = vec![];
+  let mut handler_defs = vec![];
+  for handler in handlers {
+    let mut compiler = HandlerCompiler {
+      names: &mut names,
+      literals: &mut literals,
+      handler_names: &handler_names,
+      properties: &properties,
+      globals: globals.iter().chain(&handler.globals).cloned().collect(),
+      args: handler.args.clone(),
+      locals: vec![],
+      variable_multiplier: variable_multiplier.max(1) as i64,
+      bytecodes: vec![],
+      pos: 0,
+      loops: vec![],
+      stack_depth: 0,
+    };
+    compiler.compile_block(&handler.body)?;
+    compiler.emit(OpCode::Ret, 0);
+    handler_defs.push(compiler.finish(&handler));
+  }
+  let property_name_ids = properties.iter().map(|name| name_id(&mut names, name)).collect();
+  lctx.names = names;
+  Ok(ScriptChunk { literals, handlers: handler_defs, property_name_ids })
+}
+
+fn name_id(names: &mut Vec<String>, name: &str) -> u16 {
+  match names.iter().position(|existing| existing == name) {
+    Some(id) => id as u16,
+    None => {
+      names.push(name.to_owned());
+      names.len() as u16 - 1
+    }
+  }
+}
+
+fn line_of(pair: &Pair<Rule>) -> usize {
+  pair.as_span().start_pos().line_col().0
+}
+
+fn error_at(pair: &Pair<Rule>, message: &str) -> String {
+  format!("Line {}: {}", line_of(pair), message)
+}
+
+fn build_names(pair: Pair<Rule>) -> Vec<String> {
+  pair.into_inner().map(|name| name.as_str().to_owned()).collect()
+}
+
+/// Turns the pairs of the `script` rule of lingo.pest into the handlers to compile.
+fn build_script(script: Pair<Rule>) -> Result<ScriptSource, String> {
+  let mut properties = vec![];
+  let mut globals = vec![];
+  let mut handlers: Vec<HandlerSource> = vec![];
+  for declaration in script.into_inner() {
+    match declaration.as_rule() {
+      Rule::script_property => properties.extend(build_names(declaration.into_inner().next().unwrap())),
+      Rule::script_global => globals.extend(build_names(declaration.into_inner().next().unwrap())),
+      Rule::script_handler => {
+        let line = line_of(&declaration);
+        let handler = build_handler(declaration)?;
+        if handlers.iter().any(|existing| existing.name.eq_ignore_ascii_case(&handler.name)) {
+          return Err(format!("Line {}: Handler {} is defined twice", line, handler.name));
+        }
+        handlers.push(handler);
+      }
+      _ => {}
+    }
+  }
+  Ok(ScriptSource { properties, globals, handlers })
+}
+
+fn build_handler(handler: Pair<Rule>) -> Result<HandlerSource, String> {
+  let mut inner = handler.into_inner();
+  let name = inner.next().unwrap().as_str().to_owned();
+  let mut args = vec![];
+  let mut globals = vec![];
+  let mut body = vec![];
+  for part in inner {
+    match part.as_rule() {
+      Rule::script_names => args = build_names(part),
+      Rule::script_block => body = build_statements(part, &mut globals)?,
+      Rule::script_name if !part.as_str().eq_ignore_ascii_case(&name) => {
+        return Err(error_at(&part, &format!("Expected end {}, found end {}", name, part.as_str())));
+      }
+      _ => {}
+    }
+  }
+  Ok(HandlerSource { name, args, globals, body })
+}
+
+/// Builds the statements of a block, of a statement on the line of an if, or of an
+/// else if. Globals declared on the way are added to those of the handler.
+fn build_statements(pair: Pair<Rule>, globals: &mut Vec<String>) -> Result<Vec<Stmt>, String> {
+  match pair.as_rule() {
+    Rule::script_block | Rule::script_line_block | Rule::script_case_body => {
+      let mut statements = vec![];
+      for statement in pair.into_inner() {
+        statements.extend(build_statement(statement, globals)?);
+      }
+      Ok(statements)
+    }
+    _ => Ok(build_statement(pair, globals)?.into_iter().collect()),
+  }
+}
+
+fn build_statement(pair: Pair<Rule>, globals: &mut Vec<String>) -> Result<Option<Stmt>, String> {
+  let rule = pair.as_rule();
+  let error = error_at(&pair, "Expected a statement");
+  let mut inner = pair.into_inner();
+  let statement = match rule {
+    Rule::script_local_global => {
+      globals.extend(build_names(inner.next().unwrap()));
+      return Ok(None);
+    }
+    Rule::script_if | Rule::script_else_if => {
+      let condition = build_expr(inner.next().unwrap())?;
+      let then_body = build_statements(inner.next().unwrap(), globals)?;
+      let mut else_body = vec![];
+      for part in inner {
+        else_body.extend(build_statements(part, globals)?);
+      }
+      Stmt::If(condition, then_body, else_body)
+    }
+    Rule::script_repeat_while => {
+      let condition = build_expr(inner.next().unwrap())?;
+      Stmt::RepeatWhile(condition, build_statements(inner.next().unwrap(), globals)?)
+    }
+    Rule::script_repeat_in => {
+      let var = inner.next().unwrap().as_str().to_owned();
+      let list = build_expr(inner.next().unwrap())?;
+      Stmt::RepeatIn(var, list, build_statements(inner.next().unwrap(), globals)?)
+    }
+    Rule::script_repeat_with => {
+      let var = inner.next().unwrap().as_str().to_owned();
+      let start = build_expr(inner.next().unwrap())?;
+      let is_down = inner.peek().is_some_and(|part| part.as_rule() == Rule::script_down);
+      if is_down {
+        inner.next();
+      }
+      let end = build_expr(inner.next().unwrap())?;
+      Stmt::RepeatWith(var, start, end, is_down, build_statements(inner.next().unwrap(), globals)?)
+    }
+    Rule::script_case => {
+      let value = build_expr(inner.next().unwrap())?;
+      let mut labels = vec![];
+      let mut otherwise = None;
+      for label in inner {
+        let is_otherwise = label.as_rule() == Rule::script_otherwise;
+        let mut label_inner = label.into_inner();
+        if is_otherwise {
+          otherwise = Some(build_statements(label_inner.next().unwrap(), globals)?);
+          continue;
+        }
+        let values = label_inner.next().unwrap().into_inner().map(build_expr).collect::<Result<Vec<_>, _>>()?;
+        labels.push((values, build_statements(label_inner.next().unwrap(), globals)?));
+      }
+      Stmt::Case(value, labels, otherwise)
+    }
+    Rule::script_exit_repeat => Stmt::ExitRepeat,
+    Rule::script_exit => Stmt::Exit,
+    Rule::script_next_repeat => Stmt::NextRepeat,
+    Rule::script_return => Stmt::Return(inner.next().map(build_expr).transpose()?),
+    Rule::script_put => {
+      let value = build_expr(inner.next().unwrap())?;
+      let put_type = match inner.next() {
+        Some(put_type) => put_type.as_str().to_lowercase(),
+        None => return Ok(Some(Stmt::Put(value))),
+      };
+      let target = match build_expr(inner.next().unwrap())? {
+        // Fields are written through the text of their member
+        Expr::Call(name, args) if name.eq_ignore_ascii_case("field") => {
+          Expr::Prop(Box::new(Expr::Call("member".to_owned(), args)), "text".to_owned())
+        }
+        target => target,
+      };
+      let value = match put_type.as_str() {
+        "after" => Expr::Binary(OpCode::JoinStr, Box::new(target.clone()), Box::new(value)),
+        "before" => Expr::Binary(OpCode::JoinStr, Box::new(value), Box::new(target.clone())),
+        _ => value,
+      };
+      Stmt::Assign(target, value)
+    }
+    Rule::script_go => Stmt::Call(Expr::Call("go".to_owned(), vec![build_expr(inner.next().unwrap())?])),
+    Rule::script_set | Rule::script_assign => {
+      let target = build_expr(inner.next().unwrap())?;
+      Stmt::Assign(target, build_expr(inner.next().unwrap())?)
+    }
+    Rule::script_command => {
+      let name = inner.next().unwrap().as_str().to_owned();
+      Stmt::Call(Expr::Call(name, inner.map(build_expr).collect::<Result<Vec<_>, _>>()?))
+    }
+    _ => match build_expr(inner.next().unwrap())? {
+      call @ (Expr::Call(..) | Expr::ObjCall(..)) => Stmt::Call(call),
+      _ => return Err(error),
+    },
+  };
+  Ok(Some(statement))
+}
+
+fn binary_opcode(op: &str) -> OpCode {
+  match op.to_lowercase().as_str() {
+    "or" => OpCode::Or,
+    "and" => OpCode::And,
+    "=" => OpCode::Eq,
+    "<>" => OpCode::NtEq,
+    "<" => OpCode::Lt,
+    "<=" => OpCode::LtEq,
+    ">" => OpCode::Gt,
+    ">=" => OpCode::GtEq,
+    "contains" => OpCode::ContainsStr,
+    "starts" => OpCode::Contains0Str,
+    "&" => OpCode::JoinStr,
+    "&&" => OpCode::JoinPadStr,
+    "+" => OpCode::Add,
+    "-" => OpCode::Sub,
+    "*" => OpCode::Mul,
+    "/" => OpCode::Div,
+    _ => OpCode::Mod,
+  }
+}
+
+fn build_args(args: Pair<Rule>) -> Result<Vec<Expr>, String> {
+  args.into_inner().map(build_expr).collect()
+}
+
+fn build_expr(pair: Pair<Rule>) -> Result<Expr, String> {
+  let rule = pair.as_rule();
+  let text = pair.as_str();
+  let mut inner = pair.clone().into_inner();
+  let expr = match rule {
+    // Operands separated by the operators of their precedence level
+    Rule::script_expr | Rule::script_and | Rule::script_comparison | Rule::script_concat | Rule::script_sum | Rule::script_product => {
+      let mut left = build_expr(inner.next().unwrap())?;
+      while let (Some(op), Some(right)) = (inner.next(), inner.next()) {
+        left = Expr::Binary(binary_opcode(op.as_str()), Box::new(left), Box::new(build_expr(right)?));
+      }
+      left
+    }
+    Rule::script_not | Rule::script_unary => {
+      let first = inner.next().unwrap();
+      match first.as_rule() {
+        Rule::debug_not_op => Expr::Unary(OpCode::Not, Box::new(build_expr(inner.next().unwrap())?)),
+        Rule::script_neg_op => match build_expr(inner.next().unwrap())? {
+          Expr::Int(value) => Expr::Int(-value),
+          Expr::Float(value) => Expr::Float(-value),
+          value => Expr::Unary(OpCode::Inv, Box::new(value)),
+        },
+        _ => build_expr(first)?,
+      }
+    }
+    Rule::script_postfix => {
+      let mut expr = build_expr(inner.next().unwrap())?;
+      for suffix in inner {
+        let is_member = suffix.as_rule() == Rule::script_member;
+        let mut suffix_inner = suffix.into_inner();
+        let part = suffix_inner.next().unwrap();
+        expr = match suffix_inner.next() {
+          Some(args) => Expr::ObjCall(Box::new(expr), part.as_str().to_owned(), build_args(args)?),
+          None if is_member => Expr::Prop(Box::new(expr), part.as_str().to_owned()),
+          None => Expr::Index(Box::new(expr), Box::new(build_expr(part)?)),
+        };
+      }
+      expr
+    }
+    Rule::number_int => match text.parse::<i32>() {
+      Ok(value) => Expr::Int(value),
+      Err(_) => Expr::Float(text.parse::<f32>().map_err(|_| error_at(&pair, &format!("Invalid number {}", text)))?),
+    },
+    Rule::number_float => Expr::Float(text.parse::<f32>().map_err(|_| error_at(&pair, &format!("Invalid number {}", text)))?),
+    Rule::script_string => Expr::String(inner.next().unwrap().as_str().to_owned()),
+    Rule::symbol => Expr::Symbol(inner.next().unwrap().as_str().to_owned()),
+    Rule::script_list => build_list(pair)?,
+    Rule::script_the => {
+      let prop = inner.next().unwrap().as_str().to_owned();
+      match inner.next() {
+        Some(obj) => Expr::Prop(Box::new(build_expr(obj)?), prop),
+        None => Expr::The(prop),
+      }
+    }
+    Rule::script_call => {
+      let name = inner.next().unwrap().as_str().to_owned();
+      Expr::Call(name, build_args(inner.next().unwrap())?)
+    }
+    Rule::script_chunk => {
+      let chunk_type = inner.next().unwrap().as_str();
+      let chunk_type = ["char", "word", "item", "line"].iter().position(|name| chunk_type.eq_ignore_ascii_case(name)).unwrap();
+      let mut parts = inner.map(build_expr).collect::<Result<Vec<_>, _>>()?;
+      let string = parts.pop().unwrap();
+      let last = if parts.len() > 1 { parts.pop().map(Box::new) } else { None };
+      Expr::Chunk(chunk_type, Box::new(parts.pop().unwrap()), last, Box::new(string))
+    }
+    // References like `sprite 1` and `member "a" of castLib 2`
+    Rule::script_ref => {
+      let name = inner.next().unwrap().as_str().to_owned();
+      Expr::Call(name, inner.map(build_expr).collect::<Result<Vec<_>, _>>()?)
+    }
+    Rule::script_name => match text.to_lowercase().as_str() {
+      "true" => Expr::Int(1),
+      "false" => Expr::Int(0),
+      "void" => Expr::Void,
+      "empty" => Expr::String(String::new()),
+      "return" => Expr::String("\r".to_owned()),
+      "enter" => Expr::String("\u{3}".to_owned()),
+      "quote" => Expr::String("\"".to_owned()),
+      "tab" => Expr::String("\t".to_owned()),
+      "space" => Expr::String(" ".to_owned()),
+      "backspace" => Expr::String("\u{8}".to_owned()),
+      "pi" => Expr::Float(std::f32::consts::PI),
+      "_movie" | "_player" => Expr::TopLevel(text.to_lowercase()),
+      _ => Expr::Var(text.to_owned()),
+    },
+    _ => return Err(error_at(&pair, "Expected an expression")),
+  };
+  Ok(expr)
+}
+
+fn build_list(list: Pair<Rule>) -> Result<Expr, String> {
+  let mut items = vec![];
+  let mut pairs = vec![];
+  for item in list.into_inner() {
+    if item.as_rule() == Rule::script_empty_props {
+      return Ok(Expr::PropList(vec![]));
+    }
+    let error = error_at(&item, "Expected a list or a property list, found both");
+    let mut item_inner = item.into_inner();
+    let key = build_expr(item_inner.next().unwrap())?;
+    match item_inner.next() {
+      Some(value) => {
+        // Bare words are symbols as property list keys
+        let key = match key {
+          Expr::Var(name) => Expr::Symbol(name),
+          key => key,
+        };
+        pairs.push((key, build_expr(value)?));
+      }
+      None => items.push(key),
+    }
+    if !items.is_empty() && !pairs.is_empty() {
+      return Err(error);
+    }
+  }
+  if pairs.is_empty() { Ok(Expr::List(items)) } else { Ok(Expr::PropList(pairs)) }
+}
+
+struct LoopLabels {
+  next_jumps: Vec<usize>,
+  exit_jumps: Vec<usize>,
+  /// How many values the enclosing statements keep on the stack inside the loop.
+  stack_depth: usize,
+}
+
+struct HandlerCompiler<'a> {
+  names: &'a mut Vec<String>,
+  literals: &'a mut Vec<Datum>,
+  handler_names: &'a [String],
+  properties: &'a [String],
+  globals: Vec<String>,
+  args: Vec<String>,
+  locals: Vec<String>,
+  variable_multiplier: i64,
+  bytecodes: Vec<Bytecode>,
+  pos: usize,
+  loops: Vec<LoopLabels>,
+  /// Values kept on the stack by case and `repeat with ... in` statements.
+  stack_depth: usize,
+}
+
+enum Var {
+  Param(usize),
+  Local(usize),
+  Prop,
+  Global,
+}
+
+impl<'a> HandlerCompiler<'a> {
+  fn emit(&mut self, opcode: OpCode, obj: i64) -> usize {
+    let arg_len = match opcode {
+      _ if (opcode as u16) < 0x40 => 0,
+      OpCode::Jmp | OpCode::JmpIfZ | OpCode::EndRepeat | OpCode::PushInt16 => 2,
+      OpCode::PushInt8 => 1,
+      OpCode::PushInt32 => 4,
+      _ if obj <= 0xff => 1,
+      _ if obj <= 0xffff => 2,
+      _ => 4,
+    };
+    self.bytecodes.push(Bytecode::new(opcode, obj, self.pos));
+    self.pos += 1 + arg_len;
+    self.bytecodes.len() - 1
+  }
+
+  fn emit_name(&mut self, opcode: OpCode, name: &str) {
+    let id = name_id(self.names, name);
+    self.emit(opcode, id as i64);
+  }
+
+  fn emit_literal(&mut self, literal: Datum) {
+    let index = match self.literals.iter().position(|existing| literal_equals(existing, &literal)) {
+      Some(index) => index,
+      None => {
+        self.literals.push(literal);
+        self.literals.len() - 1
+      }
+    };
+    self.emit(OpCode::PushCons, index as i64 * self.variable_multiplier);
+  }
+
+  fn emit_int(&mut self, value: i32) {
+    let opcode = if (-0x80..0x80).contains(&value) {
+      OpCode::PushInt8
+    } else if (-0x8000..0x8000).contains(&value) {
+      OpCode::PushInt16
+    } else {
+      OpCode::PushInt32
+    };
+    self.emit(opcode, value as i64);
+  }
+
+  /// Points a jump at the given position, or at the next bytecode.
+  fn patch_jump(&mut self, index: usize, target: Option<usize>) {
+    let target = target.unwrap_or(self.pos);
+    let bytecode = &mut self.bytecodes[index];
+    bytecode.obj = target as i64 - bytecode.pos as i64;
+  }
+
+  fn emit_end_repeat(&mut self, start: usize) {
+    let index = self.emit(OpCode::EndRepeat, 0);
+    self.bytecodes[index].obj = (self.bytecodes[index].pos - start) as i64;
+  }
+
+  fn resolve(&mut self, name: &str) -> Var {
+    let find = |names: &[String]| names.iter().position(|existing| existing.eq_ignore_ascii_case(name));
+    if let Some(index) = find(&self.args) {
+      Var::Param(index)
+    } else if let Some(index) = find(&self.locals) {
+      Var::Local(index)
+    } else if find(self.properties).is_some() {
+      Var::Prop
+    } else if find(&self.globals).is_some() {
+      Var::Global
+    } else {
+      self.locals.push(name.to_owned());
+      Var::Local(self.locals.len() - 1)
+    }
+  }
+
+  fn emit_var(&mut self, name: &str, is_set: bool) {
+    match self.resolve(name) {
+      Var::Param(index) => {
+        let opcode = if is_set { OpCode::SetParam } else { OpCode::GetParam };
+        self.emit(opcode, index as i64 * self.variable_multiplier);
+      }
+      Var::Local(index) => {
+        let opcode = if is_set { OpCode::SetLocal } else { OpCode::GetLocal };
+        self.emit(opcode, index as i64 * self.variable_multiplier);
+      }
+      Var::Prop => self.emit_name(if is_set { OpCode::SetProp } else { OpCode::GetProp }, name),
+      Var::Global => self.emit_name(if is_set { OpCode::SetGlobal } else { OpCode::GetGlobal }, name),
+    }
+  }
+
+  /// Pushes the arguments as an arg list, along with the values already on the stack
+  /// that are part of it, like the object of a method call.
+  fn compile_args(&mut self, args: &[Expr], pushed: usize, no_ret: bool) -> Result<(), String> {
+    for arg in args {
+      self.compile_expr(arg)?;
+    }
+    let opcode = if no_ret { OpCode::PushArgListNoRet } else { OpCode::PushArgList };
+    self.emit(opcode, (args.len() + pushed) as i64);
+    Ok(())
+  }
+
+  fn compile_call(&mut self, expr: &Expr, no_ret: bool) -> Result<(), String> {
+    match expr {
+      Expr::Call(name, args) => {
+        self.compile_args(args, 0, no_ret)?;
+        match self.handler_names.iter().position(|handler| handler.eq_ignore_ascii_case(name)) {
+          Some(index) => {
+            self.emit(OpCode::LocalCall, index as i64);
+          }
+          None => self.emit_name(OpCode::ExtCall, name),
+        }
+      }
+      Expr::ObjCall(obj, name, args) => {
+        self.compile_expr(obj)?;
+        self.compile_args(args, 1, no_ret)?;
+        self.emit_name(OpCode::ObjCall, name);
+      }
+      _ => return Err("Expected a call".to_owned()),
+    }
+    Ok(())
+  }
+
+  fn compile_expr(&mut self, expr: &Expr) -> Result<(), String> {
+    match expr {
+      Expr::Int(value) => self.emit_int(*value),
+      Expr::Float(value) => self.emit_literal(Datum::Float(*value)),
+      Expr::String(value) => self.emit_literal(Datum::String(value.clone())),
+      Expr::Void => self.emit_literal(Datum::Void),
+      Expr::Symbol(name) => self.emit_name(OpCode::PushSymb, name),
+      Expr::Var(name) => self.emit_var(name, false),
+      Expr::TopLevel(name) => self.emit_name(OpCode::GetTopLevelProp, name),
+      Expr::The(name) => self.emit_name(OpCode::GetMovieProp, name),
+      Expr::Prop(obj, name) => {
+        self.compile_expr(obj)?;
+        self.emit_name(OpCode::GetObjProp, name);
+      }
+      Expr::Index(obj, index) => {
+        self.compile_expr(obj)?;
+        self.compile_expr(index)?;
+        self.emit(OpCode::PushArgList, 2);
+        self.emit_name(OpCode::ObjCall, "getAt");
+      }
+      Expr::Call(..) | Expr::ObjCall(..) => self.compile_call(expr, false)?,
+      Expr::List(items) => {
+        self.compile_args(items, 0, false)?;
+        self.emit(OpCode::PushList, 0);
+      }
+      Expr::PropList(pairs) => {
+        for (key, value) in pairs {
+          self.compile_expr(key)?;
+          self.compile_expr(value)?;
+        }
+        self.emit(OpCode::PushArgList, pairs.len() as i64 * 2);
+        self.emit(OpCode::PushPropList, 0);
+      }
+      Expr::Binary(opcode, left, right) => {
+        self.compile_expr(left)?;
+        self.compile_expr(right)?;
+        self.emit(*opcode, 0);
+      }
+      Expr::Unary(opcode, value) => {
+        self.compile_expr(value)?;
+        self.emit(*opcode, 0);
+      }
+      Expr::Chunk(chunk_type, first, last, string) => {
+        // The first and last of each chunk type, from char to line
+        for slot in 0..4 {
+          if slot == *chunk_type {
+            self.compile_expr(first)?;
+            match last {
+              Some(last) => self.compile_expr(last)?,
+              None => self.emit_int(0),
+            }
+          } else {
+            self.emit_int(0);
+            self.emit_int(0);
+          }
+        }
+        self.compile_expr(string)?;
+        self.emit(OpCode::GetChunk, 0);
+      }
+    }
+    Ok(())
+  }
+
+  fn compile_assign(&mut self, target: &Expr, value: &Expr) -> Result<(), String> {
+    match target {
+      Expr::Var(name) => {
+        self.compile_expr(value)?;
+        self.emit_var(name, true);
+      }
+      Expr::The(name) => {
+        self.compile_expr(value)?;
+        self.emit_name(OpCode::SetMovieProp, name);
+      }
+      Expr::Prop(obj, name) => {
+        self.compile_expr(obj)?;
+        self.compile_expr(value)?;
+        self.emit_name(OpCode::SetObjProp, name);
+      }
+      Expr::Index(obj, index) => {
+        self.compile_expr(obj)?;
+        self.compile_expr(index)?;
+        self.compile_expr(value)?;
+        self.emit(OpCode::PushArgListNoRet, 3);
+        self.emit_name(OpCode::ObjCall, "setAt");
+      }
+      _ => return Err("Cannot assign to this expression".to_owned()),
+    }
+    Ok(())
+  }
+
+  fn compile_block(&mut self, statements: &[Stmt]) -> Result<(), String> {
+    for statement in statements {
+      self.compile_statement(statement)?;
+    }
+    Ok(())
+  }
+
+  fn compile_statement(&mut self, statement: &Stmt) -> Result<(), String> {
+    match statement {
+      Stmt::Assign(target, value) => self.compile_assign(target, value)?,
+      Stmt::Call(call) => self.compile_call(call, true)?,
+      Stmt::Put(value) => self.compile_call(&Expr::Call("put".to_owned(), vec![value.clone()]), true)?,
+      Stmt::Return(value) => {
+        if let Some(value) = value {
+          self.compile_expr(value)?;
+          self.emit(OpCode::PushArgListNoRet, 1);
+          self.emit_name(OpCode::ExtCall, "return");
+        }
+        self.emit(OpCode::Ret, 0);
+      }
+      Stmt::Exit => {
+        self.emit(OpCode::Ret, 0);
+      }
+      Stmt::ExitRepeat | Stmt::NextRepeat => {
+        let loop_depth = self.loops.last().map(|labels| labels.stack_depth).ok_or_else(|| "exit repeat outside of a repeat".to_owned())?;
+        if self.stack_depth > loop_depth {
+          self.emit(OpCode::Pop, (self.stack_depth - loop_depth) as i64);
+        }
+        let jump = self.emit(OpCode::Jmp, 0);
+        let labels = self.loops.last_mut().unwrap();
+        if matches!(statement, Stmt::ExitRepeat) {
+          labels.exit_jumps.push(jump);
+        } else {
+          labels.next_jumps.push(jump);
+        }
+      }
+      Stmt::If(condition, then_body, else_body) => {
+        self.compile_expr(condition)?;
+        let else_jump = self.emit(OpCode::JmpIfZ, 0);
+        self.compile_block(then_body)?;
+        if else_body.is_empty() {
+          self.patch_jump(else_jump, None);
+        } else {
+          let end_jump = self.emit(OpCode::Jmp, 0);
+          self.patch_jump(else_jump, None);
+          self.compile_block(else_body)?;
+          self.patch_jump(end_jump, None);
+        }
+      }
+      Stmt::RepeatWhile(condition, body) => {
+        let start = self.pos;
+        self.compile_expr(condition)?;
+        let end_jump = self.emit(OpCode::JmpIfZ, 0);
+        self.compile_loop_body(body, end_jump, |compiler| compiler.emit_end_repeat(start))?;
+      }
+      Stmt::RepeatWith(var, first, last, is_down, body) => {
+        self.compile_expr(first)?;
+        self.emit_var(var, true);
+        let start = self.pos;
+        self.emit_var(var, false);
+        self.compile_expr(last)?;
+        self.emit(if *is_down { OpCode::GtEq } else { OpCode::LtEq }, 0);
+        let end_jump = self.emit(OpCode::JmpIfZ, 0);
+        self.compile_loop_body(body, end_jump, |compiler| {
+          if *is_down {
+            compiler.emit_var(var, false);
+            compiler.emit_int(1);
+            compiler.emit(OpCode::Sub, 0);
+          } else {
+            compiler.emit_int(1);
+            compiler.emit_var(var, false);
+            compiler.emit(OpCode::Add, 0);
+          }
+          compiler.emit_var(var, true);
+          compiler.emit_end_repeat(start);
+        })?;
+      }
+      Stmt::RepeatIn(var, list, body) => {
+        // The list, its count and the index stay on the stack while the loop runs
+        self.compile_expr(list)?;
+        self.emit(OpCode::Peek, 0);
+        self.emit(OpCode::PushArgList, 1);
+        self.emit_name(OpCode::ExtCall, "count");
+        self.emit_int(1);
+        let start = self.pos;
+        self.emit(OpCode::Peek, 0);
+        self.emit(OpCode::Peek, 2);
+        self.emit(OpCode::LtEq, 0);
+        let end_jump = self.emit(OpCode::JmpIfZ, 0);
+        self.emit(OpCode::Peek, 2);
+        self.emit(OpCode::Peek, 1);
+        self.emit(OpCode::PushArgList, 2);
+        self.emit_name(OpCode::ExtCall, "getAt");
+        self.emit_var(var, true);
+        self.stack_depth += 3;
+        self.compile_loop_body(body, end_jump, |compiler| {
+          compiler.emit_int(1);
+          compiler.emit(OpCode::Add, 0);
+          compiler.emit_end_repeat(start);
+        })?;
+        self.stack_depth -= 3;
+        self.emit(OpCode::Pop, 3);
+      }
+      Stmt::Case(value, labels, otherwise) => {
+        self.compile_expr(value)?;
+        self.stack_depth += 1;
+        let mut end_jumps = vec![];
+        for (values, body) in labels {
+          let mut body_jumps = vec![];
+          for (index, label) in values.iter().enumerate() {
+            let is_last = index == values.len() - 1;
+            self.emit(OpCode::Peek, 0);
+            self.compile_expr(label)?;
+            self.emit(if is_last { OpCode::Eq } else { OpCode::NtEq }, 0);
+            body_jumps.push(self.emit(OpCode::JmpIfZ, 0));
+          }
+          let next_label_jump = body_jumps.pop().unwrap();
+          for jump in body_jumps {
+            self.patch_jump(jump, None);
+          }
+          self.compile_block(body)?;
+          end_jumps.push(self.emit(OpCode::Jmp, 0));
+          self.patch_jump(next_label_jump, None);
+        }
+        if let Some(otherwise) = otherwise {
+          self.compile_block(otherwise)?;
+        }
+        for jump in end_jumps {
+          self.patch_jump(jump, None);
+        }
+        self.stack_depth -= 1;
+        self.emit(OpCode::Pop, 1);
+      }
+    }
+    Ok(())
+  }
+
+  /// Compiles the body of a loop followed by its increment and end repeat, which
+  /// `next repeat` jumps to.
+  fn compile_loop_body<F: FnOnce(&mut Self)>(&mut self, body: &[Stmt], end_jump: usize, emit_end: F) -> Result<(), String> {
+    self.loops.push(LoopLabels { next_jumps: vec![], exit_jumps: vec![], stack_depth: self.stack_depth });
+    self.compile_block(body)?;
+    let labels = self.loops.pop().unwrap();
+    let next_pos = self.pos;
+    emit_end(self);
+    self.patch_jump(end_jump, None);
+    for jump in labels.next_jumps {
+      self.patch_jump(jump, Some(next_pos));
+    }
+    for jump in labels.exit_jumps {
+      self.patch_jump(jump, None);
+    }
+    Ok(())
+  }
+
+  fn finish(self, handler: &HandlerSource) -> HandlerDef {
+    let bytecode_index_map = self.bytecodes.iter().enumerate().map(|(index, bytecode)| (bytecode.pos, index)).collect::<FxHashMap<_, _>>();
+    let names = self.names;
+    HandlerDef {
+      name_id: name_id(names, &handler.name),
+      bytecode_array: self.bytecodes,
+      bytecode_index_map,
+      argument_name_ids: handler.args.iter().map(|name| name_id(names, name)).collect(),
+      local_name_ids: self.locals.iter().map(|name| name_id(names, name)).collect(),
+      global_name_ids: handler.globals.iter().map(|name| name_id(names, name)).collect(),
+    }
+  }
+}
+
+fn literal_equals(left: &Datum, right: &Datum) -> bool {
+  match (left, right) {
+    (Datum::String(left), Datum::String(right)) => left == right,
+    (Datum::Float(left), Datum::Float(right)) => left.to_bits() == right.to_bits(),
+    (Datum::Void, Datum::Void) => true,
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use wasm_bindgen_test::*;
+
+  use binary_reader::BinaryReader;
+
+  use crate::{director::{chunks::script::ScriptChunk, enums::ScriptType, lingo::{datum::Datum, script::ScriptContext}}, io::text_encoding::TextEncoding, player::{authoring::compile_and_attach_script, reserve_player_mut, ScriptError}, test_utils::{eval, init_player}};
+
+  /// Adds an empty cast for a test's scripts, as the tests run without a movie.
+  fn add_empty_cast(name: &str) -> u32 {
+    reserve_player_mut(|player| {
+      let text_encoding = player.movie.text_encoding();
+      player.movie.cast_manager.add_empty_cast(name, text_encoding)
+    })
+  }
+
+  fn compile_script(cast_lib: u32, member: u32, name: &str, script_type: ScriptType, source: &str) -> Result<(), ScriptError> {
+    reserve_player_mut(|player| compile_and_attach_script(player, cast_lib, Some(member), name, source, script_type))?;
+    Ok(())
+  }
+
+  #[wasm_bindgen_test]
+  async fn compiled_handlers_branch_with_if_and_case() {
+    init_player();
+    let cast_lib = add_empty_cast("Branches");
+    compile_script(cast_lib, 1, "branches", ScriptType::Movie, "
+on compiledSign n
+  if n > 0 then
+    return \"positive\"
+  else if n < 0 then
+    return \"negative\"
+  end if
+  return \"zero\"
+end
+
+on compiledSize n
+  case n of
+    1: return #one
+    2, 3:
+      return #few
+    otherwise
+      return #many
+  end case
+end
+").unwrap();
+    assert_eq!(eval("compiledSign(5)").await, "\"positive\"");
+    assert_eq!(eval("compiledSign(-5)").await, "\"negative\"");
+    assert_eq!(eval("compiledSign(0)").await, "\"zero\"");
+    assert_eq!(eval("compiledSize(1)").await, "#one");
+    assert_eq!(eval("compiledSize(3)").await, "#few");
+    assert_eq!(eval("compiledSize(9)").await, "#many");
+  }
+
+  #[wasm_bindgen_test]
+  async fn compiled_handlers_loop_with_repeat() {
+    init_player();
+    let cast_lib = add_empty_cast("Loops");
+    compile_script(cast_lib, 1, "loops", ScriptType::Movie, "
+on compiledSum aList
+  total = 0
+  repeat with value in aList
+    if value = 3 then next repeat
+    total = total + value
+  end repeat
+  repeat with i = 3 down to 1
+    total = total + i * 100
+  end repeat
+  repeat while total < 10000
+    total = total + 1000
+    if total > 2000 then exit repeat
+  end repeat
+  return total
+end
+").unwrap();
+    // 1 + 2 + 4, then 600, then 1000 twice
+    assert_eq!(eval("compiledSum([1, 2, 3, 4])").await, "2607");
+  }
+
+  #[wasm_bindgen_test]
+  async fn compiled_handlers_call_handlers_and_access_properties() {
+    init_player();
+    let cast_lib = add_empty_cast("Calls");
+    compile_script(cast_lib, 1, "compiledCounterScript", ScriptType::Parent, "
+property pCount
+
+on new me, start
+  pCount = start
+  return me
+end
+
+on increment me, amount
+  pCount = pCount + amount
+  return me.pCount
+end
+").unwrap();
+    compile_script(cast_lib, 2, "calls", ScriptType::Movie, "
+on compiledCount start
+  counter = new(script \"compiledCounterScript\", start)
+  counter.increment(2)
+  counter.pCount = compiledDouble(counter.pCount)
+  return counter.increment(1) & \":\" & [#a: [10, 20]][#a][2]
+end
+
+on compiledDouble n
+  return n * 2
+end
+").unwrap();
+    assert_eq!(eval("compiledCount(5)").await, "\"15:20\"");
+  }
+
+  #[wasm_bindgen_test]
+  async fn compiling_reports_the_line_of_syntax_errors() {
+    init_player();
+    let cast_lib = add_empty_cast("Errors");
+    let result = compile_script(cast_lib, 1, "broken", ScriptType::Movie, "on broken\n  x = \nend");
+    assert!(result.unwrap_err().message.contains("2:7"));
+  }
+
+  #[wasm_bindgen_test]
+  fn compiled_scripts_round_trip_through_script_chunks() {
+    let mut lctx = ScriptContext { names: vec![], scripts: Default::default() };
+    let chunk = super::compile_script("
+property pTotal
+
+on compiledTotal n
+  repeat with i = 1 to n
+    pTotal = pTotal + i * 1.5
+  end repeat
+  return \"total\" && pTotal && 100000
+end
+", &mut lctx, 8).unwrap();
+    let data = chunk.write(1, 500, false, TextEncoding::MacRoman);
+    let read = ScriptChunk::from_reader(&mut BinaryReader::from_u8(&data), 500, false, TextEncoding::MacRoman).unwrap();
+
+    assert_eq!(read.property_name_ids, chunk.property_name_ids);
+    assert_eq!(read.handlers.len(), chunk.handlers.len());
+    for (read, handler) in read.handlers.iter().zip(&chunk.handlers) {
+      assert_eq!(read.name_id, handler.name_id);
+      assert_eq!(read.argument_name_ids, handler.argument_name_ids);
+      assert_eq!(read.local_name_ids, handler.local_name_ids);
+      let bytecodes = |handler: &super::HandlerDef| handler.bytecode_array.iter().map(|bytecode| (bytecode.opcode, bytecode.obj, bytecode.pos)).collect::<Vec<_>>();
+      assert!(bytecodes(read) == bytecodes(handler));
+    }
+    let literals = read.literals.iter().map(|literal| match literal {
+      Datum::Int(value) => value.to_string(),
+      Datum::Float(value) => value.to_string(),
+      Datum::String(value) => format!("\"{}\"", value),
+      _ => "?".to_string(),
+    }).collect::<Vec<_>>();
+    assert_eq!(literals, ["1.5", "\"total\""]);
+  }
+}
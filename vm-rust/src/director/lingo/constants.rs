@@ -89,7 +89,7 @@ pub fn opcode_names() -> &'static HashMap<OpCode, Box<str>> {
   })
 }
 
-fn anim_prop_names() -> &'static HashMap<u16, Box<str>> {
+pub fn anim_prop_names() -> &'static HashMap<u16, Box<str>> {
   static MAP: OnceLock<HashMap<u16, Box<str>>> = OnceLock::new();
   MAP.get_or_init(|| {
     HashMap::from([
@@ -138,7 +138,7 @@ fn anim_prop_names() -> &'static HashMap<u16, Box<str>> {
   })
 }
 
-fn anim2_prop_names() -> &'static HashMap<u16, Box<str>> {
+pub fn anim2_prop_names() -> &'static HashMap<u16, Box<str>> {
   static MAP: OnceLock<HashMap<u16, Box<str>>> = OnceLock::new();
   MAP.get_or_init(|| {
     HashMap::from([
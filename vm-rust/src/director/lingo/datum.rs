@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use num_derive::FromPrimitive;
 
-use crate::player::{bitmap::{bitmap::PaletteRef, manager::BitmapRef, mask::BitmapMask}, cast_lib::CastMemberRef, datum_ref::DatumRef, script_ref::ScriptInstanceRef, sprite::{ColorRef, CursorRef}, ScriptError};
+use crate::player::{bitmap::{bitmap::PaletteRef, manager::BitmapRef, mask::BitmapMask}, cast_lib::CastMemberRef, date::LingoDate, datum_ref::DatumRef, script_ref::ScriptInstanceRef, sprite::{ColorRef, CursorRef}, ScriptError};
 
 #[allow(dead_code)]
 #[derive(Clone)]
@@ -39,6 +39,8 @@ pub enum DatumType {
   PlayerRef,
   MovieRef,
   SoundRef,
+  WindowRef,
+  Date,
 }
 
 #[derive(Clone, FromPrimitive)]
@@ -133,6 +135,8 @@ pub enum Datum {
   PlayerRef,
   MovieRef,
   SoundRef(u16),
+  WindowRef(String),
+  Date(LingoDate),
   Null,
 }
 
@@ -171,6 +175,8 @@ impl DatumType {
       DatumType::PlayerRef => "player_ref".to_string(),
       DatumType::MovieRef => "movie_ref".to_string(),
       DatumType::SoundRef => "sound_ref".to_string(),
+      DatumType::WindowRef => "window_ref".to_string(),
+      DatumType::Date => "date".to_string(),
     }
   }
 }
@@ -206,6 +212,8 @@ impl Datum {
       Datum::PlayerRef => DatumType::PlayerRef,
       Datum::MovieRef => DatumType::MovieRef,
       Datum::SoundRef(_) => DatumType::SoundRef,
+      Datum::WindowRef(_) => DatumType::WindowRef,
+      Datum::Date(_) => DatumType::Date,
       Datum::Null => DatumType::Null,
     }
   }
@@ -399,6 +407,13 @@ impl Datum {
     }
   }
 
+  pub fn to_date(&self) -> Result<LingoDate, ScriptError> {
+    match self {
+      Datum::Date(date) => Ok(*date),
+      _ => Err(ScriptError::new("Cannot convert datum to date".to_string())),
+    }
+  }
+
   pub fn to_sprite_ref(&self) -> Result<i16, ScriptError> {
     match self {
       Datum::SpriteRef(sprite_ref) => Ok(*sprite_ref),
@@ -0,0 +1,924 @@
+use crate::director::chunks::{handler::{Bytecode, HandlerDef}, script::ScriptChunk};
+
+use super::{
+  constants::{anim2_prop_names, anim_prop_names, movie_prop_names, sprite_prop_names},
+  datum::Datum,
+  opcode::OpCode,
+  script::ScriptContext,
+};
+
+const PREC_OR: u8 = 1;
+const PREC_AND: u8 = 2;
+const PREC_NOT: u8 = 3;
+const PREC_COMPARE: u8 = 4;
+const PREC_CONCAT: u8 = 5;
+const PREC_SUM: u8 = 6;
+const PREC_PRODUCT: u8 = 7;
+const PREC_UNARY: u8 = 8;
+const PREC_ATOM: u8 = 9;
+
+/// A line of decompiled Lingo along with the bytecodes it was decompiled from, so the
+/// debugger can map lines to bytecode indices and back.
+pub struct DecompiledLine {
+  pub text: String,
+  pub indent: usize,
+  /// Index of the first bytecode of the line. Lines like `end if` have no bytecodes,
+  /// in which case this equals `bytecode_end`.
+  pub bytecode_start: usize,
+  pub bytecode_end: usize,
+}
+
+#[derive(Clone)]
+struct Expr {
+  text: String,
+  precedence: u8,
+  /// Left and right side of binary operations.
+  operands: Vec<Expr>,
+}
+
+impl Expr {
+  fn atom(text: String) -> Expr {
+    Expr { text, precedence: PREC_ATOM, operands: vec![] }
+  }
+
+  fn wrapped(&self, precedence: u8) -> String {
+    if self.precedence < precedence {
+      format!("({})", self.text)
+    } else {
+      self.text.clone()
+    }
+  }
+}
+
+enum StackItem {
+  Expr(Expr),
+  ArgList(Vec<Expr>, bool),
+}
+
+enum Block {
+  If { depth: usize, else_index: Option<usize>, end_pos: usize },
+  Loop { depth: usize, next_pos: usize, end_pos: usize },
+  Case {
+    depth: usize,
+    values: Vec<String>,
+    comparing: bool,
+    expecting_or: bool,
+    in_otherwise: bool,
+    label_end: usize,
+    end_pos: Option<usize>,
+  },
+  Tell { depth: usize },
+}
+
+impl Block {
+  fn body_depth(&self) -> usize {
+    match self {
+      Block::If { depth, .. } | Block::Loop { depth, .. } | Block::Tell { depth } => depth + 1,
+      Block::Case { depth, .. } => depth + 2,
+    }
+  }
+}
+
+/// Reconstructs readable Lingo from the bytecode of a handler, including if/else,
+/// repeat and case structures. Bytecode the decompiler doesn't understand is kept as
+/// comments rather than failing.
+pub fn decompile_handler(
+  handler: &HandlerDef,
+  script: &ScriptChunk,
+  lctx: &ScriptContext,
+  variable_multiplier: u32,
+  dir_version: u16,
+) -> Vec<DecompiledLine> {
+  let mut decompiler = Decompiler {
+    handler,
+    script,
+    lctx,
+    variable_multiplier: variable_multiplier.max(1) as i64,
+    dir_version,
+    stack: vec![],
+    blocks: vec![],
+    lines: vec![],
+    hidden: vec![false; handler.bytecode_array.len()],
+    statement_start: 0,
+    last_assignment: None,
+  };
+  decompiler.run();
+  decompiler.lines
+}
+
+struct Decompiler<'a> {
+  handler: &'a HandlerDef,
+  script: &'a ScriptChunk,
+  lctx: &'a ScriptContext,
+  variable_multiplier: i64,
+  dir_version: u16,
+  stack: Vec<StackItem>,
+  blocks: Vec<Block>,
+  lines: Vec<DecompiledLine>,
+  /// Bytecodes that are part of a structure and don't translate to anything by themselves.
+  hidden: Vec<bool>,
+  statement_start: usize,
+  /// The line index, variable and value of the last `x = value` line, which may turn
+  /// out to be the start of a `repeat with` loop.
+  last_assignment: Option<(usize, String, String)>,
+}
+
+impl<'a> Decompiler<'a> {
+  fn run(&mut self) {
+    let bytecodes = &self.handler.bytecode_array[..];
+    let mut index = 0;
+    while index < bytecodes.len() {
+      self.close_blocks(bytecodes[index].pos, index);
+      if self.hidden[index] {
+        index += 1;
+        continue;
+      }
+      index += self.translate(index);
+    }
+    self.close_blocks(usize::MAX, bytecodes.len());
+  }
+
+  fn depth(&self) -> usize {
+    self.blocks.last().map_or(0, |block| block.body_depth())
+  }
+
+  fn emit(&mut self, text: String, indent: usize, bytecode_end: usize) {
+    let bytecode_start = self.statement_start.min(bytecode_end);
+    self.lines.push(DecompiledLine { text, indent, bytecode_start, bytecode_end });
+    self.statement_start = bytecode_end;
+  }
+
+  fn emit_statement(&mut self, text: String, bytecode_end: usize) {
+    let depth = self.depth();
+    self.emit(text, depth, bytecode_end);
+  }
+
+  fn emit_assignment(&mut self, name: String, value: Expr, bytecode_end: usize) {
+    self.emit_statement(format!("{} = {}", name, value.text), bytecode_end);
+    self.last_assignment = Some((self.lines.len() - 1, name, value.text));
+  }
+
+  /// Emits the `end` lines and `else`/`otherwise` transitions of the blocks that end
+  /// at the given position.
+  fn close_blocks(&mut self, pos: usize, index: usize) {
+    let is_end = index >= self.handler.bytecode_array.len();
+    loop {
+      match self.blocks.last_mut() {
+        Some(Block::If { depth, end_pos, .. }) if is_end || *end_pos == pos => {
+          let depth = *depth;
+          self.blocks.pop();
+          self.statement_start = index;
+          self.emit("end if".to_owned(), depth, index);
+        }
+        Some(Block::Loop { depth, end_pos, .. }) if is_end || *end_pos == pos => {
+          let depth = *depth;
+          self.blocks.pop();
+          self.statement_start = index;
+          self.emit("end repeat".to_owned(), depth, index);
+        }
+        Some(Block::Tell { depth }) if is_end => {
+          let depth = *depth;
+          self.blocks.pop();
+          self.statement_start = index;
+          self.emit("end tell".to_owned(), depth, index);
+        }
+        Some(Block::Case { depth, label_end, end_pos, in_otherwise, .. }) if is_end || *label_end == pos => {
+          let depth = *depth;
+          let bytecode = self.handler.bytecode_array.get(index);
+          let is_pop = bytecode.is_some_and(|bytecode| bytecode.opcode == OpCode::Pop && bytecode.obj == 1);
+          if is_end || *end_pos == Some(pos) || (end_pos.is_none() && is_pop) {
+            // Without an otherwise block, the switch value is popped at the end
+            let covers_pop = !is_end && !*in_otherwise && is_pop;
+            self.blocks.pop();
+            self.statement_start = index;
+            if covers_pop {
+              self.hidden[index] = true;
+            }
+            self.emit("end case".to_owned(), depth, if covers_pop { index + 1 } else { index });
+          } else if bytecode.is_some_and(|bytecode| bytecode.opcode == OpCode::Peek) {
+            break;
+          } else {
+            // Otherwise blocks start by popping the switch value
+            *in_otherwise = true;
+            *label_end = end_pos.unwrap_or(usize::MAX);
+            self.statement_start = index;
+            if is_pop {
+              self.hidden[index] = true;
+            }
+            self.emit("otherwise:".to_owned(), depth + 1, if is_pop { index + 1 } else { index });
+          }
+        }
+        _ => break,
+      }
+    }
+  }
+
+  fn push(&mut self, expr: Expr) {
+    self.stack.push(StackItem::Expr(expr));
+  }
+
+  fn pop(&mut self) -> Expr {
+    match self.stack.pop() {
+      Some(StackItem::Expr(expr)) => expr,
+      Some(StackItem::ArgList(args, _)) => Expr::atom(join_exprs(&args)),
+      None => Expr::atom("?".to_owned()),
+    }
+  }
+
+  fn pop_args(&mut self) -> (Vec<Expr>, bool) {
+    match self.stack.pop() {
+      Some(StackItem::ArgList(args, no_ret)) => (args, no_ret),
+      Some(StackItem::Expr(expr)) => (vec![expr], false),
+      None => (vec![], false),
+    }
+  }
+
+  fn name(&self, name_id: i64) -> String {
+    self.lctx.names.get(name_id as usize).cloned().unwrap_or_else(|| format!("name{}", name_id))
+  }
+
+  fn local_name(&self, obj: i64) -> String {
+    let name_id = self.handler.local_name_ids.get((obj / self.variable_multiplier) as usize);
+    name_id.map_or_else(|| format!("local{}", obj), |name_id| self.name(*name_id as i64))
+  }
+
+  fn arg_name(&self, obj: i64) -> String {
+    let name_id = self.handler.argument_name_ids.get((obj / self.variable_multiplier) as usize);
+    name_id.map_or_else(|| format!("arg{}", obj), |name_id| self.name(*name_id as i64))
+  }
+
+  /// The variable a get/set bytecode reads or writes, if it's one.
+  fn var_name(&self, bytecode: &Bytecode) -> Option<String> {
+    match bytecode.opcode {
+      OpCode::GetLocal | OpCode::SetLocal => Some(self.local_name(bytecode.obj)),
+      OpCode::GetParam | OpCode::SetParam => Some(self.arg_name(bytecode.obj)),
+      OpCode::GetGlobal | OpCode::GetGlobal2 | OpCode::SetGlobal | OpCode::SetGlobal2 | OpCode::GetProp | OpCode::SetProp => {
+        Some(self.name(bytecode.obj))
+      }
+      _ => None,
+    }
+  }
+
+  fn index_of_pos(&self, pos: usize) -> usize {
+    self.handler.bytecode_index_map.get(&pos).copied().unwrap_or(self.handler.bytecode_array.len())
+  }
+
+  fn binary(&mut self, op: &str, precedence: u8) {
+    let right = self.pop();
+    let left = self.pop();
+    let text = format!("{} {} {}", left.wrapped(precedence), op, right.wrapped(precedence + 1));
+    self.push(Expr { text, precedence, operands: vec![left, right] });
+  }
+
+  /// Reads the variable of put, delete and chunk reference bytecodes.
+  fn read_var(&mut self, var_type: i64) -> Expr {
+    let cast_id = if var_type == 0x6 && self.dir_version >= 500 { Some(self.pop()) } else { None };
+    let id = self.pop();
+    let id_int = id.text.parse::<i64>().ok();
+    match (var_type, id_int) {
+      (0x4, Some(id)) => Expr::atom(self.arg_name(id)),
+      (0x5, Some(id)) => Expr::atom(self.local_name(id)),
+      (0x6, _) => match cast_id {
+        Some(cast_id) if cast_id.text != "0" => Expr::atom(format!("field {} of castLib {}", id.wrapped(PREC_ATOM), cast_id.wrapped(PREC_ATOM))),
+        _ => Expr::atom(format!("field {}", id.wrapped(PREC_ATOM))),
+      },
+      _ => Expr::atom(id.text.trim_start_matches('#').to_owned()),
+    }
+  }
+
+  /// Reads the ranges of a chunk expression like `char 1 to 3 of word 2 of x`.
+  fn read_chunk_ref(&mut self, string: Expr) -> Expr {
+    let last_line = self.pop();
+    let first_line = self.pop();
+    let last_item = self.pop();
+    let first_item = self.pop();
+    let last_word = self.pop();
+    let first_word = self.pop();
+    let last_char = self.pop();
+    let first_char = self.pop();
+    let mut text = string.wrapped(PREC_ATOM);
+    for (chunk_type, first, last) in [
+      ("line", first_line, last_line),
+      ("item", first_item, last_item),
+      ("word", first_word, last_word),
+      ("char", first_char, last_char),
+    ] {
+      if first.text == "0" {
+        continue;
+      }
+      text = if last.text == "0" {
+        format!("{} {} of {}", chunk_type, first.wrapped(PREC_ATOM), text)
+      } else {
+        format!("{} {} to {} of {}", chunk_type, first.wrapped(PREC_ATOM), last.wrapped(PREC_ATOM), text)
+      };
+    }
+    Expr { text, precedence: PREC_UNARY, operands: vec![] }
+  }
+
+  fn call_text(name: &str, args: &[Expr], is_statement: bool) -> String {
+    if is_statement {
+      if args.is_empty() {
+        name.to_owned()
+      } else {
+        format!("{} {}", name, join_exprs(args))
+      }
+    } else {
+      format!("{}({})", name, join_exprs(args))
+    }
+  }
+
+  fn push_call(&mut self, text: String, no_ret: bool, bytecode_end: usize) {
+    if no_ret {
+      self.emit_statement(text, bytecode_end);
+    } else {
+      self.push(Expr::atom(text));
+    }
+  }
+
+  /// Translates the bytecode at `index`, returning how many bytecodes it consumed.
+  fn translate(&mut self, index: usize) -> usize {
+    let bytecode = &self.handler.bytecode_array[index];
+    let next = index + 1;
+    match bytecode.opcode {
+      OpCode::Ret | OpCode::RetFactory => {
+        if next == self.handler.bytecode_array.len() {
+          self.statement_start = next;
+        } else {
+          self.emit_statement("exit".to_owned(), next);
+        }
+      }
+      OpCode::PushZero => self.push(Expr::atom("0".to_owned())),
+      OpCode::Mul => self.binary("*", PREC_PRODUCT),
+      OpCode::Add => self.binary("+", PREC_SUM),
+      OpCode::Sub => self.binary("-", PREC_SUM),
+      OpCode::Div => self.binary("/", PREC_PRODUCT),
+      OpCode::Mod => self.binary("mod", PREC_PRODUCT),
+      OpCode::JoinStr => self.binary("&", PREC_CONCAT),
+      OpCode::JoinPadStr => self.binary("&&", PREC_CONCAT),
+      OpCode::Lt => self.binary("<", PREC_COMPARE),
+      OpCode::LtEq => self.binary("<=", PREC_COMPARE),
+      OpCode::NtEq | OpCode::Eq => {
+        if let Some(consumed) = self.translate_case_comparison(index) {
+          return consumed;
+        }
+        self.binary(if bytecode.opcode == OpCode::Eq { "=" } else { "<>" }, PREC_COMPARE);
+      }
+      OpCode::Gt => self.binary(">", PREC_COMPARE),
+      OpCode::GtEq => self.binary(">=", PREC_COMPARE),
+      OpCode::And => self.binary("and", PREC_AND),
+      OpCode::Or => self.binary("or", PREC_OR),
+      OpCode::ContainsStr => self.binary("contains", PREC_COMPARE),
+      OpCode::Contains0Str => self.binary("starts", PREC_COMPARE),
+      OpCode::Inv => {
+        let value = self.pop();
+        self.push(Expr { text: format!("-{}", value.wrapped(PREC_UNARY)), precedence: PREC_UNARY, operands: vec![] });
+      }
+      OpCode::Not => {
+        let value = self.pop();
+        self.push(Expr { text: format!("not {}", value.wrapped(PREC_NOT)), precedence: PREC_NOT, operands: vec![] });
+      }
+      OpCode::GetChunk => {
+        let string = self.pop();
+        let chunk = self.read_chunk_ref(string);
+        self.push(chunk);
+      }
+      OpCode::HiliteChunk => {
+        let field = self.read_var(0x6);
+        let chunk = self.read_chunk_ref(field);
+        self.emit_statement(format!("hilite {}", chunk.text), next);
+      }
+      OpCode::OntoSpr | OpCode::IntoSpr => {
+        let second = self.pop();
+        let first = self.pop();
+        let op = if bytecode.opcode == OpCode::OntoSpr { "intersects" } else { "within" };
+        let text = format!("sprite {} {} {}", first.wrapped(PREC_ATOM), op, second.wrapped(PREC_ATOM));
+        self.push(Expr { text, precedence: PREC_COMPARE, operands: vec![] });
+      }
+      OpCode::GetField => {
+        let field = self.read_var(0x6);
+        self.push(field);
+      }
+      OpCode::StartTell => {
+        let window = self.pop();
+        let depth = self.depth();
+        self.emit(format!("tell {}", window.text), depth, next);
+        self.blocks.push(Block::Tell { depth });
+      }
+      OpCode::EndTell => {
+        if let Some(Block::Tell { depth }) = self.blocks.last() {
+          let depth = *depth;
+          self.blocks.pop();
+          self.emit("end tell".to_owned(), depth, next);
+        }
+      }
+      OpCode::PushList => {
+        let (items, _) = self.pop_args();
+        self.push(Expr::atom(format!("[{}]", join_exprs(&items))));
+      }
+      OpCode::PushPropList => {
+        let (items, _) = self.pop_args();
+        let text = if items.is_empty() {
+          "[:]".to_owned()
+        } else {
+          let pairs = items
+            .chunks(2)
+            .map(|pair| format!("{}: {}", pair[0].text, pair.get(1).map_or("?", |value| value.text.as_str())))
+            .collect::<Vec<_>>();
+          format!("[{}]", pairs.join(", "))
+        };
+        self.push(Expr::atom(text));
+      }
+      OpCode::Swap => {
+        let len = self.stack.len();
+        if len >= 2 {
+          self.stack.swap(len - 1, len - 2);
+        }
+      }
+      OpCode::PushInt8 | OpCode::PushInt16 | OpCode::PushInt32 => {
+        let expr = Expr { text: bytecode.obj.to_string(), precedence: if bytecode.obj < 0 { PREC_UNARY } else { PREC_ATOM }, operands: vec![] };
+        self.push(expr);
+      }
+      OpCode::PushFloat32 => self.push(Expr::atom(format_float(f32::from_bits(bytecode.obj as u32)))),
+      OpCode::PushArgListNoRet | OpCode::PushArgList => {
+        let count = (bytecode.obj.max(0) as usize).min(self.stack.len());
+        let args = self.stack.split_off(self.stack.len() - count);
+        let args = args
+          .into_iter()
+          .map(|item| match item {
+            StackItem::Expr(expr) => expr,
+            StackItem::ArgList(args, _) => Expr::atom(join_exprs(&args)),
+          })
+          .collect();
+        self.stack.push(StackItem::ArgList(args, bytecode.opcode == OpCode::PushArgListNoRet));
+      }
+      OpCode::PushCons => {
+        let literal = self.script.literals.get(bytecode.obj as usize / self.variable_multiplier as usize);
+        self.push(Expr::atom(literal.map_or_else(|| "?".to_owned(), format_literal)));
+      }
+      OpCode::PushSymb => self.push(Expr::atom(format!("#{}", self.name(bytecode.obj)))),
+      OpCode::PushVarRef | OpCode::GetTopLevelProp => self.push(Expr::atom(self.name(bytecode.obj))),
+      OpCode::GetGlobal | OpCode::GetGlobal2 | OpCode::GetProp | OpCode::GetParam | OpCode::GetLocal => {
+        let name = self.var_name(bytecode).unwrap();
+        self.push(Expr::atom(name));
+      }
+      OpCode::SetGlobal | OpCode::SetGlobal2 | OpCode::SetProp | OpCode::SetParam | OpCode::SetLocal => {
+        let name = self.var_name(bytecode).unwrap();
+        let value = self.pop();
+        self.emit_assignment(name, value, next);
+      }
+      OpCode::Jmp => self.translate_jmp(index),
+      OpCode::JmpIfZ => self.translate_jmp_if_z(index),
+      OpCode::EndRepeat => {
+        if let Some(Block::Loop { depth, end_pos, .. }) = self.blocks.last() {
+          let depth = *depth;
+          // `repeat with ... in` loops pop the list, count and index when they end
+          let end_index = self.index_of_pos(*end_pos);
+          let end = match self.handler.bytecode_array.get(end_index) {
+            Some(end_bytecode) if end_index == next && end_bytecode.opcode == OpCode::Pop && end_bytecode.obj == 3 => {
+              self.hidden[end_index] = true;
+              next + 1
+            }
+            _ => next,
+          };
+          self.blocks.pop();
+          self.emit("end repeat".to_owned(), depth, end);
+        } else {
+          self.emit_statement("-- end repeat".to_owned(), next);
+        }
+      }
+      OpCode::LocalCall => {
+        let (args, no_ret) = self.pop_args();
+        let name = self
+          .script
+          .handlers
+          .get(bytecode.obj as usize)
+          .map_or_else(|| format!("handler{}", bytecode.obj), |handler| self.name(handler.name_id as i64));
+        self.push_call(Self::call_text(&name, &args, no_ret), no_ret, next);
+      }
+      OpCode::ExtCall | OpCode::TellCall => {
+        let (args, no_ret) = self.pop_args();
+        let name = self.name(bytecode.obj);
+        if name == "return" && no_ret {
+          let text = Self::call_text(&name, &args, true);
+          let is_followed_by_ret = self.handler.bytecode_array.get(next).is_some_and(|bytecode| bytecode.opcode == OpCode::Ret);
+          let end = if is_followed_by_ret { next + 1 } else { next };
+          self.emit_statement(text, end);
+          return end - index;
+        }
+        self.push_call(Self::call_text(&name, &args, no_ret), no_ret, next);
+      }
+      OpCode::ObjCall => {
+        let (mut args, no_ret) = self.pop_args();
+        let name = self.name(bytecode.obj);
+        if args.is_empty() {
+          self.push_call(Self::call_text(&name, &args, no_ret), no_ret, next);
+          return 1;
+        }
+        let obj = args.remove(0);
+        match (name.as_str(), args.len()) {
+          ("getAt", 1) => self.push_call(format!("{}[{}]", obj.wrapped(PREC_ATOM), args[0].text), no_ret, next),
+          ("setAt", 2) => self.emit_statement(format!("{}[{}] = {}", obj.wrapped(PREC_ATOM), args[0].text, args[1].text), next),
+          _ => self.push_call(format!("{}.{}({})", obj.wrapped(PREC_ATOM), name, join_exprs(&args)), no_ret, next),
+        }
+      }
+      OpCode::Put => {
+        let put_type = put_type_name((bytecode.obj >> 4) & 0xf);
+        let var = self.read_var(bytecode.obj & 0xf);
+        let value = self.pop();
+        self.emit_statement(format!("put {} {} {}", value.text, put_type, var.text), next);
+      }
+      OpCode::PutChunk => {
+        let put_type = put_type_name((bytecode.obj >> 4) & 0xf);
+        let var = self.read_var(bytecode.obj & 0xf);
+        let chunk = self.read_chunk_ref(var);
+        let value = self.pop();
+        self.emit_statement(format!("put {} {} {}", value.text, put_type, chunk.text), next);
+      }
+      OpCode::DeleteChunk => {
+        let var = self.read_var(bytecode.obj);
+        let chunk = self.read_chunk_ref(var);
+        self.emit_statement(format!("delete {}", chunk.text), next);
+      }
+      OpCode::Get => {
+        let expr = self.translate_the_prop(bytecode.obj);
+        self.push(expr);
+      }
+      OpCode::Set => {
+        let prop_id = self.pop();
+        let value = self.pop();
+        let prop_id = prop_id.text.parse::<u16>().unwrap_or(u16::MAX);
+        let prop = match bytecode.obj {
+          0x00 => movie_prop_names().get(&prop_id).map(|name| format!("the {}", name)),
+          0x06 => sprite_prop_names().get(&prop_id).map(|name| {
+            let sprite = self.pop();
+            format!("the {} of sprite {}", name, sprite.wrapped(PREC_ATOM))
+          }),
+          0x07 => anim_prop_names().get(&prop_id).map(|name| format!("the {}", name)),
+          _ => None,
+        };
+        let prop = prop.unwrap_or_else(|| format!("the prop{} (type {})", prop_id, bytecode.obj));
+        self.emit_statement(format!("set {} to {}", prop, value.text), next);
+      }
+      OpCode::GetMovieProp => self.push(Expr::atom(format!("the {}", self.name(bytecode.obj)))),
+      OpCode::SetMovieProp => {
+        let value = self.pop();
+        self.emit_statement(format!("set the {} to {}", self.name(bytecode.obj), value.text), next);
+      }
+      OpCode::GetObjProp | OpCode::GetChainedProp => {
+        let obj = self.pop();
+        self.push(Expr::atom(format!("{}.{}", obj.wrapped(PREC_ATOM), self.name(bytecode.obj))));
+      }
+      OpCode::SetObjProp => {
+        let value = self.pop();
+        let obj = self.pop();
+        self.emit_statement(format!("{}.{} = {}", obj.wrapped(PREC_ATOM), self.name(bytecode.obj), value.text), next);
+      }
+      OpCode::Peek => return self.translate_peek(index),
+      OpCode::Pop => {
+        // Anything still on the stack is the discarded result of a call
+        let mut discarded = None;
+        for _ in 0..bytecode.obj {
+          if self.stack.is_empty() {
+            break;
+          }
+          discarded = Some(self.pop());
+        }
+        match discarded {
+          Some(expr) => self.emit_statement(expr.text, next),
+          None => self.statement_start = next,
+        }
+      }
+      OpCode::TheBuiltin => {
+        self.pop_args();
+        self.push(Expr::atom(format!("the {}", self.name(bytecode.obj))));
+      }
+      OpCode::PushChunkVarRef => {
+        let var = self.read_var(bytecode.obj);
+        self.push(var);
+      }
+      OpCode::NewObj => {
+        let (args, _) = self.pop_args();
+        self.push(Expr::atom(format!("new {}({})", self.name(bytecode.obj), join_exprs(&args))));
+      }
+      _ => {
+        let text = format!("-- {}", bytecode.to_bytecode_text(self.lctx, self.handler));
+        self.emit_statement(text, next);
+      }
+    }
+    1
+  }
+
+  fn translate_the_prop(&mut self, prop_type: i64) -> Expr {
+    let prop_id = self.pop();
+    let prop_id = prop_id.text.parse::<u16>().unwrap_or(u16::MAX);
+    let max_movie_prop_id = movie_prop_names().keys().max().copied().unwrap_or(0);
+    let text = match prop_type {
+      0x00 if prop_id <= max_movie_prop_id => movie_prop_names().get(&prop_id).map(|name| format!("the {}", name)),
+      0x00 => {
+        let string = self.pop();
+        chunk_type_name(prop_id.wrapping_sub(max_movie_prop_id)).map(|chunk_type| format!("the last {} in {}", chunk_type, string.wrapped(PREC_ATOM)))
+      }
+      0x01 => {
+        let string = self.pop();
+        chunk_type_name(prop_id).map(|chunk_type| format!("the number of {}s in {}", chunk_type, string.wrapped(PREC_ATOM)))
+      }
+      0x06 => sprite_prop_names().get(&prop_id).map(|name| {
+        let sprite = self.pop();
+        format!("the {} of sprite {}", name, sprite.wrapped(PREC_ATOM))
+      }),
+      0x07 => anim_prop_names().get(&prop_id).map(|name| format!("the {}", name)),
+      0x08 if prop_id == 0x02 && self.dir_version >= 500 => {
+        let cast_lib = self.pop();
+        if cast_lib.text == "0" {
+          Some("the number of castMembers".to_owned())
+        } else {
+          Some(format!("the number of castMembers of castLib {}", cast_lib.wrapped(PREC_ATOM)))
+        }
+      }
+      0x08 => anim2_prop_names().get(&prop_id).map(|name| format!("the {}", name)),
+      _ => None,
+    };
+    Expr::atom(text.unwrap_or_else(|| format!("the prop{} (type {})", prop_id, prop_type)))
+  }
+
+  fn innermost_loop(&self) -> Option<(usize, usize)> {
+    self.blocks.iter().rev().find_map(|block| match block {
+      Block::Loop { next_pos, end_pos, .. } => Some((*next_pos, *end_pos)),
+      _ => None,
+    })
+  }
+
+  fn innermost_case_end(&self) -> Option<usize> {
+    self.blocks.iter().rev().find_map(|block| match block {
+      Block::Case { end_pos, .. } => Some(*end_pos),
+      _ => None,
+    }).flatten()
+  }
+
+  fn translate_jmp(&mut self, index: usize) {
+    let bytecode = &self.handler.bytecode_array[index];
+    let target_pos = (bytecode.pos as i64 + bytecode.obj) as usize;
+    let next = index + 1;
+    if let Some(Block::If { depth, else_index, .. }) = self.blocks.last_mut() {
+      if *else_index == Some(index) {
+        let depth = *depth;
+        *else_index = None;
+        self.emit("else".to_owned(), depth, next);
+        return;
+      }
+    }
+    match self.innermost_loop() {
+      Some((_, end_pos)) if end_pos == target_pos => return self.emit_statement("exit repeat".to_owned(), next),
+      Some((next_pos, _)) if next_pos == target_pos => return self.emit_statement("next repeat".to_owned(), next),
+      _ => {}
+    }
+    if self.innermost_case_end() == Some(target_pos) {
+      // The jump out of a case label's body
+      self.statement_start = next;
+      return;
+    }
+    let text = format!("-- {}", bytecode.to_bytecode_text(self.lctx, self.handler));
+    self.emit_statement(text, next);
+  }
+
+  fn translate_jmp_if_z(&mut self, index: usize) {
+    let bytecodes = &self.handler.bytecode_array[..];
+    let bytecode = &bytecodes[index];
+    let target_pos = bytecode.pos + bytecode.obj as usize;
+    let target_index = self.index_of_pos(target_pos);
+    let next = index + 1;
+    let condition = self.pop();
+    let depth = self.depth();
+    let before_target = target_index.checked_sub(1).and_then(|i| bytecodes.get(i)).filter(|_| target_index > next);
+
+    if let Some(end_repeat) = before_target.filter(|x| x.opcode == OpCode::EndRepeat && x.pos as i64 - x.obj <= bytecode.pos as i64) {
+      let loop_start_pos = (end_repeat.pos as i64 - end_repeat.obj) as usize;
+      let end_repeat_index = target_index - 1;
+      let next_pos = match self.match_repeat_with(index, end_repeat_index, &condition) {
+        Some(increment_index) => bytecodes[increment_index].pos,
+        None => {
+          self.emit(format!("repeat while {}", condition.text), depth, next);
+          loop_start_pos
+        }
+      };
+      self.blocks.push(Block::Loop { depth, next_pos, end_pos: target_pos });
+      return;
+    }
+
+    self.emit(format!("if {} then", condition.text), depth, next);
+    let else_jmp = before_target.filter(|x| x.opcode == OpCode::Jmp && x.obj > 0).map(|x| (x.pos as i64 + x.obj) as usize);
+    let is_else = else_jmp.is_some_and(|else_end| {
+      let is_loop_jump = self.innermost_loop().is_some_and(|(next_pos, end_pos)| else_end == end_pos || else_end == next_pos);
+      let outer_end = self.blocks.iter().rev().find_map(|block| match block {
+        Block::If { end_pos, .. } | Block::Loop { end_pos, .. } => Some(*end_pos),
+        _ => None,
+      });
+      !is_loop_jump && self.innermost_case_end() != Some(else_end) && outer_end.is_none_or(|outer_end| else_end <= outer_end)
+    });
+    self.blocks.push(if is_else {
+      Block::If { depth, else_index: Some(target_index - 1), end_pos: else_jmp.unwrap() }
+    } else {
+      Block::If { depth, else_index: None, end_pos: target_pos }
+    });
+  }
+
+  /// Recognizes `repeat with x = a to b`, which assigns the variable, compares it at the
+  /// top of the loop and increments it at the bottom. Returns where the increment starts.
+  fn match_repeat_with(&mut self, index: usize, end_repeat_index: usize, condition: &Expr) -> Option<usize> {
+    let bytecodes = &self.handler.bytecode_array[..];
+    let (line_index, var, start) = self.last_assignment.clone()?;
+    let compare = bytecodes.get(index.checked_sub(1)?)?;
+    let direction = match compare.opcode {
+      OpCode::LtEq => "to",
+      OpCode::GtEq => "down to",
+      _ => return None,
+    };
+    let increment_index = end_repeat_index.checked_sub(4).filter(|increment_index| *increment_index > index)?;
+    let increment = &bytecodes[increment_index..end_repeat_index];
+    let is_increment = increment[0].opcode == OpCode::PushInt8
+      && increment[0].obj == 1
+      && self.var_name(&increment[1]).as_ref() == Some(&var)
+      && matches!(increment[2].opcode, OpCode::Add | OpCode::Sub)
+      && self.var_name(&increment[3]).as_ref() == Some(&var);
+    let line = self.lines.get(line_index)?;
+    if !is_increment || line_index + 1 != self.lines.len() || line.bytecode_end != self.statement_start || condition.operands.len() != 2 || condition.operands[0].text != var {
+      return None;
+    }
+    let line = self.lines.last_mut().unwrap();
+    line.text = format!("repeat with {} = {} {} {}", var, start, direction, condition.operands[1].text);
+    line.bytecode_end = index + 1;
+    self.statement_start = index + 1;
+    self.last_assignment = None;
+    for hidden in &mut self.hidden[increment_index..end_repeat_index] {
+      *hidden = true;
+    }
+    Some(increment_index)
+  }
+
+  fn translate_peek(&mut self, index: usize) -> usize {
+    let bytecodes = &self.handler.bytecode_array[..];
+    let bytecode = &bytecodes[index];
+    if bytecode.obj != 0 {
+      let copy = match self.stack.len().checked_sub(1 + bytecode.obj as usize).and_then(|i| self.stack.get(i)) {
+        Some(StackItem::Expr(expr)) => expr.clone(),
+        _ => Expr::atom("?".to_owned()),
+      };
+      self.push(copy);
+      return 1;
+    }
+    if let Some(consumed) = self.match_repeat_with_in(index) {
+      return consumed;
+    }
+
+    let is_next_label = matches!(
+      self.blocks.last(),
+      Some(Block::Case { expecting_or, label_end, .. }) if *expecting_or || *label_end == bytecode.pos
+    );
+    if is_next_label {
+      if let Some(Block::Case { comparing, expecting_or, values, .. }) = self.blocks.last_mut() {
+        if !*expecting_or {
+          values.clear();
+        }
+        *comparing = true;
+      }
+    } else {
+      let switch = self.pop();
+      let depth = self.depth();
+      self.emit(format!("case {} of", switch.text), depth, index);
+      self.blocks.push(Block::Case {
+        depth,
+        values: vec![],
+        comparing: true,
+        expecting_or: false,
+        in_otherwise: false,
+        label_end: usize::MAX,
+        end_pos: None,
+      });
+    }
+    // Stands in for the switch value until it's compared
+    self.push(Expr::atom("?".to_owned()));
+    1
+  }
+
+  /// Handles the comparison of a case label against the switch value, along with the
+  /// jump that follows it.
+  fn translate_case_comparison(&mut self, index: usize) -> Option<usize> {
+    let bytecodes = &self.handler.bytecode_array[..];
+    let jmp_if_z = bytecodes.get(index + 1).filter(|x| x.opcode == OpCode::JmpIfZ)?;
+    let is_comparing = matches!(self.blocks.last(), Some(Block::Case { comparing: true, .. }));
+    if !is_comparing {
+      return None;
+    }
+    let value = self.pop();
+    self.pop();
+    let is_eq = bytecodes[index].opcode == OpCode::Eq;
+    let target_pos = jmp_if_z.pos + jmp_if_z.obj as usize;
+    let target_index = self.index_of_pos(target_pos);
+    let target = bytecodes.get(target_index);
+    let before_target = target_index.checked_sub(1).and_then(|i| bytecodes.get(i));
+    let body_end_jmp = before_target.filter(|x| x.opcode == OpCode::Jmp && x.obj > 0).map(|x| (x.pos as i64 + x.obj) as usize);
+
+    let (depth, values, comparing, expecting_or, label_end, end_pos) = match self.blocks.last_mut() {
+      Some(Block::Case { depth, values, comparing, expecting_or, label_end, end_pos, .. }) => (depth, values, comparing, expecting_or, label_end, end_pos),
+      _ => return None,
+    };
+    values.push(value.text);
+    *comparing = false;
+    // `a, b:` labels compare with <> and jump to the body when equal
+    *expecting_or = !is_eq;
+    if !is_eq {
+      return Some(2);
+    }
+    *label_end = target_pos;
+    if let Some(body_end) = body_end_jmp {
+      end_pos.get_or_insert(body_end);
+    }
+    let is_last_label = target.is_some_and(|target| target.opcode == OpCode::Pop && target.obj == 1)
+      && body_end_jmp.is_none_or(|body_end| body_end == target_pos);
+    if is_last_label {
+      *end_pos = Some(target_pos);
+    }
+    let text = format!("{}:", values.join(", "));
+    let depth = *depth + 1;
+    self.emit(text, depth, index + 2);
+    Some(2)
+  }
+
+  /// Recognizes the header of `repeat with x in list`, which keeps the list, its count
+  /// and the current index on the stack while the loop runs.
+  fn match_repeat_with_in(&mut self, index: usize) -> Option<usize> {
+    let bytecodes = &self.handler.bytecode_array[..];
+    let header = bytecodes.get(index..index + 13)?;
+    let is_header = header[1].opcode == OpCode::PushArgList
+      && header[1].obj == 1
+      && header[2].opcode == OpCode::ExtCall
+      && self.name(header[2].obj) == "count"
+      && header[3].opcode == OpCode::PushInt8
+      && header[4].opcode == OpCode::Peek
+      && header[5].opcode == OpCode::Peek
+      && header[6].opcode == OpCode::LtEq
+      && header[7].opcode == OpCode::JmpIfZ
+      && header[8].opcode == OpCode::Peek
+      && header[9].opcode == OpCode::Peek
+      && header[10].opcode == OpCode::PushArgList
+      && header[11].opcode == OpCode::ExtCall
+      && self.name(header[11].obj) == "getAt";
+    let var = self.var_name(&header[12]).filter(|_| is_header)?;
+    let end_pos = header[7].pos + header[7].obj as usize;
+    let end_repeat_index = self.index_of_pos(end_pos).checked_sub(1)?;
+    let increment_index = end_repeat_index.checked_sub(2).filter(|increment_index| *increment_index > index + 12)?;
+    if bytecodes[end_repeat_index].opcode != OpCode::EndRepeat {
+      return None;
+    }
+    let list = self.pop();
+    let depth = self.depth();
+    self.emit(format!("repeat with {} in {}", var, list.text), depth, index + 13);
+    for hidden in &mut self.hidden[increment_index..end_repeat_index] {
+      *hidden = true;
+    }
+    self.blocks.push(Block::Loop { depth, next_pos: bytecodes[increment_index].pos, end_pos });
+    Some(13)
+  }
+}
+
+fn join_exprs(exprs: &[Expr]) -> String {
+  exprs.iter().map(|expr| expr.text.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+fn put_type_name(put_type: i64) -> &'static str {
+  match put_type {
+    0x2 => "after",
+    0x3 => "before",
+    _ => "into",
+  }
+}
+
+fn chunk_type_name(chunk_type: u16) -> Option<&'static str> {
+  match chunk_type {
+    0x01 => Some("char"),
+    0x02 => Some("word"),
+    0x03 => Some("item"),
+    0x04 => Some("line"),
+    _ => None,
+  }
+}
+
+fn format_float(value: f32) -> String {
+  if value.fract() == 0.0 && value.is_finite() {
+    format!("{:.1}", value)
+  } else {
+    value.to_string()
+  }
+}
+
+fn format_literal(literal: &Datum) -> String {
+  match literal {
+    Datum::String(value) => {
+      let parts = value.split('"').map(|part| format!("\"{}\"", part)).collect::<Vec<_>>();
+      parts.join(" & QUOTE & ").replace("\"\" & ", "").replace(" & \"\"", "")
+    }
+    Datum::Int(value) => value.to_string(),
+    Datum::Float(value) => format_float(*value),
+    _ => "?".to_owned(),
+  }
+}
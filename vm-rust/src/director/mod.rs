@@ -4,5 +4,7 @@ pub mod guid;
 pub mod chunks;
 pub mod cast;
 pub mod rifx;
+pub mod rifx_writer;
+pub mod dump;
 pub mod enums;
 pub mod lingo;
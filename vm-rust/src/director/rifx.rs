@@ -1,7 +1,21 @@
+use log::warn;
+
+use crate::io::text_encoding::TextEncoding;
 
 pub struct RIFXReaderContext {
   pub after_burned: bool,
   pub ils_body_offset: usize,
   pub dir_version: u16,
   pub lctx_capital_x: bool,
+  /// The encoding of text and script strings, from the platform in the config chunk.
+  pub text_encoding: TextEncoding,
+  /// Problems that didn't stop the file from loading, kept for structure dumps.
+  pub warnings: Vec<String>,
+}
+
+impl RIFXReaderContext {
+  pub fn warn(&mut self, message: String) {
+    warn!("{}", message);
+    self.warnings.push(message);
+  }
 }
@@ -0,0 +1,115 @@
+use super::utils::FOURCC;
+
+/// The RIFX, imap, mmap and KEY* chunks come first in the memory map.
+const FIRST_CHUNK_ID: u32 = 4;
+const MMAP_HEADER_LEN: u16 = 24;
+const MMAP_ENTRY_LEN: u16 = 20;
+const KEY_TABLE_ENTRY_LEN: u16 = 12;
+const IMAP_LEN: u32 = 24;
+
+/// Writes chunks into an uncompressed big-endian RIFX file, the kind Director saves
+/// before a movie is protected or shocked.
+pub struct RifxWriter {
+  codec: u32,
+  director_version: u32,
+  chunks: Vec<(u32, Vec<u8>)>,
+  /// The chunks owned by another one, as (child id, owner id, child fourcc).
+  keys: Vec<(u32, u32, u32)>,
+}
+
+impl RifxWriter {
+  pub fn new(codec: &str, director_version: u16) -> RifxWriter {
+    RifxWriter {
+      codec: FOURCC(codec),
+      director_version: director_version as u32,
+      chunks: vec![],
+      keys: vec![],
+    }
+  }
+
+  /// Adds a chunk and returns its id in the memory map.
+  pub fn add_chunk(&mut self, fourcc: &str, data: Vec<u8>) -> u32 {
+    self.chunks.push((FOURCC(fourcc), data));
+    FIRST_CHUNK_ID + self.chunks.len() as u32 - 1
+  }
+
+  /// Adds a chunk owned by another one, like the media of a member.
+  pub fn add_child_chunk(&mut self, owner_id: u32, fourcc: &str, data: Vec<u8>) -> u32 {
+    let id = self.add_chunk(fourcc, data);
+    self.keys.push((id, owner_id, FOURCC(fourcc)));
+    id
+  }
+
+  pub fn finish(self) -> Vec<u8> {
+    let mut key_table = Vec::with_capacity(12 + self.keys.len() * 12);
+    key_table.extend_from_slice(&KEY_TABLE_ENTRY_LEN.to_be_bytes());
+    key_table.extend_from_slice(&KEY_TABLE_ENTRY_LEN.to_be_bytes());
+    key_table.extend_from_slice(&(self.keys.len() as u32).to_be_bytes());
+    key_table.extend_from_slice(&(self.keys.len() as u32).to_be_bytes());
+    for (id, owner_id, fourcc) in &self.keys {
+      key_table.extend_from_slice(&id.to_be_bytes());
+      key_table.extend_from_slice(&owner_id.to_be_bytes());
+      key_table.extend_from_slice(&fourcc.to_be_bytes());
+    }
+    let chunks = std::iter::once((FOURCC("KEY*"), key_table)).chain(self.chunks).collect::<Vec<_>>();
+
+    let entry_count = FIRST_CHUNK_ID as usize + chunks.len() - 1;
+    let imap_offset = 12;
+    let mmap_offset = imap_offset + 8 + IMAP_LEN as usize;
+    let mmap_len = MMAP_HEADER_LEN as usize + entry_count * MMAP_ENTRY_LEN as usize;
+    // Each chunk has an 8 byte header and starts on an even offset
+    let mut offsets = Vec::with_capacity(chunks.len());
+    let mut offset = mmap_offset + 8 + mmap_len;
+    for (_, data) in &chunks {
+      offsets.push(offset);
+      offset += 8 + data.len() + data.len() % 2;
+    }
+    let file_len = offset;
+
+    let mut out = Vec::with_capacity(file_len);
+    out.extend_from_slice(&FOURCC("RIFX").to_be_bytes());
+    out.extend_from_slice(&(file_len as u32 - 8).to_be_bytes());
+    out.extend_from_slice(&self.codec.to_be_bytes());
+
+    out.extend_from_slice(&FOURCC("imap").to_be_bytes());
+    out.extend_from_slice(&IMAP_LEN.to_be_bytes());
+    out.extend_from_slice(&1u32.to_be_bytes());
+    out.extend_from_slice(&(mmap_offset as u32).to_be_bytes());
+    out.extend_from_slice(&self.director_version.to_be_bytes());
+    out.extend_from_slice(&[0; 12]);
+
+    out.extend_from_slice(&FOURCC("mmap").to_be_bytes());
+    out.extend_from_slice(&(mmap_len as u32).to_be_bytes());
+    out.extend_from_slice(&MMAP_HEADER_LEN.to_be_bytes());
+    out.extend_from_slice(&MMAP_ENTRY_LEN.to_be_bytes());
+    out.extend_from_slice(&(entry_count as u32).to_be_bytes());
+    out.extend_from_slice(&(entry_count as u32).to_be_bytes());
+    // No junk or free entries
+    out.extend_from_slice(&(-1i32).to_be_bytes());
+    out.extend_from_slice(&(-1i32).to_be_bytes());
+    out.extend_from_slice(&(-1i32).to_be_bytes());
+    let write_entry = |out: &mut Vec<u8>, fourcc: u32, len: usize, offset: usize| {
+      out.extend_from_slice(&fourcc.to_be_bytes());
+      out.extend_from_slice(&(len as u32).to_be_bytes());
+      out.extend_from_slice(&(offset as u32).to_be_bytes());
+      out.extend_from_slice(&[0; 4]);
+      out.extend_from_slice(&(-1i32).to_be_bytes());
+    };
+    write_entry(&mut out, FOURCC("RIFX"), file_len - 8, 0);
+    write_entry(&mut out, FOURCC("imap"), IMAP_LEN as usize, imap_offset);
+    write_entry(&mut out, FOURCC("mmap"), mmap_len, mmap_offset);
+    for ((fourcc, data), offset) in chunks.iter().zip(&offsets) {
+      write_entry(&mut out, *fourcc, data.len(), *offset);
+    }
+
+    for (fourcc, data) in &chunks {
+      out.extend_from_slice(&fourcc.to_be_bytes());
+      out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+      out.extend_from_slice(data);
+      if data.len() % 2 == 1 {
+        out.push(0);
+      }
+    }
+    out
+  }
+}
@@ -0,0 +1,18 @@
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as padded base64, like the data of a data URL.
+pub fn encode_base64(data: &[u8]) -> String {
+  let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+  for chunk in data.chunks(3) {
+    let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+    let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    for i in 0..4 {
+      if i <= chunk.len() {
+        out.push(ALPHABET[(group >> (18 - i * 6) & 0x3F) as usize] as char);
+      } else {
+        out.push('=');
+      }
+    }
+  }
+  out
+}
@@ -1,11 +1,12 @@
 use binary_reader::{BinaryReader, Endian};
 
-use super::reader::DirectorExt;
+use super::{reader::DirectorExt, text_encoding::TextEncoding};
 
 pub fn read_pascal_string(
   item_bufs: &Vec<Vec<u8>>, 
   index: usize,
   item_endian: Endian,
+  encoding: TextEncoding,
 ) -> String {
   if index >= item_bufs.len() {
     return "".to_owned();
@@ -18,19 +19,20 @@ pub fn read_pascal_string(
     return "".to_owned();
   }
 
-  return reader.read_pascal_string().unwrap();
+  let len = reader.read_u8().unwrap() as usize;
+  return reader.read_encoded_string(len, encoding).unwrap();
 }
 
 pub fn read_string(
   item_bufs: &Vec<Vec<u8>>, 
   index: usize,
+  encoding: TextEncoding,
 ) -> String {
   if index >= item_bufs.len() {
     return "".to_owned();
   }
 
-  let buf = &item_bufs[index];
-  return String::from_utf8(buf.to_vec()).unwrap();
+  return encoding.decode(&item_bufs[index]);
 }
 
 pub fn read_u16(
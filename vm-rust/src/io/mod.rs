@@ -1,2 +1,5 @@
 pub mod reader;
 pub mod list_readers;
+pub mod text_encoding;
+pub mod zip;
+pub mod base64;
@@ -2,11 +2,14 @@ use std::io::Read;
 
 use binary_reader::BinaryReader;
 
+use super::text_encoding::TextEncoding;
+
 pub trait DirectorExt {
   fn read_var_int(&mut self) -> Result<i32, std::io::Error>;
   fn read_zlib_bytes(&mut self, length: usize) -> Result<Vec<u8>, std::io::Error>;
   fn read_pascal_string(&mut self) -> Result<String, std::io::Error>;
   fn read_string(&mut self, len: usize) -> Result<String, std::io::Error>;
+  fn read_encoded_string(&mut self, len: usize, encoding: TextEncoding) -> Result<String, std::io::Error>;
   fn read_apple_float_80(&mut self) -> Result<f64, String>;
   fn eof(&self) -> bool;
 }
@@ -47,6 +50,11 @@ impl DirectorExt for BinaryReader {
     return Ok(unsafe { String::from_utf8_unchecked(bytes.to_vec()).to_string() });
   }
 
+  fn read_encoded_string(&mut self, len: usize, encoding: TextEncoding) -> Result<String, std::io::Error> {
+    let bytes = self.read_bytes(len)?;
+    Ok(encoding.decode(bytes))
+  }
+
   fn read_apple_float_80(&mut self) -> Result<f64, String> {
     // Floats are stored as an "80 bit IEEE Standard 754 floating
     // point number (Standard Apple Numeric Environment [SANE] data type
@@ -0,0 +1,104 @@
+use encoding_rs::{Encoding, MACINTOSH, SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+/// The platform id stored in the config chunk of movies authored on Windows.
+const PLATFORM_WINDOWS: u16 = 1024;
+
+/// The character set text and script strings were authored in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextEncoding {
+  MacRoman,
+  Windows1252,
+  ShiftJis,
+  Utf8,
+}
+
+impl TextEncoding {
+  /// The default encoding of a movie, from the platform it was authored on.
+  pub fn from_platform(platform: u16) -> TextEncoding {
+    if platform == PLATFORM_WINDOWS {
+      TextEncoding::Windows1252
+    } else {
+      TextEncoding::MacRoman
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<TextEncoding> {
+    match name.to_ascii_lowercase().replace(['-', '_', ' '], "").as_str() {
+      "macroman" | "macintosh" | "mac" => Some(TextEncoding::MacRoman),
+      "windows1252" | "cp1252" | "latin1" | "ansi" => Some(TextEncoding::Windows1252),
+      "shiftjis" | "sjis" | "japanese" => Some(TextEncoding::ShiftJis),
+      "utf8" => Some(TextEncoding::Utf8),
+      _ => None,
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      TextEncoding::MacRoman => "macRoman",
+      TextEncoding::Windows1252 => "windows1252",
+      TextEncoding::ShiftJis => "shiftJIS",
+      TextEncoding::Utf8 => "utf8",
+    }
+  }
+
+  fn encoding(&self) -> &'static Encoding {
+    match self {
+      TextEncoding::MacRoman => MACINTOSH,
+      TextEncoding::Windows1252 => WINDOWS_1252,
+      TextEncoding::ShiftJis => SHIFT_JIS,
+      TextEncoding::Utf8 => UTF_8,
+    }
+  }
+
+  pub fn decode(&self, bytes: &[u8]) -> String {
+    self.encoding().decode_without_bom_handling(bytes).0.into_owned()
+  }
+
+  pub fn encode(&self, text: &str) -> Vec<u8> {
+    self.encoding().encode(text).0.into_owned()
+  }
+
+  /// The code charToNum gives a char: its byte, or both bytes of a double-byte char
+  /// such as Shift-JIS kanji. Chars the encoding lacks keep their Unicode code point.
+  pub fn char_code(&self, c: char) -> i32 {
+    if c.is_ascii() || *self == TextEncoding::Utf8 {
+      return c as i32;
+    }
+    let mut buf = [0; 4];
+    match self.encoding().encode(c.encode_utf8(&mut buf)) {
+      (bytes, _, false) => bytes.iter().fold(0, |code, byte| code << 8 | *byte as i32),
+      _ => c as i32,
+    }
+  }
+
+  /// The char numToChar gives a code, the reverse of `char_code`.
+  pub fn code_char(&self, code: i32) -> Option<char> {
+    let bytes = match code {
+      0..=127 => return Some(code as u8 as char),
+      128..=255 if *self != TextEncoding::Utf8 => vec![code as u8],
+      256..=0xFFFF if *self == TextEncoding::ShiftJis => vec![(code >> 8) as u8, code as u8],
+      _ => return std::char::from_u32(code as u32),
+    };
+    self.encoding()
+      .decode_without_bom_handling_and_without_replacement(&bytes)
+      .and_then(|text| text.chars().next())
+      .or_else(|| std::char::from_u32(code as u32))
+  }
+
+  /// Decodes again text that was decoded with the wrong encoding.
+  pub fn transcode(&self, text: &str, from: TextEncoding) -> String {
+    self.decode(&from.encode(text))
+  }
+}
+
+/// The code of a char in a bitmap font, which lays out its glyphs in Mac Roman order.
+pub fn mac_roman_code(c: char) -> u8 {
+  if c.is_ascii() {
+    return c as u8;
+  }
+  let mut buf = [0; 4];
+  match MACINTOSH.encode(c.encode_utf8(&mut buf)) {
+    (bytes, _, false) => bytes[0],
+    _ => b'?',
+  }
+}
@@ -0,0 +1,155 @@
+use std::io::{Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression, Crc};
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034B50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x02014B50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054B50;
+const ZIP_VERSION: u16 = 20;
+/// The file names are UTF-8.
+const UTF8_FLAG: u16 = 0x0800;
+const STORED_METHOD: u16 = 0;
+const DEFLATE_METHOD: u16 = 8;
+/// 1980-01-01, the earliest date a zip can hold.
+const DOS_DATE: u16 = 0x0021;
+
+struct ZipEntry {
+  name: String,
+  crc: u32,
+  compressed_len: u32,
+  len: u32,
+  offset: u32,
+}
+
+/// Writes deflated files into a zip archive in memory.
+#[derive(Default)]
+pub struct ZipWriter {
+  out: Vec<u8>,
+  entries: Vec<ZipEntry>,
+}
+
+impl ZipWriter {
+  pub fn new() -> ZipWriter {
+    ZipWriter { out: vec![], entries: vec![] }
+  }
+
+  pub fn add_file(&mut self, name: &str, data: &[u8]) {
+    let mut crc = Crc::new();
+    crc.update(data);
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    let compressed = encoder.write_all(data).and_then(|_| encoder.finish()).unwrap_or_default();
+    let entry = ZipEntry {
+      name: name.to_string(),
+      crc: crc.sum(),
+      compressed_len: compressed.len() as u32,
+      len: data.len() as u32,
+      offset: self.out.len() as u32,
+    };
+
+    self.out.extend_from_slice(&LOCAL_FILE_HEADER_SIGNATURE.to_le_bytes());
+    self.out.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+    self.write_entry_info(&entry);
+    self.out.extend_from_slice(&0u16.to_le_bytes());
+    self.out.extend_from_slice(entry.name.as_bytes());
+    self.out.extend_from_slice(&compressed);
+    self.entries.push(entry);
+  }
+
+  /// The fields the local header and the central directory share, from the flags to
+  /// the length of the name.
+  fn write_entry_info(&mut self, entry: &ZipEntry) {
+    self.out.extend_from_slice(&UTF8_FLAG.to_le_bytes());
+    self.out.extend_from_slice(&DEFLATE_METHOD.to_le_bytes());
+    self.out.extend_from_slice(&0u16.to_le_bytes());
+    self.out.extend_from_slice(&DOS_DATE.to_le_bytes());
+    self.out.extend_from_slice(&entry.crc.to_le_bytes());
+    self.out.extend_from_slice(&entry.compressed_len.to_le_bytes());
+    self.out.extend_from_slice(&entry.len.to_le_bytes());
+    self.out.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+  }
+
+  pub fn finish(mut self) -> Vec<u8> {
+    let directory_offset = self.out.len() as u32;
+    let entries = std::mem::take(&mut self.entries);
+    for entry in &entries {
+      self.out.extend_from_slice(&CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+      self.out.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+      self.out.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+      self.write_entry_info(entry);
+      // No extra field, comment, disk number or attributes
+      self.out.extend_from_slice(&[0; 12]);
+      self.out.extend_from_slice(&entry.offset.to_le_bytes());
+      self.out.extend_from_slice(entry.name.as_bytes());
+    }
+    let directory_len = self.out.len() as u32 - directory_offset;
+
+    self.out.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+    self.out.extend_from_slice(&[0; 4]);
+    self.out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    self.out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    self.out.extend_from_slice(&directory_len.to_le_bytes());
+    self.out.extend_from_slice(&directory_offset.to_le_bytes());
+    self.out.extend_from_slice(&0u16.to_le_bytes());
+    self.out
+  }
+}
+
+/// The most deflate can expand its input by.
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+/// Reads every file in a zip archive, skipping folders. Only stored and deflated files
+/// are supported.
+pub fn read_zip(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+  let u16_at = |offset: usize| data.get(offset..offset.checked_add(2)?).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize);
+  let u32_at = |offset: usize| data.get(offset..offset.checked_add(4)?).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize);
+  let truncated = || "Zip archive ends early".to_string();
+  let add = |a: usize, b: usize| a.checked_add(b).ok_or_else(truncated);
+
+  // The end of the central directory is followed by a comment of up to 64K
+  let end_offset = (0..data.len().saturating_sub(21)).rev()
+    .take(0x10000 + 22)
+    .find(|offset| u32_at(*offset) == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE as usize))
+    .ok_or_else(|| "Not a zip archive".to_string())?;
+  let entry_count = u16_at(end_offset + 10).ok_or_else(truncated)?;
+  let mut offset = u32_at(end_offset + 16).ok_or_else(truncated)?;
+
+  let mut files = Vec::with_capacity(entry_count);
+  for _ in 0..entry_count {
+    if u32_at(offset) != Some(CENTRAL_DIRECTORY_SIGNATURE as usize) {
+      return Err("Zip central directory is corrupt".to_string());
+    }
+    let method = u16_at(offset + 10).ok_or_else(truncated)?;
+    let compressed_len = u32_at(offset + 20).ok_or_else(truncated)?;
+    let len = u32_at(offset + 24).ok_or_else(truncated)?;
+    let name_len = u16_at(offset + 28).ok_or_else(truncated)?;
+    let extra_len = u16_at(offset + 30).ok_or_else(truncated)?;
+    let comment_len = u16_at(offset + 32).ok_or_else(truncated)?;
+    let header_offset = u32_at(offset + 42).ok_or_else(truncated)?;
+    let name_offset = add(offset, 46)?;
+    let name = data.get(name_offset..add(name_offset, name_len)?).ok_or_else(truncated)?;
+    let name = String::from_utf8_lossy(name).into_owned();
+    offset = add(add(name_offset, name_len)?, extra_len + comment_len)?;
+    if name.ends_with('/') {
+      continue;
+    }
+
+    // The local header can have a different extra field than the central directory
+    let local_name_len = u16_at(add(header_offset, 26)?).ok_or_else(truncated)?;
+    let local_extra_len = u16_at(add(header_offset, 28)?).ok_or_else(truncated)?;
+    let data_offset = add(add(header_offset, 30)?, local_name_len + local_extra_len)?;
+    let compressed = data.get(data_offset..add(data_offset, compressed_len)?).ok_or_else(truncated)?;
+    let contents = match method as u16 {
+      STORED_METHOD => compressed.to_vec(),
+      DEFLATE_METHOD => {
+        // The size comes from the file, so it's only trusted as far as deflate can expand
+        let mut contents = Vec::with_capacity(len.min(compressed.len().saturating_mul(MAX_DEFLATE_RATIO)));
+        DeflateDecoder::new(compressed).read_to_end(&mut contents)
+          .map_err(|err| format!("Could not inflate {}: {}", name, err))?;
+        contents
+      }
+      method => return Err(format!("{} uses unsupported compression method {}", name, method)),
+    };
+    files.push((name, contents));
+  }
+  Ok(files)
+}
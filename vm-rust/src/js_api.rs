@@ -6,12 +6,12 @@ use wasm_bindgen::prelude::*;
 
 use crate::{
     director::{
-        chunks::{script::ScriptChunk, ChunkContainer},
+        chunks::{script::ScriptChunk, sound::SoundChunk, ChunkContainer},
         enums::ScriptType,
-        file::DirectorFile,
-        lingo::{datum::Datum, script::ScriptContext}, utils::fourcc_to_string,
+        file::{get_variable_multiplier, DirectorFile},
+        lingo::{datum::Datum, decompiler::decompile_handler, script::ScriptContext}, utils::fourcc_to_string,
     }, player::{
-        allocator::ScriptInstanceAllocatorTrait, bitmap::bitmap::PaletteRef, cast_lib::CastMemberRef, cast_member::{CastMember, CastMemberType, ScriptMember}, datum_formatting::{format_concrete_datum, format_datum}, datum_ref::{DatumId, DatumRef}, handlers::datum_handlers::cast_member_ref::CastMemberRefHandlers, reserve_player_ref, score::Score, script::ScriptInstanceId, script_ref::ScriptInstanceRef, DirPlayer, ScriptError, PLAYER_OPT
+        allocator::ScriptInstanceAllocatorTrait, bitmap::bitmap::PaletteRef, cast_lib::{CastLib, CastMemberRef}, cast_member::{CastMember, CastMemberType, ScriptMember}, datum_formatting::{format_concrete_datum, format_datum}, datum_ref::{DatumId, DatumRef}, frame_hook::FrameDigest, property_descriptions::{get_description_entry, BehaviorDescription}, script::{get_lctx_for_script_ref, script_get_prop_opt}, handlers::datum_handlers::cast_member_ref::CastMemberRefHandlers, reserve_player_ref, score::Score, script::ScriptInstanceId, script_ref::ScriptInstanceRef, DirPlayer, ScriptError, PLAYER_OPT
     }, rendering::RENDERER_LOCK
 };
 
@@ -64,6 +64,7 @@ pub struct JsBridgeBreakpoint {
   pub script_name: String,
   pub handler_name: String,
   pub bytecode_index: usize,
+  pub condition: Option<String>,
 }
 
 impl Into<js_sys::Map> for JsBridgeBreakpoint {
@@ -72,6 +73,7 @@ impl Into<js_sys::Map> for JsBridgeBreakpoint {
     map.str_set("script_name", &JsValue::from_str(&self.script_name));
     map.str_set("handler_name", &JsValue::from_str(&self.handler_name));
     map.str_set("bytecode_index", &JsValue::from(self.bytecode_index as u32));
+    map.str_set("condition", &self.condition.map_or(JsValue::NULL, |condition| JsValue::from_str(&condition)));
     map
   }
 }
@@ -168,6 +170,9 @@ extern "C" {
   pub fn onScriptError(data: js_sys::Object);
   pub fn onScopeListChanged(scopes: Vec<js_sys::Object>);
   pub fn onBreakpointListChanged(data: Vec<js_sys::Object>);
+  pub fn onWatchListChanged(watches: Vec<js_sys::Object>);
+  pub fn onCoverageSummaryChanged(scripts: Vec<js_sys::Object>);
+  pub fn onBehaviorDescriptionsChanged(sprite_num: i16, behaviors: Vec<js_sys::Object>);
   pub fn onGlobalListChanged(data: js_sys::Object);
   pub fn onScriptErrorCleared();
   pub fn onDebugMessage(message: &str);
@@ -176,6 +181,17 @@ extern "C" {
   pub fn onClearTimeouts();
   pub fn onDatumSnapshot(datum_id: DatumId, data: js_sys::Object);
   pub fn onScriptInstanceSnapshot(script_ref: ScriptInstanceId, data: js_sys::Object);
+  pub fn onGoToNetPage(url: &str, target: &str);
+  pub fn onExternalEvent(event: &str);
+  pub fn onFrameDigest(digest: js_sys::Object);
+  pub fn onWindowOpened(name: &str, title: &str, width: i32, height: i32);
+  pub fn onWindowClosed(name: &str);
+  pub fn onSoundChannelChanged(channel: u16, volume: i32, pan: i32, ramp_ms: i32);
+  pub fn onSoundMasterVolumeChanged(gain: f32);
+  pub fn onSoundPlay(channel: u16, samples: js_sys::Uint8Array, sample_rate: u32, channel_count: u16, bits_per_sample: u16, offset_ms: i32, play_count: i32);
+  pub fn onSoundStop(channel: u16);
+  pub fn onSoundBreakLoop(channel: u16);
+  pub fn onStageCursorChanged(cursor: &str);
 }
 
 pub struct JsApi {}
@@ -290,7 +306,7 @@ impl JsApi {
 
       let cast = player.movie.cast_manager.get_cast(member_ref.cast_lib as u32).unwrap();
       let member = cast.members.get(&(member_ref.cast_member as u32)).unwrap();
-      let member_map = Self::get_member_snapshot(member, cast.lctx.as_ref(), player);
+      let member_map = Self::get_member_snapshot(member, cast, player);
 
       onCastMemberChanged(member_ref.to_js().to_js_value(), member_map.to_js_object());
     });
@@ -342,6 +358,65 @@ impl JsApi {
     onDebugMessage(message);
   }
 
+  pub fn dispatch_go_to_net_page(url: &str, target: &str) {
+    onGoToNetPage(url, target);
+  }
+
+  pub fn dispatch_external_event(event: &str) {
+    onExternalEvent(event);
+  }
+
+  pub fn dispatch_window_opened(name: &str, title: &str, width: i32, height: i32) {
+    onWindowOpened(name, title, width, height);
+  }
+
+  pub fn dispatch_window_closed(name: &str) {
+    onWindowClosed(name);
+  }
+
+  pub fn dispatch_sound_channel_changed(channel: u16, volume: i32, pan: i32, ramp_ms: i32) {
+    onSoundChannelChanged(channel, volume, pan, ramp_ms);
+  }
+
+  pub fn dispatch_sound_master_volume_changed(gain: f32) {
+    onSoundMasterVolumeChanged(gain);
+  }
+
+  /// Starts the sound in the channel, `offset_ms` into it, playing it `play_count` times.
+  pub fn dispatch_sound_play(channel: u16, sound: &SoundChunk, offset_ms: i32, play_count: i32) {
+    // Copied out of the wasm memory, so the page can keep it
+    let samples = js_sys::Uint8Array::from(sound.samples.as_ref());
+    onSoundPlay(channel, samples, sound.sample_rate, sound.channel_count, sound.bits_per_sample, offset_ms, play_count);
+  }
+
+  pub fn dispatch_sound_stop(channel: u16) {
+    onSoundStop(channel);
+  }
+
+  /// Lets the sound in the channel play to its end instead of looping again.
+  pub fn dispatch_sound_break_loop(channel: u16) {
+    onSoundBreakLoop(channel);
+  }
+
+  pub fn dispatch_stage_cursor_changed(cursor: &str) {
+    onStageCursorChanged(cursor);
+  }
+
+  pub fn dispatch_frame_digest(digest: &FrameDigest) {
+    let changed_globals = js_sys::Map::new();
+    for (name, value) in &digest.changed_globals {
+      changed_globals.str_set(name, &JsValue::from_str(value));
+    }
+    let digest_map = js_sys::Map::new();
+    digest_map.str_set("frame", &JsValue::from_f64(digest.frame as f64));
+    digest_map.str_set(
+      "label",
+      &digest.frame_label.as_ref().map_or(JsValue::NULL, |label| JsValue::from_str(label)),
+    );
+    digest_map.str_set("changedGlobals", &changed_globals.to_js_object());
+    onFrameDigest(digest_map.to_js_object());
+  }
+
   pub fn get_mini_member_snapshot(member: &CastMember) -> js_sys::Map {
     let member_map = js_sys::Map::new();
     member_map.str_set("name", &JsValue::from_str(&member.name));
@@ -352,7 +427,7 @@ impl JsApi {
     return member_map;
   }
 
-  pub fn get_member_snapshot(member: &CastMember, lctx: Option<&ScriptContext>, player: &DirPlayer) -> js_sys::Map {
+  pub fn get_member_snapshot(member: &CastMember, cast: &CastLib, player: &DirPlayer) -> js_sys::Map {
     let member_map = js_sys::Map::new();
     member_map.str_set("number", &JsValue::from(member.number));
     member_map.str_set("name", &JsValue::from_str(&member.name));
@@ -369,11 +444,12 @@ impl JsApi {
         member_map.str_set("text", &ascii_safe(&text_data.text).to_js_value());
       }
       CastMemberType::Script(script_data) => {
-        let lctx = lctx.unwrap();
+        let lctx = cast.lctx.as_ref().unwrap();
         let script = &lctx.scripts[&script_data.script_id];
+        let variable_multiplier = get_variable_multiplier(cast.capital_x, cast.dir_version);
         member_map.str_set(
             "script",
-            &Self::get_script_snapshot(&script_data, &script, &lctx).to_js_object(),
+            &Self::get_script_snapshot(&script_data, &script, &lctx, variable_multiplier, cast.dir_version).to_js_object(),
         );
       }
       CastMemberType::Bitmap(bitmap_data) => {
@@ -521,9 +597,13 @@ impl JsApi {
     member: &ScriptMember,
     chunk: &ScriptChunk,
     lctx: &ScriptContext,
+    variable_multiplier: u32,
+    dir_version: u16,
   ) -> js_sys::Map {
     let member_map = js_sys::Map::new();
     member_map.str_set("name", &member.name.to_js_value());
+    // Without the source the handlers are only known from their bytecode
+    member_map.str_set("reconstructed", &JsValue::from_bool(member.source_text.is_empty()));
     member_map.str_set(
       "script_type",
       &match member.script_type {
@@ -550,6 +630,16 @@ impl JsApi {
         bytecode_array.push(&bytecode_map.to_js_object());
       }
 
+      let lines_array = js_sys::Array::new();
+      for line in decompile_handler(handler, chunk, lctx, variable_multiplier, dir_version) {
+        let line_map = js_sys::Map::new();
+        line_map.str_set("text", &line.text.to_js_value());
+        line_map.str_set("indent", &JsValue::from(line.indent));
+        line_map.str_set("bytecode_start", &JsValue::from(line.bytecode_start));
+        line_map.str_set("bytecode_end", &JsValue::from(line.bytecode_end));
+        lines_array.push(&line_map.to_js_object());
+      }
+
       for arg in &handler.argument_name_ids {
         args_array.push(&lctx.names[*arg as usize].to_js_value());
       }
@@ -557,6 +647,7 @@ impl JsApi {
       handler_map.str_set("name", &name.to_js_value());
       handler_map.str_set("args", &args_array);
       handler_map.str_set("bytecode", &bytecode_array);
+      handler_map.str_set("lines", &lines_array);
       handlers_array.push(&handler_map.to_js_object());
     }
     member_map.str_set("handlers", &handlers_array);
@@ -571,9 +662,9 @@ impl JsApi {
         .iter()
         .enumerate()
         .filter(|(i, _)| player.scope_count > *i as u32)
-        .map(|(_, scope)| {
-          let cast_lib = player.movie.cast_manager.get_cast(scope.script_ref.cast_lib as u32).unwrap();
-          let handler_name = cast_lib.lctx.as_ref().unwrap().names.get(scope.handler_name_id as usize).unwrap();
+        // Scopes that don't belong to a script, like the one of the Lingo console, aren't listed
+        .filter_map(|(_, scope)| {
+          let handler_name = get_lctx_for_script_ref(player, &scope.script_ref)?.names.get(scope.handler_name_id as usize)?;
           let scope = JsBridgeScope {
             script_member_ref: scope.script_ref.to_js(),
             bytecode_index: scope.bytecode_index as u32,
@@ -583,7 +674,7 @@ impl JsApi {
             args: scope.args.clone()
           };
           let scope_js: js_sys::Map = scope.into();
-          scope_js.to_js_object()
+          Some(scope_js.to_js_object())
         })
         .collect(),
     );
@@ -600,6 +691,63 @@ impl JsApi {
     onGlobalListChanged(globals.to_js_object());
   }
 
+  pub fn dispatch_watch_list(player: &DirPlayer) {
+    onWatchListChanged(
+      player
+        .breakpoint_manager
+        .watches
+        .iter()
+        .map(|watch| {
+          let watch_map = js_sys::Map::new();
+          watch_map.str_set("expression", &JsValue::from_str(&watch.expression));
+          match &watch.value {
+            Ok(value) => watch_map.str_set("datum_ref", &value.unwrap().to_js_value()),
+            Err(err) => watch_map.str_set("error", &JsValue::from_str(err)),
+          }
+          watch_map.to_js_object()
+        })
+        .collect(),
+    );
+  }
+
+  /// Sends the coverage of each script, or an empty list when coverage isn't being recorded.
+  pub fn dispatch_coverage_summary(player: &DirPlayer) {
+    onCoverageSummaryChanged(
+      player
+        .coverage_recorder
+        .as_ref()
+        .map(|recorder| recorder.script_summaries(&player.movie.cast_manager))
+        .unwrap_or_default(),
+    );
+  }
+
+  /// Sends the parameters each behavior of a sprite describes, with their current values.
+  pub fn dispatch_behavior_descriptions(player: &mut DirPlayer, sprite_num: i16, behaviors: &[BehaviorDescription]) {
+    let mut behavior_objects = vec![];
+    for behavior in behaviors {
+      let property_array = js_sys::Array::new();
+      for (prop_name, description_ref) in behavior.properties.iter() {
+        let property_map = js_sys::Map::new();
+        property_map.str_set("name", &JsValue::from_str(prop_name));
+        for key in ["format", "comment", "default", "range"] {
+          if let Some(value_ref) = get_description_entry(player, description_ref, key) {
+            property_map.str_set(key, &JsValue::from_str(&format_datum(&value_ref, player)));
+          }
+        }
+        if let Some(value_ref) = script_get_prop_opt(player, &behavior.instance_ref, prop_name) {
+          property_map.str_set("value", &JsValue::from_str(&format_datum(&value_ref, player)));
+        }
+        property_array.push(&property_map.to_js_object());
+      }
+      let behavior_map = js_sys::Map::new();
+      behavior_map.str_set("scriptInstance", &JsValue::from_f64(*behavior.instance_ref as f64));
+      behavior_map.str_set("scriptMemberRef", &behavior.script_ref.to_js().to_js_value());
+      behavior_map.str_set("properties", &property_array);
+      behavior_objects.push(behavior_map.to_js_object());
+    }
+    onBehaviorDescriptionsChanged(sprite_num, behavior_objects);
+  }
+
   pub fn dispatch_debug_update(player: &DirPlayer) {
     Self::dispatch_scope_list(player);
     Self::dispatch_global_list(player);
@@ -607,8 +755,8 @@ impl JsApi {
 
   pub fn dispatch_script_error(player: &DirPlayer, err: &ScriptError) {
     let data: js_sys::Map = if let Some(current_scope) = player.scopes.get(player.current_scope_ref()) {
-      let cast_lib = player.movie.cast_manager.get_cast(current_scope.script_ref.cast_lib as u32).unwrap();
-      let current_handler_name = cast_lib.lctx.as_ref().unwrap().names.get(current_scope.handler_name_id as usize).unwrap();
+      let lctx = get_lctx_for_script_ref(player, &current_scope.script_ref).unwrap();
+      let current_handler_name = lctx.names.get(current_scope.handler_name_id as usize).unwrap();
 
       OnScriptErrorCallbackData {
         message: err.message.to_owned(),
@@ -639,6 +787,7 @@ impl JsApi {
             script_name: x.script_name.to_owned(),
             handler_name: x.handler_name.to_owned(),
             bytecode_index: x.bytecode_index,
+            condition: x.condition.clone(),
           };
           let breakpoint_js: js_sys::Map = breakpoint.into();
           breakpoint_js.to_js_object()
@@ -802,6 +951,17 @@ fn concrete_datum_to_js_bridge(datum: &Datum, player: &DirPlayer, depth: u8) ->
     Datum::SoundRef(_) => {
       map.str_set("type", &JsValue::from_str("soundRef"));
     }
+    Datum::WindowRef(name) => {
+      map.str_set("type", &JsValue::from_str("windowRef"));
+      map.str_set("name", &JsValue::from_str(name));
+    }
+    Datum::Date(date) => {
+      map.str_set("type", &JsValue::from_str("date"));
+      map.str_set("year", &JsValue::from(date.year()));
+      map.str_set("month", &JsValue::from(date.month()));
+      map.str_set("day", &JsValue::from(date.day()));
+      map.str_set("seconds", &JsValue::from(date.seconds));
+    }
   }
   return map.to_js_object();
 }
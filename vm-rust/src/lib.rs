@@ -3,11 +3,14 @@ mod player;
 mod io;
 mod js_api;
 mod rendering;
+#[cfg(test)]
+mod test_utils;
 
 use async_std::task::spawn_local;
-use js_api::JsApi;
-use num::ToPrimitive;
-use utils::set_panic_hook;
+use director::{dump::dump_director_file, enums::ScriptType};
+use itertools::Itertools;
+use js_api::{JsApi, JsUtils};
+use utils::{performance_now, set_panic_hook};
 use wasm_bindgen::prelude::*;
 
 #[macro_use]
@@ -15,7 +18,7 @@ extern crate pest_derive;
 
 mod director;
 
-use player::{cast_lib::{cast_member_ref, CastMemberRef}, commands::{player_dispatch, PlayerVMCommand}, datum_ref::DatumId, init_player, PLAYER_OPT};
+use player::{cast_lib::{cast_member_ref, CastMemberRef}, key_capture::{with_key_capture_policy, KeyCapturePolicy, KeyPress}, commands::{player_dispatch, player_dispatch_async, PlayerVMCommand}, compatibility::CompatibilityProfile, net_bundle::NetBundle, watchdog::WatchdogAction, net_task::{NetResponseInfo, NET_ERROR_CONNECTION_FAILED}, datum_ref::DatumId, init_player, reserve_player_mut, reserve_player_ref, save_state::{load_player_state, save_player_state}, stage::render_stage_image, bitmap::png::encode_png, xtra::buddyapi::borrow_buddy_api_manager_mut, PLAYER_OPT};
 
 #[wasm_bindgen]
 extern "C" {
@@ -24,17 +27,316 @@ extern "C" {
 
 #[wasm_bindgen]
 pub fn set_external_params(params: js_sys::Object) {
-  let mut external_params = std::collections::HashMap::new();
+  let mut external_params = vec![];
   let keys = js_sys::Object::keys(&params);
   for key in keys.iter() {
     let key_str = key.as_string().unwrap();
     let value = js_sys::Reflect::get(&params, &key).unwrap().as_string().unwrap();
-    external_params.insert(key_str, value);
+    external_params.push((key_str, value));
   }
 
   player_dispatch(PlayerVMCommand::SetExternalParams(external_params));
 }
 
+/// Calls `onFrameDigest` every `interval` frames with the current frame, label and
+/// changed globals. An interval of 0 turns the hook off.
+#[wasm_bindgen]
+pub fn set_frame_hook_interval(interval: u32) {
+  player_dispatch(PlayerVMCommand::SetFrameHookInterval(interval));
+}
+
+#[wasm_bindgen]
+pub fn set_buddy_api_defaults(params: js_sys::Object) {
+  let keys = js_sys::Object::keys(&params);
+  borrow_buddy_api_manager_mut(|manager| {
+    for key in keys.iter() {
+      let key_str = key.as_string().unwrap_or_default();
+      let value = js_sys::Reflect::get(&params, &key).unwrap_or(JsValue::UNDEFINED);
+      // Numbers and booleans are what BuddyAPI hands back for flags like baFileExists
+      let value = if let Some(value) = value.as_string() {
+        value
+      } else if let Some(value) = value.as_f64() {
+        value.to_string()
+      } else if let Some(value) = value.as_bool() {
+        (value as i32).to_string()
+      } else {
+        console_warn!("Ignoring BuddyAPI default {}: not a string, number or boolean", key_str);
+        continue;
+      };
+      manager.set_default(&key_str, &value);
+    }
+  });
+}
+
+/// In safe mode the movie is loaded and frame 1 is shown, but no Lingo is ever run,
+/// which allows inspecting the assets of movies that crash on startup.
+#[wasm_bindgen]
+pub fn set_safe_mode(enabled: bool) {
+  player_dispatch(PlayerVMCommand::SetSafeMode(enabled));
+}
+
+/// Lets movies run Lingo built from strings with `do` and `doLater`. Hosts can turn it off
+/// for untrusted movies, which then get a script error instead.
+#[wasm_bindgen]
+pub fn set_allow_do(enabled: bool) {
+  player_dispatch(PlayerVMCommand::SetAllowDo(enabled));
+}
+
+/// When enabled, calls to global handlers the player doesn't implement return VOID with
+/// a warning instead of stopping the movie with a script error.
+#[wasm_bindgen]
+pub fn set_lenient_missing_handlers(enabled: bool) {
+  player_dispatch(PlayerVMCommand::SetLenientMissingHandlers(enabled));
+}
+
+/// Sets how a missing handler is treated regardless of `set_lenient_missing_handlers`:
+/// `"skip"` returns VOID, `"error"` stops the movie, and `"default"` follows the setting.
+#[wasm_bindgen]
+pub fn set_missing_handler_behavior(name: String, behavior: String) {
+  let skip = match behavior.as_str() {
+    "skip" => Some(true),
+    "error" => Some(false),
+    _ => None,
+  };
+  player_dispatch(PlayerVMCommand::SetMissingHandlerOverride(name, skip));
+}
+
+/// Applies per-movie fixes from a JSON compatibility profile, or clears them when `json`
+/// is empty. Set it before loading the movie so it's in place when playback starts.
+#[wasm_bindgen]
+pub fn set_compatibility_profile(json: String) -> Result<(), JsValue> {
+  let profile = if json.trim().is_empty() {
+    None
+  } else {
+    Some(CompatibilityProfile::from_json(&json).map_err(|err| JsValue::from_str(&err))?)
+  };
+  player_dispatch(PlayerVMCommand::SetCompatibilityProfile(profile));
+  Ok(())
+}
+
+/// When enabled, loading `movie.dcr` also looks for `movie.compat.json` next to it,
+/// unless the host already set a profile.
+#[wasm_bindgen]
+pub fn set_fetch_compatibility_profiles(enabled: bool) {
+  player_dispatch(PlayerVMCommand::SetFetchCompatibilityProfiles(enabled));
+}
+
+/// Limits how long a script can run without returning, for movies stuck in a loop.
+/// Past `budget_ms`, the script is paused in the debugger with `"pause"` or fails with a
+/// script error with `"abort"`. Zero lets scripts run forever, which is the default.
+#[wasm_bindgen]
+pub fn set_script_watchdog(budget_ms: u32, action: String) {
+  let action = match action.as_str() {
+    "abort" => WatchdogAction::Abort,
+    _ => WatchdogAction::Pause,
+  };
+  player_dispatch(PlayerVMCommand::SetScriptWatchdog((budget_ms > 0).then_some(budget_ms), action));
+}
+
+/// Sets how many handler calls can be nested before scripts fail with a stack overflow
+/// error. Defaults to 256.
+#[wasm_bindgen]
+pub fn set_max_stack_size(size: u32) {
+  player_dispatch(PlayerVMCommand::SetMaxStackSize(size as usize));
+}
+
+/// Starts recording which handlers and bytecode offsets run, discarding any previous
+/// recording. Scripts run slower while coverage is enabled.
+#[wasm_bindgen]
+pub fn set_coverage_enabled(enabled: bool) {
+  player_dispatch(PlayerVMCommand::SetCoverageEnabled(enabled));
+}
+
+/// Returns the recorded coverage as JSON, listing the executed offsets of every handler.
+#[wasm_bindgen]
+pub fn get_coverage_report() -> Option<String> {
+  reserve_player_ref(|player| {
+    player.coverage_recorder.as_ref().map(|recorder| recorder.to_json(&player.movie.cast_manager))
+  })
+}
+
+/// Requests the recorded coverage aggregated per script member, which is sent to
+/// `onCoverageSummaryChanged`.
+#[wasm_bindgen]
+pub fn request_coverage_summary() {
+  player_dispatch(PlayerVMCommand::RequestCoverageSummary);
+}
+
+/// Requests the parameters the behaviors of a sprite describe in getPropertyDescriptionList,
+/// which are sent to `onBehaviorDescriptionsChanged`.
+#[wasm_bindgen]
+pub fn request_behavior_descriptions(sprite_num: i16) {
+  player_dispatch(PlayerVMCommand::RequestBehaviorDescriptions(sprite_num));
+}
+
+/// Starts timing every handler call, discarding any previous recording.
+#[wasm_bindgen]
+pub fn set_profiling_enabled(enabled: bool) {
+  player_dispatch(PlayerVMCommand::SetProfilingEnabled(enabled));
+}
+
+/// Returns the recorded handler calls in the speedscope format, to be opened at
+/// https://www.speedscope.app.
+#[wasm_bindgen]
+pub fn get_profile_json() -> Option<String> {
+  reserve_player_ref(|player| {
+    player.handler_profiler.as_ref().map(|profiler| profiler.speedscope_json(performance_now()))
+  })
+}
+
+/// Returns call counts and inclusive and exclusive times per handler, along with the
+/// calls between handlers, as JSON.
+#[wasm_bindgen]
+pub fn get_profile_summary() -> Option<String> {
+  reserve_player_ref(|player| player.handler_profiler.as_ref().map(|profiler| profiler.summary_json()))
+}
+
+/// Returns a readable tree of the chunks of the movie and its loaded external casts,
+/// along with the problems found while reading them, for attaching to issue reports.
+#[wasm_bindgen]
+pub fn dump_movie_structure() -> Option<String> {
+  reserve_player_ref(|player| {
+    let movie_file = player.movie.file.as_ref()?;
+    let mut dump = dump_director_file(movie_file);
+    for (_, cast_file) in player.dir_cache.iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+      dump.push('\n');
+      dump.push_str(&dump_director_file(cast_file));
+    }
+    Some(dump)
+  })
+}
+
+/// Returns what the loaded movie files and the cast images take up, as JSON.
+#[wasm_bindgen]
+pub fn get_memory_stats() -> String {
+  reserve_player_ref(|player| {
+    let files = player.movie.file.iter().chain(player.dir_cache.values());
+    let (chunk_count, chunk_bytes) = files.fold((0, 0), |(count, bytes), file| {
+      (count + file.chunk_container.cached_chunk_views.len(), bytes + file.chunk_container.cached_bytes())
+    });
+    let bitmaps = player.bitmap_manager.get_memory_stats();
+    let stats = js_sys::Map::new();
+    stats.str_set("cachedChunkCount", &JsValue::from_f64(chunk_count as f64));
+    stats.str_set("cachedChunkBytes", &JsValue::from_f64(chunk_bytes as f64));
+    stats.str_set("bitmapCount", &JsValue::from_f64(bitmaps.lazy_count as f64));
+    stats.str_set("compressedBitmapBytes", &JsValue::from_f64(bitmaps.compressed_bytes as f64));
+    stats.str_set("decodedBitmapCount", &JsValue::from_f64(bitmaps.decoded_count as f64));
+    stats.str_set("decodedBitmapBytes", &JsValue::from_f64(bitmaps.decoded_bytes as f64));
+    stats.str_set("modifiedBitmapCount", &JsValue::from_f64(bitmaps.modified_count as f64));
+    stats.str_set("modifiedBitmapBytes", &JsValue::from_f64(bitmaps.modified_bytes as f64));
+    js_sys::JSON::stringify(&js_sys::Object::from_entries(&stats).unwrap()).map(String::from).unwrap_or_default()
+  })
+}
+
+/// Returns the frames, sprite spans, behaviors and labels of the movie's score as JSON.
+#[wasm_bindgen]
+pub fn get_score_json() -> String {
+  reserve_player_ref(|player| player.movie.score.to_json())
+}
+
+/// Renders a cast member into a `width` by `height` RGBA preview for the cast inspector.
+/// Returns no pixels for members that don't exist or have nothing to show.
+#[wasm_bindgen]
+pub fn get_member_thumbnail(cast_lib: i32, member: i32, width: u16, height: u16) -> Vec<u8> {
+  reserve_player_ref(|player| {
+    let member_ref = CastMemberRef { cast_lib, cast_member: member };
+    rendering::render_member_thumbnail(player, &member_ref, width, height).map_or(vec![], |thumbnail| thumbnail.data)
+  })
+}
+
+/// Converts a cast member to a file, returned as an object with its `fileName`,
+/// `mimeType` and `data`.
+#[wasm_bindgen]
+pub fn export_member(cast_lib: i32, member: i32) -> Result<js_sys::Object, JsValue> {
+  reserve_player_ref(|player| {
+    let exported = player::export::export_member(player, &CastMemberRef { cast_lib, cast_member: member })
+      .map_err(|err| JsValue::from_str(&err.message))?;
+    let result = js_sys::Map::new();
+    result.str_set("fileName", &JsValue::from_str(&exported.file_name));
+    result.str_set("mimeType", &JsValue::from_str(exported.mime_type));
+    result.str_set("data", &js_sys::Uint8Array::from(exported.data.as_slice()));
+    js_sys::Object::from_entries(&result)
+  })
+}
+
+/// Exports every member that can be converted to a file into a zip, one folder per cast.
+#[wasm_bindgen]
+pub fn export_all_members() -> Vec<u8> {
+  reserve_player_ref(player::export::export_all_members)
+}
+
+/// Adds a bitmap member from PNG, JPEG or GIF bytes at `member`, or at the first free
+/// number when it's 0, and returns the member number.
+#[wasm_bindgen]
+pub fn add_bitmap_member(cast_lib: u32, member: u32, name: String, data: &[u8]) -> Result<i32, JsValue> {
+  reserve_player_mut(|player| {
+    player::authoring::add_bitmap_member(player, cast_lib, Some(member), &name, data)
+      .map(|member_ref| member_ref.cast_member)
+      .map_err(|err| JsValue::from_str(&err.message))
+  })
+}
+
+/// Adds a text member, or a field when `is_field` is set, and returns the member number.
+#[wasm_bindgen]
+pub fn add_text_member(cast_lib: u32, member: u32, name: String, text: String, is_field: bool) -> Result<i32, JsValue> {
+  reserve_player_mut(|player| {
+    player::authoring::add_text_member(player, cast_lib, Some(member), &name, &text, is_field)
+      .map(|member_ref| member_ref.cast_member)
+      .map_err(|err| JsValue::from_str(&err.message))
+  })
+}
+
+/// Compiles Lingo source into a script member at `member`, or at the first free number
+/// when it's 0, and returns the member number. The type is movie, score or parent.
+#[wasm_bindgen]
+pub fn compile_and_attach_script(cast_lib: u32, member: u32, name: String, script_type: String, source: String) -> Result<i32, JsValue> {
+  let script_type = match script_type.as_str() {
+    "movie" => ScriptType::Movie,
+    "score" => ScriptType::Score,
+    "parent" => ScriptType::Parent,
+    _ => return Err(JsValue::from_str(&format!("Unknown script type {}", script_type))),
+  };
+  reserve_player_mut(|player| {
+    player::authoring::compile_and_attach_script(player, cast_lib, Some(member), &name, &source, script_type)
+      .map(|member_ref| member_ref.cast_member)
+      .map_err(|err| JsValue::from_str(&err.message))
+  })
+}
+
+/// Writes a cast with its changes as .cst bytes, returned as an object with the `data`
+/// and the `skippedMembers` that couldn't be written, like sounds.
+#[wasm_bindgen]
+pub fn export_cast_file(cast_lib: u32) -> Result<js_sys::Object, JsValue> {
+  reserve_player_ref(|player| {
+    let exported = player::export::export_cast_file(player, cast_lib).map_err(|err| JsValue::from_str(&err.message))?;
+    let skipped_members = exported.skipped_members.iter().map(|number| JsValue::from(*number)).collect::<js_sys::Array>();
+    let result = js_sys::Map::new();
+    result.str_set("data", &js_sys::Uint8Array::from(exported.data.as_slice()));
+    result.str_set("skippedMembers", &skipped_members);
+    js_sys::Object::from_entries(&result)
+  })
+}
+
+/// Snapshots the player state between frames, for rewinding or resuming later.
+#[wasm_bindgen]
+pub fn save_state() -> Result<Vec<u8>, JsValue> {
+  reserve_player_ref(save_player_state).map_err(|err| JsValue::from_str(&err.message))
+}
+
+/// Encodes the current stage composite as a PNG, for screenshots and thumbnails.
+#[wasm_bindgen]
+pub fn capture_stage_png() -> Vec<u8> {
+  reserve_player_mut(|player| {
+    let image = render_stage_image(player);
+    encode_png(&image, &player.movie.cast_manager.palettes())
+  })
+}
+
+#[wasm_bindgen]
+pub fn load_state(bytes: &[u8]) -> Result<(), JsValue> {
+  reserve_player_mut(|player| load_player_state(player, bytes)).map_err(|err| JsValue::from_str(&err.message))
+}
+
 #[wasm_bindgen]
 pub fn set_base_path(path: String) {
   player_dispatch(PlayerVMCommand::SetBasePath(path));
@@ -45,6 +347,142 @@ pub fn set_system_font_path(path: String) {
   player_dispatch(PlayerVMCommand::SetSystemFontPath(path));
 }
 
+/// Provides a TrueType font for text and field members. Registering a replacement like
+/// "Liberation Sans" also covers the classic fonts it stands in for, like Arial and Geneva.
+#[wasm_bindgen]
+pub fn load_font(name: String, path: String) {
+  player_dispatch(PlayerVMCommand::LoadFont(name, path));
+}
+
+/// Adds a folder to `the searchPath`, which is where external casts and files are
+/// looked for when they aren't found at their own path.
+#[wasm_bindgen]
+pub fn add_search_path(path: String) {
+  player_dispatch(PlayerVMCommand::AddSearchPath(path));
+}
+
+#[wasm_bindgen]
+pub fn clear_search_paths() {
+  player_dispatch(PlayerVMCommand::ClearSearchPaths);
+}
+
+#[wasm_bindgen]
+pub fn set_net_cache_ttl(ttl_ms: u32) {
+  player_dispatch(PlayerVMCommand::SetNetCacheTtl(ttl_ms));
+}
+
+#[wasm_bindgen]
+pub fn set_net_upgrade_insecure(enabled: bool) {
+  player_dispatch(PlayerVMCommand::SetNetUpgradeInsecure(enabled));
+}
+
+#[wasm_bindgen]
+pub fn add_net_rewrite_rule(from: String, to: String) {
+  player_dispatch(PlayerVMCommand::AddNetRewriteRule(from, to));
+}
+
+/// Serves net requests from the files in a zip, such as a movie with its casts and media,
+/// before going to the network. Entries match by the end of the requested path, so
+/// `casts/a.cct` in the zip answers `http://example.com/game/casts/a.cct`.
+#[wasm_bindgen]
+pub fn add_net_bundle(data: &[u8]) -> Result<(), JsValue> {
+  let bundle = NetBundle::from_zip(data).map_err(|err| JsValue::from_str(&err))?;
+  player_dispatch(PlayerVMCommand::AddNetBundle(bundle));
+  Ok(())
+}
+
+/// Completes a pending net task with data the host fetched itself, or fails it with a
+/// netError code such as 4165 when `data` is missing. `status` is the HTTP status the
+/// host got, if any. Tasks that already finished are left as they are.
+#[wasm_bindgen]
+pub fn provide_net_task_data(task_id: u32, data: Option<Vec<u8>>, error_code: Option<i32>, status: Option<u16>) {
+  let result = match data {
+    Some(data) => Ok(data),
+    None => Err(error_code.unwrap_or(NET_ERROR_CONNECTION_FAILED)),
+  };
+  let response = status.map(|status| NetResponseInfo { status, headers: vec![] });
+  player_dispatch(PlayerVMCommand::ProvideNetTaskData(task_id, result, response));
+}
+
+/// The HTTP status and headers a net task got, as `{status, headers}` with the header
+/// names in lowercase. None while the task is pending or when it had no HTTP response.
+#[wasm_bindgen]
+pub fn get_net_task_response(task_id: u32) -> Option<js_sys::Object> {
+  let response = reserve_player_ref(|player| player.net_manager.get_task_response(Some(task_id)))?;
+  let headers = js_sys::Map::new();
+  for (name, value) in &response.headers {
+    headers.str_set(&name.to_lowercase(), &JsValue::from_str(value));
+  }
+  let headers = js_sys::Object::from_entries(&headers).ok()?;
+  let result = js_sys::Map::new();
+  result.str_set("status", &JsValue::from(response.status));
+  result.str_set("headers", &headers);
+  js_sys::Object::from_entries(&result).ok()
+}
+
+/// Runs movie time at `scale` times real time. Zero pauses it until step_virtual_clock is called.
+#[wasm_bindgen]
+pub fn set_time_scale(scale: f64) {
+  player_dispatch(PlayerVMCommand::SetTimeScale(scale));
+}
+
+#[wasm_bindgen]
+pub fn step_virtual_clock(ms: u32) {
+  player_dispatch(PlayerVMCommand::StepVirtualClock(ms));
+}
+
+#[wasm_bindgen]
+pub fn set_random_seed(seed: i32) {
+  player_dispatch(PlayerVMCommand::SetRandomSeed(seed));
+}
+
+/// Scales all sound output, from 0 to 1, on top of the movie's own soundLevel.
+#[wasm_bindgen]
+pub fn set_master_volume(volume: f32) {
+  player_dispatch(PlayerVMCommand::SetMasterVolume(volume));
+}
+
+/// Silences all sound output without changing the movie's soundEnabled.
+#[wasm_bindgen]
+pub fn set_muted(muted: bool) {
+  player_dispatch(PlayerVMCommand::SetMuted(muted));
+}
+
+/// Draws stretched sprites with bilinear sampling. Off by default, which matches the
+/// pixel-exact nearest-neighbor scaling of Director.
+#[wasm_bindgen]
+pub fn set_smooth_scaling(enabled: bool) {
+  player_dispatch(PlayerVMCommand::SetSmoothScaling(enabled));
+}
+
+/// Shows movies that set a colorDepth of 8 or less with the 256 colors of their
+/// palette, like an 8-bit monitor. Off by default.
+#[wasm_bindgen]
+pub fn set_emulate_8bit_stage(enabled: bool) {
+  player_dispatch(PlayerVMCommand::SetEmulate8BitStage(enabled));
+}
+
+/// Sets the locale of movies, "english" or "european", which picks the decimal
+/// separator of floats, the default itemDelimiter and the format of the date.
+#[wasm_bindgen]
+pub fn set_locale(name: String) {
+  player_dispatch(PlayerVMCommand::SetLocale(name));
+}
+
+/// Decodes the text, script strings and names of movies loaded from now on with an
+/// encoding such as "shiftJIS" or "windows1252", rather than the one of the platform
+/// they were authored on. "auto" goes back to the platform's.
+#[wasm_bindgen]
+pub fn set_text_encoding(name: String) {
+  player_dispatch(PlayerVMCommand::SetTextEncoding(name));
+}
+
+/// Erases what sprites with trails left on the stage.
+#[wasm_bindgen]
+pub fn clear_stage_trails() {
+  player_dispatch(PlayerVMCommand::ClearStageTrails);
+}
+
 #[wasm_bindgen]
 pub async fn load_movie_file(path: String) {
   player_dispatch(PlayerVMCommand::LoadMovieFromFile(path));
@@ -80,13 +518,75 @@ pub fn toggle_breakpoint(script_name: String, handler_name: String, bytecode_ind
   player_dispatch(PlayerVMCommand::ToggleBreakpoint(script_name, handler_name, bytecode_index))
 }
 
+#[wasm_bindgen]
+pub fn set_breakpoint_condition(script_name: String, handler_name: String, bytecode_index: usize, condition: Option<String>) {
+  player_dispatch(PlayerVMCommand::SetBreakpointCondition(script_name, handler_name, bytecode_index, condition))
+}
+
+/// Runs a Lingo statement against the running movie and resolves with the formatted
+/// result. Output of `put` is also sent to the debug console.
+#[wasm_bindgen]
+pub async fn eval_lingo(source: String) -> Result<String, JsValue> {
+  let result = player_dispatch_async(PlayerVMCommand::EvalLingo(source)).await.map_err(|err| JsValue::from_str(&err.message))?;
+  reserve_player_ref(|player| player.get_datum(&result).string_value()).map_err(|err| JsValue::from_str(&err.message))
+}
+
+/// Sets a sprite property from the debugger. The value is a Lingo literal and goes
+/// through the same checks as `set the prop of sprite` in scripts.
+#[wasm_bindgen]
+pub async fn set_sprite_prop(sprite_num: i16, prop_name: String, value: String) -> Result<(), JsValue> {
+  dispatch_debug_edit(PlayerVMCommand::SetSpriteProp(sprite_num, prop_name, value)).await
+}
+
+#[wasm_bindgen]
+pub async fn set_member_prop(cast_lib: i32, cast_member: i32, prop_name: String, value: String) -> Result<(), JsValue> {
+  let member_ref = CastMemberRef { cast_lib, cast_member };
+  dispatch_debug_edit(PlayerVMCommand::SetMemberProp(member_ref, prop_name, value)).await
+}
+
+#[wasm_bindgen]
+pub async fn set_global(name: String, value: String) -> Result<(), JsValue> {
+  dispatch_debug_edit(PlayerVMCommand::SetGlobal(name, value)).await
+}
+
+async fn dispatch_debug_edit(command: PlayerVMCommand) -> Result<(), JsValue> {
+  let result = player_dispatch_async(command).await.map_err(|err| JsValue::from_str(&err.message))?;
+  let message = reserve_player_ref(|player| player.get_datum(&result).string_value()).map_err(|err| JsValue::from_str(&err.message))?;
+  if message.is_empty() {
+    Ok(())
+  } else {
+    Err(JsValue::from_str(&message))
+  }
+}
+
+#[wasm_bindgen]
+pub fn add_watch_expression(expression: String) {
+  player_dispatch(PlayerVMCommand::AddWatchExpression(expression));
+}
+
+#[wasm_bindgen]
+pub fn remove_watch_expression(expression: String) {
+  player_dispatch(PlayerVMCommand::RemoveWatchExpression(expression));
+}
+
 #[wasm_bindgen]
 pub fn resume_breakpoint() {
   player_dispatch(PlayerVMCommand::ResumeBreakpoint);
 }
 
+#[wasm_bindgen]
+pub fn step_bytecode() {
+  player_dispatch(PlayerVMCommand::StepBytecode);
+}
+
+#[wasm_bindgen]
+pub fn step_back_bytecode() {
+  player_dispatch(PlayerVMCommand::StepBackBytecode);
+}
+
 #[wasm_bindgen]
 pub fn set_stage_size(width: u32, height: u32) {
+  rendering::set_renderer_stage_size(width, height);
   player_dispatch(PlayerVMCommand::SetStageSize(width, height));
 }
 
@@ -102,17 +602,61 @@ pub fn player_print_member_bitmap_hex(cast_lib: i32, cast_member: i32) {
 
 #[wasm_bindgen]
 pub fn mouse_down(x: f64, y: f64) {
-  player_dispatch(PlayerVMCommand::MouseDown((x.to_i32().unwrap(), y.to_i32().unwrap())));
+  player_dispatch(PlayerVMCommand::MouseDown(rendering::to_movie_point(x, y)));
 }
 
 #[wasm_bindgen]
 pub fn mouse_up(x: f64, y: f64) {
-  player_dispatch(PlayerVMCommand::MouseUp((x.to_i32().unwrap(), y.to_i32().unwrap())));
+  player_dispatch(PlayerVMCommand::MouseUp(rendering::to_movie_point(x, y)));
 }
 
 #[wasm_bindgen]
 pub fn mouse_move(x: f64, y: f64) {
-  player_dispatch(PlayerVMCommand::MouseMove((x.to_i32().unwrap(), y.to_i32().unwrap())));
+  player_dispatch(PlayerVMCommand::MouseMove(rendering::to_movie_point(x, y)));
+}
+
+#[wasm_bindgen]
+pub fn mouse_wheel(delta_x: f64, delta_y: f64) {
+  player_dispatch(PlayerVMCommand::MouseWheel(delta_x, delta_y));
+}
+
+/// Touches are played as the mouse: the first finger down moves, presses and releases
+/// it, while other fingers are ignored until it is lifted.
+#[wasm_bindgen]
+pub fn touch_start(id: i32, x: f64, y: f64) {
+  let is_primary = reserve_player_mut(|player| {
+    if player.primary_touch_id.is_some() {
+      return false;
+    }
+    player.primary_touch_id = Some(id);
+    true
+  });
+  if is_primary {
+    let point = rendering::to_movie_point(x, y);
+    player_dispatch(PlayerVMCommand::MouseMove(point));
+    player_dispatch(PlayerVMCommand::MouseDown(point));
+  }
+}
+
+#[wasm_bindgen]
+pub fn touch_move(id: i32, x: f64, y: f64) {
+  if reserve_player_ref(|player| player.primary_touch_id == Some(id)) {
+    player_dispatch(PlayerVMCommand::MouseMove(rendering::to_movie_point(x, y)));
+  }
+}
+
+#[wasm_bindgen]
+pub fn touch_end(id: i32, x: f64, y: f64) {
+  let is_primary = reserve_player_mut(|player| {
+    if player.primary_touch_id != Some(id) {
+      return false;
+    }
+    player.primary_touch_id = None;
+    true
+  });
+  if is_primary {
+    player_dispatch(PlayerVMCommand::MouseUp(rendering::to_movie_point(x, y)));
+  }
 }
 
 #[wasm_bindgen]
@@ -125,6 +669,31 @@ pub fn key_up(key: String, code: u16) {
   player_dispatch(PlayerVMCommand::KeyUp(key, code));
 }
 
+/// Whether the page should consume a key press instead of letting the browser handle
+/// it. Call it from the keydown handler before forwarding the key with `key_down`.
+#[wasm_bindgen]
+pub fn should_capture_key(key: &str, ctrl: bool, alt: bool, shift: bool, meta: bool) -> bool {
+  let key_press = KeyPress { key, ctrl, alt, shift, meta };
+  with_key_capture_policy(|policy| policy.should_capture(&key_press))
+}
+
+/// Sets whether a category of keys is captured: `navigation`, `space`, `shortcuts` or `functionKeys`.
+#[wasm_bindgen]
+pub fn set_key_capture(category: &str, captured: bool) -> Result<(), JsValue> {
+  with_key_capture_policy(|policy| policy.set_category(category, captured)).map_err(|err| JsValue::from_str(&err))
+}
+
+/// Overrides the category of a single combination such as `ctrl+s` or `space`.
+#[wasm_bindgen]
+pub fn set_key_combination_captured(combination: &str, captured: bool) {
+  with_key_capture_policy(|policy| policy.set_combination(combination, captured));
+}
+
+#[wasm_bindgen]
+pub fn reset_key_capture() {
+  with_key_capture_policy(|policy| *policy = KeyCapturePolicy::new());
+}
+
 #[wasm_bindgen]
 pub fn request_datum(datum_id: u32) {
   player_dispatch(PlayerVMCommand::RequestDatum(datum_id as DatumId));
@@ -172,7 +741,7 @@ pub fn unsubscribe_from_channel_names() {
 }
 
 #[wasm_bindgen(start)]
-pub fn main() {
+pub fn start() {
   set_panic_hook();
   init_player();
 }
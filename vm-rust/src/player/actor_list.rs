@@ -0,0 +1,58 @@
+use crate::director::lingo::datum::{Datum, DatumType};
+
+use super::{
+  events::player_invoke_event_to_instances, reserve_player_ref, script_ref::ScriptInstanceRef, DatumRef, DirPlayer,
+  ScriptError,
+};
+
+/// The list behind `the actorList`. Lingo adds and removes actors in place, so the same
+/// list is handed out until it is replaced.
+pub fn actor_list(player: &mut DirPlayer) -> DatumRef {
+  if let Some(list_ref) = &player.actor_list {
+    return list_ref.clone();
+  }
+  let list_ref = player.alloc_datum(Datum::List(DatumType::List, vec![], false));
+  player.actor_list = Some(list_ref.clone());
+  list_ref
+}
+
+pub fn set_actor_list(player: &mut DirPlayer, value: Datum) -> Result<(), ScriptError> {
+  match value {
+    Datum::List(..) => {
+      player.actor_list = Some(player.alloc_datum(value));
+      Ok(())
+    }
+    _ => Err(ScriptError::new("actorList must be a list".to_string())),
+  }
+}
+
+fn get_actors(player: &DirPlayer) -> Vec<ScriptInstanceRef> {
+  let list_ref = match &player.actor_list {
+    Some(list_ref) => list_ref,
+    None => return vec![],
+  };
+  match player.get_datum(list_ref) {
+    Datum::List(_, item_refs, _) => item_refs
+      .iter()
+      .filter_map(|item_ref| match player.get_datum(item_ref) {
+        Datum::ScriptInstanceRef(instance_ref) => Some(instance_ref.clone()),
+        _ => None,
+      })
+      .collect(),
+    _ => vec![],
+  }
+}
+
+/// Sends stepFrame to the objects in the actorList, in list order. Objects added while
+/// the actors step, like from the new handler of an object a stepFrame creates, first
+/// step on the next frame. Objects removed meanwhile don't step anymore.
+pub async fn player_step_actors() -> Result<DatumRef, ScriptError> {
+  let actors = reserve_player_ref(get_actors);
+  for actor in actors {
+    let is_actor = reserve_player_ref(|player| get_actors(player).iter().any(|x| **x == *actor));
+    if is_actor {
+      player_invoke_event_to_instances(&"stepFrame".to_string(), &vec![], &vec![actor]).await?;
+    }
+  }
+  Ok(DatumRef::Void)
+}
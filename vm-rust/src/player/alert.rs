@@ -0,0 +1,170 @@
+use manual_future::{ManualFuture, ManualFutureCompleter};
+
+use crate::director::lingo::datum::Datum;
+
+use super::{
+  allocator::ScriptInstanceAllocatorTrait, geometry::IntRect, player_call_script_handler, reserve_player_mut, reserve_player_ref, DatumRef, DirPlayer,
+  ScriptError, ScriptReceiver,
+};
+
+const ALERT_PADDING: i32 = 12;
+const ALERT_MIN_WIDTH: i32 = 160;
+const ALERT_BUTTON_WIDTH: i32 = 60;
+const ALERT_BUTTON_HEIGHT: i32 = 20;
+
+/// A modal alert drawn over the stage. Scripts stay paused until it is dismissed.
+pub struct Alert {
+  pub message: String,
+  pub completer: ManualFutureCompleter<()>,
+}
+
+/// Where an alert is drawn, in stage coordinates.
+pub struct AlertLayout {
+  pub box_rect: IntRect,
+  pub button_rect: IntRect,
+  pub lines: Vec<String>,
+  pub line_height: i32,
+}
+
+pub fn get_alert_layout(player: &DirPlayer, message: &str) -> AlertLayout {
+  let (char_width, line_height) = match player.font_manager.get_system_font() {
+    Some(font) => (font.char_width as i32 + 1, font.char_height as i32 + 1),
+    None => (6, 12),
+  };
+  let stage_width = player.movie.rect.width();
+  let stage_height = player.movie.rect.height();
+  let max_chars = ((stage_width - 4 * ALERT_PADDING) / char_width).max(8) as usize;
+  let lines = wrap_alert_text(message, max_chars);
+  let text_width = lines.iter().map(|line| line.chars().count() as i32).max().unwrap_or(0) * char_width;
+  let width = (text_width + 2 * ALERT_PADDING).max(ALERT_MIN_WIDTH);
+  let height = lines.len() as i32 * line_height + ALERT_BUTTON_HEIGHT + 3 * ALERT_PADDING;
+  let box_rect = IntRect::from_size((stage_width - width) / 2, (stage_height - height) / 2, width, height);
+  let button_rect = IntRect::from_size(
+    box_rect.right - ALERT_PADDING - ALERT_BUTTON_WIDTH,
+    box_rect.bottom - ALERT_PADDING - ALERT_BUTTON_HEIGHT,
+    ALERT_BUTTON_WIDTH,
+    ALERT_BUTTON_HEIGHT,
+  );
+  AlertLayout { box_rect, button_rect, lines, line_height }
+}
+
+fn wrap_alert_text(message: &str, max_chars: usize) -> Vec<String> {
+  let mut lines = vec![];
+  for paragraph in message.split(['\r', '\n']) {
+    let mut line = String::new();
+    for word in paragraph.split(' ') {
+      if !line.is_empty() && line.chars().count() + word.chars().count() + 1 > max_chars {
+        lines.push(std::mem::take(&mut line));
+      }
+      if !line.is_empty() {
+        line.push(' ');
+      }
+      line.push_str(word);
+    }
+    lines.push(line);
+  }
+  lines
+}
+
+/// Whether a click at the given stage location hits the OK button of the open alert.
+pub fn is_alert_button_at(player: &DirPlayer, x: i32, y: i32) -> bool {
+  match &player.alert {
+    Some(alert) => {
+      let rect = get_alert_layout(player, &alert.message).button_rect;
+      x >= rect.left && x < rect.right && y >= rect.top && y < rect.bottom
+    }
+    None => false,
+  }
+}
+
+/// Builds the error object handed to the alertHook, with the script and handler that
+/// were running when the error or alert happened.
+fn alloc_alert_error_object(player: &mut DirPlayer, error: &str, message: &str) -> DatumRef {
+  let (script_name, handler_name) = player.scopes.get(player.scope_count.wrapping_sub(1) as usize)
+    .filter(|_| player.scope_count > 0)
+    .and_then(|scope| player.get_scope_names(scope))
+    .map_or((None, None), |(script_name, handler_name)| (Some(script_name.to_owned()), Some(handler_name.to_owned())));
+  let props = vec![
+    ("error", Datum::String(error.to_owned())),
+    ("message", Datum::String(message.to_owned())),
+    ("script", script_name.map_or(Datum::Void, Datum::String)),
+    ("handler", handler_name.map_or(Datum::Void, Datum::Symbol)),
+  ];
+  let props = props
+    .into_iter()
+    .map(|(key, value)| (player.alloc_datum(Datum::Symbol(key.to_owned())), player.alloc_datum(value)))
+    .collect();
+  player.alloc_datum(Datum::PropList(props, false))
+}
+
+/// Calls the movie's alertHook with the error type, the message and an error object
+/// holding #error, #message, #script and #handler. Returns true when the hook returned 1,
+/// which tells the player not to report the error itself.
+pub async fn player_call_alert_hook(error: &str, message: &str) -> Result<bool, ScriptError> {
+  let call_params = reserve_player_mut(|player| {
+    let (receiver, script_ref) = match player.movie.alert_hook.clone()? {
+      ScriptReceiver::ScriptInstance(instance_ref) => {
+        let script_ref = player.allocator.get_script_instance(&instance_ref).script.clone();
+        (Some(instance_ref), script_ref)
+      }
+      ScriptReceiver::Script(script_ref) => (None, script_ref),
+    };
+    let handler = player
+      .movie
+      .cast_manager
+      .get_script_by_ref(&script_ref)?
+      .get_own_handler_ref(&"alertHook".to_string())?;
+    let args = vec![
+      player.alloc_datum(Datum::String(error.to_owned())),
+      player.alloc_datum(Datum::String(message.to_owned())),
+      alloc_alert_error_object(player, error, message),
+    ];
+    Some((receiver, handler, args))
+  });
+  let (receiver, handler, args) = match call_params {
+    Some(call_params) => call_params,
+    None => return Ok(false),
+  };
+  let result = player_call_script_handler(receiver, handler, &args).await?;
+  Ok(reserve_player_ref(|player| {
+    matches!(player.get_datum(&result.return_value), Datum::Int(1))
+  }))
+}
+
+/// Runs the Lingo alert command. The alertHook sees the alert first and can return 1 to
+/// suppress it, otherwise the message is shown over the stage until it is dismissed.
+pub async fn player_alert(message: String) -> Result<(), ScriptError> {
+  if player_call_alert_hook("Alert", &message).await? {
+    return Ok(());
+  }
+  let (future, completer) = ManualFuture::new();
+  reserve_player_mut(|player| {
+    // Only one alert can be open, a new one dismisses the previous
+    player.dismiss_alert();
+    player.alert = Some(Alert { message, completer });
+    player.pause_script();
+  });
+  future.await;
+  reserve_player_mut(|player| {
+    if player.alert.is_none() {
+      player.resume_script();
+    }
+  });
+  Ok(())
+}
+
+/// Reports a script error to the alertHook. When the hook returns 1 the scopes left by
+/// the failed handler are dropped and the movie keeps playing, otherwise it stops.
+pub async fn player_handle_script_error(err: &ScriptError, base_scope_count: u32) {
+  let error_scope_count = reserve_player_ref(|player| player.scope_count);
+  let suppressed = matches!(player_call_alert_hook("Script error", &err.message).await, Ok(true));
+  reserve_player_mut(|player| {
+    player.step_history.abandon_steps();
+    if suppressed {
+      player.scope_count = base_scope_count;
+    } else {
+      player.scope_count = error_scope_count;
+      player.on_script_error(err);
+    }
+  });
+}
@@ -1,10 +1,10 @@
-use std::{cell::UnsafeCell, rc::Rc};
+use std::{cell::{RefCell, UnsafeCell}, rc::Rc};
 
 use async_std::channel::{Receiver, Sender};
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use log::warn;
 
-use crate::{console_warn, director::lingo::datum::Datum};
+use crate::{console_warn, director::lingo::datum::{Datum, StringChunkSource, VarRef}};
 
 use super::{datum_ref::{DatumId, DatumRef}, reserve_player_mut, reserve_player_ref, script::{ScriptInstance, ScriptInstanceId}, script_ref::ScriptInstanceRef, ScriptError};
 
@@ -40,9 +40,29 @@ pub trait ScriptInstanceAllocatorTrait {
   fn on_script_instance_ref_dropped(&mut self, id: ScriptInstanceId);
 }
 
+#[derive(Default)]
+pub struct GarbageCollectionStats {
+  pub collection_count: u32,
+  pub last_freed_datums: usize,
+  pub last_freed_script_instances: usize,
+  pub total_freed_datums: usize,
+  pub total_freed_script_instances: usize,
+}
+
+/// Contents taken out of unreachable datums and script instances. Dropping it releases
+/// the references that kept the cycles alive, which frees the objects themselves.
+pub struct UnreachableCycles {
+  pub datums: Vec<Datum>,
+  pub script_instance_properties: Vec<(FxHashMap<String, DatumRef>, Option<ScriptInstanceRef>)>,
+}
+
 pub struct DatumAllocator {
   pub datums: FxHashMap<DatumId, DatumRefEntry>,
   pub script_instances: FxHashMap<ScriptInstanceId, ScriptInstanceRefEntry>,
+  pub gc_stats: GarbageCollectionStats,
+  /// Resolved ancestor chains, dropped whenever any ancestor changes or an instance is freed.
+  ancestor_chains: RefCell<FxHashMap<ScriptInstanceId, Rc<[ScriptInstanceId]>>>,
+  gc_threshold: usize,
   datum_id_counter: DatumId,
   script_instance_counter: ScriptInstanceId,
   void_datum: Datum,
@@ -50,12 +70,46 @@ pub struct DatumAllocator {
 
 const MAX_DATUM_ID: DatumId = 0xFFFFFF;
 const MAX_SCRIPT_INSTANCE_ID: ScriptInstanceId = 0xFFFFFF;
+const MIN_GC_THRESHOLD: usize = 10000;
+
+enum HeapRef {
+  Datum(DatumId),
+  ScriptInstance(ScriptInstanceId),
+}
+
+fn datum_children(datum: &Datum, children: &mut Vec<HeapRef>) {
+  match datum {
+    Datum::List(_, items, _) => {
+      children.extend(items.iter().map(|x| HeapRef::Datum(x.unwrap())));
+    }
+    Datum::PropList(pairs, _) => {
+      for (key, value) in pairs {
+        children.push(HeapRef::Datum(key.unwrap()));
+        children.push(HeapRef::Datum(value.unwrap()));
+      }
+    }
+    Datum::StringChunk(StringChunkSource::Datum(source), ..) => children.push(HeapRef::Datum(source.unwrap())),
+    Datum::ScriptInstanceRef(instance_ref) => children.push(HeapRef::ScriptInstance(**instance_ref)),
+    Datum::VarRef(VarRef::ScriptInstance(instance_ref)) => children.push(HeapRef::ScriptInstance(**instance_ref)),
+    _ => {}
+  }
+}
+
+fn script_instance_children(instance: &ScriptInstance, children: &mut Vec<HeapRef>) {
+  children.extend(instance.properties.values().map(|x| HeapRef::Datum(x.unwrap())));
+  if let Some(ancestor) = &instance.ancestor {
+    children.push(HeapRef::ScriptInstance(**ancestor));
+  }
+}
 
 impl DatumAllocator {
   pub fn default() -> Self {
     DatumAllocator {
       datums: FxHashMap::default(),
       script_instances: FxHashMap::default(),
+      gc_stats: GarbageCollectionStats::default(),
+      ancestor_chains: RefCell::new(FxHashMap::default()),
+      gc_threshold: MIN_GC_THRESHOLD,
       datum_id_counter: 1,
       script_instance_counter: 1,
       void_datum: Datum::Void,
@@ -118,6 +172,135 @@ impl DatumAllocator {
 
   fn dealloc_script_instance(&mut self, id: ScriptInstanceId) {
     self.script_instances.remove(&id);
+    self.ancestor_chains.get_mut().clear();
+  }
+
+  pub fn get_script_instance_by_id(&self, id: ScriptInstanceId) -> Option<&ScriptInstance> {
+    self.script_instances.get(&id).map(|entry| &entry.script_instance)
+  }
+
+  /// The instance followed by its ancestors, nearest first.
+  pub fn get_ancestor_chain(&self, id: ScriptInstanceId) -> Result<Rc<[ScriptInstanceId]>, ScriptError> {
+    if let Some(chain) = self.ancestor_chains.borrow().get(&id) {
+      return Ok(chain.clone());
+    }
+    let mut chain = vec![id];
+    let mut visited = FxHashSet::default();
+    visited.insert(id);
+    let mut current = self.get_script_instance_by_id(id);
+    while let Some(ancestor) = current.and_then(|instance| instance.ancestor.as_ref()) {
+      let ancestor_id = **ancestor;
+      if !visited.insert(ancestor_id) {
+        return Err(ScriptError::new(format!("Ancestor cycle found on script instance {id}")));
+      }
+      chain.push(ancestor_id);
+      current = self.get_script_instance_by_id(ancestor_id);
+    }
+    let chain: Rc<[ScriptInstanceId]> = chain.into();
+    self.ancestor_chains.borrow_mut().insert(id, chain.clone());
+    Ok(chain)
+  }
+
+  /// Sets the ancestor of an instance, refusing one that already has the instance in its own chain.
+  pub fn set_script_instance_ancestor(&mut self, instance_ref: &ScriptInstanceRef, ancestor: Option<ScriptInstanceRef>) -> Result<(), ScriptError> {
+    if let Some(ancestor) = &ancestor {
+      if self.get_ancestor_chain(**ancestor)?.contains(&**instance_ref) {
+        return Err(ScriptError::new(format!("Setting ancestor {} on script instance {} would create an ancestor cycle", ancestor, instance_ref)));
+      }
+    }
+    self.ancestor_chains.get_mut().clear();
+    self.get_script_instance_mut(instance_ref).ancestor = ancestor;
+    Ok(())
+  }
+
+  pub fn should_collect_garbage(&self) -> bool {
+    self.datum_count() >= self.gc_threshold
+  }
+
+  /// Finds datums and script instances that are only referenced by each other, such as
+  /// two objects pointing at one another, and takes their contents so the cycles can be
+  /// released. Objects whose ref count is higher than the number of references held by
+  /// other heap objects are referenced from outside the heap (globals, scopes, sprites,
+  /// timeouts, xtras...) and are treated as roots.
+  pub fn take_unreachable_cycles(&mut self) -> UnreachableCycles {
+    let mut internal_datum_refs: FxHashMap<DatumId, u32> = FxHashMap::default();
+    let mut internal_instance_refs: FxHashMap<ScriptInstanceId, u32> = FxHashMap::default();
+    let mut children = vec![];
+    for entry in self.datums.values() {
+      datum_children(&entry.datum, &mut children);
+    }
+    for entry in self.script_instances.values() {
+      script_instance_children(&entry.script_instance, &mut children);
+    }
+    for child in children.drain(..) {
+      match child {
+        HeapRef::Datum(id) => *internal_datum_refs.entry(id).or_default() += 1,
+        HeapRef::ScriptInstance(id) => *internal_instance_refs.entry(id).or_default() += 1,
+      }
+    }
+
+    let mut reachable_datums = FxHashSet::default();
+    let mut reachable_instances = FxHashSet::default();
+    let mut pending = vec![];
+    for (id, entry) in &self.datums {
+      let ref_count = unsafe { *entry.ref_count.get() };
+      if ref_count > internal_datum_refs.get(id).copied().unwrap_or(0) {
+        pending.push(HeapRef::Datum(*id));
+      }
+    }
+    for (id, entry) in &self.script_instances {
+      let ref_count = unsafe { *entry.ref_count.get() };
+      if ref_count > internal_instance_refs.get(id).copied().unwrap_or(0) {
+        pending.push(HeapRef::ScriptInstance(*id));
+      }
+    }
+    while let Some(heap_ref) = pending.pop() {
+      match heap_ref {
+        HeapRef::Datum(id) => {
+          if id == 0 || !reachable_datums.insert(id) {
+            continue;
+          }
+          if let Some(entry) = self.datums.get(&id) {
+            datum_children(&entry.datum, &mut pending);
+          }
+        }
+        HeapRef::ScriptInstance(id) => {
+          if !reachable_instances.insert(id) {
+            continue;
+          }
+          if let Some(entry) = self.script_instances.get(&id) {
+            script_instance_children(&entry.script_instance, &mut pending);
+          }
+        }
+      }
+    }
+
+    let mut result = UnreachableCycles { datums: vec![], script_instance_properties: vec![] };
+    for (id, entry) in self.datums.iter_mut() {
+      if !reachable_datums.contains(id) {
+        result.datums.push(std::mem::replace(&mut entry.datum, Datum::Void));
+      }
+    }
+    for (id, entry) in self.script_instances.iter_mut() {
+      if !reachable_instances.contains(id) {
+        let instance = &mut entry.script_instance;
+        instance.property_names.clear();
+        result.script_instance_properties.push((std::mem::take(&mut instance.properties), instance.ancestor.take()));
+      }
+    }
+    self.ancestor_chains.get_mut().clear();
+    result
+  }
+
+  pub fn on_garbage_collected(&mut self, freed_datums: usize, freed_script_instances: usize) {
+    let stats = &mut self.gc_stats;
+    stats.collection_count += 1;
+    stats.last_freed_datums = freed_datums;
+    stats.last_freed_script_instances = freed_script_instances;
+    stats.total_freed_datums += freed_datums;
+    stats.total_freed_script_instances += freed_script_instances;
+    // Wait for the heap to double before collecting again so that live data isn't rescanned every frame
+    self.gc_threshold = (self.datum_count() * 2).max(MIN_GC_THRESHOLD);
   }
 
   pub fn get_datum_ref(&self, id: DatumId) -> Option<DatumRef> {
@@ -234,5 +417,8 @@ impl ResetableAllocator for DatumAllocator {
     self.datum_id_counter = 1;
     self.script_instances.clear();
     self.script_instance_counter = 1;
+    self.ancestor_chains.get_mut().clear();
+    self.gc_stats = GarbageCollectionStats::default();
+    self.gc_threshold = MIN_GC_THRESHOLD;
   }
 }
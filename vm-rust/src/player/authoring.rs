@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use crate::{director::{enums::ScriptType, file::get_variable_multiplier, lingo::{compiler::compile_script, script::ScriptContext}}, js_api::JsApi};
+
+use super::{bitmap::{bitmap::{get_system_default_palette, PaletteRef}, encoded_image::decode_image}, cast_lib::{cast_member_ref, CastMemberRef}, cast_member::{BitmapMember, CastMember, CastMemberType, FieldMember, ScriptMember, TextMember}, DirPlayer, ScriptError};
+
+/// Adds a bitmap member from a PNG, JPEG or GIF, replacing the member at `number` or
+/// taking the first free slot of the cast. The registration point is the center.
+pub fn add_bitmap_member(player: &mut DirPlayer, cast_lib: u32, number: Option<u32>, name: &str, data: &[u8]) -> Result<CastMemberRef, ScriptError> {
+  let bitmap = decode_image(data, PaletteRef::BuiltIn(get_system_default_palette())).map_err(ScriptError::new)?;
+  let reg_point = ((bitmap.width / 2) as i16, (bitmap.height / 2) as i16);
+  player.movie.cast_manager.get_cast(cast_lib)?;
+  let image_ref = player.bitmap_manager.add_bitmap(bitmap);
+  Ok(insert_member(player, cast_lib, number, name, CastMemberType::Bitmap(BitmapMember { image_ref, reg_point })))
+}
+
+/// Adds a text member, or a field when `is_field` is set, holding the text.
+pub fn add_text_member(player: &mut DirPlayer, cast_lib: u32, number: Option<u32>, name: &str, text: &str, is_field: bool) -> Result<CastMemberRef, ScriptError> {
+  player.movie.cast_manager.get_cast(cast_lib)?;
+  // Lines are separated by returns in Lingo
+  let text = text.replace("\r\n", "\r").replace('\n', "\r");
+  let member_type = if is_field {
+    let mut field = FieldMember::new();
+    field.text = text;
+    CastMemberType::Field(field)
+  } else {
+    let mut text_member = TextMember::new();
+    text_member.text = text;
+    CastMemberType::Text(text_member)
+  };
+  Ok(insert_member(player, cast_lib, number, name, member_type))
+}
+
+/// Compiles Lingo source into a new script member, replacing the member at `number` or
+/// taking the first free slot of the cast.
+pub fn compile_and_attach_script(player: &mut DirPlayer, cast_lib: u32, number: Option<u32>, name: &str, source: &str, script_type: ScriptType) -> Result<CastMemberRef, ScriptError> {
+  player.movie.cast_manager.get_cast(cast_lib)?;
+  let script_id = compile_into_cast(player, cast_lib, None, source)?;
+  let script_member = ScriptMember { script_id, script_type, name: name.to_string(), source_text: source.to_string() };
+  let member_ref = insert_member(player, cast_lib, number, name, CastMemberType::Script(script_member));
+  player.movie.cast_manager.clear_movie_script_cache();
+  Ok(member_ref)
+}
+
+/// Recompiles a script member from new source. Its instances and behaviors run the new
+/// handlers from then on, which lets a buggy handler of a movie be patched.
+pub fn set_script_text(player: &mut DirPlayer, member_ref: &CastMemberRef, source: &str) -> Result<(), ScriptError> {
+  let script_id = player.movie.cast_manager.find_member_by_ref(member_ref)
+    .and_then(|member| member.member_type.as_script())
+    .map(|script| script.script_id)
+    .ok_or_else(|| ScriptError::new("scriptText can only be set on script members".to_string()))?;
+  let cast_lib = member_ref.cast_lib as u32;
+  compile_into_cast(player, cast_lib, Some(script_id), source)?;
+  // Inserting the member again rebuilds its handlers from the new chunk
+  let cast = player.movie.cast_manager.get_cast_mut(cast_lib);
+  let mut member = cast.members.get(&(member_ref.cast_member as u32)).unwrap().clone();
+  if let CastMemberType::Script(script) = &mut member.member_type {
+    script.source_text = source.to_string();
+  }
+  cast.insert_member(member_ref.cast_member as u32, member);
+  player.movie.cast_manager.clear_movie_script_cache();
+  Ok(())
+}
+
+/// Compiles the source into the script context of the cast, under the given script
+/// id or a new one, and returns the id.
+fn compile_into_cast(player: &mut DirPlayer, cast_lib: u32, script_id: Option<u32>, source: &str) -> Result<u32, ScriptError> {
+  let cast = player.movie.cast_manager.get_cast_mut(cast_lib);
+  let variable_multiplier = get_variable_multiplier(cast.capital_x, cast.dir_version);
+  let lctx = cast.lctx.get_or_insert_with(|| ScriptContext { names: vec![], scripts: HashMap::new() });
+  let chunk = compile_script(source, lctx, variable_multiplier).map_err(ScriptError::new)?;
+  let script_id = script_id.unwrap_or_else(|| lctx.scripts.keys().max().map_or(1, |id| id + 1));
+  lctx.scripts.insert(script_id, chunk);
+  Ok(script_id)
+}
+
+fn insert_member(player: &mut DirPlayer, cast_lib: u32, number: Option<u32>, name: &str, member_type: CastMemberType) -> CastMemberRef {
+  let cast = player.movie.cast_manager.get_cast_mut(cast_lib);
+  let number = number.filter(|number| *number > 0).unwrap_or_else(|| cast.first_free_member_id());
+  let mut member = CastMember::new(number, member_type);
+  member.name = name.to_string();
+  cast.insert_member(number, member);
+  JsApi::dispatch_cast_member_list_changed(cast_lib);
+  cast_member_ref(cast_lib as i32, number as i32)
+}
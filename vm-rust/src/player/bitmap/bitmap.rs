@@ -17,7 +17,7 @@ use num::FromPrimitive;
 
 use super::{mask::BitmapMask, palette::{SYSTEM_MAC_PALETTE, SYSTEM_WIN_PALETTE, WEB_216_PALETTE}, palette_map::PaletteMap};
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum PaletteRef {
     BuiltIn(BuiltInPalette),
     Member(CastMemberRef),
@@ -126,7 +126,7 @@ fn get_num_channels(bit_depth: u8) -> Result<u8, String> {
     match bit_depth {
         1 | 2 | 4 | 8 | 16 => Ok(1),
         32 => Ok(4),
-        _ => Err("Invalid bit depth".to_string()),
+        _ => Err(format!("Unsupported bitmap bit depth {}", bit_depth)),
     }
 }
 
@@ -135,7 +135,7 @@ fn get_alignment_width(bit_depth: u8) -> Result<u16, String> {
         1 | 4 | 32 => Ok(4),
         2 | 8 => Ok(2),
         16 => Ok(1),
-        _ => Err("Invalid bit depth".to_string()),
+        _ => Err(format!("Unsupported bitmap bit depth {}", bit_depth)),
     }
 }
 
@@ -373,15 +373,91 @@ fn decode_generic_bitmap(
     }
 }
 
+/// Decodes a 16-bit image into 5-6-5 pixels. Compressed rows hold the high bytes of the
+/// row followed by the low bytes, uncompressed ones hold big endian pixels. Director
+/// saves them as 5-5-5 with the top bit unused.
+fn decode_bitmap_16bit(width: u16, height: u16, row_bytes: usize, is_planar: bool, palette_ref: PaletteRef, data: &[u8]) -> Result<Bitmap, String> {
+    let (width, height) = (width as usize, height as usize);
+    if row_bytes < width * 2 || data.len() < row_bytes * height {
+        return Err(format!("decode_bitmap_16bit: {} bytes is too short for {}x{} with rows of {} bytes", data.len(), width, height, row_bytes));
+    }
+    let mut result = Vec::with_capacity(width * height * 2);
+    for y in 0..height {
+        let row = &data[y * row_bytes..];
+        for x in 0..width {
+            let pixel = if is_planar {
+                u16::from_be_bytes([row[x], row[width + x]])
+            } else {
+                u16::from_be_bytes([row[x * 2], row[x * 2 + 1]])
+            };
+            // Widen the 5 bits of green to 6
+            let (red, green, blue) = ((pixel >> 10) & 0x1F, (pixel >> 5) & 0x1F, pixel & 0x1F);
+            let value = (red << 11) | (green << 6) | ((green >> 4) << 5) | blue;
+            result.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    Ok(Bitmap {
+        width: width as u16,
+        height: height as u16,
+        bit_depth: 16,
+        data: result,
+        palette_ref,
+        matte: None,
+    })
+}
+
+/// Decodes a 32-bit image into RGBA. Compressed rows hold the alpha, red, green and
+/// blue of the row one after the other, uncompressed ones hold ARGB pixels. Images
+/// that weren't saved with an alpha channel have it all zero and are opaque.
+fn decode_bitmap_32bit(width: u16, height: u16, row_bytes: usize, is_planar: bool, palette_ref: PaletteRef, data: &[u8]) -> Result<Bitmap, String> {
+    let (width, height) = (width as usize, height as usize);
+    if row_bytes < width * 4 || data.len() < row_bytes * height {
+        return Err(format!("decode_bitmap_32bit: {} bytes is too short for {}x{} with rows of {} bytes", data.len(), width, height, row_bytes));
+    }
+    let mut result = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let row = &data[y * row_bytes..];
+        for x in 0..width {
+            let channel = |c: usize| if is_planar { row[c * width + x] } else { row[x * 4 + c] };
+            result.extend_from_slice(&[channel(1), channel(2), channel(3), channel(0)]);
+        }
+    }
+    if result.chunks_exact(4).all(|pixel| pixel[3] == 0) {
+        result.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 0xFF);
+    }
+    Ok(Bitmap {
+        width: width as u16,
+        height: height as u16,
+        bit_depth: 32,
+        data: result,
+        palette_ref,
+        matte: None,
+    })
+}
+
+/// Expands the PackBits runs of a compressed image.
+fn unpack_bits(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut result = Vec::new();
+    let mut reader = BinaryReader::from_u8(data);
+    while !reader.eof() {
+        let r_len = reader.read_u8().map_err(|x| x.to_string())? as u16;
+        if 0x101 - r_len > 0x7F {
+            let bytes = reader.read_bytes(r_len as usize + 1)
+                .map_err(|_| format!("Bitmap run of {} bytes goes past the end of the data", r_len + 1))?;
+            result.extend_from_slice(bytes);
+        } else {
+            let val = reader.read_u8().map_err(|x| x.to_string())?;
+            result.extend(std::iter::repeat_n(val, (0x101 - r_len) as usize));
+        }
+    }
+    Ok(result)
+}
+
 // Converts a NUU-encoded bitmap to a raw bitmap
 pub fn decompress_bitmap(data: &[u8], info: &BitmapInfo, cast_lib: u32) -> Result<Bitmap, String> {
-    let mut result = Vec::new();
-    let mut _current_index = 0;
     let num_channels = get_num_channels(info.bit_depth)?;
     let alignment_width = get_alignment_width(info.bit_depth)?;
 
-    let mut reader = BinaryReader::from_u8(data);
-
     let scan_height = info.height;
     let mut scan_width = if info.width % alignment_width == 0 {
         info.width
@@ -389,37 +465,31 @@ pub fn decompress_bitmap(data: &[u8], info: &BitmapInfo, cast_lib: u32) -> Resul
         alignment_width * info.width.div_ceil(alignment_width)
     };
 
-    if reader.length * 8 == scan_width as usize * scan_height as usize * info.bit_depth as usize {
-        // no compression
-        result.append(&mut reader.data.clone());
+    // Rows are padded differently depending on the version and platform the image
+    // was saved with. The member header records the padding, older headers that are
+    // too short for the image are replaced by the usual alignment of the bit depth.
+    let min_row_bytes = (info.width as usize * info.bit_depth as usize).div_ceil(8);
+    let row_bytes = if info.pitch as usize >= min_row_bytes {
+        info.pitch as usize
     } else {
-        while !reader.eof() {
-            let mut r_len = reader.read_u8().map_err(|x| x.to_string())? as u16;
-            if 0x101 - r_len > 0x7F {
-                r_len += 1;
-                for _ in 0..r_len {
-                    let val = reader.read_u8().map_err(|x| x.to_string())?;
-                    result.push(val);
-                    _current_index += 1;
-                }
-            } else {
-                r_len = 0x101 - r_len;
-                let val = reader.read_u8().map_err(|x| x.to_string())?;
-
-                for _ in 0..r_len {
-                    result.push(val);
-                    _current_index += 1;
-                }
-            }
-        }
+        scan_width as usize * info.bit_depth as usize / 8
+    };
+    let is_compressed = data.len() != row_bytes * scan_height as usize;
+    let result = if is_compressed {
+        unpack_bits(data)?
+    } else {
+        data.to_vec()
+    };
+    match info.bit_depth {
+        16 => return decode_bitmap_16bit(info.width, info.height, row_bytes, is_compressed, PaletteRef::from(info.palette_id, cast_lib), &result),
+        32 => return decode_bitmap_32bit(info.width, info.height, row_bytes, is_compressed, PaletteRef::from(info.palette_id, cast_lib), &result),
+        _ => {}
     }
 
     if result.len() == info.width as usize * info.height as usize * num_channels as usize {
         scan_width = info.width;
-    } else if info.width % alignment_width == 0 {
-        scan_width = info.width;
     } else {
-        scan_width = alignment_width * info.width.div_ceil(alignment_width);
+        scan_width = (row_bytes * 8 / info.bit_depth as usize) as u16;
     }
 
     match info.bit_depth {
@@ -457,26 +527,6 @@ pub fn decompress_bitmap(data: &[u8], info: &BitmapInfo, cast_lib: u32) -> Resul
             PaletteRef::from(info.palette_id, cast_lib),
             &result,
         ),
-        16 => decode_generic_bitmap(
-            info.width,
-            info.height,
-            16,
-            1,
-            scan_width,
-            scan_height,
-            PaletteRef::from(info.palette_id, cast_lib),
-            &result,
-        ),
-        32 => decode_generic_bitmap(
-            info.width,
-            info.height,
-            8,
-            4,
-            scan_width,
-            scan_height,
-            PaletteRef::from(info.palette_id, cast_lib),
-            &result,
-        ),
         _ => Err(format!(
             "Decompression not implemented for bitmap width {}, height {}, bit depth {}",
             info.width, info.height, info.bit_depth
@@ -533,3 +583,87 @@ pub fn resolve_color_ref(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    fn bitmap_info(width: u16, height: u16, bit_depth: u8, pitch: u16) -> BitmapInfo {
+        BitmapInfo {
+            width,
+            height,
+            reg_x: 0,
+            reg_y: 0,
+            bit_depth,
+            palette_id: -1,
+            pitch,
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn decodes_1bit_rows_padded_to_the_pitch() {
+        let data = [0b1010_0000, 0x00, 0b0100_0000, 0x00];
+        let bitmap = decompress_bitmap(&data, &bitmap_info(3, 2, 1, 2), 1).unwrap();
+        assert_eq!(bitmap.data, vec![0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00]);
+    }
+
+    #[wasm_bindgen_test]
+    fn decodes_uncompressed_8bit_rows_padded_to_the_pitch() {
+        let data = [1, 2, 3, 0, 4, 5, 6, 0];
+        let bitmap = decompress_bitmap(&data, &bitmap_info(3, 2, 8, 4), 1).unwrap();
+        assert_eq!(bitmap.data, vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[wasm_bindgen_test]
+    fn decodes_packbits_8bit_runs() {
+        // A run of four 7s, then four literal bytes
+        let data = [0xFD, 7, 0x03, 1, 2, 3, 4];
+        let bitmap = decompress_bitmap(&data, &bitmap_info(4, 2, 8, 4), 1).unwrap();
+        assert_eq!(bitmap.data, vec![7, 7, 7, 7, 1, 2, 3, 4]);
+    }
+
+    #[wasm_bindgen_test]
+    fn decodes_uncompressed_16bit_as_555() {
+        // Full red, then full green
+        let data = [0x7C, 0x00, 0x03, 0xE0];
+        let bitmap = decompress_bitmap(&data, &bitmap_info(2, 1, 16, 4), 1).unwrap();
+        assert_eq!(bitmap.bit_depth, 16);
+        assert_eq!(bitmap.data, vec![0x00, 0xF8, 0xE0, 0x07]);
+    }
+
+    #[wasm_bindgen_test]
+    fn decodes_compressed_16bit_from_planar_rows() {
+        // The high bytes of the row, then the low bytes
+        let data = [0x03, 0x7C, 0x03, 0x00, 0xE0];
+        let bitmap = decompress_bitmap(&data, &bitmap_info(2, 1, 16, 4), 1).unwrap();
+        assert_eq!(bitmap.data, vec![0x00, 0xF8, 0xE0, 0x07]);
+    }
+
+    #[wasm_bindgen_test]
+    fn decodes_compressed_32bit_from_planar_argb_rows() {
+        let data = [0x07, 0xFF, 0x80, 0x10, 0x20, 0x30, 0x40, 0x50, 0x60];
+        let bitmap = decompress_bitmap(&data, &bitmap_info(2, 1, 32, 8), 1).unwrap();
+        assert_eq!(bitmap.data, vec![0x10, 0x30, 0x50, 0xFF, 0x20, 0x40, 0x60, 0x80]);
+    }
+
+    #[wasm_bindgen_test]
+    fn makes_32bit_images_without_alpha_opaque() {
+        let data = [0x00, 0x10, 0x20, 0x30];
+        let bitmap = decompress_bitmap(&data, &bitmap_info(1, 1, 32, 4), 1).unwrap();
+        assert_eq!(bitmap.data, vec![0x10, 0x20, 0x30, 0xFF]);
+    }
+
+    #[wasm_bindgen_test]
+    fn reports_unsupported_bit_depths() {
+        let error = decompress_bitmap(&[0; 3], &bitmap_info(1, 1, 24, 3), 1).err().unwrap();
+        assert!(error.contains("Unsupported bitmap bit depth 24"));
+    }
+
+    #[wasm_bindgen_test]
+    fn reports_runs_past_the_end_of_the_data() {
+        let error = decompress_bitmap(&[0x05, 1, 2], &bitmap_info(4, 2, 8, 4), 1).err().unwrap();
+        assert!(error.contains("past the end"));
+    }
+}
@@ -1,11 +1,12 @@
 use std::collections::HashMap;
 
+use itertools::Itertools;
 use nohash_hasher::IntMap;
 use rgb565::Rgb565;
 
-use crate::{director::lingo::datum::Datum, player::{font::{bitmap_font_copy_char, BitmapFont}, geometry::IntRect, sprite::ColorRef}};
+use crate::{director::lingo::datum::Datum, io::text_encoding::mac_roman_code, player::{font::{bitmap_font_copy_char, get_char_advances, BitmapFont, BitmapTextParams}, geometry::{IntRect, SpriteTransform}, sprite::ColorRef}};
 
-use super::{bitmap::{resolve_color_ref, Bitmap}, mask::BitmapMask, palette_map::PaletteMap};
+use super::{bitmap::{resolve_color_ref, Bitmap, PaletteRef}, mask::BitmapMask, palette_map::PaletteMap};
 
 pub struct CopyPixelsParams<'a> {
     pub blend: i32,
@@ -13,6 +14,10 @@ pub struct CopyPixelsParams<'a> {
     pub color: ColorRef,
     pub bg_color: ColorRef,
     pub mask_image: Option<&'a BitmapMask>,
+    /// Colors to use for the source's palette indices instead of its own palette.
+    pub palette_remap: Option<&'a [(u8, u8, u8)]>,
+    /// Samples stretched sources bilinearly instead of taking the nearest pixel.
+    pub smooth: bool,
 }
 
 impl CopyPixelsParams<'_> {
@@ -23,10 +28,38 @@ impl CopyPixelsParams<'_> {
             color: bitmap.get_fg_color_ref(),
             bg_color: bitmap.get_bg_color_ref(),
             mask_image: None,
+            palette_remap: None,
+            smooth: false,
         }
     }
 }
 
+/// Maps every index of `palette_ref` to the nearest color of `target_palette_ref`, which is
+/// how indexed members with a foreign palette are drawn when `the paletteMapping` is on.
+pub fn get_palette_remap_table(
+    palettes: &PaletteMap,
+    palette_ref: &PaletteRef,
+    target_palette_ref: &PaletteRef,
+) -> Vec<(u8, u8, u8)> {
+    let target_colors = (0..=255u8)
+        .map(|index| resolve_color_ref(palettes, &ColorRef::PaletteIndex(index), target_palette_ref))
+        .collect_vec();
+    (0..=255u8)
+        .map(|index| {
+            let (r, g, b) = resolve_color_ref(palettes, &ColorRef::PaletteIndex(index), palette_ref);
+            *target_colors
+                .iter()
+                .min_by_key(|(tr, tg, tb)| {
+                    let dr = r as i32 - *tr as i32;
+                    let dg = g as i32 - *tg as i32;
+                    let db = b as i32 - *tb as i32;
+                    dr * dr + dg * dg + db * db
+                })
+                .unwrap()
+        })
+        .collect()
+}
+
 fn blend_alpha(dst: u8, src: u8, alpha: f32) -> u8 {
     (src as f32 * alpha + dst as f32 * (1.0 - alpha)) as u8
 }
@@ -43,10 +76,44 @@ fn blend_color_alpha(dst: (u8, u8, u8), src: (u8, u8, u8), alpha: f32) -> (u8, u
     (r, g, b)
 }
 
+/// Applies an arithmetic ink to each color component.
+fn map_components(dst: (u8, u8, u8), src: (u8, u8, u8), f: impl Fn(i32, i32) -> i32) -> (u8, u8, u8) {
+    let component = |dst: u8, src: u8| f(dst as i32, src as i32) as u8;
+    (component(dst.0, src.0), component(dst.1, src.1), component(dst.2, src.2))
+}
+
+/// Scales a component of `bits` bits to 8 bits, rounding to the nearest value.
+fn expand_component(value: u8, bits: u32) -> u8 {
+    let max = (1u32 << bits) - 1;
+    ((value as u32 * 255 + max / 2) / max) as u8
+}
+
+/// Scales an 8-bit component down to `bits` bits, rounding to the nearest value.
+fn reduce_component(value: u8, bits: u32) -> u8 {
+    let max = (1u32 << bits) - 1;
+    ((value as u32 * max + 127) / 255) as u8
+}
+
+/// Inks that leave pixels of the background color out, which smoothing must not blur into.
+fn is_bg_transparent_ink(ink: u32) -> bool {
+    matches!(ink, 33 | 34 | 35 | 36 | 38)
+}
+
 pub fn should_matte_sprite(ink: u32) -> bool {
     ink == 36 || ink == 33 || ink == 41 || ink == 8 || ink == 7
 }
 
+/// The source pixels smoothing leaves out: the bg color of bg-transparent inks and
+/// whatever is outside the mask or matte.
+fn smoothing_exclusion(
+    ink: u32,
+    bg_color: (u8, u8, u8),
+    mask_image: Option<&BitmapMask>,
+) -> impl Fn(u16, u16, (u8, u8, u8)) -> bool + Copy + '_ {
+    let bg_transparent = is_bg_transparent_ink(ink);
+    move |x: u16, y: u16, color: (u8, u8, u8)| (bg_transparent && color == bg_color) || mask_image.is_some_and(|mask| !mask.get_bit(x, y))
+}
+
 fn blend_pixel(
     dst: (u8, u8, u8), 
     src: (u8, u8, u8), 
@@ -74,19 +141,23 @@ fn blend_pixel(
             // TODO
             blend_color_alpha(dst, src, alpha)
         }
+        32 => {
+            // Blend
+            blend_color_alpha(dst, src, alpha)
+        }
+        // The arithmetic inks leave the background color of the source out
+        33 | 34 | 35 | 38 if src == bg_color => dst,
         33 => {
             // Add pin
-            if src == bg_color {
-                dst
-            } else {
-                let r = dst.0 as i32 + src.0 as i32;
-                let g = dst.1 as i32 + src.1 as i32;
-                let b = dst.2 as i32 + src.2 as i32;
-                let r = r.min(255).max(0) as u8;
-                let g = g.min(255).max(0) as u8;
-                let b = b.min(255).max(0) as u8;
-                (r, g, b)
-            }
+            blend_color_alpha(dst, map_components(dst, src, |d, s| (d + s).min(255)), alpha)
+        }
+        34 => {
+            // Add, components wrap around
+            blend_color_alpha(dst, map_components(dst, src, |d, s| (d + s) & 0xFF), alpha)
+        }
+        35 => {
+            // Subtract pin
+            blend_color_alpha(dst, map_components(dst, src, |d, s| (d - s).max(0)), alpha)
         }
         36 => {
             // Background transparent
@@ -96,6 +167,18 @@ fn blend_pixel(
                 blend_color_alpha(dst, src, alpha)
             }
         }
+        37 => {
+            // Lightest
+            blend_color_alpha(dst, map_components(dst, src, i32::max), alpha)
+        }
+        38 => {
+            // Subtract, components wrap around
+            blend_color_alpha(dst, map_components(dst, src, |d, s| (d - s) & 0xFF), alpha)
+        }
+        39 => {
+            // Darkest
+            blend_color_alpha(dst, map_components(dst, src, i32::min), alpha)
+        }
         41 => {
             // Darken
             // TODO
@@ -185,10 +268,7 @@ impl Bitmap {
                     self.data[index] = result_index;
                 }
                 16 => {
-                    let r = r as f32 * 31.0 / 255.0;
-                    let g = g as f32 * 63.0 / 255.0;
-                    let b = b as f32 * 31.0 / 255.0;
-                    let value = Rgb565::pack_565((r as u8, g as u8, b as u8));
+                    let value = Rgb565::pack_565((reduce_component(r, 5), reduce_component(g, 6), reduce_component(b, 5)));
                     let bytes = value.to_le_bytes();
                     self.data[index] = bytes[0];
                     self.data[index + 1] = bytes[1];
@@ -239,10 +319,7 @@ impl Bitmap {
                 let index = (y * self.width as usize + x) * 2;
                 let value = u16::from_le_bytes([self.data[index], self.data[index + 1]]);
                 let (red, green, blue) = Rgb565::unpack_565(value);
-                let red = (red as f32 / 31.0 * 255.0) as u8;
-                let green = (green as f32 / 63.0 * 255.0) as u8;
-                let blue = (blue as f32 / 31.0 * 255.0) as u8;
-                ColorRef::Rgb(red, green, blue)
+                ColorRef::Rgb(expand_component(red, 5), expand_component(green, 6), expand_component(blue, 5))
             }
             32 => {
                 let bytes_per_pixel = 4;
@@ -256,11 +333,89 @@ impl Bitmap {
         }
     }
 
+    /// The opacity of a pixel, which only 32-bit images carry.
+    pub fn get_pixel_alpha(&self, x: u16, y: u16) -> f32 {
+        if self.bit_depth != 32 || x >= self.width || y >= self.height {
+            return 1.0;
+        }
+        let index = (y as usize * self.width as usize + x as usize) * 4;
+        self.data[index + 3] as f32 / 255.0
+    }
+
     pub fn get_pixel_color(&self, palettes: &PaletteMap, x: u16, y: u16) -> (u8, u8, u8) {
         let color_ref = self.get_pixel_color_ref(x, y);
         resolve_color_ref(palettes, &color_ref, &self.palette_ref)
     }
 
+    /// Resolves every palette index up front for indexed bitmaps, so that drawing
+    /// doesn't have to look up the palette again for each pixel.
+    pub fn get_palette_lookup_table(&self, palettes: &PaletteMap) -> Option<Vec<(u8, u8, u8)>> {
+        if !self.has_palette() {
+            return None;
+        }
+        Some(
+            (0..=255u8)
+                .map(|index| resolve_color_ref(palettes, &ColorRef::PaletteIndex(index), &self.palette_ref))
+                .collect(),
+        )
+    }
+
+    #[inline]
+    fn get_pixel_color_with_table(
+        &self,
+        palettes: &PaletteMap,
+        lookup_table: Option<&[(u8, u8, u8)]>,
+        x: u16,
+        y: u16,
+    ) -> (u8, u8, u8) {
+        match (self.get_pixel_color_ref(x, y), lookup_table) {
+            (ColorRef::PaletteIndex(index), Some(lookup_table)) => lookup_table[index as usize],
+            (color_ref, _) => resolve_color_ref(palettes, &color_ref, &self.palette_ref),
+        }
+    }
+
+    /// Interpolates between the four pixels around a position, in pixel units with
+    /// pixel centers at half coordinates. Pixels `is_excluded` rejects are left out of
+    /// the weights, so transparent pixels don't bleed into the edges of a sprite. Gives
+    /// `None` when all of them are.
+    fn get_pixel_color_bilinear(
+        &self,
+        palettes: &PaletteMap,
+        lookup_table: Option<&[(u8, u8, u8)]>,
+        x: f32,
+        y: f32,
+        is_excluded: impl Fn(u16, u16, (u8, u8, u8)) -> bool,
+    ) -> Option<(u8, u8, u8)> {
+        let max_x = self.width as f32 - 1.0;
+        let max_y = self.height as f32 - 1.0;
+        let x = (x - 0.5).clamp(0.0, max_x.max(0.0));
+        let y = (y - 0.5).clamp(0.0, max_y.max(0.0));
+        let (x0, y0) = (x.floor(), y.floor());
+        let (x1, y1) = ((x0 + 1.0).min(max_x), (y0 + 1.0).min(max_y));
+        let (fx, fy) = (x - x0, y - y0);
+        let samples = [
+            (x0, y0, (1.0 - fx) * (1.0 - fy)),
+            (x1, y0, fx * (1.0 - fy)),
+            (x0, y1, (1.0 - fx) * fy),
+            (x1, y1, fx * fy),
+        ];
+        let (mut r, mut g, mut b, mut total) = (0.0, 0.0, 0.0, 0.0);
+        for (x, y, weight) in samples {
+            let color = self.get_pixel_color_with_table(palettes, lookup_table, x as u16, y as u16);
+            if weight <= 0.0 || is_excluded(x as u16, y as u16, color) {
+                continue;
+            }
+            r += color.0 as f32 * weight;
+            g += color.1 as f32 * weight;
+            b += color.2 as f32 * weight;
+            total += weight;
+        }
+        if total <= 0.0 {
+            return None;
+        }
+        Some(((r / total).round() as u8, (g / total).round() as u8, (b / total).round() as u8))
+    }
+
     pub const fn has_palette(&self) -> bool {
         self.bit_depth != 16 && self.bit_depth != 32
     }
@@ -384,6 +539,10 @@ impl Bitmap {
         if alpha == 0.0 {
             return;
         }
+        let x1 = x1.max(0);
+        let y1 = y1.max(0);
+        let x2 = x2.min(self.width as i32);
+        let y2 = y2.min(self.height as i32);
         for y in y1..y2 {
             for x in x1..x2 {
                 let blended_color = if alpha == 1.0 {
@@ -436,6 +595,8 @@ impl Bitmap {
             bg_color,
             mask_image,
             color,
+            palette_remap: None,
+            smooth: false,
         };
         self.copy_pixels_with_params(palettes, src, dst_rect, src_rect, &params);
     }
@@ -454,7 +615,6 @@ impl Bitmap {
         let bg_color = &params.bg_color;
         let bg_color = resolve_color_ref(palettes, &bg_color, &self.palette_ref);
 
-        let mut src_y = if dst_rect.height() < 0 { src_rect.bottom } else { src_rect.top } as f32;
         let step_x = src_rect.width() as f32 / dst_rect.width() as f32;
         let step_y = src_rect.height() as f32 / dst_rect.height() as f32;
 
@@ -473,18 +633,51 @@ impl Bitmap {
             }
         };
 
-        for dst_y in min_dst_y..max_dst_y {
-            let mut src_x = if dst_rect.width() < 0 { src_rect.right } else { src_rect.left } as f32;
-            for dst_x in min_dst_x..max_dst_x {
+        // Clip to the destination bounds, advancing the source position by the skipped pixels
+        let clipped_min_dst_x = min_dst_x.max(0);
+        let clipped_max_dst_x = max_dst_x.min(self.width as i32);
+        let clipped_min_dst_y = min_dst_y.max(0);
+        let clipped_max_dst_y = max_dst_y.min(self.height as i32);
+        if clipped_min_dst_x >= clipped_max_dst_x || clipped_min_dst_y >= clipped_max_dst_y {
+            return;
+        }
+        // Resolving whole palettes only pays off when more pixels than palette entries are copied
+        let pixel_count = (clipped_max_dst_x - clipped_min_dst_x) * (clipped_max_dst_y - clipped_min_dst_y);
+        let (src_lookup_table, dst_lookup_table) = if pixel_count > 256 {
+            (src.get_palette_lookup_table(palettes), self.get_palette_lookup_table(palettes))
+        } else {
+            (None, None)
+        };
+        let src_lookup_table = params.palette_remap.or(src_lookup_table.as_deref());
+        let smooth = params.smooth && (step_x.abs() != 1.0 || step_y.abs() != 1.0);
+        let is_excluded = smoothing_exclusion(ink, bg_color, mask_image);
+        let start_src_x = if dst_rect.width() < 0 { src_rect.right } else { src_rect.left } as f32
+            + step_x * (clipped_min_dst_x - min_dst_x) as f32;
+        let mut src_y = if dst_rect.height() < 0 { src_rect.bottom } else { src_rect.top } as f32
+            + step_y * (clipped_min_dst_y - min_dst_y) as f32;
+
+        for dst_y in clipped_min_dst_y..clipped_max_dst_y {
+            let mut src_x = start_src_x;
+            for dst_x in clipped_min_dst_x..clipped_max_dst_x {
                 if let Some(mask_image) = mask_image {
                     if !mask_image.get_bit(src_x as u16, src_y as u16) {
                         src_x += step_x;
                         continue;
                     }
                 }
-                let src_color = src.get_pixel_color(palettes, src_x.floor() as u16, src_y.floor() as u16);
-                let dst_color = self.get_pixel_color(palettes, dst_x as u16, dst_y as u16);
-                let blended_color = blend_pixel(dst_color, src_color, ink, bg_color, alpha);
+                let pixel_alpha = src.get_pixel_alpha(src_x.floor() as u16, src_y.floor() as u16);
+                if pixel_alpha == 0.0 {
+                    src_x += step_x;
+                    continue;
+                }
+                let mut src_color = src.get_pixel_color_with_table(palettes, src_lookup_table, src_x.floor() as u16, src_y.floor() as u16);
+                if smooth && !is_excluded(src_x.floor() as u16, src_y.floor() as u16, src_color) {
+                    src_color = src
+                        .get_pixel_color_bilinear(palettes, src_lookup_table, src_x + step_x / 2.0, src_y + step_y / 2.0, is_excluded)
+                        .unwrap_or(src_color);
+                }
+                let dst_color = self.get_pixel_color_with_table(palettes, dst_lookup_table.as_deref(), dst_x as u16, dst_y as u16);
+                let blended_color = blend_pixel(dst_color, src_color, ink, bg_color, alpha * pixel_alpha);
 
                 self.set_pixel(dst_x, dst_y, blended_color, palettes);
                 src_x += step_x;
@@ -495,6 +688,68 @@ impl Bitmap {
         // self.stroke_rect(min_dst_x, min_dst_y, max_dst_x, max_dst_y, (0, 255, 0), palettes, 1.0);
     }
 
+    /// Like `copy_pixels_with_params`, but maps every destination pixel through the
+    /// inverse of `transform` first, so rotated and skewed sprites can be drawn.
+    /// `dst_rect` is the untransformed, possibly flipped, rect of the sprite.
+    pub fn copy_pixels_transformed(
+        &mut self,
+        palettes: &PaletteMap,
+        src: &Bitmap,
+        dst_rect: IntRect,
+        src_rect: IntRect,
+        transform: &SpriteTransform,
+        params: &CopyPixelsParams,
+    ) {
+        if dst_rect.width() == 0 || dst_rect.height() == 0 {
+            return;
+        }
+        let ink = params.ink;
+        let alpha = params.blend as f32 / 100.0;
+        let bg_color = resolve_color_ref(palettes, &params.bg_color, &self.palette_ref);
+        let step_x = src_rect.width() as f32 / dst_rect.width() as f32;
+        let step_y = src_rect.height() as f32 / dst_rect.height() as f32;
+        let (min_x, max_x) = (dst_rect.left.min(dst_rect.right) as f32, dst_rect.left.max(dst_rect.right) as f32);
+        let (min_y, max_y) = (dst_rect.top.min(dst_rect.bottom) as f32, dst_rect.top.max(dst_rect.bottom) as f32);
+        let normalized_rect = IntRect::from(min_x as i32, min_y as i32, max_x as i32, max_y as i32);
+        let bounds = transform
+            .bounds(&normalized_rect)
+            .intersect(&IntRect::from(0, 0, self.width as i32, self.height as i32));
+        let src_lookup_table = params.palette_remap;
+        let smooth = params.smooth && (step_x.abs() != 1.0 || step_y.abs() != 1.0);
+        let is_excluded = smoothing_exclusion(ink, bg_color, params.mask_image);
+        let src_max_x = src_rect.left.max(src_rect.right) as f32;
+        let src_max_y = src_rect.top.max(src_rect.bottom) as f32;
+
+        for dst_y in bounds.top..bounds.bottom {
+            for dst_x in bounds.left..bounds.right {
+                let (x, y) = transform.invert((dst_x as f32 + 0.5, dst_y as f32 + 0.5));
+                if !(x >= min_x && x < max_x && y >= min_y && y < max_y) {
+                    continue;
+                }
+                let src_x = (src_rect.left as f32 + (x - dst_rect.left as f32) * step_x).clamp(0.0, src_max_x - 1.0);
+                let src_y = (src_rect.top as f32 + (y - dst_rect.top as f32) * step_y).clamp(0.0, src_max_y - 1.0);
+                if let Some(mask_image) = params.mask_image {
+                    if !mask_image.get_bit(src_x as u16, src_y as u16) {
+                        continue;
+                    }
+                }
+                let pixel_alpha = src.get_pixel_alpha(src_x as u16, src_y as u16);
+                if pixel_alpha == 0.0 {
+                    continue;
+                }
+                let mut src_color = src.get_pixel_color_with_table(palettes, src_lookup_table, src_x as u16, src_y as u16);
+                if smooth && !is_excluded(src_x as u16, src_y as u16, src_color) {
+                    src_color = src
+                        .get_pixel_color_bilinear(palettes, src_lookup_table, src_x, src_y, is_excluded)
+                        .unwrap_or(src_color);
+                }
+                let dst_color = self.get_pixel_color(palettes, dst_x as u16, dst_y as u16);
+                let blended_color = blend_pixel(dst_color, src_color, ink, bg_color, alpha * pixel_alpha);
+                self.set_pixel(dst_x, dst_y, blended_color, palettes);
+            }
+        }
+    }
+
     pub fn _draw_bitmap(
         &mut self,
         palettes: &PaletteMap,
@@ -522,30 +777,26 @@ impl Bitmap {
         text: &str,
         font: &BitmapFont,
         font_bitmap: &Bitmap,
-        loc_h: i32,
-        loc_v: i32,
-        ink: u32,
-        bg_color: ColorRef,
+        (loc_h, loc_v): (i32, i32),
         palettes: &PaletteMap,
-        line_spacing: u16,
-        top_spacing: i16,
+        params: &BitmapTextParams,
     ) {
         let mut x = loc_h;
         let mut y = loc_v;
         let line_height = font.char_height;
 
-        let mut params = CopyPixelsParams::default(&self);
-        params.ink = ink;
-        params.bg_color = bg_color;
-
-        for char_num in text.chars() {
+        for (char_num, advance) in get_char_advances(font, text, params.style) {
             if char_num == '\r' || char_num == '\n' {
                 x = loc_h;
-                y += line_height as i32 + line_spacing as i32 + 1;
+                y += line_height as i32 + params.line_spacing as i32 + 1;
                 continue;
             }
-            bitmap_font_copy_char(font, font_bitmap, char_num as u8, self, x, y, &palettes, &params);
-            x += font.char_width as i32 + 1;
+            bitmap_font_copy_char(font, font_bitmap, mac_roman_code(char_num), self, (x, y), palettes, params);
+            if params.style.bold {
+                // Bold is synthesized by drawing the glyph again one pixel to the right
+                bitmap_font_copy_char(font, font_bitmap, mac_roman_code(char_num), self, (x + 1, y), palettes, params);
+            }
+            x += advance;
         }
     }
 
@@ -642,3 +893,78 @@ impl Bitmap {
         mask
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::*;
+
+    use crate::player::{bitmap::{bitmap::{Bitmap, BuiltInPalette, PaletteRef}, palette_map::PaletteMap}, geometry::IntRect};
+
+    use super::CopyPixelsParams;
+
+    /// The source row drawn by the ink tests. The white pixel is the background color of
+    /// the source, which the arithmetic inks leave out.
+    const INK_SOURCE: [(u8, u8, u8); 4] = [(100, 100, 100), (20, 10, 40), (255, 255, 255), (60, 200, 250)];
+    const INK_DESTINATION: [(u8, u8, u8); 4] = [(200, 100, 50), (10, 20, 30), (128, 128, 128), (0, 0, 0)];
+
+    /// Draws the source row over the destination row with an ink and reads back the result.
+    fn draw_with_ink(src_bit_depth: u8, ink: u32, blend: i32) -> Vec<(u8, u8, u8)> {
+        let palettes = PaletteMap::new();
+        let mut src = Bitmap::new(4, 1, src_bit_depth, PaletteRef::BuiltIn(BuiltInPalette::GrayScale));
+        let mut dst = Bitmap::new(4, 1, 32, PaletteRef::BuiltIn(BuiltInPalette::GrayScale));
+        for x in 0..4 {
+            src.set_pixel(x as i32, 0, INK_SOURCE[x], &palettes);
+            dst.set_pixel(x as i32, 0, INK_DESTINATION[x], &palettes);
+        }
+        let params = CopyPixelsParams {
+            ink,
+            blend,
+            ..CopyPixelsParams::default(&src)
+        };
+        let rect = IntRect::from(0, 0, 4, 1);
+        dst.copy_pixels_with_params(&palettes, &src, rect.clone(), rect, &params);
+        (0..4).map(|x| dst.get_pixel_color(&palettes, x, 0)).collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn draws_32bit_sources_with_arithmetic_inks() {
+        assert_eq!(draw_with_ink(32, 32, 50), vec![(150, 100, 75), (15, 15, 35), (191, 191, 191), (30, 100, 125)]);
+        assert_eq!(draw_with_ink(32, 33, 100), vec![(255, 200, 150), (30, 30, 70), (128, 128, 128), (60, 200, 250)]);
+        assert_eq!(draw_with_ink(32, 34, 100), vec![(44, 200, 150), (30, 30, 70), (128, 128, 128), (60, 200, 250)]);
+        assert_eq!(draw_with_ink(32, 35, 100), vec![(100, 0, 0), (0, 10, 0), (128, 128, 128), (0, 0, 0)]);
+        assert_eq!(draw_with_ink(32, 37, 100), vec![(200, 100, 100), (20, 20, 40), (255, 255, 255), (60, 200, 250)]);
+        assert_eq!(draw_with_ink(32, 38, 100), vec![(100, 0, 206), (246, 10, 246), (128, 128, 128), (196, 56, 6)]);
+        assert_eq!(draw_with_ink(32, 39, 100), vec![(100, 100, 50), (10, 10, 30), (128, 128, 128), (0, 0, 0)]);
+    }
+
+    #[wasm_bindgen_test]
+    fn draws_16bit_sources_with_arithmetic_inks() {
+        // The source colors are rounded to the nearest 5-6-5 color first
+        assert_eq!(draw_with_ink(16, 33, 100), vec![(255, 201, 149), (26, 28, 71), (128, 128, 128), (58, 198, 247)]);
+        assert_eq!(draw_with_ink(16, 34, 100), vec![(43, 201, 149), (26, 28, 71), (128, 128, 128), (58, 198, 247)]);
+        assert_eq!(draw_with_ink(16, 35, 100), vec![(101, 0, 0), (0, 12, 0), (128, 128, 128), (0, 0, 0)]);
+        assert_eq!(draw_with_ink(16, 37, 100), vec![(200, 101, 99), (16, 20, 41), (255, 255, 255), (58, 198, 247)]);
+        assert_eq!(draw_with_ink(16, 38, 100), vec![(101, 255, 207), (250, 12, 245), (128, 128, 128), (198, 58, 9)]);
+        assert_eq!(draw_with_ink(16, 39, 100), vec![(99, 100, 50), (10, 8, 30), (128, 128, 128), (0, 0, 0)]);
+    }
+
+    #[wasm_bindgen_test]
+    fn smoothing_leaves_the_bg_color_of_bg_transparent_sprites_out() {
+        let palettes = PaletteMap::new();
+        let mut src = Bitmap::new(2, 1, 32, PaletteRef::BuiltIn(BuiltInPalette::GrayScale));
+        let mut dst = Bitmap::new(4, 1, 32, PaletteRef::BuiltIn(BuiltInPalette::GrayScale));
+        src.set_pixel(0, 0, (0, 0, 0), &palettes);
+        src.set_pixel(1, 0, (255, 255, 255), &palettes);
+        for x in 0..4 {
+            dst.set_pixel(x, 0, (255, 0, 0), &palettes);
+        }
+        let params = CopyPixelsParams {
+            ink: 36,
+            smooth: true,
+            ..CopyPixelsParams::default(&src)
+        };
+        dst.copy_pixels_with_params(&palettes, &src, IntRect::from(0, 0, 4, 1), IntRect::from(0, 0, 2, 1), &params);
+        let colors = (0..4).map(|x| dst.get_pixel_color(&palettes, x, 0)).collect::<Vec<_>>();
+        assert_eq!(colors, vec![(0, 0, 0), (0, 0, 0), (255, 0, 0), (255, 0, 0)]);
+    }
+}
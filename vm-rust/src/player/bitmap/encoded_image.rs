@@ -0,0 +1,182 @@
+use binary_reader::{BinaryReader, Endian};
+
+use super::bitmap::{Bitmap, PaletteRef};
+
+/// How far into the media data the image file is looked for, past the header some
+/// versions write before it.
+const MAX_HEADER_LEN: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Gif,
+}
+
+/// Finds the image file in the media of a member, returning its format and data.
+pub fn sniff_image(data: &[u8]) -> Option<(ImageFormat, &[u8])> {
+    (0..data.len().min(MAX_HEADER_LEN)).find_map(|offset| {
+        let data = &data[offset..];
+        if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some((ImageFormat::Jpeg, data))
+        } else if data.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+            Some((ImageFormat::Png, data))
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some((ImageFormat::Gif, data))
+        } else {
+            None
+        }
+    })
+}
+
+/// Decodes an imported JPEG, PNG or GIF into a 32-bit bitmap, keeping the
+/// transparency of PNG and GIF images in its alpha.
+pub fn decode_image(data: &[u8], palette_ref: PaletteRef) -> Result<Bitmap, String> {
+    let (format, data) = sniff_image(data).ok_or_else(|| "Media is not a JPEG, PNG or GIF image".to_string())?;
+    let (width, height, rgba) = match format {
+        ImageFormat::Jpeg => decode_jpeg(data)?,
+        ImageFormat::Png => decode_png(data)?,
+        ImageFormat::Gif => decode_gif(data)?,
+    };
+    Ok(Bitmap {
+        width,
+        height,
+        bit_depth: 32,
+        data: rgba,
+        palette_ref,
+        matte: None,
+    })
+}
+
+fn image_size(width: u32, height: u32) -> Result<(u16, u16), String> {
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(format!("Image of {}x{} is too large", width, height));
+    }
+    Ok((width as u16, height as u16))
+}
+
+fn decode_jpeg(data: &[u8]) -> Result<(u16, u16, Vec<u8>), String> {
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let pixels = decoder.decode().map_err(|err| format!("Could not decode JPEG: {}", err))?;
+    let info = decoder.info().ok_or_else(|| "JPEG has no header".to_string())?;
+    let (width, height) = image_size(info.width as u32, info.height as u32)?;
+    let rgba = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => pixels.iter().flat_map(|l| [*l, *l, *l, 0xFF]).collect(),
+        jpeg_decoder::PixelFormat::L16 => pixels.chunks_exact(2).flat_map(|l| [l[0], l[0], l[0], 0xFF]).collect(),
+        jpeg_decoder::PixelFormat::RGB24 => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 0xFF]).collect(),
+        jpeg_decoder::PixelFormat::CMYK32 => pixels.chunks_exact(4)
+            .flat_map(|p| {
+                let k = p[3] as u32;
+                let channel = |c: u8| (c as u32 * k / 255) as u8;
+                [channel(p[0]), channel(p[1]), channel(p[2]), 0xFF]
+            })
+            .collect(),
+    };
+    Ok((width, height, rgba))
+}
+
+fn decode_png(data: &[u8]) -> Result<(u16, u16, Vec<u8>), String> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|err| format!("Could not decode PNG: {}", err))?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).map_err(|err| format!("Could not decode PNG: {}", err))?;
+    let pixels = &pixels[..frame.buffer_size()];
+    let (width, height) = image_size(frame.width, frame.height)?;
+    let rgba = match frame.color_type {
+        png::ColorType::Grayscale => pixels.iter().flat_map(|l| [*l, *l, *l, 0xFF]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Rgb => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 0xFF]).collect(),
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Indexed => return Err("PNG palette was not expanded".to_string()),
+    };
+    Ok((width, height, rgba))
+}
+
+/// Decodes the first frame of a GIF onto its logical screen.
+fn decode_gif(data: &[u8]) -> Result<(u16, u16, Vec<u8>), String> {
+    let truncated = |_| "GIF data ends early".to_string();
+    let mut reader = BinaryReader::from_u8(data);
+    reader.set_endian(Endian::Little);
+    reader.jmp(6);
+    let width = reader.read_u16().map_err(truncated)?;
+    let height = reader.read_u16().map_err(truncated)?;
+    let flags = reader.read_u8().map_err(truncated)?;
+    reader.read_bytes(2).map_err(truncated)?;
+    let global_colors = if flags & 0x80 != 0 {
+        reader.read_bytes(3 << ((flags & 0x07) + 1)).map_err(truncated)?.to_vec()
+    } else {
+        vec![]
+    };
+
+    let mut transparent_index = None;
+    loop {
+        match reader.read_u8().map_err(truncated)? {
+            0x21 => {
+                let label = reader.read_u8().map_err(truncated)?;
+                let blocks = read_gif_sub_blocks(&mut reader)?;
+                // Graphic control extension, whose first flag marks a transparent color
+                if label == 0xF9 && blocks.len() >= 4 && blocks[0] & 0x01 != 0 {
+                    transparent_index = Some(blocks[3]);
+                }
+            }
+            0x2C => {
+                let left = reader.read_u16().map_err(truncated)? as usize;
+                let top = reader.read_u16().map_err(truncated)? as usize;
+                let frame_width = reader.read_u16().map_err(truncated)? as usize;
+                let frame_height = reader.read_u16().map_err(truncated)? as usize;
+                let frame_flags = reader.read_u8().map_err(truncated)?;
+                let colors = if frame_flags & 0x80 != 0 {
+                    reader.read_bytes(3 << ((frame_flags & 0x07) + 1)).map_err(truncated)?.to_vec()
+                } else {
+                    global_colors.clone()
+                };
+                let min_code_size = reader.read_u8().map_err(truncated)?;
+                let compressed = read_gif_sub_blocks(&mut reader)?;
+                let mut indices = vec![];
+                let result = weezl::decode::Decoder::new(weezl::BitOrder::Lsb, min_code_size)
+                    .into_vec(&mut indices)
+                    .decode(&compressed);
+                if indices.is_empty() {
+                    result.status.map_err(|err| format!("Could not decode GIF: {}", err))?;
+                }
+
+                let rows = gif_row_order(frame_height, frame_flags & 0x40 != 0);
+                let mut rgba = vec![0; width as usize * height as usize * 4];
+                for (i, index) in indices.iter().enumerate().take(frame_width * frame_height) {
+                    let (x, y) = (left + i % frame_width, top + rows[i / frame_width]);
+                    if x >= width as usize || y >= height as usize || Some(*index) == transparent_index {
+                        continue;
+                    }
+                    let color = colors.get(*index as usize * 3..*index as usize * 3 + 3).unwrap_or(&[0, 0, 0]);
+                    let pixel = (y * width as usize + x) * 4;
+                    rgba[pixel..pixel + 4].copy_from_slice(&[color[0], color[1], color[2], 0xFF]);
+                }
+                return Ok((width, height, rgba));
+            }
+            _ => return Err("GIF has no image".to_string()),
+        }
+    }
+}
+
+fn read_gif_sub_blocks(reader: &mut BinaryReader) -> Result<Vec<u8>, String> {
+    let mut data = vec![];
+    loop {
+        let len = reader.read_u8().map_err(|_| "GIF data ends early".to_string())?;
+        if len == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(reader.read_bytes(len as usize).map_err(|_| "GIF data ends early".to_string())?);
+    }
+}
+
+/// The row of the frame each decoded row goes to, which for interlaced images
+/// is every 8th row, then the 4th ones in between and so on.
+fn gif_row_order(height: usize, is_interlaced: bool) -> Vec<usize> {
+    if !is_interlaced {
+        return (0..height).collect();
+    }
+    [(0, 8), (4, 8), (2, 4), (1, 2)].iter()
+        .flat_map(|(start, step)| (*start..height).step_by(*step))
+        .collect()
+}
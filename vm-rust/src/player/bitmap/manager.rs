@@ -1,20 +1,89 @@
-use std::collections::HashMap;
+use std::{cell::{Cell, OnceCell}, collections::HashMap, rc::Rc, sync::Arc};
 
-use super::bitmap::Bitmap;
+use log::warn;
+
+use crate::director::enums::BitmapInfo;
+
+use super::{bitmap::{decompress_bitmap, Bitmap, BuiltInPalette, PaletteRef}, encoded_image::decode_image, mask::BitmapMask, palette_map::PaletteMap};
 
 pub type BitmapRef = u32;
 pub const INVALID_BITMAP_REF: BitmapRef = 0;
 
+/// Bytes of decoded cast images kept before the least recently used are dropped.
+const DECODED_BITMAP_LIMIT: usize = 256 * 1024 * 1024;
+
+/// How the data of a cast image is stored.
+#[derive(Clone, Copy, PartialEq)]
+pub enum LazyBitmapFormat {
+    /// A BITD chunk.
+    Director,
+    /// A JPEG, PNG or GIF file in an ediM chunk.
+    Encoded,
+}
+
+/// A cast member image that stays compressed until it's first used, so loading
+/// a movie doesn't decode every bitmap in its casts.
+struct LazyBitmap {
+    data: Rc<[u8]>,
+    format: LazyBitmapFormat,
+    info: BitmapInfo,
+    cast_lib: u32,
+    decoded: OnceCell<Bitmap>,
+    /// When the image was last drawn or read, from the manager's use counter.
+    last_used: Cell<u64>,
+}
+
+impl LazyBitmap {
+    fn decoded_size(&self) -> usize {
+        (self.info.width as usize * self.info.height as usize * self.info.bit_depth as usize).div_ceil(8)
+    }
+
+    fn decode(&self) -> Bitmap {
+        let decoded = match self.format {
+            LazyBitmapFormat::Director => decompress_bitmap(&self.data, &self.info, self.cast_lib),
+            LazyBitmapFormat::Encoded => decode_image(&self.data, PaletteRef::from(self.info.palette_id, self.cast_lib)),
+        };
+        match decoded {
+            Ok(bitmap) => bitmap,
+            // TODO create error texture?
+            Err(err) => {
+                warn!("Could not decode bitmap: {}", err);
+                Bitmap::new(1, 1, 8, PaletteRef::BuiltIn(BuiltInPalette::GrayScale))
+            }
+        }
+    }
+}
+
 pub struct BitmapManager {
     bitmaps: HashMap<BitmapRef, Bitmap>,
+    /// Cast images that haven't been modified. Once modified, they move to `bitmaps`.
+    lazy_bitmaps: HashMap<BitmapRef, LazyBitmap>,
+    /// Bumped whenever a bitmap may have been modified, so renderers can tell when
+    /// cached output that depends on it is stale.
+    versions: HashMap<BitmapRef, u32>,
     ref_counter: BitmapRef,
+    use_counter: Cell<u64>,
+}
+
+/// What the bitmaps of the loaded movies take up, as reported to the host.
+pub struct BitmapMemoryStats {
+    pub lazy_count: usize,
+    pub compressed_bytes: usize,
+    pub decoded_count: usize,
+    pub decoded_bytes: usize,
+    /// Images created or modified by scripts, which can't be dropped.
+    pub modified_count: usize,
+    pub modified_bytes: usize,
 }
 
 impl BitmapManager {
     pub fn new() -> Self {
         Self {
             bitmaps: HashMap::new(),
+            lazy_bitmaps: HashMap::new(),
+            versions: HashMap::new(),
             ref_counter: 0,
+            use_counter: Cell::new(0),
         }
     }
 
@@ -26,17 +95,143 @@ impl BitmapManager {
         bitmap_ref
     }
 
+    /// Adds a compressed cast image, which is decoded when it's first needed.
+    pub fn add_lazy_bitmap(&mut self, data: Rc<[u8]>, format: LazyBitmapFormat, info: BitmapInfo, cast_lib: u32) -> BitmapRef {
+        self.ref_counter += 1;
+
+        let bitmap_ref = self.ref_counter;
+        let lazy = LazyBitmap { data, format, info, cast_lib, decoded: OnceCell::new(), last_used: Cell::new(0) };
+        self.lazy_bitmaps.insert(bitmap_ref, lazy);
+        bitmap_ref
+    }
+
     pub fn replace_bitmap(&mut self, bitmap_ref: BitmapRef, bitmap: Bitmap) {
+        self.lazy_bitmaps.remove(&bitmap_ref);
         self.bitmaps.insert(bitmap_ref, bitmap);
+        self.bump_version(bitmap_ref);
+    }
+
+    fn bump_version(&mut self, bitmap_ref: BitmapRef) {
+        let version = self.versions.entry(bitmap_ref).or_insert(0);
+        *version = version.wrapping_add(1);
+    }
+
+    pub fn get_bitmap_version(&self, bitmap_ref: BitmapRef) -> u32 {
+        self.versions.get(&bitmap_ref).copied().unwrap_or(0)
+    }
+
+    /// Bitmaps that were changed since they were loaded, including ones created by scripts.
+    pub fn get_modified_bitmap_refs(&self) -> Vec<BitmapRef> {
+        let mut bitmap_refs = self.versions.keys().copied().filter(|bitmap_ref| self.bitmaps.contains_key(bitmap_ref)).collect::<Vec<_>>();
+        bitmap_refs.sort();
+        bitmap_refs
+    }
+
+    /// Puts back a bitmap from a save state under its original ref.
+    pub fn restore_bitmap(&mut self, bitmap_ref: BitmapRef, bitmap: Bitmap) {
+        self.ref_counter = self.ref_counter.max(bitmap_ref);
+        self.replace_bitmap(bitmap_ref, bitmap);
     }
 
     #[allow(dead_code)]
     pub fn get_bitmap(&self, bitmap_ref: BitmapRef) -> Option<&Bitmap> {
-        self.bitmaps.get(&bitmap_ref)
+        match self.lazy_bitmaps.get(&bitmap_ref) {
+            Some(lazy) => {
+                self.use_counter.set(self.use_counter.get() + 1);
+                lazy.last_used.set(self.use_counter.get());
+                Some(lazy.decoded.get_or_init(|| lazy.decode()))
+            }
+            None => self.bitmaps.get(&bitmap_ref),
+        }
     }
 
     #[allow(dead_code)]
     pub fn get_bitmap_mut(&mut self, bitmap_ref: BitmapRef) -> Option<&mut Bitmap> {
+        if let Some(mut lazy) = self.lazy_bitmaps.remove(&bitmap_ref) {
+            let bitmap = lazy.decoded.take().unwrap_or_else(|| lazy.decode());
+            self.bitmaps.insert(bitmap_ref, bitmap);
+        }
+        self.bump_version(bitmap_ref);
         self.bitmaps.get_mut(&bitmap_ref)
     }
+
+    /// The matte of an image for matte ink, built the first time it's needed. Building it
+    /// doesn't change the image, so its version stays the same and a cast image can
+    /// still be unloaded.
+    pub fn get_or_create_matte(&mut self, bitmap_ref: BitmapRef, palettes: &PaletteMap) -> Option<Arc<BitmapMask>> {
+        if let Some(matte) = &self.get_bitmap(bitmap_ref)?.matte {
+            return Some(matte.clone());
+        }
+        let bitmap = match self.lazy_bitmaps.get_mut(&bitmap_ref) {
+            Some(lazy) => lazy.decoded.get_mut()?,
+            None => self.bitmaps.get_mut(&bitmap_ref)?,
+        };
+        bitmap.create_matte(palettes);
+        bitmap.matte.clone()
+    }
+
+    /// Whether the image is decoded. Images created or modified by scripts are always loaded.
+    pub fn is_bitmap_loaded(&self, bitmap_ref: BitmapRef) -> bool {
+        match self.lazy_bitmaps.get(&bitmap_ref) {
+            Some(lazy) => lazy.decoded.get().is_some(),
+            None => self.bitmaps.contains_key(&bitmap_ref),
+        }
+    }
+
+    pub fn preload_bitmap(&self, bitmap_ref: BitmapRef) {
+        self.get_bitmap(bitmap_ref);
+    }
+
+    /// Drops the decoded copy of a cast image. Images that were modified can't be
+    /// decoded again, so they stay loaded.
+    pub fn unload_bitmap(&mut self, bitmap_ref: BitmapRef) {
+        if let Some(lazy) = self.lazy_bitmaps.get_mut(&bitmap_ref) {
+            lazy.decoded.take();
+        }
+    }
+
+    /// Bytes the decoded image takes up, known without decoding it.
+    pub fn get_decoded_size(&self, bitmap_ref: BitmapRef) -> usize {
+        match self.lazy_bitmaps.get(&bitmap_ref) {
+            Some(lazy) => lazy.decoded_size(),
+            None => self.bitmaps.get(&bitmap_ref).map_or(0, Self::bitmap_size),
+        }
+    }
+
+    fn bitmap_size(bitmap: &Bitmap) -> usize {
+        (bitmap.width as usize * bitmap.height as usize * bitmap.bit_depth as usize).div_ceil(8)
+    }
+
+    /// Drops the decoded copies of the least recently used cast images until the
+    /// decoded ones fit in the limit. They're decoded again when next used.
+    pub fn trim_decoded_bitmaps(&mut self) {
+        let mut decoded = self.lazy_bitmaps.iter()
+            .filter(|(_, lazy)| lazy.decoded.get().is_some())
+            .map(|(bitmap_ref, lazy)| (lazy.last_used.get(), *bitmap_ref, lazy.decoded_size()))
+            .collect::<Vec<_>>();
+        let mut decoded_bytes = decoded.iter().map(|(_, _, size)| size).sum::<usize>();
+        if decoded_bytes <= DECODED_BITMAP_LIMIT {
+            return;
+        }
+        decoded.sort();
+        for (_, bitmap_ref, size) in decoded {
+            if decoded_bytes <= DECODED_BITMAP_LIMIT {
+                break;
+            }
+            self.unload_bitmap(bitmap_ref);
+            decoded_bytes -= size;
+        }
+    }
+
+    pub fn get_memory_stats(&self) -> BitmapMemoryStats {
+        let decoded = self.lazy_bitmaps.values().filter(|lazy| lazy.decoded.get().is_some());
+        BitmapMemoryStats {
+            lazy_count: self.lazy_bitmaps.len(),
+            compressed_bytes: self.lazy_bitmaps.values().map(|lazy| lazy.data.len()).sum(),
+            decoded_count: decoded.clone().count(),
+            decoded_bytes: decoded.map(|lazy| lazy.decoded_size()).sum(),
+            modified_count: self.bitmaps.len(),
+            modified_bytes: self.bitmaps.values().map(Self::bitmap_size).sum(),
+        }
+    }
 }
@@ -4,3 +4,5 @@ pub mod drawing;
 pub mod palette;
 pub mod palette_map;
 pub mod mask;
+pub mod png;
+pub mod encoded_image;
@@ -5,8 +5,9 @@ struct PaletteEntry {
     member: PaletteMember,
 }
 
+#[derive(Default)]
 pub struct PaletteMap {
-    pub palettes: Vec<PaletteEntry>,
+    palettes: Vec<PaletteEntry>,
 }
 
 impl PaletteMap {
@@ -0,0 +1,67 @@
+use std::io::Write;
+
+use flate2::{write::ZlibEncoder, Compression, Crc};
+
+use super::{bitmap::Bitmap, mask::BitmapMask, palette_map::PaletteMap};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+fn write_chunk(out: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(chunk_type);
+    out.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(chunk_type);
+    crc.update(data);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Encodes a bitmap of any depth as an 8-bit RGB PNG.
+pub fn encode_png(bitmap: &Bitmap, palettes: &PaletteMap) -> Vec<u8> {
+    let mut rows = Vec::with_capacity((bitmap.width as usize * 3 + 1) * bitmap.height as usize);
+    for y in 0..bitmap.height {
+        // Filter type None
+        rows.push(0);
+        for x in 0..bitmap.width {
+            let (r, g, b) = bitmap.get_pixel_color(palettes, x, y);
+            rows.extend_from_slice(&[r, g, b]);
+        }
+    }
+    // Truecolor
+    write_png(bitmap, 2, &rows)
+}
+
+/// Encodes a bitmap as an 8-bit RGBA PNG, transparent where the mask is clear.
+pub fn encode_png_with_mask(bitmap: &Bitmap, palettes: &PaletteMap, mask: &BitmapMask) -> Vec<u8> {
+    let mut rows = Vec::with_capacity((bitmap.width as usize * 4 + 1) * bitmap.height as usize);
+    for y in 0..bitmap.height {
+        rows.push(0);
+        for x in 0..bitmap.width {
+            let (r, g, b) = bitmap.get_pixel_color(palettes, x, y);
+            let alpha = if mask.get_bit(x, y) { (bitmap.get_pixel_alpha(x, y) * 255.0) as u8 } else { 0 };
+            rows.extend_from_slice(&[r, g, b, alpha]);
+        }
+    }
+    // Truecolor with alpha
+    write_png(bitmap, 6, &rows)
+}
+
+fn write_png(bitmap: &Bitmap, color_type: u8, rows: &[u8]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(bitmap.width as u32).to_be_bytes());
+    header.extend_from_slice(&(bitmap.height as u32).to_be_bytes());
+    // Bit depth 8, deflate, no filtering, no interlacing
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    let image_data = encoder
+        .write_all(rows)
+        .and_then(|_| encoder.finish())
+        .unwrap_or_default();
+
+    let mut out = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut out, b"IHDR", &header);
+    write_chunk(&mut out, b"IDAT", &image_data);
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
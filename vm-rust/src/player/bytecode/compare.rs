@@ -1,6 +1,6 @@
 use num::ToPrimitive;
 
-use crate::{director::lingo::datum::{datum_bool, Datum}, player::{compare::{datum_equals, datum_greater_than, datum_less_than}, reserve_player_mut, HandlerExecutionResult, HandlerExecutionResultContext, ScriptError}};
+use crate::{director::lingo::datum::{datum_bool, Datum}, player::{compare::{datum_equals, datum_greater_than, datum_less_than}, reserve_player_mut, score::{compare_sprites, sprite_within, sprites_intersect}, DirPlayer, HandlerExecutionResult, HandlerExecutionResultContext, ScriptError}};
 
 use super::handler_manager::BytecodeHandlerContext;
 
@@ -190,4 +190,40 @@ impl CompareBytecodeHandler {
       Ok(HandlerExecutionResult::Advance)
     })
   }
+
+  fn pop_sprite_numbers(player: &mut DirPlayer, ctx: &BytecodeHandlerContext) -> Result<(i16, i16), ScriptError> {
+    let (first_ref, second_ref) = {
+      let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
+      let second = scope.stack.pop().unwrap();
+      let first = scope.stack.pop().unwrap();
+      (first, second)
+    };
+    let first_num = player.get_datum(&first_ref).int_value()?;
+    let second_num = player.get_datum(&second_ref).int_value()?;
+    Ok((first_num as i16, second_num as i16))
+  }
+
+  /// `sprite a intersects b`
+  pub fn onto_spr(ctx: &BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError> {
+    reserve_player_mut(|player| {
+      let (first_num, second_num) = Self::pop_sprite_numbers(player, ctx)?;
+      let result = compare_sprites(player, first_num, second_num, sprites_intersect)?;
+      let result_id = player.alloc_datum(datum_bool(result));
+      let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
+      scope.stack.push(result_id);
+      Ok(HandlerExecutionResult::Advance)
+    })
+  }
+
+  /// `sprite a within b`
+  pub fn into_spr(ctx: &BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError> {
+    reserve_player_mut(|player| {
+      let (first_num, second_num) = Self::pop_sprite_numbers(player, ctx)?;
+      let result = compare_sprites(player, first_num, second_num, sprite_within)?;
+      let result_id = player.alloc_datum(datum_bool(result));
+      let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
+      scope.stack.push(result_id);
+      Ok(HandlerExecutionResult::Advance)
+    })
+  }
 }
@@ -1,4 +1,4 @@
-use crate::{director::lingo::datum::{Datum, DatumType}, player::{compare::datum_is_zero, handlers::datum_handlers::{player_call_datum_handler, script_instance::ScriptInstanceUtils}, player_call_script_handler_raw_args, player_ext_call, player_handle_scope_return, reserve_player_mut, reserve_player_ref, script::{get_current_handler_def, get_current_script, get_name}, HandlerExecutionResult, ScriptError, PLAYER_OPT}};
+use crate::{director::lingo::datum::{Datum, DatumType}, player::{compare::datum_is_zero, handlers::datum_handlers::{player_call_datum_handler, script_instance::ScriptInstanceUtils}, player_call_script_handler_raw_args, player_ext_call, player_handle_scope_return, reserve_player_mut, reserve_player_ref, script::{get_current_handler_def, get_current_script, get_name}, window::{begin_tell, end_tell, player_apply_window_go}, HandlerExecutionResult, ScriptError, PLAYER_OPT}};
 
 use super::handler_manager::BytecodeHandlerContext;
 
@@ -183,4 +183,30 @@ impl FlowControlBytecodeHandler {
       Ok(HandlerExecutionResult::Jump)
     })
   }
+
+  pub async fn start_tell(ctx: &BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError> {
+    let target_ref = reserve_player_mut(|player| {
+      let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
+      scope.stack.pop().unwrap()
+    });
+    player_apply_window_go().await?;
+    reserve_player_mut(|player| {
+      // Statements inside the tell run against the movie of the target until the end tell
+      let target = match player.get_datum(&target_ref) {
+        Datum::Stage => None,
+        Datum::WindowRef(name) => Some(name.to_owned()),
+        target => return Err(ScriptError::new(format!("tell is only supported for the stage and windows, got {}", target.type_str()))),
+      };
+      begin_tell(player, target)?;
+      Ok(HandlerExecutionResult::Advance)
+    })
+  }
+
+  pub async fn end_tell(_: &BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError> {
+    player_apply_window_go().await?;
+    reserve_player_mut(|player| {
+      end_tell(player)?;
+      Ok(HandlerExecutionResult::Advance)
+    })
+  }
 }
@@ -1,4 +1,4 @@
-use crate::{director::lingo::{constants::{get_anim_prop_name, get_sprite_prop_name, movie_prop_names, sprite_prop_names}, datum::{Datum, StringChunkType}}, player::{allocator::DatumAllocatorTrait, handlers::datum_handlers::string_chunk::StringChunkUtils, reserve_player_mut, score::{sprite_get_prop, sprite_set_prop}, script::{get_current_handler_def, get_current_variable_multiplier, get_name, get_obj_prop, player_set_obj_prop, script_get_prop, script_get_static_prop, script_set_prop, script_set_static_prop}, DatumRef, DirPlayer, HandlerExecutionResult, ScriptError, PLAYER_OPT}};
+use crate::{director::lingo::{constants::{get_anim_prop_name, get_sprite_prop_name, movie_prop_names, sprite_prop_names}, datum::{Datum, DatumType, StringChunkType}}, player::{actor_list::actor_list, allocator::DatumAllocatorTrait, handlers::datum_handlers::string_chunk::StringChunkUtils, reserve_player_mut, scope::ScopeRef, score::{sprite_get_prop, sprite_set_prop}, script::{get_current_handler_def, get_current_variable_multiplier, get_name, get_obj_prop, player_set_obj_prop, script_get_prop, script_get_static_prop, script_set_prop, script_set_static_prop}, search_path::search_path_list, window::window_list, DatumRef, DirPlayer, HandlerExecutionResult, ScriptError, PLAYER_OPT}};
 
 use super::handler_manager::BytecodeHandlerContext;
 
@@ -8,16 +8,57 @@ pub struct GetSetUtils { }
 impl GetSetUtils {
   pub fn get_the_built_in_prop(
       player: &mut DirPlayer,
-      ctx: &BytecodeHandlerContext,
+      scope_ref: ScopeRef,
       prop_name: &str,
   ) -> Result<DatumRef, ScriptError> {
       match prop_name {
-        "paramCount" => Ok(player.alloc_datum(Datum::Int(player.scopes.get(ctx.scope_ref).unwrap().args.len() as i32))),
+        "paramCount" => Ok(player.alloc_datum(Datum::Int(player.scopes.get(scope_ref).unwrap().args.len() as i32))),
+        // Handlers running, including the current one
+        "stackDepth" => Ok(player.alloc_datum(Datum::Int(player.scope_count as i32))),
+        "callers" => Ok(Self::get_callers(player, scope_ref)),
         "result" => Ok(player.last_handler_result.clone()),
+        "timeoutList" => {
+          let timeout_refs = player.timeout_manager.timeout_names().clone()
+            .into_iter()
+            .map(|name| player.alloc_datum(Datum::TimeoutRef(name)))
+            .collect();
+          Ok(player.alloc_datum(Datum::List(DatumType::List, timeout_refs, false)))
+        }
+        "windowList" => Ok(window_list(player)),
+        "searchPath" | "searchPaths" => Ok(search_path_list(player)),
+        "actorList" => Ok(actor_list(player)),
         _ => Ok(player.alloc_datum(player.get_movie_prop(prop_name)?))
       }
   }
 
+  /// The handlers that led to the one running in `scope_ref`, innermost first, as
+  /// property lists with the script, the handler and the arguments it got.
+  fn get_callers(player: &mut DirPlayer, scope_ref: ScopeRef) -> DatumRef {
+    let callers = player.scopes.iter()
+      .take(scope_ref)
+      .rev()
+      .filter_map(|scope| {
+        let (script_name, handler_name) = player.get_scope_names(scope)?;
+        Some((script_name.to_owned(), handler_name.to_owned(), scope.args.clone(), scope.bytecode_index))
+      })
+      .collect::<Vec<_>>();
+    let callers = callers.into_iter()
+      .map(|(script_name, handler_name, args, bytecode_index)| {
+        let props = vec![
+          ("script", Datum::String(script_name)),
+          ("handler", Datum::Symbol(handler_name)),
+          ("params", Datum::List(DatumType::List, args, false)),
+          ("bytecodeIndex", Datum::Int(bytecode_index as i32)),
+        ];
+        let props = props.into_iter()
+          .map(|(key, value)| (player.alloc_datum(Datum::Symbol(key.to_owned())), player.alloc_datum(value)))
+          .collect();
+        player.alloc_datum(Datum::PropList(props, false))
+      })
+      .collect();
+    player.alloc_datum(Datum::List(DatumType::List, callers, false))
+  }
+
   pub fn set_the_built_in_prop(
       player: &mut DirPlayer,
       _ctx: &BytecodeHandlerContext,
@@ -102,9 +143,8 @@ impl GetSetBytecodeHandler {
 
   pub fn get_movie_prop(ctx: &BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError> {
     reserve_player_mut(|player| {
-      let prop_name = get_name(&player, &ctx, player.get_ctx_current_bytecode(ctx).obj as u16).unwrap();
-      let value = player.get_movie_prop(&prop_name)?;
-      let result_id = player.alloc_datum(value);
+      let prop_name = get_name(&player, &ctx, player.get_ctx_current_bytecode(ctx).obj as u16).unwrap().to_owned();
+      let result_id = GetSetUtils::get_the_built_in_prop(player, ctx.scope_ref, &prop_name)?;
       let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
       scope.stack.push(result_id);
       Ok(HandlerExecutionResult::Advance)
@@ -168,8 +208,8 @@ impl GetSetBytecodeHandler {
     reserve_player_mut(|player| {
       let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
       let value_ref = scope.stack.pop().unwrap();
-      let prop_name = get_name(&player, &ctx, player.get_ctx_current_bytecode(ctx).obj as u16).unwrap();
-      player.globals.insert(prop_name.to_owned(), value_ref);
+      let prop_name = get_name(&player, &ctx, player.get_ctx_current_bytecode(ctx).obj as u16).unwrap().to_owned();
+      player.set_global(&prop_name, value_ref);
       Ok(HandlerExecutionResult::Advance)
     })
   }
@@ -283,7 +323,7 @@ impl GetSetBytecodeHandler {
   pub fn the_built_in(ctx: &BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError> {
     reserve_player_mut(|player| {
       let prop_name = get_name(&player, &ctx, player.get_ctx_current_bytecode(ctx).obj as u16).unwrap();
-      let result_id = GetSetUtils::get_the_built_in_prop(player, ctx, &prop_name.clone())?;
+      let result_id = GetSetUtils::get_the_built_in_prop(player, ctx.scope_ref, &prop_name.clone())?;
 
       let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
       scope.stack.pop(); // empty arglist
@@ -319,7 +359,7 @@ impl GetSetBytecodeHandler {
       let result = if prop_type == 0 && prop_id <= max_movie_prop_id as i32 {
         // movie prop
         let prop_name = movie_prop_names().get(&(prop_id as u16)).unwrap();
-        GetSetUtils::get_the_built_in_prop(player, ctx, prop_name)
+        GetSetUtils::get_the_built_in_prop(player, ctx.scope_ref, prop_name)
       } else if prop_type == 0 {
         // last chunk
         let string_id = {
@@ -1,12 +1,14 @@
+use std::{collections::{BTreeMap, HashSet}, rc::Rc, sync::OnceLock};
+
 use async_recursion::async_recursion;
 
 use crate::{
-    director::{chunks::handler::HandlerDef, lingo::{constants::get_opcode_name, opcode::OpCode}},
+    director::{chunks::handler::{Bytecode, HandlerDef}, lingo::{constants::get_opcode_name, opcode::OpCode}},
     player::{
         bytecode::{
             arithmetics::ArithmeticsBytecodeHandler, flow_control::FlowControlBytecodeHandler,
             stack::StackBytecodeHandler,
-        }, scope::ScopeRef, script::Script, HandlerExecutionResult, ScriptError, PLAYER_OPT
+        }, cast_manager::CastManager, handlers::manager::BuiltInHandlerManager, scope::{Scope, ScopeRef}, script::Script, HandlerExecutionResult, ScriptError, PLAYER_OPT
     },
 };
 
@@ -15,73 +17,118 @@ use super::{compare::CompareBytecodeHandler, get_set::GetSetBytecodeHandler, str
 #[derive(Clone)]
 pub struct BytecodeHandlerContext {
     pub scope_ref: ScopeRef,
-    pub handler_def_ptr: *const HandlerDef,
-    pub script_ptr: *const Script,
+    pub handler_def: Rc<HandlerDef>,
+    pub script: Rc<Script>,
+}
+
+type SyncBytecodeHandler = fn(&BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError>;
+
+const OPCODE_TABLE_SIZE: usize = 0x80;
+/// Opcodes run in one synchronous batch before the caller gets control back.
+const SYNC_BATCH_LIMIT: usize = 10000;
+
+pub enum BytecodeBatchResult {
+    /// The next opcode awaits (calls, object creation) and must go through `player_execute_bytecode`.
+    Yield,
+    Stop,
+    /// Many opcodes ran without the handler stopping, which gives the watchdog a chance
+    /// to check on it.
+    Limit,
 }
+
 pub struct StaticBytecodeHandlerManager {}
 impl StaticBytecodeHandlerManager {
+    fn get_sync_handler(opcode: OpCode) -> Option<SyncBytecodeHandler> {
+        let handler: SyncBytecodeHandler = match opcode {
+            OpCode::Add => ArithmeticsBytecodeHandler::add,
+            OpCode::PushInt8 => StackBytecodeHandler::push_int,
+            OpCode::PushInt16 => StackBytecodeHandler::push_int,
+            OpCode::PushInt32 => StackBytecodeHandler::push_int,
+            OpCode::PushArgList => StackBytecodeHandler::push_arglist,
+            OpCode::PushArgListNoRet => StackBytecodeHandler::push_arglist_no_ret,
+            OpCode::PushSymb => StackBytecodeHandler::push_symb,
+            OpCode::Swap => StackBytecodeHandler::swap,
+            OpCode::GetProp => GetSetBytecodeHandler::get_prop,
+            OpCode::GetObjProp => GetSetBytecodeHandler::get_obj_prop,
+            OpCode::GetMovieProp => GetSetBytecodeHandler::get_movie_prop,
+            OpCode::Set => GetSetBytecodeHandler::set,
+            OpCode::Ret => FlowControlBytecodeHandler::ret,
+            OpCode::JmpIfZ => FlowControlBytecodeHandler::jmp_if_zero,
+            OpCode::Jmp => FlowControlBytecodeHandler::jmp,
+            OpCode::GetGlobal => GetSetBytecodeHandler::get_global,
+            OpCode::SetGlobal => GetSetBytecodeHandler::set_global,
+            OpCode::PushCons => StackBytecodeHandler::push_cons,
+            OpCode::PushZero => StackBytecodeHandler::push_zero,
+            OpCode::GetField => GetSetBytecodeHandler::get_field,
+            OpCode::GetLocal => GetSetBytecodeHandler::get_local,
+            OpCode::SetLocal => GetSetBytecodeHandler::set_local,
+            OpCode::GetParam => GetSetBytecodeHandler::get_param,
+            OpCode::SetMovieProp => GetSetBytecodeHandler::set_movie_prop,
+            OpCode::PushPropList => StackBytecodeHandler::push_prop_list,
+            OpCode::Gt => CompareBytecodeHandler::gt,
+            OpCode::Lt => CompareBytecodeHandler::lt,
+            OpCode::GtEq => CompareBytecodeHandler::gt_eq,
+            OpCode::LtEq => CompareBytecodeHandler::lt_eq,
+            OpCode::Sub => ArithmeticsBytecodeHandler::sub,
+            OpCode::EndRepeat => FlowControlBytecodeHandler::end_repeat,
+            OpCode::SetProp => GetSetBytecodeHandler::set_prop,
+            OpCode::PushList => StackBytecodeHandler::push_list,
+            OpCode::Not => CompareBytecodeHandler::not,
+            OpCode::NtEq => CompareBytecodeHandler::nt_eq,
+            OpCode::TheBuiltin => GetSetBytecodeHandler::the_built_in,
+            OpCode::Peek => StackBytecodeHandler::peek,
+            OpCode::Pop => StackBytecodeHandler::pop,
+            OpCode::And => CompareBytecodeHandler::and,
+            OpCode::Eq => CompareBytecodeHandler::eq,
+            OpCode::SetParam => GetSetBytecodeHandler::set_param,
+            OpCode::GetChainedProp => GetSetBytecodeHandler::get_chained_prop,
+            OpCode::ContainsStr => StringBytecodeHandler::contains_str,
+            OpCode::Contains0Str => StringBytecodeHandler::contains_0str,
+            OpCode::JoinPadStr => StringBytecodeHandler::join_pad_str,
+            OpCode::JoinStr => StringBytecodeHandler::join_str,
+            OpCode::Get => GetSetBytecodeHandler::get,
+            OpCode::Mod => ArithmeticsBytecodeHandler::mod_handler,
+            OpCode::GetChunk => StringBytecodeHandler::get_chunk,
+            OpCode::Put => StringBytecodeHandler::put,
+            OpCode::Or => CompareBytecodeHandler::or,
+            OpCode::Inv => ArithmeticsBytecodeHandler::inv,
+            OpCode::Div => ArithmeticsBytecodeHandler::div,
+            OpCode::PushFloat32 => StackBytecodeHandler::push_f32,
+            OpCode::Mul => ArithmeticsBytecodeHandler::mul,
+            OpCode::PushChunkVarRef => StackBytecodeHandler::push_chunk_var_ref,
+            OpCode::DeleteChunk => StringBytecodeHandler::delete_chunk,
+            OpCode::GetTopLevelProp => GetSetBytecodeHandler::get_top_level_prop,
+            OpCode::GetGlobal2 => GetSetBytecodeHandler::get_global,
+            OpCode::SetGlobal2 => GetSetBytecodeHandler::set_global,
+            OpCode::RetFactory => FlowControlBytecodeHandler::ret,
+            OpCode::PutChunk => StringBytecodeHandler::put_chunk,
+            OpCode::HiliteChunk => StringBytecodeHandler::hilite_chunk,
+            OpCode::OntoSpr => CompareBytecodeHandler::onto_spr,
+            OpCode::IntoSpr => CompareBytecodeHandler::into_spr,
+            _ => return None,
+        };
+        Some(handler)
+    }
+
+    fn sync_handler_table() -> &'static [Option<SyncBytecodeHandler>; OPCODE_TABLE_SIZE] {
+        static TABLE: OnceLock<[Option<SyncBytecodeHandler>; OPCODE_TABLE_SIZE]> = OnceLock::new();
+        TABLE.get_or_init(|| {
+            let mut table: [Option<SyncBytecodeHandler>; OPCODE_TABLE_SIZE] = [None; OPCODE_TABLE_SIZE];
+            for (value, entry) in table.iter_mut().enumerate() {
+                if let Some(opcode) = num::FromPrimitive::from_usize(value) {
+                    *entry = Self::get_sync_handler(opcode);
+                }
+            }
+            table
+        })
+    }
+
     #[inline(always)]
     pub fn call_sync_handler(opcode: OpCode, ctx: &BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError> {
-        match opcode {
-            OpCode::Add => ArithmeticsBytecodeHandler::add(ctx),
-            OpCode::PushInt8 => StackBytecodeHandler::push_int(ctx),
-            OpCode::PushInt16 => StackBytecodeHandler::push_int(ctx),
-            OpCode::PushInt32 => StackBytecodeHandler::push_int(ctx),
-            OpCode::PushArgList => StackBytecodeHandler::push_arglist(ctx),
-            OpCode::PushArgListNoRet => StackBytecodeHandler::push_arglist_no_ret(ctx),
-            OpCode::PushSymb => StackBytecodeHandler::push_symb(ctx),
-            OpCode::Swap => StackBytecodeHandler::swap(ctx),
-            OpCode::GetProp => GetSetBytecodeHandler::get_prop(ctx),
-            OpCode::GetObjProp => GetSetBytecodeHandler::get_obj_prop(ctx),
-            OpCode::GetMovieProp => GetSetBytecodeHandler::get_movie_prop(ctx),
-            OpCode::Set => GetSetBytecodeHandler::set(ctx),
-            OpCode::Ret => FlowControlBytecodeHandler::ret(ctx),
-            OpCode::JmpIfZ => FlowControlBytecodeHandler::jmp_if_zero(ctx),
-            OpCode::Jmp => FlowControlBytecodeHandler::jmp(ctx),
-            OpCode::GetGlobal => GetSetBytecodeHandler::get_global(ctx),
-            OpCode::SetGlobal => GetSetBytecodeHandler::set_global(ctx),
-            OpCode::PushCons => StackBytecodeHandler::push_cons(ctx),
-            OpCode::PushZero => StackBytecodeHandler::push_zero(ctx),
-            OpCode::GetField => GetSetBytecodeHandler::get_field(ctx),
-            OpCode::GetLocal => GetSetBytecodeHandler::get_local(ctx),
-            OpCode::SetLocal => GetSetBytecodeHandler::set_local(ctx),
-            OpCode::GetParam => GetSetBytecodeHandler::get_param(ctx),
-            OpCode::SetMovieProp => GetSetBytecodeHandler::set_movie_prop(ctx),
-            OpCode::PushPropList => StackBytecodeHandler::push_prop_list(ctx),
-            OpCode::Gt => CompareBytecodeHandler::gt(ctx),
-            OpCode::Lt => CompareBytecodeHandler::lt(ctx),
-            OpCode::GtEq => CompareBytecodeHandler::gt_eq(ctx),
-            OpCode::LtEq => CompareBytecodeHandler::lt_eq(ctx),
-            OpCode::Sub => ArithmeticsBytecodeHandler::sub(ctx),
-            OpCode::EndRepeat => FlowControlBytecodeHandler::end_repeat(ctx),
-            OpCode::SetProp => GetSetBytecodeHandler::set_prop(ctx),
-            OpCode::PushList => StackBytecodeHandler::push_list(ctx),
-            OpCode::Not => CompareBytecodeHandler::not(ctx),
-            OpCode::NtEq => CompareBytecodeHandler::nt_eq(ctx),
-            OpCode::TheBuiltin => GetSetBytecodeHandler::the_built_in(ctx),
-            OpCode::Peek => StackBytecodeHandler::peek(ctx),
-            OpCode::Pop => StackBytecodeHandler::pop(ctx),
-            OpCode::And => CompareBytecodeHandler::and(ctx),
-            OpCode::Eq => CompareBytecodeHandler::eq(ctx),
-            OpCode::SetParam => GetSetBytecodeHandler::set_param(ctx),
-            OpCode::GetChainedProp => GetSetBytecodeHandler::get_chained_prop(ctx),
-            OpCode::ContainsStr => StringBytecodeHandler::contains_str(ctx),
-            OpCode::Contains0Str => StringBytecodeHandler::contains_0str(ctx),
-            OpCode::JoinPadStr => StringBytecodeHandler::join_pad_str(ctx),
-            OpCode::JoinStr => StringBytecodeHandler::join_str(ctx),
-            OpCode::Get => GetSetBytecodeHandler::get(ctx),
-            OpCode::Mod => ArithmeticsBytecodeHandler::mod_handler(ctx),
-            OpCode::GetChunk => StringBytecodeHandler::get_chunk(ctx),
-            OpCode::Put => StringBytecodeHandler::put(ctx),
-            OpCode::Or => CompareBytecodeHandler::or(ctx),
-            OpCode::Inv => ArithmeticsBytecodeHandler::inv(ctx),
-            OpCode::Div => ArithmeticsBytecodeHandler::div(ctx),
-            OpCode::PushFloat32 => StackBytecodeHandler::push_f32(ctx),
-            OpCode::Mul => ArithmeticsBytecodeHandler::mul(ctx),
-            OpCode::PushChunkVarRef => StackBytecodeHandler::push_chunk_var_ref(ctx),
-            OpCode::DeleteChunk => StringBytecodeHandler::delete_chunk(ctx),
-            OpCode::GetTopLevelProp => GetSetBytecodeHandler::get_top_level_prop(ctx),
-            _ => {
+        let table = Self::sync_handler_table();
+        match table.get(opcode as usize).copied().flatten() {
+            Some(handler) => handler(ctx),
+            None => {
                 let prim = num::ToPrimitive::to_u16(&opcode).unwrap();
                 let name = get_opcode_name(opcode);
                 let fmt = format!("No handler for opcode {name} ({prim:#04x})");
@@ -90,14 +137,21 @@ impl StaticBytecodeHandlerManager {
         }
     }
 
+    pub fn is_opcode_supported(opcode: &OpCode) -> bool {
+        Self::has_async_handler(opcode) || Self::sync_handler_table()[*opcode as usize].is_some()
+    }
+
     #[inline(always)]
     pub fn has_async_handler(opcode: &OpCode) -> bool {
         match opcode {
             OpCode::NewObj => true,
             OpCode::ExtCall => true,
+            OpCode::TellCall => true,
             OpCode::ObjCall => true,
             OpCode::LocalCall => true,
             OpCode::SetObjProp => true,
+            OpCode::StartTell => true,
+            OpCode::EndTell => true,
             _ => false,
         }
     }
@@ -107,9 +161,13 @@ impl StaticBytecodeHandlerManager {
         match opcode {
             OpCode::NewObj => StackBytecodeHandler::new_obj(&ctx).await,
             OpCode::ExtCall => FlowControlBytecodeHandler::ext_call(&ctx).await,
+            // Inside a tell block, which only targets the stage, calls resolve like regular ones
+            OpCode::TellCall => FlowControlBytecodeHandler::ext_call(ctx).await,
             OpCode::ObjCall => FlowControlBytecodeHandler::obj_call(&ctx).await,
             OpCode::LocalCall => FlowControlBytecodeHandler::local_call(&ctx).await,
             OpCode::SetObjProp => GetSetBytecodeHandler::set_obj_prop(&ctx).await,
+            OpCode::StartTell => FlowControlBytecodeHandler::start_tell(ctx).await,
+            OpCode::EndTell => FlowControlBytecodeHandler::end_tell(ctx).await,
             _ => {
                 let prim = num::ToPrimitive::to_u16(&opcode).unwrap();
                 let name = get_opcode_name(opcode);
@@ -120,6 +178,86 @@ impl StaticBytecodeHandlerManager {
     }
 }
 
+pub struct UnsupportedOpcodeUsage {
+    pub opcode: OpCode,
+    pub count: usize,
+    pub first_location: String,
+}
+
+/// Scans every loaded script for opcodes the VM has no handler for, so that a movie
+/// relying on them can be diagnosed up front instead of failing mid-game.
+pub fn find_unsupported_opcodes(cast_manager: &CastManager) -> Vec<UnsupportedOpcodeUsage> {
+    let mut usages: BTreeMap<u16, UnsupportedOpcodeUsage> = BTreeMap::new();
+    for cast in &cast_manager.casts {
+        for script in cast.scripts.values() {
+            for handler_name in &script.handler_names {
+                let handler = match script.get_own_handler(handler_name) {
+                    Some(handler) => handler,
+                    None => continue,
+                };
+                for bytecode in &handler.bytecode_array {
+                    if StaticBytecodeHandlerManager::is_opcode_supported(&bytecode.opcode) {
+                        continue;
+                    }
+                    let usage = usages.entry(bytecode.opcode as u16).or_insert_with(|| UnsupportedOpcodeUsage {
+                        opcode: bytecode.opcode,
+                        count: 0,
+                        first_location: format!("{}:{} {}", script.name, handler_name, Bytecode::pos_to_str(bytecode.pos)),
+                    });
+                    usage.count += 1;
+                }
+            }
+        }
+    }
+    usages.into_values().collect()
+}
+
+pub struct MissingHandlerUsage {
+    pub name: String,
+    pub count: usize,
+    pub first_location: String,
+}
+
+/// Scans every loaded script for calls to global handlers that neither a script nor the
+/// player defines, which would otherwise only fail once the call is reached.
+pub fn find_missing_handlers(cast_manager: &CastManager) -> Vec<MissingHandlerUsage> {
+    let script_handler_names: HashSet<String> = cast_manager.casts.iter()
+        .flat_map(|cast| cast.scripts.values())
+        .flat_map(|script| script.handler_names.iter().map(|name| name.to_lowercase()))
+        .collect();
+    let mut usages: BTreeMap<String, MissingHandlerUsage> = BTreeMap::new();
+    for cast in &cast_manager.casts {
+        for script in cast.scripts.values() {
+            let names = match script.lctx.as_deref().or(cast.lctx.as_ref()) {
+                Some(lctx) => &lctx.names,
+                None => continue,
+            };
+            for handler_name in &script.handler_names {
+                let handler = match script.get_own_handler(handler_name) {
+                    Some(handler) => handler,
+                    None => continue,
+                };
+                for bytecode in handler.bytecode_array.iter().filter(|bytecode| bytecode.opcode == OpCode::ExtCall) {
+                    let name = match names.get(bytecode.obj as usize) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    if name == "return" || script_handler_names.contains(&name.to_lowercase()) || BuiltInHandlerManager::has_handler(name) {
+                        continue;
+                    }
+                    let usage = usages.entry(name.to_owned()).or_insert_with(|| MissingHandlerUsage {
+                        name: name.to_owned(),
+                        count: 0,
+                        first_location: format!("{}:{} {}", script.name, handler_name, Bytecode::pos_to_str(bytecode.pos)),
+                    });
+                    usage.count += 1;
+                }
+            }
+        }
+    }
+    usages.into_values().collect()
+}
+
 #[async_recursion(?Send)]
 #[inline(always)]
 pub async fn player_execute_bytecode<'a>(
@@ -129,8 +267,7 @@ pub async fn player_execute_bytecode<'a>(
         let player = unsafe { PLAYER_OPT.as_ref().unwrap() };
         let scope = player.scopes.get(ctx.scope_ref).unwrap();
 
-        let handler = unsafe { &*ctx.handler_def_ptr };
-        let bytecode = &handler.bytecode_array[scope.bytecode_index];
+        let bytecode = &ctx.handler_def.bytecode_array[scope.bytecode_index];
 
         bytecode.opcode
     };
@@ -141,3 +278,27 @@ pub async fn player_execute_bytecode<'a>(
         StaticBytecodeHandlerManager::call_sync_handler(opcode, ctx)
     }
 }
+
+/// Runs consecutive opcodes that don't need to await in a tight loop, without going
+/// through the async machinery for each of them. Returns when the handler stops or when
+/// the next opcode has to be awaited.
+pub fn player_execute_sync_bytecodes(ctx: &BytecodeHandlerContext) -> Result<BytecodeBatchResult, ScriptError> {
+    // Synchronous opcodes never push new scopes, so the scope list isn't reallocated
+    // and the scope stays at the same address for the whole batch.
+    let scope: *mut Scope = unsafe { PLAYER_OPT.as_mut().unwrap().scopes.get_mut(ctx.scope_ref).unwrap() };
+    let handler = &ctx.handler_def;
+    for _ in 0..SYNC_BATCH_LIMIT {
+        let bytecode_index = unsafe { (*scope).bytecode_index };
+        let opcode = handler.bytecode_array[bytecode_index].opcode;
+        if StaticBytecodeHandlerManager::has_async_handler(&opcode) {
+            return Ok(BytecodeBatchResult::Yield);
+        }
+        match StaticBytecodeHandlerManager::call_sync_handler(opcode, ctx)? {
+            HandlerExecutionResult::Advance => unsafe { (*scope).bytecode_index += 1 },
+            HandlerExecutionResult::Stop => return Ok(BytecodeBatchResult::Stop),
+            HandlerExecutionResult::Error(err) => return Err(err),
+            HandlerExecutionResult::Jump => {}
+        }
+    }
+    Ok(BytecodeBatchResult::Limit)
+}
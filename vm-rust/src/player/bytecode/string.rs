@@ -1,4 +1,4 @@
-use crate::{director::{chunks::handler::Bytecode, lingo::datum::{datum_bool, Datum, StringChunkExpr, StringChunkSource, StringChunkType}}, player::{context_vars::{player_get_context_var, player_set_context_var, read_context_var_args}, datum_formatting::format_concrete_datum, handlers::datum_handlers::string_chunk::StringChunkUtils, reserve_player_mut, DirPlayer, HandlerExecutionResult, HandlerExecutionResultContext, ScriptError}};
+use crate::{director::{chunks::handler::Bytecode, lingo::datum::{datum_bool, Datum, StringChunkExpr, StringChunkSource, StringChunkType}}, player::{compare::{string_contains, string_starts}, context_vars::{player_get_context_var, player_set_context_var, read_context_var_args}, datum_formatting::{float_to_string, format_concrete_datum}, handlers::datum_handlers::string_chunk::StringChunkUtils, reserve_player_mut, DirPlayer, HandlerExecutionResult, HandlerExecutionResultContext, ScriptError}};
 
 use super::handler_manager::BytecodeHandlerContext;
 
@@ -22,12 +22,12 @@ impl From<u8> for PutType {
 pub struct StringBytecodeHandler { }
 
 impl StringBytecodeHandler {
-  fn get_datum_concat_value(datum: &Datum, player: &DirPlayer) -> Result<String, ScriptError> {
+  pub fn get_datum_concat_value(datum: &Datum, player: &DirPlayer) -> Result<String, ScriptError> {
     match datum {
       Datum::String(s) => Ok(s.clone()),
       Datum::StringChunk(..) => datum.string_value(),
       Datum::Int(i) => Ok(i.to_string()),
-      Datum::Float(f) => Ok(f.to_string()), // TODO how to format this?
+      Datum::Float(f) => Ok(float_to_string(*f, player)),
       Datum::Symbol(s) => Ok(s.to_string()),
       Datum::Void => Ok("".to_string()),
       _ => Ok(format_concrete_datum(datum, &player)),
@@ -52,7 +52,7 @@ impl StringBytecodeHandler {
           let item = player.get_datum(item);
           if item.is_string() {
             let item = item.string_value()?;
-            if item.contains(search_str.as_str()) {
+            if string_contains(&item, &search_str) {
               contains = true;
               break;
             }
@@ -61,7 +61,7 @@ impl StringBytecodeHandler {
         Ok(contains)
       } else if search_in.is_string() {
         let search_in = search_in.string_value()?;
-        Ok(search_in.contains(search_str.as_str()))
+        Ok(string_contains(&search_in, &search_str))
       } else if search_in.is_symbol() {
         Ok(false)
       } else if search_in.is_number() {
@@ -274,7 +274,7 @@ impl StringBytecodeHandler {
       } else {
         let search_str = player.get_datum(&search_str_ref).string_value()?;
         let search_in = search_in.string_value()?;
-        search_in.starts_with(search_str.as_str())
+        string_starts(&search_in, &search_str)
       };
       let result = player.alloc_datum(datum_bool(result));
       let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
@@ -282,4 +282,53 @@ impl StringBytecodeHandler {
       Ok(HandlerExecutionResult::Advance)
     })
   }
+
+  pub fn put_chunk(ctx: &BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError> {
+    reserve_player_mut(|player| {
+      let bytecode = player.get_ctx_current_bytecode(ctx);
+      let put_type = PutType::from(((bytecode.obj >> 4) & 0xF) as u8);
+      let var_type = (bytecode.obj & 0xF) as u32;
+      let (id_ref, cast_id_ref) = read_context_var_args(player, var_type, ctx.scope_ref);
+      let string_ref = player_get_context_var(player, &id_ref, cast_id_ref.as_ref(), var_type, ctx)?;
+      let chunk_expr = Self::read_chunk_ref(player, ctx)?;
+      let value_ref = {
+        let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
+        scope.stack.pop().unwrap()
+      };
+      let value = Self::get_datum_concat_value(player.get_datum(&value_ref), player)?;
+      let new_chunk = match put_type {
+        PutType::Into => value,
+        PutType::Before | PutType::After => {
+          let string = player.get_datum(&string_ref).string_value()?;
+          let chunk = StringChunkUtils::resolve_chunk_expr_string(&string, &chunk_expr)?;
+          if let PutType::Before = put_type {
+            value + &chunk
+          } else {
+            chunk + &value
+          }
+        }
+      };
+      StringChunkUtils::set_contents(player, &StringChunkSource::Datum(string_ref), &chunk_expr, new_chunk)?;
+      Ok(HandlerExecutionResult::Advance)
+    })
+  }
+
+  pub fn hilite_chunk(ctx: &BytecodeHandlerContext) -> Result<HandlerExecutionResult, ScriptError> {
+    reserve_player_mut(|player| {
+      {
+        // The field being hilited, which is always the one being edited
+        let scope = player.scopes.get_mut(ctx.scope_ref).unwrap();
+        if player.movie.dir_version >= 500 {
+          scope.stack.pop();
+        }
+        scope.stack.pop();
+      }
+      let chunk_expr = Self::read_chunk_ref(player, ctx)?;
+      if let StringChunkType::Char = chunk_expr.chunk_type {
+        player.text_selection_start = (chunk_expr.start - 1).max(0) as u16;
+        player.text_selection_end = chunk_expr.end.max(chunk_expr.start) as u16;
+      }
+      Ok(HandlerExecutionResult::Advance)
+    })
+  }
 }
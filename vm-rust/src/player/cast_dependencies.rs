@@ -0,0 +1,87 @@
+use fxhash::{FxHashMap, FxHashSet};
+
+use crate::director::lingo::datum::Datum;
+
+use super::{cast_lib::CastMemberRef, cast_manager::CastManager, cast_member::CastMemberType};
+
+/// Records which cast members other members need in order to render or run.
+/// Film loops depend on every member placed in their score, and scripts depend on
+/// the members they refer to by name through string literals.
+pub struct CastDependencyGraph {
+  dependencies: FxHashMap<CastMemberRef, Vec<CastMemberRef>>,
+  dependents: FxHashMap<CastMemberRef, Vec<CastMemberRef>>,
+}
+
+impl CastDependencyGraph {
+  pub fn build(cast_manager: &CastManager) -> CastDependencyGraph {
+    let mut graph = CastDependencyGraph {
+      dependencies: FxHashMap::default(),
+      dependents: FxHashMap::default(),
+    };
+    for cast in &cast_manager.casts {
+      for member in cast.members.values() {
+        let member_ref = CastMemberRef { cast_lib: cast.number as i32, cast_member: member.number as i32 };
+        if let CastMemberType::FilmLoop(film_loop) = &member.member_type {
+          for (_, _, channel_data) in &film_loop.score.frame_data.frame_channel_data {
+            if channel_data.cast_member == 0 {
+              continue;
+            }
+            let cast_lib = if channel_data.cast_lib == 0 { cast.number as i32 } else { channel_data.cast_lib as i32 };
+            graph.add_dependency(&member_ref, CastMemberRef { cast_lib, cast_member: channel_data.cast_member as i32 });
+          }
+        }
+      }
+      for (member_number, script) in &cast.scripts {
+        let member_ref = CastMemberRef { cast_lib: cast.number as i32, cast_member: *member_number as i32 };
+        for literal in &script.chunk.literals {
+          if let Datum::String(name) = literal {
+            if let Some(dependency) = cast_manager.find_member_ref_by_name(name) {
+              graph.add_dependency(&member_ref, dependency);
+            }
+          }
+        }
+      }
+    }
+    graph
+  }
+
+  fn add_dependency(&mut self, member_ref: &CastMemberRef, dependency: CastMemberRef) {
+    if *member_ref == dependency {
+      return;
+    }
+    let dependencies = self.dependencies.entry(member_ref.clone()).or_default();
+    if dependencies.contains(&dependency) {
+      return;
+    }
+    dependencies.push(dependency.clone());
+    self.dependents.entry(dependency).or_default().push(member_ref.clone());
+  }
+
+  #[allow(dead_code)]
+  pub fn get_dependencies(&self, member_ref: &CastMemberRef) -> &[CastMemberRef] {
+    self.dependencies.get(member_ref).map_or(&[], |x| x.as_slice())
+  }
+
+  pub fn get_dependents(&self, member_ref: &CastMemberRef) -> &[CastMemberRef] {
+    self.dependents.get(member_ref).map_or(&[], |x| x.as_slice())
+  }
+
+  /// Returns true if a member that is in use needs `member_ref`, either directly or
+  /// through a chain of dependencies (e.g. a film loop nested in another film loop).
+  pub fn is_required(&self, member_ref: &CastMemberRef, is_in_use: impl Fn(&CastMemberRef) -> bool) -> bool {
+    let mut visited = FxHashSet::default();
+    let mut pending = vec![member_ref.clone()];
+    while let Some(current) = pending.pop() {
+      for dependent in self.get_dependents(&current) {
+        if !visited.insert(dependent.clone()) {
+          continue;
+        }
+        if is_in_use(dependent) {
+          return true;
+        }
+        pending.push(dependent.clone());
+      }
+    }
+    false
+  }
+}
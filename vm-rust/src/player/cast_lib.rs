@@ -1,11 +1,11 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{collections::HashMap, rc::Rc};
 
 use fxhash::FxHashMap;
 use url::Url;
 
-use crate::{director::{cast::CastDef, file::{read_director_file_bytes, DirectorFile}, lingo::{datum::Datum, script::ScriptContext}}, js_api::{self, JsApi}, utils::{get_base_url, get_basename_no_extension, log_i}};
+use crate::{director::{cast::CastDef, file::{read_director_file_bytes, DirectorFile}, lingo::{datum::Datum, script::ScriptContext}}, io::text_encoding::TextEncoding, js_api::{self, JsApi}, utils::{get_base_url, get_basename_no_extension, log_i}};
 
-use super::{allocator::DatumAllocator, bitmap::{bitmap::{Bitmap, BuiltInPalette, PaletteRef}, manager::BitmapManager}, cast_member::{BitmapMember, CastMember, CastMemberType, FieldMember, PaletteMember, TextMember}, datum_ref::DatumRef, handlers::datum_handlers::cast_member_ref::CastMemberRefHandlers, net_manager::NetManager, net_task::NetResult, reserve_player_mut, script::Script, ScriptError, PLAYER_OPT};
+use super::{allocator::DatumAllocator, bitmap::{bitmap::{Bitmap, BuiltInPalette, PaletteRef}, manager::BitmapManager}, cast_member::{BitmapMember, CastMember, CastMemberType, FieldMember, PaletteMember, TextMember}, handlers::datum_handlers::cast_member_ref::CastMemberRefHandlers, net_manager::NetManager, net_task::NetResult, reserve_player_mut, script::Script, search_path::sync_search_paths, ScriptError, PLAYER_OPT};
 
 #[repr(u8)]
 #[derive(PartialEq)]
@@ -27,6 +27,8 @@ pub struct CastLib {
   pub preload_mode: u8,
   pub capital_x: bool,
   pub dir_version: u16,
+  /// The encoding of the movie, which external casts are read with too.
+  pub text_encoding: TextEncoding,
 }
 
 impl CastLib {
@@ -70,9 +72,9 @@ impl CastLib {
       if !net_manager.is_task_done(Some(task_id)) {
         net_manager.await_task(task_id).await;
       }
-      let task = net_manager.get_task(task_id).unwrap();
+      let task_url = net_manager.get_task_url(task_id).unwrap();
       let result = net_manager.get_task_result(Some(task_id)).unwrap();
-      self.on_cast_preload_result(&result, &task.resolved_url, bitmap_manager, dir_cache);
+      self.on_cast_preload_result(&result, &task_url, bitmap_manager, dir_cache);
     }
   }
 
@@ -85,7 +87,7 @@ impl CastLib {
   ) {
     let load_file_name = resolved_url.as_str();
     if let Ok(cast_bytes) = result {
-      let cast_file = read_director_file_bytes(cast_bytes, &resolved_url.to_string(), &get_base_url(resolved_url).to_string());
+      let cast_file = read_director_file_bytes(cast_bytes, &resolved_url.to_string(), &get_base_url(resolved_url).to_string(), Some(self.text_encoding));
       if let Ok(cast_file) = cast_file {
         dir_cache.insert(load_file_name.into(), cast_file);
         let cast_file = dir_cache.get(load_file_name).unwrap();
@@ -127,6 +129,9 @@ impl CastLib {
     self.scripts.clear();
     self.lctx = None;
     self.state = CastLibState::None;
+    reserve_player_mut(|player| {
+      player.movie.cast_manager.invalidate_palette_cache();
+    });
 
     JsApi::dispatch_cast_member_list_changed(self.number);
   }
@@ -149,6 +154,10 @@ impl CastLib {
       },
       "fileName" => {
         self.file_name = value.string_value()?;
+        // An external cast without a file is unloaded
+        if self.is_external && self.file_name.is_empty() {
+          self.clear();
+        }
       },
       _ => {
         return Err(ScriptError::new(format!("Cannot set castLib property {}", prop)));
@@ -203,42 +212,31 @@ impl CastLib {
       JsApi::on_cast_member_name_changed(CastMemberRefHandlers::get_cast_slot_number(self.number, *id));
     }
     JsApi::dispatch_cast_member_list_changed(self.number);
-    unsafe { PLAYER_OPT.as_mut().unwrap().movie.cast_manager.clear_movie_script_cache() };
+    let cast_manager = unsafe { &mut PLAYER_OPT.as_mut().unwrap().movie.cast_manager };
+    cast_manager.clear_movie_script_cache();
+    cast_manager.invalidate_dependency_graph();
   }
 
   pub fn insert_member(&mut self, number: u32, member: CastMember) {
     if let CastMemberType::Script(script_member) = &member.member_type {
-      let script_def = self.lctx.as_ref().unwrap().scripts.get(&script_member.script_id).unwrap();
-
-      let mut handler_names = Vec::new();
-      let mut handler_name_map = FxHashMap::default();
-      for handler in &script_def.handlers {
-        let handler_name = &self.lctx.as_ref().unwrap().names[handler.name_id as usize];
-        handler_name_map.insert(handler_name.to_lowercase(), Rc::new(handler.clone()));
-        handler_names.push(handler_name.to_owned());
-      }
-
-      let property_names = script_def.property_name_ids.iter().map(|id| self.lctx.as_ref().unwrap().names[*id as usize].to_owned());
-      let mut properties = FxHashMap::default();
-      for name in property_names {
-        properties.insert(name.clone(), DatumRef::Void);
-      }
-
-      let script = Script { 
-        member_ref: cast_member_ref(self.number as i32, number as i32),
-        name: (&member.name).to_owned(),
-        chunk: script_def.clone(),
-        script_type: script_member.script_type,
-        handlers: handler_name_map,
-        handler_names,
-        properties: RefCell::new(properties),
-      };
+      let lctx = self.lctx.as_ref().unwrap();
+      let script_def = lctx.scripts.get(&script_member.script_id).unwrap();
+      let script = Script::new(
+        cast_member_ref(self.number as i32, number as i32),
+        member.name.to_owned(),
+        script_def.clone(),
+        script_member.script_type,
+        &lctx.names,
+      );
       self.scripts.insert(number, Rc::new(script));
     } else if let CastMemberType::Palette(_) = &member.member_type {
       reserve_player_mut(|player| {
         player.movie.cast_manager.invalidate_palette_cache();
       });
     }
+    reserve_player_mut(|player| {
+      player.movie.cast_manager.invalidate_dependency_graph();
+    });
 
     self.members.insert(number, member);
   }
@@ -270,7 +268,7 @@ impl CastLib {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct CastMemberRef {
   pub cast_lib: i32,
   pub cast_member: i32,
@@ -291,12 +289,23 @@ impl CastMemberRef {
 
 pub async fn player_cast_lib_set_prop(cast_lib: u32, prop_name: &String, value: Datum) -> Result<(), ScriptError> {
   let player = unsafe { PLAYER_OPT.as_mut().unwrap() };
+  sync_search_paths(player);
 
   let cast_manager = &mut player.movie.cast_manager;
   let cast_lib = cast_manager.get_cast_mut(cast_lib as u32);
+  let previous_bitmaps = cast_lib.members.values()
+    .filter_map(|member| match &member.member_type {
+      CastMemberType::Bitmap(bitmap_member) => Some(bitmap_member.image_ref),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
   cast_lib.set_prop(&prop_name, value, &player.allocator)?;
   if prop_name == "fileName" {
     cast_lib.preload(&mut player.net_manager, &mut player.bitmap_manager, &mut player.dir_cache).await;
+    // Images of the swapped out cast are only decoded again if a script still uses them
+    for bitmap_ref in previous_bitmaps {
+      player.bitmap_manager.unload_bitmap(bitmap_ref);
+    }
   }
   // TODO handle preload error
   Ok(())
@@ -4,14 +4,19 @@ use fxhash::FxHashMap;
 use itertools::Itertools;
 use url::Url;
 
-use crate::{director::{enums::ScriptType, file::DirectorFile, lingo::datum::Datum}, js_api::JsApi, player::cast_lib::CastLib};
+use crate::{director::{enums::ScriptType, file::DirectorFile, lingo::datum::Datum}, io::text_encoding::TextEncoding, js_api::JsApi, player::cast_lib::CastLib};
 
-use super::{allocator::DatumAllocator, bitmap::{manager::BitmapManager, palette_map::PaletteMap}, cast_lib::{CastLibState, CastMemberRef, INVALID_CAST_MEMBER_REF}, cast_member::{CastMember, CastMemberType}, handlers::datum_handlers::cast_member_ref::CastMemberRefHandlers, net_manager::NetManager, script::Script, ScriptError};
+use super::{allocator::DatumAllocator, bitmap::{bitmap::PaletteRef, drawing::get_palette_remap_table, manager::{BitmapManager, BitmapRef}, palette_map::PaletteMap}, cast_dependencies::CastDependencyGraph, cast_lib::{CastLibState, CastMemberRef, INVALID_CAST_MEMBER_REF}, cast_member::{CastMember, CastMemberType}, handlers::datum_handlers::cast_member_ref::CastMemberRefHandlers, net_manager::NetManager, score::Score, script::Script, search_path::normalize_director_path, ScriptError};
 
 pub struct CastManager {
   pub casts: Vec<CastLib>,
   pub movie_script_cache: RefCell<Option<Vec<Rc<Script>>>>,
   pub palette_cache: RefCell<Option<Rc<PaletteMap>>>,
+  /// Tables that remap the colors of a palette to the stage palette for paletteMapping,
+  /// by the member palette and the stage palette. A movie only uses a few of each, and
+  /// the tables are dropped along with the palettes.
+  pub palette_remap_cache: RefCell<Vec<(PaletteRef, PaletteRef, Rc<Vec<(u8, u8, u8)>>)>>,
+  pub dependency_graph_cache: RefCell<Option<Rc<CastDependencyGraph>>>,
 }
 
 const IS_WEB: bool = false;
@@ -28,6 +33,8 @@ impl CastManager {
       casts: Vec::new(),
       movie_script_cache: RefCell::new(None),
       palette_cache: RefCell::new(None),
+      palette_remap_cache: RefCell::new(Vec::new()),
+      dependency_graph_cache: RefCell::new(None),
     }
   }
 
@@ -58,6 +65,7 @@ impl CastManager {
         preload_mode: 0,
         capital_x: false,
         dir_version: 0,
+        text_encoding: dir.text_encoding,
       };
       if let Some(cast_def) = cast_def {
         cast.apply_cast_def(dir, cast_def, bitmap_manager);
@@ -100,6 +108,28 @@ impl CastManager {
     }
   }
 
+  /// Adds an internal cast with no members after the others, returning its number.
+  #[cfg(test)]
+  pub fn add_empty_cast(&mut self, name: &str, text_encoding: TextEncoding) -> u32 {
+    let number = self.casts.len() as u32 + 1;
+    self.casts.push(CastLib {
+      name: name.to_owned(),
+      file_name: String::new(),
+      number,
+      is_external: false,
+      state: CastLibState::Loaded,
+      lctx: None,
+      members: FxHashMap::default(),
+      scripts: FxHashMap::default(),
+      preload_mode: 0,
+      capital_x: false,
+      dir_version: 0,
+      text_encoding,
+    });
+    JsApi::dispatch_cast_list_changed();
+    number
+  }
+
   pub fn get_cast(&self, number: u32) -> Result<&CastLib, ScriptError> {
     return self.get_cast_or_null(number).ok_or_else(|| ScriptError::new(format!("Cast not found: {}", number)));
   }
@@ -112,6 +142,10 @@ impl CastManager {
     return self.casts.get_mut(number as usize - 1).unwrap();
   }
 
+  pub fn get_cast_mut_or_null(&mut self, number: u32) -> Option<&mut CastLib> {
+    self.casts.get_mut((number as usize).checked_sub(1)?)
+  }
+
   pub fn get_cast_by_name(&self, name: &String) -> Option<&CastLib> {
     return self.casts.iter().find(|cast| cast.name == *name);
   }
@@ -129,6 +163,19 @@ impl CastManager {
 
   pub fn invalidate_palette_cache(&self) {
     self.palette_cache.replace(None);
+    self.palette_remap_cache.borrow_mut().clear();
+  }
+
+  pub fn get_palette_remap_table(&self, palette_ref: &PaletteRef, stage_palette: &PaletteRef) -> Rc<Vec<(u8, u8, u8)>> {
+    let cached = self.palette_remap_cache.borrow().iter()
+      .find(|(cached_palette, cached_stage_palette, _)| cached_palette == palette_ref && cached_stage_palette == stage_palette)
+      .map(|(_, _, table)| table.clone());
+    if let Some(table) = cached {
+      return table;
+    }
+    let table = Rc::new(get_palette_remap_table(&self.palettes(), palette_ref, stage_palette));
+    self.palette_remap_cache.borrow_mut().push((palette_ref.clone(), stage_palette.clone(), table.clone()));
+    table
   }
 
   pub fn palettes(&self) -> Rc<PaletteMap> {
@@ -148,6 +195,48 @@ impl CastManager {
     self.palette_cache.borrow().as_ref().unwrap().clone()
   }
 
+  pub fn invalidate_dependency_graph(&self) {
+    self.dependency_graph_cache.replace(None);
+  }
+
+  pub fn dependency_graph(&self) -> Rc<CastDependencyGraph> {
+    let has_cache = self.dependency_graph_cache.borrow().is_some();
+    if !has_cache {
+      self.dependency_graph_cache.replace(Some(Rc::new(CastDependencyGraph::build(self))));
+    }
+    self.dependency_graph_cache.borrow().as_ref().unwrap().clone()
+  }
+
+  /// Whether a member can be purged from memory without breaking anything still on stage.
+  /// Members placed on a sprite, or needed by a member placed on a sprite (such as a
+  /// film loop's frames), are kept.
+  pub fn can_purge_member(&self, member_ref: &CastMemberRef, score: &Score) -> bool {
+    let is_in_use = |member_ref: &CastMemberRef| {
+      score.channels.iter().any(|channel| channel.sprite.member.as_ref() == Some(member_ref))
+    };
+    !is_in_use(member_ref) && !self.dependency_graph().is_required(member_ref, is_in_use)
+  }
+
+  /// Every member of every cast, in cast and member order.
+  pub fn all_member_refs(&self) -> Vec<CastMemberRef> {
+    self.casts.iter()
+      .flat_map(|cast| {
+        cast.members.keys().sorted().map(move |number| CastMemberRef {
+          cast_lib: cast.number as i32,
+          cast_member: *number as i32,
+        })
+      })
+      .collect()
+  }
+
+  /// The image of a bitmap member. Other members have no data that is loaded on demand.
+  pub fn get_member_bitmap_ref(&self, member_ref: &CastMemberRef) -> Option<BitmapRef> {
+    match &self.find_member_by_ref(member_ref)?.member_type {
+      CastMemberType::Bitmap(bitmap_member) => Some(bitmap_member.image_ref),
+      _ => None,
+    }
+  }
+
   pub fn find_member_ref_by_name(&self, name: &String) -> Option<CastMemberRef> {
     for cast in &self.casts {
       if let Some(member) = cast.find_member_by_name(name) {
@@ -288,6 +377,8 @@ impl CastManager {
     }
     let cast = self.get_cast_mut(member_ref.cast_lib as u32);
     cast.remove_member(member_ref.cast_member as u32);
+    self.invalidate_dependency_graph();
+    self.invalidate_palette_cache();
     Ok(())
   }
 
@@ -317,14 +408,38 @@ fn normalize_cast_lib_path(base_path: &Option<Url>, file_path: &String, ) -> Opt
   if file_path.is_empty() {
     return None;
   }
-  let slash_normalized = file_path.replace("\\", "/");
-  let file_base_name = slash_normalized.split("/").last().unwrap();
-  let file_base_name_without_ext_split = file_base_name.split(".").collect_vec();
-  let file_base_name_without_ext = &file_base_name_without_ext_split[0..(file_base_name_without_ext_split.len() - 1)].join(".");
-  let cast_file_name = format!("{file_base_name_without_ext}.cct");
+  // Casts are published as .cct next to the movie, or in the folder of an @ path
+  let normalized_path = normalize_director_path(file_path);
+  let cast_path = match normalized_path.rsplit_once('.') {
+    Some((path_without_ext, _)) if !path_without_ext.ends_with('/') => format!("{path_without_ext}.cct"),
+    _ => format!("{normalized_path}.cct"),
+  };
 
   match base_path {
-    Some(base_path) => { Some(base_path.join(&cast_file_name).unwrap().to_string()) }
-    None => { Some(cast_file_name.to_owned()) }
+    Some(base_path) => { Some(base_path.join(&cast_path).unwrap().to_string()) }
+    None => { Some(cast_path.to_owned()) }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::rc::Rc;
+
+  use wasm_bindgen_test::*;
+
+  use crate::player::bitmap::bitmap::{BuiltInPalette, PaletteRef};
+
+  use super::CastManager;
+
+  #[wasm_bindgen_test]
+  fn palette_remap_tables_are_kept_until_the_palettes_change() {
+    let cast_manager = CastManager::empty();
+    let grayscale = PaletteRef::BuiltIn(BuiltInPalette::GrayScale);
+    let stage_palette = PaletteRef::BuiltIn(BuiltInPalette::Rainbow);
+    let table = cast_manager.get_palette_remap_table(&grayscale, &stage_palette);
+    assert!(Rc::ptr_eq(&table, &cast_manager.get_palette_remap_table(&grayscale, &stage_palette)));
+    assert!(!Rc::ptr_eq(&table, &cast_manager.get_palette_remap_table(&stage_palette, &grayscale)));
+    cast_manager.invalidate_palette_cache();
+    assert!(!Rc::ptr_eq(&table, &cast_manager.get_palette_remap_table(&grayscale, &stage_palette)));
   }
 }
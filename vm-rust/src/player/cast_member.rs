@@ -3,9 +3,9 @@ use std::fmt::Formatter;
 
 use log::warn;
 
-use crate::director::{chunks::{cast_member::CastMemberDef, score::ScoreChunk}, enums::{FilmLoopInfo, MemberType, ScriptType, ShapeInfo}, lingo::script::ScriptContext};
+use crate::{director::{chunks::{cast_member::CastMemberDef, score::ScoreChunk, sound::SoundChunk}, enums::{FilmLoopInfo, MemberType, ScriptType, ShapeInfo}, lingo::script::ScriptContext}, io::text_encoding::TextEncoding};
 
-use super::{bitmap::{bitmap::{decompress_bitmap, Bitmap, BuiltInPalette, PaletteRef}, manager::{BitmapManager, BitmapRef}}, sprite::ColorRef, ScriptError};
+use super::{font::FontStyle, bitmap::{bitmap::{Bitmap, BuiltInPalette, PaletteRef}, manager::{BitmapManager, BitmapRef, LazyBitmapFormat}}, sprite::ColorRef, ScriptError};
 
 #[derive(Clone)]
 pub struct CastMember {
@@ -26,12 +26,21 @@ pub struct FieldMember {
   pub font_size: u16,
   pub fixed_line_space: u16,
   pub top_spacing: i16,
+  /// Extra pixels between characters.
+  pub char_spacing: i16,
+  /// #adjust, #scroll, #fixed or #limit.
   pub box_type: String,
   pub anti_alias: bool,
   pub width: u16,
+  /// Height of the box when it doesn't adjust to the text.
+  pub height: u16,
   pub auto_tab: bool, // Tabbing order depends on sprite number order, not position on the Stage.
   pub editable: bool,
   pub border: u16,
+  /// Pixels scrolled past the top of the text.
+  pub scroll_top: u16,
+  /// The encoding the text was decoded with, which defaults to the movie's.
+  pub encoding: TextEncoding,
 }
 
 #[derive(Clone)]
@@ -46,7 +55,37 @@ pub struct TextMember {
   pub font_size: u16,
   pub fixed_line_space: u16,
   pub top_spacing: i16,
+  /// Extra pixels between characters.
+  pub char_spacing: i16,
   pub width: u16,
+  /// Pixels scrolled past the top of the text.
+  pub scroll_top: u16,
+  pub hyperlinks: Vec<TextHyperlink>,
+}
+
+/// A run of characters of a text member that links somewhere.
+#[derive(Clone)]
+pub struct TextHyperlink {
+  /// First and last 1-based character positions of the link.
+  pub range: (usize, usize),
+  /// The data sent to hyperlinkClicked.
+  pub link: String,
+  /// #normal, #active while pressed, or #visited once clicked.
+  pub state: String,
+}
+
+impl TextHyperlink {
+  pub fn contains(&self, char_pos: usize) -> bool {
+    char_pos >= self.range.0 && char_pos <= self.range.1
+  }
+
+  pub fn color(&self) -> (u8, u8, u8) {
+    match self.state.as_str() {
+      "active" => (255, 0, 0),
+      "visited" => (128, 0, 128),
+      _ => (0, 0, 255),
+    }
+  }
 }
 
 impl CastMember {
@@ -72,14 +111,22 @@ impl FieldMember {
       font_size: 12,
       fixed_line_space: 0,
       top_spacing: 0,
+      char_spacing: 0,
       box_type: "adjust".to_string(),
       anti_alias: false,
       width: 100,
+      height: 0,
       auto_tab: false,
       editable: false,
       border: 0,
+      scroll_top: 0,
+      encoding: TextEncoding::MacRoman,
     }
   }
+
+  pub fn get_font_style(&self) -> FontStyle {
+    FontStyle { char_spacing: self.char_spacing as i32, ..FontStyle::parse(&self.font_style) }
+  }
 }
 
 impl TextMember {
@@ -93,18 +140,31 @@ impl TextMember {
       font_size: 12,
       fixed_line_space: 0,
       top_spacing: 0,
+      char_spacing: 0,
       box_type: "adjust".to_string(),
       anti_alias: false,
       width: 100,
+      scroll_top: 0,
+      hyperlinks: vec![],
     }
   }
+
+  pub fn get_font_style(&self) -> FontStyle {
+    FontStyle { char_spacing: self.char_spacing as i32, ..FontStyle::from_list(&self.font_style) }
+  }
+
+  pub fn get_hyperlink_at(&self, char_pos: usize) -> Option<usize> {
+    self.hyperlinks.iter().position(|link| link.contains(char_pos))
+  }
 }
 
 #[derive(Clone)]
 pub struct ScriptMember {
   pub script_id: u32,
   pub script_type: ScriptType,
-  pub name: String
+  pub name: String,
+  /// The Lingo source, empty when the movie doesn't store it, as with protected movies.
+  pub source_text: String,
 }
 
 #[derive(Clone)]
@@ -139,7 +199,8 @@ pub struct FilmLoopMember {
 
 #[derive(Clone)]
 pub struct SoundMember {
-  // TODO add fields
+  /// Missing when the sound is compressed or not downloaded yet.
+  pub sound: Option<SoundChunk>,
 }
 
 #[allow(dead_code)]
@@ -310,6 +371,12 @@ impl CastMember {
         let text_chunk = member_def.children[0].as_ref().unwrap().as_text().expect("Not a text chunk");
         let mut field_member = FieldMember::new();
         field_member.text = text_chunk.text.clone();
+        field_member.encoding = text_chunk.encoding;
+        if let Some(field_info) = chunk.specific_data.field_info() {
+          field_member.box_type = field_info.box_type.symbol_string().to_string();
+          field_member.width = field_info.width();
+          field_member.height = field_info.height();
+        }
         CastMemberType::Field(field_member)
       }
       MemberType::Script => {
@@ -322,31 +389,19 @@ impl CastMember {
           ScriptMember { 
             script_id, 
             script_type, 
-            name: member_info.name.clone() 
+            name: member_info.name.clone(),
+            source_text: member_info.script_src_text.clone(),
           }
         )
       }
       MemberType::Bitmap => {
         let bitmap_info = chunk.specific_data.bitmap_info().unwrap();
-        let abmp_chunk = member_def.children
-          .get(0)
-          .and_then(|x| x.as_ref());
+        let abmp_chunk = member_def.children.iter().flatten().find_map(|x| x.as_bitmap());
+        let media_chunk = member_def.children.iter().flatten().find_map(|x| x.as_media());
         let new_bitmap_ref = if let Some(abmp_chunk) = abmp_chunk {
-          let abmp_chunk = abmp_chunk
-            .as_bitmap()
-            .unwrap();
-          let decompressed = decompress_bitmap(&abmp_chunk.data, &bitmap_info, cast_lib);
-          match decompressed {
-            Ok(new_bitmap) => {
-              bitmap_manager.add_bitmap(new_bitmap)
-            },
-            Err(_e) => {
-              // warn!("Failed to decompress bitmap. Using an empty image instead. {:?}", e);
-              // TODO create error texture?
-              // INVALID_BITMAP_REF
-              bitmap_manager.add_bitmap(Bitmap::new(1, 1, 8, PaletteRef::BuiltIn(BuiltInPalette::GrayScale)))
-            }
-          }
+          bitmap_manager.add_lazy_bitmap(abmp_chunk.data.clone(), LazyBitmapFormat::Director, bitmap_info.clone(), cast_lib)
+        } else if let Some(media_chunk) = media_chunk {
+          bitmap_manager.add_lazy_bitmap(media_chunk.data.clone(), LazyBitmapFormat::Encoded, bitmap_info.clone(), cast_lib)
         } else {
           warn!("No bitmap chunk found for member {}", number);
           bitmap_manager.add_bitmap(Bitmap::new(1, 1, 8, PaletteRef::BuiltIn(BuiltInPalette::GrayScale)))
@@ -377,8 +432,9 @@ impl CastMember {
         })
       }
       MemberType::Sound => {
+        let sound = member_def.children.iter().flatten().find_map(|child| child.as_sound());
         CastMemberType::Sound(SoundMember {
-          // TODO populate fields
+          sound: sound.cloned(),
         })
       }
       _ => { 
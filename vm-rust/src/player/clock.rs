@@ -0,0 +1,83 @@
+use chrono::{DateTime, Local};
+
+/// Source of movie time, read by the ticks, the milliSeconds, the timer, frame
+/// pacing and timeouts.
+pub trait Clock {
+  /// Milliseconds elapsed since the player started.
+  fn elapsed_ms(&self) -> i64;
+  /// How fast movie time runs relative to real time. Zero means time is paused.
+  fn time_scale(&self) -> f64;
+
+  fn as_virtual_mut(&mut self) -> Option<&mut VirtualClock> {
+    None
+  }
+
+  fn elapsed_ticks(&self) -> i32 {
+    (self.elapsed_ms() * 60 / 1000) as i32
+  }
+}
+
+pub struct RealClock {
+  // Supposed to be the time at which the computer started, but we don't have access
+  // to that from the browser. This is sufficient for calculating elapsed time.
+  start_time: DateTime<Local>,
+}
+
+impl RealClock {
+  pub fn new() -> RealClock {
+    RealClock { start_time: Local::now() }
+  }
+}
+
+impl Clock for RealClock {
+  fn elapsed_ms(&self) -> i64 {
+    (Local::now() - self.start_time).num_milliseconds()
+  }
+
+  fn time_scale(&self) -> f64 {
+    1.0
+  }
+}
+
+/// A clock that runs at an adjustable rate and can be advanced by hand, so that
+/// time-dependent scripts can be slowed down, sped up or single-stepped.
+pub struct VirtualClock {
+  base_ms: i64,
+  base_real_time: DateTime<Local>,
+  scale: f64,
+}
+
+impl VirtualClock {
+  pub fn new(elapsed_ms: i64, scale: f64) -> VirtualClock {
+    VirtualClock {
+      base_ms: elapsed_ms,
+      base_real_time: Local::now(),
+      scale,
+    }
+  }
+
+  pub fn set_time_scale(&mut self, scale: f64) {
+    self.base_ms = self.elapsed_ms();
+    self.base_real_time = Local::now();
+    self.scale = scale;
+  }
+
+  pub fn advance(&mut self, ms: i64) {
+    self.base_ms += ms;
+  }
+}
+
+impl Clock for VirtualClock {
+  fn elapsed_ms(&self) -> i64 {
+    let real_ms = (Local::now() - self.base_real_time).num_milliseconds();
+    self.base_ms + (real_ms as f64 * self.scale) as i64
+  }
+
+  fn time_scale(&self) -> f64 {
+    self.scale
+  }
+
+  fn as_virtual_mut(&mut self) -> Option<&mut VirtualClock> {
+    Some(self)
+  }
+}
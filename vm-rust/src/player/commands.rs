@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use async_std::channel::Receiver;
 use chrono::Local;
 use log::warn;
@@ -7,11 +5,11 @@ use manual_future::ManualFuture;
 use url::Url;
 
 use crate::{
-    console_warn, director::lingo::datum::{Datum, TimeoutRef}, js_api::JsApi, player::PLAYER_OPT, utils::ToHexString
+    console_warn, director::lingo::datum::{Datum, DatumType, TimeoutRef}, io::text_encoding::TextEncoding, js_api::JsApi, player::PLAYER_OPT, rendering::request_stage_redraw, utils::{performance_now, ToHexString}
 };
 
 use super::{
-    allocator::ScriptInstanceAllocatorTrait, cast_lib::CastMemberRef, cast_member::CastMemberType, datum_ref::{DatumId, DatumRef}, events::{player_dispatch_callback_event, player_dispatch_event_to_sprite, player_dispatch_targeted_event, player_wait_available}, font::player_load_system_font, keyboard_events::{player_key_down, player_key_up}, player_alloc_datum, player_call_script_handler, player_dispatch_global_event, player_is_playing, reserve_player_mut, reserve_player_ref, score::{concrete_sprite_hit_test, get_sprite_at}, script::ScriptInstanceId, script_ref::ScriptInstanceRef, PlayerVMExecutionItem, ScriptError, ScriptReceiver, PLAYER_TX
+    alert::{is_alert_button_at, player_call_alert_hook, player_handle_script_error}, cast_lib::CastMemberRef, compatibility::{player_apply_compatibility_profile, CompatibilityProfile}, cast_member::CastMemberType, console::player_eval_console_lingo, do_command::player_do, debug::{add_watch_expression, coverage::CoverageRecorder, remove_watch_expression, update_watch_expressions}, eval::eval_lingo, handlers::datum_handlers::{cast_member::text_geometry::TextGeometryHandlers, cast_member_ref::CastMemberRefHandlers}, datum_ref::{DatumId, DatumRef}, events::{player_dispatch_callback_event, player_dispatch_event_to_sprite, player_dispatch_targeted_event, player_wait_available}, font::{layout::{TextLayout, SCROLLBAR_WIDTH}, player_load_font, player_load_system_font}, frame_hook::FrameHook, profiling::HandlerProfiler, property_descriptions::player_describe_sprite_behaviors, keyboard_events::{player_key_down, player_key_up}, locale::Locale, net_bundle::NetBundle, net_task::{NetResponseInfo, NetResult}, player_alloc_datum, player_dispatch_global_event, player_is_playing, reserve_player_mut, reserve_player_ref, score::{concrete_sprite_hit_test, constrain_sprite_loc, get_sprite_at, sprite_set_prop}, script::ScriptInstanceId, script_ref::ScriptInstanceRef, search_path::sync_search_paths, watchdog::WatchdogAction, xtra::wheelhook::borrow_wheel_hook_manager_mut, DirPlayer, PlayerVMExecutionItem, ScriptError, PLAYER_TX
 };
 
 #[allow(dead_code)]
@@ -21,26 +19,68 @@ pub enum PlayerVMCommand {
     Stop,
     Reset,
     LoadMovieFromFile(String),
-    SetExternalParams(HashMap<String, String>),
+    SetExternalParams(Vec<(String, String)>),
+    SetFrameHookInterval(u32),
+    SetSafeMode(bool),
+    SetAllowDo(bool),
+    SetLenientMissingHandlers(bool),
+    SetMissingHandlerOverride(String, Option<bool>),
+    SetCompatibilityProfile(Option<CompatibilityProfile>),
+    SetFetchCompatibilityProfiles(bool),
+    SetScriptWatchdog(Option<u32>, WatchdogAction),
+    SetMaxStackSize(usize),
+    SetLocale(String),
+    SetTextEncoding(String),
+    SetCoverageEnabled(bool),
+    SetProfilingEnabled(bool),
     SetBasePath(String),
     SetSystemFontPath(String),
+    LoadFont(String, String),
+    SetNetCacheTtl(u32),
+    SetNetUpgradeInsecure(bool),
+    AddNetRewriteRule(String, String),
+    AddNetBundle(NetBundle),
+    ProvideNetTaskData(u32, NetResult, Option<NetResponseInfo>),
+    AddSearchPath(String),
+    ClearSearchPaths,
+    SetTimeScale(f64),
+    StepVirtualClock(u32),
+    SetRandomSeed(i32),
+    SetMasterVolume(f32),
+    SetMuted(bool),
+    SetSmoothScaling(bool),
+    SetEmulate8BitStage(bool),
+    ClearStageTrails,
     AddBreakpoint(String, String, usize),
     RemoveBreakpoint(String, String, usize),
     ToggleBreakpoint(String, String, usize),
+    SetBreakpointCondition(String, String, usize, Option<String>),
+    EvalLingo(String),
+    DoLater(String),
+    AddWatchExpression(String),
+    RemoveWatchExpression(String),
     ResumeBreakpoint,
+    StepBytecode,
+    StepBackBytecode,
     SetStageSize(u32, u32),
     TimeoutTriggered(TimeoutRef),
     PrintMemberBitmapHex(CastMemberRef),
     MouseDown((i32, i32)),
     MouseUp((i32, i32)),
     MouseMove((i32, i32)),
+    MouseWheel(f64, f64),
     KeyDown(String, u16),
     KeyUp(String, u16),
     RequestDatum(DatumId),
     RequestScriptInstanceSnapshot(ScriptInstanceId),
+    RequestCoverageSummary,
     SubscribeToMember(CastMemberRef),
     UnsubscribeFromMember(CastMemberRef),
     TriggerAlertHook,
+    SetSpriteProp(i16, String, String),
+    SetMemberProp(CastMemberRef, String, String),
+    SetGlobal(String, String),
+    RequestBehaviorDescriptions(i16),
 }
 
 pub fn _format_player_cmd(command: &PlayerVMCommand) -> String {
@@ -51,10 +91,39 @@ pub fn _format_player_cmd(command: &PlayerVMCommand) -> String {
         PlayerVMCommand::Reset => "Reset".to_string(),
         PlayerVMCommand::LoadMovieFromFile(path) => format!("LoadMovieFromFile({})", path),
         PlayerVMCommand::SetExternalParams(params) => {
-            format!("SetExternalParams({:?})", params.keys().collect::<Vec<_>>())
-        }
+            format!("SetExternalParams({:?})", params.iter().map(|(key, _)| key).collect::<Vec<_>>())
+        }
+        PlayerVMCommand::SetFrameHookInterval(interval) => format!("SetFrameHookInterval({})", interval),
+        PlayerVMCommand::SetSafeMode(enabled) => format!("SetSafeMode({})", enabled),
+        PlayerVMCommand::SetAllowDo(enabled) => format!("SetAllowDo({})", enabled),
+        PlayerVMCommand::SetLenientMissingHandlers(enabled) => format!("SetLenientMissingHandlers({})", enabled),
+        PlayerVMCommand::SetMissingHandlerOverride(name, skip) => format!("SetMissingHandlerOverride({}, {:?})", name, skip),
+        PlayerVMCommand::SetCompatibilityProfile(profile) => format!("SetCompatibilityProfile({})", profile.is_some()),
+        PlayerVMCommand::SetFetchCompatibilityProfiles(enabled) => format!("SetFetchCompatibilityProfiles({})", enabled),
+        PlayerVMCommand::SetScriptWatchdog(budget_ms, action) => format!("SetScriptWatchdog({:?}, {:?})", budget_ms, action),
+        PlayerVMCommand::SetMaxStackSize(size) => format!("SetMaxStackSize({})", size),
+        PlayerVMCommand::SetLocale(name) => format!("SetLocale({})", name),
+        PlayerVMCommand::SetTextEncoding(name) => format!("SetTextEncoding({})", name),
+        PlayerVMCommand::SetCoverageEnabled(enabled) => format!("SetCoverageEnabled({})", enabled),
+        PlayerVMCommand::SetProfilingEnabled(enabled) => format!("SetProfilingEnabled({})", enabled),
         PlayerVMCommand::SetBasePath(path) => format!("SetBasePath({})", path),
         PlayerVMCommand::SetSystemFontPath(path) => format!("SetSystemFontPath({})", path),
+        PlayerVMCommand::LoadFont(name, path) => format!("LoadFont({}, {})", name, path),
+        PlayerVMCommand::SetNetCacheTtl(ttl_ms) => format!("SetNetCacheTtl({})", ttl_ms),
+        PlayerVMCommand::SetNetUpgradeInsecure(enabled) => format!("SetNetUpgradeInsecure({})", enabled),
+        PlayerVMCommand::AddNetRewriteRule(from, to) => format!("AddNetRewriteRule({}, {})", from, to),
+        PlayerVMCommand::AddNetBundle(_) => "AddNetBundle".to_string(),
+        PlayerVMCommand::ProvideNetTaskData(task_id, result, _) => format!("ProvideNetTaskData({}, {:?})", task_id, result.as_ref().err()),
+        PlayerVMCommand::AddSearchPath(path) => format!("AddSearchPath({})", path),
+        PlayerVMCommand::ClearSearchPaths => "ClearSearchPaths".to_string(),
+        PlayerVMCommand::SetTimeScale(scale) => format!("SetTimeScale({})", scale),
+        PlayerVMCommand::StepVirtualClock(ms) => format!("StepVirtualClock({})", ms),
+        PlayerVMCommand::SetRandomSeed(seed) => format!("SetRandomSeed({})", seed),
+        PlayerVMCommand::SetMasterVolume(volume) => format!("SetMasterVolume({})", volume),
+        PlayerVMCommand::SetMuted(muted) => format!("SetMuted({})", muted),
+        PlayerVMCommand::SetSmoothScaling(enabled) => format!("SetSmoothScaling({})", enabled),
+        PlayerVMCommand::SetEmulate8BitStage(enabled) => format!("SetEmulate8BitStage({})", enabled),
+        PlayerVMCommand::ClearStageTrails => "ClearStageTrails".to_string(),
         PlayerVMCommand::AddBreakpoint(script_name, handler_name, bytecode_index) => format!(
             "AddBreakpoint({}, {}, {})",
             script_name, handler_name, bytecode_index
@@ -67,7 +136,17 @@ pub fn _format_player_cmd(command: &PlayerVMCommand) -> String {
             "ToggleBreakpoint({}, {}, {})",
             script_name, handler_name, bytecode_index
         ),
+        PlayerVMCommand::SetBreakpointCondition(script_name, handler_name, bytecode_index, condition) => format!(
+            "SetBreakpointCondition({}, {}, {}, {:?})",
+            script_name, handler_name, bytecode_index, condition
+        ),
+        PlayerVMCommand::EvalLingo(source) => format!("EvalLingo({})", source),
+        PlayerVMCommand::DoLater(source) => format!("DoLater({})", source),
+        PlayerVMCommand::AddWatchExpression(expression) => format!("AddWatchExpression({})", expression),
+        PlayerVMCommand::RemoveWatchExpression(expression) => format!("RemoveWatchExpression({})", expression),
         PlayerVMCommand::ResumeBreakpoint => "ResumeBreakpoint".to_string(),
+        PlayerVMCommand::StepBytecode => "StepBytecode".to_string(),
+        PlayerVMCommand::StepBackBytecode => "StepBackBytecode".to_string(),
         PlayerVMCommand::SetStageSize(width, height) => {
             format!("SetStageSize({}, {})", width, height)
         }
@@ -78,9 +157,11 @@ pub fn _format_player_cmd(command: &PlayerVMCommand) -> String {
         PlayerVMCommand::MouseDown((x, y)) => format!("MouseDown({}, {})", x, y),
         PlayerVMCommand::MouseUp((x, y)) => format!("MouseUp({}, {})", x, y),
         PlayerVMCommand::MouseMove((x, y)) => format!("MouseMove({}, {})", x, y),
+        PlayerVMCommand::MouseWheel(delta_x, delta_y) => format!("MouseWheel({}, {})", delta_x, delta_y),
         PlayerVMCommand::KeyDown(key, ..) => format!("KeyDown({})", key),
         PlayerVMCommand::KeyUp(key, ..) => format!("KeyUp({})", key),
         PlayerVMCommand::RequestDatum(datum_ref) => format!("RequestDatum({})", datum_ref),
+        PlayerVMCommand::RequestCoverageSummary => "RequestCoverageSummary".to_string(),
         PlayerVMCommand::RequestScriptInstanceSnapshot(script_instance_id) => {
             format!("RequestScriptInstanceSnapshot({})", script_instance_id)
         }
@@ -91,6 +172,16 @@ pub fn _format_player_cmd(command: &PlayerVMCommand) -> String {
             format!("UnsubscribeFromMember({:?})", member_ref)
         }
         PlayerVMCommand::TriggerAlertHook => "TriggerAlertHook".to_string(),
+        PlayerVMCommand::SetSpriteProp(sprite_num, prop_name, value) => {
+            format!("SetSpriteProp({}, {}, {})", sprite_num, prop_name, value)
+        }
+        PlayerVMCommand::SetMemberProp(member_ref, prop_name, value) => {
+            format!("SetMemberProp({:?}, {}, {})", member_ref, prop_name, value)
+        }
+        PlayerVMCommand::SetGlobal(name, value) => format!("SetGlobal({}, {})", name, value),
+        PlayerVMCommand::RequestBehaviorDescriptions(sprite_num) => {
+            format!("RequestBehaviorDescriptions({})", sprite_num)
+        }
     }
 }
 
@@ -99,6 +190,7 @@ pub async fn run_command_loop(rx: Receiver<PlayerVMExecutionItem>) {
 
   while !rx.is_closed() {
     let item = rx.recv().await.unwrap();
+    let scope_count = reserve_player_ref(|player| player.scope_count);
     let result = run_player_command(item.command).await;
     match result {
       Ok(result) => {
@@ -109,9 +201,7 @@ pub async fn run_command_loop(rx: Receiver<PlayerVMExecutionItem>) {
       Err(err) => {
         // TODO ignore error if it's a CancelledException
         // TODO print stack trace
-        reserve_player_mut(|player| {
-          player.on_script_error(&err)
-        });
+        player_handle_script_error(&err, scope_count).await;
         if let Some(completer) = item.completer {
           completer.complete(Err(err)).await;
         }
@@ -126,7 +216,6 @@ pub fn player_dispatch(command: PlayerVMCommand) {
   tx.try_send(PlayerVMExecutionItem { command, completer: None }).unwrap();
 }
 
-#[allow(dead_code)]
 pub async fn player_dispatch_async(command: PlayerVMCommand) -> Result<DatumRef, ScriptError> {
   let tx = unsafe { PLAYER_TX.clone() }.unwrap();
   let (future, completer) = ManualFuture::new();
@@ -146,6 +235,66 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
                 player.external_params = params;
             });
         }
+        PlayerVMCommand::SetFrameHookInterval(interval) => {
+            reserve_player_mut(|player| {
+                player.frame_hook = if interval > 0 { Some(FrameHook::new(interval)) } else { None };
+            });
+        }
+        PlayerVMCommand::SetSafeMode(enabled) => {
+            reserve_player_mut(|player| {
+                player.is_safe_mode = enabled;
+            });
+        }
+        PlayerVMCommand::SetAllowDo(enabled) => {
+            reserve_player_mut(|player| {
+                player.allow_do = enabled;
+            });
+        }
+        PlayerVMCommand::SetLenientMissingHandlers(enabled) => {
+            reserve_player_mut(|player| {
+                player.missing_handler_policy.lenient = enabled;
+            });
+        }
+        PlayerVMCommand::SetMissingHandlerOverride(name, skip) => {
+            reserve_player_mut(|player| {
+                player.missing_handler_policy.set_override(&name, skip);
+            });
+        }
+        PlayerVMCommand::SetCompatibilityProfile(profile) => {
+            reserve_player_mut(|player| {
+                player.compatibility_profile = profile;
+                // Removing the profile undoes it right away, a new one waits for the movie
+                if player.movie.file.is_some() || player.compatibility_profile.is_none() {
+                    player_apply_compatibility_profile(player);
+                }
+            });
+        }
+        PlayerVMCommand::SetFetchCompatibilityProfiles(enabled) => {
+            reserve_player_mut(|player| {
+                player.fetch_compatibility_profiles = enabled;
+            });
+        }
+        PlayerVMCommand::SetScriptWatchdog(budget_ms, action) => {
+            reserve_player_mut(|player| {
+                player.script_watchdog.budget_ms = budget_ms;
+                player.script_watchdog.action = action;
+            });
+        }
+        PlayerVMCommand::SetMaxStackSize(size) => {
+            reserve_player_mut(|player| {
+                player.max_stack_size = size.max(1);
+            });
+        }
+        PlayerVMCommand::SetCoverageEnabled(enabled) => {
+            reserve_player_mut(|player| {
+                player.coverage_recorder = if enabled { Some(CoverageRecorder::new()) } else { None };
+            });
+        }
+        PlayerVMCommand::SetProfilingEnabled(enabled) => {
+            reserve_player_mut(|player| {
+                player.handler_profiler = if enabled { Some(HandlerProfiler::new(performance_now())) } else { None };
+            });
+        }
         PlayerVMCommand::SetBasePath(path) => {
             reserve_player_mut(|player| {
                 player.net_manager.set_base_path(Url::parse(&path).unwrap());
@@ -155,6 +304,113 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
             console_warn!("Loading system font: {}", path);
             player_load_system_font(&path).await;
         }
+        PlayerVMCommand::LoadFont(name, path) => {
+            player_load_font(&name, &path).await;
+            request_stage_redraw();
+        }
+        PlayerVMCommand::SetNetCacheTtl(ttl_ms) => {
+            reserve_player_mut(|player| {
+                player.net_manager.set_cache_ttl(ttl_ms as i64);
+            });
+        }
+        PlayerVMCommand::SetNetUpgradeInsecure(enabled) => {
+            reserve_player_mut(|player| {
+                player.net_manager.policy.upgrade_insecure = enabled;
+            });
+        }
+        PlayerVMCommand::AddNetRewriteRule(from, to) => {
+            reserve_player_mut(|player| {
+                player.net_manager.policy.add_rewrite_rule(from, to);
+            });
+        }
+        PlayerVMCommand::AddNetBundle(bundle) => {
+            reserve_player_mut(|player| {
+                player.net_manager.add_bundle(bundle);
+            });
+        }
+        PlayerVMCommand::ProvideNetTaskData(task_id, result, response) => {
+            let shared_state = reserve_player_ref(|player| {
+                player.net_manager.get_task(task_id).map(|_| player.net_manager.shared_state.clone())
+            });
+            match shared_state {
+                Some(shared_state) => shared_state.lock().await.fulfill_task(task_id, result, response).await,
+                None => warn!("Net task data provided for unknown task {}", task_id),
+            }
+        }
+        PlayerVMCommand::AddSearchPath(path) => {
+            reserve_player_mut(|player| {
+                sync_search_paths(player);
+                player.net_manager.search_paths.push(path);
+                player.search_path_list = None;
+            });
+        }
+        PlayerVMCommand::ClearSearchPaths => {
+            reserve_player_mut(|player| {
+                player.net_manager.search_paths.clear();
+                player.search_path_list = None;
+            });
+        }
+        PlayerVMCommand::SetTimeScale(scale) => {
+            reserve_player_mut(|player| {
+                player.set_time_scale(scale.max(0.0));
+            });
+        }
+        PlayerVMCommand::StepVirtualClock(ms) => {
+            let due_timeouts = reserve_player_mut(|player| player.step_virtual_clock(ms as i64));
+            for timeout_name in due_timeouts {
+                player_dispatch(PlayerVMCommand::TimeoutTriggered(timeout_name));
+            }
+        }
+        PlayerVMCommand::SetRandomSeed(seed) => {
+            reserve_player_mut(|player| {
+                player.random.set_seed(seed);
+            });
+        }
+        PlayerVMCommand::SetMasterVolume(volume) => {
+            reserve_player_mut(|player| {
+                player.sound_manager.set_master_volume(volume);
+            });
+        }
+        PlayerVMCommand::SetMuted(muted) => {
+            reserve_player_mut(|player| {
+                player.sound_manager.set_muted(muted);
+            });
+        }
+        PlayerVMCommand::SetLocale(name) => {
+            match Locale::from_name(&name) {
+                Some(locale) => reserve_player_mut(|player| {
+                    player.locale_override = Some(locale);
+                    player.movie.set_locale(locale);
+                }),
+                None => warn!("Unknown locale {}", name),
+            }
+        }
+        PlayerVMCommand::SetTextEncoding(name) => {
+            match name.as_str() {
+                "" | "auto" => reserve_player_mut(|player| player.text_encoding_override = None),
+                name => match TextEncoding::from_name(name) {
+                    Some(encoding) => reserve_player_mut(|player| player.text_encoding_override = Some(encoding)),
+                    None => warn!("Unknown text encoding {}", name),
+                },
+            }
+        }
+        PlayerVMCommand::SetSmoothScaling(enabled) => {
+            reserve_player_mut(|player| {
+                player.smooth_scaling = enabled;
+            });
+        }
+        PlayerVMCommand::SetEmulate8BitStage(enabled) => {
+            reserve_player_mut(|player| {
+                player.emulate_8bit_stage = enabled;
+            });
+            request_stage_redraw();
+        }
+        PlayerVMCommand::ClearStageTrails => {
+            reserve_player_mut(|player| {
+                player.stage_trails = None;
+            });
+            request_stage_redraw();
+        }
         PlayerVMCommand::Play => {
             reserve_player_mut(|player| {
                 player.play();
@@ -202,11 +458,58 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
                 );
             });
         }
+        PlayerVMCommand::SetBreakpointCondition(script_name, handler_name, bytecode_index, condition) => {
+            reserve_player_mut(|player| {
+                player.breakpoint_manager.set_breakpoint_condition(
+                    script_name,
+                    handler_name,
+                    bytecode_index,
+                    condition,
+                );
+            });
+        }
+        PlayerVMCommand::EvalLingo(source) => {
+            // Mistakes typed into the console are reported back instead of stopping the movie
+            let output = match player_eval_console_lingo(&source).await {
+                Ok(output) => output,
+                Err(err) => format!("Script error: {}", err.message),
+            };
+            return Ok(player_alloc_datum(Datum::String(output)));
+        }
+        PlayerVMCommand::DoLater(source) => {
+            let (is_playing, scope_count) = reserve_player_ref(|player| (player.is_playing, player.scope_count));
+            if !is_playing {
+                return Ok(DatumRef::Void);
+            }
+            if let Err(err) = player_do(&source).await {
+                player_handle_script_error(&err, scope_count).await;
+            }
+        }
+        PlayerVMCommand::AddWatchExpression(expression) => {
+            reserve_player_mut(|player| {
+                add_watch_expression(player, expression);
+            });
+        }
+        PlayerVMCommand::RemoveWatchExpression(expression) => {
+            reserve_player_mut(|player| {
+                remove_watch_expression(player, &expression);
+            });
+        }
         PlayerVMCommand::ResumeBreakpoint => {
             reserve_player_mut(|player| {
                 player.resume_breakpoint();
             });
         }
+        PlayerVMCommand::StepBytecode => {
+            reserve_player_mut(|player| {
+                player.step_bytecode();
+            });
+        }
+        PlayerVMCommand::StepBackBytecode => {
+            reserve_player_mut(|player| {
+                player.step_back_bytecode();
+            });
+        }
         PlayerVMCommand::SetStageSize(width, height) => {
             reserve_player_mut(|player| {
                 player.stage_size = (width, height);
@@ -215,7 +518,9 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
         PlayerVMCommand::TimeoutTriggered(timeout_ref) => {
             let (is_found, is_playing, is_script_paused, target_ref, handler_name, timeout_name) =
                 reserve_player_mut(|player| {
-                    if let Some(timeout) = player.timeout_manager.get_timeout(&timeout_ref) {
+                    let now = player.clock.elapsed_ms();
+                    if let Some(timeout) = player.timeout_manager.get_timeout_mut(&timeout_ref) {
+                        timeout.last_fired_ms = now;
                         let is_playing = player.is_playing;
                         let is_script_paused = player.is_script_paused;
                         (
@@ -268,24 +573,44 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
             });
         }
         PlayerVMCommand::MouseDown((x, y)) => {
-            if !player_is_playing().await {
+            // The stage doesn't take input while an alert is open
+            if !player_is_playing().await || reserve_player_ref(|player| player.alert.is_some()) {
                 return Ok(DatumRef::Void);
             }
             let instance_ids = reserve_player_mut(|player| {
                 let now = Local::now().timestamp_millis().abs();
                 let is_double_click = (now - player.last_mouse_down_time) < 500;
                 player.mouse_loc = (x, y);
+                player.click_loc = (x, y);
                 player.is_double_click = is_double_click;
                 player.last_mouse_down_time = now;
+                player.dragged_sprite = get_sprite_at(player, x, y, false)
+                    .and_then(|sprite_number| player.movie.score.get_sprite(sprite_number as i16))
+                    .filter(|sprite| sprite.moveable)
+                    .map(|sprite| (sprite.number as i16, (sprite.loc_h - x, sprite.loc_v - y)));
                 let sprite = get_sprite_at(player, x, y, true);
+                player.click_on = sprite.unwrap_or(0) as i16;
                 if let Some(sprite_number) = sprite {
                     let sprite = player.movie.score.get_sprite(sprite_number as i16);
                     let sprite_member = sprite
                         .and_then(|x| x.member.as_ref())
                         .and_then(|x| player.movie.cast_manager.find_member_by_ref(&x));
+                    let mut scrolled_field = None;
                     if let Some(sprite_member) = sprite_member {
                         match &sprite_member.member_type {
                             CastMemberType::Field(field_member) => {
+                                let sprite = sprite.unwrap();
+                                let scrollbar_left = sprite.loc_h + field_member.width as i32 - SCROLLBAR_WIDTH;
+                                if field_member.box_type == "scroll" && x >= scrollbar_left {
+                                    // Clicking the upper half of the scrollbar scrolls up a line, the lower half down
+                                    let layout = TextLayout::for_member(&player.font_manager, &sprite_member.member_type);
+                                    if let Some(layout) = layout {
+                                        let box_height = layout.get_field_box_height(field_member);
+                                        let line_count = if y - sprite.loc_v < box_height / 2 { -1 } else { 1 };
+                                        let scroll_top = layout.scroll_by_lines(field_member.scroll_top as i32, line_count);
+                                        scrolled_field = Some((sprite.member.clone().unwrap(), scroll_top));
+                                    }
+                                }
                                 if field_member.editable {
                                     player.keyboard_focus_sprite = sprite_number as i16;
                                 }
@@ -293,6 +618,19 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
                             _ => {}
                         }
                     }
+                    if let Some((member_ref, scroll_top)) = scrolled_field {
+                        if let Some(field) = player.movie.cast_manager.find_mut_member_by_ref(&member_ref).and_then(|x| x.member_type.as_field_mut()) {
+                            field.scroll_top = scroll_top as u16;
+                        }
+                    }
+                    // A pressed hyperlink shows as active until the mouse is released
+                    player.pressed_hyperlink = TextGeometryHandlers::get_hyperlink_at(player, sprite_number as i16, (x, y))
+                        .and_then(|(member_ref, index)| {
+                            let text_member = player.movie.cast_manager.find_mut_member_by_ref(&member_ref)?.member_type.as_text_mut()?;
+                            let state = std::mem::replace(&mut text_member.hyperlinks[index].state, "active".to_string());
+                            Some((member_ref, index, state))
+                        });
+                    let sprite = player.movie.score.get_sprite(sprite_number as i16);
 
                     player.mouse_down_sprite = sprite_number as i16;
                     sprite.map(|x| x.script_instance_list.clone())
@@ -300,17 +638,30 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
                     None
                 }
             });
-            player_dispatch_targeted_event(
-                &"mouseDown".to_string(),
-                &vec![],
-                instance_ids.as_ref(),
-            );
+            let script = reserve_player_ref(|player| player.mouse_down_script.clone());
+            if player_run_primary_event_handler(script).await {
+                player_dispatch_targeted_event(
+                    &"mouseDown".to_string(),
+                    &vec![],
+                    instance_ids.as_ref(),
+                );
+            }
             return Ok(DatumRef::Void);
         }
         PlayerVMCommand::MouseUp((x, y)) => {
-            if !player_is_playing().await {
+            let is_alert_open = reserve_player_mut(|player| {
+                if player.alert.is_none() {
+                    return false;
+                }
+                if is_alert_button_at(player, x, y) {
+                    player.dismiss_alert();
+                }
+                true
+            });
+            if is_alert_open || !player_is_playing().await {
                 return Ok(DatumRef::Void);
             }
+            let hyperlink_args = reserve_player_mut(|player| player_release_hyperlink(player, (x, y)));
             let result = reserve_player_mut(|player| {
                 player.mouse_loc = (x, y);
                 let sprite = if player.mouse_down_sprite > 0 {
@@ -319,6 +670,7 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
                     None
                 };
                 player.mouse_down_sprite = -1;
+                player.dragged_sprite = None;
                 if let Some(sprite) = sprite {
                     let is_inside = concrete_sprite_hit_test(player, sprite, x, y);
                     Some((sprite.script_instance_list.clone(), is_inside))
@@ -328,8 +680,15 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
             });
             let is_inside = result.as_ref().map(|x| x.1).unwrap_or(true);
             let instance_ids = result.as_ref().map(|x| &x.0);
-            let event_name = if is_inside { "mouseUp" } else { "mouseUpOutSide" };
-            player_dispatch_targeted_event(&event_name.to_string(), &vec![], instance_ids);
+            // The sprite that was pressed gets the release, even once the mouse left it
+            let event_name = if is_inside { "mouseUp" } else { "mouseUpOutside" };
+            let script = reserve_player_ref(|player| player.mouse_up_script.clone());
+            if player_run_primary_event_handler(script).await {
+                if let Some(args) = hyperlink_args {
+                    player_dispatch_targeted_event(&"hyperlinkClicked".to_string(), &args, instance_ids);
+                }
+                player_dispatch_targeted_event(&event_name.to_string(), &vec![], instance_ids);
+            }
             reserve_player_mut(|player| {
                 player.is_double_click = false;
             });
@@ -339,8 +698,19 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
             if !player_is_playing().await {
                 return Ok(DatumRef::Void);
             }
+            if reserve_player_ref(|player| player.alert.is_some()) {
+                reserve_player_mut(|player| player.mouse_loc = (x, y));
+                return Ok(DatumRef::Void);
+            }
             let (sprite_num, hovered_sprite) = reserve_player_mut(|player| {
                 player.mouse_loc = (x, y);
+                if let Some((dragged_sprite, (offset_h, offset_v))) = player.dragged_sprite {
+                    let (loc_h, loc_v) = constrain_sprite_loc(player, dragged_sprite, (x + offset_h, y + offset_v));
+                    let sprite = player.movie.score.get_sprite_mut(dragged_sprite);
+                    sprite.loc_h = loc_h;
+                    sprite.loc_v = loc_v;
+                    JsApi::dispatch_channel_changed(dragged_sprite);
+                }
                 
                 let hovered_sprite = player.hovered_sprite;
                 let sprite_num = get_sprite_at(player, x, y, false);
@@ -361,12 +731,47 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
                 }
             }
         }
+        PlayerVMCommand::MouseWheel(_, delta_y) => {
+            let steps = wheel_steps(delta_y);
+            if steps == 0 || !player_is_playing().await || reserve_player_ref(|player| player.alert.is_some()) {
+                return Ok(DatumRef::Void);
+            }
+            // The mouseWheel event goes to the sprite under the mouse, then to the movie
+            let (instance_ids, args) = reserve_player_mut(|player| {
+                let instance_ids = get_sprite_at(player, player.mouse_loc.0, player.mouse_loc.1, true)
+                    .and_then(|sprite_num| player.movie.score.get_sprite(sprite_num as i16))
+                    .map(|sprite| sprite.script_instance_list.clone());
+                (instance_ids, vec![player.alloc_datum(Datum::Int(steps))])
+            });
+            player_dispatch_targeted_event(&"mouseWheel".to_string(), &args, instance_ids.as_ref());
+            borrow_wheel_hook_manager_mut(|x| x.dispatch_wheel(steps));
+        }
         PlayerVMCommand::KeyDown(key, code) => {
+            let is_alert_open = reserve_player_mut(|player| {
+                if player.alert.is_none() {
+                    return false;
+                }
+                if key == "Enter" || key == "Escape" {
+                    player.dismiss_alert();
+                }
+                true
+            });
+            if is_alert_open {
+                return Ok(DatumRef::Void);
+            }
             return player_key_down(key, code).await;
         }
         PlayerVMCommand::KeyUp(key, code) => {
+            if reserve_player_ref(|player| player.alert.is_some()) {
+                return Ok(DatumRef::Void);
+            }
             return player_key_up(key, code).await;
         }
+        PlayerVMCommand::RequestCoverageSummary => {
+            reserve_player_ref(|player| {
+                JsApi::dispatch_coverage_summary(player);
+            });
+        }
         PlayerVMCommand::RequestDatum(datum_id) => {
             reserve_player_ref(|player| {
                 if let Some(datum_ref) = player.allocator.get_datum_ref(datum_id) {
@@ -393,50 +798,111 @@ pub async fn run_player_command(command: PlayerVMCommand) -> Result<DatumRef, Sc
             });
         }
         PlayerVMCommand::TriggerAlertHook => {
-            let call_params = reserve_player_mut(|player| {
-                let arg_list = vec![
-                    player.alloc_datum(Datum::String("Script Error".to_string())),
-                    player.alloc_datum(Datum::String("An error occurred in the script".to_string())),
-                ];
-                if let Some(alert_hook) = &player.movie.alert_hook {
-                    match alert_hook {
-                        ScriptReceiver::ScriptInstance(instance_ref) => {
-                            let script_instance =
-                                player.allocator.get_script_instance(&instance_ref);
-                            let script = player
-                                .movie
-                                .cast_manager
-                                .get_script_by_ref(&script_instance.script)
-                                .unwrap();
-                            let handler = script.get_own_handler_ref(&"alertHook".to_string());
-                            if let Some(handler) = handler {
-                                Some((Some(instance_ref.clone()), handler, arg_list))
-                            } else {
-                                None
-                            }
-                        }
-                        ScriptReceiver::Script(script_ref) => {
-                            let script = player
-                                .movie
-                                .cast_manager
-                                .get_script_by_ref(&script_ref)
-                                .unwrap();
-                            let handler = script.get_own_handler_ref(&"alertHook".to_string());
-                            if let Some(handler) = handler {
-                                Some((None, handler, arg_list))
-                            } else {
-                                None
-                            }
-                        }
-                    }
-                } else {
-                    None
+            player_call_alert_hook("Script error", "An error occurred in the script").await?;
+        }
+        PlayerVMCommand::SetSpriteProp(sprite_num, prop_name, value) => {
+            let result = eval_debug_value(&value).and_then(|value| sprite_set_prop(sprite_num, &prop_name, value));
+            return Ok(debug_edit_result(result));
+        }
+        PlayerVMCommand::SetMemberProp(member_ref, prop_name, value) => {
+            let result = eval_debug_value(&value)
+                .and_then(|value| CastMemberRefHandlers::set_prop(&member_ref, &prop_name, value));
+            return Ok(debug_edit_result(result));
+        }
+        PlayerVMCommand::SetGlobal(name, value) => {
+            let result = reserve_player_mut(|player| {
+                let value_ref = eval_lingo(value, player)?;
+                player.set_global(&name, value_ref);
+                JsApi::dispatch_global_list(player);
+                Ok(())
+            });
+            return Ok(debug_edit_result(result));
+        }
+        PlayerVMCommand::RequestBehaviorDescriptions(sprite_num) => {
+            // A failing getPropertyDescriptionList is reported without stopping the movie
+            let behaviors = match player_describe_sprite_behaviors(sprite_num).await {
+                Ok(behaviors) => behaviors,
+                Err(err) => {
+                    JsApi::dispatch_debug_message(&format!("getPropertyDescriptionList failed: {}", err.message));
+                    vec![]
                 }
+            };
+            reserve_player_mut(|player| {
+                JsApi::dispatch_behavior_descriptions(player, sprite_num, &behaviors);
             });
-            if let Some((receiver, handler, args)) = call_params {
-                player_call_script_handler(receiver, handler, &args).await?;
-            }
         }
     }
     Ok(DatumRef::Void)
 }
+
+/// Pixels of browser wheel movement per wheel step. Steps are positive when the wheel
+/// turns away from the user, like the wheel notches Windows reports.
+const WHEEL_STEP_PIXELS: f64 = 100.0;
+
+fn wheel_steps(delta: f64) -> i32 {
+    let steps = -(delta / WHEEL_STEP_PIXELS).round() as i32;
+    // Touchpads scroll in small deltas that still need to move lists
+    if steps == 0 && delta != 0.0 {
+        -(delta.signum() as i32)
+    } else {
+        steps
+    }
+}
+
+/// Releases the pressed hyperlink. Released over the same link, it becomes visited and the
+/// arguments of hyperlinkClicked are returned, otherwise it goes back to its previous state.
+fn player_release_hyperlink(player: &mut DirPlayer, loc: (i32, i32)) -> Option<Vec<DatumRef>> {
+    let (member_ref, index, previous_state) = player.pressed_hyperlink.take()?;
+    let is_released_on_link = TextGeometryHandlers::get_hyperlink_at(player, player.mouse_down_sprite, loc)
+        .is_some_and(|(released_member, released_index)| released_member == member_ref && released_index == index);
+    let text_member = player.movie.cast_manager.find_mut_member_by_ref(&member_ref)?.member_type.as_text_mut()?;
+    let link = text_member.hyperlinks.get_mut(index)?;
+    if !is_released_on_link {
+        link.state = previous_state;
+        return None;
+    }
+    link.state = "visited".to_string();
+    let (data, (start, end)) = (link.link.clone(), link.range);
+    let range = vec![player.alloc_datum(Datum::Int(start as i32)), player.alloc_datum(Datum::Int(end as i32))];
+    Some(vec![player.alloc_datum(Datum::String(data)), player.alloc_datum(Datum::List(DatumType::List, range, false))])
+}
+
+/// Runs the mouseDownScript or mouseUpScript, Director's primary event handlers, before
+/// the event is sent on to sprites and the movie. Returns whether the event goes on,
+/// which it doesn't once the script calls dontPassEvent.
+async fn player_run_primary_event_handler(script: Option<String>) -> bool {
+    let script = match script {
+        Some(script) => script,
+        None => return true,
+    };
+    let scope_count = reserve_player_mut(|player| {
+        player.dont_pass_event = false;
+        player.scope_count
+    });
+    if let Err(err) = player_do(&script).await {
+        player_handle_script_error(&err, scope_count).await;
+    }
+    reserve_player_mut(|player| !std::mem::take(&mut player.dont_pass_event))
+}
+
+/// Values typed into the property inspector are Lingo literals, like `10`, `"text"` or `#sym`.
+fn eval_debug_value(source: &str) -> Result<Datum, ScriptError> {
+    reserve_player_mut(|player| {
+        let value_ref = eval_lingo(source.to_owned(), player)?;
+        Ok(player.get_datum(&value_ref).clone())
+    })
+}
+
+/// Edits made from the debugger report their errors back instead of stopping the movie.
+/// Successful edits show on the stage right away, even while paused at a breakpoint.
+fn debug_edit_result(result: Result<(), ScriptError>) -> DatumRef {
+    let message = match result {
+        Ok(()) => {
+            reserve_player_mut(update_watch_expressions);
+            request_stage_redraw();
+            String::new()
+        }
+        Err(err) => err.message,
+    };
+    player_alloc_datum(Datum::String(message))
+}
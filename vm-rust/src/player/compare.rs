@@ -2,7 +2,32 @@ use log::warn;
 
 use crate::{console_warn, director::lingo::datum::Datum};
 
-use super::{allocator::{DatumAllocator, DatumAllocatorTrait}, bitmap::bitmap::PaletteRef, handlers::datum_handlers::cast_member_ref::CastMemberRefHandlers, DatumRef, ScriptError};
+use super::{allocator::{DatumAllocator, DatumAllocatorTrait}, bitmap::{bitmap::{get_system_default_palette, resolve_color_ref, PaletteRef}, palette_map::PaletteMap}, handlers::datum_handlers::{cast_member_ref::CastMemberRefHandlers, color::ColorUtils}, DatumRef, ScriptError, PLAYER_OPT};
+
+/// Lingo finds text regardless of case.
+fn fold_case(string: &str) -> Vec<char> {
+  string.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect()
+}
+
+/// The character index where `find` first appears in `string`, ignoring case.
+pub fn string_offset(find: &str, string: &str) -> Option<usize> {
+  let find = fold_case(find);
+  let string = fold_case(string);
+  if find.is_empty() {
+    return Some(0);
+  }
+  string.windows(find.len()).position(|window| window == find.as_slice())
+}
+
+/// The contains operator, which ignores case.
+pub fn string_contains(string: &str, find: &str) -> bool {
+  string_offset(find, string).is_some()
+}
+
+/// The starts operator, which ignores case.
+pub fn string_starts(string: &str, find: &str) -> bool {
+  fold_case(string).starts_with(&fold_case(find))
+}
 
 pub fn datum_equals(left: &Datum, right: &Datum, allocator: &DatumAllocator) -> Result<bool, ScriptError> {
   match (left, right) {
@@ -35,7 +60,19 @@ pub fn datum_equals(left: &Datum, right: &Datum, allocator: &DatumAllocator) ->
     (Datum::ScriptInstanceRef(left), Datum::ScriptInstanceRef(right)) => Ok(**left == **right),
     (Datum::Symbol(left), Datum::Symbol(right)) => Ok(left.eq_ignore_ascii_case(right)),
     (Datum::Void, Datum::Void) => Ok(true),
-    (Datum::ColorRef(left), Datum::ColorRef(right)) => Ok(*left == *right),
+    (Datum::Date(left), Datum::Date(right)) => Ok(left.date == right.date),
+    (Datum::ColorRef(left), Datum::ColorRef(right)) => {
+      // An RGB color equals a palette index color that shows as the same color in the active palette
+      let is_same_color = match unsafe { PLAYER_OPT.as_ref() } {
+        Some(player) => ColorUtils::to_rgb(player, left) == ColorUtils::to_rgb(player, right),
+        None => {
+          let system_palette = PaletteRef::BuiltIn(get_system_default_palette());
+          let palettes = PaletteMap::new();
+          resolve_color_ref(&palettes, left, &system_palette) == resolve_color_ref(&palettes, right, &system_palette)
+        }
+      };
+      Ok(*left == *right || (is_same_color && std::mem::discriminant(left) != std::mem::discriminant(right)))
+    },
     (Datum::Int(_), Datum::Symbol(_)) => Ok(false),
     (Datum::Void, Datum::Int(right)) => Ok(*right == 0),
     (Datum::String(_), Datum::ScriptInstanceRef(_)) => Ok(false),
@@ -117,6 +154,7 @@ pub fn datum_greater_than(left: &Datum, right: &Datum) -> Result<bool, ScriptErr
     (Datum::Float(left), Datum::Int(right)) => Ok(*left > (*right as f32)),
     (Datum::Float(left), Datum::Float(right)) => Ok(*left > *right),
     (Datum::IntPoint(left), Datum::IntPoint(right)) => Ok(left.0 > right.0 && left.1 > right.1),
+    (Datum::Date(left), Datum::Date(right)) => Ok(left.date > right.date),
     (Datum::Void, Datum::Int(_)) => Ok(false),
     _ => {
       warn!("datum_greater_than not supported for types: {} and {}", left.type_str(), right.type_str());
@@ -144,6 +182,7 @@ pub fn datum_less_than(left: &Datum, right: &Datum) -> Result<bool, ScriptError>
     (Datum::Float(left), Datum::Int(right)) => Ok(*left < (*right as f32)),
     (Datum::Float(left), Datum::Float(right)) => Ok(*left < *right),
     (Datum::IntPoint(left), Datum::IntPoint(right)) => Ok(left.0 < right.0 && left.1 < right.1),
+    (Datum::Date(left), Datum::Date(right)) => Ok(left.date < right.date),
     (Datum::String(..), Datum::String(..)) => Ok(false),
     _ => {
       warn!("datum_less_than not supported for types: {} and {}", left.type_str(), right.type_str());
@@ -0,0 +1,176 @@
+use std::collections::{HashMap, HashSet};
+
+use wasm_bindgen::JsValue;
+
+use super::{net_policy::NetRewriteRule, random::RandomGenerator, search_path::sync_search_paths, DirPlayer};
+
+/// What happens when a movie calls a global handler the player doesn't implement.
+#[derive(Default, Clone)]
+pub struct MissingHandlerPolicy {
+  /// Missing handlers return VOID with a warning instead of stopping the movie.
+  pub lenient: bool,
+  /// Lowercase names of the handlers that are skipped even when not lenient.
+  pub skipped: HashSet<String>,
+  /// Lowercase names of the handlers that stop the movie even when lenient.
+  pub fatal: HashSet<String>,
+  /// The skipped handlers that were already warned about, to warn only once.
+  pub warned: HashSet<String>,
+}
+
+impl MissingHandlerPolicy {
+  pub fn should_skip(&self, name: &str) -> bool {
+    let name = name.to_lowercase();
+    !self.fatal.contains(&name) && (self.lenient || self.skipped.contains(&name))
+  }
+
+  /// Makes a handler always skipped, always fatal, or follow `lenient` again.
+  pub fn set_override(&mut self, name: &str, skip: Option<bool>) {
+    let name = name.to_lowercase();
+    self.skipped.remove(&name);
+    self.fatal.remove(&name);
+    match skip {
+      Some(true) => { self.skipped.insert(name); }
+      Some(false) => { self.fatal.insert(name); }
+      None => {}
+    }
+  }
+}
+
+/// Fixes for a particular movie that the host provides as JSON, or that is fetched from
+/// a `.compat.json` file next to the movie, like:
+///
+/// `{"frameRate": 15, "inkFallbacks": {"41": 0}, "randomSeed": 1, "lenientMissingHandlers": true,
+/// "skipHandlers": ["getPos"], "fatalHandlers": [], "stubXtras": ["FileIO"],
+/// "hostRewrites": {"old.example.com": "new.example.com"}, "searchPaths": ["media/"],
+/// "eightBitStage": true}`
+#[derive(Default, Clone)]
+pub struct CompatibilityProfile {
+  /// Plays at this many frames per second whatever the tempo.
+  pub frame_rate: Option<u32>,
+  /// Inks drawn as another ink, for the ones that don't render right yet.
+  pub ink_fallbacks: HashMap<u32, u32>,
+  pub random_seed: Option<i32>,
+  pub lenient_missing_handlers: Option<bool>,
+  pub skip_handlers: Vec<String>,
+  pub fatal_handlers: Vec<String>,
+  /// Xtras that are reported as present, with every call returning VOID.
+  pub stub_xtras: Vec<String>,
+  pub host_rewrites: Vec<(String, String)>,
+  pub search_paths: Vec<String>,
+  /// Turns the 8-bit stage emulation on or off for the movie.
+  pub eight_bit_stage: Option<bool>,
+}
+
+impl CompatibilityProfile {
+  pub fn from_json(json: &str) -> Result<CompatibilityProfile, String> {
+    let profile = js_sys::JSON::parse(json).map_err(|_| "Compatibility profile is not valid JSON".to_string())?;
+    if !profile.is_object() {
+      return Err("Compatibility profile is not an object".to_string());
+    }
+    let get = |key: &str| js_sys::Reflect::get(&profile, &JsValue::from_str(key)).ok().filter(|value| !value.is_undefined() && !value.is_null());
+    let strings = |key: &str| get(key)
+      .map(|value| js_sys::Array::from(&value).iter().filter_map(|item| item.as_string()).collect())
+      .unwrap_or_default();
+    let entries = |key: &str| get(key)
+      .map(|value| {
+        let object = js_sys::Object::from(value);
+        js_sys::Object::keys(&object).iter()
+          .filter_map(|key| Some((key.as_string()?, js_sys::Reflect::get(&object, &key).ok()?)))
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default();
+
+    Ok(CompatibilityProfile {
+      frame_rate: get("frameRate").and_then(|value| value.as_f64()).filter(|rate| *rate > 0.0).map(|rate| rate as u32),
+      ink_fallbacks: entries("inkFallbacks").into_iter()
+        .filter_map(|(ink, fallback)| Some((ink.parse().ok()?, fallback.as_f64()? as u32)))
+        .collect(),
+      random_seed: get("randomSeed").and_then(|value| value.as_f64()).map(|seed| seed as i32),
+      lenient_missing_handlers: get("lenientMissingHandlers").and_then(|value| value.as_bool()),
+      skip_handlers: strings("skipHandlers"),
+      fatal_handlers: strings("fatalHandlers"),
+      stub_xtras: strings("stubXtras"),
+      host_rewrites: entries("hostRewrites").into_iter()
+        .filter_map(|(from, to)| Some((from, to.as_string()?)))
+        .collect(),
+      search_paths: strings("searchPaths"),
+      eight_bit_stage: get("eightBitStage").and_then(|value| value.as_bool()),
+    })
+  }
+
+  pub fn map_ink(&self, ink: u32) -> u32 {
+    self.ink_fallbacks.get(&ink).copied().unwrap_or(ink)
+  }
+
+  pub fn stubs_xtra(&self, name: &str) -> bool {
+    self.stub_xtras.iter().any(|xtra| xtra.eq_ignore_ascii_case(name))
+  }
+}
+
+/// The settings a profile changes, as they were before it was applied.
+pub struct CompatibilityBaseline {
+  random: RandomGenerator,
+  emulate_8bit_stage: bool,
+  missing_handler_policy: MissingHandlerPolicy,
+  rewrite_rules: Vec<NetRewriteRule>,
+  search_paths: Vec<String>,
+}
+
+/// Puts back the settings the last applied profile changed.
+fn restore_compatibility_baseline(player: &mut DirPlayer) {
+  let baseline = match player.compatibility_baseline.take() {
+    Some(baseline) => baseline,
+    None => return,
+  };
+  player.random = baseline.random;
+  player.emulate_8bit_stage = baseline.emulate_8bit_stage;
+  player.missing_handler_policy = baseline.missing_handler_policy;
+  player.net_manager.policy.rewrite_rules = baseline.rewrite_rules;
+  player.net_manager.search_paths = baseline.search_paths;
+  player.search_path_list = None;
+}
+
+/// Sets up the player for its profile, once the movie is loaded and before it plays.
+/// A profile replaces the one applied before it, and without one the player goes back
+/// to the settings it had before any was applied.
+pub fn player_apply_compatibility_profile(player: &mut DirPlayer) {
+  restore_compatibility_baseline(player);
+  let profile = match player.compatibility_profile.clone() {
+    Some(profile) => profile,
+    None => return,
+  };
+  sync_search_paths(player);
+  player.compatibility_baseline = Some(CompatibilityBaseline {
+    random: player.random.clone(),
+    emulate_8bit_stage: player.emulate_8bit_stage,
+    missing_handler_policy: player.missing_handler_policy.clone(),
+    rewrite_rules: player.net_manager.policy.rewrite_rules.clone(),
+    search_paths: player.net_manager.search_paths.clone(),
+  });
+  if let Some(seed) = profile.random_seed {
+    player.random.set_seed(seed);
+  }
+  if let Some(enabled) = profile.eight_bit_stage {
+    player.emulate_8bit_stage = enabled;
+  }
+  if let Some(lenient) = profile.lenient_missing_handlers {
+    player.missing_handler_policy.lenient = lenient;
+  }
+  for name in &profile.skip_handlers {
+    player.missing_handler_policy.set_override(name, Some(true));
+  }
+  for name in &profile.fatal_handlers {
+    player.missing_handler_policy.set_override(name, Some(false));
+  }
+  for (from, to) in profile.host_rewrites {
+    player.net_manager.policy.add_rewrite_rule(from, to);
+  }
+  if !profile.search_paths.is_empty() {
+    for path in profile.search_paths {
+      if !player.net_manager.search_paths.contains(&path) {
+        player.net_manager.search_paths.push(path);
+      }
+    }
+    player.search_path_list = None;
+  }
+}
@@ -0,0 +1,234 @@
+use futures::future::{FutureExt, LocalBoxFuture};
+use pest::iterators::Pair;
+
+use crate::{director::lingo::datum::{datum_bool, Datum, DatumType}, js_api::JsApi};
+
+use super::{
+  bytecode::{get_set::GetSetUtils, string::StringBytecodeHandler},
+  datum_formatting::format_datum,
+  datum_operations::{add_datums, divide_datums, modulo_datums, multiply_datums, subtract_datums},
+  eval::{compare_datums, eval_lingo_pair, parse_console_statement, resolve_debug_name, Rule},
+  handlers::datum_handlers::player_call_datum_handler,
+  player_call_global_handler, reserve_player_mut, reserve_player_ref,
+  scope::ScopeRef,
+  script::{get_obj_prop, player_set_obj_prop},
+  DatumRef, ScriptError,
+};
+
+/// Runs a statement typed into the Lingo console, like Director's Message window, and
+/// returns the formatted result. Statements run in a scope of their own, so they see
+/// globals and can call movie handlers and the behaviors of the current frame.
+pub async fn player_eval_console_lingo(source: &str) -> Result<String, ScriptError> {
+  let statement = parse_console_statement(source.trim())?;
+  let scope_ref = reserve_player_mut(|player| player.push_scope())?;
+  let result = eval_console_statement(statement, scope_ref).await;
+  reserve_player_mut(|player| player.pop_scope());
+  let result = result?;
+  Ok(reserve_player_ref(|player| {
+    match result {
+      Some(result) => format_datum(&result, player),
+      None => String::new(),
+    }
+  }))
+}
+
+async fn eval_console_statement(statement: Pair<'_, Rule>, scope_ref: ScopeRef) -> Result<Option<DatumRef>, ScriptError> {
+  match statement.as_rule() {
+    Rule::console_put => {
+      let value = eval_console_pair(statement.into_inner().nth(1).unwrap(), scope_ref).await?;
+      reserve_player_ref(|player| JsApi::dispatch_debug_message(&format_datum(&value, player)));
+      Ok(Some(value))
+    }
+    Rule::console_assign => {
+      let mut inner = statement.into_inner();
+      let target = inner.next().unwrap();
+      let value = eval_console_pair(inner.next().unwrap(), scope_ref).await?;
+      assign_console_target(target, &value, scope_ref).await?;
+      Ok(None)
+    }
+    _ => Ok(Some(eval_console_pair(statement, scope_ref).await?)),
+  }
+}
+
+/// Plain names assign globals, `the` names set movie properties and a trailing `.name`
+/// sets a property of the object before it.
+async fn assign_console_target(target: Pair<'_, Rule>, value: &DatumRef, scope_ref: ScopeRef) -> Result<(), ScriptError> {
+  let mut parts = target.into_inner().collect::<Vec<_>>();
+  let last = parts.pop().unwrap();
+  if parts.is_empty() {
+    return match last.as_rule() {
+      Rule::console_var => reserve_player_mut(|player| {
+        player.set_global(last.as_str().trim(), value.clone());
+        JsApi::dispatch_global_list(player);
+        Ok(())
+      }),
+      Rule::console_the => reserve_player_mut(|player| {
+        let prop_name = last.into_inner().nth(1).unwrap().as_str();
+        let value = player.get_datum(value).clone();
+        player.set_movie_prop(prop_name, value)
+      }),
+      _ => Err(ScriptError::new(format!("Cannot assign to {}", last.as_str().trim()))),
+    };
+  }
+  let mut member = last.into_inner();
+  let prop_name = member.next().unwrap().as_str().to_owned();
+  if member.next().is_some() {
+    return Err(ScriptError::new(format!("Cannot assign to a call of {}", prop_name)));
+  }
+  let mut obj = eval_console_pair(parts.remove(0), scope_ref).await?;
+  for part in parts {
+    obj = eval_console_suffix(obj, part, scope_ref).await?;
+  }
+  player_set_obj_prop(&obj, &prop_name, value).await
+}
+
+/// Boxed, since expressions nest.
+fn eval_console_pair(pair: Pair<'_, Rule>, scope_ref: ScopeRef) -> LocalBoxFuture<'_, Result<DatumRef, ScriptError>> {
+  eval_console_expr(pair, scope_ref).boxed_local()
+}
+
+async fn eval_console_expr(pair: Pair<'_, Rule>, scope_ref: ScopeRef) -> Result<DatumRef, ScriptError> {
+  match pair.as_rule() {
+    Rule::console_or | Rule::console_and => {
+      let is_or = pair.as_rule() == Rule::console_or;
+      let mut operands = pair.into_inner().filter(|inner| !matches!(inner.as_rule(), Rule::debug_or_op | Rule::debug_and_op)).peekable();
+      let first = operands.next().unwrap();
+      if operands.peek().is_none() {
+        return eval_console_pair(first, scope_ref).await;
+      }
+      let mut result = None;
+      for operand in std::iter::once(first).chain(operands) {
+        let value = eval_console_pair(operand, scope_ref).await?;
+        let value = reserve_player_ref(|player| player.get_datum(&value).to_bool())?;
+        result = Some(match result {
+          Some(previous) if is_or => previous || value,
+          Some(previous) => previous && value,
+          None => value,
+        });
+      }
+      Ok(reserve_player_mut(|player| player.alloc_datum(datum_bool(result.unwrap_or(false)))))
+    }
+    Rule::console_not => {
+      let mut inner = pair.into_inner();
+      let first = inner.next().unwrap();
+      if first.as_rule() != Rule::debug_not_op {
+        return eval_console_pair(first, scope_ref).await;
+      }
+      let value = eval_console_pair(inner.next().unwrap(), scope_ref).await?;
+      reserve_player_mut(|player| {
+        let value = player.get_datum(&value).to_bool()?;
+        Ok(player.alloc_datum(datum_bool(!value)))
+      })
+    }
+    Rule::console_comparison => {
+      let mut inner = pair.into_inner();
+      let left = eval_console_pair(inner.next().unwrap(), scope_ref).await?;
+      let op = match inner.next() {
+        Some(op) => op.as_str().to_owned(),
+        None => return Ok(left),
+      };
+      let right = eval_console_pair(inner.next().unwrap(), scope_ref).await?;
+      reserve_player_mut(|player| {
+        let result = compare_datums(&op, &left, &right, player)?;
+        Ok(player.alloc_datum(datum_bool(result)))
+      })
+    }
+    Rule::console_product | Rule::console_sum | Rule::console_concat => {
+      let mut inner = pair.into_inner();
+      let mut result = eval_console_pair(inner.next().unwrap(), scope_ref).await?;
+      while let Some(op) = inner.next() {
+        let right = eval_console_pair(inner.next().unwrap(), scope_ref).await?;
+        result = reserve_player_mut(|player| {
+          let left_datum = player.get_datum(&result).clone();
+          let right_datum = player.get_datum(&right).clone();
+          let value = match op.as_str() {
+            "+" => add_datums(left_datum, right_datum, player)?,
+            "-" => subtract_datums(left_datum, right_datum, player)?,
+            "*" => multiply_datums(left_datum, right_datum, player)?,
+            "/" => divide_datums(left_datum, right_datum, player)?,
+            name if name.eq_ignore_ascii_case("mod") => modulo_datums(left_datum, right_datum, player)?,
+            separator => {
+              let left_str = StringBytecodeHandler::get_datum_concat_value(&left_datum, player)?;
+              let right_str = StringBytecodeHandler::get_datum_concat_value(&right_datum, player)?;
+              let separator = if separator == "&&" { " " } else { "" };
+              Datum::String(format!("{}{}{}", left_str, separator, right_str))
+            }
+          };
+          Ok(player.alloc_datum(value))
+        })?;
+      }
+      Ok(result)
+    }
+    Rule::console_postfix => {
+      let mut inner = pair.into_inner();
+      let mut value = eval_console_pair(inner.next().unwrap(), scope_ref).await?;
+      for suffix in inner {
+        value = eval_console_suffix(value, suffix, scope_ref).await?;
+      }
+      Ok(value)
+    }
+    Rule::console_the => {
+      let prop_name = pair.into_inner().nth(1).unwrap().as_str().to_owned();
+      reserve_player_mut(|player| GetSetUtils::get_the_built_in_prop(player, scope_ref, &prop_name))
+    }
+    Rule::console_call => {
+      let mut inner = pair.into_inner();
+      let handler_name = inner.next().unwrap().as_str().to_owned();
+      let args = eval_console_args(inner.next().unwrap(), scope_ref).await?;
+      player_call_global_handler(&handler_name, &args).await
+    }
+    Rule::console_var => {
+      let name = pair.as_str().trim().to_owned();
+      reserve_player_mut(|player| resolve_debug_name(&name, scope_ref, player))
+    }
+    Rule::console_list => {
+      let items = eval_console_args(pair, scope_ref).await?;
+      Ok(reserve_player_mut(|player| player.alloc_datum(Datum::List(DatumType::List, items, false))))
+    }
+    Rule::console_prop_list => {
+      let mut props = vec![];
+      for prop_pair in pair.into_inner() {
+        let mut prop_pair = prop_pair.into_inner();
+        let key = eval_console_pair(prop_pair.next().unwrap(), scope_ref).await?;
+        let value = eval_console_pair(prop_pair.next().unwrap(), scope_ref).await?;
+        props.push((key, value));
+      }
+      Ok(reserve_player_mut(|player| player.alloc_datum(Datum::PropList(props, false))))
+    }
+    _ => reserve_player_mut(|player| eval_lingo_pair(pair, player)),
+  }
+}
+
+/// `.name` reads a property, `.name(args)` calls a handler of the object and `[index]`
+/// reads an item.
+async fn eval_console_suffix(obj: DatumRef, suffix: Pair<'_, Rule>, scope_ref: ScopeRef) -> Result<DatumRef, ScriptError> {
+  match suffix.as_rule() {
+    Rule::console_member => {
+      let mut inner = suffix.into_inner();
+      let name = inner.next().unwrap().as_str().to_owned();
+      match inner.next() {
+        Some(args) => {
+          let args = eval_console_args(args, scope_ref).await?;
+          player_call_datum_handler(&obj, &name, &args).await
+        }
+        None => reserve_player_mut(|player| get_obj_prop(player, &obj, &name)),
+      }
+    }
+    _ => {
+      let index = eval_console_pair(suffix.into_inner().next().unwrap(), scope_ref).await?;
+      let handler_name = reserve_player_ref(|player| match player.get_datum(&index) {
+        Datum::Int(_) => "getAt",
+        _ => "getProp",
+      });
+      player_call_datum_handler(&obj, &handler_name.to_owned(), &vec![index]).await
+    }
+  }
+}
+
+async fn eval_console_args(args: Pair<'_, Rule>, scope_ref: ScopeRef) -> Result<Vec<DatumRef>, ScriptError> {
+  let mut values = vec![];
+  for arg in args.into_inner() {
+    values.push(eval_console_pair(arg, scope_ref).await?);
+  }
+  Ok(values)
+}
@@ -0,0 +1,128 @@
+use crate::{director::lingo::datum::Datum, io::base64::encode_base64};
+
+use super::{
+  bitmap::{manager::BitmapRef, png::encode_png_with_mask},
+  handlers::datum_handlers::{cast_member_ref::CastMemberRefHandlers, prop_list::PropListUtils},
+  score::get_sprite_at,
+  sprite::CursorRef,
+  DirPlayer, ScriptError,
+};
+
+/// The largest cursor image browsers take, larger ones are drawn on the stage instead.
+const MAX_CSS_CURSOR_SIZE: u16 = 128;
+/// How long each frame of an animated cursor is shown.
+const CURSOR_FRAME_MS: i64 = 100;
+
+/// The bitmap a member cursor shows at the moment.
+#[derive(Clone, PartialEq)]
+pub struct CursorImage {
+  pub image_ref: BitmapRef,
+  pub image_version: u32,
+  pub mask_ref: Option<BitmapRef>,
+  pub hot_spot: (i32, i32),
+}
+
+impl CursorImage {
+  pub fn fits_css_cursor(&self, player: &DirPlayer) -> bool {
+    player.bitmap_manager.get_bitmap(self.image_ref)
+      .is_some_and(|bitmap| bitmap.width <= MAX_CSS_CURSOR_SIZE && bitmap.height <= MAX_CSS_CURSOR_SIZE)
+  }
+}
+
+/// Reads what is given to `cursor` or set as the cursor of a sprite: a system cursor
+/// number, a member, `[cursor, mask]`, a list of members for an animated cursor, or
+/// `[#member: m, #mask: m, #hotSpot: point(x, y)]`.
+pub fn cursor_ref_from_datum(player: &DirPlayer, value: &Datum) -> Result<CursorRef, ScriptError> {
+  match value {
+    Datum::Int(id) => Ok(CursorRef::System(*id)),
+    Datum::CastMember(_) => Ok(CursorRef::Member(vec![cursor_slot_number(value)?], None)),
+    Datum::List(_, items, _) => {
+      let slot_numbers = items.iter()
+        .map(|item| cursor_slot_number(player.get_datum(item)))
+        .collect::<Result<Vec<_>, _>>()?;
+      Ok(CursorRef::Member(slot_numbers, None))
+    }
+    Datum::PropList(props, _) => {
+      let get_prop = |name: &str| {
+        let value_ref = PropListUtils::get_by_concrete_key(props, &Datum::Symbol(name.to_string()), &player.allocator)?;
+        Ok::<_, ScriptError>(player.get_datum(&value_ref))
+      };
+      let mut slot_numbers = vec![cursor_slot_number(get_prop("member")?)?];
+      let mask = get_prop("mask")?;
+      if !matches!(mask, Datum::Void) {
+        slot_numbers.push(cursor_slot_number(mask)?);
+      }
+      let hot_spot = match get_prop("hotSpot")? {
+        Datum::Void => None,
+        hot_spot => Some(hot_spot.to_int_point()?),
+      };
+      Ok(CursorRef::Member(slot_numbers, hot_spot))
+    }
+    _ => Err(ScriptError::new(format!("Invalid cursor {}", value.type_str()))),
+  }
+}
+
+fn cursor_slot_number(value: &Datum) -> Result<i32, ScriptError> {
+  match value {
+    Datum::CastMember(member_ref) => Ok(CastMemberRefHandlers::get_cast_slot_number(member_ref.cast_lib as u32, member_ref.cast_member as u32) as i32),
+    _ => value.int_value(),
+  }
+}
+
+/// The cursor of the sprite under the mouse, or the one set for the movie.
+pub fn get_active_cursor_ref(player: &DirPlayer) -> &CursorRef {
+  get_sprite_at(player, player.mouse_loc.0, player.mouse_loc.1, false)
+    .and_then(|sprite_num| player.movie.score.get_sprite(sprite_num as i16))
+    .and_then(|sprite| sprite.cursor_ref.as_ref())
+    .unwrap_or(&player.cursor)
+}
+
+/// The bitmap the active cursor shows now, when it is made of members. `[cursor, mask]`
+/// takes the second member as the mask when it's 1-bit, otherwise every member is a
+/// frame of an animated cursor.
+pub fn get_cursor_image(player: &DirPlayer, now_ms: i64) -> Option<CursorImage> {
+  let (slot_numbers, hot_spot) = match get_active_cursor_ref(player) {
+    CursorRef::Member(slot_numbers, hot_spot) => (slot_numbers, hot_spot),
+    CursorRef::System(_) => return None,
+  };
+  let bitmap_member = |slot_number: i32| {
+    player.movie.cast_manager.find_member_by_slot_number(slot_number as u32)
+      .and_then(|member| member.member_type.as_bitmap())
+  };
+  let mask_ref = slot_numbers.get(1)
+    .filter(|_| slot_numbers.len() == 2)
+    .and_then(|slot_number| bitmap_member(*slot_number))
+    .map(|mask| mask.image_ref)
+    .filter(|mask_ref| player.bitmap_manager.get_bitmap(*mask_ref).is_some_and(|mask| mask.bit_depth == 1));
+  let frames = if mask_ref.is_some() { &slot_numbers[..1] } else { &slot_numbers[..] };
+  if frames.is_empty() {
+    return None;
+  }
+  let frame = frames[(now_ms / CURSOR_FRAME_MS).max(0) as usize % frames.len()];
+  let member = bitmap_member(frame)?;
+  player.bitmap_manager.get_bitmap(member.image_ref)?;
+  Some(CursorImage {
+    image_ref: member.image_ref,
+    image_version: player.bitmap_manager.get_bitmap_version(member.image_ref),
+    mask_ref,
+    hot_spot: hot_spot.unwrap_or((member.reg_point.0 as i32, member.reg_point.1 as i32)),
+  })
+}
+
+/// The CSS cursor showing the image, with the pixels outside its mask left out. Without
+/// a mask the background color of the image is left out.
+pub fn get_css_cursor(player: &DirPlayer, image: &CursorImage) -> String {
+  let bitmap = match player.bitmap_manager.get_bitmap(image.image_ref) {
+    Some(bitmap) => bitmap,
+    None => return String::new(),
+  };
+  let mask = image.mask_ref
+    .and_then(|mask_ref| player.bitmap_manager.get_bitmap(mask_ref))
+    .unwrap_or(bitmap)
+    .to_mask();
+  let png = encode_png_with_mask(bitmap, &player.movie.cast_manager.palettes(), &mask);
+  // Browsers ignore cursors with a hot spot outside the image
+  let hot_x = image.hot_spot.0.clamp(0, bitmap.width.max(1) as i32 - 1);
+  let hot_y = image.hot_spot.1.clamp(0, bitmap.height.max(1) as i32 - 1);
+  format!("url(data:image/png;base64,{}) {} {}, auto", encode_base64(&png), hot_x, hot_y)
+}
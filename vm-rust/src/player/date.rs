@@ -0,0 +1,67 @@
+use chrono::{Datelike, Duration, Local, NaiveDate, Timelike};
+
+use super::ScriptError;
+
+/// A Lingo date object: a calendar day, with the seconds since its midnight.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LingoDate {
+  pub date: NaiveDate,
+  pub seconds: i32,
+}
+
+impl LingoDate {
+  /// The systemDate, the current day and time of the computer.
+  pub fn now() -> LingoDate {
+    let now = Local::now();
+    LingoDate {
+      date: now.date_naive(),
+      seconds: now.num_seconds_from_midnight() as i32,
+    }
+  }
+
+  pub fn from_ymd(year: i32, month: i32, day: i32) -> Result<LingoDate, ScriptError> {
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+      .map(|date| LingoDate { date, seconds: 0 })
+      .ok_or_else(|| ScriptError::new(format!("Invalid date {year}-{month}-{day}")))
+  }
+
+  /// Reads a date written as "YYYYMMDD", the form date() takes a single string or integer in.
+  pub fn parse(value: &str) -> Result<LingoDate, ScriptError> {
+    let value = value.trim();
+    if value.len() != 8 || !value.chars().all(|c| c.is_ascii_digit()) {
+      return Err(ScriptError::new(format!("Invalid date {value}, expected YYYYMMDD")));
+    }
+    let year = value[0..4].parse().unwrap();
+    let month = value[4..6].parse().unwrap();
+    let day = value[6..8].parse().unwrap();
+    Self::from_ymd(year, month, day)
+  }
+
+  pub fn year(&self) -> i32 {
+    self.date.year()
+  }
+
+  pub fn month(&self) -> i32 {
+    self.date.month() as i32
+  }
+
+  pub fn day(&self) -> i32 {
+    self.date.day() as i32
+  }
+
+  pub fn add_days(&self, days: i32) -> Result<LingoDate, ScriptError> {
+    self.date.checked_add_signed(Duration::days(days as i64))
+      .map(|date| LingoDate { date, seconds: self.seconds })
+      .ok_or_else(|| ScriptError::new(format!("Date out of range adding {days} days")))
+  }
+
+  /// Whole days from another date to this one, which is what subtracting dates gives.
+  pub fn days_since(&self, other: &LingoDate) -> i32 {
+    (self.date - other.date).num_days() as i32
+  }
+
+  /// Moves the date to another year, month or day, keeping the other parts.
+  pub fn with_ymd(&self, year: i32, month: i32, day: i32) -> Result<LingoDate, ScriptError> {
+    Ok(LingoDate { seconds: self.seconds, ..Self::from_ymd(year, month, day)? })
+  }
+}
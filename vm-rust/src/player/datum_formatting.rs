@@ -2,21 +2,36 @@ use crate::{director::lingo::datum::Datum, player::{allocator::ScriptInstanceAll
 
 use super::{DatumRef, DirPlayer};
 
+/// Quotes a string so `value()` reads it back, splicing in QUOTE for embedded quotes.
+pub fn format_string_literal(s: &str) -> String {
+  s.split('"').map(|part| format!("\"{part}\"")).collect::<Vec<_>>().join(" & QUOTE & ")
+}
+
+/// Formats a float with the floatPrecision. The decimal separator is always a period,
+/// so floats in lists read back with value().
+pub fn format_float(f: f32, player: &DirPlayer) -> String {
+  match player.float_precision {
+    1 => format!("{:.1}", f),
+    2 => format!("{:.2}", f),
+    3 => format!("{:.3}", f),
+    4 => format!("{:.4}", f),
+    5 => format!("{:.5}", f),
+    6 => format!("{:.6}", f),
+    _ => f.to_string(),
+  }
+}
+
+/// A float turned into a string by string() or concatenation, which uses the decimal
+/// separator of the locale.
+pub fn float_to_string(f: f32, player: &DirPlayer) -> String {
+  player.movie.locale.format_float(format_float(f, player))
+}
+
 pub fn format_concrete_datum(datum: &Datum, player: &DirPlayer) -> String {
   match datum {
-    Datum::String(s) => format!("\"{s}\""),
+    Datum::String(s) => format_string_literal(s),
     Datum::Int(i) => i.to_string(),
-    Datum::Float(f) => {
-      match player.float_precision {
-        1 => format!("{:.1}", f),
-        2 => format!("{:.2}", f),
-        3 => format!("{:.3}", f),
-        4 => format!("{:.4}", f),
-        5 => format!("{:.5}", f),
-        6 => format!("{:.6}", f),
-        _ => f.to_string(),
-      }
-    },
+    Datum::Float(f) => format_float(*f, player),
     Datum::List(_, items, _) => {
       let formatted_items: Vec<String> = items.iter().map(|x| format_datum(x, player)).collect();
       format!("[{}]", formatted_items.join(", "))
@@ -33,7 +48,7 @@ pub fn format_concrete_datum(datum: &Datum, player: &DirPlayer) -> String {
       let formatted_entries: Vec<String> = entries.iter().map(|(k, v)| format!("{}: {}", format_datum(k, player), format_datum(v, player))).collect();
       format!("[{}]", formatted_entries.join(", "))
     }
-    Datum::StringChunk(..) => format!("\"{}\"", datum.string_value().unwrap_or("!!!ERR!!!".to_string())),
+    Datum::StringChunk(..) => format_string_literal(&datum.string_value().unwrap_or("!!!ERR!!!".to_string())),
     Datum::ScriptRef(member_ref) => {
       let script = player.movie.cast_manager.get_script_by_ref(&member_ref).unwrap();
       format!("(script {})", script.name)
@@ -101,6 +116,12 @@ pub fn format_concrete_datum(datum: &Datum, player: &DirPlayer) -> String {
     Datum::SoundRef(_) => {
       format!("<_sound>")
     }
+    Datum::WindowRef(name) => {
+      format!("(window \"{name}\")")
+    }
+    Datum::Date(date) => {
+      format!("date( {}, {}, {} )", date.year(), date.month(), date.day())
+    }
   }
 }
 
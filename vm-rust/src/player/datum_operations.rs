@@ -2,17 +2,19 @@ use std::cmp::min;
 
 use crate::director::lingo::datum::{Datum, DatumType};
 
-use super::{sprite::ColorRef, DirPlayer, ScriptError};
+use super::{datum_formatting::{format_concrete_datum, format_datum}, handlers::datum_handlers::color::ColorUtils, sprite::ColorRef, DirPlayer, ScriptError};
 
+/// Integer arithmetic wraps around like Director's 32-bit signed integers, which
+/// hash and random number routines in movies rely on.
 pub fn add_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Result<Datum, ScriptError> {
   match (&left, &right) {
     (Datum::Void, some) => Ok(some.clone()),
     (some, Datum::Void) => Ok(some.clone()),
-    (Datum::Int(a), Datum::Int(b)) => Ok(Datum::Int(a + b)),
+    (Datum::Int(a), Datum::Int(b)) => Ok(Datum::Int(a.wrapping_add(*b))),
     (Datum::Float(a), Datum::Float(b)) => Ok(Datum::Float(a + b)),
     (Datum::Float(a), Datum::Int(b)) => Ok(Datum::Float(a + (*b as f32))),
     (Datum::Int(a), Datum::Float(b)) => Ok(Datum::Float((*a as f32) + b)),
-    (Datum::IntRect(a), Datum::IntRect(b)) => Ok(Datum::IntRect((a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3))),
+    (Datum::IntRect(a), Datum::IntRect(b)) => Ok(Datum::IntRect((a.0.wrapping_add(b.0), a.1.wrapping_add(b.1), a.2.wrapping_add(b.2), a.3.wrapping_add(b.3)))),
     (Datum::IntRect(a), Datum::List(_, ref_list, _)) => {
       if ref_list.len() == 4 {
         let b = ref_list.iter()
@@ -20,7 +22,7 @@ pub fn add_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Result<D
             player.get_datum(r).int_value()
               .map(|x| x as i32)
           ).collect::<Result<Vec<i32>, ScriptError>>()?;
-        Ok(Datum::IntRect((a.0 + b[0], a.1 + b[1], a.2 + b[2], a.3 + b[3])))
+        Ok(Datum::IntRect((a.0.wrapping_add(b[0]), a.1.wrapping_add(b[1]), a.2.wrapping_add(b[2]), a.3.wrapping_add(b[3]))))
       } else {
         Err(ScriptError::new(format!("Invalid list length for add_datums: {}", ref_list.len())))
       }
@@ -41,7 +43,7 @@ pub fn add_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Result<D
       for r in list {
         let datum = player.get_datum(r);
         let result_datum = match datum {
-          Datum::Int(n) => Datum::Int(n + i),
+          Datum::Int(n) => Datum::Int(n.wrapping_add(*i)),
           Datum::Float(n) => Datum::Float(n + *i as f32),
           _ => return Err(ScriptError::new(format!("Invalid list element for add_datums: {}", r))),
         };
@@ -49,7 +51,29 @@ pub fn add_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Result<D
       }
       Ok(Datum::List(DatumType::List, result_refs, false))
     },
-    (Datum::IntPoint(a), Datum::IntPoint(b)) => Ok(Datum::IntPoint((a.0 + b.0, a.1 + b.1))),
+    (Datum::PropList(list_a, ..), Datum::PropList(list_b, ..)) => {
+      // Director does list arithmetic by position, not by property: [#a: 1] + [#b: 2] is
+      // [#a: 3]. The result keeps the properties of the left list and is as long as the
+      // shorter list, so the properties of the right one are not merged in.
+      let mut result = Vec::with_capacity(min(list_a.len(), list_b.len()));
+      for ((key, a), (_, b)) in list_a.iter().zip(list_b.iter()) {
+        let a = player.get_datum(a).clone();
+        let b = player.get_datum(b).clone();
+        let result_datum = add_datums(a, b, player)?;
+        result.push((key.clone(), player.alloc_datum(result_datum)));
+      }
+      Ok(Datum::PropList(result, false))
+    },
+    (Datum::PropList(list, ..), Datum::Int(_) | Datum::Float(_)) => {
+      let mut result = Vec::with_capacity(list.len());
+      for (key, value) in list {
+        let value = player.get_datum(value).clone();
+        let result_datum = add_datums(value, right.clone(), player)?;
+        result.push((key.clone(), player.alloc_datum(result_datum)));
+      }
+      Ok(Datum::PropList(result, false))
+    },
+    (Datum::IntPoint(a), Datum::IntPoint(b)) => Ok(Datum::IntPoint((a.0.wrapping_add(b.0), a.1.wrapping_add(b.1)))),
     (Datum::IntPoint(a), Datum::List(_, ref_list, _)) => {
       if ref_list.len() == 2 {
         let b = ref_list.iter()
@@ -58,17 +82,25 @@ pub fn add_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Result<D
               .map(|x| x as i32)
           )
           .collect::<Result<Vec<i32>, ScriptError>>()?;
-        Ok(Datum::IntPoint((a.0 + b[0], a.1 + b[1])))
+        Ok(Datum::IntPoint((a.0.wrapping_add(b[0]), a.1.wrapping_add(b[1]))))
       } else {
         Err(ScriptError::new(format!("Invalid list length for add_datums: {}", ref_list.len())))
       }
     },
-    (Datum::IntPoint(a), Datum::Int(b)) => Ok(Datum::IntPoint((a.0 + *b as i32, a.1 + *b as i32))),
+    (Datum::IntPoint(a), Datum::Int(b)) => Ok(Datum::IntPoint((a.0.wrapping_add(*b), a.1.wrapping_add(*b)))),
+    (Datum::Int(a), Datum::IntPoint(b)) => Ok(Datum::IntPoint((a.wrapping_add(b.0), a.wrapping_add(b.1)))),
+    (Datum::IntRect(a), Datum::Int(b)) => Ok(Datum::IntRect((a.0.wrapping_add(*b), a.1.wrapping_add(*b), a.2.wrapping_add(*b), a.3.wrapping_add(*b)))),
+    (Datum::Int(a), Datum::IntRect(b)) => Ok(Datum::IntRect((a.wrapping_add(b.0), a.wrapping_add(b.1), a.wrapping_add(b.2), a.wrapping_add(b.3)))),
+    (Datum::Date(date), Datum::Int(days)) | (Datum::Int(days), Datum::Date(date)) => Ok(Datum::Date(date.add_days(*days)?)),
     (Datum::ColorRef(a), Datum::ColorRef(b)) => {
+      // Components are clamped, and a palette index color added to an RGB one is added by its RGB value
       match (a, b) {
-        (ColorRef::PaletteIndex(a), ColorRef::PaletteIndex(b)) => Ok(Datum::ColorRef(ColorRef::PaletteIndex(a + b))),
-        (ColorRef::Rgb(a_r, a_g, a_b), ColorRef::Rgb(b_r, b_g, b_b)) => Ok(Datum::ColorRef(ColorRef::Rgb(a_r + b_r, a_g + b_g, a_b + b_b))),
-        _ => Err(ScriptError::new(format!("Invalid operands for add_datums: {:?}, {:?}", a, b))),
+        (ColorRef::PaletteIndex(a), ColorRef::PaletteIndex(b)) => Ok(Datum::ColorRef(ColorRef::PaletteIndex(a.wrapping_add(*b)))),
+        _ => {
+          let (a_r, a_g, a_b) = ColorUtils::to_rgb(player, a);
+          let (b_r, b_g, b_b) = ColorUtils::to_rgb(player, b);
+          Ok(Datum::ColorRef(ColorRef::Rgb(a_r.saturating_add(b_r), a_g.saturating_add(b_g), a_b.saturating_add(b_b))))
+        }
       }
     },
     (Datum::String(left), Datum::Int(right)) => {
@@ -122,6 +154,26 @@ pub fn subtract_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Res
       }
       Ok(Datum::List(DatumType::List, result, false))
     },
+    (Datum::PropList(list_a, ..), Datum::PropList(list_b, ..)) => {
+      // By position like addition, keeping the properties of the left list
+      let mut result = Vec::with_capacity(min(list_a.len(), list_b.len()));
+      for ((key, a), (_, b)) in list_a.iter().zip(list_b.iter()) {
+        let a = player.get_datum(a).clone();
+        let b = player.get_datum(b).clone();
+        let result_datum = subtract_datums(a, b, player)?;
+        result.push((key.clone(), player.alloc_datum(result_datum)));
+      }
+      Ok(Datum::PropList(result, false))
+    },
+    (Datum::PropList(list, ..), Datum::Int(_) | Datum::Float(_)) => {
+      let mut result = Vec::with_capacity(list.len());
+      for (key, value) in list {
+        let value = player.get_datum(value).clone();
+        let result_datum = subtract_datums(value, right.clone(), player)?;
+        result.push((key.clone(), player.alloc_datum(result_datum)));
+      }
+      Ok(Datum::PropList(result, false))
+    },
     (Datum::IntPoint(a), Datum::IntPoint(b)) => Ok(Datum::IntPoint((a.0.wrapping_sub(b.0), a.1.wrapping_sub(b.1)))),
     (Datum::IntPoint(a), Datum::List(_, ref_list, _)) => {
       if ref_list.len() == 2 {
@@ -133,11 +185,23 @@ pub fn subtract_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Res
       }
     },
     (Datum::Int(a), Datum::IntPoint(b)) => Ok(Datum::IntPoint(((*a as i32).wrapping_sub(b.0), (*a as i32).wrapping_sub(b.1)))),
+    (Datum::IntPoint(a), Datum::Int(b)) => Ok(Datum::IntPoint((a.0.wrapping_sub(*b), a.1.wrapping_sub(*b)))),
+    (Datum::IntRect(a), Datum::Int(b)) => Ok(Datum::IntRect((a.0.wrapping_sub(*b), a.1.wrapping_sub(*b), a.2.wrapping_sub(*b), a.3.wrapping_sub(*b)))),
+    (Datum::Int(a), Datum::IntRect(b)) => Ok(Datum::IntRect((a.wrapping_sub(b.0), a.wrapping_sub(b.1), a.wrapping_sub(b.2), a.wrapping_sub(b.3)))),
+    // Subtracting dates gives the days between them
+    (Datum::Date(a), Datum::Date(b)) => Ok(Datum::Int(a.days_since(b))),
+    (Datum::Date(date), Datum::Int(days)) => {
+      let days = days.checked_neg().ok_or_else(|| ScriptError::new(format!("Date out of range subtracting {days} days")))?;
+      Ok(Datum::Date(date.add_days(days)?))
+    }
     (Datum::ColorRef(a), Datum::ColorRef(b)) => {
       match (a, b) {
         (ColorRef::PaletteIndex(a), ColorRef::PaletteIndex(b)) => Ok(Datum::ColorRef(ColorRef::PaletteIndex(a.wrapping_sub(*b)))),
-        (ColorRef::Rgb(a_r, a_g, a_b), ColorRef::Rgb(b_r, b_g, b_b)) => Ok(Datum::ColorRef(ColorRef::Rgb(a_r.wrapping_sub(*b_r), a_g.wrapping_sub(*b_g), a_b.wrapping_sub(*b_b)))),
-        _ => Err(ScriptError::new(format!("Invalid operands for subtract_datums: {:?}, {:?}", a, b))),
+        _ => {
+          let (a_r, a_g, a_b) = ColorUtils::to_rgb(player, a);
+          let (b_r, b_g, b_b) = ColorUtils::to_rgb(player, b);
+          Ok(Datum::ColorRef(ColorRef::Rgb(a_r.saturating_sub(b_r), a_g.saturating_sub(b_g), a_b.saturating_sub(b_b))))
+        }
       }
     },
     (Datum::String(left), Datum::Int(right)) => {
@@ -160,3 +224,140 @@ pub fn subtract_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Res
     _ => Err(ScriptError::new(format!("Invalid operands for subtract_datums: {}, {}", left.type_str(), right.type_str()))),
   }
 }
+
+/// The number a string stands for in arithmetic. Like Director, the spaces it's padded
+/// with are ignored, whole numbers stay integers and anything else counts as 0.
+fn string_number(value: &str) -> Datum {
+  let value = value.trim();
+  if let Ok(n) = value.parse::<i32>() {
+    Datum::Int(n)
+  } else if let Ok(n) = value.parse::<f32>() {
+    Datum::Float(n)
+  } else {
+    Datum::Int(0)
+  }
+}
+
+pub fn multiply_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Result<Datum, ScriptError> {
+  let left = if left.is_string() { string_number(&left.string_value()?) } else { left };
+  let right = if right.is_string() { string_number(&right.string_value()?) } else { right };
+  match (&left, &right) {
+    (Datum::Int(left), Datum::Int(right)) => Ok(Datum::Int(left.wrapping_mul(*right))),
+    (Datum::Int(left), Datum::Float(right)) => Ok(Datum::Float((*left as f32) * right)),
+    (Datum::Float(left), Datum::Int(right)) => Ok(Datum::Float(*left * (*right as f32))),
+    (Datum::Float(left), Datum::Float(right)) => Ok(Datum::Float(left * right)),
+    (Datum::IntRect((x1, y1, x2, y2)), Datum::Int(right)) => Ok(Datum::IntRect((x1.wrapping_mul(*right), y1.wrapping_mul(*right), x2.wrapping_mul(*right), y2.wrapping_mul(*right)))),
+    (Datum::IntPoint((x, y)), Datum::Int(right)) => Ok(Datum::IntPoint((x.wrapping_mul(*right), y.wrapping_mul(*right)))),
+    (Datum::Int(left), Datum::IntRect((x1, y1, x2, y2))) => Ok(Datum::IntRect((left.wrapping_mul(*x1), left.wrapping_mul(*y1), left.wrapping_mul(*x2), left.wrapping_mul(*y2)))),
+    (Datum::Int(left), Datum::IntPoint((x, y))) => Ok(Datum::IntPoint((left.wrapping_mul(*x), left.wrapping_mul(*y)))),
+    (Datum::List(_, list, _), Datum::Float(right)) => {
+      let mut new_list = vec![];
+      for item in list {
+        let item_datum = player.get_datum(item);
+        let result_datum = match item_datum {
+          Datum::Int(n) => Datum::Float((*n as f32) * right),
+          Datum::Float(n) => Datum::Float(n * right),
+          _ => return Err(ScriptError::new(format!("Mul operator in list only works with ints and floats. Given: {}", format_datum(item, player)))),
+        };
+        new_list.push(result_datum);
+      }
+      let mut ref_list = vec![];
+      for item in new_list {
+        ref_list.push(player.alloc_datum(item));
+      }
+      Ok(Datum::List(DatumType::List, ref_list, false))
+    }
+    _ => Err(ScriptError::new(format!("Mul operator only works with ints and floats. Given: {}, {}", format_concrete_datum(&left, player), format_concrete_datum(&right, player)))),
+  }
+}
+
+pub fn divide_datums(left: Datum, right: Datum, _player: &mut DirPlayer) -> Result<Datum, ScriptError> {
+  match (&left, &right) {
+    (Datum::Int(_), Datum::Int(0)) => Err(ScriptError::new("Divide by zero".to_string())),
+    (Datum::Int(left), Datum::Int(right)) => Ok(Datum::Int(left.wrapping_div(*right))),
+    (Datum::IntRect(_) | Datum::IntPoint(_), Datum::Int(0)) => Err(ScriptError::new("Divide by zero".to_string())),
+    (Datum::IntRect((x1, y1, x2, y2)), Datum::Int(right)) => Ok(Datum::IntRect((x1.wrapping_div(*right), y1.wrapping_div(*right), x2.wrapping_div(*right), y2.wrapping_div(*right)))),
+    (Datum::IntPoint((x, y)), Datum::Int(right)) => Ok(Datum::IntPoint((x.wrapping_div(*right), y.wrapping_div(*right)))),
+    (Datum::Int(left), Datum::Float(right)) => Ok(Datum::Float((*left as f32) / right)),
+    (Datum::Float(left), Datum::Int(right)) => Ok(Datum::Float(*left / (*right as f32))),
+    (Datum::Float(left), Datum::Float(right)) => Ok(Datum::Float(left / right)),
+    (Datum::Int(left), Datum::String(right)) => {
+      let right = right.parse::<f32>().map_err(|_| ScriptError::new(format!("Cannot divide int by string: {}", right)))?;
+      Ok(Datum::Float((*left as f32) / right))
+    }
+    (Datum::Float(left), Datum::String(right)) => {
+      let right = right.parse::<f32>().map_err(|_| ScriptError::new(format!("Cannot divide float by string: {}", right)))?;
+      Ok(Datum::Float(left / right))
+    }
+    (Datum::String(left), Datum::Int(right)) => {
+      let left_float = left.parse::<f32>().unwrap_or(0.0);
+      Ok(Datum::Float(left_float / (*right as f32)))
+    }
+    (Datum::String(left), Datum::Float(right)) => {
+      let left_float = left.parse::<f32>().unwrap_or(0.0);
+      Ok(Datum::Float(left_float / right))
+    }
+    (Datum::Void, _) => Ok(Datum::Int(0)),
+    _ => Err(ScriptError::new(format!("Div operator only works with ints and floats (Provided: {} and {})", left.type_str(), right.type_str()))),
+  }
+}
+
+fn safe_mod_int(left: i32, right: i32) -> i32 {
+  if right == 0 {
+    0
+  } else {
+    left.wrapping_rem(right)
+  }
+}
+
+fn safe_mod_float(left: f32, right: f32) -> f32 {
+  if right == 0.0 {
+    0.0
+  } else {
+    left % right
+  }
+}
+
+pub fn modulo_datums(left: Datum, right: Datum, player: &mut DirPlayer) -> Result<Datum, ScriptError> {
+  match (&left, &right) {
+    (Datum::Int(left), Datum::Int(right)) => Ok(Datum::Int(safe_mod_int(*left, *right))),
+    (Datum::Int(left), Datum::Float(right)) => Ok(Datum::Float(safe_mod_float(*left as f32, *right))),
+    (Datum::Float(left), Datum::Int(right)) => Ok(Datum::Float(safe_mod_float(*left, *right as f32))),
+    (Datum::Float(left), Datum::Float(right)) => Ok(Datum::Float(safe_mod_float(*left, *right))),
+    (Datum::List(_, list, _), Datum::Float(right)) => {
+      let mut new_list = vec![];
+      for item in list {
+        let item_datum = player.get_datum(item);
+        let result_datum = match item_datum {
+          Datum::Int(n) => Datum::Int(safe_mod_float(*n as f32, *right) as i32),
+          Datum::Float(n) => Datum::Int(safe_mod_float(*n, *right) as i32),
+          _ => return Err(ScriptError::new(format!("Modulus operator in list only works with ints and floats. Given: {}", format_datum(item, player)))),
+        };
+        new_list.push(result_datum);
+      }
+      let mut ref_list = vec![];
+      for item in new_list {
+        ref_list.push(player.alloc_datum(item));
+      }
+      Ok(Datum::List(DatumType::List, ref_list, false))
+    }
+    (Datum::List(_, list, _), Datum::Int(right)) => {
+      let mut new_list = vec![];
+      for item in list {
+        let item_datum = player.get_datum(item);
+        let result_datum = match item_datum {
+          Datum::Int(n) => Datum::Int(safe_mod_int(*n, *right)),
+          Datum::Float(n) => Datum::Int(safe_mod_float(*n, *right as f32) as i32),
+          _ => return Err(ScriptError::new(format!("Modulus operator in list only works with ints and floats. Given: {}", format_datum(item, player)))),
+        };
+        new_list.push(result_datum);
+      }
+      let mut ref_list = vec![];
+      for item in new_list {
+        ref_list.push(player.alloc_datum(item));
+      }
+      Ok(Datum::List(DatumType::List, ref_list, false))
+    }
+    _ => Err(ScriptError::new(format!("Modulus operator only works with ints and floats (given {} and {})", left.type_str(), right.type_str()))),
+  }
+}
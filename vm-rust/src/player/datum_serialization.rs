@@ -0,0 +1,277 @@
+use binary_reader::{BinaryReader, Endian};
+
+use crate::director::lingo::datum::{Datum, DatumType};
+
+use super::{sprite::ColorRef, DirPlayer, ScriptError};
+
+// Director serializes Lingo values as a big-endian type tag followed by the value.
+// The same layout is used by behavior initializers in the score and by Shockwave
+// Multiuser Server messages.
+const SERIALIZED_TYPE_VOID: i16 = 0;
+const SERIALIZED_TYPE_INTEGER: i16 = 1;
+const SERIALIZED_TYPE_SYMBOL: i16 = 2;
+const SERIALIZED_TYPE_STRING: i16 = 3;
+const SERIALIZED_TYPE_FLOAT: i16 = 6;
+const SERIALIZED_TYPE_LIST: i16 = 7;
+const SERIALIZED_TYPE_POINT: i16 = 8;
+const SERIALIZED_TYPE_RECT: i16 = 9;
+const SERIALIZED_TYPE_PROP_LIST: i16 = 10;
+const SERIALIZED_TYPE_COLOR: i16 = 18;
+
+// Colors are stored as a kind byte followed by either three RGB bytes or a palette
+// index padded to the same length.
+const SERIALIZED_COLOR_RGB: u8 = 0;
+const SERIALIZED_COLOR_PALETTE_INDEX: u8 = 1;
+
+const MAX_SERIALIZED_DEPTH: usize = 100;
+
+fn write_i16(buf: &mut Vec<u8>, value: i16) {
+  buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_i32(buf: &mut Vec<u8>, value: i32) {
+  buf.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Strings are length-prefixed and padded to an even number of bytes.
+pub fn write_serialized_string(buf: &mut Vec<u8>, value: &str) {
+  let bytes = value
+    .chars()
+    .map(|c| if (c as u32) < 256 { c as u8 } else { b'?' })
+    .collect::<Vec<u8>>();
+  write_i32(buf, bytes.len() as i32);
+  buf.extend_from_slice(&bytes);
+  if bytes.len() % 2 != 0 {
+    buf.push(0);
+  }
+}
+
+pub fn write_serialized_datum(player: &DirPlayer, datum: &Datum, buf: &mut Vec<u8>) -> Result<(), ScriptError> {
+  match datum {
+    Datum::Void | Datum::Null => write_i16(buf, SERIALIZED_TYPE_VOID),
+    Datum::Int(value) => {
+      write_i16(buf, SERIALIZED_TYPE_INTEGER);
+      write_i32(buf, *value);
+    }
+    Datum::Float(value) => {
+      write_i16(buf, SERIALIZED_TYPE_FLOAT);
+      buf.extend_from_slice(&(*value as f64).to_be_bytes());
+    }
+    Datum::Symbol(value) => {
+      write_i16(buf, SERIALIZED_TYPE_SYMBOL);
+      write_serialized_string(buf, value);
+    }
+    Datum::String(_) | Datum::StringChunk(..) => {
+      write_i16(buf, SERIALIZED_TYPE_STRING);
+      write_serialized_string(buf, &datum.string_value()?);
+    }
+    Datum::List(_, items, _) => {
+      write_i16(buf, SERIALIZED_TYPE_LIST);
+      write_i32(buf, items.len() as i32);
+      for item in items {
+        write_serialized_datum(player, player.get_datum(item), buf)?;
+      }
+    }
+    Datum::PropList(pairs, _) => {
+      write_i16(buf, SERIALIZED_TYPE_PROP_LIST);
+      write_i32(buf, pairs.len() as i32);
+      for (key, value) in pairs {
+        write_serialized_datum(player, player.get_datum(key), buf)?;
+        write_serialized_datum(player, player.get_datum(value), buf)?;
+      }
+    }
+    Datum::IntPoint((x, y)) => {
+      write_i16(buf, SERIALIZED_TYPE_POINT);
+      write_serialized_datum(player, &Datum::Int(*x), buf)?;
+      write_serialized_datum(player, &Datum::Int(*y), buf)?;
+    }
+    Datum::IntRect((left, top, right, bottom)) => {
+      write_i16(buf, SERIALIZED_TYPE_RECT);
+      for value in [left, top, right, bottom] {
+        write_serialized_datum(player, &Datum::Int(*value), buf)?;
+      }
+    }
+    Datum::ColorRef(color) => {
+      write_i16(buf, SERIALIZED_TYPE_COLOR);
+      match color {
+        ColorRef::Rgb(r, g, b) => buf.extend_from_slice(&[SERIALIZED_COLOR_RGB, *r, *g, *b]),
+        ColorRef::PaletteIndex(index) => buf.extend_from_slice(&[SERIALIZED_COLOR_PALETTE_INDEX, *index, 0, 0]),
+      }
+    }
+    _ => {
+      return Err(ScriptError::new(format!(
+        "Cannot serialize datum of type {}",
+        datum.type_str()
+      )))
+    }
+  }
+  Ok(())
+}
+
+pub fn read_serialized_string(reader: &mut BinaryReader) -> Result<String, ScriptError> {
+  let length = reader.read_i32().map_err(|_| ScriptError::new("Truncated serialized string".to_string()))?;
+  if length < 0 {
+    return Err(ScriptError::new(format!("Invalid serialized string length {}", length)));
+  }
+  let bytes = reader
+    .read_bytes(length as usize)
+    .map_err(|_| ScriptError::new("Truncated serialized string".to_string()))?;
+  let result = bytes.iter().map(|b| *b as char).collect();
+  if length % 2 != 0 {
+    let _ = reader.read_u8();
+  }
+  Ok(result)
+}
+
+pub fn read_serialized_datum(player: &mut DirPlayer, reader: &mut BinaryReader) -> Result<Datum, ScriptError> {
+  read_nested_serialized_datum(player, reader, 0)
+}
+
+/// Serialized values come from the network and from movie files, so how deeply lists
+/// may nest is capped before the recursion can run out of stack.
+fn read_nested_serialized_datum(player: &mut DirPlayer, reader: &mut BinaryReader, depth: usize) -> Result<Datum, ScriptError> {
+  if depth > MAX_SERIALIZED_DEPTH {
+    return Err(ScriptError::new("Serialized datum is nested too deeply".to_string()));
+  }
+  let truncated = |_| ScriptError::new("Truncated serialized datum".to_string());
+  let value_type = reader.read_i16().map_err(truncated)?;
+  match value_type {
+    SERIALIZED_TYPE_VOID => Ok(Datum::Void),
+    SERIALIZED_TYPE_INTEGER => Ok(Datum::Int(reader.read_i32().map_err(truncated)?)),
+    SERIALIZED_TYPE_FLOAT => Ok(Datum::Float(reader.read_f64().map_err(truncated)? as f32)),
+    SERIALIZED_TYPE_SYMBOL => Ok(Datum::Symbol(read_serialized_string(reader)?)),
+    SERIALIZED_TYPE_STRING => Ok(Datum::String(read_serialized_string(reader)?)),
+    SERIALIZED_TYPE_LIST => {
+      let count = reader.read_i32().map_err(truncated)?;
+      let mut items = vec![];
+      for _ in 0..count {
+        let item = read_nested_serialized_datum(player, reader, depth + 1)?;
+        items.push(player.alloc_datum(item));
+      }
+      Ok(Datum::List(DatumType::List, items, false))
+    }
+    SERIALIZED_TYPE_PROP_LIST => {
+      let count = reader.read_i32().map_err(truncated)?;
+      let mut pairs = vec![];
+      for _ in 0..count {
+        let key = read_nested_serialized_datum(player, reader, depth + 1)?;
+        let value = read_nested_serialized_datum(player, reader, depth + 1)?;
+        pairs.push((player.alloc_datum(key), player.alloc_datum(value)));
+      }
+      Ok(Datum::PropList(pairs, false))
+    }
+    SERIALIZED_TYPE_POINT => {
+      let x = read_nested_serialized_datum(player, reader, depth + 1)?.int_value()?;
+      let y = read_nested_serialized_datum(player, reader, depth + 1)?.int_value()?;
+      Ok(Datum::IntPoint((x, y)))
+    }
+    SERIALIZED_TYPE_RECT => {
+      let left = read_nested_serialized_datum(player, reader, depth + 1)?.int_value()?;
+      let top = read_nested_serialized_datum(player, reader, depth + 1)?.int_value()?;
+      let right = read_nested_serialized_datum(player, reader, depth + 1)?.int_value()?;
+      let bottom = read_nested_serialized_datum(player, reader, depth + 1)?.int_value()?;
+      Ok(Datum::IntRect((left, top, right, bottom)))
+    }
+    SERIALIZED_TYPE_COLOR => {
+      let bytes = reader.read_bytes(4).map_err(truncated)?;
+      match bytes[0] {
+        SERIALIZED_COLOR_RGB => Ok(Datum::ColorRef(ColorRef::Rgb(bytes[1], bytes[2], bytes[3]))),
+        SERIALIZED_COLOR_PALETTE_INDEX => Ok(Datum::ColorRef(ColorRef::PaletteIndex(bytes[1]))),
+        kind => Err(ScriptError::new(format!("Invalid serialized color kind {}", kind))),
+      }
+    }
+    _ => Err(ScriptError::new(format!("Unsupported serialized datum type {}", value_type))),
+  }
+}
+
+/// Decodes a standalone serialized value, such as a behavior initializer blob.
+pub fn deserialize_datum(player: &mut DirPlayer, data: &[u8]) -> Result<Datum, ScriptError> {
+  let mut reader = BinaryReader::from_u8(data);
+  reader.set_endian(Endian::Big);
+  read_serialized_datum(player, &mut reader)
+}
+
+#[cfg(test)]
+mod tests {
+  use wasm_bindgen_test::*;
+
+  use crate::{player::{datum_formatting::format_concrete_datum, eval::eval_lingo, reserve_player_mut, DatumRef}, test_utils::init_player};
+
+  use super::*;
+
+  /// Serializes `value` the way behavior initializers and Multiuser messages store it,
+  /// decodes it again and returns both values as Lingo prints them.
+  fn serialization_round_trip(value: Datum) -> (String, String) {
+    init_player();
+    reserve_player_mut(|player| {
+      let mut buf = vec![];
+      write_serialized_datum(player, &value, &mut buf)?;
+      let decoded = deserialize_datum(player, &buf)?;
+      Ok::<_, ScriptError>((format_concrete_datum(&value, player), format_concrete_datum(&decoded, player)))
+    }).unwrap()
+  }
+
+  fn parse_literal(source: &str) -> Datum {
+    init_player();
+    reserve_player_mut(|player| {
+      // Literals that don't parse come back as VOID
+      let value_ref = eval_lingo(source.to_string(), player).unwrap();
+      assert!(source == "VOID" || value_ref != DatumRef::Void, "{} didn't parse", source);
+      player.get_datum(&value_ref).clone()
+    })
+  }
+
+  #[wasm_bindgen_test]
+  fn serialization_round_trips_scalars() {
+    for source in ["42", "-7", "1.25", "#symbol", "\"a string\"", "\"odd\"", "\"\"", "VOID"] {
+      let (original, decoded) = serialization_round_trip(parse_literal(source));
+      assert_eq!(decoded, original, "{}", source);
+    }
+  }
+
+  #[wasm_bindgen_test]
+  fn serialization_round_trips_colors_points_and_rects() {
+    for source in ["rgb(255, 128, 0)", "point(-3, 12)", "rect(1, 2, 300, 400)"] {
+      let (original, decoded) = serialization_round_trip(parse_literal(source));
+      assert_eq!(decoded, original, "{}", source);
+    }
+    let (original, decoded) = serialization_round_trip(Datum::ColorRef(ColorRef::PaletteIndex(35)));
+    assert_eq!(decoded, original);
+  }
+
+  #[wasm_bindgen_test]
+  fn serialization_round_trips_nested_lists_and_prop_lists() {
+    let (original, decoded) = serialization_round_trip(parse_literal(
+      "[1, [2.5, #two, [\"three\"]], [#a: [point(1, 2), rect(0, 0, 10, 10)], #b: [#c: rgb(1, 2, 3)]], []]",
+    ));
+    assert_eq!(decoded, original);
+    let (original, decoded) = serialization_round_trip(parse_literal("[#name: \"x\", #items: [1, 2], #empty: [:]]"));
+    assert_eq!(decoded, original);
+  }
+
+  #[wasm_bindgen_test]
+  fn serialization_round_trip_copies_lists() {
+    let original = parse_literal("[[1, 2]]");
+    reserve_player_mut(|player| {
+      let mut buf = vec![];
+      write_serialized_datum(player, &original, &mut buf).unwrap();
+      let decoded = deserialize_datum(player, &buf).unwrap();
+      let inner_ref = decoded.to_list().unwrap()[0].clone();
+      let appended = player.alloc_datum(Datum::Int(3));
+      player.get_datum_mut(&inner_ref).to_list_mut().unwrap().1.push(appended);
+      assert_eq!(format_concrete_datum(&original, player), "[[1, 2]]");
+      assert_eq!(format_concrete_datum(&decoded, player), "[[1, 2, 3]]");
+    });
+  }
+
+  #[wasm_bindgen_test]
+  fn deserializing_rejects_lists_nested_too_deeply() {
+    init_player();
+    let mut data = vec![];
+    for _ in 0..10_000 {
+      data.extend_from_slice(&[0, SERIALIZED_TYPE_LIST as u8, 0, 0, 0, 1]);
+    }
+    let result = reserve_player_mut(|player| deserialize_datum(player, &data));
+    assert!(result.is_err_and(|err| err.message.contains("nested too deeply")));
+  }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeSet;
+
+use fxhash::FxHashMap;
+use wasm_bindgen::JsValue;
+
+use crate::{
+  director::chunks::handler::HandlerDef,
+  js_api::{JsSerializable, JsUtils, ToJsValue},
+  player::{cast_lib::CastMemberRef, cast_manager::CastManager, script::{Script, ScriptHandlerRef}},
+};
+
+/// Records the bytecode offsets executed by each handler, so contributors can see which
+/// code paths of a movie the VM actually runs.
+pub struct CoverageRecorder {
+  executed_positions: FxHashMap<ScriptHandlerRef, BTreeSet<usize>>,
+}
+
+impl CoverageRecorder {
+  pub fn new() -> CoverageRecorder {
+    CoverageRecorder {
+      executed_positions: FxHashMap::default(),
+    }
+  }
+
+  pub fn record(&mut self, handler_ref: &ScriptHandlerRef, bytecode_pos: usize) {
+    if let Some(positions) = self.executed_positions.get_mut(handler_ref) {
+      positions.insert(bytecode_pos);
+    } else {
+      self.executed_positions.insert(handler_ref.clone(), BTreeSet::from([bytecode_pos]));
+    }
+  }
+
+  /// Lists every handler of the loaded scripts along with the offsets that ran,
+  /// including handlers that were never called.
+  pub fn to_json(&self, cast_manager: &CastManager) -> String {
+    let handlers = js_sys::Array::new();
+    self.for_each_handler(cast_manager, |script, handler_name, handler, executed| {
+      let executed_array = js_sys::Array::new();
+      for pos in executed.into_iter().flat_map(|x| x.iter()) {
+        executed_array.push(&JsValue::from_f64(*pos as f64));
+      }
+
+      let entry = js_sys::Map::new();
+      entry.str_set("script", &JsValue::from_str(&script.name));
+      entry.str_set("castLib", &JsValue::from_f64(script.member_ref.cast_lib as f64));
+      entry.str_set("castMember", &JsValue::from_f64(script.member_ref.cast_member as f64));
+      entry.str_set("handler", &JsValue::from_str(handler_name));
+      entry.str_set("bytecodeCount", &JsValue::from_f64(handler.bytecode_array.len() as f64));
+      entry.str_set("executedPositions", &executed_array);
+      handlers.push(&js_sys::Object::from_entries(&entry).unwrap());
+    });
+    js_sys::JSON::stringify_with_replacer_and_space(&handlers, &JsValue::NULL, &JsValue::from_f64(2.0))
+      .map(String::from)
+      .unwrap_or_default()
+  }
+
+  /// Aggregates the coverage of each script member: how many of its handlers were
+  /// called, how many of its bytecodes ran and which offset ranges of each handler ran.
+  pub fn script_summaries(&self, cast_manager: &CastManager) -> Vec<js_sys::Object> {
+    let mut summaries: Vec<(CastMemberRef, js_sys::Map, js_sys::Array, ScriptCoverageCounts)> = vec![];
+    self.for_each_handler(cast_manager, |script, handler_name, handler, executed| {
+      if summaries.last().is_none_or(|(member_ref, ..)| member_ref != &script.member_ref) {
+        let entry = js_sys::Map::new();
+        entry.str_set("script", &JsValue::from_str(&script.name));
+        entry.str_set("castLib", &JsValue::from_f64(script.member_ref.cast_lib as f64));
+        entry.str_set("castMember", &JsValue::from_f64(script.member_ref.cast_member as f64));
+        summaries.push((script.member_ref.clone(), entry, js_sys::Array::new(), ScriptCoverageCounts::default()));
+      }
+      let (_, _, handler_entries, counts) = summaries.last_mut().unwrap();
+
+      let executed_ranges = js_sys::Array::new();
+      let mut executed_count = 0;
+      let mut range_start = None;
+      let mut range_end = 0;
+      for bytecode in &handler.bytecode_array {
+        if executed.is_some_and(|positions| positions.contains(&bytecode.pos)) {
+          executed_count += 1;
+          range_start.get_or_insert(bytecode.pos);
+          range_end = bytecode.pos;
+        } else if let Some(start) = range_start.take() {
+          executed_ranges.push(&vec![start, range_end].to_js_value());
+        }
+      }
+      if let Some(start) = range_start {
+        executed_ranges.push(&vec![start, range_end].to_js_value());
+      }
+
+      counts.handler_count += 1;
+      counts.bytecode_count += handler.bytecode_array.len();
+      counts.executed_bytecode_count += executed_count;
+      if executed_count > 0 {
+        counts.executed_handler_count += 1;
+      }
+
+      let handler_entry = js_sys::Map::new();
+      handler_entry.str_set("handler", &JsValue::from_str(handler_name));
+      handler_entry.str_set("bytecodeCount", &JsValue::from_f64(handler.bytecode_array.len() as f64));
+      handler_entry.str_set("executedBytecodeCount", &JsValue::from_f64(executed_count as f64));
+      handler_entry.str_set("executedRanges", &executed_ranges);
+      handler_entries.push(&handler_entry.to_js_object());
+    });
+    summaries
+      .into_iter()
+      .map(|(_, entry, handler_entries, counts)| {
+        entry.str_set("handlerCount", &JsValue::from_f64(counts.handler_count as f64));
+        entry.str_set("executedHandlerCount", &JsValue::from_f64(counts.executed_handler_count as f64));
+        entry.str_set("bytecodeCount", &JsValue::from_f64(counts.bytecode_count as f64));
+        entry.str_set("executedBytecodeCount", &JsValue::from_f64(counts.executed_bytecode_count as f64));
+        entry.str_set("handlers", &handler_entries);
+        entry.to_js_object()
+      })
+      .collect()
+  }
+
+  /// Calls `f` for every handler of the loaded scripts, script by script, with the
+  /// offsets that ran in it.
+  fn for_each_handler<F>(&self, cast_manager: &CastManager, mut f: F)
+  where
+    F: FnMut(&Script, &str, &HandlerDef, Option<&BTreeSet<usize>>),
+  {
+    let executed_positions = self.executed_positions
+      .iter()
+      .map(|((member_ref, handler_name), positions)| ((member_ref.clone(), handler_name.to_lowercase()), positions))
+      .collect::<FxHashMap<_, _>>();
+    for cast in &cast_manager.casts {
+      for script in cast.scripts.values() {
+        for handler_name in &script.handler_names {
+          let handler = match script.get_own_handler(handler_name) {
+            Some(handler) => handler,
+            None => continue,
+          };
+          let executed = executed_positions.get(&(script.member_ref.clone(), handler_name.to_lowercase())).copied();
+          f(script, handler_name, handler, executed);
+        }
+      }
+    }
+  }
+}
+
+#[derive(Default)]
+struct ScriptCoverageCounts {
+  handler_count: usize,
+  executed_handler_count: usize,
+  bytecode_count: usize,
+  executed_bytecode_count: usize,
+}
@@ -0,0 +1,138 @@
+use std::collections::VecDeque;
+
+use fxhash::FxHashMap;
+
+use crate::player::{datum_ref::DatumRef, scope::Scope};
+
+/// How many bytecodes can be stepped back over.
+pub const MAX_STEP_HISTORY: usize = 1000;
+
+/// The state of a scope right before one of its bytecodes ran. Datums are kept by
+/// reference, so lists and other values changed in place aren't rolled back.
+pub struct StepSnapshot {
+  pub invocation_id: u32,
+  pub bytecode_index: usize,
+  pub stack: Vec<DatumRef>,
+  pub locals: FxHashMap<String, DatumRef>,
+  pub args: Vec<DatumRef>,
+  pub loop_return_indices: Vec<usize>,
+  pub return_value: DatumRef,
+  /// Globals the bytecode changed and their previous values, None if it created them.
+  pub changed_globals: Vec<(String, Option<DatumRef>)>,
+}
+
+impl StepSnapshot {
+  pub fn new(invocation_id: u32, scope: &Scope) -> StepSnapshot {
+    StepSnapshot {
+      invocation_id,
+      bytecode_index: scope.bytecode_index,
+      stack: scope.stack.clone(),
+      locals: scope.locals.clone(),
+      args: scope.args.clone(),
+      loop_return_indices: scope.loop_return_indices.clone(),
+      return_value: scope.return_value.clone(),
+      changed_globals: vec![],
+    }
+  }
+
+  pub fn restore(self, scope: &mut Scope, globals: &mut FxHashMap<String, DatumRef>) {
+    scope.bytecode_index = self.bytecode_index;
+    scope.stack = self.stack;
+    scope.locals = self.locals;
+    scope.args = self.args;
+    scope.loop_return_indices = self.loop_return_indices;
+    scope.return_value = self.return_value;
+    for (name, value) in self.changed_globals {
+      match value {
+        Some(value) => globals.insert(name, value),
+        None => globals.remove(&name),
+      };
+    }
+  }
+}
+
+/// The last bytecodes run by handlers being debugged, so the debugger can step
+/// backwards within the paused handler.
+pub struct StepHistory {
+  snapshots: VecDeque<StepSnapshot>,
+  next_invocation_id: u32,
+  /// Globals set while bytecodes are being recorded, with their previous values.
+  global_changes: Vec<(String, Option<DatumRef>)>,
+  /// Bytecodes being recorded. A call runs the bytecodes of the handler it calls while
+  /// its own is still being recorded.
+  open_steps: usize,
+}
+
+impl StepHistory {
+  pub fn new() -> StepHistory {
+    StepHistory {
+      snapshots: VecDeque::new(),
+      next_invocation_id: 1,
+      global_changes: vec![],
+      open_steps: 0,
+    }
+  }
+
+  /// Starts recording the globals a bytecode sets, returning where its changes start.
+  pub fn begin_step(&mut self) -> usize {
+    self.open_steps += 1;
+    self.global_changes.len()
+  }
+
+  pub fn is_recording(&self) -> bool {
+    self.open_steps > 0
+  }
+
+  pub fn record_global_change(&mut self, name: String, previous: Option<DatumRef>) {
+    if self.is_recording() {
+      self.global_changes.push((name, previous));
+    }
+  }
+
+  /// Forgets the bytecodes a script error interrupted before they could be pushed.
+  pub fn abandon_steps(&mut self) {
+    self.open_steps = 0;
+    self.global_changes.clear();
+  }
+
+  /// Identifies a call of a handler, so stepping back never crosses into another call.
+  pub fn begin_invocation(&mut self) -> u32 {
+    let invocation_id = self.next_invocation_id;
+    self.next_invocation_id = self.next_invocation_id.wrapping_add(1);
+    invocation_id
+  }
+
+  pub fn end_invocation(&mut self, invocation_id: u32) {
+    self.snapshots.retain(|snapshot| snapshot.invocation_id != invocation_id);
+  }
+
+  /// Stores the snapshot along with the globals the bytecode set since `begin_step`.
+  pub fn push(&mut self, mut snapshot: StepSnapshot, changes_start: usize) {
+    for (name, previous) in &self.global_changes[changes_start.min(self.global_changes.len())..] {
+      // Only the value from before the bytecode ran is restored
+      if !snapshot.changed_globals.iter().any(|(changed_name, _)| changed_name == name) {
+        snapshot.changed_globals.push((name.to_owned(), previous.clone()));
+      }
+    }
+    self.open_steps = self.open_steps.saturating_sub(1);
+    if self.open_steps == 0 {
+      self.global_changes.clear();
+    }
+    if self.snapshots.len() >= MAX_STEP_HISTORY {
+      self.snapshots.pop_front();
+    }
+    self.snapshots.push_back(snapshot);
+  }
+
+  /// Takes the latest snapshot if it belongs to the given call.
+  pub fn pop(&mut self, invocation_id: u32) -> Option<StepSnapshot> {
+    match self.snapshots.back() {
+      Some(snapshot) if snapshot.invocation_id == invocation_id => self.snapshots.pop_back(),
+      _ => None,
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.snapshots.clear();
+  }
+}
@@ -1,14 +1,27 @@
+pub mod coverage;
+pub mod history;
+
 use manual_future::ManualFutureCompleter;
 
+use log::warn;
+
 use crate::js_api::JsApi;
 
-use super::{cast_lib::CastMemberRef, script::ScriptHandlerRef};
+use super::{cast_lib::CastMemberRef, datum_ref::DatumRef, eval::eval_debug_expr, scope::ScopeRef, script::ScriptHandlerRef, DirPlayer};
 
 #[derive(Clone)]
 pub struct Breakpoint {
   pub script_name: String,
   pub handler_name: String,
   pub bytecode_index: usize,
+  /// Lingo expression that has to be true for the breakpoint to pause, e.g. `spriteNum = 12`.
+  pub condition: Option<String>,
+}
+
+/// An expression shown in the debugger, re-evaluated every time execution pauses.
+pub struct WatchExpression {
+  pub expression: String,
+  pub value: Result<DatumRef, String>,
 }
 
 pub struct BreakpointContext {
@@ -16,17 +29,24 @@ pub struct BreakpointContext {
   pub script_ref: CastMemberRef,
   pub handler_ref: ScriptHandlerRef,
   pub bytecode_index: usize,
+  /// The call of the handler that's paused, see `StepHistory::begin_invocation`.
+  pub invocation_id: u32,
   pub completer: ManualFutureCompleter<()>,
 }
 
 pub struct BreakpointManager {
   pub breakpoints: Vec<Breakpoint>,
+  /// Pauses at the next bytecode run, wherever it is.
+  pub step_requested: bool,
+  pub watches: Vec<WatchExpression>,
 }
 
 impl BreakpointManager {
   pub fn new() -> BreakpointManager {
     BreakpointManager {
       breakpoints: vec![],
+      step_requested: false,
+      watches: vec![],
     }
   }
 
@@ -35,10 +55,22 @@ impl BreakpointManager {
       script_name,
       handler_name,
       bytecode_index,
+      condition: None,
     });
     JsApi::dispatch_breakpoint_list_changed();
   }
 
+  /// An empty condition makes the breakpoint unconditional again.
+  pub fn set_breakpoint_condition(&mut self, script_name: String, handler_name: String, bytecode_index: usize, condition: Option<String>) {
+    let condition = condition.filter(|condition| !condition.trim().is_empty());
+    if let Some(breakpoint) = self.breakpoints.iter_mut().find(|bp| {
+      bp.script_name == script_name && bp.handler_name == handler_name && bp.bytecode_index == bytecode_index
+    }) {
+      breakpoint.condition = condition;
+      JsApi::dispatch_breakpoint_list_changed();
+    }
+  }
+
   pub fn remove_breakpoint(&mut self, script_name: String, handler_name: String, bytecode_index: usize) {
     self.breakpoints.retain(|bp| {
       bp.script_name != script_name || bp.handler_name != handler_name || bp.bytecode_index != bytecode_index
@@ -60,9 +92,59 @@ impl BreakpointManager {
     })
   }
 
+  pub fn has_breakpoints_in_handler(&self, script_name: &String, handler_name: &String) -> bool {
+    self.breakpoints.iter().any(|bp| bp.script_name == *script_name && bp.handler_name == *handler_name)
+  }
+
   pub fn find_breakpoint_for_bytecode(&self, script_name: &String, handler_name: &String, bytecode_index: usize) -> Option<&Breakpoint> {
     self.breakpoints.iter().find(|bp| {
       bp.script_name == *script_name && bp.handler_name == *handler_name && bp.bytecode_index == bytecode_index
     })
   }
 }
+
+/// Whether a breakpoint pauses in the given scope. A condition that can't be evaluated
+/// pauses too, so that mistakes in it don't go unnoticed.
+pub fn is_breakpoint_condition_met(breakpoint: &Breakpoint, scope_ref: ScopeRef, player: &mut DirPlayer) -> bool {
+  let condition = match &breakpoint.condition {
+    Some(condition) => condition,
+    None => return true,
+  };
+  match eval_debug_expr(condition, scope_ref, player).and_then(|result| player.get_datum(&result).to_bool()) {
+    Ok(is_met) => is_met,
+    Err(err) => {
+      warn!("Breakpoint condition {} failed: {}", condition, err.message);
+      true
+    }
+  }
+}
+
+/// Evaluates the watch expressions in the paused scope and sends them to the debugger.
+pub fn update_watch_expressions(player: &mut DirPlayer) {
+  let scope_ref = if player.current_breakpoint.is_some() { Some(player.current_scope_ref()) } else { None };
+  let expressions = player.breakpoint_manager.watches.iter().map(|watch| watch.expression.clone()).collect::<Vec<_>>();
+  let watches = expressions
+    .into_iter()
+    .map(|expression| {
+      let value = match scope_ref {
+        Some(scope_ref) => eval_debug_expr(&expression, scope_ref, player).map_err(|err| err.message),
+        None => Err("Not paused".to_owned()),
+      };
+      WatchExpression { expression, value }
+    })
+    .collect();
+  player.breakpoint_manager.watches = watches;
+  JsApi::dispatch_watch_list(player);
+}
+
+pub fn add_watch_expression(player: &mut DirPlayer, expression: String) {
+  if !player.breakpoint_manager.watches.iter().any(|watch| watch.expression == expression) {
+    player.breakpoint_manager.watches.push(WatchExpression { expression, value: Err("Not paused".to_owned()) });
+  }
+  update_watch_expressions(player);
+}
+
+pub fn remove_watch_expression(player: &mut DirPlayer, expression: &str) {
+  player.breakpoint_manager.watches.retain(|watch| watch.expression != expression);
+  JsApi::dispatch_watch_list(player);
+}
@@ -0,0 +1,78 @@
+use std::rc::Rc;
+
+use crate::director::{enums::ScriptType, file::get_variable_multiplier, lingo::{compiler::compile_script, script::ScriptContext}};
+
+use super::{cast_lib::{cast_member_ref, CastMemberRef}, player_call_script_handler, reserve_player_mut, script::Script, DirPlayer, ScriptError};
+
+/// Statements run by `do` are compiled into scripts numbered past any member, one for
+/// each `do` that is running so a nested one doesn't replace the script of another.
+const DO_SCRIPT_FIRST_NUMBER: u32 = 0x7FFF_0000;
+const DO_HANDLER_NAME: &str = "__do";
+
+/// Compiles Lingo statements and runs them, like the `do` command. Globals must be
+/// declared in the statements, and their other variables are locals of their own.
+pub async fn player_do(source: &str) -> Result<(), ScriptError> {
+  let script_ref = reserve_player_mut(|player| compile_do_script(player, source))?;
+  let result = player_call_script_handler(None, (script_ref.clone(), DO_HANDLER_NAME.to_string()), &vec![]).await;
+  reserve_player_mut(|player| {
+    let cast = player.movie.cast_manager.get_cast_mut(script_ref.cast_lib as u32);
+    cast.scripts.remove(&(script_ref.cast_member as u32));
+  });
+  result.map(|_| ())
+}
+
+fn compile_do_script(player: &mut DirPlayer, source: &str) -> Result<CastMemberRef, ScriptError> {
+  if !player.allow_do {
+    return Err(ScriptError::new("do is disabled for this movie".to_string()));
+  }
+  // The names the statements use go to the cast of the script that runs them
+  let cast_lib = if player.scope_count > 0 {
+    player.scopes.get(player.current_scope_ref()).map_or(1, |scope| scope.script_ref.cast_lib.max(1) as u32)
+  } else {
+    1
+  };
+  let cast = player.movie.cast_manager.get_cast_mut_or_null(cast_lib)
+    .ok_or_else(|| ScriptError::new(format!("Cannot do \"{}\": castLib {} not found", source, cast_lib)))?;
+  let variable_multiplier = get_variable_multiplier(cast.capital_x, cast.dir_version);
+  // The names are compiled into a context of their own, so that running statements
+  // doesn't grow the names of the cast
+  let mut lctx = ScriptContext {
+    names: cast.lctx.as_ref().map(|lctx| lctx.names.clone()).unwrap_or_default(),
+    scripts: Default::default(),
+  };
+  let wrapped_source = format!("on {}\n{}\nend", DO_HANDLER_NAME, source.replace('\r', "\n"));
+  let chunk = compile_script(&wrapped_source, &mut lctx, variable_multiplier)
+    .map_err(|err| ScriptError::new(format!("Cannot do \"{}\": {}", source, err)))?;
+  let number = (DO_SCRIPT_FIRST_NUMBER..).find(|number| !cast.scripts.contains_key(number)).unwrap();
+  let script_ref = cast_member_ref(cast_lib as i32, number as i32);
+  let mut script = Script::new(script_ref.clone(), DO_HANDLER_NAME.to_string(), chunk, ScriptType::Parent, &lctx.names);
+  script.lctx = Some(Rc::new(lctx));
+  cast.scripts.insert(number, Rc::new(script));
+  Ok(script_ref)
+}
+
+#[cfg(test)]
+mod tests {
+  use wasm_bindgen_test::*;
+
+  use crate::{player::reserve_player_mut, test_utils::{eval, init_player}};
+
+  fn cast_name_count() -> Option<usize> {
+    reserve_player_mut(|player| player.movie.cast_manager.get_cast(1).ok()?.lctx.as_ref().map(|lctx| lctx.names.len()))
+  }
+
+  #[wasm_bindgen_test]
+  async fn do_leaves_the_names_of_the_cast_alone() {
+    init_player();
+    reserve_player_mut(|player| {
+      if player.movie.cast_manager.casts.is_empty() {
+        let text_encoding = player.movie.text_encoding();
+        player.movie.cast_manager.add_empty_cast("Do", text_encoding);
+      }
+    });
+    let name_count = cast_name_count();
+    eval("do(\"global gDoResult\" & RETURN & \"gDoResult = [#doneByDo: 1 + 2]\")").await;
+    assert_eq!(eval("gDoResult").await, "[#doneByDo: 3]");
+    assert_eq!(cast_name_count(), name_count);
+  }
+}
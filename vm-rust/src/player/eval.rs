@@ -3,7 +3,7 @@ use pest::{iterators::Pair, Parser};
 
 use crate::{console_error, director::lingo::datum::{datum_bool, Datum, DatumType}, js_api::ascii_safe};
 
-use super::{sprite::ColorRef, DatumRef, DirPlayer, ScriptError};
+use super::{cast_lib::CastMemberRef, compare::{datum_equals, datum_greater_than, datum_less_than}, date::LingoDate, handlers::datum_handlers::color::ColorUtils, scope::ScopeRef, script::{get_lctx_for_script_ref, get_obj_prop, script_get_prop_opt}, sprite::ColorRef, DatumRef, DirPlayer, ScriptError};
 
 #[derive(Parser)]
 #[grammar = "lingo.pest"]
@@ -13,6 +13,27 @@ fn tokenize_lingo(_expr: &String) -> Vec<String> {
   [].to_vec()
 }
 
+fn parse_int_pair(pair: Option<Pair<Rule>>) -> Result<i32, ScriptError> {
+  let pair = pair.ok_or_else(|| ScriptError::new("Missing number in Lingo expression".to_string()))?;
+  pair.as_str().trim().parse::<i32>().map_err(|_| ScriptError::new(format!("Invalid number {}", pair.as_str())))
+}
+
+fn string_const_value(name: &str) -> &'static str {
+  match name.to_uppercase().as_str() {
+    "QUOTE" => "\"",
+    "RETURN" => "\r",
+    "TAB" => "\t",
+    _ => "",
+  }
+}
+
+fn eval_string_part(pair: Pair<Rule>) -> String {
+  match pair.as_rule() {
+    Rule::string => pair.into_inner().next().unwrap().as_str().to_owned(),
+    _ => string_const_value(pair.as_str()).to_owned(),
+  }
+}
+
 pub fn eval_lingo_pair(pair: Pair<Rule>, player: &mut DirPlayer) -> Result<DatumRef, ScriptError> {
   // warn!("eval_lingo_expr: {:?}", pair);
 
@@ -28,9 +49,10 @@ pub fn eval_lingo_pair(pair: Pair<Rule>, player: &mut DirPlayer) -> Result<Datum
       }
       Ok(player.alloc_datum(Datum::List(DatumType::List, result_vec, false)))
     }
-    Rule::string => {
-      let str_val = pair.into_inner().next().unwrap().as_str();
-      Ok(player.alloc_datum(Datum::String(str_val.to_owned())))
+    Rule::string | Rule::string_const => Ok(player.alloc_datum(Datum::String(eval_string_part(pair)))),
+    Rule::string_concat => {
+      let str_val = pair.into_inner().map(eval_string_part).collect::<String>();
+      Ok(player.alloc_datum(Datum::String(str_val)))
     }
     Rule::prop_list => eval_lingo_pair(pair.into_inner().next().unwrap(), player),
     Rule::multi_prop_list => {
@@ -45,53 +67,42 @@ pub fn eval_lingo_pair(pair: Pair<Rule>, player: &mut DirPlayer) -> Result<Datum
       Ok(player.alloc_datum(Datum::PropList(result_vec, false)))
     }
     Rule::empty_prop_list => Ok(player.alloc_datum(Datum::PropList(vec![], false))),
-    Rule::number_int => Ok(player.alloc_datum(Datum::Int(pair.as_str().parse::<i32>().unwrap()))),
-    Rule::number_float => Ok(player.alloc_datum(Datum::Float(pair.as_str().parse::<f32>().unwrap()))),
+    Rule::number_int => Ok(player.alloc_datum(Datum::Int(parse_int_pair(Some(pair))?))),
+    Rule::number_float => {
+      let value = pair.as_str().trim().parse::<f32>().map_err(|_| ScriptError::new(format!("Invalid number {}", pair.as_str())))?;
+      Ok(player.alloc_datum(Datum::Float(value)))
+    }
     Rule::rect => {
       let mut inner = pair.into_inner();
-      Ok(
-        player.alloc_datum(
-          Datum::IntRect(
-            (
-              inner.next().unwrap().as_str().parse::<i32>().unwrap(),
-              inner.next().unwrap().as_str().parse::<i32>().unwrap(),
-              inner.next().unwrap().as_str().parse::<i32>().unwrap(),
-              inner.next().unwrap().as_str().parse::<i32>().unwrap(),
-            )
-          )
-        )
-      )
+      let rect = (
+        parse_int_pair(inner.next())?,
+        parse_int_pair(inner.next())?,
+        parse_int_pair(inner.next())?,
+        parse_int_pair(inner.next())?,
+      );
+      Ok(player.alloc_datum(Datum::IntRect(rect)))
     }
     Rule::rgb_num_color => {
       let mut inner = pair.into_inner();
-      Ok(
-        player.alloc_datum(
-          Datum::ColorRef(
-            ColorRef::Rgb(
-              inner.next().unwrap().as_str().parse::<u8>().unwrap(),
-              inner.next().unwrap().as_str().parse::<u8>().unwrap(),
-              inner.next().unwrap().as_str().parse::<u8>().unwrap()
-            )
-          )
-        )
-      )
+      let r = ColorUtils::clamp_component(parse_int_pair(inner.next())?);
+      let g = ColorUtils::clamp_component(parse_int_pair(inner.next())?);
+      let b = ColorUtils::clamp_component(parse_int_pair(inner.next())?);
+      Ok(player.alloc_datum(Datum::ColorRef(ColorRef::Rgb(r, g, b))))
     }
     Rule::rgb_str_color => {
       let mut inner = pair.into_inner();
       let str_inner = inner.next().unwrap().into_inner().next().unwrap();
-      let str_val = str_inner.as_str();
-      Ok(
-        player.alloc_datum(
-          Datum::ColorRef(
-            ColorRef::from_hex(str_val),
-          )
-        )
-      )
+      let (r, g, b) = ColorUtils::parse_hex_string(str_inner.as_str())?;
+      Ok(player.alloc_datum(Datum::ColorRef(ColorRef::Rgb(r, g, b))))
     }
     Rule::rgb_color => {
       let inner = pair.into_inner().next().unwrap();
       eval_lingo_pair(inner, player)
     }
+    Rule::palette_color => {
+      let index = ColorUtils::clamp_component(parse_int_pair(pair.into_inner().next())?);
+      Ok(player.alloc_datum(Datum::ColorRef(ColorRef::PaletteIndex(index))))
+    }
     Rule::symbol => {
       let str_val = pair.into_inner().next().unwrap().as_str();
       Ok(player.alloc_datum(Datum::Symbol(str_val.to_owned())))
@@ -99,21 +110,25 @@ pub fn eval_lingo_pair(pair: Pair<Rule>, player: &mut DirPlayer) -> Result<Datum
     Rule::bool_true => Ok(player.alloc_datum(datum_bool(true))),
     Rule::bool_false => Ok(player.alloc_datum(datum_bool(false))),
     Rule::void => Ok(DatumRef::Void),
-    Rule::string_empty => Ok(player.alloc_datum(Datum::String("".to_owned()))),
     Rule::nohash_symbol => Ok(player.alloc_datum(Datum::Symbol(pair.as_str().to_owned()))),
     Rule::point => {
       let mut inner = pair.into_inner();
-      Ok(
-        player.alloc_datum(
-          Datum::IntPoint(
-            (
-              inner.next().unwrap().as_str().parse::<i32>().unwrap(),
-              inner.next().unwrap().as_str().parse::<i32>().unwrap()
-            )
-          )
-        )
-      )
+      let point = (parse_int_pair(inner.next())?, parse_int_pair(inner.next())?);
+      Ok(player.alloc_datum(Datum::IntPoint(point)))
     }
+    Rule::date => {
+      let mut inner = pair.into_inner();
+      let date = LingoDate::from_ymd(parse_int_pair(inner.next())?, parse_int_pair(inner.next())?, parse_int_pair(inner.next())?)?;
+      Ok(player.alloc_datum(Datum::Date(date)))
+    }
+    Rule::member_ref => {
+      let mut inner = pair.into_inner();
+      let cast_member = parse_int_pair(inner.next())?;
+      let cast_lib = parse_int_pair(inner.next())?;
+      Ok(player.alloc_datum(Datum::CastMember(CastMemberRef { cast_lib, cast_member })))
+    }
+    Rule::sprite_ref => Ok(player.alloc_datum(Datum::SpriteRef(parse_int_pair(pair.into_inner().next())? as i16))),
+    Rule::cast_lib_ref => Ok(player.alloc_datum(Datum::CastLib(parse_int_pair(pair.into_inner().next())? as u32))),
     Rule::empty_list => Ok(player.alloc_datum(Datum::List(DatumType::List, vec![], false))),
     _ => Err(ScriptError::new(format!("Invalid Lingo expression {:?}", inner_rule)))
   }
@@ -135,3 +150,124 @@ pub fn eval_lingo(expr: String, player: &mut DirPlayer) -> Result<DatumRef, Scri
     }
   } 
 }
+
+/// Evaluates a breakpoint condition or watch expression in a paused scope. Besides
+/// literals it understands names of locals, arguments, properties of `me` and globals,
+/// dotted properties, comparisons, and `and`, `or` and `not`.
+pub fn eval_debug_expr(expr: &str, scope_ref: ScopeRef, player: &mut DirPlayer) -> Result<DatumRef, ScriptError> {
+  match LingoParser::parse(Rule::debug_expr, expr) {
+    Ok(mut parse_result) => eval_debug_pair(parse_result.next().unwrap(), scope_ref, player),
+    Err(e) => Err(ScriptError::new(format!("Invalid expression: {}", ascii_safe(&e.to_string())))),
+  }
+}
+
+pub fn parse_console_statement(source: &str) -> Result<Pair<'_, Rule>, ScriptError> {
+  match LingoParser::parse(Rule::console_statement, source) {
+    Ok(mut parse_result) => Ok(parse_result.next().unwrap()),
+    Err(e) => Err(ScriptError::new(format!("Invalid statement: {}", ascii_safe(&e.to_string())))),
+  }
+}
+
+/// Parses the source of a script, which the compiler turns into handlers.
+pub fn parse_script(source: &str) -> Result<Pair<'_, Rule>, ScriptError> {
+  match LingoParser::parse(Rule::script, source) {
+    Ok(mut parse_result) => Ok(parse_result.next().unwrap()),
+    Err(e) => Err(ScriptError::new(format!("Invalid script: {}", ascii_safe(&e.to_string())))),
+  }
+}
+
+fn eval_debug_pair(pair: Pair<Rule>, scope_ref: ScopeRef, player: &mut DirPlayer) -> Result<DatumRef, ScriptError> {
+  match pair.as_rule() {
+    Rule::debug_or | Rule::debug_and => {
+      let is_or = pair.as_rule() == Rule::debug_or;
+      let mut result = None;
+      for operand in pair.into_inner().filter(|inner| !matches!(inner.as_rule(), Rule::debug_or_op | Rule::debug_and_op)) {
+        let value = eval_debug_pair(operand, scope_ref, player)?;
+        let value = player.get_datum(&value).to_bool()?;
+        result = Some(match result {
+          Some(previous) if is_or => previous || value,
+          Some(previous) => previous && value,
+          None => value,
+        });
+      }
+      Ok(player.alloc_datum(datum_bool(result.unwrap_or(false))))
+    }
+    Rule::debug_not => {
+      let mut inner = pair.into_inner();
+      let first = inner.next().unwrap();
+      if first.as_rule() == Rule::debug_not_op {
+        let value = eval_debug_pair(inner.next().unwrap(), scope_ref, player)?;
+        let value = player.get_datum(&value).to_bool()?;
+        Ok(player.alloc_datum(datum_bool(!value)))
+      } else {
+        eval_debug_pair(first, scope_ref, player)
+      }
+    }
+    Rule::debug_comparison => {
+      let mut inner = pair.into_inner();
+      let left = eval_debug_pair(inner.next().unwrap(), scope_ref, player)?;
+      let op = match inner.next() {
+        Some(op) => op.as_str().to_owned(),
+        None => return Ok(left),
+      };
+      let right = eval_debug_pair(inner.next().unwrap(), scope_ref, player)?;
+      let result = compare_datums(&op, &left, &right, player)?;
+      Ok(player.alloc_datum(datum_bool(result)))
+    }
+    Rule::debug_ref => {
+      let mut names = pair.into_inner();
+      let mut value = resolve_debug_name(names.next().unwrap().as_str(), scope_ref, player)?;
+      for prop_name in names {
+        value = get_obj_prop(player, &value, &prop_name.as_str().to_owned())?;
+      }
+      Ok(value)
+    }
+    _ => eval_lingo_pair(pair, player),
+  }
+}
+
+/// Applies one of the comparison operators of the Lingo grammar.
+pub fn compare_datums(op: &str, left: &DatumRef, right: &DatumRef, player: &DirPlayer) -> Result<bool, ScriptError> {
+  let left = player.get_datum(left);
+  let right = player.get_datum(right);
+  match op {
+    "=" => datum_equals(left, right, &player.allocator),
+    "<>" => Ok(!datum_equals(left, right, &player.allocator)?),
+    "<" => datum_less_than(left, right),
+    ">" => datum_greater_than(left, right),
+    "<=" => Ok(!datum_greater_than(left, right)?),
+    _ => Ok(!datum_less_than(left, right)?),
+  }
+}
+
+/// Looks a name up the way the handler would see it: locals, then arguments, then
+/// properties of the receiver, then globals.
+pub fn resolve_debug_name(name: &str, scope_ref: ScopeRef, player: &mut DirPlayer) -> Result<DatumRef, ScriptError> {
+  let scope = player.scopes.get(scope_ref).unwrap();
+  if let Some((_, value)) = scope.locals.iter().find(|(local_name, _)| local_name.eq_ignore_ascii_case(name)) {
+    return Ok(value.clone());
+  }
+  let receiver = scope.receiver.clone();
+  let arg_index = player.movie.cast_manager.get_script_by_ref(&scope.script_ref)
+    .and_then(|script| script.get_own_handler_by_name_id(scope.handler_name_id))
+    .zip(get_lctx_for_script_ref(player, &scope.script_ref))
+    .and_then(|(handler, lctx)| {
+      let names = &lctx.names;
+      handler.argument_name_ids.iter().position(|name_id| names[*name_id as usize].eq_ignore_ascii_case(name))
+    });
+  if let Some(value) = arg_index.and_then(|arg_index| scope.args.get(arg_index)) {
+    return Ok(value.clone());
+  }
+  if let Some(receiver) = receiver {
+    if name.eq_ignore_ascii_case("me") {
+      return Ok(player.alloc_datum(Datum::ScriptInstanceRef(receiver)));
+    }
+    if let Some(value) = script_get_prop_opt(player, &receiver, &name.to_owned()) {
+      return Ok(value);
+    }
+  }
+  match player.globals.iter().find(|(global_name, _)| global_name.eq_ignore_ascii_case(name)) {
+    Some((_, value)) => Ok(value.clone()),
+    None => Err(ScriptError::new(format!("Unknown variable {}", name))),
+  }
+}
@@ -1,3 +1,5 @@
+use std::future::Future;
+
 use async_std::channel::Receiver;
 use log::warn;
 
@@ -8,7 +10,7 @@ use crate::{
 };
 
 use super::{
-    cast_lib::CastMemberRef, handlers::datum_handlers::script_instance::ScriptInstanceUtils, player_call_script_handler, reserve_player_ref, script::ScriptInstanceId, script_ref::ScriptInstanceRef, DatumRef, ScriptError, ScriptErrorCode, PLAYER_EVENT_TX, player_semaphone
+    alert::player_handle_script_error, cast_lib::CastMemberRef, handlers::datum_handlers::script_instance::ScriptInstanceUtils, player_call_script_handler, reserve_player_ref, script::ScriptInstanceId, script_ref::ScriptInstanceRef, DatumRef, ScriptError, ScriptErrorCode, PLAYER_EVENT_TX, player_semaphone
 };
 
 pub enum PlayerVMEvent {
@@ -77,6 +79,26 @@ pub fn player_dispatch_event_to_sprite(
     .unwrap();
 }
 
+/// Sends an event to the behaviors of a sprite and waits for it to be handled. Meanwhile
+/// `the currentSpriteNum` is the sprite's number, also in movie scripts the event reaches.
+pub async fn player_invoke_event_to_sprite(
+    handler_name: &String,
+    args: &Vec<DatumRef>,
+    sprite_num: u16,
+) -> Result<DatumRef, ScriptError> {
+    let instance_ids = reserve_player_ref(|player| {
+        player.movie.score.get_sprite(sprite_num as i16).map(|sprite| sprite.script_instance_list.clone())
+    });
+    let instance_ids = match instance_ids {
+        Some(instance_ids) => instance_ids,
+        None => return Ok(DatumRef::Void),
+    };
+    let previous_sprite_num = reserve_player_mut(|player| player.current_sprite_num.replace(sprite_num as i16));
+    let result = player_invoke_targeted_event(handler_name, args, Some(&instance_ids)).await;
+    reserve_player_mut(|player| player.current_sprite_num = previous_sprite_num);
+    result
+}
+
 pub async fn player_invoke_event_to_instances(
     handler_name: &String,
     args: &Vec<DatumRef>,
@@ -113,7 +135,7 @@ pub async fn player_invoke_static_event(
         let frame_script = player
             .movie
             .score
-            .get_script_in_frame(player.movie.current_frame);
+            .get_static_script_in_frame(player.movie.current_frame);
         let movie_scripts = player.movie.cast_manager.get_movie_scripts();
         let movie_scripts = movie_scripts.as_ref().unwrap();
 
@@ -171,6 +193,15 @@ pub async fn player_invoke_targeted_event(
     Ok(DatumRef::Void)
 }
 
+/// Sends an event that only the frame script and movie scripts get, like idle.
+pub async fn player_invoke_frame_event(
+    handler_name: &String,
+    args: &Vec<DatumRef>,
+) -> Result<DatumRef, ScriptError> {
+    let frame_instances = reserve_player_ref(|player| player.movie.score.get_frame_script_instance_list());
+    player_invoke_targeted_event(handler_name, args, Some(&frame_instances)).await
+}
+
 pub async fn player_invoke_global_event(
     handler_name: &String,
     args: &Vec<DatumRef>,
@@ -217,6 +248,7 @@ pub async fn run_event_loop(rx: Receiver<PlayerVMEvent>) {
         if !player_is_playing().await {
             continue;
         }
+        let scope_count = reserve_player_ref(|player| player.scope_count);
         let result = match item {
             PlayerVMEvent::Global(name, args) => player_invoke_global_event(&name, &args).await,
             PlayerVMEvent::Targeted(name, args, instances) => {
@@ -230,7 +262,7 @@ pub async fn run_event_loop(rx: Receiver<PlayerVMEvent>) {
             Err(err) => {
                 // TODO ignore error if it's a CancelledException
                 // TODO print stack trace
-                reserve_player_mut(|player| player.on_script_error(&err));
+                player_handle_script_error(&err, scope_count).await;
             }
             _ => {}
         };
@@ -238,11 +270,12 @@ pub async fn run_event_loop(rx: Receiver<PlayerVMEvent>) {
     warn!("Event loop stopped!")
 }
 
-pub fn player_unwrap_result(result: Result<DatumRef, ScriptError>) -> DatumRef {
-    match result {
+pub async fn player_unwrap_result(result: impl Future<Output = Result<DatumRef, ScriptError>>) -> DatumRef {
+    let scope_count = reserve_player_ref(|player| player.scope_count);
+    match result.await {
         Ok(result) => result,
         Err(err) => {
-            reserve_player_mut(|player| player.on_script_error(&err));
+            player_handle_script_error(&err, scope_count).await;
             DatumRef::Void
         }
     }
@@ -251,3 +284,11 @@ pub fn player_unwrap_result(result: Result<DatumRef, ScriptError>) -> DatumRef {
 pub async fn player_wait_available() {
     player_semaphone().lock().await;
 }
+
+/// Suspends the running handler until `future` completes. The player stays busy
+/// meanwhile, so commands, events and frames wait for the handler to finish instead
+/// of running Lingo on top of its scopes.
+pub async fn player_suspend_handler<F: Future>(future: F) -> F::Output {
+    let _busy = player_semaphone().lock().await;
+    future.await
+}
@@ -0,0 +1,314 @@
+use log::warn;
+
+use crate::{director::{chunks::sound::SoundChunk, enums::MemberType, file::get_variable_multiplier, lingo::decompiler::decompile_handler, rifx_writer::RifxWriter}, io::{text_encoding::TextEncoding, zip::ZipWriter}};
+
+use super::{bitmap::png::encode_png, cast_lib::{CastLib, CastMemberRef}, cast_member::{CastMember, CastMemberType, ScriptMember}, DirPlayer, ScriptError};
+
+/// A cast member as a file of its own.
+pub struct ExportedMember {
+  pub file_name: String,
+  pub mime_type: &'static str,
+  pub data: Vec<u8>,
+}
+
+/// Converts a member to the file an editor would open: bitmaps to PNG with their
+/// palette applied, sounds to WAV, and fields, text and scripts to UTF-8 text.
+pub fn export_member(player: &DirPlayer, member_ref: &CastMemberRef) -> Result<ExportedMember, ScriptError> {
+  let cast = player.movie.cast_manager.get_cast(member_ref.cast_lib as u32)?;
+  let member = cast.members.get(&(member_ref.cast_member as u32))
+    .ok_or_else(|| ScriptError::new(format!("Member {} of castLib {} not found", member_ref.cast_member, member_ref.cast_lib)))?;
+  export_cast_member(player, cast, member)
+}
+
+fn export_cast_member(player: &DirPlayer, cast: &CastLib, member: &CastMember) -> Result<ExportedMember, ScriptError> {
+  let (extension, mime_type, data) = match &member.member_type {
+    CastMemberType::Bitmap(bitmap_member) => {
+      let bitmap = player.bitmap_manager.get_bitmap(bitmap_member.image_ref)
+        .ok_or_else(|| ScriptError::new("Bitmap member has no image".to_string()))?;
+      ("png", "image/png", encode_png(bitmap, &player.movie.cast_manager.palettes()))
+    }
+    CastMemberType::Sound(sound_member) => {
+      let sound = sound_member.sound.as_ref()
+        .ok_or_else(|| ScriptError::new("Sound member has no samples that can be exported".to_string()))?;
+      ("wav", "audio/wav", encode_wav(sound))
+    }
+    CastMemberType::Field(field) => ("txt", "text/plain", field.text.replace('\r', "\n").into_bytes()),
+    CastMemberType::Text(text) => ("txt", "text/plain", text.text.replace('\r', "\n").into_bytes()),
+    CastMemberType::Script(script_member) => ("ls", "text/plain", script_member_text(cast, script_member)?.into_bytes()),
+    member_type => return Err(ScriptError::new(format!("Cannot export {} members", member_type.type_string()))),
+  };
+  let name = if member.name.is_empty() { member.number.to_string() } else { format!("{} {}", member.number, member.name) };
+  Ok(ExportedMember {
+    file_name: format!("{}.{}", sanitize_file_name(&name), extension),
+    mime_type,
+    data,
+  })
+}
+
+/// The first line of the Lingo of a script that was decompiled from its bytecode.
+pub const RECONSTRUCTED_SCRIPT_HEADER: &str = "-- Reconstructed from bytecode, the source isn't stored in this movie";
+
+/// The source of a script member, or its handlers decompiled when the movie doesn't
+/// store the source.
+pub fn script_member_text(cast: &CastLib, script: &ScriptMember) -> Result<String, ScriptError> {
+  if !script.source_text.is_empty() {
+    return Ok(script.source_text.replace("\r\n", "\n").replace('\r', "\n"));
+  }
+  Ok(format!("{}\n\n{}", RECONSTRUCTED_SCRIPT_HEADER, decompile_script(cast, script.script_id)?))
+}
+
+/// Decompiles every handler of a script of the cast into Lingo source.
+pub fn decompile_script(cast: &CastLib, script_id: u32) -> Result<String, ScriptError> {
+  let lctx = cast.lctx.as_ref().ok_or_else(|| ScriptError::new("Cast has no scripts".to_string()))?;
+  let chunk = lctx.scripts.get(&script_id)
+    .ok_or_else(|| ScriptError::new(format!("Script {} not found", script_id)))?;
+  let variable_multiplier = get_variable_multiplier(cast.capital_x, cast.dir_version);
+  let handlers = chunk.handlers.iter().map(|handler| {
+    let args = handler.argument_name_ids.iter().map(|arg| lctx.names[*arg as usize].as_str()).collect::<Vec<_>>();
+    let mut text = format!("on {}", lctx.names[handler.name_id as usize]);
+    if !args.is_empty() {
+      text.push(' ');
+      text.push_str(&args.join(", "));
+    }
+    text.push('\n');
+    for line in decompile_handler(handler, chunk, lctx, variable_multiplier, cast.dir_version) {
+      text.push_str(&"  ".repeat(line.indent + 1));
+      text.push_str(&line.text);
+      text.push('\n');
+    }
+    text.push_str("end\n");
+    text
+  });
+  Ok(handlers.collect::<Vec<_>>().join("\n"))
+}
+
+/// Exports every member that can be exported into a zip, with a folder per cast.
+pub fn export_all_members(player: &DirPlayer) -> Vec<u8> {
+  let mut zip = ZipWriter::new();
+  for cast in &player.movie.cast_manager.casts {
+    let folder = if cast.name.is_empty() { cast.number.to_string() } else { sanitize_file_name(&cast.name) };
+    let mut member_numbers = cast.members.keys().collect::<Vec<_>>();
+    member_numbers.sort();
+    for member in member_numbers.into_iter().filter_map(|number| cast.members.get(number)) {
+      if let Ok(exported) = export_cast_member(player, cast, member) {
+        zip.add_file(&format!("{}/{}", folder, exported.file_name), &exported.data);
+      }
+    }
+  }
+  zip.finish()
+}
+
+const SCRIPT_NAMES_HEADER_LEN: u16 = 20;
+const SCRIPT_CONTEXT_HEADER_LEN: u16 = 42;
+
+/// A cast written back as a .cst file, with the members that couldn't be written.
+pub struct ExportedCastFile {
+  pub data: Vec<u8>,
+  pub skipped_members: Vec<u32>,
+}
+
+/// Writes a cast with the changes made to it as an uncompressed .cst file. Bitmaps,
+/// fields, text, palettes and scripts are written, with bitmaps stored as PNG media,
+/// text members as fields and scripts as the bytecode they run. Other members are
+/// left out, since they can't be encoded again.
+pub fn export_cast_file(player: &DirPlayer, cast_lib: u32) -> Result<ExportedCastFile, ScriptError> {
+  let cast = player.movie.cast_manager.get_cast(cast_lib)?;
+  let movie_file = player.movie.file.as_ref().ok_or_else(|| ScriptError::new("No movie is loaded".to_string()))?;
+  let encoding = TextEncoding::from_platform(movie_file.config.platform);
+  let palettes = player.movie.cast_manager.palettes();
+
+  let mut member_numbers = cast.members.keys().copied().collect::<Vec<_>>();
+  member_numbers.sort();
+  let max_member = member_numbers.last().copied().unwrap_or(0);
+  let mut config = movie_file.config.clone();
+  config.min_member = 1;
+  config.max_member = max_member as u16;
+  config.checksum = config.compute_checksum(binary_reader::Endian::Big);
+
+  let mut writer = RifxWriter::new("MV93", config.director_version);
+  writer.add_chunk("DRCF", config.write());
+  let mut section_ids = vec![0u32; max_member as usize];
+  let mut skipped_members = vec![];
+  for member in member_numbers.iter().filter_map(|number| cast.members.get(number)) {
+    let section_id = match &member.member_type {
+      CastMemberType::Bitmap(bitmap_member) => {
+        let bitmap = player.bitmap_manager.get_bitmap(bitmap_member.image_ref)
+          .ok_or_else(|| ScriptError::new(format!("Bitmap member {} has no image", member.number)))?;
+        let mut info = vec![];
+        info.extend_from_slice(&0x8000u16.to_be_bytes());
+        for value in [0, 0, bitmap.height as i16, bitmap.width as i16, 0, 0, bitmap.height as i16, bitmap.width as i16] {
+          info.extend_from_slice(&value.to_be_bytes());
+        }
+        info.extend_from_slice(&bitmap_member.reg_point.1.to_be_bytes());
+        info.extend_from_slice(&bitmap_member.reg_point.0.to_be_bytes());
+        info.extend_from_slice(&[0, 32]);
+        // No palette of its own, which reads back as the system palette
+        info.extend_from_slice(&[0; 4]);
+        let section_id = writer.add_chunk("CASt", encode_member_chunk(MemberType::Bitmap, &member.name, None, encoding, &info));
+        writer.add_child_chunk(section_id, "ediM", encode_png(bitmap, &palettes));
+        section_id
+      }
+      CastMemberType::Field(field) => {
+        let section_id = writer.add_chunk("CASt", encode_member_chunk(MemberType::Text, &member.name, None, encoding, &[]));
+        writer.add_child_chunk(section_id, "STXT", encode_text_chunk(&field.text, encoding, field.font_size));
+        section_id
+      }
+      CastMemberType::Text(text) => {
+        let section_id = writer.add_chunk("CASt", encode_member_chunk(MemberType::Text, &member.name, None, encoding, &[]));
+        writer.add_child_chunk(section_id, "STXT", encode_text_chunk(&text.text, encoding, text.font_size));
+        section_id
+      }
+      CastMemberType::Palette(palette) => {
+        let section_id = writer.add_chunk("CASt", encode_member_chunk(MemberType::Palette, &member.name, None, encoding, &[]));
+        let colors = palette.colors.iter().flat_map(|(r, g, b)| [*r, *r, *g, *g, *b, *b]).collect();
+        writer.add_child_chunk(section_id, "CLUT", colors);
+        section_id
+      }
+      CastMemberType::Script(script) => {
+        let script_info = (script.script_type as u16).to_be_bytes();
+        let member_chunk = encode_member_chunk(MemberType::Script, &member.name, Some((script.script_id, &script.source_text)), encoding, &script_info);
+        writer.add_chunk("CASt", member_chunk)
+      }
+      _ => {
+        warn!("Member {} of castLib {} can't be written to a cast file", member.number, cast_lib);
+        skipped_members.push(member.number);
+        continue;
+      }
+    };
+    section_ids[member.number as usize - 1] = section_id;
+  }
+  writer.add_chunk("CAS*", section_ids.iter().flat_map(|id| id.to_be_bytes()).collect());
+  if let Some(lctx) = &cast.lctx {
+    let lnam_section_id = writer.add_chunk("Lnam", encode_script_names_chunk(&lctx.names));
+    let script_count = lctx.scripts.keys().max().copied().unwrap_or(0);
+    let script_section_ids = (1..=script_count)
+      .map(|script_id| match lctx.scripts.get(&script_id) {
+        Some(script) => writer.add_chunk("Lscr", script.write(script_id as u16, cast.dir_version, cast.capital_x, encoding)) as i32,
+        None => -1,
+      })
+      .collect::<Vec<_>>();
+    // The script context belongs to the cast itself, which a .cst file numbers 1024
+    let fourcc = if cast.capital_x { "LctX" } else { "Lctx" };
+    writer.add_child_chunk(1024, fourcc, encode_script_context_chunk(&script_section_ids, lnam_section_id));
+  }
+  Ok(ExportedCastFile {
+    data: writer.finish(),
+    skipped_members,
+  })
+}
+
+/// A CASt chunk in the layout of Director 5 and later. The info holds the name, and
+/// for scripts their number in the script context and their source.
+fn encode_member_chunk(member_type: MemberType, name: &str, script: Option<(u32, &str)>, encoding: TextEncoding, specific_data: &[u8]) -> Vec<u8> {
+  let mut name = encoding.encode(name);
+  name.truncate(255);
+  let (script_id, script_text) = script.map_or((0, vec![]), |(script_id, text)| (script_id, encoding.encode(text)));
+  let mut info = vec![];
+  // The header, then a list of the script text and the name
+  info.extend_from_slice(&20u32.to_be_bytes());
+  info.extend_from_slice(&[0; 12]);
+  info.extend_from_slice(&script_id.to_be_bytes());
+  info.extend_from_slice(&2u16.to_be_bytes());
+  info.extend_from_slice(&0u32.to_be_bytes());
+  info.extend_from_slice(&(script_text.len() as u32).to_be_bytes());
+  info.extend_from_slice(&((script_text.len() + name.len()) as u32 + 1).to_be_bytes());
+  info.extend_from_slice(&script_text);
+  info.push(name.len() as u8);
+  info.extend_from_slice(&name);
+
+  let mut out = vec![];
+  out.extend_from_slice(&(member_type as u32).to_be_bytes());
+  out.extend_from_slice(&(info.len() as u32).to_be_bytes());
+  out.extend_from_slice(&(specific_data.len() as u32).to_be_bytes());
+  out.extend_from_slice(&info);
+  out.extend_from_slice(specific_data);
+  out
+}
+
+/// An Lnam chunk, with the names the bytecode of the cast refers to by index.
+fn encode_script_names_chunk(names: &[String]) -> Vec<u8> {
+  let mut data = vec![];
+  for name in names {
+    let name = &name.as_bytes()[..name.len().min(255)];
+    data.push(name.len() as u8);
+    data.extend_from_slice(name);
+  }
+  let len = SCRIPT_NAMES_HEADER_LEN as u32 + data.len() as u32;
+  let mut out = vec![];
+  out.extend_from_slice(&[0; 8]);
+  out.extend_from_slice(&len.to_be_bytes());
+  out.extend_from_slice(&len.to_be_bytes());
+  out.extend_from_slice(&SCRIPT_NAMES_HEADER_LEN.to_be_bytes());
+  out.extend_from_slice(&(names.len() as u16).to_be_bytes());
+  out.extend_from_slice(&data);
+  out
+}
+
+/// An Lctx chunk with an entry per script number, -1 where there's no script.
+fn encode_script_context_chunk(script_section_ids: &[i32], lnam_section_id: u32) -> Vec<u8> {
+  let valid_count = script_section_ids.iter().filter(|id| **id >= 0).count();
+  let mut out = vec![];
+  out.extend_from_slice(&[0; 8]);
+  out.extend_from_slice(&(script_section_ids.len() as u32).to_be_bytes());
+  out.extend_from_slice(&(script_section_ids.len() as u32).to_be_bytes());
+  out.extend_from_slice(&SCRIPT_CONTEXT_HEADER_LEN.to_be_bytes());
+  out.extend_from_slice(&[0; 14]);
+  out.extend_from_slice(&lnam_section_id.to_be_bytes());
+  out.extend_from_slice(&(valid_count as u16).to_be_bytes());
+  out.extend_from_slice(&0u16.to_be_bytes());
+  // No free entries
+  out.extend_from_slice(&(-1i16).to_be_bytes());
+  for section_id in script_section_ids {
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend_from_slice(&section_id.to_be_bytes());
+    out.extend_from_slice(&[0; 4]);
+  }
+  out
+}
+
+/// An STXT chunk with a single style run covering the whole text.
+fn encode_text_chunk(text: &str, encoding: TextEncoding, font_size: u16) -> Vec<u8> {
+  let text = encoding.encode(text);
+  let mut style = vec![];
+  style.extend_from_slice(&1u16.to_be_bytes());
+  style.extend_from_slice(&0u32.to_be_bytes());
+  // Line height, ascent, font id, style and size, then the color
+  for value in [font_size + font_size / 3, font_size, 0, 0, font_size, 0, 0, 0] {
+    style.extend_from_slice(&value.to_be_bytes());
+  }
+
+  let mut out = vec![];
+  out.extend_from_slice(&12u32.to_be_bytes());
+  out.extend_from_slice(&(text.len() as u32).to_be_bytes());
+  out.extend_from_slice(&(style.len() as u32).to_be_bytes());
+  out.extend_from_slice(&text);
+  out.extend_from_slice(&style);
+  out
+}
+
+fn sanitize_file_name(name: &str) -> String {
+  name.chars()
+    .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+    .collect()
+}
+
+fn encode_wav(sound: &SoundChunk) -> Vec<u8> {
+  let block_align = sound.channel_count * sound.bits_per_sample / 8;
+  let mut out = Vec::with_capacity(44 + sound.samples.len());
+  out.extend_from_slice(b"RIFF");
+  out.extend_from_slice(&(36 + sound.samples.len() as u32).to_le_bytes());
+  out.extend_from_slice(b"WAVE");
+  out.extend_from_slice(b"fmt ");
+  out.extend_from_slice(&16u32.to_le_bytes());
+  // PCM
+  out.extend_from_slice(&1u16.to_le_bytes());
+  out.extend_from_slice(&sound.channel_count.to_le_bytes());
+  out.extend_from_slice(&sound.sample_rate.to_le_bytes());
+  out.extend_from_slice(&(sound.sample_rate * block_align as u32).to_le_bytes());
+  out.extend_from_slice(&block_align.to_le_bytes());
+  out.extend_from_slice(&sound.bits_per_sample.to_le_bytes());
+  out.extend_from_slice(b"data");
+  out.extend_from_slice(&(sound.samples.len() as u32).to_le_bytes());
+  out.extend_from_slice(&sound.samples);
+  out
+}
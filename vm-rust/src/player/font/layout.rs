@@ -0,0 +1,163 @@
+use std::ops::Range;
+
+use crate::player::cast_member::{CastMemberType, FieldMember};
+
+use super::{
+    get_char_advances,
+    truetype::{get_char_offsets, get_line_height, TrueTypeTextParams},
+    FontManager,
+};
+
+/// Width of the scrollbar at the right of #scroll fields.
+pub const SCROLLBAR_WIDTH: i32 = 16;
+
+/// A line of laid out text, in member coordinates.
+pub struct TextLine {
+    /// Index of the first character of the line in the text.
+    pub start: usize,
+    /// Where each character of the line starts, followed by where the line ends.
+    pub char_x: Vec<i32>,
+    pub top: i32,
+    pub height: i32,
+}
+
+/// Where the characters of a text or field member are, for the Lingo text geometry
+/// functions. Lines break at returns only, like the text is drawn.
+pub struct TextLayout {
+    pub lines: Vec<TextLine>,
+    pub text_len: usize,
+}
+
+impl TextLayout {
+    pub fn for_member(font_manager: &FontManager, member_type: &CastMemberType) -> Option<TextLayout> {
+        let (text, font_name, font_size, style, fixed_line_space, top_spacing) = match member_type {
+            CastMemberType::Field(field) => (&field.text, &field.font, field.font_size, field.get_font_style(), field.fixed_line_space, field.top_spacing),
+            CastMemberType::Text(text) => (&text.text, &text.font, text.font_size, text.get_font_style(), text.fixed_line_space, text.top_spacing),
+            _ => return None,
+        };
+        let mut lines = vec![];
+        let mut start = 0;
+        let mut top = top_spacing as i32;
+        for line in text.split(['\r', '\n']) {
+            let (char_x, height) = match font_manager.get_truetype_font(font_name) {
+                Some(font) => {
+                    let params = TrueTypeTextParams {
+                        size: font_size as f32,
+                        color: (0, 0, 0),
+                        anti_alias: false,
+                        fixed_line_space,
+                        top_spacing,
+                        char_spacing: style.char_spacing as f32,
+                    };
+                    let char_x = get_char_offsets(font, line, &params).iter().map(|x| x.round() as i32).collect();
+                    (char_x, get_line_height(font, &params).1 as i32)
+                }
+                None => {
+                    let font = font_manager.get_system_font()?;
+                    let mut char_x = vec![0];
+                    for (_, advance) in get_char_advances(font, line, style) {
+                        char_x.push(char_x.last().unwrap() + advance);
+                    }
+                    (char_x, font.char_height as i32 + fixed_line_space as i32 + 1)
+                }
+            };
+            let char_count = char_x.len() - 1;
+            lines.push(TextLine { start, char_x, top, height });
+            start += char_count + 1;
+            top += height;
+        }
+        Some(TextLayout { lines, text_len: text.chars().count() })
+    }
+
+    /// The line at a vertical location, clamped to the first and last lines.
+    pub fn get_line_index_at(&self, y: i32) -> usize {
+        self.lines
+            .iter()
+            .position(|line| y < line.top + line.height)
+            .unwrap_or(self.lines.len().saturating_sub(1))
+    }
+
+    /// The 1-based position of the character under a location, if there is one.
+    pub fn get_char_at(&self, x: i32, y: i32) -> Option<usize> {
+        let line = self.lines.iter().find(|line| y >= line.top && y < line.top + line.height)?;
+        let index = line.char_x.windows(2).position(|edges| x >= edges[0] && x < edges[1])?;
+        Some(line.start + index + 1)
+    }
+
+    /// The 1-based position of the character closest to a location.
+    pub fn get_char_pos_at(&self, x: i32, y: i32) -> usize {
+        let line = match self.lines.get(self.get_line_index_at(y)) {
+            Some(line) => line,
+            None => return 1,
+        };
+        let char_count = line.char_x.len() - 1;
+        let index = (0..char_count)
+            .find(|index| x < (line.char_x[*index] + line.char_x[*index + 1]) / 2)
+            .unwrap_or(char_count);
+        line.start + index + 1
+    }
+
+    /// The bottom left corner of the character at a 1-based position. Positions past the
+    /// end of the text are after its last character.
+    pub fn get_char_loc(&self, char_pos: usize) -> (i32, i32) {
+        let index = char_pos.max(1).min(self.text_len + 1) - 1;
+        let line = self
+            .lines
+            .iter()
+            .rev()
+            .find(|line| line.start <= index)
+            .or(self.lines.first());
+        match line {
+            Some(line) => {
+                let x = line.char_x[(index - line.start).min(line.char_x.len() - 1)];
+                (x, line.top + line.height)
+            }
+            None => (0, 0),
+        }
+    }
+
+    /// The height of a 1-based line, or 0 when there is no such line.
+    pub fn get_line_height(&self, line_num: usize) -> i32 {
+        line_num
+            .checked_sub(1)
+            .and_then(|index| self.lines.get(index))
+            .map_or(0, |line| line.height)
+    }
+
+    /// Scrolls by whole lines from a scrollTop, without going past the last line.
+    pub fn scroll_by_lines(&self, scroll_top: i32, line_count: i32) -> i32 {
+        let first_top = self.lines.first().map_or(0, |line| line.top);
+        let current = self.get_line_index_at(first_top + scroll_top) as i32;
+        let target = (current + line_count).clamp(0, self.lines.len() as i32 - 1).max(0) as usize;
+        self.lines.get(target).map_or(0, |line| line.top - first_top)
+    }
+
+    pub fn get_height(&self) -> i32 {
+        self.lines.last().map_or(0, |line| line.top + line.height)
+    }
+
+    /// The height of the box a field is shown in. #adjust fields grow to fit their text,
+    /// the other box types keep the height they were given.
+    pub fn get_field_box_height(&self, field: &FieldMember) -> i32 {
+        if field.box_type == "adjust" || field.height == 0 {
+            self.get_height()
+        } else {
+            field.height as i32
+        }
+    }
+
+    /// The characters shown at a scrollTop in a box of some height, and how far below the
+    /// top of the box the first of them is drawn. Lines that don't fit in the box are left
+    /// out, except the first one.
+    pub fn get_visible_range(&self, scroll_top: i32, box_height: Option<i32>) -> Option<(Range<usize>, i32)> {
+        let first_top = self.lines.first()?.top;
+        let mut visible_lines = self.lines.iter().filter(|line| line.top - first_top >= scroll_top);
+        let first_line = visible_lines.next()?;
+        let last_line = visible_lines
+            .take_while(|line| box_height.is_none_or(|box_height| line.top - scroll_top + line.height <= box_height))
+            .last()
+            .unwrap_or(first_line);
+        let end = last_line.start + last_line.char_x.len() - 1;
+        Some((first_line.start..end, first_line.top - first_top - scroll_top))
+    }
+}
@@ -1,23 +1,37 @@
+pub mod layout;
+pub mod truetype;
+
+use fontdue::Font;
 use fxhash::FxHashMap;
+use js_sys::Uint8Array;
 use log::warn;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
 
-use crate::player::{
-    bitmap::bitmap::{get_system_default_palette, Bitmap, PaletteRef},
-    reserve_player_mut,
+use crate::{
+    io::text_encoding::mac_roman_code,
+    player::{
+        bitmap::bitmap::{get_system_default_palette, Bitmap, PaletteRef},
+        reserve_player_mut,
+    },
+    utils::{create_image_bitmap_with_blob, fetch_with_str},
 };
 
 use super::{
     bitmap::{drawing::CopyPixelsParams, manager::BitmapRef, palette_map::PaletteMap},
     geometry::IntRect,
+    sprite::ColorRef,
 };
+use self::truetype::{get_font_substitute, measure_truetype_text, parse_truetype_font, TrueTypeTextParams};
+
 pub type FontRef = u32;
 
 pub struct FontManager {
     pub fonts: FxHashMap<FontRef, BitmapFont>,
     pub system_font: Option<FontRef>,
     pub font_counter: FontRef,
+    /// TrueType fonts provided by the host, keyed by lowercase font name.
+    pub truetype_fonts: FxHashMap<String, Font>,
 }
 
 pub struct BitmapFont {
@@ -31,13 +45,165 @@ pub struct BitmapFont {
     pub char_offset_x: u16,
     pub char_offset_y: u16,
     pub first_char_num: u8,
+    /// Width of each glyph from first_char_num, for proportional fonts. Glyphs of grid
+    /// fonts without per-glyph metrics are all char_width wide.
+    pub char_widths: Option<Vec<u16>>,
+    /// Blank columns skipped at the left of each glyph from first_char_num.
+    pub char_left_offsets: Option<Vec<u16>>,
+    /// Adjustments to the advance between pairs of glyphs.
+    pub kerning: FxHashMap<(u8, u8), i16>,
 }
 
-pub struct DrawTextParams<'a> {
-    pub font: &'a BitmapFont,
-    pub line_height: Option<u16>,
+/// Rows of an italic glyph are shifted right by one pixel for every this many rows
+/// they are above the bottom of the glyph.
+const ITALIC_SLANT: i32 = 2;
+
+/// How bitmap font text is drawn: the ink and background color the glyphs are copied
+/// with, the extra pixels between lines and the synthesized style.
+pub struct BitmapTextParams {
+    pub ink: u32,
+    pub bg_color: ColorRef,
     pub line_spacing: u16,
-    pub top_spacing: i16,
+    pub style: FontStyle,
+}
+
+/// Styles drawn by altering the plain glyphs, since bitmap fonts have no bold or italic variants.
+#[derive(Clone, Copy, Default)]
+pub struct FontStyle {
+    pub bold: bool,
+    pub italic: bool,
+    /// Extra pixels between characters, from the charSpacing of the member.
+    pub char_spacing: i32,
+}
+
+impl FontStyle {
+    /// Reads a style like "bold italic" or "plain".
+    pub fn parse(style: &str) -> FontStyle {
+        let style = style.to_lowercase();
+        FontStyle {
+            bold: style.contains("bold"),
+            italic: style.contains("italic"),
+            char_spacing: 0,
+        }
+    }
+
+    pub fn from_list(styles: &[String]) -> FontStyle {
+        FontStyle::parse(&styles.join(" "))
+    }
+}
+
+/// How dark a pixel of the font bitmap has to be to be part of a glyph.
+const GLYPH_INK_THRESHOLD: u8 = 128;
+
+/// The first and last column with ink in each row of a glyph.
+type GlyphRows = Vec<Option<(u16, u16)>>;
+
+impl BitmapFont {
+    pub fn get_char_width(&self, char_num: u8) -> u16 {
+        self.char_widths
+            .as_ref()
+            .zip(char_num.checked_sub(self.first_char_num))
+            .and_then(|(char_widths, index)| char_widths.get(index as usize))
+            .copied()
+            .unwrap_or(self.char_width)
+    }
+
+    pub fn get_char_left_offset(&self, char_num: u8) -> u16 {
+        self.char_left_offsets
+            .as_ref()
+            .zip(char_num.checked_sub(self.first_char_num))
+            .and_then(|(char_left_offsets, index)| char_left_offsets.get(index as usize))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Finds the columns with ink in each glyph cell of the 32-bit font bitmap.
+    fn read_glyph_rows(&self, bitmap: &Bitmap) -> Vec<GlyphRows> {
+        let glyph_count = self.grid_columns as u16 * self.grid_rows as u16;
+        (0..glyph_count)
+            .map(|index| {
+                let cell_x = (index % self.grid_columns as u16) * self.grid_cell_width + self.char_offset_x;
+                let cell_y = (index / self.grid_columns as u16) * self.grid_cell_height + self.char_offset_y;
+                (0..self.char_height)
+                    .map(|row| {
+                        let is_ink = |column: &u16| {
+                            let (x, y) = (cell_x + column, cell_y + row);
+                            let offset = (y as usize * bitmap.width as usize + x as usize) * 4;
+                            x < bitmap.width
+                                && y < bitmap.height
+                                && bitmap.data.get(offset).is_some_and(|red| *red < GLYPH_INK_THRESHOLD)
+                        };
+                        let first = (0..self.char_width).find(is_ink)?;
+                        let last = (0..self.char_width).rev().find(is_ink)?;
+                        Some((first, last))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Makes a grid font proportional by trimming each glyph to its ink, and kerns pairs
+    /// whose facing sides leave a gap of two pixels or more in every row they both have
+    /// ink in. Glyphs without ink, like the space, keep the full width.
+    pub fn measure_glyphs(&mut self, bitmap: &Bitmap) {
+        let glyph_rows = self.read_glyph_rows(bitmap);
+        let spans = glyph_rows
+            .iter()
+            .map(|rows| {
+                let first = rows.iter().flatten().map(|(first, _)| *first).min()?;
+                let last = rows.iter().flatten().map(|(_, last)| *last).max()?;
+                Some((first, last))
+            })
+            .collect::<Vec<_>>();
+        self.char_left_offsets = Some(spans.iter().map(|span| span.map_or(0, |(first, _)| first)).collect());
+        self.char_widths = Some(spans.iter().map(|span| span.map_or(self.char_width, |(first, last)| last - first + 1)).collect());
+
+        self.kerning.clear();
+        for (left_index, left_rows) in glyph_rows.iter().enumerate() {
+            let Some((_, left_last)) = spans[left_index] else { continue };
+            for (right_index, right_rows) in glyph_rows.iter().enumerate() {
+                let Some((right_first, _)) = spans[right_index] else { continue };
+                let gap = left_rows
+                    .iter()
+                    .zip(right_rows)
+                    .filter_map(|(left, right)| Some(left_last - left.as_ref()?.1 + right.as_ref()?.0 - right_first))
+                    .min();
+                let pair = self.first_char_num.checked_add(left_index as u8)
+                    .zip(self.first_char_num.checked_add(right_index as u8));
+                if let Some(pair) = pair.filter(|_| gap.is_some_and(|gap| gap >= 2)) {
+                    self.kerning.insert(pair, -1);
+                }
+            }
+        }
+    }
+
+    /// Distance from a glyph to the one after it, including the pixel between glyphs.
+    pub fn get_char_advance(&self, char_num: u8, next_char_num: Option<u8>, style: FontStyle) -> i32 {
+        let kerning = next_char_num
+            .and_then(|next_char_num| self.kerning.get(&(char_num, next_char_num)))
+            .copied()
+            .unwrap_or(0);
+        self.get_char_width(char_num) as i32 + 1 + kerning as i32 + style.bold as i32 + style.char_spacing
+    }
+
+    /// How far the top of an italic glyph leans past its plain width.
+    pub fn get_italic_overhang(&self, style: FontStyle) -> i32 {
+        if style.italic {
+            (self.char_height as i32 - 1) / ITALIC_SLANT
+        } else {
+            0
+        }
+    }
+}
+
+/// The characters of a text with the advance of each to the next.
+pub fn get_char_advances<'a>(font: &'a BitmapFont, text: &'a str, style: FontStyle) -> impl Iterator<Item = (char, i32)> + 'a {
+    let mut chars = text.chars().peekable();
+    std::iter::from_fn(move || {
+        let c = chars.next()?;
+        let next_char_num = chars.peek().map(|next| mac_roman_code(*next));
+        Some((c, font.get_char_advance(mac_roman_code(c), next_char_num, style)))
+    })
 }
 
 impl FontManager {
@@ -46,6 +212,7 @@ impl FontManager {
             system_font: None,
             fonts: FxHashMap::default(),
             font_counter: 0,
+            truetype_fonts: FxHashMap::default(),
         };
     }
 
@@ -55,49 +222,91 @@ impl FontManager {
             None => None,
         }
     }
-}
 
-pub async fn player_load_system_font(path: &str) {
-    let window = web_sys::window().unwrap();
-    let result = JsFuture::from(window.fetch_with_str(path)).await;
+    /// Finds the TrueType font for a font name, or the replacement registered for it.
+    pub fn get_truetype_font(&self, name: &str) -> Option<&Font> {
+        self.truetype_fonts.get(&name.to_lowercase()).or_else(|| {
+            get_font_substitute(name).and_then(|substitute| self.truetype_fonts.get(&substitute.to_lowercase()))
+        })
+    }
+}
 
-    match result {
-        Ok(result) => {
-            let result = result.dyn_into::<web_sys::Response>().unwrap();
-            let blob = JsFuture::from(result.blob().unwrap()).await.unwrap();
-            let blob = blob.dyn_into::<web_sys::Blob>().unwrap();
-            let image_data = window.create_image_bitmap_with_blob(&blob).unwrap();
-            let image_data = JsFuture::from(image_data).await.unwrap();
-            let image_bitmap = image_data.dyn_into::<web_sys::ImageBitmap>().unwrap();
+/// Measures the text of a text or field member, with its TrueType font when one is
+/// available and with the system font otherwise.
+pub fn measure_member_text(
+    font_manager: &FontManager,
+    text: &str,
+    font_name: &str,
+    font_size: u16,
+    style: FontStyle,
+    fixed_line_space: u16,
+    top_spacing: i16,
+) -> (u16, u16) {
+    match font_manager.get_truetype_font(font_name) {
+        Some(font) => measure_truetype_text(font, text, &TrueTypeTextParams {
+            size: font_size as f32,
+            color: (0, 0, 0),
+            anti_alias: false,
+            fixed_line_space,
+            top_spacing,
+            char_spacing: style.char_spacing as f32,
+        }),
+        None => match font_manager.get_system_font() {
+            Some(font) => measure_text(text, font, None, fixed_line_space, top_spacing, style),
+            None => (0, 0),
+        },
+    }
+}
 
-            let canvas = web_sys::window()
-                .unwrap()
-                .document()
-                .unwrap()
+/// Reads back the pixels of a decoded image. Workers have no document, so an
+/// OffscreenCanvas is used there instead.
+fn get_image_bitmap_data(image_bitmap: &web_sys::ImageBitmap) -> web_sys::ImageData {
+    let width = image_bitmap.width();
+    let height = image_bitmap.height();
+    match web_sys::window().and_then(|window| window.document()) {
+        Some(document) => {
+            let canvas = document
                 .create_element("canvas")
+                .unwrap()
+                .dyn_into::<web_sys::HtmlCanvasElement>()
                 .unwrap();
-            let canvas = canvas.dyn_into::<web_sys::HtmlCanvasElement>().unwrap();
-            canvas.set_width(image_bitmap.width());
-            canvas.set_height(image_bitmap.height());
+            canvas.set_width(width);
+            canvas.set_height(height);
             let context = canvas
                 .get_context("2d")
                 .unwrap()
                 .unwrap()
                 .dyn_into::<web_sys::CanvasRenderingContext2d>()
                 .unwrap();
-
-            context
-                .draw_image_with_image_bitmap(&image_bitmap, 0.0, 0.0)
+            context.draw_image_with_image_bitmap(image_bitmap, 0.0, 0.0).unwrap();
+            context.get_image_data(0.0, 0.0, width as f64, height as f64).unwrap()
+        }
+        None => {
+            let canvas = web_sys::OffscreenCanvas::new(width, height).unwrap();
+            let context = canvas
+                .get_context("2d")
+                .unwrap()
+                .unwrap()
+                .dyn_into::<web_sys::OffscreenCanvasRenderingContext2d>()
                 .unwrap();
+            context.draw_image_with_image_bitmap(image_bitmap, 0.0, 0.0).unwrap();
+            context.get_image_data(0.0, 0.0, width as f64, height as f64).unwrap()
+        }
+    }
+}
 
-            let image_data = context
-                .get_image_data(
-                    0.0,
-                    0.0,
-                    image_bitmap.width() as f64,
-                    image_bitmap.height() as f64,
-                )
-                .unwrap();
+pub async fn player_load_system_font(path: &str) {
+    let result = JsFuture::from(fetch_with_str(path)).await;
+
+    match result {
+        Ok(result) => {
+            let result = result.dyn_into::<web_sys::Response>().unwrap();
+            let blob = JsFuture::from(result.blob().unwrap()).await.unwrap();
+            let blob = blob.dyn_into::<web_sys::Blob>().unwrap();
+            let image_data = create_image_bitmap_with_blob(&blob).unwrap();
+            let image_data = JsFuture::from(image_data).await.unwrap();
+            let image_bitmap = image_data.dyn_into::<web_sys::ImageBitmap>().unwrap();
+            let image_data = get_image_bitmap_data(&image_bitmap);
 
             let bitmap = Bitmap {
                 width: image_data.width() as u16,
@@ -114,7 +323,7 @@ pub async fn player_load_system_font(path: &str) {
                 let grid_cell_height = bitmap.height / grid_rows;
 
                 let bitmap_ref = player.bitmap_manager.add_bitmap(bitmap);
-                let font = BitmapFont {
+                let mut font = BitmapFont {
                     bitmap_ref,
                     char_width: 5,
                     char_height: 7,
@@ -124,8 +333,14 @@ pub async fn player_load_system_font(path: &str) {
                     grid_cell_height,
                     first_char_num: 32,
                     char_offset_x: 1,
-                    char_offset_y: 1
+                    char_offset_y: 1,
+                    char_widths: None,
+                    char_left_offsets: None,
+                    kerning: FxHashMap::default(),
                 };
+                if let Some(bitmap) = player.bitmap_manager.get_bitmap(bitmap_ref) {
+                    font.measure_glyphs(bitmap);
+                }
                 let font_ref = player.font_manager.font_counter;
                 player.font_manager.font_counter += 1;
                 player.font_manager.fonts.insert(font_ref, font);
@@ -141,52 +356,89 @@ pub async fn player_load_system_font(path: &str) {
     };
 }
 
+/// Loads a TrueType font for text and field members using the font name, or one of the
+/// classic fonts it replaces.
+pub async fn player_load_font(name: &str, path: &str) {
+    let result = JsFuture::from(fetch_with_str(path)).await;
+    let response = match result {
+        Ok(response) => response.dyn_into::<web_sys::Response>().unwrap(),
+        Err(err) => {
+            warn!("Error fetching font {}: {:?}", name, err);
+            return;
+        }
+    };
+    let data = match response.array_buffer() {
+        Ok(data) => JsFuture::from(data).await,
+        Err(err) => Err(err),
+    };
+    let data = match data {
+        Ok(data) => Uint8Array::new(&data).to_vec(),
+        Err(err) => {
+            warn!("Error reading font {}: {:?}", name, err);
+            return;
+        }
+    };
+    match parse_truetype_font(&data) {
+        Ok(font) => reserve_player_mut(|player| {
+            player.font_manager.truetype_fonts.insert(name.to_lowercase(), font);
+        }),
+        Err(err) => warn!("Error parsing font {}: {}", name, err),
+    }
+}
+
 pub fn bitmap_font_copy_char(
     font: &BitmapFont,
     font_bitmap: &Bitmap,
     char_num: u8,
     dest: &mut Bitmap,
-    dest_x: i32,
-    dest_y: i32,
+    (dest_x, dest_y): (i32, i32),
     palettes: &PaletteMap,
-    draw_params: &CopyPixelsParams,
+    params: &BitmapTextParams,
 ) {
     if char_num < font.first_char_num {
         return;
     }
+    let char_width = font.get_char_width(char_num) as i32;
+    let char_left_offset = font.get_char_left_offset(char_num) as i32;
     let char_num = char_num - font.first_char_num;
     let char_x = char_num % font.grid_columns;
     let char_y = char_num / font.grid_columns;
 
-    let src_x = char_x as i32 * font.grid_cell_width as i32 + font.char_offset_x as i32;
+    let src_x = char_x as i32 * font.grid_cell_width as i32 + font.char_offset_x as i32 + char_left_offset;
     let src_y = char_y as i32 * font.grid_cell_height as i32 + font.char_offset_y as i32;
 
-    dest.copy_pixels_with_params(
-        palettes,
-        font_bitmap,
-        IntRect::from(
-            dest_x,
-            dest_y,
-            dest_x + font.char_width as i32,
-            dest_y + font.char_height as i32,
-        ),
-        IntRect::from(
-            src_x,
-            src_y,
-            src_x + font.char_width as i32,
-            src_y + font.char_height as i32,
-        ),
-        &draw_params,
-    )
+    let mut draw_params = CopyPixelsParams::default(dest);
+    draw_params.ink = params.ink;
+    draw_params.bg_color = params.bg_color.clone();
+    if !params.style.italic {
+        dest.copy_pixels_with_params(
+            palettes,
+            font_bitmap,
+            IntRect::from(dest_x, dest_y, dest_x + char_width, dest_y + font.char_height as i32),
+            IntRect::from(src_x, src_y, src_x + char_width, src_y + font.char_height as i32),
+            &draw_params,
+        );
+        return;
+    }
+    // Italics are synthesized by shearing the glyph one row at a time
+    for row in 0..font.char_height as i32 {
+        let shift = (font.char_height as i32 - 1 - row) / ITALIC_SLANT;
+        dest.copy_pixels_with_params(
+            palettes,
+            font_bitmap,
+            IntRect::from(dest_x + shift, dest_y + row, dest_x + shift + char_width, dest_y + row + 1),
+            IntRect::from(src_x, src_y + row, src_x + char_width, src_y + row + 1),
+            &draw_params,
+        );
+    }
 }
 
-pub fn measure_text(text: &str, font: &BitmapFont, line_height: Option<u16>, line_spacing: u16, top_spacing: i16) -> (u16, u16) {
+pub fn measure_text(text: &str, font: &BitmapFont, line_height: Option<u16>, line_spacing: u16, top_spacing: i16, style: FontStyle) -> (u16, u16) {
     let mut width = 0;
     let mut line_width = 0;
     let line_height = line_height.unwrap_or(font.char_height);
     let mut height = (top_spacing + line_height as i16) as u16;
-    let mut index = 0;
-    for c in text.chars() {
+    for (index, (c, advance)) in get_char_advances(font, text, style).enumerate() {
         if c == '\r' || c == '\n' {
             if line_width > width {
                 width = line_width;
@@ -196,69 +448,14 @@ pub fn measure_text(text: &str, font: &BitmapFont, line_height: Option<u16>, lin
             if line_width == 0 && index > 0 {
                 height += (line_height as i16 + line_spacing as i16 + 1) as u16;
             }
-            line_width += font.char_width + 1;
+            line_width += advance;
         }
-        index += 1;
     }
     if line_width > width {
         width = line_width;
     }
-    return (width, height);
-}
-
-pub fn _get_text_char_pos(text: &str, params: &DrawTextParams, char_index: usize) -> (i16, i16) {
-    let mut x = 0;
-    let mut y = params.top_spacing;
-    let mut line_width = 0;
-    let mut line_index = 0;
-    for c in text.chars() {
-        if c == '\r' || c == '\n' {
-            if line_index == char_index {
-                return (x, y);
-            }
-            if line_width > x {
-                x = line_width;
-            }
-            line_width = 0;
-            y += params.line_height.unwrap_or(params.font.char_height) as i16 + params.line_spacing as i16 + 1;
-        } else {
-            if line_index == char_index {
-                return (x, y);
-            }
-            line_width += params.font.char_width as i16 + 1;
-        }
-        line_index += 1;
-    }
-    if line_width > x {
-        x = line_width;
-    }
-    return (x, y);
-}
-
-pub fn get_text_index_at_pos(text: &str, params: &DrawTextParams, x: i32, y: i32) -> usize {
-    let mut index = 0;
-    let mut line_width = 0;
-    let mut line_y = params.top_spacing as i32;
-    for c in text.chars() {
-        if c == '\r' || c == '\n' {
-            if y >= line_y && y < line_y + params.line_height.unwrap_or(params.font.char_height) as i32 {
-                if x < line_width {
-                    return index;
-                }
-            }
-            if line_width > x {
-                line_width = 0;
-            }
-            line_y += params.line_height.unwrap_or(params.font.char_height) as i32 + params.line_spacing as i32 + 1;
-        } else {
-            if y >= line_y && y < line_y + params.line_height.unwrap_or(params.font.char_height) as i32 {
-                if x < line_width {
-                    return index;
-                }
-            }
-            line_width += params.font.char_width as i32 + 1;
-        }
-        index += 1;
+    if width > 0 {
+        width += font.get_italic_overhang(style);
     }
-    return index;
+    return (width.max(0) as u16, height);
 }
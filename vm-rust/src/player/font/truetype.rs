@@ -0,0 +1,139 @@
+use fontdue::{Font, FontSettings};
+
+use crate::player::bitmap::{bitmap::Bitmap, palette_map::PaletteMap};
+
+/// Fonts of classic Mac and Windows movies, with the metrics-compatible replacements
+/// looked up when the movie's own font isn't available.
+const FONT_SUBSTITUTES: &[(&str, &str)] = &[
+    ("arial", "Liberation Sans"),
+    ("helvetica", "Liberation Sans"),
+    ("geneva", "Liberation Sans"),
+    ("chicago", "Liberation Sans"),
+    ("times", "Liberation Serif"),
+    ("times new roman", "Liberation Serif"),
+    ("new york", "Liberation Serif"),
+    ("courier", "Liberation Mono"),
+    ("courier new", "Liberation Mono"),
+    ("monaco", "Liberation Mono"),
+];
+
+/// Without antialiasing, pixels at least this covered by a glyph are drawn solid.
+const ALIASED_COVERAGE_THRESHOLD: u8 = 128;
+
+pub fn get_font_substitute(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    FONT_SUBSTITUTES
+        .iter()
+        .find(|(font_name, _)| *font_name == name)
+        .map(|(_, substitute)| *substitute)
+}
+
+pub fn parse_truetype_font(data: &[u8]) -> Result<Font, String> {
+    Font::from_bytes(data, FontSettings::default()).map_err(|err| err.to_string())
+}
+
+pub struct TrueTypeTextParams {
+    pub size: f32,
+    pub color: (u8, u8, u8),
+    pub anti_alias: bool,
+    /// Height of each line, or 0 to use the font's own line height.
+    pub fixed_line_space: u16,
+    pub top_spacing: i16,
+    /// Extra pixels between characters.
+    pub char_spacing: f32,
+}
+
+/// The distance from the top of a line to its baseline, and the height of each line.
+pub fn get_line_height(font: &Font, params: &TrueTypeTextParams) -> (f32, f32) {
+    let (ascent, line_height) = match font.horizontal_line_metrics(params.size) {
+        Some(metrics) => (metrics.ascent, metrics.new_line_size),
+        None => (params.size, params.size),
+    };
+    if params.fixed_line_space > 0 {
+        (ascent, params.fixed_line_space as f32)
+    } else {
+        (ascent, line_height.ceil())
+    }
+}
+
+/// Where each character of a line starts, followed by where the line ends.
+pub fn get_char_offsets(font: &Font, line: &str, params: &TrueTypeTextParams) -> Vec<f32> {
+    let size = params.size;
+    let mut offsets = vec![0.0];
+    let mut x = 0.0;
+    let mut prev_char = None;
+    for c in line.chars() {
+        if let Some(kern) = prev_char.and_then(|prev| font.horizontal_kern(prev, c, size)) {
+            x += kern;
+            *offsets.last_mut().unwrap() = x;
+        }
+        x += font.metrics(c, size).advance_width + params.char_spacing;
+        offsets.push(x);
+        prev_char = Some(c);
+    }
+    offsets
+}
+
+fn measure_line(font: &Font, line: &str, params: &TrueTypeTextParams) -> f32 {
+    get_char_offsets(font, line, params).last().copied().unwrap_or(0.0)
+}
+
+pub fn measure_truetype_text(font: &Font, text: &str, params: &TrueTypeTextParams) -> (u16, u16) {
+    let (_, line_height) = get_line_height(font, params);
+    let lines = text.split(['\r', '\n']).collect::<Vec<_>>();
+    let width = lines
+        .iter()
+        .map(|line| measure_line(font, line, params))
+        .fold(0.0, f32::max);
+    let height = params.top_spacing as f32 + line_height * lines.len() as f32;
+    (width.ceil() as u16, height.max(0.0).ceil() as u16)
+}
+
+/// Rasterizes the text with the glyph outlines of a TrueType font. Antialiased text blends
+/// the glyph edges into the bitmap, otherwise partly covered pixels are drawn solid or not at all.
+pub fn draw_truetype_text(
+    dest: &mut Bitmap,
+    font: &Font,
+    text: &str,
+    loc_h: i32,
+    loc_v: i32,
+    params: &TrueTypeTextParams,
+    palettes: &PaletteMap,
+) {
+    let (ascent, line_height) = get_line_height(font, params);
+    let mut baseline = loc_v as f32 + params.top_spacing as f32 + ascent;
+    for line in text.split(['\r', '\n']) {
+        let mut x = loc_h as f32;
+        let mut prev_char = None;
+        for c in line.chars() {
+            if let Some(kern) = prev_char.and_then(|prev| font.horizontal_kern(prev, c, params.size)) {
+                x += kern;
+            }
+            let (metrics, coverage) = font.rasterize(c, params.size);
+            let glyph_x = x.round() as i32 + metrics.xmin;
+            let glyph_y = baseline.round() as i32 - metrics.height as i32 - metrics.ymin;
+            for (index, alpha) in coverage.iter().enumerate() {
+                let px = glyph_x + (index % metrics.width) as i32;
+                let py = glyph_y + (index / metrics.width) as i32;
+                if px < 0 || py < 0 || px >= dest.width as i32 || py >= dest.height as i32 {
+                    continue;
+                }
+                if !params.anti_alias {
+                    if *alpha >= ALIASED_COVERAGE_THRESHOLD {
+                        dest.set_pixel(px, py, params.color, palettes);
+                    }
+                } else if *alpha == 255 {
+                    dest.set_pixel(px, py, params.color, palettes);
+                } else if *alpha > 0 {
+                    let (r, g, b) = dest.get_pixel_color(palettes, px as u16, py as u16);
+                    let blend = |from: u8, to: u8| ((from as u32 * (255 - *alpha as u32) + to as u32 * *alpha as u32) / 255) as u8;
+                    let (cr, cg, cb) = params.color;
+                    dest.set_pixel(px, py, (blend(r, cr), blend(g, cg), blend(b, cb)), palettes);
+                }
+            }
+            x += metrics.advance_width + params.char_spacing;
+            prev_char = Some(c);
+        }
+        baseline += line_height;
+    }
+}
@@ -0,0 +1,66 @@
+use fxhash::FxHashMap;
+
+use crate::js_api::JsApi;
+
+use super::{datum_formatting::format_datum, DirPlayer};
+
+/// Reports a compact state digest to the host page every `interval` frames, so that
+/// external tools can follow gameplay without polling the player.
+pub struct FrameHook {
+  pub interval: u32,
+  frames_since_dispatch: u32,
+  last_globals: FxHashMap<String, String>,
+}
+
+pub struct FrameDigest {
+  pub frame: u32,
+  pub frame_label: Option<String>,
+  pub changed_globals: Vec<(String, String)>,
+}
+
+impl FrameHook {
+  pub fn new(interval: u32) -> FrameHook {
+    FrameHook {
+      interval,
+      frames_since_dispatch: 0,
+      last_globals: FxHashMap::default(),
+    }
+  }
+
+  pub fn on_frame(&mut self, player: &DirPlayer) -> Option<FrameDigest> {
+    self.frames_since_dispatch += 1;
+    if self.frames_since_dispatch < self.interval {
+      return None;
+    }
+    self.frames_since_dispatch = 0;
+
+    let mut changed_globals = vec![];
+    for (name, value_ref) in &player.globals {
+      let value = format_datum(value_ref, player);
+      if self.last_globals.get(name) != Some(&value) {
+        changed_globals.push((name.clone(), value.clone()));
+        self.last_globals.insert(name.clone(), value);
+      }
+    }
+    self.last_globals.retain(|name, _| player.globals.contains_key(name));
+
+    let frame_label = player.movie.score.frame_labels.iter()
+      .filter(|&label| label.frame_num <= player.movie.current_frame as i32)
+      .max_by_key(|label| label.frame_num)
+      .map(|label| label.label.clone());
+    Some(FrameDigest {
+      frame: player.movie.current_frame,
+      frame_label,
+      changed_globals,
+    })
+  }
+}
+
+pub fn player_dispatch_frame_hook(player: &mut DirPlayer) {
+  if let Some(mut frame_hook) = player.frame_hook.take() {
+    if let Some(digest) = frame_hook.on_frame(player) {
+      JsApi::dispatch_frame_digest(&digest);
+    }
+    player.frame_hook = Some(frame_hook);
+  }
+}
@@ -2,6 +2,7 @@ use log::warn;
 
 use crate::console_warn;
 
+#[derive(Clone)]
 pub struct IntRect {
   pub left: i32,
   pub top: i32,
@@ -64,4 +65,251 @@ impl IntRect {
 
     return IntRect::from(left, top, right, bottom);
   }
+
+  pub const fn offset(&self, dx: i32, dy: i32) -> IntRect {
+    IntRect::from(self.left + dx, self.top + dy, self.right + dx, self.bottom + dy)
+  }
+
+  pub fn intersects(&self, other: &IntRect) -> bool {
+    self.left < other.right && other.left < self.right && self.top < other.bottom && other.top < self.bottom
+  }
+}
+
+/// Skew and rotation of a sprite around its registration point, applied in Director's
+/// order after the sprite has been stretched and flipped into its rect. A sprite with a
+/// quad is instead projected from its rect onto the quad.
+pub struct SpriteTransform {
+  pivot: (f32, f32),
+  skew_tan: f32,
+  sin: f32,
+  cos: f32,
+  quad: Option<QuadProjection>,
+}
+
+/// The perspective mapping of a rect onto four points, and back.
+struct QuadProjection {
+  origin: (f64, f64),
+  size: (f64, f64),
+  matrix: [f64; 9],
+  inverse: [f64; 9],
+}
+
+impl QuadProjection {
+  /// The quad goes from the top left corner clockwise.
+  fn new(rect: &IntRect, quad: &[(f32, f32); 4]) -> QuadProjection {
+    let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = quad.map(|(x, y)| (x as f64, y as f64));
+    // Maps the unit square onto the quad, see Heckbert's "Fundamentals of Texture Mapping"
+    let (sx, sy) = (x0 - x1 + x2 - x3, y0 - y1 + y2 - y3);
+    let (dx1, dx2, dy1, dy2) = (x1 - x2, x3 - x2, y1 - y2, y3 - y2);
+    let den = dx1 * dy2 - dx2 * dy1;
+    let (g, h) = if (sx == 0.0 && sy == 0.0) || den == 0.0 {
+      (0.0, 0.0)
+    } else {
+      ((sx * dy2 - dx2 * sy) / den, (dx1 * sy - sx * dy1) / den)
+    };
+    let matrix = [
+      x1 - x0 + g * x1, x3 - x0 + h * x3, x0,
+      y1 - y0 + g * y1, y3 - y0 + h * y3, y0,
+      g, h, 1.0,
+    ];
+    QuadProjection {
+      origin: (rect.left as f64, rect.top as f64),
+      size: ((rect.width() as f64).max(1.0), (rect.height() as f64).max(1.0)),
+      matrix,
+      inverse: invert_matrix(&matrix),
+    }
+  }
+
+  fn apply(&self, point: (f32, f32)) -> (f32, f32) {
+    let u = (point.0 as f64 - self.origin.0) / self.size.0;
+    let v = (point.1 as f64 - self.origin.1) / self.size.1;
+    let (x, y) = project(&self.matrix, (u, v));
+    (x as f32, y as f32)
+  }
+
+  fn invert(&self, point: (f32, f32)) -> (f32, f32) {
+    let (u, v) = project(&self.inverse, (point.0 as f64, point.1 as f64));
+    ((self.origin.0 + u * self.size.0) as f32, (self.origin.1 + v * self.size.1) as f32)
+  }
+}
+
+fn project(matrix: &[f64; 9], (x, y): (f64, f64)) -> (f64, f64) {
+  let w = matrix[6] * x + matrix[7] * y + matrix[8];
+  // Points on the horizon of the quad don't map anywhere
+  let w = if w.abs() < f64::EPSILON { f64::EPSILON } else { w };
+  (
+    (matrix[0] * x + matrix[1] * y + matrix[2]) / w,
+    (matrix[3] * x + matrix[4] * y + matrix[5]) / w,
+  )
+}
+
+/// The adjugate of the matrix, which projects like its inverse.
+fn invert_matrix(m: &[f64; 9]) -> [f64; 9] {
+  [
+    m[4] * m[8] - m[5] * m[7], m[2] * m[7] - m[1] * m[8], m[1] * m[5] - m[2] * m[4],
+    m[5] * m[6] - m[3] * m[8], m[0] * m[8] - m[2] * m[6], m[2] * m[3] - m[0] * m[5],
+    m[3] * m[7] - m[4] * m[6], m[1] * m[6] - m[0] * m[7], m[0] * m[4] - m[1] * m[3],
+  ]
+}
+
+impl SpriteTransform {
+  pub fn new(pivot: (i32, i32), rotation: f32, skew: f32) -> SpriteTransform {
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    SpriteTransform {
+      pivot: (pivot.0 as f32, pivot.1 as f32),
+      skew_tan: skew.to_radians().tan(),
+      sin,
+      cos,
+      quad: None,
+    }
+  }
+
+  /// Projects the rect of a sprite onto a quad, from its top left corner clockwise.
+  pub fn from_quad(rect: &IntRect, quad: &[(f32, f32); 4]) -> SpriteTransform {
+    let mut transform = SpriteTransform::new((0, 0), 0.0, 0.0);
+    transform.quad = Some(QuadProjection::new(rect, quad));
+    transform
+  }
+
+  pub fn is_identity(&self) -> bool {
+    self.quad.is_none() && self.skew_tan == 0.0 && self.sin == 0.0 && self.cos == 1.0
+  }
+
+  /// Maps a point of the untransformed sprite rect to the stage.
+  pub fn apply(&self, point: (f32, f32)) -> (f32, f32) {
+    if let Some(quad) = &self.quad {
+      return quad.apply(point);
+    }
+    let y = point.1 - self.pivot.1;
+    let x = point.0 - self.pivot.0 + y * self.skew_tan;
+    (
+      self.pivot.0 + x * self.cos - y * self.sin,
+      self.pivot.1 + x * self.sin + y * self.cos,
+    )
+  }
+
+  /// Maps a stage point back into the untransformed sprite rect.
+  pub fn invert(&self, point: (f32, f32)) -> (f32, f32) {
+    if let Some(quad) = &self.quad {
+      return quad.invert(point);
+    }
+    let (dx, dy) = (point.0 - self.pivot.0, point.1 - self.pivot.1);
+    let y = -dx * self.sin + dy * self.cos;
+    let x = dx * self.cos + dy * self.sin - y * self.skew_tan;
+    (self.pivot.0 + x, self.pivot.1 + y)
+  }
+
+  /// The bounding box of a transformed rect.
+  pub fn bounds(&self, rect: &IntRect) -> IntRect {
+    let corners = [
+      self.apply((rect.left as f32, rect.top as f32)),
+      self.apply((rect.right as f32, rect.top as f32)),
+      self.apply((rect.right as f32, rect.bottom as f32)),
+      self.apply((rect.left as f32, rect.bottom as f32)),
+    ];
+    let min_x = corners.iter().map(|corner| corner.0).fold(f32::INFINITY, f32::min);
+    let min_y = corners.iter().map(|corner| corner.1).fold(f32::INFINITY, f32::min);
+    let max_x = corners.iter().map(|corner| corner.0).fold(f32::NEG_INFINITY, f32::max);
+    let max_y = corners.iter().map(|corner| corner.1).fold(f32::NEG_INFINITY, f32::max);
+    IntRect::from(min_x.floor() as i32, min_y.floor() as i32, max_x.ceil() as i32, max_y.ceil() as i32)
+  }
+}
+
+/// The rect a bitmap is stretched over so its registration point lands on the loc.
+/// Flipping the sprite mirrors the registration point within the bitmap.
+pub fn get_registered_rect(loc: (i32, i32), size: (i32, i32), bitmap_size: (i32, i32), reg_point: (i32, i32), flip: (bool, bool)) -> IntRect {
+  let reg_x = if flip.0 { bitmap_size.0 - reg_point.0 } else { reg_point.0 };
+  let reg_y = if flip.1 { bitmap_size.1 - reg_point.1 } else { reg_point.1 };
+  IntRect::from_size(loc.0 - reg_x, loc.1 - reg_y, size.0, size.1)
+}
+
+/// Where a point of a member lands in the rect it's stretched and flipped over, before
+/// the rect is rotated and skewed.
+pub fn map_member_to_rect(rect: &IntRect, member_size: (i32, i32), flip: (bool, bool), point: (f32, f32)) -> (f32, f32) {
+  let x = if flip.0 { member_size.0 as f32 - point.0 } else { point.0 };
+  let y = if flip.1 { member_size.1 as f32 - point.1 } else { point.1 };
+  (
+    rect.left as f32 + x * rect.width() as f32 / member_size.0.max(1) as f32,
+    rect.top as f32 + y * rect.height() as f32 / member_size.1.max(1) as f32,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use wasm_bindgen_test::*;
+
+  use super::*;
+
+  fn assert_near(actual: (f32, f32), expected: (f32, f32)) {
+    assert!(
+      (actual.0 - expected.0).abs() < 0.001 && (actual.1 - expected.1).abs() < 0.001,
+      "expected {:?}, got {:?}",
+      expected,
+      actual
+    );
+  }
+
+  #[wasm_bindgen_test]
+  fn sprite_transforms_invert_what_they_apply() {
+    let rect = IntRect::from(95, 48, 115, 58);
+    let transforms = [
+      SpriteTransform::new((100, 50), 30.0, 0.0),
+      SpriteTransform::new((100, 50), 0.0, 20.0),
+      SpriteTransform::new((100, 50), -135.0, 45.0),
+      SpriteTransform::from_quad(&rect, &[(90.0, 40.0), (130.0, 45.0), (120.0, 70.0), (92.0, 60.0)]),
+    ];
+    for transform in &transforms {
+      for point in [(95.0, 48.0), (115.0, 58.0), (101.5, 52.25), (0.0, 0.0)] {
+        assert_near(transform.invert(transform.apply(point)), point);
+        assert_near(transform.apply(transform.invert(point)), point);
+      }
+    }
+  }
+
+  // A 20x10 member with its registration point at (5, 2), placed at (100, 50)
+  fn registered_rect(flip: (bool, bool)) -> IntRect {
+    get_registered_rect((100, 50), (20, 10), (20, 10), (5, 2), flip)
+  }
+
+  fn member_corners(transform: &SpriteTransform, flip: (bool, bool)) -> Vec<(f32, f32)> {
+    let rect = registered_rect(flip);
+    [(0.0, 0.0), (20.0, 0.0), (20.0, 10.0), (0.0, 10.0)]
+      .iter()
+      .map(|corner| transform.apply(map_member_to_rect(&rect, (20, 10), flip, *corner)))
+      .collect()
+  }
+
+  fn assert_corners(actual: Vec<(f32, f32)>, expected: [(f32, f32); 4]) {
+    for (actual, expected) in actual.into_iter().zip(expected) {
+      assert_near(actual, expected);
+    }
+  }
+
+  #[wasm_bindgen_test]
+  fn sprite_transforms_rotate_clockwise_around_the_reg_point() {
+    let transform = SpriteTransform::new((100, 50), 90.0, 0.0);
+    let corners = member_corners(&transform, (false, false));
+    assert_corners(corners, [(102.0, 45.0), (102.0, 65.0), (92.0, 65.0), (92.0, 45.0)]);
+    // Half a turn lands each corner on the other side of the reg point
+    let transform = SpriteTransform::new((100, 50), 180.0, 0.0);
+    let corners = member_corners(&transform, (false, false));
+    assert_corners(corners, [(105.0, 52.0), (85.0, 52.0), (85.0, 42.0), (105.0, 42.0)]);
+  }
+
+  #[wasm_bindgen_test]
+  fn sprite_transforms_skew_rows_below_the_reg_point_right() {
+    let transform = SpriteTransform::new((100, 50), 0.0, 45.0);
+    let corners = member_corners(&transform, (false, false));
+    assert_corners(corners, [(93.0, 48.0), (113.0, 48.0), (123.0, 58.0), (103.0, 58.0)]);
+  }
+
+  #[wasm_bindgen_test]
+  fn flipping_mirrors_the_reg_point_before_the_rotation() {
+    let transform = SpriteTransform::new((100, 50), 0.0, 0.0);
+    assert_corners(member_corners(&transform, (true, false)), [(105.0, 48.0), (85.0, 48.0), (85.0, 58.0), (105.0, 58.0)]);
+    assert_corners(member_corners(&transform, (false, true)), [(95.0, 52.0), (115.0, 52.0), (115.0, 42.0), (95.0, 42.0)]);
+    let transform = SpriteTransform::new((100, 50), 90.0, 0.0);
+    assert_corners(member_corners(&transform, (true, false)), [(102.0, 55.0), (102.0, 35.0), (92.0, 35.0), (92.0, 55.0)]);
+    assert_corners(member_corners(&transform, (false, true)), [(98.0, 45.0), (98.0, 65.0), (108.0, 65.0), (108.0, 45.0)]);
+  }
 }
@@ -1,4 +1,6 @@
-use crate::{director::lingo::datum::Datum, player::{reserve_player_mut, DatumRef, ScriptError}};
+use crate::{director::lingo::datum::Datum, player::{cast_lib::CastMemberRef, reserve_player_mut, DatumRef, DirPlayer, ScriptError}};
+
+use super::movie::MovieHandlers;
 
 
 pub struct CastHandlers { }
@@ -27,4 +29,127 @@ impl CastHandlers {
       }
     })
   }
-}
\ No newline at end of file
+
+  fn frame_arg(player: &DirPlayer, arg: &DatumRef) -> Result<u32, ScriptError> {
+    MovieHandlers::resolve_frame(player, player.get_datum(arg))?
+      .ok_or_else(|| ScriptError::new("Invalid frame".to_string()))
+  }
+
+  fn member_arg(player: &DirPlayer, arg: &DatumRef) -> Result<CastMemberRef, ScriptError> {
+    let datum = player.get_datum(arg);
+    if let Datum::CastMember(member_ref) = datum {
+      return Ok(member_ref.to_owned());
+    }
+    player.movie.cast_manager.find_member_ref_by_identifiers(datum, None, &player.allocator)?
+      .ok_or_else(|| ScriptError::new("Member not found".to_string()))
+  }
+
+  /// Every member without args, a single member, or the members from one through
+  /// another within the cast of the first.
+  fn member_range(player: &DirPlayer, args: &[DatumRef]) -> Result<Vec<CastMemberRef>, ScriptError> {
+    match args {
+      [] => Ok(player.movie.cast_manager.all_member_refs()),
+      [member] => Ok(vec![Self::member_arg(player, member)?]),
+      [first, last, ..] => {
+        let first = Self::member_arg(player, first)?;
+        let last = Self::member_arg(player, last)?;
+        let members = (first.cast_member..=last.cast_member)
+          .map(|number| CastMemberRef { cast_lib: first.cast_lib, cast_member: number })
+          .filter(|member_ref| player.movie.cast_manager.find_member_by_ref(member_ref).is_some())
+          .collect();
+        Ok(members)
+      }
+    }
+  }
+
+  fn preload_members(player: &DirPlayer, members: &[CastMemberRef]) {
+    for member_ref in members {
+      if let Some(bitmap_ref) = player.movie.cast_manager.get_member_bitmap_ref(member_ref) {
+        player.bitmap_manager.preload_bitmap(bitmap_ref);
+      }
+    }
+  }
+
+  /// Members still on stage stay loaded.
+  fn unload_members(player: &mut DirPlayer, members: &[CastMemberRef]) {
+    for member_ref in members {
+      if !player.movie.cast_manager.can_purge_member(member_ref, &player.movie.score) {
+        continue;
+      }
+      if let Some(bitmap_ref) = player.movie.cast_manager.get_member_bitmap_ref(member_ref) {
+        player.bitmap_manager.unload_bitmap(bitmap_ref);
+      }
+    }
+  }
+
+  /// preLoad loads the members of the frames from the current one through the last
+  /// frame of the movie, through a given frame, or of a range of frames. Returns the
+  /// last frame that was loaded.
+  pub fn pre_load(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let current_frame = player.movie.current_frame;
+      let (first_frame, last_frame) = match args {
+        [] => (current_frame, player.movie.score.get_last_frame()),
+        [last] => (current_frame, Self::frame_arg(player, last)?),
+        [first, last, ..] => (Self::frame_arg(player, first)?, Self::frame_arg(player, last)?),
+      };
+      let members = player.movie.score.get_members_in_frames(first_frame, last_frame);
+      Self::preload_members(player, &members);
+      Ok(player.alloc_datum(Datum::Int(last_frame as i32)))
+    })
+  }
+
+  /// unLoad without args unloads every member that isn't on stage, otherwise the
+  /// members of a frame or a range of frames.
+  pub fn unload(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let members = match args {
+        [] => player.movie.cast_manager.all_member_refs(),
+        [frame] => {
+          let frame = Self::frame_arg(player, frame)?;
+          player.movie.score.get_members_in_frames(frame, frame)
+        }
+        [first, last, ..] => {
+          let first_frame = Self::frame_arg(player, first)?;
+          let last_frame = Self::frame_arg(player, last)?;
+          player.movie.score.get_members_in_frames(first_frame, last_frame)
+        }
+      };
+      Self::unload_members(player, &members);
+      Ok(DatumRef::Void)
+    })
+  }
+
+  pub fn pre_load_member(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let members = Self::member_range(player, args)?;
+      Self::preload_members(player, &members);
+      Ok(DatumRef::Void)
+    })
+  }
+
+  pub fn unload_member(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let members = Self::member_range(player, args)?;
+      Self::unload_members(player, &members);
+      Ok(DatumRef::Void)
+    })
+  }
+
+  /// Bytes needed to hold the decoded members of a range of frames.
+  pub fn ram_needed(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      if args.len() < 2 {
+        return Err(ScriptError::new("ramNeeded expects a first and last frame".to_string()));
+      }
+      let first_frame = Self::frame_arg(player, &args[0])?;
+      let last_frame = Self::frame_arg(player, &args[1])?;
+      let size = player.movie.score.get_members_in_frames(first_frame, last_frame)
+        .iter()
+        .filter_map(|member_ref| player.movie.cast_manager.get_member_bitmap_ref(member_ref))
+        .map(|bitmap_ref| player.bitmap_manager.get_decoded_size(bitmap_ref))
+        .sum::<usize>();
+      Ok(player.alloc_datum(Datum::Int(size.min(i32::MAX as usize) as i32)))
+    })
+  }
+}
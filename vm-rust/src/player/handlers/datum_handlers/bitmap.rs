@@ -49,9 +49,7 @@ impl BitmapDatumHandlers {
         return Err(ScriptError::new("Invalid number of arguments for createMatte".to_string()));
       }
       let bitmap = player.get_datum(datum).to_bitmap_ref()?;
-      let bitmap = player.bitmap_manager.get_bitmap_mut(*bitmap).unwrap();
-      bitmap.create_matte(&player.movie.cast_manager.palettes());
-      let matte_arc = bitmap.matte.as_ref().unwrap().clone();
+      let matte_arc = player.bitmap_manager.get_or_create_matte(*bitmap, &player.movie.cast_manager.palettes()).unwrap();
       Ok(player.alloc_datum(Datum::Matte(matte_arc)))
     })
   }
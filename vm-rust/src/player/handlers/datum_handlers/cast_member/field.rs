@@ -1,9 +1,12 @@
 use crate::{
-    director::lingo::datum::{Datum, StringChunkType},
+    director::lingo::datum::{datum_bool, Datum, StringChunkType},
+    io::text_encoding::TextEncoding,
     player::{
         cast_lib::CastMemberRef,
+        font::layout::TextLayout,
         handlers::datum_handlers::{
-            cast_member_ref::borrow_member_mut, string_chunk::StringChunkUtils,
+            cast_member::text_geometry::TextGeometryHandlers, cast_member_ref::borrow_member_mut,
+            string_chunk::StringChunkUtils,
         },
         DatumRef, DirPlayer, ScriptError,
     },
@@ -58,6 +61,31 @@ impl FieldMemberHandlers {
         let field = member.member_type.as_field().unwrap();
         match prop.as_str() {
             "text" => Ok(Datum::String(field.text.to_owned())),
+            "alignment" => Ok(Datum::String(field.alignment.to_owned())),
+            "wordWrap" => Ok(datum_bool(field.word_wrap)),
+            "font" => Ok(Datum::String(field.font.to_owned())),
+            "fontSize" => Ok(Datum::Int(field.font_size as i32)),
+            "fontStyle" => Ok(Datum::String(field.font_style.to_owned())),
+            "fixedLineSpace" => Ok(Datum::Int(field.fixed_line_space as i32)),
+            "topSpacing" => Ok(Datum::Int(field.top_spacing as i32)),
+            "charSpacing" => Ok(Datum::Int(field.char_spacing as i32)),
+            "boxType" => Ok(Datum::Symbol(field.box_type.to_owned())),
+            "antialias" => Ok(datum_bool(field.anti_alias)),
+            "autoTab" => Ok(datum_bool(field.auto_tab)),
+            "editable" => Ok(datum_bool(field.editable)),
+            "border" => Ok(Datum::Int(field.border as i32)),
+            "encoding" => Ok(Datum::Symbol(field.encoding.name().to_string())),
+            "width" => Ok(Datum::Int(field.width as i32)),
+            "height" | "rect" => {
+                let height = TextLayout::for_member(&player.font_manager, &member.member_type)
+                    .map_or(field.height as i32, |layout| layout.get_field_box_height(field));
+                if prop == "height" {
+                    Ok(Datum::Int(height))
+                } else {
+                    Ok(Datum::IntRect((0, 0, field.width as i32, height)))
+                }
+            }
+            "lineCount" | "lineHeight" | "scrollTop" => TextGeometryHandlers::get_prop(player, cast_member_ref, prop),
             _ => Err(ScriptError::new(format!(
                 "Cannot get castMember property {} for field",
                 prop
@@ -79,13 +107,15 @@ impl FieldMemberHandlers {
                     Ok(())
                 },
             ),
+            "scrollTop" => TextGeometryHandlers::set_scroll_top(member_ref, value),
             "rect" => borrow_member_mut(
                 member_ref,
                 |_| value.to_int_rect(),
                 |cast_member, value| {
                     let value = value?;
                     let field_data = cast_member.member_type.as_field_mut().unwrap();
-                    field_data.width = value.2 as u16;
+                    field_data.width = (value.2 - value.0).clamp(0, u16::MAX as i32) as u16;
+                    field_data.height = (value.3 - value.1).clamp(0, u16::MAX as i32) as u16;
                     Ok(())
                 },
             ),
@@ -109,7 +139,15 @@ impl FieldMemberHandlers {
                 member_ref,
                 |player| value.int_value(),
                 |cast_member, value| {
-                    cast_member.member_type.as_field_mut().unwrap().width = value? as u16;
+                    cast_member.member_type.as_field_mut().unwrap().width = value?.clamp(0, u16::MAX as i32) as u16;
+                    Ok(())
+                },
+            ),
+            "height" => borrow_member_mut(
+                member_ref,
+                |_| value.int_value(),
+                |cast_member, value| {
+                    cast_member.member_type.as_field_mut().unwrap().height = value?.clamp(0, u16::MAX as i32) as u16;
                     Ok(())
                 },
             ),
@@ -139,7 +177,7 @@ impl FieldMemberHandlers {
             ),
             "fixedLineSpace" => borrow_member_mut(
                 member_ref,
-                |player| value.bool_value(),
+                |player| value.int_value(),
                 |cast_member, value| {
                     cast_member
                         .member_type
@@ -157,6 +195,14 @@ impl FieldMemberHandlers {
                     Ok(())
                 },
             ),
+            "charSpacing" => borrow_member_mut(
+                member_ref,
+                |_| value.int_value(),
+                |cast_member, value| {
+                    cast_member.member_type.as_field_mut().unwrap().char_spacing = value? as i16;
+                    Ok(())
+                },
+            ),
             "boxType" => borrow_member_mut(
                 member_ref,
                 |player| value.string_value(),
@@ -197,6 +243,19 @@ impl FieldMemberHandlers {
                     Ok(())
                 },
             ),
+            "encoding" => borrow_member_mut(
+                member_ref,
+                |_| value.string_value(),
+                |cast_member, value| {
+                    let value = value?;
+                    let encoding = TextEncoding::from_name(&value)
+                        .ok_or_else(|| ScriptError::new(format!("Unknown text encoding {}", value)))?;
+                    let field_data = cast_member.member_type.as_field_mut().unwrap();
+                    field_data.text = encoding.transcode(&field_data.text, field_data.encoding);
+                    field_data.encoding = encoding;
+                    Ok(())
+                },
+            ),
             _ => Err(ScriptError::new(format!(
                 "Cannot set castMember prop {} for field",
                 prop
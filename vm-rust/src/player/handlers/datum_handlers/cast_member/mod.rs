@@ -1,4 +1,6 @@
 pub mod text;
 pub mod field;
 pub mod bitmap;
-pub mod film_loop;
\ No newline at end of file
+pub mod film_loop;
+pub mod script;
+pub mod text_geometry;
\ No newline at end of file
@@ -0,0 +1,52 @@
+use crate::{
+    director::{enums::ScriptType, lingo::datum::Datum},
+    player::{authoring::set_script_text, cast_lib::CastMemberRef, export::script_member_text, reserve_player_mut, DirPlayer, ScriptError},
+};
+
+pub struct ScriptMemberHandlers {}
+
+impl ScriptMemberHandlers {
+    pub fn get_prop(
+        player: &mut DirPlayer,
+        cast_member_ref: &CastMemberRef,
+        prop: &String,
+    ) -> Result<Datum, ScriptError> {
+        let cast = player.movie.cast_manager.get_cast(cast_member_ref.cast_lib as u32)?;
+        let script = cast
+            .members
+            .get(&(cast_member_ref.cast_member as u32))
+            .and_then(|member| member.member_type.as_script())
+            .unwrap();
+        match prop.as_str() {
+            // Lines of Lingo text end with returns
+            "scriptText" => Ok(Datum::String(script_member_text(cast, script)?.replace('\n', "\r"))),
+            "scriptType" => {
+                let script_type = match script.script_type {
+                    ScriptType::Movie => "movie",
+                    ScriptType::Parent => "parent",
+                    ScriptType::Score => "score",
+                    ScriptType::Invalid => "unknown",
+                };
+                Ok(Datum::Symbol(script_type.to_string()))
+            }
+            _ => Err(ScriptError::new(format!(
+                "Cannot get castMember property {} for script",
+                prop
+            ))),
+        }
+    }
+
+    pub fn set_prop(
+        member_ref: &CastMemberRef,
+        prop: &String,
+        value: Datum,
+    ) -> Result<(), ScriptError> {
+        match prop.as_str() {
+            "scriptText" => reserve_player_mut(|player| set_script_text(player, member_ref, &value.string_value()?)),
+            _ => Err(ScriptError::new(format!(
+                "Cannot set castMember property {} for script",
+                prop
+            ))),
+        }
+    }
+}
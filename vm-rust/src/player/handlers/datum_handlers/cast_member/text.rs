@@ -1,7 +1,7 @@
 use crate::{
     director::lingo::datum::{datum_bool, Datum, DatumType, StringChunkExpr, StringChunkSource, StringChunkType},
     player::{
-        bitmap::bitmap::{Bitmap, BuiltInPalette, PaletteRef}, cast_lib::CastMemberRef, font::{get_text_index_at_pos, measure_text, DrawTextParams}, handlers::datum_handlers::{cast_member_ref::borrow_member_mut, string_chunk::StringChunkUtils}, DatumRef, DirPlayer, ScriptError
+        bitmap::bitmap::{Bitmap, BuiltInPalette, PaletteRef}, cast_lib::CastMemberRef, cast_member::TextHyperlink, font::{measure_member_text, truetype::{draw_truetype_text, TrueTypeTextParams}, BitmapTextParams}, handlers::datum_handlers::{cast_member::text_geometry::TextGeometryHandlers, cast_member_ref::borrow_member_mut, string_chunk::StringChunkUtils}, DatumRef, DirPlayer, ScriptError
     },
 };
 
@@ -36,17 +36,6 @@ impl TextMemberHandlers {
               let resolved_str = StringChunkUtils::resolve_chunk_expr_string(&text.text, &chunk_expr)?;
               Ok(player.alloc_datum(Datum::StringChunk(StringChunkSource::Member(member_ref), chunk_expr, resolved_str)))
             }
-            "locToCharPos" => {
-                let (x, y) = player.get_datum(&args[0]).to_int_point()?;
-                let params = DrawTextParams {
-                    font: player.font_manager.get_system_font().unwrap(),
-                    line_height: None,
-                    line_spacing: text.fixed_line_space,
-                    top_spacing: text.top_spacing,
-                };
-                let index = get_text_index_at_pos(&text.text, &params, x, y);
-                Ok(player.alloc_datum(Datum::Int((index + 1) as i32)))
-            }
             _ => Err(ScriptError::new(format!("No handler {handler_name} for text member type")))
           }
     }
@@ -78,25 +67,37 @@ impl TextMemberHandlers {
             }
             "fixedLineSpace" => Ok(Datum::Int(text_data.fixed_line_space as i32)),
             "topSpacing" => Ok(Datum::Int(text_data.top_spacing as i32)),
+            "charSpacing" => Ok(Datum::Int(text_data.char_spacing as i32)),
             "boxType" => Ok(Datum::Symbol(text_data.box_type.to_owned())),
             "antialias" => Ok(datum_bool(text_data.anti_alias)),
+            "hyperlinks" => {
+                let ranges = text_data
+                    .hyperlinks
+                    .iter()
+                    .map(|link| Self::alloc_range(player, link.range))
+                    .collect();
+                Ok(Datum::List(DatumType::List, ranges, false))
+            }
+            "lineCount" | "lineHeight" | "scrollTop" => TextGeometryHandlers::get_prop(player, cast_member_ref, prop),
             "rect" => {
-                let font = player.font_manager.get_system_font().unwrap();
-                let (width, height) = measure_text(
+                let (width, height) = measure_member_text(
+                    &player.font_manager,
                     &text_data.text,
-                    &font,
-                    None,
+                    &text_data.font,
+                    text_data.font_size,
+                    text_data.get_font_style(),
                     text_data.fixed_line_space,
                     text_data.top_spacing,
                 );
                 Ok(Datum::IntRect((0, 0, width as i32, height as i32)))
             }
             "height" => {
-                let font = player.font_manager.get_system_font().unwrap();
-                let (_, height) = measure_text(
+                let (_, height) = measure_member_text(
+                    &player.font_manager,
                     &text_data.text,
-                    &font,
-                    None,
+                    &text_data.font,
+                    text_data.font_size,
+                    text_data.get_font_style(),
                     text_data.fixed_line_space,
                     text_data.top_spacing,
                 );
@@ -104,11 +105,12 @@ impl TextMemberHandlers {
             }
             "image" => {
                 // TODO: alignment
-                let font = player.font_manager.get_system_font().unwrap();
-                let (width, height) = measure_text(
+                let (width, height) = measure_member_text(
+                    &player.font_manager,
                     &text_data.text,
-                    &font,
-                    None,
+                    &text_data.font,
+                    text_data.font_size,
+                    text_data.get_font_style(),
                     text_data.fixed_line_space,
                     text_data.top_spacing,
                 );
@@ -119,21 +121,36 @@ impl TextMemberHandlers {
                     8,
                     PaletteRef::BuiltIn(BuiltInPalette::GrayScale),
                 );
-                let font_bitmap = player.bitmap_manager.get_bitmap(font.bitmap_ref).unwrap();
                 let palettes = player.movie.cast_manager.palettes();
+                if let Some(font) = player.font_manager.get_truetype_font(&text_data.font) {
+                    let params = TrueTypeTextParams {
+                        size: text_data.font_size as f32,
+                        color: (0, 0, 0),
+                        anti_alias: text_data.anti_alias,
+                        fixed_line_space: text_data.fixed_line_space,
+                        top_spacing: text_data.top_spacing,
+                        char_spacing: text_data.char_spacing as f32,
+                    };
+                    draw_truetype_text(&mut bitmap, font, &text_data.text, 0, 0, &params, &palettes);
+                    let bitmap_ref = player.bitmap_manager.add_bitmap(bitmap);
+                    return Ok(Datum::BitmapRef(bitmap_ref));
+                }
+                let font = player.font_manager.get_system_font().unwrap();
+                let font_bitmap = player.bitmap_manager.get_bitmap(font.bitmap_ref).unwrap();
 
-                let ink = 36;
+                let params = BitmapTextParams {
+                    ink: 36,
+                    bg_color: bitmap.get_bg_color_ref(),
+                    line_spacing: text_data.fixed_line_space,
+                    style: text_data.get_font_style(),
+                };
                 bitmap.draw_text(
                     &text_data.text,
                     font,
                     font_bitmap,
-                    0,
-                    text_data.top_spacing as i32,
-                    ink,
-                    bitmap.get_bg_color_ref(),
+                    (0, text_data.top_spacing as i32),
                     &palettes,
-                    text_data.fixed_line_space,
-                    text_data.top_spacing,
+                    &params,
                 );
 
                 let bitmap_ref = player.bitmap_manager.add_bitmap(bitmap);
@@ -156,7 +173,9 @@ impl TextMemberHandlers {
                 member_ref,
                 |player| value.string_value(),
                 |cast_member, value| {
-                    cast_member.member_type.as_text_mut().unwrap().text = value?;
+                    let text_data = cast_member.member_type.as_text_mut().unwrap();
+                    text_data.text = value?;
+                    text_data.hyperlinks.clear();
                     Ok(())
                 },
             ),
@@ -234,6 +253,14 @@ impl TextMemberHandlers {
                     Ok(())
                 },
             ),
+            "charSpacing" => borrow_member_mut(
+                member_ref,
+                |_| value.int_value(),
+                |cast_member, value| {
+                    cast_member.member_type.as_text_mut().unwrap().char_spacing = value? as i16;
+                    Ok(())
+                },
+            ),
             "boxType" => borrow_member_mut(
                 member_ref,
                 |player| value.string_value(),
@@ -250,6 +277,7 @@ impl TextMemberHandlers {
                     Ok(())
                 },
             ),
+            "scrollTop" => TextGeometryHandlers::set_scroll_top(member_ref, value),
             "rect" => borrow_member_mut(
                 member_ref,
                 |player| {
@@ -271,4 +299,75 @@ impl TextMemberHandlers {
             ))),
         }
     }
+
+    fn alloc_range(player: &mut DirPlayer, (start, end): (usize, usize)) -> DatumRef {
+        let items = vec![player.alloc_datum(Datum::Int(start as i32)), player.alloc_datum(Datum::Int(end as i32))];
+        player.alloc_datum(Datum::List(DatumType::List, items, false))
+    }
+
+    fn get_chunk_range(player: &DirPlayer, member_ref: &CastMemberRef, chunk_expr: &StringChunkExpr) -> Result<Option<(usize, usize)>, ScriptError> {
+        let member = player.movie.cast_manager.find_member_by_ref(member_ref)
+            .ok_or_else(|| ScriptError::new("Cannot get hyperlink of a missing member".to_string()))?;
+        let text_data = member.member_type.as_text()
+            .ok_or_else(|| ScriptError::new("Hyperlinks are only supported on text members".to_string()))?;
+        Ok(StringChunkUtils::resolve_chunk_char_range(&text_data.text, chunk_expr))
+    }
+
+    /// The hyperlink, hyperlinkRange or hyperlinkState of a chunk of a text member, taken from
+    /// the link at the start of the chunk.
+    pub fn get_chunk_hyperlink_prop(
+        player: &mut DirPlayer,
+        member_ref: &CastMemberRef,
+        chunk_expr: &StringChunkExpr,
+        prop: &str,
+    ) -> Result<Datum, ScriptError> {
+        let range = Self::get_chunk_range(player, member_ref, chunk_expr)?;
+        let text_data = player.movie.cast_manager.find_member_by_ref(member_ref).unwrap().member_type.as_text().unwrap();
+        let link = range
+            .and_then(|(start, _)| text_data.get_hyperlink_at(start))
+            .map(|index| text_data.hyperlinks[index].clone());
+        match prop {
+            "hyperlink" => Ok(Datum::String(link.map(|link| link.link).unwrap_or_default())),
+            "hyperlinkRange" => {
+                let (start, end) = link.map_or((0, 0), |link| link.range);
+                let items = vec![player.alloc_datum(Datum::Int(start as i32)), player.alloc_datum(Datum::Int(end as i32))];
+                Ok(Datum::List(DatumType::List, items, false))
+            }
+            "hyperlinkState" => Ok(Datum::Symbol(link.map_or("normal".to_string(), |link| link.state))),
+            _ => Err(ScriptError::new(format!("Cannot get hyperlink property {}", prop))),
+        }
+    }
+
+    /// Links a chunk of a text member, replacing the links it overlaps, or unlinks it when
+    /// given an empty string. Setting the hyperlinkState changes the link at its start.
+    pub fn set_chunk_hyperlink_prop(
+        player: &mut DirPlayer,
+        member_ref: &CastMemberRef,
+        chunk_expr: &StringChunkExpr,
+        prop: &str,
+        value: Datum,
+    ) -> Result<(), ScriptError> {
+        let range = match Self::get_chunk_range(player, member_ref, chunk_expr)? {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+        let value = value.string_value()?;
+        let text_data = player.movie.cast_manager.find_mut_member_by_ref(member_ref).unwrap().member_type.as_text_mut().unwrap();
+        match prop {
+            "hyperlink" => {
+                text_data.hyperlinks.retain(|link| link.range.1 < range.0 || link.range.0 > range.1);
+                if !value.is_empty() {
+                    text_data.hyperlinks.push(TextHyperlink { range, link: value, state: "normal".to_string() });
+                    text_data.hyperlinks.sort_by_key(|link| link.range.0);
+                }
+            }
+            "hyperlinkState" => {
+                if let Some(index) = text_data.get_hyperlink_at(range.0) {
+                    text_data.hyperlinks[index].state = value;
+                }
+            }
+            _ => return Err(ScriptError::new(format!("Cannot set hyperlink property {}", prop))),
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,183 @@
+use crate::{
+    director::lingo::datum::Datum,
+    player::{
+        cast_lib::CastMemberRef,
+        cast_member::CastMemberType,
+        font::layout::TextLayout,
+        reserve_player_mut,
+        score::get_sprite_at, DatumRef, DirPlayer, ScriptError,
+    },
+};
+
+/// The Lingo functions that locate characters and lines in text and field members,
+/// and scroll them.
+pub struct TextGeometryHandlers {}
+
+fn get_member_layout(player: &DirPlayer, member_ref: &CastMemberRef) -> Result<TextLayout, ScriptError> {
+    let member = player
+        .movie
+        .cast_manager
+        .find_member_by_ref(member_ref)
+        .ok_or_else(|| ScriptError::new("Cannot get text geometry of a missing member".to_string()))?;
+    TextLayout::for_member(&player.font_manager, &member.member_type)
+        .ok_or_else(|| ScriptError::new("Text geometry needs a text or field member".to_string()))
+}
+
+fn get_scroll_top(player: &DirPlayer, member_ref: &CastMemberRef) -> i32 {
+    match player.movie.cast_manager.find_member_by_ref(member_ref).map(|member| &member.member_type) {
+        Some(CastMemberType::Field(field)) => field.scroll_top as i32,
+        Some(CastMemberType::Text(text)) => text.scroll_top as i32,
+        _ => 0,
+    }
+}
+
+fn set_scroll_top(player: &mut DirPlayer, member_ref: &CastMemberRef, scroll_top: i32) {
+    let scroll_top = scroll_top.clamp(0, u16::MAX as i32) as u16;
+    match player.movie.cast_manager.find_mut_member_by_ref(member_ref).map(|member| &mut member.member_type) {
+        Some(CastMemberType::Field(field)) => field.scroll_top = scroll_top,
+        Some(CastMemberType::Text(text)) => text.scroll_top = scroll_top,
+        _ => {}
+    }
+}
+
+/// The height of the box a member is shown in, taken from the first sprite showing it.
+fn get_page_height(player: &DirPlayer, member_ref: &CastMemberRef, layout: &TextLayout) -> i32 {
+    player
+        .movie
+        .score
+        .channels
+        .iter()
+        .map(|channel| &channel.sprite)
+        .find(|sprite| sprite.member.as_ref() == Some(member_ref) && sprite.height > 0)
+        .map_or(layout.get_line_height(1), |sprite| sprite.height)
+}
+
+impl TextGeometryHandlers {
+    pub fn call(datum: &DatumRef, handler_name: &String, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+        reserve_player_mut(|player| {
+            let member_ref = player.get_datum(datum).to_member_ref()?;
+            let layout = get_member_layout(player, &member_ref)?;
+            let result = match handler_name.as_str() {
+                "charPosToLoc" => {
+                    let char_pos = player.get_datum(&args[0]).int_value()?;
+                    Datum::IntPoint(layout.get_char_loc(char_pos.max(0) as usize))
+                }
+                "locToCharPos" => {
+                    let (x, y) = player.get_datum(&args[0]).to_int_point()?;
+                    Datum::Int(layout.get_char_pos_at(x, y) as i32)
+                }
+                "lineHeight" => {
+                    let line_num = player.get_datum(&args[0]).int_value()?;
+                    Datum::Int(layout.get_line_height(line_num.max(0) as usize))
+                }
+                "scrollByLine" | "scrollByPage" => {
+                    let amount = player.get_datum(&args[0]).int_value()?;
+                    let scroll_top = get_scroll_top(player, &member_ref);
+                    let line_count = if handler_name == "scrollByPage" {
+                        // A page is as many whole lines as fit in the box
+                        let page_height = get_page_height(player, &member_ref, &layout);
+                        amount * (page_height / layout.get_line_height(1).max(1)).max(1)
+                    } else {
+                        amount
+                    };
+                    set_scroll_top(player, &member_ref, layout.scroll_by_lines(scroll_top, line_count));
+                    return Ok(DatumRef::Void);
+                }
+                _ => {
+                    return Err(ScriptError::new(format!("No text geometry handler {}", handler_name)));
+                }
+            };
+            Ok(player.alloc_datum(result))
+        })
+    }
+
+    pub fn get_prop(player: &DirPlayer, member_ref: &CastMemberRef, prop: &String) -> Result<Datum, ScriptError> {
+        let layout = get_member_layout(player, member_ref)?;
+        match prop.as_str() {
+            "lineCount" => Ok(Datum::Int(layout.lines.len() as i32)),
+            "lineHeight" => Ok(Datum::Int(layout.get_line_height(1))),
+            "scrollTop" => Ok(Datum::Int(get_scroll_top(player, member_ref))),
+            _ => Err(ScriptError::new(format!("Cannot get text geometry property {}", prop))),
+        }
+    }
+
+    pub fn set_scroll_top(member_ref: &CastMemberRef, value: Datum) -> Result<(), ScriptError> {
+        let scroll_top = value.int_value()?;
+        reserve_player_mut(|player| set_scroll_top(player, member_ref, scroll_top));
+        Ok(())
+    }
+
+    /// The 1-based position of the character under a stage location in a sprite showing a
+    /// text or field member, or -1 when the location is outside the sprite.
+    pub fn point_to_char(player: &DirPlayer, sprite_num: i16, (x, y): (i32, i32)) -> Result<i32, ScriptError> {
+        let sprite = match player.movie.score.get_sprite(sprite_num) {
+            Some(sprite) => sprite,
+            None => return Ok(-1),
+        };
+        let member_ref = match &sprite.member {
+            Some(member_ref) => member_ref.clone(),
+            None => return Ok(-1),
+        };
+        let is_inside = x >= sprite.loc_h
+            && y >= sprite.loc_v
+            && (sprite.width <= 0 || x < sprite.loc_h + sprite.width)
+            && (sprite.height <= 0 || y < sprite.loc_v + sprite.height);
+        if !is_inside {
+            return Ok(-1);
+        }
+        let layout = get_member_layout(player, &member_ref)?;
+        let scroll_top = get_scroll_top(player, &member_ref);
+        Ok(layout.get_char_pos_at(x - sprite.loc_h, y - sprite.loc_v + scroll_top) as i32)
+    }
+
+    /// The hyperlink of a text sprite under a stage location, as its member and index.
+    pub fn get_hyperlink_at(player: &DirPlayer, sprite_num: i16, (x, y): (i32, i32)) -> Option<(CastMemberRef, usize)> {
+        let sprite = player.movie.score.get_sprite(sprite_num)?;
+        let member_ref = sprite.member.clone()?;
+        let member = player.movie.cast_manager.find_member_by_ref(&member_ref)?;
+        let text_member = member.member_type.as_text().filter(|text_member| !text_member.hyperlinks.is_empty())?;
+        let layout = TextLayout::for_member(&player.font_manager, &member.member_type)?;
+        let char_pos = layout.get_char_at(x - sprite.loc_h, y - sprite.loc_v + text_member.scroll_top as i32)?;
+        text_member.get_hyperlink_at(char_pos).map(|index| (member_ref, index))
+    }
+
+    /// The mouseChar, mouseWord, mouseItem or mouseLine: the position of the chunk under the
+    /// mouse in the field it's over, or -1 when it's not over the text of a field.
+    pub fn get_mouse_chunk(player: &DirPlayer, prop: &str) -> Result<i32, ScriptError> {
+        let (x, y) = player.mouse_loc;
+        let sprite = get_sprite_at(player, x, y, false).and_then(|sprite_num| player.movie.score.get_sprite(sprite_num as i16));
+        let (sprite, member_ref) = match sprite.and_then(|sprite| sprite.member.clone().map(|member_ref| (sprite, member_ref))) {
+            Some(sprite_member) => sprite_member,
+            None => return Ok(-1),
+        };
+        let text = match player.movie.cast_manager.find_member_by_ref(&member_ref).map(|member| &member.member_type) {
+            Some(CastMemberType::Field(field)) => field.text.chars().collect::<Vec<_>>(),
+            _ => return Ok(-1),
+        };
+        let layout = get_member_layout(player, &member_ref)?;
+        let scroll_top = get_scroll_top(player, &member_ref);
+        let char_pos = match layout.get_char_at(x - sprite.loc_h, y - sprite.loc_v + scroll_top) {
+            Some(char_pos) => char_pos,
+            None => return Ok(-1),
+        };
+        let before = &text[..char_pos - 1];
+        let position = match prop {
+            "mouseChar" => char_pos,
+            "mouseLine" => before.iter().filter(|c| **c == '\r' || **c == '\n').count() + 1,
+            "mouseItem" => before.iter().filter(|c| **c == player.movie.item_delimiter).count() + 1,
+            "mouseWord" => {
+                if text.get(char_pos - 1).is_none_or(|c| c.is_whitespace()) {
+                    return Ok(-1);
+                }
+                // Count the words that start up to the character under the mouse
+                text[..char_pos]
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, c)| !c.is_whitespace() && (*index == 0 || text[index - 1].is_whitespace()))
+                    .count()
+            }
+            _ => return Err(ScriptError::new(format!("Unknown mouse chunk property {}", prop))),
+        };
+        Ok(position as i32)
+    }
+}
@@ -1,8 +1,8 @@
 use log::warn;
 
-use crate::{director::lingo::datum::Datum, js_api::JsApi, player::{cast_lib::CastMemberRef, cast_member::{CastMember, CastMemberType, CastMemberTypeId, TextMember}, handlers::types::TypeUtils, reserve_player_mut, reserve_player_ref, DatumRef, DirPlayer, ScriptError}};
+use crate::{director::lingo::datum::{datum_bool, Datum}, js_api::JsApi, player::{cast_lib::CastMemberRef, cast_member::{CastMember, CastMemberType, CastMemberTypeId}, handlers::types::TypeUtils, reserve_player_mut, reserve_player_ref, streaming::is_member_media_ready, DatumRef, DirPlayer, ScriptError}};
 
-use super::cast_member::{bitmap::BitmapMemberHandlers, field::FieldMemberHandlers, text::TextMemberHandlers, film_loop::FilmLoopMemberHandlers};
+use super::cast_member::{bitmap::BitmapMemberHandlers, field::FieldMemberHandlers, text::TextMemberHandlers, film_loop::FilmLoopMemberHandlers, script::ScriptMemberHandlers, text_geometry::TextGeometryHandlers};
 
 pub struct CastMemberRefHandlers {}
 
@@ -18,10 +18,6 @@ pub fn borrow_member_mut<T1, F1, T2, F2>(
   })
 }
 
-fn get_text_member_line_height(text_data: &TextMember) -> u16 {
-  return text_data.font_size + 3; // TODO: Implement text line height
-}
-
 impl CastMemberRefHandlers {
   pub fn get_cast_slot_number(cast_lib: u32, cast_member: u32) -> u32 {
     (cast_lib << 16) | (cast_member & 0xFFFF)
@@ -38,28 +34,9 @@ impl CastMemberRefHandlers {
     match handler_name.as_str() {
       "duplicate" => Self::duplicate(datum, args),
       "erase" => Self::erase(datum, args),
-      "charPosToLoc" => {
-        reserve_player_mut(|player| {
-          let cast_member_ref = match player.get_datum(datum) {
-            Datum::CastMember(cast_member_ref) => cast_member_ref.to_owned(),
-            _ => return Err(ScriptError::new("Cannot call charPosToLoc on non-cast-member".to_string())),
-          };
-          let cast_member = player.movie.cast_manager.find_member_by_ref(&cast_member_ref).unwrap();
-          let text_data = cast_member.member_type.as_text().unwrap();
-          let char_pos = player.get_datum(&args[0]).int_value()? as u16;
-          let char_width: u16 = 7; // TODO: Implement char width
-          let line_height = get_text_member_line_height(&text_data);
-          let result = if text_data.text.is_empty() || char_pos <= 0 {
-            Datum::IntPoint((0, 0))
-          } else if char_pos > text_data.text.len() as u16 {
-            Datum::IntPoint(((char_width * (text_data.text.len() as u16)) as i32, line_height as i32))
-          } else {
-            Datum::IntPoint(((char_width * (char_pos - 1)) as i32, line_height as i32))
-          };
-          // TODO this is a stub!
-          Ok(player.alloc_datum(result))
-        })
-      },
+      "charPosToLoc" | "locToCharPos" | "lineHeight" | "scrollByLine" | "scrollByPage" => {
+        TextGeometryHandlers::call(datum, handler_name, args)
+      }
       "getProp" => {
         let result_ref = reserve_player_mut(|player| {
           let cast_member_ref = match player.get_datum(datum) {
@@ -179,6 +156,9 @@ impl CastMemberRefHandlers {
       CastMemberTypeId::FilmLoop => {
         FilmLoopMemberHandlers::get_prop(player, cast_member_ref, prop)
       }
+      CastMemberTypeId::Script => {
+        ScriptMemberHandlers::get_prop(player, cast_member_ref, prop)
+      }
       _ => {
         Err(ScriptError::new(format!("Cannot get castMember prop {} for member of type {:?}", prop, member_type)))
       }
@@ -208,6 +188,9 @@ impl CastMemberRefHandlers {
       CastMemberTypeId::Bitmap => {
         BitmapMemberHandlers::set_prop(member_ref, prop, value)
       }
+      CastMemberTypeId::Script => {
+        ScriptMemberHandlers::set_prop(member_ref, prop, value)
+      }
       _ => {
         Err(ScriptError::new(format!("Cannot set castMember prop {} for member of type {:?}", prop, member_type)))
       }
@@ -223,6 +206,9 @@ impl CastMemberRefHandlers {
     if is_invalid {
       return Self::get_invalid_member_prop(player, cast_member_ref, prop);
     }
+    if prop == "mediaReady" {
+      return Ok(datum_bool(is_member_media_ready(player, cast_member_ref)));
+    }
     let cast_member = player.movie.cast_manager.find_member_by_ref(cast_member_ref);
     let (name, slot_number, member_type, color, bg_color) = match cast_member {
       Some(cast_member) => {
@@ -246,6 +232,10 @@ impl CastMemberRefHandlers {
       "castLibNum" => Ok(Datum::Int(cast_member_ref.cast_lib as i32)),
       "color" => Ok(Datum::ColorRef(color)),
       "bgColor" => Ok(Datum::ColorRef(bg_color)),
+      "loaded" => {
+        let bitmap_ref = player.movie.cast_manager.get_member_bitmap_ref(cast_member_ref);
+        Ok(datum_bool(bitmap_ref.is_none_or(|bitmap_ref| player.bitmap_manager.is_bitmap_loaded(bitmap_ref))))
+      }
       _ => Self::get_member_type_prop(player, cast_member_ref, &member_type, prop),
     }
   }
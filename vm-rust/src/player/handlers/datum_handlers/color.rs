@@ -1,6 +1,56 @@
 use crate::{director::lingo::datum::Datum, player::{bitmap::bitmap::{get_system_default_palette, resolve_color_ref, PaletteRef}, reserve_player_mut, sprite::ColorRef, DatumRef, DirPlayer, ScriptError}};
 
 pub struct ColorDatumHandlers {}
+pub struct ColorUtils {}
+
+impl ColorUtils {
+  /// The palette of the current frame, which palette index colors are resolved against.
+  pub fn get_active_palette(player: &DirPlayer) -> PaletteRef {
+    player
+      .movie
+      .score
+      .get_frame_palette(player.movie.current_frame)
+      .unwrap_or(PaletteRef::BuiltIn(get_system_default_palette()))
+  }
+
+  pub fn to_rgb(player: &DirPlayer, color_ref: &ColorRef) -> (u8, u8, u8) {
+    resolve_color_ref(&player.movie.cast_manager.palettes(), color_ref, &Self::get_active_palette(player))
+  }
+
+  /// The index of the active palette color closest to an RGB color.
+  pub fn to_palette_index(player: &DirPlayer, (r, g, b): (u8, u8, u8)) -> u8 {
+    let palettes = player.movie.cast_manager.palettes();
+    let palette_ref = Self::get_active_palette(player);
+    (0..=255u8)
+      .min_by_key(|index| {
+        let (pr, pg, pb) = resolve_color_ref(&palettes, &ColorRef::PaletteIndex(*index), &palette_ref);
+        let dr = r as i32 - pr as i32;
+        let dg = g as i32 - pg as i32;
+        let db = b as i32 - pb as i32;
+        dr * dr + dg * dg + db * db
+      })
+      .unwrap()
+  }
+
+  /// Reads a color like "#FF00FF" or "FF00FF".
+  pub fn parse_hex_string(hex_string: &str) -> Result<(u8, u8, u8), ScriptError> {
+    let hex = hex_string.trim().trim_start_matches('#');
+    let component = |index: usize| {
+      hex
+        .get(index..index + 2)
+        .and_then(|component| u8::from_str_radix(component, 16).ok())
+        .ok_or_else(|| ScriptError::new(format!("Invalid color string {}", hex_string)))
+    };
+    if hex.len() != 6 {
+      return Err(ScriptError::new(format!("Invalid color string {}", hex_string)));
+    }
+    Ok((component(0)?, component(2)?, component(4)?))
+  }
+
+  pub fn clamp_component(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+  }
+}
 
 impl ColorDatumHandlers {
   pub fn call(datum: &DatumRef, handler_name: &String, _args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
@@ -8,7 +58,7 @@ impl ColorDatumHandlers {
       "hexString" => {
         reserve_player_mut(|player| {
           let color_ref = player.get_datum(datum).to_color_ref()?;
-          let (r, g, b) = resolve_color_ref(&player.movie.cast_manager.palettes(), color_ref, &PaletteRef::BuiltIn(get_system_default_palette()));
+          let (r, g, b) = ColorUtils::to_rgb(player, color_ref);
           let hex_string = format!("#{:02x}{:02x}{:02x}", r, g, b);
           Ok(player.alloc_datum(Datum::String(hex_string)))
         })
@@ -18,37 +68,25 @@ impl ColorDatumHandlers {
   }
 
   pub fn get_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String) -> Result<DatumRef, ScriptError> {
-    let color_ref = player.get_datum(datum).to_color_ref()?;
+    let color_ref = player.get_datum(datum).to_color_ref()?.clone();
+    let (r, g, b) = ColorUtils::to_rgb(player, &color_ref);
     match prop.as_str() {
-      "red" => {
-        match color_ref {
-          ColorRef::Rgb(r, _, _timeout_name) => Ok(player.alloc_datum(Datum::Int(*r as i32))),
-          ColorRef::PaletteIndex(i) => match i {
-            0 => Ok(player.alloc_datum(Datum::Int(255))),
-            255 => Ok(player.alloc_datum(Datum::Int(0))),
-            _ => Ok(player.alloc_datum(Datum::Int(255))),
-          },
-        }
-      },
-      "green" => {
-        match color_ref {
-          ColorRef::Rgb(_, g, _timeout_name) => Ok(player.alloc_datum(Datum::Int(*g as i32))),
-          ColorRef::PaletteIndex(i) => match i {
-            0 => Ok(player.alloc_datum(Datum::Int(255))),
-            255 => Ok(player.alloc_datum(Datum::Int(0))),
-            _ => Ok(player.alloc_datum(Datum::Int(0))),
-          },
-        }
+      "red" => Ok(player.alloc_datum(Datum::Int(r as i32))),
+      "green" => Ok(player.alloc_datum(Datum::Int(g as i32))),
+      "blue" => Ok(player.alloc_datum(Datum::Int(b as i32))),
+      "colorType" => {
+        let color_type = match color_ref {
+          ColorRef::Rgb(..) => "rgb",
+          ColorRef::PaletteIndex(_) => "paletteIndex",
+        };
+        Ok(player.alloc_datum(Datum::Symbol(color_type.to_owned())))
       },
-      "blue" => {
-        match color_ref {
-          ColorRef::Rgb(_, _, b) => Ok(player.alloc_datum(Datum::Int(*b as i32))),
-          ColorRef::PaletteIndex(i) => match i {
-            0 => Ok(player.alloc_datum(Datum::Int(255))),
-            255 => Ok(player.alloc_datum(Datum::Int(0))),
-            _ => Ok(player.alloc_datum(Datum::Int(255))),
-          },
-        }
+      "paletteIndex" => {
+        let index = match color_ref {
+          ColorRef::PaletteIndex(index) => index,
+          ColorRef::Rgb(r, g, b) => ColorUtils::to_palette_index(player, (r, g, b)),
+        };
+        Ok(player.alloc_datum(Datum::Int(index as i32)))
       },
       "ilk" => {
         Ok(player.alloc_datum(Datum::Symbol("color".to_owned())))
@@ -59,53 +97,25 @@ impl ColorDatumHandlers {
     }
   }
 
+  /// Setting a component of a palette index color turns it into an RGB color.
   pub fn set_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String, value: &DatumRef) -> Result<(), ScriptError> {
-    match prop.as_str() {
-      "red" => {
-        let r = player.get_datum(value).int_value()?;
-        let color_ref = player.get_datum_mut(datum).to_color_ref_mut()?;
-        match color_ref {
-          ColorRef::Rgb(_, g, b) => {
-            *color_ref = ColorRef::Rgb(r as u8, *g, *b);
-            Ok(())
-          },
-          ColorRef::PaletteIndex(_) => {
-            *color_ref = ColorRef::Rgb(r as u8, 0, 0);
-            Ok(())
-          },
-        }
-      },
-      "green" => {
-        let g = player.get_datum(value).int_value()?;
-        let color_ref = player.get_datum_mut(datum).to_color_ref_mut()?;
-        match color_ref {
-          ColorRef::Rgb(r, _, b) => {
-            *color_ref = ColorRef::Rgb(*r, g as u8, *b);
-            Ok(())
-          },
-          ColorRef::PaletteIndex(_) => {
-            *color_ref = ColorRef::Rgb(0, g as u8, 0);
-            Ok(())
-          },
-        }
-      },
-      "blue" => {
-        let b = player.get_datum(value).int_value()?;
-        let color_ref = player.get_datum_mut(datum).to_color_ref_mut()?;
-        match color_ref {
-          ColorRef::Rgb(r, g, _) => {
-            *color_ref = ColorRef::Rgb(*r, *g, b as u8);
-            Ok(())
-          },
-          ColorRef::PaletteIndex(_) => {
-            *color_ref = ColorRef::Rgb(0, 0, b as u8);
-            Ok(())
-          },
-        }
+    let color_ref = player.get_datum(datum).to_color_ref()?.clone();
+    let (r, g, b) = ColorUtils::to_rgb(player, &color_ref);
+    let new_color_ref = match prop.as_str() {
+      "red" => ColorRef::Rgb(ColorUtils::clamp_component(player.get_datum(value).int_value()?), g, b),
+      "green" => ColorRef::Rgb(r, ColorUtils::clamp_component(player.get_datum(value).int_value()?), b),
+      "blue" => ColorRef::Rgb(r, g, ColorUtils::clamp_component(player.get_datum(value).int_value()?)),
+      "paletteIndex" => ColorRef::PaletteIndex(ColorUtils::clamp_component(player.get_datum(value).int_value()?)),
+      "colorType" => match player.get_datum(value).string_value()?.to_lowercase().as_str() {
+        "rgb" => ColorRef::Rgb(r, g, b),
+        "paletteindex" => ColorRef::PaletteIndex(ColorUtils::to_palette_index(player, (r, g, b))),
+        color_type => return Err(ScriptError::new(format!("Invalid colorType {}", color_type))),
       },
       _ => {
-        Err(ScriptError::new(format!("Cannot set color property {}", prop)))
+        return Err(ScriptError::new(format!("Cannot set color property {}", prop)));
       },
-    }
+    };
+    *player.get_datum_mut(datum).to_color_ref_mut()? = new_color_ref;
+    Ok(())
   }
 }
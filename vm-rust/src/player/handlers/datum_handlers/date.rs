@@ -0,0 +1,35 @@
+use crate::{director::lingo::datum::Datum, player::{DatumRef, DirPlayer, ScriptError}};
+
+pub struct DateDatumHandlers {}
+
+impl DateDatumHandlers {
+  pub fn get_prop(player: &DirPlayer, datum: &DatumRef, prop: &String) -> Result<Datum, ScriptError> {
+    let date = player.get_datum(datum).to_date()?;
+    match prop.as_str() {
+      "year" => Ok(Datum::Int(date.year())),
+      "month" => Ok(Datum::Int(date.month())),
+      "day" => Ok(Datum::Int(date.day())),
+      "seconds" => Ok(Datum::Int(date.seconds)),
+      "ilk" => Ok(Datum::Symbol("date".to_string())),
+      _ => Err(ScriptError::new(format!("Cannot get date property {}", prop))),
+    }
+  }
+
+  pub fn set_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String, value_ref: &DatumRef) -> Result<(), ScriptError> {
+    let value = player.get_datum(value_ref).int_value()?;
+    let date = player.get_datum(datum).to_date()?;
+    let new_date = match prop.as_str() {
+      "year" => date.with_ymd(value, date.month(), date.day())?,
+      "month" => date.with_ymd(date.year(), value, date.day())?,
+      "day" => date.with_ymd(date.year(), date.month(), value)?,
+      "seconds" => {
+        let mut date = date;
+        date.seconds = value;
+        date
+      }
+      _ => return Err(ScriptError::new(format!("Cannot set date property {}", prop))),
+    };
+    *player.get_datum_mut(datum) = Datum::Date(new_date);
+    Ok(())
+  }
+}
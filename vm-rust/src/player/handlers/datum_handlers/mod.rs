@@ -17,12 +17,14 @@ pub mod color;
 pub mod cast_member;
 pub mod player;
 pub mod sound;
+pub mod window;
+pub mod date;
 
 use player::PlayerDatumHandlers;
 
 use crate::{director::lingo::datum::DatumType, player::{format_datum, reserve_player_ref, xtra::manager::{call_xtra_instance_async_handler, call_xtra_instance_handler, has_xtra_instance_async_handler}, DatumRef, ScriptError, ScriptErrorCode}};
 
-use self::{bitmap::BitmapDatumHandlers, list_handlers::ListDatumHandlers, point::PointDatumHandlers, prop_list::PropListDatumHandlers, rect::RectDatumHandlers, script::ScriptDatumHandlers, sprite::SpriteDatumHandlers, string::StringDatumHandlers, string_chunk::StringChunkHandlers, timeout::TimeoutDatumHandlers};
+use self::{bitmap::BitmapDatumHandlers, list_handlers::ListDatumHandlers, point::PointDatumHandlers, prop_list::PropListDatumHandlers, rect::RectDatumHandlers, script::ScriptDatumHandlers, sprite::SpriteDatumHandlers, string::StringDatumHandlers, string_chunk::StringChunkHandlers, timeout::TimeoutDatumHandlers, window::WindowDatumHandlers};
 
 pub async fn player_call_datum_handler(
   obj_ref: &DatumRef,
@@ -77,7 +79,15 @@ pub async fn player_call_datum_handler(
       }
     }
     DatumType::ColorRef => color::ColorDatumHandlers::call(obj_ref, handler_name, args),
+    DatumType::SoundRef => sound::SoundDatumHandlers::call(obj_ref, handler_name, args),
     DatumType::PlayerRef => PlayerDatumHandlers::call(handler_name, args),
+    DatumType::WindowRef => {
+      if WindowDatumHandlers::has_async_handler(handler_name) {
+        WindowDatumHandlers::call_async(obj_ref, handler_name, args).await
+      } else {
+        WindowDatumHandlers::call(obj_ref, handler_name, args)
+      }
+    }
     _ => reserve_player_ref(|player| {
       let formatted_datum = format_datum(obj_ref, &player);
       Err(ScriptError::new_code(ScriptErrorCode::HandlerNotFound, format!("No handler {handler_name} for datum {}", formatted_datum)))
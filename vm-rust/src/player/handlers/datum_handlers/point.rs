@@ -1,4 +1,4 @@
-use crate::{director::lingo::datum::{datum_bool, Datum}, player::{reserve_player_mut, DatumRef, DirPlayer, ScriptError}};
+use crate::{director::lingo::datum::{datum_bool, Datum, DatumType}, player::{handlers::datum_handlers::rect::RectUtils, reserve_player_mut, DatumRef, DirPlayer, ScriptError}};
 
 pub struct PointDatumHandlers {}
 
@@ -9,6 +9,7 @@ impl PointDatumHandlers {
       "getAt" => Self::get_at(datum, args),
       "setAt" => Self::set_at(datum, args),
       "inside" => Self::inside(datum, args),
+      "map" => Self::map(datum, args),
       _ => Err(ScriptError::new(format!("No handler {handler_name} for point")))
     }
   }
@@ -17,7 +18,32 @@ impl PointDatumHandlers {
     reserve_player_mut(|player| {
       let point = player.get_datum(datum).to_int_point()?;
       let rect = player.get_datum(&args[0]).to_int_rect()?;
-      Ok(player.alloc_datum(datum_bool(rect.0 <= point.0 && point.0 < rect.2 && rect.1 <= point.1 && point.1 < rect.3)))
+      Ok(player.alloc_datum(datum_bool(RectUtils::contains_point(rect, point))))
+    })
+  }
+
+  /// map(point, fromRect, toRect) places a point in toRect where it was in fromRect.
+  pub fn map(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let point = player.get_datum(datum).to_int_point()?;
+      let from_rect = player.get_datum(&args[0]).to_int_rect()?;
+      let to_rect = player.get_datum(&args[1]).to_int_rect()?;
+      Ok(player.alloc_datum(Datum::IntPoint(RectUtils::map_point(point, from_rect, to_rect))))
+    })
+  }
+
+  /// map(quad, fromRect, toRect) maps every point of a list, like the quad of a sprite.
+  pub fn map_quad(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let points = player.get_datum(datum).to_list()?.clone();
+      let from_rect = player.get_datum(&args[0]).to_int_rect()?;
+      let to_rect = player.get_datum(&args[1]).to_int_rect()?;
+      let mut mapped = vec![];
+      for point in points {
+        let point = player.get_datum(&point).to_int_point()?;
+        mapped.push(player.alloc_datum(Datum::IntPoint(RectUtils::map_point(point, from_rect, to_rect))));
+      }
+      Ok(player.alloc_datum(Datum::List(DatumType::List, mapped, false)))
     })
   }
 
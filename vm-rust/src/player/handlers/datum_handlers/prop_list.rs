@@ -1,4 +1,4 @@
-use crate::{director::lingo::datum::{datum_bool, Datum, PropListPair}, player::{allocator::{DatumAllocator, DatumAllocatorTrait}, compare::{datum_equals, datum_less_than}, datum_formatting::{format_concrete_datum, format_datum}, handlers::types::TypeUtils, player_duplicate_datum, reserve_player_mut, reserve_player_ref, DatumRef, DirPlayer, ScriptError}};
+use crate::{director::lingo::datum::{Datum, PropListPair}, player::{allocator::{DatumAllocator, DatumAllocatorTrait}, compare::{datum_equals, datum_less_than}, datum_formatting::{format_concrete_datum, format_datum}, handlers::types::TypeUtils, player_duplicate_datum, reserve_player_mut, reserve_player_ref, DatumRef, DirPlayer, ScriptError}};
 
 pub struct PropListDatumHandlers {}
 
@@ -13,7 +13,7 @@ impl PropListUtils {
     while low < high {
       let mid = (low + high) / 2;
       let left_key = allocator.get_datum(&prop_list.get(mid as usize).unwrap().0);
-      if datum_less_than(left_key, key)? {
+      if Self::key_less_than(left_key, key)? {
         low = mid + 1;
       } else {
         high = mid;
@@ -23,6 +23,28 @@ impl PropListUtils {
     Ok(low)
   }
 
+  /// Sorted lists order symbols and strings alphabetically, ignoring case, and other
+  /// keys like the comparison operators do.
+  fn key_less_than(left: &Datum, right: &Datum) -> Result<bool, ScriptError> {
+    match (left, right) {
+      (Datum::Symbol(left) | Datum::String(left), Datum::Symbol(right) | Datum::String(right)) => {
+        Ok(left.to_lowercase() < right.to_lowercase())
+      }
+      _ => datum_less_than(left, right),
+    }
+  }
+
+  /// The index of a 1-based position, if the list has it.
+  fn position_to_index(prop_list: &[PropListPair], position: i32) -> Option<usize> {
+    if position >= 1 && position as usize <= prop_list.len() {
+      Some(position as usize - 1)
+    } else {
+      None
+    }
+  }
+
+  /// Like Director, the first matching property wins when a list has duplicates. Symbols
+  /// compare ignoring case, while a string and a symbol must match exactly.
   fn get_key_index(prop_list: &Vec<PropListPair>, key: &Datum, allocator: &DatumAllocator) -> Result<i32, ScriptError> {
     let mut pos = -1;
     for (i, (k, _)) in prop_list.iter().enumerate() {
@@ -128,12 +150,10 @@ impl PropListUtils {
     let key = allocator.get_datum(key_ref);
     match key {
       // TODO do same for float
-      Datum::Int(index) => {
-        let index = (*index as usize) - 1;
-        if index < prop_list.len() {
-          Ok(prop_list[index].1.clone())
-        } else {
-          Err(ScriptError::new(format!("Index out of range: {}", index)))
+      Datum::Int(position) => {
+        match Self::position_to_index(prop_list, *position) {
+          Some(index) => Ok(prop_list[index].1.clone()),
+          None => Err(ScriptError::new(format!("Index out of range: {}", position))),
         }
       }
       _ => {
@@ -173,13 +193,12 @@ impl PropListUtils {
     let key = &player.get_datum(key_ref);
     match key {
       // TODO do same for float
-      Datum::Int(index) => {
-        let index = (*index as usize) - 1;
+      Datum::Int(position) => {
+        let position = *position;
         let prop_list = player.get_datum_mut(prop_list_ref).to_map_mut()?;
-        if index < prop_list.len() {
-          prop_list[index].1 = value_ref.clone();
-        } else {
-          return Err(ScriptError::new(format!("Index out of range: {}", index)));
+        match Self::position_to_index(prop_list, position) {
+          Some(index) => prop_list[index].1 = value_ref.clone(),
+          None => return Err(ScriptError::new(format!("Index out of range: {}", position))),
         }
       }
       _ => {
@@ -197,6 +216,7 @@ impl PropListDatumHandlers {
       "setAt" => Self::set_at(datum, args),
       "sort" => Self::sort(datum, args),
       "getPropAt" => Self::get_prop_at(datum, args),
+      "setPropAt" => Self::set_prop_at(datum, args),
       "addProp" => Self::add_prop(datum, args),
       "setaProp" => Self::set_opt_prop(datum, args),
       "setProp" => Self::set_required_prop(datum, args),
@@ -206,6 +226,7 @@ impl PropListDatumHandlers {
       "deleteAt" => Self::delete_at(datum, args),
       "getOne" => Self::get_one(datum, args),
       "findPos" => Self::find_pos(datum, args),
+      "findPosNear" => Self::find_pos_near(datum, args),
       "getPos" => Self::get_pos(datum, args),
       "duplicate" => Self::duplicate(datum, args),
       "getLast" => Self::get_last(datum, args),
@@ -235,45 +256,52 @@ impl PropListDatumHandlers {
     })
   }
 
+  /// The property of the first entry holding a value, or 0 when there is none.
   pub fn get_one(datum: &DatumRef, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let find = player.get_datum(&args[0]);
-      let prop_list = player.get_datum(datum);
-      let prop_list = match prop_list {
-        Datum::PropList(list, ..) => list,
-        _ => return Err(ScriptError::new("Cannot set prop list at non-prop list".to_string())),
-      };
-      let position = prop_list.iter()
-        .position(|(_, v)| 
-          datum_equals(player.get_datum(&v), find, &player.allocator).unwrap()
-        )
-        .map(|x| x as i32);
-
-      Ok(player.alloc_datum(Datum::Int(position.unwrap_or(-1) + 1)))
+      let prop_list = player.get_datum(datum).to_map()?;
+      let key = prop_list.iter()
+        .find(|(_, v)| datum_equals(player.get_datum(v), find, &player.allocator).unwrap_or(false))
+        .map(|(k, _)| k.clone());
+      match key {
+        Some(key) => Ok(key),
+        None => Ok(player.alloc_datum(Datum::Int(0))),
+      }
     })
   }
 
   pub fn find_pos(datum: &DatumRef, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let find = player.get_datum(&args[0]);
-      let prop_list = player.get_datum(datum);
-      let prop_list = match prop_list {
-        Datum::PropList(list, ..) => list,
-        _ => return Err(ScriptError::new("Cannot set prop list at non-prop list".to_string())),
-      };
-      let position = prop_list.iter()
-        .position(|(k, _)| 
-          datum_equals(player.get_datum(&k), find, &player.allocator).unwrap()
-        )
-        .map(|x| x as i32);
-      if let Some(position) = position {
-        return Ok(player.alloc_datum(Datum::Int(position as i32 + 1)));
+      let prop_list = player.get_datum(datum).to_map()?;
+      let key_index = PropListUtils::get_key_index(prop_list, find, &player.allocator)?;
+      if key_index >= 0 {
+        Ok(player.alloc_datum(Datum::Int(key_index + 1)))
       } else {
-        return Ok(DatumRef::Void);
+        Ok(DatumRef::Void)
       }
     })
   }
 
+  /// The position of a property, or in a sorted list the position it would be added at.
+  /// Unsorted lists without the property give the position after their last entry.
+  pub fn find_pos_near(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let find = player.get_datum(&args[0]);
+      let (prop_list, is_sorted) = player.get_datum(datum).to_map_tuple()?;
+      let key_index = PropListUtils::get_key_index(prop_list, find, &player.allocator)?;
+      let position = if key_index >= 0 {
+        key_index + 1
+      } else if is_sorted {
+        PropListUtils::find_index_to_add(prop_list, (&args[0], &DatumRef::Void), &player.allocator)? + 1
+      } else {
+        prop_list.len() as i32 + 1
+      };
+      Ok(player.alloc_datum(Datum::Int(position)))
+    })
+  }
+
   // Finds position of value
   pub fn get_pos(datum: &DatumRef, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
@@ -453,7 +481,37 @@ impl PropListDatumHandlers {
         _ => return Err(ScriptError::new("Cannot get prop list at non-prop list".to_string())),
       };
       let position = player.get_datum(&args[0]).int_value()?;
-      Ok(prop_list.get((position - 1) as usize).unwrap().0.clone())
+      match PropListUtils::position_to_index(prop_list, position) {
+        Some(index) => Ok(prop_list[index].0.clone()),
+        None => Err(ScriptError::new(format!("Index out of range: {}", position))),
+      }
+    })
+  }
+
+  /// Renames the property at a position, keeping its value. Sorted lists move the
+  /// entry to where the new property belongs.
+  pub fn set_prop_at(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    let [position_ref, prop_name_ref] = args else {
+      return Err(ScriptError::new("Invalid number of arguments for setPropAt".to_string()));
+    };
+    reserve_player_mut(|player| {
+      let position = player.get_datum(position_ref).int_value()?;
+      let (prop_list, is_sorted) = player.get_datum(datum).to_map_tuple()?;
+      let index = PropListUtils::position_to_index(prop_list, position)
+        .ok_or_else(|| ScriptError::new(format!("Index out of range: {}", position)))?;
+      let value_ref = prop_list[index].1.clone();
+      let index_to_add = if is_sorted {
+        let mut others = prop_list.clone();
+        others.remove(index);
+        PropListUtils::find_index_to_add(&others, (prop_name_ref, &value_ref), &player.allocator)? as usize
+      } else {
+        index
+      };
+
+      let (prop_list, ..) = player.get_datum_mut(datum).to_map_tuple_mut()?;
+      prop_list.remove(index);
+      prop_list.insert(index_to_add, (prop_name_ref.clone(), value_ref));
+      Ok(DatumRef::Void)
     })
   }
 
@@ -467,9 +525,9 @@ impl PropListDatumHandlers {
         let left = player.get_datum(left_key_ref);
         let right = player.get_datum(right_key_ref);
 
-        if datum_equals(left, right, &player.allocator).unwrap() {
+        if datum_equals(left, right, &player.allocator).unwrap_or(false) {
           return std::cmp::Ordering::Equal
-        } else if datum_less_than(left, right).unwrap() {
+        } else if PropListUtils::key_less_than(left, right).unwrap_or(false) {
           std::cmp::Ordering::Less
         } else {
           std::cmp::Ordering::Greater
@@ -488,33 +546,26 @@ impl PropListDatumHandlers {
     })
   }
 
+  /// Removes the first entry with a property, or the entry at a position, and gives back
+  /// its value, or VOID when there is no such entry.
   pub fn delete_prop(datum: &DatumRef, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let prop_name = player.get_datum(&args[0]);
-      if prop_name.is_string() || prop_name.is_symbol() {
-        // let prop_name = prop_name.string_value()?;
-        let prop_list = player.get_datum(datum).to_map()?;
-        let index = PropListUtils::get_key_index(&prop_list, prop_name, &player.allocator)?;
-        if index >= 0  {
-          let prop_list = player.get_datum_mut(datum).to_map_mut()?;
-          prop_list.remove(index as usize);
-          Ok(player.alloc_datum(datum_bool(true)))
-        } else {
-          Ok(player.alloc_datum(datum_bool(false)))
-        }
-      } else if prop_name.is_int() {
-        let position = player.get_datum(&args[0]).int_value()?;
-        let prop_list = player.get_datum_mut(datum).to_map_mut()?;
-        if position >= 1 && position <= prop_list.len() as i32 {
-          prop_list.remove((position - 1) as usize);
-          Ok(player.alloc_datum(datum_bool(true)))
-        } else {
-          Ok(player.alloc_datum(datum_bool(false)))
-        }
+      let prop_list = player.get_datum(datum).to_map()?;
+      let index = if prop_name.is_int() {
+        PropListUtils::position_to_index(prop_list, prop_name.int_value()?)
       } else if prop_name.is_void() {
-        Ok(player.alloc_datum(datum_bool(false)))
+        None
       } else {
-        Err(ScriptError::new(format!("Prop name must be a string, int or symbol (is {})", prop_name.type_str())))
+        let key_index = PropListUtils::get_key_index(prop_list, prop_name, &player.allocator)?;
+        if key_index >= 0 { Some(key_index as usize) } else { None }
+      };
+      match index {
+        Some(index) => {
+          let prop_list = player.get_datum_mut(datum).to_map_mut()?;
+          Ok(prop_list.remove(index).1)
+        }
+        None => Ok(DatumRef::Void),
       }
     })
   }
@@ -1,4 +1,4 @@
-use crate::{director::lingo::datum::Datum, player::{reserve_player_mut, DatumRef, DirPlayer, ScriptError}};
+use crate::{director::lingo::datum::{datum_bool, Datum}, player::{reserve_player_mut, DatumRef, DirPlayer, ScriptError}};
 
 pub struct RectDatumHandlers {}
 pub struct RectUtils {}
@@ -12,11 +12,48 @@ impl RectUtils {
     (left, top, right, bottom)
   }
 
+  /// The overlap of two rects, or an empty rect when they don't overlap.
   pub fn intersect(rect1: (i32, i32, i32, i32), rect2: (i32, i32, i32, i32)) -> (i32, i32, i32, i32) {
     let left = rect1.0.max(rect2.0);
     let top = rect1.1.max(rect2.1);
     let right = rect1.2.min(rect2.2);
     let bottom = rect1.3.min(rect2.3);
+    if left >= right || top >= bottom {
+      return (0, 0, 0, 0);
+    }
+    (left, top, right, bottom)
+  }
+
+  /// Grows a rect by h and v on every side, keeping its center.
+  pub fn inflate(rect: (i32, i32, i32, i32), h: i32, v: i32) -> (i32, i32, i32, i32) {
+    (rect.0 - h, rect.1 - v, rect.2 + h, rect.3 + v)
+  }
+
+  pub fn offset(rect: (i32, i32, i32, i32), h: i32, v: i32) -> (i32, i32, i32, i32) {
+    (rect.0 + h, rect.1 + v, rect.2 + h, rect.3 + v)
+  }
+
+  pub fn contains_point(rect: (i32, i32, i32, i32), point: (i32, i32)) -> bool {
+    rect.0 <= point.0 && point.0 < rect.2 && rect.1 <= point.1 && point.1 < rect.3
+  }
+
+  /// Moves and scales a point from one rect to the matching place in another.
+  pub fn map_point(point: (i32, i32), from_rect: (i32, i32, i32, i32), to_rect: (i32, i32, i32, i32)) -> (i32, i32) {
+    let map = |value: i32, from_start: i32, from_end: i32, to_start: i32, to_end: i32| {
+      if from_end == from_start {
+        return to_start + value - from_start;
+      }
+      to_start + ((value - from_start) as f64 * (to_end - to_start) as f64 / (from_end - from_start) as f64).round() as i32
+    };
+    (
+      map(point.0, from_rect.0, from_rect.2, to_rect.0, to_rect.2),
+      map(point.1, from_rect.1, from_rect.3, to_rect.1, to_rect.3),
+    )
+  }
+
+  pub fn map_rect(rect: (i32, i32, i32, i32), from_rect: (i32, i32, i32, i32), to_rect: (i32, i32, i32, i32)) -> (i32, i32, i32, i32) {
+    let (left, top) = Self::map_point((rect.0, rect.1), from_rect, to_rect);
+    let (right, bottom) = Self::map_point((rect.2, rect.3), from_rect, to_rect);
     (left, top, right, bottom)
   }
 }
@@ -28,6 +65,11 @@ impl RectDatumHandlers {
       "getAt" => Self::get_at(datum, args),
       "setAt" => Self::set_at(datum, args),
       "intersect" => Self::intersect(datum, args),
+      "union" => Self::union(datum, args),
+      "inflate" => Self::inflate(datum, args),
+      "offset" => Self::offset(datum, args),
+      "map" => Self::map(datum, args),
+      "inside" => Self::inside(datum, args),
       _ => Err(ScriptError::new(format!("No handler {handler_name} for rect")))
     }
   }
@@ -41,6 +83,51 @@ impl RectDatumHandlers {
     })
   }
 
+  pub fn union(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let rect1 = player.get_datum(datum).to_int_rect()?;
+      let rect2 = player.get_datum(&args[0]).to_int_rect()?;
+      Ok(player.alloc_datum(Datum::IntRect(RectUtils::union(rect1, rect2))))
+    })
+  }
+
+  pub fn inflate(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let rect = player.get_datum(datum).to_int_rect()?;
+      let h = player.get_datum(&args[0]).int_value()?;
+      let v = player.get_datum(&args[1]).int_value()?;
+      Ok(player.alloc_datum(Datum::IntRect(RectUtils::inflate(rect, h, v))))
+    })
+  }
+
+  pub fn offset(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let rect = player.get_datum(datum).to_int_rect()?;
+      let h = player.get_datum(&args[0]).int_value()?;
+      let v = player.get_datum(&args[1]).int_value()?;
+      Ok(player.alloc_datum(Datum::IntRect(RectUtils::offset(rect, h, v))))
+    })
+  }
+
+  /// map(rect, fromRect, toRect) places a rect in toRect where it was in fromRect.
+  pub fn map(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let rect = player.get_datum(datum).to_int_rect()?;
+      let from_rect = player.get_datum(&args[0]).to_int_rect()?;
+      let to_rect = player.get_datum(&args[1]).to_int_rect()?;
+      Ok(player.alloc_datum(Datum::IntRect(RectUtils::map_rect(rect, from_rect, to_rect))))
+    })
+  }
+
+  /// Whether a rect lies entirely within another.
+  pub fn inside(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let rect = player.get_datum(datum).to_int_rect()?;
+      let outer = player.get_datum(&args[0]).to_int_rect()?;
+      Ok(player.alloc_datum(datum_bool(outer.0 <= rect.0 && outer.1 <= rect.1 && rect.2 <= outer.2 && rect.3 <= outer.3)))
+    })
+  }
+
   pub fn get_at(datum: &DatumRef, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let rect = player.get_datum(datum);
@@ -123,13 +210,13 @@ impl RectDatumHandlers {
       "right" => {
         let value = player.get_datum(value_ref).int_value()?;
         let rect = player.get_datum_mut(datum).to_int_rect_mut()?;
-        rect.1 = value;
+        rect.2 = value;
         Ok(())
       },
       "bottom" => {
         let value = player.get_datum(value_ref).int_value()?;
         let rect = player.get_datum_mut(datum).to_int_rect_mut()?;
-        rect.1 = value;
+        rect.3 = value;
         Ok(())
       },
       _ => {
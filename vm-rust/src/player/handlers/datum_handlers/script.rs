@@ -1,4 +1,4 @@
-use crate::{director::lingo::datum::{datum_bool, Datum}, player::{allocator::ScriptInstanceAllocatorTrait, cast_lib::CastMemberRef, player_call_script_handler, player_handle_scope_return, reserve_player_mut, script::{get_lctx_for_script, ScriptInstance}, script_ref::ScriptInstanceRef, DatumRef, ScriptError}};
+use crate::{director::lingo::datum::{datum_bool, Datum}, player::{allocator::ScriptInstanceAllocatorTrait, cast_lib::CastMemberRef, player_call_script_handler, player_handle_scope_return, reserve_player_mut, handlers::datum_handlers::script_instance::alloc_symbol_list, script::{get_lctx_for_script, script_get_static_prop, script_set_static_prop, ScriptInstance}, script_ref::ScriptInstanceRef, DatumRef, DirPlayer, ScriptError}};
 
 pub struct ScriptDatumHandlers {}
 
@@ -20,6 +20,9 @@ impl ScriptDatumHandlers {
   pub fn call(datum: &DatumRef, handler_name: &String, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     match handler_name.as_str() {
       "handler" => Self::handler(datum, args),
+      "handlers" => Self::handlers(datum),
+      "getaProp" => Self::get_a_prop(datum, args),
+      "setaProp" => Self::set_a_prop(datum, args),
       _ => Err(ScriptError::new(format!("No handler {handler_name} for script datum")))
     }
   }
@@ -37,6 +40,43 @@ impl ScriptDatumHandlers {
     })
   }
 
+  pub fn handlers(datum: &DatumRef) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let script_ref = match player.get_datum(datum) {
+        Datum::ScriptRef(script_ref) => script_ref,
+        _ => return Err(ScriptError::new("Cannot get handlers of non-script".to_string())),
+      };
+      let script = player.movie.cast_manager.get_script_by_ref(script_ref).unwrap();
+      let names = script.handler_names.clone();
+      Ok(alloc_symbol_list(player, names))
+    })
+  }
+
+  fn get_script_ref(player: &DirPlayer, datum: &DatumRef) -> Result<CastMemberRef, ScriptError> {
+    match player.get_datum(datum) {
+      Datum::ScriptRef(script_ref) => Ok(script_ref.clone()),
+      _ => Err(ScriptError::new("Cannot get property of non-script".to_string())),
+    }
+  }
+
+  /// Properties set on a parent script itself rather than on its instances.
+  pub fn get_a_prop(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let prop_name = player.get_datum(&args[0]).string_value()?;
+      let script_ref = Self::get_script_ref(player, datum)?;
+      Ok(script_get_static_prop(player, &script_ref, &prop_name).unwrap_or(DatumRef::Void))
+    })
+  }
+
+  pub fn set_a_prop(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let prop_name = player.get_datum(&args[0]).string_value()?;
+      let script_ref = Self::get_script_ref(player, datum)?;
+      script_set_static_prop(player, &script_ref, &prop_name, &args[1], false)?;
+      Ok(DatumRef::Void)
+    })
+  }
+
   pub fn create_script_instance(script_ref: &CastMemberRef) -> (ScriptInstanceRef, DatumRef) {
     reserve_player_mut(|player| {
       let instance_id = player.allocator.get_free_script_instance_id();
@@ -1,8 +1,14 @@
-use crate::{director::lingo::datum::{datum_bool, Datum}, player::{allocator::ScriptInstanceAllocatorTrait, cast_lib::CastMemberRef, handlers::types::TypeUtils, player_call_script_handler, player_handle_scope_return, reserve_player_mut, reserve_player_ref, script::{script_get_prop, script_set_prop, Script, ScriptHandlerRef}, script_ref::ScriptInstanceRef, DatumRef, DirPlayer, ScriptError, ScriptErrorCode}};
+use crate::{director::lingo::datum::{datum_bool, Datum, DatumType}, player::{allocator::ScriptInstanceAllocatorTrait, cast_lib::CastMemberRef, handlers::types::TypeUtils, player_call_script_handler, player_handle_scope_return, reserve_player_mut, reserve_player_ref, script::{script_get_prop, script_get_prop_opt, script_set_prop, Script, ScriptHandlerRef}, script_ref::ScriptInstanceRef, DatumRef, DirPlayer, ScriptError, ScriptErrorCode}};
 
 pub struct ScriptInstanceDatumHandlers {}
 pub struct ScriptInstanceUtils {}
 
+/// A list of symbols, as returned by handlers().
+pub fn alloc_symbol_list(player: &mut DirPlayer, names: Vec<String>) -> DatumRef {
+  let items = names.into_iter().map(|name| player.alloc_datum(Datum::Symbol(name))).collect();
+  player.alloc_datum(Datum::List(DatumType::List, items, false))
+}
+
 impl ScriptInstanceUtils {
   pub fn get_script<'a>(datum: &DatumRef, player: &'a DirPlayer) -> Result<(ScriptInstanceRef, &'a Script), ScriptError> {
     let datum = player.get_datum(datum);
@@ -36,20 +42,16 @@ impl ScriptInstanceUtils {
     }
   }
 
+  /// Finds the handler on the instance or the nearest ancestor that defines it.
   pub fn get_script_instance_handler(name: &String, instance_ref: &ScriptInstanceRef, player: &DirPlayer) -> Result<Option<ScriptHandlerRef>, ScriptError> {
-    let instance = player.allocator.get_script_instance(instance_ref);
-    let script = player.movie.cast_manager.get_script_by_ref(&instance.script).unwrap();
-    let own_handler = script.get_own_handler_ref(name);
-    if let Some(own_handler) = own_handler {
-      return Ok(Some(own_handler));
-    }
-    let script_instance = player.allocator.get_script_instance(instance_ref);
-    let ancestor_instance_id = &script_instance.ancestor;
-    if let Some(ancestor_instance_ref) = ancestor_instance_id {
-      ScriptInstanceUtils::get_script_instance_handler(name, &ancestor_instance_ref, player)
-    } else {
-      Ok(None)
+    let chain = player.allocator.get_ancestor_chain(**instance_ref)?;
+    for instance in chain.iter().filter_map(|id| player.allocator.get_script_instance_by_id(*id)) {
+      let script = player.movie.cast_manager.get_script_by_ref(&instance.script).unwrap();
+      if let Some(own_handler) = script.get_own_handler_ref(name) {
+        return Ok(Some(own_handler));
+      }
     }
+    Ok(None)
   }
 
   pub fn get_handler_from_first_arg(args: &Vec<DatumRef>, handler_name: &String)
@@ -90,9 +92,7 @@ impl ScriptInstanceUtils {
             Ok(())
           }
           Datum::ScriptInstanceRef(ancestor_instance_id) => {
-            let script_instance = player.allocator.get_script_instance_mut(&self_instance_id);
-            script_instance.ancestor = Some(ancestor_instance_id);
-            Ok(())
+            player.allocator.set_script_instance_ancestor(&self_instance_id, Some(ancestor_instance_id))
           }
           _ => Err(ScriptError::new("Cannot set ancestor to non-script instance".to_string())),
         }
@@ -131,6 +131,15 @@ impl ScriptInstanceDatumHandlers {
 
   fn get_at(datum: &DatumRef, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
+      // A position gets the value of the nth property, like in a property list
+      if let Datum::Int(position) = player.get_datum(&args[0]) {
+        let position = *position;
+        let instance = player.allocator.get_script_instance(player.get_datum(datum).to_script_instance_ref()?);
+        return instance
+          .get_own_prop_at((position - 1).max(0) as usize)
+          .map(|(_, value)| value.clone())
+          .ok_or_else(|| ScriptError::new(format!("Property {position} out of range")));
+      }
       let key = player.get_datum(&args[0]).string_value()?;
       match key.as_str() {
         "ancestor" => {
@@ -209,12 +218,18 @@ impl ScriptInstanceDatumHandlers {
     })
   }
 
+  /// The number of properties of the instance, or with a property name, the number of
+  /// items in that property.
   pub fn count(datum: &DatumRef, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let instance_ref = match player.get_datum(datum) {
         Datum::ScriptInstanceRef(instance_ref) => instance_ref.clone(),
         _ => return Err(ScriptError::new("Cannot count non-script instance".to_string())),
       };
+      if args.is_empty() {
+        let count = player.allocator.get_script_instance(&instance_ref).property_names.len();
+        return Ok(player.alloc_datum(Datum::Int(count as i32)));
+      }
       let prop_name = player.get_datum(&args[0]).string_value()?;
       let prop_value = script_get_prop(player, &instance_ref, &prop_name)?;
       let prop_value_datum = player.get_datum(&prop_value);
@@ -227,6 +242,7 @@ impl ScriptInstanceDatumHandlers {
     })
   }
 
+  /// Like getaProp on a property list, a missing property is VOID rather than an error.
   pub fn get_a_prop(datum: &DatumRef, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let prop_name = player.get_datum(&args[0]).string_value()?;
@@ -234,8 +250,29 @@ impl ScriptInstanceDatumHandlers {
         Datum::ScriptInstanceRef(instance_ref) => instance_ref.clone(),
         _ => return Err(ScriptError::new("Cannot get property on non-script instance".to_string())),
       };
-      let prop_value = script_get_prop(player, &instance_ref, &prop_name)?;
-      Ok(prop_value)
+      Ok(script_get_prop_opt(player, &instance_ref, &prop_name).unwrap_or(DatumRef::Void))
+    })
+  }
+
+  /// The name of the nth property of the instance, in the order they were declared or added.
+  pub fn get_prop_at(datum: &DatumRef, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let position = player.get_datum(&args[0]).int_value()?;
+      let instance = player.allocator.get_script_instance(player.get_datum(datum).to_script_instance_ref()?);
+      let name = instance
+        .get_own_prop_at((position - 1).max(0) as usize)
+        .map(|(name, _)| name.to_owned())
+        .ok_or_else(|| ScriptError::new(format!("Property {position} out of range")))?;
+      Ok(player.alloc_datum(Datum::Symbol(name)))
+    })
+  }
+
+  /// The names of the handlers of the instance's own script, without its ancestors.
+  pub fn handlers(datum: &DatumRef) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let (_, script) = ScriptInstanceUtils::get_script(datum, player)?;
+      let names = script.handler_names.clone();
+      Ok(alloc_symbol_list(player, names))
     })
   }
 
@@ -249,6 +286,8 @@ impl ScriptInstanceDatumHandlers {
       "getPropRef" => Self::get_prop(datum, args),
       "getaProp" => Self::get_a_prop(datum, args),
       "getAt" => Self::get_at(datum, args),
+      "getPropAt" => Self::get_prop_at(datum, args),
+      "handlers" => Self::handlers(datum),
       "count" => Self::count(datum, args),
       _ => Err(ScriptError::new_code(ScriptErrorCode::HandlerNotFound, format!("No handler {handler_name} for script instance datum")))
     }
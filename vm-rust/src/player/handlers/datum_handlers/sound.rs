@@ -1,36 +1,147 @@
-use crate::{director::lingo::datum::Datum, player::{DatumRef, DirPlayer, ScriptError}};
+use crate::{director::lingo::datum::{datum_bool, Datum, DatumType}, player::{reserve_player_mut, sound::{SoundChannel, SoundManager, SoundPlaylistEntry}, DatumRef, DirPlayer, ScriptError}};
 
 pub struct SoundDatumHandlers {}
 
 impl SoundDatumHandlers {
-  #[allow(dead_code, unused_variables)]
-  pub fn call(datum: &DatumRef, handler_name: &String, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
-    match handler_name.as_str() {
-      _ => Err(ScriptError::new(format!("No handler {handler_name} for sound")))
+  fn channel_number(player: &DirPlayer, datum: &DatumRef) -> Result<u16, ScriptError> {
+    match player.get_datum(datum) {
+      Datum::SoundRef(number) => Ok(*number),
+      _ => Err(ScriptError::new("Cannot use non-sound as a sound channel".to_string())),
+    }
+  }
+
+  fn get_channel<'a>(player: &'a DirPlayer, datum: &DatumRef) -> Result<&'a SoundChannel, ScriptError> {
+    let number = Self::channel_number(player, datum)?;
+    player.sound_manager.get_channel(number).ok_or_else(|| ScriptError::new(format!("Invalid sound channel {number}")))
+  }
+
+  fn get_channel_mut(sound_manager: &mut SoundManager, number: u16) -> Result<&mut SoundChannel, ScriptError> {
+    sound_manager.get_channel_mut(number).ok_or_else(|| ScriptError::new(format!("Invalid sound channel {number}")))
+  }
+
+  /// A member, a member name or number, or a property list with #member and #loopCount.
+  fn to_playlist_entry(player: &DirPlayer, datum_ref: &DatumRef) -> Result<SoundPlaylistEntry, ScriptError> {
+    let datum = player.get_datum(datum_ref);
+    let (member_datum, loop_count) = match datum {
+      Datum::PropList(entries, ..) => {
+        let mut member_datum = None;
+        let mut loop_count = 1;
+        for (key, value) in entries {
+          match player.get_datum(key).string_value()?.as_str() {
+            "member" => member_datum = Some(player.get_datum(value)),
+            "loopCount" => loop_count = player.get_datum(value).int_value()?,
+            _ => {}
+          }
+        }
+        let member_datum = member_datum.ok_or_else(|| ScriptError::new("Sound playlist entry needs a #member".to_string()))?;
+        (member_datum, loop_count)
+      }
+      _ => (datum, 1),
+    };
+    let member = match member_datum {
+      Datum::CastMember(member_ref) => Some(member_ref.to_owned()),
+      _ => player.movie.cast_manager.find_member_ref_by_identifiers(member_datum, None, &player.allocator)?,
+    };
+    match member {
+      Some(member) => Ok(SoundPlaylistEntry { member, loop_count }),
+      None => Err(ScriptError::new("Sound member not found".to_string())),
     }
   }
 
+  fn alloc_playlist_entry(player: &mut DirPlayer, entry: &SoundPlaylistEntry) -> DatumRef {
+    let member_key = player.alloc_datum(Datum::Symbol("member".to_string()));
+    let member = player.alloc_datum(Datum::CastMember(entry.member.to_owned()));
+    let loop_count_key = player.alloc_datum(Datum::Symbol("loopCount".to_string()));
+    let loop_count = player.alloc_datum(Datum::Int(entry.loop_count));
+    player.alloc_datum(Datum::PropList(vec![(member_key, member), (loop_count_key, loop_count)], false))
+  }
 
-  pub fn get_prop(_player: &DirPlayer, _datum: &DatumRef, prop: &String) -> Result<Datum, ScriptError> {
+  pub fn call(datum: &DatumRef, handler_name: &String, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let now_ms = player.clock.elapsed_ms();
+      player.sound_manager.update(&player.movie.cast_manager, now_ms);
+      let entries = match handler_name.as_str() {
+        "play" | "queue" => args.iter().map(|arg| Self::to_playlist_entry(player, arg)).collect::<Result<Vec<_>, _>>()?,
+        "setPlayList" => {
+          let list = player.get_datum(&args[0]).to_list()?;
+          list.iter().map(|item| Self::to_playlist_entry(player, item)).collect::<Result<Vec<_>, _>>()?
+        }
+        _ => vec![],
+      };
+      let int_arg = |index: usize| args.get(index).map(|arg| player.get_datum(arg).int_value()).transpose();
+      let (first_arg, second_arg) = (int_arg(0)?, int_arg(1)?);
+      let number = Self::channel_number(player, datum)?;
+      let cast_manager = &player.movie.cast_manager;
+      let channel = Self::get_channel_mut(&mut player.sound_manager, number)?;
+      match handler_name.as_str() {
+        "play" => {
+          match entries.into_iter().next() {
+            Some(entry) => channel.play(entry, cast_manager, now_ms),
+            None => channel.play_next(cast_manager, now_ms),
+          }
+        }
+        "queue" => channel.playlist.extend(entries),
+        "setPlayList" => channel.playlist = entries,
+        "getPlayList" => {
+          let playlist = channel.playlist.clone();
+          let items = playlist.iter().map(|entry| Self::alloc_playlist_entry(player, entry)).collect();
+          return Ok(player.alloc_datum(Datum::List(DatumType::List, items, false)));
+        }
+        "stop" => channel.stop(),
+        "pause" => channel.pause(now_ms),
+        "rewind" => channel.rewind(now_ms),
+        "breakLoop" => channel.break_loop(),
+        "fadeIn" => {
+          let volume = channel.volume(now_ms);
+          channel.fade_to(Some(0), volume, first_arg.unwrap_or(1000) as i64, now_ms);
+        }
+        "fadeOut" => channel.fade_to(None, 0, first_arg.unwrap_or(1000) as i64, now_ms),
+        "fadeTo" => {
+          let volume = first_arg.ok_or_else(|| ScriptError::new("fadeTo requires a volume".to_string()))?;
+          channel.fade_to(None, volume, second_arg.unwrap_or(1000) as i64, now_ms);
+        }
+        "isBusy" => {
+          let is_busy = channel.is_busy();
+          return Ok(player.alloc_datum(datum_bool(is_busy)));
+        }
+        _ => return Err(ScriptError::new(format!("No handler {handler_name} for sound"))),
+      }
+      Ok(DatumRef::Void)
+    })
+  }
+
+  pub fn get_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String) -> Result<Datum, ScriptError> {
+    let now_ms = player.clock.elapsed_ms();
+    player.sound_manager.update(&player.movie.cast_manager, now_ms);
+    let channel = Self::get_channel(player, datum)?;
     match prop.as_str() {
-      "volume" => {
-        Ok(Datum::Int(255)) // TODO
-      },
+      "volume" => Ok(Datum::Int(channel.volume(now_ms))),
+      "pan" => Ok(Datum::Int(channel.pan)),
+      "status" => Ok(Datum::Int(channel.status())),
+      "elapsedTime" => Ok(Datum::Int(channel.elapsed_ms(now_ms) as i32)),
+      "loopCount" => Ok(Datum::Int(channel.current.as_ref().map_or(0, |entry| entry.loop_count))),
+      "loopsRemaining" => Ok(Datum::Int(channel.loops_remaining)),
+      "member" => Ok(channel.current.as_ref().map_or(Datum::Void, |entry| Datum::CastMember(entry.member.to_owned()))),
+      "channel" | "number" => Ok(Datum::Int(channel.number as i32)),
       _ => {
-        Err(ScriptError::new(format!("Cannot get rect property {}", prop)))
+        Err(ScriptError::new(format!("Cannot get sound property {}", prop)))
       },
     }
   }
 
-  pub fn set_prop(_player: &mut DirPlayer, _datum: &DatumRef, prop: &String, _value_ref: &DatumRef) -> Result<(), ScriptError> {
+  pub fn set_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String, value_ref: &DatumRef) -> Result<(), ScriptError> {
+    let value = player.get_datum(value_ref).int_value();
+    let now_ms = player.clock.elapsed_ms();
+    let number = Self::channel_number(player, datum)?;
+    let channel = Self::get_channel_mut(&mut player.sound_manager, number)?;
     match prop.as_str() {
-      "volume" => {
-        // TODO
-        Ok(())
-      },
+      "volume" => channel.set_volume(value?),
+      "pan" => channel.set_pan(value?),
+      "loopCount" => channel.set_loop_count(value?, now_ms),
       _ => {
-        Err(ScriptError::new(format!("Cannot set rect property {}", prop)))
+        return Err(ScriptError::new(format!("Cannot set sound property {}", prop)))
       },
     }
+    Ok(())
   }
 }
@@ -1,8 +1,8 @@
-use crate::player::{
-    player_call_script_handler, player_handle_scope_return, reserve_player_ref, script_ref::ScriptInstanceRef, DatumRef, DirPlayer, ScriptError, ScriptErrorCode
-};
+use crate::{director::lingo::datum::{datum_bool, Datum}, player::{
+    player_call_script_handler, player_handle_scope_return, reserve_player_mut, reserve_player_ref, score::{compare_sprites, map_member_to_stage, map_stage_to_member, sprite_within, sprites_intersect}, script_ref::ScriptInstanceRef, DatumRef, DirPlayer, ScriptError, ScriptErrorCode
+}};
 
-use super::script_instance::ScriptInstanceUtils;
+use super::{cast_member::text_geometry::TextGeometryHandlers, script_instance::ScriptInstanceUtils};
 
 pub struct SpriteDatumHandlers {}
 
@@ -49,11 +49,40 @@ impl SpriteDatumHandlers {
     }
 
     pub fn call(
-        _: &DatumRef,
+        datum: &DatumRef,
         handler_name: &String,
-        _: &Vec<DatumRef>,
+        args: &Vec<DatumRef>,
     ) -> Result<DatumRef, ScriptError> {
         match handler_name.as_str() {
+            "intersects" | "within" => reserve_player_mut(|player| {
+                let sprite_num = player.get_datum(datum).to_sprite_ref()?;
+                let other_num = match player.get_datum(&args[0]) {
+                    Datum::SpriteRef(other_num) => *other_num,
+                    other => other.int_value()? as i16,
+                };
+                let compare = if handler_name == "intersects" { sprites_intersect } else { sprite_within };
+                let result = compare_sprites(player, sprite_num, other_num, compare)?;
+                Ok(player.alloc_datum(datum_bool(result)))
+            }),
+            "pointToChar" => reserve_player_mut(|player| {
+                let sprite_num = player.get_datum(datum).to_sprite_ref()?;
+                let point = player.get_datum(&args[0]).to_int_point()?;
+                let char_pos = TextGeometryHandlers::point_to_char(player, sprite_num, point)?;
+                Ok(player.alloc_datum(Datum::Int(char_pos)))
+            }),
+            "mapStageToMember" | "mapMemberToStage" => reserve_player_mut(|player| {
+                let sprite_num = player.get_datum(datum).to_sprite_ref()?;
+                let point = player.get_datum(&args[0]).to_int_point()?;
+                let sprite = player.movie.score.get_sprite(sprite_num)
+                    .ok_or_else(|| ScriptError::new(format!("Sprite {} does not exist", sprite_num)))?;
+                // Stage points the sprite isn't drawn at map to VOID
+                let mapped = if handler_name == "mapStageToMember" {
+                    map_stage_to_member(player, sprite, point)
+                } else {
+                    Some(map_member_to_stage(player, sprite, point))
+                };
+                Ok(mapped.map_or(DatumRef::Void, |point| player.alloc_datum(Datum::IntPoint(point))))
+            }),
             _ => Err(ScriptError::new_code(ScriptErrorCode::HandlerNotFound, format!(
                 "No sync handler {handler_name} for sprite"
             ))),
@@ -2,7 +2,7 @@ use itertools::Itertools;
 
 use crate::{director::lingo::datum::{Datum, StringChunkExpr, StringChunkSource, StringChunkType}, player::{cast_member::CastMemberType, reserve_player_mut, DatumRef, DirPlayer, ScriptError}};
 
-use super::string::{string_get_items, string_get_lines};
+use super::{cast_member::text::TextMemberHandlers, string::{string_get_items, string_get_lines}};
 
 pub struct StringChunkHandlers { }
 pub struct StringChunkUtils { }
@@ -78,6 +78,42 @@ impl StringChunkUtils {
     }
   }
 
+  /// The first and last 1-based character positions of a chunk, if the string has it.
+  pub fn resolve_chunk_char_range(string: &str, chunk_expr: &StringChunkExpr) -> Option<(usize, usize)> {
+    let chars = string.chars().collect_vec();
+    let is_separator = |c: char| match chunk_expr.chunk_type {
+      StringChunkType::Char => false,
+      StringChunkType::Word => c.is_whitespace(),
+      StringChunkType::Item => c == chunk_expr.item_delimiter,
+      StringChunkType::Line => c == '\r' || c == '\n',
+    };
+    // The character spans of each chunk, as half-open ranges
+    let is_char = matches!(chunk_expr.chunk_type, StringChunkType::Char);
+    let is_word = matches!(chunk_expr.chunk_type, StringChunkType::Word);
+    let mut spans = vec![];
+    let mut span_start = None;
+    for (index, c) in chars.iter().enumerate() {
+      if is_char {
+        spans.push((index, index + 1));
+      } else if is_separator(*c) {
+        if !is_word || span_start.is_some() {
+          spans.push((span_start.unwrap_or(index), index));
+        }
+        span_start = None;
+      } else if span_start.is_none() {
+        span_start = Some(index);
+      }
+    }
+    if !is_char && (span_start.is_some() || !is_word) {
+      spans.push((span_start.unwrap_or(chars.len()), chars.len()));
+    }
+    let first = (chunk_expr.start.max(1) - 1) as usize;
+    let last = if chunk_expr.end <= 0 { first } else { (chunk_expr.end - 1) as usize }.min(spans.len().saturating_sub(1));
+    let (start, _) = *spans.get(first)?;
+    let (_, end) = spans[last.max(first)];
+    if end > start { Some((start + 1, end)) } else { None }
+  }
+
   fn vm_range_to_host(range: (i32, i32), max_length: usize) -> (usize, usize) {
     let (start, end) = range;
     let start_index = std::cmp::max(0, start - 1) as usize;
@@ -243,11 +279,20 @@ impl StringChunkHandlers {
     })
   }
 
-  pub fn set_prop(_: &mut DirPlayer, _: &DatumRef, prop: &String, _value_ref: &DatumRef) -> Result<(), ScriptError> {
+  pub fn set_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String, value_ref: &DatumRef) -> Result<(), ScriptError> {
     match prop.as_str() {
       "font" | "fontStyle" | "color" => {
         // TODO
       },
+      "hyperlink" | "hyperlinkState" => {
+        let (source, chunk_expr, ..) = player.get_datum(datum).to_string_chunk()?;
+        let (source, chunk_expr) = (source.clone(), chunk_expr.clone());
+        let value = player.get_datum(value_ref).clone();
+        match source {
+          StringChunkSource::Member(member_ref) => TextMemberHandlers::set_chunk_hyperlink_prop(player, &member_ref, &chunk_expr, prop, value)?,
+          StringChunkSource::Datum(_) => return Err(ScriptError::new(format!("Cannot set {prop} of a string"))),
+        }
+      },
       _ => {
         return Err(ScriptError::new(format!("Cannot set property {prop} for string chunk datum")))
       }
@@ -1,4 +1,4 @@
-use crate::{console_warn, director::lingo::datum::Datum, player::{reserve_player_mut, timeout::Timeout, DatumRef, DirPlayer, ScriptError}};
+use crate::{console_warn, director::lingo::datum::{datum_bool, Datum}, player::{reserve_player_mut, timeout::Timeout, DatumRef, DirPlayer, ScriptError}};
 
 pub struct TimeoutDatumHandlers {}
 
@@ -14,9 +14,13 @@ impl TimeoutDatumHandlers {
 
   pub fn new(datum: &DatumRef, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
+      if args.len() < 2 {
+        return Err(ScriptError::new("Timeout new requires a period and a handler".to_string()));
+      }
       let timeout_period = player.get_datum(&args[0]).int_value()?;
       let timeout_handler = player.get_datum(&args[1]).string_value()?;
-      let target_ref = args[2].clone();
+      // Without a target the handler is sent to movie scripts
+      let target_ref = args.get(2).cloned().unwrap_or(DatumRef::Void);
       let timeout_datum = player.get_datum(&datum);
       let timeout_name = match timeout_datum {
         Datum::TimeoutRef(timeout_name) => timeout_name,
@@ -29,8 +33,10 @@ impl TimeoutDatumHandlers {
         period: timeout_period as u32,
         target_ref,
         is_scheduled: false,
+        persistent: false,
+        last_fired_ms: 0,
       };
-      timeout.schedule();
+      timeout.schedule(player.clock.as_ref());
       player.timeout_manager.add_timeout(timeout);
       Ok(datum.clone())
     })
@@ -52,46 +58,98 @@ impl TimeoutDatumHandlers {
 
   pub fn get_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String) -> Result<DatumRef, ScriptError> {
     let timeout_ref = player.get_datum(datum);
-    let _timeout_name = match timeout_ref {
-      Datum::TimeoutRef(timeout_name) => Ok(timeout_name),
+    let timeout_name = match timeout_ref {
+      Datum::TimeoutRef(timeout_name) => Ok(timeout_name.to_owned()),
       _ => Err(ScriptError::new("Cannot get prop of non-timeout".to_string())),
     }?;
-    let timeout = player.timeout_manager.get_timeout(_timeout_name);
-    match prop.as_str() {
-      "name" => {
-        Ok(player.alloc_datum(Datum::String(_timeout_name.to_owned())))
-      },
-      "target" => {
-        Ok(timeout.map_or(DatumRef::Void, |x| x.target_ref.clone()))
-      }
-      _ => {
-        Err(ScriptError::new(format!("Cannot get timeout property {}", prop)))
-      },
+    if prop == "name" {
+      return Ok(player.alloc_datum(Datum::String(timeout_name)));
     }
+    let timeout = player.timeout_manager.get_timeout(&timeout_name);
+    let timeout = match timeout {
+      Some(timeout) => timeout,
+      // Properties of a forgotten or never created timeout read as void
+      None => return Ok(DatumRef::Void),
+    };
+    let result = match prop.as_str() {
+      "target" => return Ok(timeout.target_ref.clone()),
+      "period" => Datum::Int(timeout.period as i32),
+      "persistent" => datum_bool(timeout.persistent),
+      "timeoutHandler" => Datum::Symbol(timeout.handler.to_owned()),
+      "time" => Datum::Int(timeout.next_time_ms() as i32),
+      _ => return Err(ScriptError::new(format!("Cannot get timeout property {}", prop))),
+    };
+    Ok(player.alloc_datum(result))
   }
 
   pub fn set_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String, value: &DatumRef) -> Result<(), ScriptError> {
     let timeout_ref = player.get_datum(datum);
-    let _timeout_name = {
-      match timeout_ref {
-        Datum::TimeoutRef(timeout_name) => Ok(timeout_name.clone()),
-        _ => Err(ScriptError::new("Cannot set prop of non-timeout".to_string())),
-      }?
+    let timeout_name = match timeout_ref {
+      Datum::TimeoutRef(timeout_name) => Ok(timeout_name.clone()),
+      _ => Err(ScriptError::new("Cannot set prop of non-timeout".to_string())),
+    }?;
+    let value_datum = player.get_datum(value).clone();
+    let clock = player.clock.as_ref();
+    let timeout = player.timeout_manager.get_timeout_mut(&timeout_name);
+    let timeout = match timeout {
+      Some(timeout) => timeout,
+      None => return Err(ScriptError::new(format!("Cannot set {} of unscheduled timeout", prop))),
     };
-    let timeout = player.timeout_manager.get_timeout_mut(&_timeout_name);
     match prop.as_str() {
       "target" => {
-        let new_target = value;
-        if let Some(timeout) = timeout {
-          timeout.target_ref = new_target.clone();
-        } else {
-          return Err(ScriptError::new("Cannot set target of unscheduled timeout".to_string()));
+        timeout.target_ref = value.clone();
+      }
+      "period" => {
+        let period = value_datum.int_value()?;
+        if period < 0 {
+          return Err(ScriptError::new(format!("Invalid timeout period {}", period)));
         }
-        Ok(())
+        timeout.set_period(period as u32, clock);
+      }
+      "persistent" => {
+        timeout.persistent = value_datum.bool_value()?;
+      }
+      "timeoutHandler" => {
+        timeout.handler = value_datum.string_value()?;
       }
-      _ => {
-        Err(ScriptError::new(format!("Cannot set timeout property {}", prop)))
-      },
+      _ => return Err(ScriptError::new(format!("Cannot set timeout property {}", prop))),
     }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use wasm_bindgen_test::*;
+
+  use crate::{player::{datum_formatting::format_datum, eval::eval_lingo}, test_utils::init_player};
+
+  use super::*;
+
+  fn get_prop_string(datum: &DatumRef, prop: &str) -> String {
+    reserve_player_mut(|player| {
+      let value_ref = TimeoutDatumHandlers::get_prop(player, datum, &prop.to_string()).unwrap();
+      format_datum(&value_ref, player)
+    })
+  }
+
+  #[wasm_bindgen_test]
+  fn forgotten_timeouts_read_as_void() {
+    init_player();
+    let (timeout_ref, args) = reserve_player_mut(|player| {
+      let timeout_ref = player.alloc_datum(Datum::TimeoutRef("forgotten".to_string()));
+      let args = vec![
+        player.alloc_datum(Datum::Int(60000)),
+        player.alloc_datum(Datum::Symbol("tick".to_string())),
+        eval_lingo("[1]".to_string(), player).unwrap(),
+      ];
+      (timeout_ref, args)
+    });
+    TimeoutDatumHandlers::new(&timeout_ref, &args).unwrap();
+    assert_eq!(get_prop_string(&timeout_ref, "target"), "[1]");
+    TimeoutDatumHandlers::call(&timeout_ref, &"forget".to_string(), &vec![]).unwrap();
+    assert_eq!(get_prop_string(&timeout_ref, "target"), "Void");
+    assert_eq!(get_prop_string(&timeout_ref, "period"), "Void");
+    assert_eq!(get_prop_string(&timeout_ref, "name"), "\"forgotten\"");
   }
 }
@@ -0,0 +1,100 @@
+use crate::{director::lingo::datum::{datum_bool, Datum}, player::{geometry::IntRect, reserve_player_mut, reserve_player_ref, window::{close_window, forget_window, open_window, player_call_window_handler, player_load_window_movie}, DatumRef, DirPlayer, ScriptError}};
+
+pub struct WindowDatumHandlers {}
+
+impl WindowDatumHandlers {
+  fn window_name(datum: &DatumRef) -> Result<String, ScriptError> {
+    reserve_player_ref(|player| {
+      match player.get_datum(datum) {
+        Datum::WindowRef(name) => Ok(name.to_owned()),
+        _ => Err(ScriptError::new("Cannot use non-window as a window".to_string())),
+      }
+    })
+  }
+
+  pub fn has_async_handler(handler_name: &str) -> bool {
+    !matches!(handler_name, "close" | "forget" | "moveToFront" | "moveToBack")
+  }
+
+  pub fn call(datum: &DatumRef, handler_name: &String, _: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    let name = Self::window_name(datum)?;
+    reserve_player_mut(|player| {
+      match handler_name.as_str() {
+        "close" => close_window(player, &name),
+        "forget" => forget_window(player, &name)?,
+        // Windows are stacked by the host page
+        "moveToFront" | "moveToBack" => {}
+        _ => return Err(ScriptError::new(format!("No handler {handler_name} for window"))),
+      }
+      Ok(DatumRef::Void)
+    })
+  }
+
+  /// Other handlers are sent to the movie in the window.
+  pub async fn call_async(datum: &DatumRef, handler_name: &String, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    let name = Self::window_name(datum)?;
+    match handler_name.as_str() {
+      "open" => {
+        player_load_window_movie(&name).await?;
+        reserve_player_mut(|player| open_window(player, &name));
+        Ok(DatumRef::Void)
+      }
+      _ => player_call_window_handler(&name, handler_name, args).await,
+    }
+  }
+
+  pub fn get_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String) -> Result<DatumRef, ScriptError> {
+    let name = match player.get_datum(datum) {
+      Datum::WindowRef(name) => name.to_owned(),
+      _ => return Err(ScriptError::new("Cannot get prop of non-window".to_string())),
+    };
+    let is_active = player.window_manager.is_active(&name);
+    let window = player.window_manager.get_or_create(&name);
+    // The movie of the window is in the player while it's being told
+    let movie_rect = if is_active {
+      Some(player.movie.rect.to_owned())
+    } else {
+      window.movie.as_ref().map(|movie| movie.rect.to_owned())
+    };
+    let result = match prop.as_str() {
+      "name" => Datum::String(window.name.to_owned()),
+      "title" => Datum::String(window.title.to_owned()),
+      "fileName" => Datum::String(window.movie_path()),
+      "visible" => datum_bool(window.visible),
+      "windowType" => Datum::Int(window.window_type),
+      "rect" | "drawRect" => {
+        let rect = window.rect.to_owned().or(movie_rect).unwrap_or(IntRect::from(0, 0, 0, 0));
+        Datum::IntRect((rect.left, rect.top, rect.right, rect.bottom))
+      }
+      "sourceRect" => {
+        let rect = movie_rect.unwrap_or(IntRect::from(0, 0, 0, 0));
+        Datum::IntRect((rect.left, rect.top, rect.right, rect.bottom))
+      }
+      _ => return Err(ScriptError::new(format!("Cannot get window property {}", prop))),
+    };
+    Ok(player.alloc_datum(result))
+  }
+
+  pub fn set_prop(player: &mut DirPlayer, datum: &DatumRef, prop: &String, value: &DatumRef) -> Result<(), ScriptError> {
+    let name = match player.get_datum(datum) {
+      Datum::WindowRef(name) => name.to_owned(),
+      _ => return Err(ScriptError::new("Cannot set prop of non-window".to_string())),
+    };
+    let value_datum = player.get_datum(value).clone();
+    let window = player.window_manager.get_or_create(&name);
+    match prop.as_str() {
+      "title" => window.title = value_datum.string_value()?,
+      "fileName" => {
+        if window.movie.is_some() {
+          return Err(ScriptError::new(format!("Cannot change the movie of window {name} once it's loaded")));
+        }
+        window.file_name = Some(value_datum.string_value()?);
+      }
+      "visible" => window.visible = value_datum.bool_value()?,
+      "windowType" => window.window_type = value_datum.int_value()?,
+      "rect" | "drawRect" => window.rect = Some(IntRect::from_tuple(value_datum.to_int_rect()?)),
+      _ => return Err(ScriptError::new(format!("Cannot set window property {}", prop))),
+    }
+    Ok(())
+  }
+}
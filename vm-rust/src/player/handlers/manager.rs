@@ -1,9 +1,24 @@
 use log::warn;
 
-use crate::{director::lingo::datum::{Datum, DatumType}, js_api::JsApi, player::{datum_formatting::format_concrete_datum, player_alloc_datum, player_call_script_handler, reserve_player_mut, reserve_player_ref, script_ref::ScriptInstanceRef, DatumRef, DirPlayer, ScriptError}};
+use crate::{director::lingo::datum::{Datum, DatumType}, js_api::JsApi, player::{datum_formatting::format_concrete_datum, player_alloc_datum, player_call_script_handler, reserve_player_mut, reserve_player_ref, script_ref::ScriptInstanceRef, xtra::manager::{call_xtra_global_async_handler, call_xtra_global_handler, has_xtra_global_async_handler, has_xtra_global_handler}, DatumRef, DirPlayer, ScriptError}};
 
-use super::{cast::CastHandlers, datum_handlers::{list_handlers::ListDatumHandlers, player_call_datum_handler, point::PointDatumHandlers, prop_list::PropListDatumHandlers, script_instance::{ScriptInstanceDatumHandlers, ScriptInstanceUtils}}, movie::MovieHandlers, net::NetHandlers, string::StringHandlers, types::TypeHandlers};
+use super::{cast::CastHandlers, datum_handlers::{list_handlers::ListDatumHandlers, player_call_datum_handler, point::PointDatumHandlers, prop_list::PropListDatumHandlers, rect::RectDatumHandlers, script::ScriptDatumHandlers, script_instance::{ScriptInstanceDatumHandlers, ScriptInstanceUtils}}, movie::MovieHandlers, net::NetHandlers, string::StringHandlers, types::{TypeHandlers, TypeUtils}};
 
+/// Defines `call_handler` along with `BUILT_IN_HANDLER_NAMES`, the names its arms match,
+/// so that the handlers a movie calls can be checked for ones that are missing.
+macro_rules! built_in_handlers {
+  (fn $fn_name:ident($name:ident, $args:ident) { $($($handler:literal)|+ $(if $guard:expr)? => $body:expr,)* }) => {
+    const BUILT_IN_HANDLER_NAMES: &[&str] = &[$($($handler,)+)*];
+
+    pub fn $fn_name($name: &String, $args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+      match $name.as_str() {
+        $($($handler)|+ $(if $guard)? => $body,)*
+        _ if has_xtra_global_handler($name) => call_xtra_global_handler($name, $args),
+        _ => Self::call_missing_handler($name, $args),
+      }
+    }
+  };
+}
 
 pub struct BuiltInHandlerManager { }
 
@@ -13,7 +28,9 @@ impl BuiltInHandlerManager {
       let param_number = player.get_datum(&args[0]).int_value()?;
       let scope_ref = player.current_scope_ref();
       let scope = player.scopes.get(scope_ref).unwrap();
-      Ok(scope.args[(param_number - 1) as usize].clone())
+      // Parameters past the ones the handler was called with are VOID
+      let param = if param_number >= 1 { scope.args.get(param_number as usize - 1) } else { None };
+      Ok(param.cloned().unwrap_or(DatumRef::Void))
     })
   }
 
@@ -61,7 +78,9 @@ impl BuiltInHandlerManager {
 
   fn clear_globals(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
-      player.globals.clear();
+      for (name, value_ref) in std::mem::take(&mut player.globals) {
+        player.step_history.record_global_change(name, Some(value_ref));
+      }
       Ok(DatumRef::Void)
     })
   }
@@ -74,7 +93,7 @@ impl BuiltInHandlerManager {
         return Err(ScriptError::new("random: max must be greater than or equal to 0".to_string()));
       }
       let max = max as f64;
-      let random = js_sys::Math::random() * max as f64;
+      let random = player.random.next_f64() * max as f64;
       let random = random.floor() as i32;
       let random = random + min;
       Ok(player.alloc_datum(Datum::Int(random)))
@@ -83,28 +102,39 @@ impl BuiltInHandlerManager {
 
   fn bit_and(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
-      let a = player.get_datum(&args[0]).int_value()?;
-      let b = player.get_datum(&args[1]).int_value()?;
+      if args.len() != 2 {
+        return Err(ScriptError::new("Bitwise AND requires 2 arguments".to_string()));
+      }
+      let a = TypeUtils::bit_operand(player.get_datum(&args[0]))?;
+      let b = TypeUtils::bit_operand(player.get_datum(&args[1]))?;
       Ok(player.alloc_datum(Datum::Int(a & b)))
     })
   }
 
   fn bit_or(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
-      let a = player.get_datum(&args[0]).int_value()?;
-      let b = player.get_datum(&args[1]).int_value()?;
+      if args.len() != 2 {
+        return Err(ScriptError::new("Bitwise OR requires 2 arguments".to_string()));
+      }
+      let a = TypeUtils::bit_operand(player.get_datum(&args[0]))?;
+      let b = TypeUtils::bit_operand(player.get_datum(&args[1]))?;
       Ok(player.alloc_datum(Datum::Int(a | b)))
     })
   }
 
   fn bit_not(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
-      let a = player.get_datum(&args[0]).int_value()?;
+      let a = TypeUtils::bit_operand(player.get_datum(&args[0]))?;
       Ok(player.alloc_datum(Datum::Int(!a)))
     })
   }
 
+  /// Calls a handler on a receiver, or on every script instance of a list of receivers, in
+  /// which case receivers without the handler are skipped and the last result is returned.
   async fn call(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    if args.len() < 2 {
+      return Err(ScriptError::new("call requires a handler name and a receiver".to_string()));
+    }
     let receiver_ref = &args[1];
     let (handler_name, args, instance_ids) = reserve_player_mut(|player| {
       let handler_name = player.get_datum(&args[0]);
@@ -153,14 +183,52 @@ impl BuiltInHandlerManager {
     Ok(result)
   }
 
-  pub fn has_async_handler(name: &String) -> bool {
-    match name.as_str() {
+  /// open window, close window and forget window are sent to the window they name, and text
+  /// geometry functions to the member or sprite they are about.
+  async fn call_first_arg_handler(name: &String, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    match args.first() {
+      Some(obj_ref) => player_call_datum_handler(obj_ref, name, &args[1..].to_vec()).await,
+      None => Err(ScriptError::new(format!("{name} requires an argument"))),
+    }
+  }
+
+  fn get_first_arg_type(args: &[DatumRef]) -> Option<DatumType> {
+    args.first().map(|arg| reserve_player_ref(|player| player.get_datum(arg).type_enum()))
+  }
+
+  /// `findPos`, `findPosNear` and `getPos` called with the list as their first argument.
+  fn find_in_list(name: &String, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    if args.len() != 2 {
+      return Err(ScriptError::new(format!("Invalid number of arguments for {}", name)));
+    }
+    let (list, find_args) = (&args[0], &args[1..].to_vec());
+    match Self::get_first_arg_type(args) {
+      Some(DatumType::PropList) => PropListDatumHandlers::call(list, name, find_args),
+      Some(DatumType::List) => ListDatumHandlers::call(list, name, find_args),
+      _ => Err(ScriptError::new(format!("Cannot {} on non list", name))),
+    }
+  }
+
+  /// Whether a global handler with this name is built into the player or one of its xtras.
+  pub fn has_handler(name: &String) -> bool {
+    Self::BUILT_IN_HANDLER_NAMES.contains(&name.as_str()) || Self::has_async_handler(name) || has_xtra_global_handler(name)
+  }
+
+  pub fn has_async_handler(name: &str) -> bool {
+    match name {
       "call" => true,
       "new" => true,
       "callAncestor" => true,
       "sendSprite" => true,
       "sendAllSprites" => true,
-      _ => false,
+      "open" => true,
+      "close" => true,
+      "forget" => true,
+      "alert" => true,
+      "updateStage" => true,
+      "do" => true,
+      "charPosToLoc" | "locToCharPos" | "lineHeight" | "scrollByLine" | "scrollByPage" | "pointToChar" => true,
+      _ => has_xtra_global_async_handler(name),
     }
   }
 
@@ -171,6 +239,15 @@ impl BuiltInHandlerManager {
       "callAncestor" => TypeHandlers::call_ancestor(args).await,
       "sendSprite" => MovieHandlers::send_sprite(args).await,
       "sendAllSprites" => MovieHandlers::send_all_sprites(args).await,
+      "open" | "close" | "forget" => Self::call_first_arg_handler(name, args).await,
+      // Text geometry functions are also called with the member or sprite as the first argument
+      "charPosToLoc" | "locToCharPos" | "lineHeight" | "scrollByLine" | "scrollByPage" | "pointToChar" => {
+        Self::call_first_arg_handler(name, args).await
+      }
+      "alert" => MovieHandlers::alert(args).await,
+      "updateStage" => MovieHandlers::update_stage(args).await,
+      "do" => MovieHandlers::do_statements(args).await,
+      _ if has_xtra_global_async_handler(name) => call_xtra_global_async_handler(name, args).await,
       _ => {
         let msg = format!("No built-in async handler: {}", name);
         return Err(ScriptError::new(msg));
@@ -178,9 +255,15 @@ impl BuiltInHandlerManager {
     }
   }
 
-  pub fn call_handler(name: &String, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
-    match name.as_str() {
+  built_in_handlers! {
+    fn call_handler(name, args) {
       "castLib" => CastHandlers::cast_lib(args),
+      "preLoad" | "preload" => CastHandlers::pre_load(args),
+      "unLoad" | "unload" => CastHandlers::unload(args),
+      // preLoadCast and unLoadCast are the names from before casts had members
+      "preLoadMember" | "preloadMember" | "preLoadCast" | "preloadCast" => CastHandlers::pre_load_member(args),
+      "unLoadMember" | "unloadMember" | "unLoadCast" | "unloadCast" => CastHandlers::unload_member(args),
+      "ramNeeded" => CastHandlers::ram_needed(args),
       "preloadNetThing" => NetHandlers::preload_net_thing(args),
       "netDone" => NetHandlers::net_done(args),
       "moveToFront" => Ok(DatumRef::Void),
@@ -192,13 +275,18 @@ impl BuiltInHandlerManager {
       "stringp" => TypeHandlers::stringp(args),
       "integerp" => TypeHandlers::integerp(args),
       "floatp" => TypeHandlers::floatp(args),
+      // offset(rect, h, v) moves a rect, offset(string, string) finds a string in another
+      "offset" if matches!(Self::get_first_arg_type(args), Some(DatumType::IntRect)) => RectDatumHandlers::offset(&args[0], &args[1..]),
       "offset" => StringHandlers::offset(args),
       "length" => StringHandlers::length(args),
       "value" => TypeHandlers::value(args),
+      "doLater" => MovieHandlers::do_later(args),
       "script" => MovieHandlers::script(args),
       "void" => TypeHandlers::void(args),
       "param" => Self::param(args),
+      "count" if matches!(Self::get_first_arg_type(args), Some(DatumType::ScriptInstanceRef)) => ScriptInstanceDatumHandlers::count(&args[0], &args[1..].to_vec()),
       "count" => Self::count(args),
+      "getAt" if matches!(Self::get_first_arg_type(args), Some(DatumType::ScriptInstanceRef)) => ScriptInstanceDatumHandlers::call(&args[0], name, &args[1..].to_vec()),
       "getAt" => Self::get_at(args),
       "ilk" => TypeHandlers::ilk(args),
       "member" => MovieHandlers::member(args),
@@ -215,20 +303,35 @@ impl BuiltInHandlerManager {
       "bitNot" => Self::bit_not(args),
       "symbol" => TypeHandlers::symbol(args),
       "go" => MovieHandlers::go(args),
+      "goLoop" => MovieHandlers::go_marker(0),
+      "goNext" => MovieHandlers::go_marker(1),
+      "goPrevious" => MovieHandlers::go_marker(-1),
+      "play" => MovieHandlers::play(args),
+      "playDone" => MovieHandlers::play_done(),
+      "marker" => MovieHandlers::marker(args),
+      "label" => MovieHandlers::label(args),
       "puppetSprite" => MovieHandlers::puppet_sprite(args),
       "clearGlobals" => Self::clear_globals(args),
       "sprite" => MovieHandlers::sprite(args),
+      "constrainH" => MovieHandlers::constrain(args, true),
+      "constrainV" => MovieHandlers::constrain(args, false),
       "point" => TypeHandlers::point(args),
       "cursor" => TypeHandlers::cursor(args),
       "externalParamValue" => MovieHandlers::external_param_value(args),
+      "externalParamName" => MovieHandlers::external_param_name(args),
+      "externalParamCount" => MovieHandlers::external_param_count(args),
+      "externalEvent" => MovieHandlers::external_event(args),
       "getNetText" => NetHandlers::get_net_text(args),
       "timeout" => TypeHandlers::timeout(args),
+      "window" => TypeHandlers::window(args),
       "rect" => TypeHandlers::rect(args),
       "getStreamStatus" => NetHandlers::get_stream_status(args),
+      "frameReady" => NetHandlers::frame_ready(args),
       "netError" => NetHandlers::net_error(args),
       "netTextresult" => NetHandlers::net_text_result(args),
       "netTextResult" => NetHandlers::net_text_result(args),
       "rgb" => TypeHandlers::rgb(args),
+      "date" => TypeHandlers::date(args),
       "list" => TypeHandlers::list(args),
       "image" => TypeHandlers::image(args),
       "chars" => StringHandlers::chars(args),
@@ -240,18 +343,24 @@ impl BuiltInHandlerManager {
       "setPref" => MovieHandlers::set_pref(args),
       "gotoNetPage" => MovieHandlers::go_to_net_page(args),
       "pass" => MovieHandlers::pass(args),
+      "dontPassEvent" => MovieHandlers::dont_pass_event(args),
       "union" => TypeHandlers::union(args),
       "bitXor" => TypeHandlers::bit_xor(args),
       "power" => TypeHandlers::power(args),
       "add" => TypeHandlers::add(args),
       "nothing" => TypeHandlers::nothing(args),
-      "updateStage" => MovieHandlers::update_stage(args),
+      "puppetTransition" => MovieHandlers::puppet_transition(args),
       "getaProp" => TypeHandlers::get_a_prop(args),
+      "inside" if matches!(Self::get_first_arg_type(args), Some(DatumType::IntRect)) => RectDatumHandlers::inside(&args[0], &args[1..]),
       "inside" => {
         let point = &args[0];
         let rect = &args[1..].to_vec();
         PointDatumHandlers::inside(point, rect)
       },
+      "inflate" => RectDatumHandlers::inflate(&args[0], &args[1..]),
+      "map" if matches!(Self::get_first_arg_type(args), Some(DatumType::IntPoint)) => PointDatumHandlers::map(&args[0], &args[1..]),
+      "map" if matches!(Self::get_first_arg_type(args), Some(DatumType::List)) => PointDatumHandlers::map_quad(&args[0], &args[1..]),
+      "map" => RectDatumHandlers::map(&args[0], &args[1..]),
       "addProp" => {
         let list = &args[0];
         let args = &args[1..].to_vec();
@@ -309,6 +418,9 @@ impl BuiltInHandlerManager {
           DatumType::ScriptInstanceRef => {
             ScriptInstanceDatumHandlers::set_a_prop(datum, args)
           }
+          DatumType::ScriptRef => {
+            ScriptDatumHandlers::set_a_prop(datum, args)
+          }
           _ => {
             Err(ScriptError::new("Cannot setaProp on non-prop list or child object".to_string()))
           }
@@ -335,8 +447,7 @@ impl BuiltInHandlerManager {
             },
           }
         })
-        
-      }
+      },
       "getProp" => {
         let list = &args[0];
         let args = &args[1..].to_vec();
@@ -347,28 +458,62 @@ impl BuiltInHandlerManager {
       "sort" => TypeHandlers::sort(args),
       "intersect" => TypeHandlers::intersect(args),
       "rollover" => MovieHandlers::rollover(args),
+      "zoomBox" => MovieHandlers::zoom_box(args),
+      "fadeIn" => MovieHandlers::fade_stage(args, true),
+      "fadeOut" => MovieHandlers::fade_stage(args, false),
+      "getPropAt" if matches!(Self::get_first_arg_type(args), Some(DatumType::ScriptInstanceRef)) => ScriptInstanceDatumHandlers::get_prop_at(&args[0], &args[1..]),
+      "getPropAt" if matches!(Self::get_first_arg_type(args), Some(DatumType::PropList)) => PropListDatumHandlers::get_prop_at(&args[0], &args[1..].to_vec()),
       "getPropAt" => TypeHandlers::get_prop_at(args),
+      "setPropAt" => match args.split_first() {
+        Some((list, args)) => PropListDatumHandlers::set_prop_at(list, args),
+        None => Err(ScriptError::new("Invalid number of arguments for setPropAt".to_string())),
+      },
+      "findPos" | "findPosNear" | "getPos" => Self::find_in_list(name, args),
+      "handlers" => match Self::get_first_arg_type(args) {
+        Some(DatumType::ScriptRef) => ScriptDatumHandlers::handlers(&args[0]),
+        Some(DatumType::ScriptInstanceRef) => ScriptInstanceDatumHandlers::handlers(&args[0]),
+        _ => Err(ScriptError::new("handlers() needs a script or a child object".to_string())),
+      },
       "puppetSound" => Ok(DatumRef::Void), // TODO
       "pi" => TypeHandlers::pi(args),
       "sin" => TypeHandlers::sin(args),
       "cos" => TypeHandlers::cos(args),
+      "atan" => TypeHandlers::atan(args),
+      "sqrt" => TypeHandlers::sqrt(args),
       "sound" => TypeHandlers::sound(args),
-      _ => {
-        let formatted_args = reserve_player_ref(|player| {
-          let mut formatted_args = String::new();
-          for arg in args {
-            if !formatted_args.is_empty() {
-              formatted_args.push_str(", ");
-            }
-            formatted_args.push_str(&format_concrete_datum(&player.get_datum(arg), player));
-          }
-          Ok(formatted_args)
-        })?;
-        let msg = format!("No built-in handler: {}({})", name, formatted_args);
-        warn!("{msg}");
-        return Err(ScriptError::new(msg));
+      "soundBusy" => TypeHandlers::sound_busy(args),
+    }
+  }
+
+  /// Reports a handler neither the player nor its xtras implement, returning VOID
+  /// instead when the missing handler policy skips it.
+  fn call_missing_handler(name: &String, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    let formatted_args = reserve_player_ref(|player| {
+      let mut formatted_args = String::new();
+      for arg in args {
+        if !formatted_args.is_empty() {
+          formatted_args.push_str(", ");
+        }
+        formatted_args.push_str(&format_concrete_datum(&player.get_datum(arg), player));
+      }
+      Ok(formatted_args)
+    })?;
+    let msg = format!("No built-in handler: {}({})", name, formatted_args);
+    let is_skipped = reserve_player_mut(|player| {
+      let policy = &mut player.missing_handler_policy;
+      if !policy.should_skip(name) {
+        return false;
       }
+      if policy.warned.insert(name.to_owned()) {
+        warn!("{msg}, returning VOID");
+      }
+      true
+    });
+    if is_skipped {
+      return Ok(DatumRef::Void);
     }
+    warn!("{msg}");
+    return Err(ScriptError::new(msg));
   }
 }
 
@@ -380,10 +525,11 @@ fn get_datum_script_instance_ids(value_ref: &DatumRef, player: &DirPlayer) -> Re
       instance_refs.push(instance_id.clone());
     },
     Datum::SpriteRef(sprite_id) => {
-      let sprite = player.movie.score.get_sprite(*sprite_id).unwrap();
-      instance_refs.extend(sprite.script_instance_list.clone());
+      if let Some(sprite) = player.movie.score.get_sprite(*sprite_id) {
+        instance_refs.extend(sprite.script_instance_list.clone());
+      }
     },
-    Datum::Int(_) => {},
+    Datum::Int(_) | Datum::Void => {},
     _ => {
       return Err(ScriptError::new(format!("Cannot get script instance ids from datum of type: {}", value.type_str())));
     }
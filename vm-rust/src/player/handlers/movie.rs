@@ -1,4 +1,6 @@
-use crate::{director::lingo::datum::{Datum, DatumType}, player::{cast_lib::INVALID_CAST_MEMBER_REF, datum_formatting::format_datum, events::{player_invoke_event_to_instances, player_invoke_static_event}, reserve_player_mut, score::get_sprite_at, DatumRef, ScriptError}};
+use log::warn;
+
+use crate::{director::lingo::datum::{Datum, DatumType}, js_api::JsApi, rendering::{draw_stage_now, next_animation_frame}, player::{alert::player_alert, bytecode::string::StringBytecodeHandler, cast_lib::INVALID_CAST_MEMBER_REF, commands::{player_dispatch, PlayerVMCommand}, datum_formatting::format_datum, do_command::player_do, events::{player_invoke_event_to_instances, player_invoke_static_event, player_suspend_handler}, reserve_player_mut, reserve_player_ref, score::{constrain_to_sprite, get_concrete_sprite_rect, get_sprite_at}, stage_effects::{StageFade, ZoomBox}, DatumRef, DirPlayer, ScriptError}};
 
 pub struct MovieHandlers {}
 
@@ -58,21 +60,18 @@ impl MovieHandlers {
     })
   }
 
+  pub fn resolve_frame(player: &DirPlayer, datum: &Datum) -> Result<Option<u32>, ScriptError> {
+    match datum.type_enum() {
+      DatumType::Int | DatumType::Float => Ok(Some(datum.int_value()? as u32)),
+      DatumType::String => Ok(player.movie.score.find_label_frame(&datum.string_value()?)),
+      _ => Ok(None),
+    }
+  }
+
   pub fn go(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let datum: &Datum = player.get_datum(&args[0]);
-      let datum_type = datum.type_enum();
-      let destination_frame = match datum_type {
-        DatumType::Int => {
-          Some( datum.int_value()? as u32)
-        },
-        DatumType::String => {
-          let label = datum.string_value()?;
-          let frame_label = player.movie.score.frame_labels.iter().find(|fl| fl.label == label);
-          frame_label.map(|frame_label| frame_label.frame_num as u32)
-        },
-        _ => None,
-      };
+      let destination_frame = Self::resolve_frame(player, datum)?;
       match destination_frame {
         Some(frame) => {
             player.next_frame = Some(frame);
@@ -83,6 +82,91 @@ impl MovieHandlers {
     })
   }
 
+  /// play frame/movie jumps like go but remembers where it was called from, so a
+  /// later play done can return there. Called without a destination, it acts as
+  /// play done.
+  pub fn play(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    let is_play_done = reserve_player_ref(|player| {
+      args.first().is_none_or(|arg| player.get_datum(arg).is_void()) && args.len() < 2
+    });
+    if is_play_done {
+      return Self::play_done();
+    }
+    reserve_player_mut(|player| {
+      if let Some(movie_ref) = args.get(1) {
+        let movie_name = player.get_datum(movie_ref).string_value()?;
+        if !Self::is_current_movie(player, &movie_name) {
+          return Err(ScriptError::new(format!("play movie \"{}\" is not supported, only frames of the current movie can be played", movie_name)));
+        }
+      }
+      let frame_datum = player.get_datum(&args[0]);
+      let destination_frame = if frame_datum.is_void() {
+        Some(1)
+      } else {
+        Self::resolve_frame(player, frame_datum)?
+      };
+      match destination_frame {
+        Some(frame) => {
+          player.movie.push_play_return();
+          player.next_frame = Some(frame);
+          Ok(DatumRef::Void)
+        }
+        None => Err(ScriptError::new("Unsupported or invalid frame label passed to play()".to_string())),
+      }
+    })
+  }
+
+  pub fn play_done() -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      match player.movie.pop_play_return() {
+        Some(frame) => player.next_frame = Some(frame),
+        None => warn!("play done called without a matching play"),
+      }
+      Ok(DatumRef::Void)
+    })
+  }
+
+  fn is_current_movie(player: &DirPlayer, movie_name: &str) -> bool {
+    let strip_extension = |name: &str| {
+      let name = name.rsplit(['/', '\\', ':']).next().unwrap_or(name);
+      name.rsplit_once('.').map_or(name, |(base, _)| base).to_lowercase()
+    };
+    strip_extension(movie_name) == strip_extension(&player.movie.file_name)
+  }
+
+  /// Navigates relative to the markers around the current frame, as `go loop`,
+  /// `go next` and `go previous` do.
+  pub fn go_marker(marker_offset: i32) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let frame = player.movie.score.get_marker_frame(player.movie.current_frame, marker_offset);
+      player.next_frame = Some(frame);
+      Ok(DatumRef::Void)
+    })
+  }
+
+  pub fn marker(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let marker_datum = player.get_datum(&args[0]);
+      let frame = match marker_datum {
+        Datum::String(label) => player.movie.score.find_label_frame(label).unwrap_or(0),
+        _ => {
+          let offset = marker_datum.int_value()?;
+          player.movie.score.get_marker_frame(player.movie.current_frame, offset)
+        }
+      };
+      Ok(player.alloc_datum(Datum::Int(frame as i32)))
+    })
+  }
+
+  /// Returns the frame of a label, or 0 when there is no such label.
+  pub fn label(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let label = player.get_datum(&args[0]).string_value()?;
+      let frame = player.movie.score.find_label_frame(&label).unwrap_or(0);
+      Ok(player.alloc_datum(Datum::Int(frame as i32)))
+    })
+  }
+
   pub fn puppet_sprite(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let sprite_number = player.get_datum(&args[0]).int_value()?;
@@ -93,6 +177,15 @@ impl MovieHandlers {
     })
   }
 
+  pub fn constrain(args: &Vec<DatumRef>, is_horizontal: bool) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let sprite_number = player.get_datum(&args[0]).int_value()?;
+      let value = player.get_datum(&args[1]).int_value()?;
+      let result = constrain_to_sprite(player, sprite_number as i16, value, is_horizontal);
+      Ok(player.alloc_datum(Datum::Int(result)))
+    })
+  }
+
   pub fn sprite(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let sprite_number = player.get_datum(&args[0]).int_value()?;
@@ -100,6 +193,14 @@ impl MovieHandlers {
     })
   }
 
+  pub async fn alert(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    let message = reserve_player_ref(|player| {
+      StringBytecodeHandler::get_datum_concat_value(player.get_datum(&args[0]), player)
+    })?;
+    player_alert(message).await?;
+    Ok(DatumRef::Void)
+  }
+
   pub async fn send_sprite(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     let (message, remaining_args, receivers) = reserve_player_mut(|player| {
       let sprite_num = player.get_datum(&args[0]).int_value().unwrap();
@@ -143,16 +244,55 @@ impl MovieHandlers {
     })
   }
 
+  fn find_external_param<'a>(player: &'a DirPlayer, key: &Datum) -> Result<Option<&'a (String, String)>, ScriptError> {
+    match key {
+      Datum::Int(index) => Ok(
+        (*index as usize).checked_sub(1).and_then(|index| player.external_params.get(index))
+      ),
+      _ => {
+        let name = key.string_value()?;
+        Ok(player.external_params.iter().find(|(key, _)| key.eq_ignore_ascii_case(&name)))
+      }
+    }
+  }
+
   pub fn external_param_value(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
-      let key = player.get_datum(&args[0]).string_value()?;
-      let value: String = player.external_params.get(&key)
-        .cloned()
-        .unwrap_or_default();
-      Ok(player.alloc_datum(Datum::String(value)))
+      let value = Self::find_external_param(player, player.get_datum(&args[0]))?
+        .map(|(_, value)| value.clone());
+      match value {
+        Some(value) => Ok(player.alloc_datum(Datum::String(value))),
+        None => Ok(DatumRef::Void),
+      }
     })
   }
 
+  pub fn external_param_name(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let name = Self::find_external_param(player, player.get_datum(&args[0]))?
+        .map(|(name, _)| name.clone());
+      match name {
+        Some(name) => Ok(player.alloc_datum(Datum::String(name))),
+        None => Ok(DatumRef::Void),
+      }
+    })
+  }
+
+  pub fn external_param_count(_: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let count = player.external_params.len() as i32;
+      Ok(player.alloc_datum(Datum::Int(count)))
+    })
+  }
+
+  pub fn external_event(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    let event = reserve_player_ref(|player| {
+      player.get_datum(&args[0]).string_value()
+    })?;
+    JsApi::dispatch_external_event(&event);
+    Ok(DatumRef::Void)
+  }
+
   pub fn stop_event(_: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     // TODO stop event
     Ok(DatumRef::Void)
@@ -166,7 +306,22 @@ impl MovieHandlers {
     Ok(DatumRef::Void)
   }
 
-  pub fn go_to_net_page(_: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+  pub fn go_to_net_page(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    let (url, target) = reserve_player_ref(|player| {
+      let url = player.get_datum(&args[0]).string_value()?;
+      let target = match args.get(1).map(|x| player.get_datum(x)) {
+        Some(target) if !target.is_void() => Some(target.string_value()?),
+        _ => None,
+      };
+      // Relative URLs are resolved against the movie's location
+      let url = player.net_manager.base_path
+        .as_ref()
+        .and_then(|base_path| base_path.join(&url).ok())
+        .map(|x| x.to_string())
+        .unwrap_or(url);
+      Ok((url, target))
+    })?;
+    JsApi::dispatch_go_to_net_page(&url, target.as_deref().unwrap_or(""));
     Ok(DatumRef::Void)
   }
 
@@ -179,13 +334,96 @@ impl MovieHandlers {
     })
   }
 
-  pub fn update_stage(_: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
-    // TODO: re-render
-    // The updateStage() method redraws sprites, performs transitions, plays sounds, sends a prepareFrame message
-    // (affecting movie and behavior scripts), and sends a stepFrame message (which affects actorList)
+  /// Keeps the event a mouseDownScript or mouseUpScript runs for from going on to sprites.
+  pub fn dont_pass_event(_: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| player.dont_pass_event = true);
+    Ok(DatumRef::Void)
+  }
+
+  /// Presents the stage as the script left it, then waits for the next animation frame so
+  /// the browser paints it. The player stays busy meanwhile, so events that come in wait
+  /// for the script to finish, and the movie clock keeps its own time.
+  pub async fn update_stage(_: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    // TODO: transitions, sounds, and the prepareFrame and stepFrame messages
+    let is_stage_active = reserve_player_mut(|player| {
+      // A window movie being told to holds the place of the stage movie
+      let is_stage_active = player.window_manager.active_window.is_none();
+      if is_stage_active {
+        draw_stage_now(player);
+      }
+      is_stage_active
+    });
+    if is_stage_active {
+      player_suspend_handler(next_animation_frame()).await;
+    }
+    Ok(DatumRef::Void)
+  }
+
+  /// Runs a string of Lingo statements, like `do "global gScore" & RETURN & "gScore = 0"`.
+  pub async fn do_statements(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    let source = reserve_player_ref(|player| player.get_datum(&args[0]).string_value())?;
+    player_do(&source).await?;
     Ok(DatumRef::Void)
   }
 
+  /// Like `do`, but the statements run once the handlers that are running now return.
+  pub fn do_later(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    let source = reserve_player_ref(|player| player.get_datum(&args[0]).string_value())?;
+    player_dispatch(PlayerVMCommand::DoLater(source));
+    Ok(DatumRef::Void)
+  }
+
+  /// Transitions aren't animated yet, but like in Director they redraw the whole stage
+  /// when the playhead moves on, which erases the trails left by sprites.
+  pub fn puppet_transition(_: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      player.transition_pending = true;
+    });
+    Ok(DatumRef::Void)
+  }
+
+  /// Zooms a rectangle from the first sprite to the second, each step lasting the delay
+  /// in ticks. The movie keeps playing while it's drawn.
+  pub fn zoom_box(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let start_sprite = player.get_datum(&args[0]).int_value()?;
+      let end_sprite = player.get_datum(&args[1]).int_value()?;
+      let delay_ticks = match args.get(2) {
+        Some(delay) => player.get_datum(delay).int_value()?.max(1),
+        None => 1,
+      };
+      let from = get_concrete_sprite_rect(player, player.movie.score.get_sprite(start_sprite as i16)
+        .ok_or_else(|| ScriptError::new(format!("Sprite {} not found", start_sprite)))?);
+      let to = get_concrete_sprite_rect(player, player.movie.score.get_sprite(end_sprite as i16)
+        .ok_or_else(|| ScriptError::new(format!("Sprite {} not found", end_sprite)))?);
+      player.zoom_box = Some(ZoomBox {
+        from,
+        to,
+        start_ms: player.clock.elapsed_ms(),
+        step_ms: delay_ticks as i64 * 1000 / 60,
+      });
+      Ok(DatumRef::Void)
+    })
+  }
+
+  /// Fades the stage in from black, or out to black, over the given milliseconds.
+  pub fn fade_stage(args: &[DatumRef], fade_in: bool) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let duration_ms = match args.first() {
+        Some(duration) => player.get_datum(duration).int_value()?,
+        None => 1000,
+      };
+      let now_ms = player.clock.elapsed_ms();
+      let (from, to) = if fade_in {
+        (0, 100)
+      } else {
+        (player.stage_fade.as_ref().map_or(100, |fade| fade.brightness_at(now_ms)), 0)
+      };
+      player.stage_fade = Some(StageFade { from, to, start_ms: now_ms, duration_ms: duration_ms as i64 });
+      Ok(DatumRef::Void)
+    })
+  }
+
   pub fn rollover(_: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let sprite = get_sprite_at(player, player.mouse_loc.0, player.mouse_loc.1, false);
@@ -1,4 +1,6 @@
-use crate::{director::lingo::datum::{datum_bool, Datum}, player::{reserve_player_mut, DatumRef, ScriptError}};
+use crate::{director::lingo::datum::{datum_bool, Datum}, player::{reserve_player_mut, search_path::sync_search_paths, streaming::is_member_media_ready, DatumRef, ScriptError}};
+
+use super::movie::MovieHandlers;
 
 
 pub struct NetHandlers { }
@@ -14,6 +16,7 @@ impl NetHandlers {
       };
       let task_state = player.net_manager.get_task_state(task_id);
       let is_done = task_state.is_some_and(|state| state.is_done());
+      player.net_manager.mark_task_read(task_id);
       Ok(player.alloc_datum(datum_bool(is_done)))
     })
   }
@@ -21,6 +24,7 @@ impl NetHandlers {
   pub fn preload_net_thing(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let url = player.get_datum(&args[0]).string_value()?;
+      sync_search_paths(player);
       let task_id = player.net_manager.preload_net_thing(url);
       Ok(player.alloc_datum(Datum::Int(task_id as i32)))
     })
@@ -29,43 +33,67 @@ impl NetHandlers {
   pub fn get_net_text(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let url = player.get_datum(&args[0]).string_value()?;
-      let task_id = player.net_manager.preload_net_thing(url);
-      // TODO should the task be tagged as a text task?
+      let task_id = player.net_manager.get_net_text(url);
       Ok(player.alloc_datum(Datum::Int(task_id as i32)))
     })
   }
 
   pub fn get_stream_status(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
-      let (state, error, url, is_ok) = {
+      let (state, error, url, bytes_so_far, bytes_total) = {
         let task_id = player.get_datum(&args[0]).int_value()? as u32;
-        let task = player.net_manager.get_task(task_id).unwrap();
+        let task = player.net_manager.get_task(task_id)
+          .ok_or_else(|| ScriptError::new(format!("No net task {task_id}")))?;
         let task_state = &player.net_manager.get_task_state(Some(task_id)).unwrap();
-        let (state, error) = if task_state.is_done() && task_state.result.as_ref().unwrap().is_ok() {
-          ("Complete", "OK")
-        } else if task_state.is_done() && task_state.result.as_ref().unwrap().is_err() {
-          ("Complete", "Task failed")
-        } else {
-          ("InProgress", "")
+        // The error is the netError code when the task failed
+        let (state, error) = match &task_state.result {
+          Some(Ok(_)) => ("Complete", Datum::String("OK".to_owned())),
+          Some(Err(code)) => ("Complete", Datum::Int(*code)),
+          None => ("InProgress", Datum::String("".to_owned())),
         };
-        let is_ok = task_state.is_done() && task_state.result.as_ref().unwrap().is_ok();
-        (state.to_owned(), error.to_owned(), task.url.to_owned(), is_ok)
+        let (bytes_so_far, bytes_total) = player.net_manager.get_task_progress(task_id);
+        (state.to_owned(), error, task.url.to_owned(), bytes_so_far, bytes_total.unwrap_or(0))
       };
       let result_map = Datum::PropList(vec![
         (player.alloc_datum(Datum::String("URL".to_owned())), player.alloc_datum(Datum::String(url))),
         (player.alloc_datum(Datum::String("state".to_owned())), player.alloc_datum(Datum::String(state))),
-        (player.alloc_datum(Datum::String("bytesSoFar".to_owned())), player.alloc_datum(Datum::Int(if is_ok { 100 } else { 0 }))),
-        (player.alloc_datum(Datum::String("bytesTotal".to_owned())), player.alloc_datum(Datum::Int(100))),
-        (player.alloc_datum(Datum::String("error".to_owned())), player.alloc_datum(Datum::String(error))),
+        (player.alloc_datum(Datum::String("bytesSoFar".to_owned())), player.alloc_datum(Datum::Int(bytes_so_far as i32))),
+        (player.alloc_datum(Datum::String("bytesTotal".to_owned())), player.alloc_datum(Datum::Int(bytes_total as i32))),
+        (player.alloc_datum(Datum::String("error".to_owned())), player.alloc_datum(error)),
       ], false);
       Ok(player.alloc_datum(result_map))
     })
   }
 
+  /// Whether the media of every member on a frame, or on a range of frames, has been
+  /// downloaded. Without arguments, checks the whole movie.
+  pub fn frame_ready(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let mut frames = vec![];
+      for arg in args.iter().take(2) {
+        match MovieHandlers::resolve_frame(player, player.get_datum(arg))? {
+          Some(frame) => frames.push(frame),
+          None => return Err(ScriptError::new("Invalid frame passed to frameReady".to_string())),
+        }
+      }
+      let (first_frame, last_frame) = match frames.as_slice() {
+        [] => (1, player.movie.score.get_last_frame()),
+        [frame] => (*frame, *frame),
+        [first, last, ..] => (*first, *last),
+      };
+      let is_ready = player.movie.score.get_members_in_frames(first_frame, last_frame)
+        .iter()
+        .all(|member_ref| is_member_media_ready(player, member_ref));
+      Ok(player.alloc_datum(datum_bool(is_ready)))
+    })
+  }
+
   pub fn net_error(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let task_id = args.get(0).map(|datum_ref| player.get_datum(datum_ref).int_value().unwrap() as u32);
-      let task_state = player.net_manager.get_task_state(task_id).unwrap();
+      let task_state = player.net_manager.get_task_state(task_id)
+        .ok_or_else(|| ScriptError::new("No net task for netError".to_string()))?;
+      player.net_manager.mark_task_read(task_id);
       let is_ok = task_state.is_done() && task_state.result.as_ref().unwrap().is_ok();
       let error = if is_ok {
         Datum::String("OK".to_owned())
@@ -81,7 +109,9 @@ impl NetHandlers {
   pub fn net_text_result(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let task_id = args.get(0).map(|datum_ref| player.get_datum(datum_ref).int_value().unwrap() as u32);
-      let task_state = player.net_manager.get_task_state(task_id).unwrap();
+      let task_state = player.net_manager.get_task_state(task_id)
+        .ok_or_else(|| ScriptError::new("No net task for netTextResult".to_string()))?;
+      player.net_manager.mark_task_read(task_id);
       let is_ok = task_state.is_done() && task_state.result.as_ref().unwrap().is_ok();
       let text = if is_ok {
         let text = task_state.result.as_ref().unwrap().as_ref().unwrap();
@@ -1,4 +1,4 @@
-use crate::{director::lingo::datum::Datum, player::{datum_formatting::format_concrete_datum, reserve_player_mut, DatumRef, ScriptError}};
+use crate::{director::lingo::datum::Datum, player::{compare::string_offset, datum_formatting::{float_to_string, format_concrete_datum}, reserve_player_mut, DatumRef, ScriptError}};
 
 pub struct StringHandlers {}
 
@@ -13,8 +13,8 @@ impl StringHandlers {
     reserve_player_mut(|player| {
       let str_to_find = player.get_datum(&args[0]).string_value()?;
       let find_in = player.get_datum(&args[1]).string_value()?;
-      let result = find_in.find(&str_to_find).map(|x| x as i32).unwrap_or(-1);
-      Ok(player.alloc_datum(Datum::Int(result + 1)))
+      let result = string_offset(&str_to_find, &find_in).map_or(0, |index| index as i32 + 1);
+      Ok(player.alloc_datum(Datum::Int(result)))
     })
   }
 
@@ -39,6 +39,8 @@ impl StringHandlers {
         Datum::String(obj.string_value()?.to_string())
       } else if obj.is_void() {
         Datum::String("".to_string())
+      } else if let Datum::Float(f) = obj {
+        Datum::String(float_to_string(*f, player))
       } else {
         Datum::String(format_concrete_datum(obj, player))
       };
@@ -49,9 +51,10 @@ impl StringHandlers {
   pub fn chars(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let string = player.get_datum(&args[0]).string_value()?;
-      let start = player.get_datum(&args[1]).int_value()? - 1;
-      let end: i32 = player.get_datum(&args[2]).int_value()?;
-      let substr = string.chars().skip(start as usize).take((end - start) as usize).collect::<String>();
+      // Positions are clamped to the string, and an end before the start gives nothing
+      let start = player.get_datum(&args[1]).int_value()?.max(1) as usize;
+      let end = player.get_datum(&args[2]).int_value()?.max(0) as usize;
+      let substr = string.chars().skip(start - 1).take((end + 1).saturating_sub(start)).collect::<String>();
 
       Ok(player.alloc_datum(Datum::String(substr)))
     })
@@ -60,7 +63,8 @@ impl StringHandlers {
   pub fn char_to_num(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let str_value = player.get_datum(&args[0]).string_value()?;
-      let num = str_value.chars().next().map(|c| c as i32).unwrap_or(0);
+      let encoding = player.movie.text_encoding();
+      let num = str_value.chars().next().map_or(0, |c| encoding.char_code(c));
       Ok(player.alloc_datum(Datum::Int(num)))
     })
   }
@@ -68,7 +72,7 @@ impl StringHandlers {
   pub fn num_to_char(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let num = player.get_datum(&args[0]).int_value()?;
-      let char_value = std::char::from_u32(num as u32).unwrap().to_string();
+      let char_value = player.movie.text_encoding().code_char(num).map(String::from).unwrap_or_default();
       Ok(player.alloc_datum(Datum::String(char_value)))
     })
   }
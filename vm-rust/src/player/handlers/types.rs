@@ -1,32 +1,52 @@
-use itertools::Itertools;
+use crate::{director::lingo::datum::{datum_bool, Datum, DatumType}, player::{allocator::ScriptInstanceAllocatorTrait, bitmap::bitmap::{get_system_default_palette, Bitmap, BuiltInPalette, PaletteRef}, compare::sort_datums, date::LingoDate, datum_formatting::format_datum, eval::eval_lingo, geometry::IntRect, player_call_script_handler, player_handle_scope_return, reserve_player_mut, reserve_player_ref, sprite::ColorRef, cursor::cursor_ref_from_datum, xtra::manager::{create_xtra_instance, is_xtra_registered}, DatumRef, DirPlayer, ScriptError}};
 
-use crate::{director::lingo::datum::{datum_bool, Datum, DatumType}, player::{allocator::ScriptInstanceAllocatorTrait, bitmap::bitmap::{get_system_default_palette, Bitmap, BuiltInPalette, PaletteRef}, compare::sort_datums, datum_formatting::format_datum, eval::eval_lingo, geometry::IntRect, reserve_player_mut, reserve_player_ref, sprite::{ColorRef, CursorRef}, xtra::manager::{create_xtra_instance, is_xtra_registered}, DatumRef, DirPlayer, ScriptError}};
-
-use super::datum_handlers::{list_handlers::ListDatumHandlers, player_call_datum_handler, prop_list::{PropListDatumHandlers, PropListUtils}, rect::RectUtils};
+use super::datum_handlers::{color::ColorUtils, list_handlers::ListDatumHandlers, player_call_datum_handler, prop_list::{PropListDatumHandlers, PropListUtils}, rect::RectUtils, script::ScriptDatumHandlers, script_instance::{ScriptInstanceDatumHandlers, ScriptInstanceUtils}};
 
 
 pub struct TypeHandlers {}
 pub struct TypeUtils {}
 
 impl TypeUtils {
+  /// Coerces an argument of a bit operation. Floats are rounded like integer() does,
+  /// and strings are parsed as numbers.
+  pub fn bit_operand(datum: &Datum) -> Result<i32, ScriptError> {
+    match datum {
+      Datum::Float(f) => Ok(f.round() as i32),
+      Datum::String(s) => Ok(s.trim().parse::<f32>().map_or(0, |f| f.round() as i32)),
+      _ => datum.int_value(),
+    }
+  }
+
+  /// The ilk of a datum followed by the broader types `ilk(value, #type)` also accepts.
   pub fn get_datum_ilks(datum: &Datum) -> Result<Vec<&str>, ScriptError> {
     match datum {
       Datum::List(..) => Ok(vec!["list", "linearlist"]),
-      Datum::Int(..) => Ok(vec!["integer"]),
-      Datum::String(..) => Ok(vec!["string"]),
+      Datum::Int(..) => Ok(vec!["integer", "number"]),
+      Datum::Float(..) => Ok(vec!["float", "number"]),
+      Datum::String(..) | Datum::StringChunk(..) => Ok(vec!["string"]),
       Datum::Symbol(..) => Ok(vec!["symbol"]),
       Datum::Void | Datum::Null => Ok(vec!["void"]),
       Datum::PropList(..) => Ok(vec!["proplist", "list"]),
       Datum::ScriptInstanceRef(..) => Ok(vec!["instance"]),
       Datum::ScriptRef(..) => Ok(vec!["script"]),
+      Datum::CastLib(..) => Ok(vec!["castlib"]),
       Datum::CastMember(member_ref) => Ok(vec![if member_ref.is_valid() { "member" } else { "void" }]),
       Datum::ColorRef(..) => Ok(vec!["color"]),
-      Datum::TimeoutRef(..) => Ok(vec!["timeout"]), // TODO verify this
+      Datum::TimeoutRef(..) => Ok(vec!["timeout"]),
+      Datum::CursorRef(..) => Ok(vec!["cursor"]),
       Datum::BitmapRef(..) => Ok(vec!["image"]),
+      Datum::Matte(..) => Ok(vec!["mask"]),
       Datum::IntRect(..) => Ok(vec!["rect"]),
       Datum::IntPoint(..) => Ok(vec!["point"]),
       Datum::SpriteRef(..) => Ok(vec!["sprite"]),
       Datum::PaletteRef(..) => Ok(vec!["palette"]),
+      Datum::Stage | Datum::WindowRef(..) => Ok(vec!["window"]),
+      Datum::Xtra(..) => Ok(vec!["xtra"]),
+      Datum::XtraInstance(..) => Ok(vec!["instance"]),
+      Datum::SoundRef(..) => Ok(vec!["sound"]),
+      Datum::PlayerRef => Ok(vec!["player"]),
+      Datum::MovieRef => Ok(vec!["movie"]),
+      Datum::Date(..) => Ok(vec!["date"]),
       _ => Err(ScriptError::new(format!("Getting ilk for unknown type: {}", datum.type_str())))?,
     }
   }
@@ -36,9 +56,20 @@ impl TypeUtils {
   }
 
   fn is_datum_ilk(datum: &Datum, ilk: &str) -> Result<bool, ScriptError> {
+    if ilk.eq_ignore_ascii_case("object") {
+      return Ok(Self::is_object(datum));
+    }
     Ok(Self::get_datum_ilks(datum)?.iter().any(|x| x.eq_ignore_ascii_case(ilk)))
   }
 
+  /// Everything except void and the scalar types counts as an object.
+  pub fn is_object(datum: &Datum) -> bool {
+    !matches!(
+      datum,
+      Datum::Void | Datum::Null | Datum::Int(_) | Datum::Float(_) | Datum::Symbol(_) | Datum::String(_) | Datum::StringChunk(..)
+    )
+  }
+
   pub fn get_sub_prop(datum_ref: &DatumRef, prop_key_ref: &DatumRef, player: &mut DirPlayer) -> Result<DatumRef, ScriptError> {
     let datum = player.get_datum(datum_ref);
     let formatted_key = format_datum(prop_key_ref, player);
@@ -102,15 +133,7 @@ impl TypeUtils {
 impl TypeHandlers {
   pub fn objectp(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
-      let obj = player.get_datum(&args[0]);
-      let is_object = match obj {
-        Datum::Void => false,
-        Datum::Float(_) => false,
-        Datum::Int(_) => false,
-        Datum::Symbol(_) => false,
-        Datum::String(_) => false,
-        _ => true,
-      };
+      let is_object = TypeUtils::is_object(player.get_datum(&args[0]));
       Ok(player.alloc_datum(datum_bool(is_object)))
     })
   }
@@ -119,7 +142,7 @@ impl TypeHandlers {
     reserve_player_mut(|player| {
       let obj = player.get_datum(&args[0]);
       let is_void = match obj {
-        Datum::Void => true,
+        Datum::Void | Datum::Null => true,
         _ => false,
       };
       Ok(player.alloc_datum(datum_bool(is_void)))
@@ -187,7 +210,10 @@ impl TypeHandlers {
     reserve_player_mut(|player| {
       let expr = player.get_datum(&args[0]);
       match expr {
-        Datum::String(s) => eval_lingo(s.to_owned(), player),
+        Datum::String(s) => {
+          let expr = player.movie.locale.normalize_number(s).unwrap_or_else(|| s.to_owned());
+          eval_lingo(expr, player)
+        }
         _ => Ok(args[0].clone()),
       }
     })
@@ -298,7 +324,7 @@ impl TypeHandlers {
       let result = if value.is_number() {
         Ok(Datum::Float(value.to_float()?))
       } else if value.is_string() {
-        if let Ok(float_value) = value.string_value()?.parse::<f32>() {
+        if let Some(float_value) = player.movie.locale.parse_float(&value.string_value()?) {
           Ok(Datum::Float(float_value))
         } else {
           Ok(value.to_owned())
@@ -370,19 +396,8 @@ impl TypeHandlers {
   pub fn cursor(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       if args.len() == 1 {
-        let arg = player.get_datum(&args[0]);
-        if arg.is_int() {
-          player.cursor = CursorRef::System(arg.int_value()?);
-          Ok(DatumRef::Void)
-        } else if arg.is_list() {
-          let list = arg.to_list()?;
-          // TODO why not: let members = list.clone().iter().map(|x| player.get_datum(x).int_value()).collect_vec();
-          let members = list.clone().iter().map(|x| x.unwrap() as i32).collect_vec();
-          player.cursor = CursorRef::Member(members);
-          Ok(DatumRef::Void)
-        } else {
-          Err(ScriptError::new("Invalid argument for cursor".to_string()))
-        }
+        player.cursor = cursor_ref_from_datum(player, player.get_datum(&args[0]))?;
+        Ok(DatumRef::Void)
       } else if args.len() == 2 {
         Err(ScriptError::new("Cursor call not implemented".to_string()))
       } else {
@@ -435,20 +450,30 @@ impl TypeHandlers {
     })
   }
 
+  /// Referring to a window creates it, so it shows up in the windowList before it's opened.
+  pub fn window(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let name = match args.first() {
+        Some(name_ref) => player.get_datum(name_ref).string_value()?,
+        None => return Err(ScriptError::new("window requires a name".to_string())),
+      };
+      let name = player.window_manager.get_or_create(&name).name.to_owned();
+      Ok(player.alloc_datum(Datum::WindowRef(name)))
+    })
+  }
+
+  /// rgb(r, g, b) or rgb("#RRGGBB"). Components are clamped to 0-255.
   pub fn rgb(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       if args.len() == 3 {
-        let r = player.get_datum(&args[0]).int_value()? as u8;
-        let g = player.get_datum(&args[1]).int_value()? as u8;
-        let b = player.get_datum(&args[2]).int_value()? as u8;
+        let r = ColorUtils::clamp_component(player.get_datum(&args[0]).int_value()?);
+        let g = ColorUtils::clamp_component(player.get_datum(&args[1]).int_value()?);
+        let b = ColorUtils::clamp_component(player.get_datum(&args[2]).int_value()?);
         Ok(player.alloc_datum(Datum::ColorRef(ColorRef::Rgb(r, g, b))))
       } else {
         let first_arg = player.get_datum(&args[0]);
         if first_arg.is_string() {
-          let hex_str = first_arg.string_value()?.replace("#", "");
-          let r = u8::from_str_radix(&hex_str[0..2], 16).unwrap();
-          let g = u8::from_str_radix(&hex_str[2..4], 16).unwrap();
-          let b = u8::from_str_radix(&hex_str[4..6], 16).unwrap();
+          let (r, g, b) = ColorUtils::parse_hex_string(&first_arg.string_value()?)?;
           Ok(player.alloc_datum(Datum::ColorRef(ColorRef::Rgb(r, g, b))))
         } else {
           Err(ScriptError::new("Invalid number of arguments for rgb".to_string()))
@@ -457,10 +482,29 @@ impl TypeHandlers {
     })
   }
 
+  /// date("YYYYMMDD"), date(YYYYMMDD) or date(year, month, day).
+  pub fn date(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let date = if args.len() == 3 {
+        let year = player.get_datum(&args[0]).int_value()?;
+        let month = player.get_datum(&args[1]).int_value()?;
+        let day = player.get_datum(&args[2]).int_value()?;
+        LingoDate::from_ymd(year, month, day)?
+      } else {
+        match player.get_datum(&args[0]) {
+          Datum::Int(value) => LingoDate::parse(&value.to_string())?,
+          Datum::Date(date) => *date,
+          value => LingoDate::parse(&value.string_value()?)?,
+        }
+      };
+      Ok(player.alloc_datum(Datum::Date(date)))
+    })
+  }
+
   pub fn palette_index(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let color = player.get_datum(&args[0]).int_value()?;
-      Ok(player.alloc_datum(Datum::ColorRef(ColorRef::PaletteIndex(color as u8))))
+      Ok(player.alloc_datum(Datum::ColorRef(ColorRef::PaletteIndex(ColorUtils::clamp_component(color)))))
     })
   }
 
@@ -534,33 +578,22 @@ impl TypeHandlers {
       if args.len() != 2 {
         return Err(ScriptError::new("Bitwise XOR requires 2 arguments".to_string()));
       }
-      let left = player.get_datum(&args[0]).int_value()?;
-      let right = player.get_datum(&args[1]).int_value()?;
+      let left = TypeUtils::bit_operand(player.get_datum(&args[0]))?;
+      let right = TypeUtils::bit_operand(player.get_datum(&args[1]))?;
 
       Ok(player.alloc_datum(Datum::Int(left ^ right)))
     })
   }
 
+  /// power() always returns a float, even for integer arguments.
   pub fn power(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       if args.len() != 2 {
         return Err(ScriptError::new("Power requires 2 arguments".to_string()));
       }
-      let base = player.get_datum(&args[0]);
-      let exponent = player.get_datum(&args[1]);
-
-      match (base, exponent) {
-        (Datum::Int(base), Datum::Int(exponent)) => {
-          Ok(player.alloc_datum(Datum::Int(base.pow(*exponent as u32))))
-        },
-        (Datum::Float(base), Datum::Float(exponent)) => {
-          Ok(player.alloc_datum(Datum::Float(base.powf(*exponent))))
-        },
-        (Datum::Float(base), Datum::Int(exponent)) => {
-          Ok(player.alloc_datum(Datum::Float(base.powf(*exponent as f32))))
-        },
-        _ => Err(ScriptError::new("Power requires two numbers".to_string())),
-      }
+      let base = player.get_datum(&args[0]).to_float()?;
+      let exponent = player.get_datum(&args[1]).to_float()?;
+      Ok(player.alloc_datum(Datum::Float(base.powf(exponent))))
     })
   }
 
@@ -590,6 +623,8 @@ impl TypeHandlers {
       DatumType::PropList => {
         PropListDatumHandlers::get_a_prop(datum_ref, &vec![args.get(1).unwrap().clone()])
       },
+      DatumType::ScriptInstanceRef => ScriptInstanceDatumHandlers::get_a_prop(datum_ref, &args[1..].to_vec()),
+      DatumType::ScriptRef => ScriptDatumHandlers::get_a_prop(datum_ref, &args[1..]),
       _ => Err(ScriptError::new(format!("Cannot getaProp prop of type: {}", datum_type.type_str()))),
     }
   }
@@ -684,6 +719,33 @@ impl TypeHandlers {
     })
   }
 
+  /// atan(x) returns the arctangent in radians; atan(y, x) returns the angle of the
+  /// point (x, y), taking the signs of both into account.
+  pub fn atan(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let result = match args.len() {
+        1 => player.get_datum(&args[0]).to_float()?.atan(),
+        2 => {
+          let y = player.get_datum(&args[0]).to_float()?;
+          let x = player.get_datum(&args[1]).to_float()?;
+          y.atan2(x)
+        }
+        _ => return Err(ScriptError::new("atan requires 1 or 2 arguments".to_string())),
+      };
+      Ok(player.alloc_datum(Datum::Float(result)))
+    })
+  }
+
+  pub fn sqrt(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let value = player.get_datum(&args[0]).to_float()?;
+      if value < 0.0 {
+        return Err(ScriptError::new(format!("Cannot get square root of negative number {}", value)));
+      }
+      Ok(player.alloc_datum(Datum::Float(value.sqrt())))
+    })
+  }
+
   pub fn sound(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
     reserve_player_mut(|player| {
       let channel_num = player.get_datum(&args[0]).int_value()? as u16;
@@ -691,8 +753,21 @@ impl TypeHandlers {
     })
   }
 
+  pub fn sound_busy(args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+    reserve_player_mut(|player| {
+      let channel_num = player.get_datum(&args[0]).int_value()? as u16;
+      player.sound_manager.update(&player.movie.cast_manager, player.clock.elapsed_ms());
+      let is_busy = player.sound_manager.get_channel(channel_num).is_some_and(|channel| channel.is_busy());
+      Ok(player.alloc_datum(datum_bool(is_busy)))
+    })
+  }
+
+  /// Runs the handler found on the ancestors of each receiver, with the receiver itself as me.
   pub async fn call_ancestor(args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
-    let (ref_list, handler_name, args) = reserve_player_mut(|player| {
+    if args.len() < 2 {
+      return Err(ScriptError::new("callAncestor requires a handler name and a receiver".to_string()));
+    }
+    let (calls, args) = reserve_player_mut(|player| {
       let handler_name = player.get_datum(&args[0]).string_value()?;
 
       let list_or_script_instance = player.get_datum(&args[1]);
@@ -700,7 +775,7 @@ impl TypeHandlers {
         Datum::List(_, list, _) => {
           list.to_owned()
         }
-        Datum::ScriptInstanceRef(s) => {
+        Datum::ScriptInstanceRef(_) => {
           vec![args[1].clone()]
         }
         _ => {
@@ -708,19 +783,24 @@ impl TypeHandlers {
         }
       };
 
-      let mut ref_list = vec![];
+      let mut calls = vec![];
       for instance_ref in instance_list {
-        let instance_ref = player.get_datum(&instance_ref).to_script_instance_ref()?;
-        let instance = player.allocator.get_script_instance(instance_ref);
-        let ancestor = instance.ancestor.as_ref().unwrap();
-        ref_list.push(player.alloc_datum(Datum::ScriptInstanceRef(ancestor.clone())));
+        let instance_ref = player.get_datum(&instance_ref).to_script_instance_ref()?.clone();
+        let instance = player.allocator.get_script_instance(&instance_ref);
+        let ancestor = instance.ancestor.clone()
+          .ok_or_else(|| ScriptError::new(format!("Script instance {} has no ancestor", instance_ref)))?;
+        let handler = ScriptInstanceUtils::get_script_instance_handler(&handler_name, &ancestor, player)?
+          .ok_or_else(|| ScriptError::new(format!("Handler {} not found on the ancestors of script instance {}", handler_name, instance_ref)))?;
+        calls.push((instance_ref, handler));
       }
       let args = args[2..].to_vec();
-      Ok((ref_list, handler_name, args))
+      Ok((calls, args))
     })?;
     let mut result = DatumRef::Void;
-    for ref_item in ref_list {
-      result = player_call_datum_handler(&ref_item, &handler_name.to_string(), &args).await?;
+    for (instance_ref, handler) in calls {
+      let scope = player_call_script_handler(Some(instance_ref), handler, &args).await?;
+      player_handle_scope_return(&scope);
+      result = scope.return_value;
     }
     Ok(result)
   }
@@ -0,0 +1,113 @@
+use std::{cell::RefCell, collections::HashMap};
+
+/// Decides which key presses the player consumes and which are left to the browser.
+/// Consumed keys don't scroll the page or trigger browser shortcuts. Every key is
+/// still sent to the movie either way.
+///
+/// The policy lives on the page thread rather than in the player, since the page has to
+/// decide synchronously in its keydown handler, even when the player runs in a worker.
+pub struct KeyCapturePolicy {
+    /// Arrow keys, page up/down, home and end, which scroll the page.
+    pub capture_navigation: bool,
+    pub capture_space: bool,
+    /// Combinations with ctrl or meta, which are mostly browser shortcuts.
+    pub capture_shortcuts: bool,
+    pub capture_function_keys: bool,
+    /// Per-combination overrides, keyed by normalized combination, e.g. `ctrl+s`.
+    pub overrides: HashMap<String, bool>,
+}
+
+pub struct KeyPress<'a> {
+    pub key: &'a str,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+impl KeyPress<'_> {
+    /// Modifiers in a fixed order followed by the lowercase key name, e.g. `ctrl+shift+arrowup`.
+    pub fn combination(&self) -> String {
+        let mut combination = String::new();
+        for (is_down, name) in [(self.ctrl, "ctrl"), (self.alt, "alt"), (self.shift, "shift"), (self.meta, "meta")] {
+            if is_down {
+                combination.push_str(name);
+                combination.push('+');
+            }
+        }
+        let key = if self.key == " " { "space".to_owned() } else { self.key.to_lowercase() };
+        combination.push_str(&key);
+        combination
+    }
+}
+
+impl KeyCapturePolicy {
+    pub fn new() -> KeyCapturePolicy {
+        KeyCapturePolicy {
+            capture_navigation: true,
+            capture_space: true,
+            capture_shortcuts: false,
+            capture_function_keys: false,
+            overrides: HashMap::new(),
+        }
+    }
+
+    pub fn set_category(&mut self, category: &str, captured: bool) -> Result<(), String> {
+        match category {
+            "navigation" => self.capture_navigation = captured,
+            "space" => self.capture_space = captured,
+            "shortcuts" => self.capture_shortcuts = captured,
+            "functionKeys" => self.capture_function_keys = captured,
+            _ => return Err(format!("Unknown key capture category {}", category)),
+        }
+        Ok(())
+    }
+
+    /// Accepts modifiers in any order and case, e.g. `Shift+Ctrl+S`.
+    pub fn set_combination(&mut self, combination: &str, captured: bool) {
+        let parts = combination.split('+').collect::<Vec<_>>();
+        let (key, modifiers) = parts.split_last().unwrap();
+        let has_modifier = |name: &str| modifiers.iter().any(|modifier| modifier.eq_ignore_ascii_case(name));
+        let key_press = KeyPress {
+            key,
+            ctrl: has_modifier("ctrl"),
+            alt: has_modifier("alt"),
+            shift: has_modifier("shift"),
+            meta: has_modifier("meta"),
+        };
+        self.overrides.insert(key_press.combination(), captured);
+    }
+
+    pub fn should_capture(&self, key_press: &KeyPress) -> bool {
+        if let Some(captured) = self.overrides.get(&key_press.combination()) {
+            return *captured;
+        }
+        if key_press.ctrl || key_press.meta {
+            return self.capture_shortcuts;
+        }
+        match key_press.key {
+            "ArrowUp" | "ArrowDown" | "ArrowLeft" | "ArrowRight" | "PageUp" | "PageDown" | "Home" | "End" => {
+                self.capture_navigation
+            }
+            " " | "Spacebar" => self.capture_space,
+            key if is_function_key(key) => self.capture_function_keys,
+            // Typing into the movie shouldn't tab out of the stage or navigate back
+            _ => true,
+        }
+    }
+}
+
+fn is_function_key(key: &str) -> bool {
+    key.len() > 1 && key.starts_with('F') && key[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+thread_local! {
+    static KEY_CAPTURE_POLICY: RefCell<KeyCapturePolicy> = RefCell::new(KeyCapturePolicy::new());
+}
+
+pub fn with_key_capture_policy<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut KeyCapturePolicy) -> R,
+{
+    KEY_CAPTURE_POLICY.with_borrow_mut(f)
+}
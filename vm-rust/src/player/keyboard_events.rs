@@ -1,24 +1,35 @@
-use super::{cast_member::CastMemberType, events::player_dispatch_targeted_event, player_is_playing, reserve_player_mut, DatumRef, DirPlayer, ScriptError};
+use super::{cast_member::CastMemberType, events::player_dispatch_targeted_event, font::layout::TextLayout, player_is_playing, reserve_player_mut, DatumRef, DirPlayer, ScriptError};
 
+fn is_editable_field_sprite(player: &DirPlayer, sprite_id: i16) -> bool {
+    let sprite = match player.movie.score.get_sprite(sprite_id) {
+        Some(sprite) => sprite,
+        None => return false,
+    };
+    let member = sprite.member.as_ref().and_then(|x| player.movie.cast_manager.find_member_by_ref(x));
+    match member.map(|x| &x.member_type) {
+        Some(CastMemberType::Field(field)) => field.editable || sprite.editable,
+        _ => false,
+    }
+}
+
+/// The next editable field after a sprite in channel order, wrapping around to the first one.
 fn get_next_focus_sprite_id(player: &DirPlayer, after: i16) -> i16 {
-    for sprite_id in after + 1..=player.movie.score.get_channel_count() as i16 {
-        let sprite = player.movie.score.get_sprite(sprite_id);
-        let member_ref = sprite.and_then(|x| x.member.clone());
-        let member = member_ref.and_then(|x| player.movie.cast_manager.find_member_by_ref(&x));
-        let field = member.and_then(|x| match &x.member_type {
-            CastMemberType::Field(field) => Some(field),
-            _ => None,
-        });
+    let channel_count = player.movie.score.get_channel_count() as i16;
+    (after + 1..=channel_count)
+        .chain(1..=after)
+        .find(|sprite_id| is_editable_field_sprite(player, *sprite_id))
+        .unwrap_or(-1)
+}
 
-        if field.is_none() {
-            continue;
-        }
-        let field = field.unwrap();
-        if field.editable {
-            return sprite_id;
+/// Whether text still fits in the box of a #limit field, which takes no more input once full.
+fn fits_field_box(player: &DirPlayer, member_type: &CastMemberType) -> bool {
+    match member_type {
+        CastMemberType::Field(field) if field.box_type == "limit" && field.height > 0 => {
+            TextLayout::for_member(&player.font_manager, member_type)
+                .is_none_or(|layout| layout.get_height() <= field.height as i32)
         }
+        _ => true,
     }
-    return -1;
 }
 
 pub async fn player_key_down(key: String, code: u16) -> Result<DatumRef, ScriptError> {
@@ -33,24 +44,22 @@ pub async fn player_key_down(key: String, code: u16) -> Result<DatumRef, ScriptE
             if let Some(sprite) = sprite {
                 let instance_list = sprite.script_instance_list.clone();
                 let member_ref = sprite.member.clone();
-                let member =
-                    member_ref.and_then(|x| player.movie.cast_manager.find_mut_member_by_ref(&x));
-                if let Some(member) = member {
-                    match &mut member.member_type {
-                        CastMemberType::Field(field_member) => {
-                            if field_member.editable {
-                                if key == "Backspace" {
-                                    field_member.text.pop();
-                                } else if key == "Tab" {
-                                    let next_focus_sprite_id =
-                                        get_next_focus_sprite_id(player, sprite_id as i16);
-                                    player.keyboard_focus_sprite = next_focus_sprite_id;
-                                } else if key.len() == 1 {
-                                    field_member.text = format!("{}{}", field_member.text, key);
-                                }
-                            }
-                        }
-                        _ => {}
+                let member = member_ref.as_ref().and_then(|x| player.movie.cast_manager.find_member_by_ref(x));
+                let field = member.and_then(|x| x.member_type.as_field()).cloned();
+                if let Some(mut field) = field.filter(|_| is_editable_field_sprite(player, sprite_id as i16)) {
+                    if field.auto_tab && (key == "Tab" || key == "Enter") {
+                        player.keyboard_focus_sprite = get_next_focus_sprite_id(player, sprite_id as i16);
+                    } else if key == "Backspace" {
+                        field.text.pop();
+                    } else if key == "Enter" {
+                        field.text.push('\r');
+                    } else if key.chars().count() == 1 {
+                        field.text.push_str(&key);
+                    }
+                    let edited_member_type = CastMemberType::Field(field);
+                    if fits_field_box(player, &edited_member_type) {
+                        let member = player.movie.cast_manager.find_mut_member_by_ref(member_ref.as_ref().unwrap()).unwrap();
+                        member.member_type = edited_member_type;
                     }
                 }
                 Some(instance_list)
@@ -0,0 +1,68 @@
+/// The number and date conventions of the system a movie was authored for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Locale {
+  English,
+  /// Comma as decimal separator, semicolon items and day-first dates.
+  European,
+}
+
+impl Locale {
+  pub fn from_name(name: &str) -> Option<Locale> {
+    match name.to_ascii_lowercase().as_str() {
+      "english" | "en" | "us" => Some(Locale::English),
+      "european" | "eu" | "de" | "fr" | "nl" | "it" | "es" | "pt" => Some(Locale::European),
+      _ => None,
+    }
+  }
+
+  pub fn decimal_separator(&self) -> char {
+    match self {
+      Locale::English => '.',
+      Locale::European => ',',
+    }
+  }
+
+  /// The itemDelimiter a movie starts with, when the locale has its own.
+  pub fn item_delimiter(&self) -> Option<char> {
+    match self {
+      Locale::English => None,
+      Locale::European => Some(';'),
+    }
+  }
+
+  /// The chrono format of `the date`.
+  pub fn date_format(&self) -> &'static str {
+    match self {
+      Locale::English => "%m/%d/%Y",
+      Locale::European => "%d.%m.%Y",
+    }
+  }
+
+  pub fn format_float(&self, formatted: String) -> String {
+    match self.decimal_separator() {
+      '.' => formatted,
+      separator => formatted.replace('.', &separator.to_string()),
+    }
+  }
+
+  /// Turns a number written with the locale's decimal separator into one Lingo reads.
+  pub fn normalize_number(&self, text: &str) -> Option<String> {
+    let separator = self.decimal_separator();
+    let trimmed = text.trim();
+    let digits = trimmed.strip_prefix('-').unwrap_or(trimmed);
+    let is_number = separator != '.'
+      && digits.matches(separator).count() == 1
+      && digits.chars().any(|c| c.is_ascii_digit())
+      && digits.chars().all(|c| c.is_ascii_digit() || c == separator);
+    if is_number {
+      Some(trimmed.replace(separator, "."))
+    } else {
+      None
+    }
+  }
+
+  pub fn parse_float(&self, text: &str) -> Option<f32> {
+    let text = self.normalize_number(text).unwrap_or_else(|| text.to_string());
+    text.trim().parse::<f32>().ok()
+  }
+}
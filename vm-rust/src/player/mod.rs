@@ -3,7 +3,13 @@ pub mod movie;
 pub mod geometry;
 pub mod cast_manager;
 pub mod cast_lib;
+pub mod cast_dependencies;
 pub mod net_task;
+pub mod net_policy;
+pub mod net_bundle;
+pub mod clock;
+pub mod date;
+pub mod random;
 pub mod cast_member;
 pub mod score;
 pub mod sprite;
@@ -28,30 +34,62 @@ pub mod events;
 pub mod keyboard;
 pub mod keyboard_map;
 pub mod keyboard_events;
+pub mod key_capture;
 pub mod allocator;
 pub mod datum_ref;
 pub mod script_ref;
-
-use std::{collections::HashMap, sync::{Arc, OnceLock}, time::Duration};
+pub mod frame_hook;
+pub mod datum_serialization;
+pub mod save_state;
+pub mod window;
+pub mod sound;
+pub mod streaming;
+pub mod search_path;
+pub mod console;
+pub mod alert;
+pub mod actor_list;
+pub mod cursor;
+pub mod property_descriptions;
+pub mod locale;
+pub mod export;
+pub mod compatibility;
+pub mod watchdog;
+pub mod stage_effects;
+pub mod authoring;
+pub mod do_command;
+
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::{Arc, OnceLock}, time::Duration};
 
 use allocator::{DatumAllocator, DatumAllocatorTrait, ResetableAllocator, ScriptInstanceAllocatorTrait};
 use datum_ref::DatumRef;
 use async_std::{channel::{self, Receiver, Sender}, future::{self, timeout}, sync::Mutex, task::spawn_local};
 use cast_manager::CastPreloadReason;
+use frame_hook::{player_dispatch_frame_hook, FrameHook};
 use fxhash::FxHashMap;
 use handlers::datum_handlers::script_instance::ScriptInstanceUtils;
+use handlers::datum_handlers::cast_member::text_geometry::TextGeometryHandlers;
 use log::warn;
 use manual_future::{ManualFutureCompleter, ManualFuture};
 use net_manager::NetManager;
-use profiling::{end_profiling, start_profiling};
+use net_policy::NetPolicy;
+use xtra::manager::dispose_xtra_instances;
+use clock::{Clock, RealClock, VirtualClock};
+use random::RandomGenerator;
+use window::WindowManager;
+use sound::SoundManager;
+use locale::Locale;
+use profiling::{end_profiling, start_profiling, HandlerProfiler};
 use scope::ScopeResult;
 use script::script_get_prop_opt;
 use script_ref::ScriptInstanceRef;
-use xtra::multiuser::{MultiuserXtraManager, MULTIUSER_XTRA_MANAGER_OPT};
+use xtra::{
+  buddyapi::{BuddyApiXtraManager, BUDDY_API_XTRA_MANAGER_OPT},
+  multiuser::{MultiuserXtraManager, MULTIUSER_XTRA_MANAGER_OPT},
+};
 
-use crate::{console_warn, director::{chunks::handler::{Bytecode, HandlerDef}, enums::ScriptType, file::{read_director_file_bytes, DirectorFile}, lingo::{constants::{get_anim2_prop_name, get_anim_prop_name}, datum::{datum_bool, Datum, DatumType, VarRef}}}, js_api::JsApi, player::{bytecode::handler_manager::{player_execute_bytecode, BytecodeHandlerContext}, datum_formatting::format_datum, geometry::IntRect, profiling::get_profiler_report, scope::Scope}, utils::{get_base_url, get_basename_no_extension, get_elapsed_ticks}};
+use crate::{console_warn, director::{chunks::handler::Bytecode, enums::ScriptType, file::{get_initial_load_size, read_director_file_bytes, DirectorFile}, lingo::{constants::{get_anim2_prop_name, get_anim_prop_name, get_opcode_name}, datum::{datum_bool, Datum, DatumType, TimeoutRef, VarRef}}}, io::text_encoding::TextEncoding, js_api::JsApi, rendering::StageTrails, player::{bytecode::handler_manager::{find_missing_handlers, find_unsupported_opcodes, player_execute_bytecode, player_execute_sync_bytecodes, BytecodeBatchResult, BytecodeHandlerContext}, datum_formatting::format_datum, profiling::get_profiler_report, scope::Scope}, utils::{get_base_url, get_basename_no_extension, performance_now}};
 
-use self::{bytecode::handler_manager::StaticBytecodeHandlerManager, cast_lib::CastMemberRef, cast_manager::CastManager, commands::{run_command_loop, PlayerVMCommand}, debug::{Breakpoint, BreakpointContext, BreakpointManager}, events::{player_dispatch_global_event, player_invoke_global_event, player_unwrap_result, player_wait_available, run_event_loop, PlayerVMEvent}, font::{player_load_system_font, FontManager}, handlers::manager::BuiltInHandlerManager, keyboard::KeyboardManager, movie::Movie, net_manager::NetManagerSharedState, scope::ScopeRef, score::{get_sprite_at, Score}, script::{Script, ScriptHandlerRef, ScriptInstance, ScriptInstanceId}, sprite::{ColorRef, CursorRef}, timeout::TimeoutManager};
+use self::{alert::{player_handle_script_error, Alert}, compatibility::{player_apply_compatibility_profile, CompatibilityBaseline, CompatibilityProfile, MissingHandlerPolicy}, property_descriptions::player_apply_property_defaults, bytecode::handler_manager::StaticBytecodeHandlerManager, cast_lib::CastMemberRef, commands::{run_command_loop, PlayerVMCommand}, debug::{coverage::CoverageRecorder, history::{StepHistory, StepSnapshot}, is_breakpoint_condition_met, update_watch_expressions, Breakpoint, BreakpointContext, BreakpointManager}, actor_list::player_step_actors, events::{player_dispatch_global_event, player_invoke_event_to_sprite, player_invoke_frame_event, player_invoke_global_event, player_suspend_handler, player_unwrap_result, player_wait_available, run_event_loop, PlayerVMEvent}, font::{player_load_system_font, FontManager}, handlers::manager::BuiltInHandlerManager, keyboard::KeyboardManager, movie::Movie, net_manager::NetManagerSharedState, scope::ScopeRef, score::get_sprite_at, script::{ScriptHandlerRef, ScriptInstance, ScriptInstanceId}, sprite::{ColorRef, CursorRef}, stage_effects::{StageFade, ZoomBox}, streaming::player_stream_movie_media, timeout::TimeoutManager, watchdog::{ScriptWatchdog, WatchdogAction, WatchdogEvent}};
 
 pub enum HandlerExecutionResult {
   Advance,
@@ -69,7 +107,9 @@ pub struct PlayerVMExecutionItem {
   pub completer: Option<ManualFutureCompleter<Result<DatumRef, ScriptError>>>,
 }
 
-pub const MAX_STACK_SIZE: usize = 50;
+/// How many handler calls can be nested before a stack overflow error. Recursive
+/// handlers in real movies go well past a few dozen calls.
+pub const DEFAULT_MAX_STACK_SIZE: usize = 256;
 
 pub struct DirPlayer {
   pub net_manager: NetManager,
@@ -83,10 +123,12 @@ pub struct DirPlayer {
   pub bytecode_handler_manager: StaticBytecodeHandlerManager,
   pub breakpoint_manager: BreakpointManager,
   pub current_breakpoint: Option<BreakpointContext>,
+  pub step_history: StepHistory,
   pub stage_size: (u32, u32),
   pub bitmap_manager: bitmap::manager::BitmapManager,
   pub cursor: CursorRef,
-  pub start_time: chrono::DateTime<chrono::Local>,
+  pub clock: Box<dyn Clock>,
+  pub random: RandomGenerator,
   pub timeout_manager: TimeoutManager,
   pub title: String,
   pub bg_color: ColorRef,
@@ -97,6 +139,19 @@ pub struct DirPlayer {
   pub last_mouse_down_time: i64,
   pub is_double_click: bool,
   pub mouse_down_sprite: i16,
+  /// `the clickOn`, the last sprite with scripts that was clicked, or 0.
+  pub click_on: i16,
+  /// `the clickLoc`, where the mouse was last pressed.
+  pub click_loc: (i32, i32),
+  /// Lingo run before mouseDown and mouseUp events are sent to sprites.
+  pub mouse_down_script: Option<String>,
+  pub mouse_up_script: Option<String>,
+  /// Set by dontPassEvent while a primary event handler runs, to keep the event from sprites.
+  pub dont_pass_event: bool,
+  /// Moveable sprite being dragged and its loc relative to the mouse.
+  pub dragged_sprite: Option<(i16, (i32, i32))>,
+  /// Text member hyperlink being pressed, by index, and its state before it was.
+  pub pressed_hyperlink: Option<(CastMemberRef, usize, String)>,
   pub subscribed_member_refs: Vec<CastMemberRef>, // TODO move to debug module
   pub is_subscribed_to_channel_names: bool, // TODO move to debug module
   pub font_manager: FontManager,
@@ -104,52 +159,88 @@ pub struct DirPlayer {
   pub float_precision: u8,
   pub last_handler_result: DatumRef,
   pub hovered_sprite: Option<i16>,
+  /// The touch that drives the mouse, for movies made before touch screens.
+  pub primary_touch_id: Option<i32>,
   pub allocator: DatumAllocator,
   pub dir_cache: HashMap<Box<str>, DirectorFile>,
   pub scope_count: u32,
-  pub external_params: HashMap<String, String>,
+  pub external_params: Vec<(String, String)>,
+  pub frame_hook: Option<FrameHook>,
+  pub is_safe_mode: bool,
+  /// Whether `do` and `doLater` may run Lingo built from strings, off for untrusted movies.
+  pub allow_do: bool,
+  pub missing_handler_policy: MissingHandlerPolicy,
+  pub compatibility_profile: Option<CompatibilityProfile>,
+  /// The settings from before the compatibility profile was applied.
+  pub compatibility_baseline: Option<CompatibilityBaseline>,
+  pub script_watchdog: ScriptWatchdog,
+  pub max_stack_size: usize,
+  /// Looks for a `.compat.json` profile next to movies when the host hasn't set one.
+  pub fetch_compatibility_profiles: bool,
+  pub coverage_recorder: Option<CoverageRecorder>,
+  pub handler_profiler: Option<HandlerProfiler>,
+  pub window_manager: WindowManager,
+  pub sound_manager: SoundManager,
+  /// Stretched sprites are sampled bilinearly rather than with nearest-neighbor.
+  pub smooth_scaling: bool,
+  /// Reduces the stage to its palette like an 8-bit monitor would, for movies with a
+  /// colorDepth of 8 or less. Movies report a colorDepth of 8 by default while it's on.
+  pub emulate_8bit_stage: bool,
+  /// Locale picked by the host, which replaces the one guessed for each movie.
+  pub locale_override: Option<Locale>,
+  /// Encoding picked by the host, which replaces the platform's when movies and their
+  /// casts are read.
+  pub text_encoding_override: Option<TextEncoding>,
+  /// What sprites with trails left on the stage, until it's erased.
+  pub stage_trails: Option<StageTrails>,
+  /// Set by puppetTransition, which erases the trails when the next frame is entered.
+  pub transition_pending: bool,
+  /// The rectangle of the last zoomBox, drawn until it has zoomed all the way.
+  pub zoom_box: Option<ZoomBox>,
+  /// The brightness of the stage after a fadeIn or fadeOut.
+  pub stage_fade: Option<StageFade>,
+  /// The list handed out for `the searchPath`, see search_path_list.
+  pub search_path_list: Option<DatumRef>,
+  /// The alert shown over the stage, see alert::player_alert.
+  pub alert: Option<Alert>,
+  /// The list behind `the actorList`, see actor_list::actor_list.
+  pub actor_list: Option<DatumRef>,
+  /// The sprite whose beginSprite or endSprite is running, for `the currentSpriteNum`.
+  pub current_sprite_num: Option<i16>,
 }
 
 impl DirPlayer {
   pub fn new<'a>(
     tx: Sender<PlayerVMExecutionItem>,
   ) -> DirPlayer {
-    let mut result = DirPlayer {
-      movie: Movie { 
-        rect: IntRect::from(0, 0, 0, 0),
-        cast_manager: CastManager::empty(),
-        score: Score::empty(),
-        current_frame: 1,
-        puppet_tempo: 0,
-        exit_lock: false,
-        dir_version: 0,
-        item_delimiter: '.',
-        alert_hook: None,
-        base_path: "".to_string(),
-        file_name: "".to_string(),
-        stage_color: (0, 0, 0),
-        frame_rate: 30,
-        file: None,
-      },
+    DirPlayer {
+      movie: Movie::empty(),
       net_manager: NetManager {
         base_path: None,
         tasks: HashMap::new(),
-        task_states: HashMap::new(),
-        shared_state: Arc::new(Mutex::new(NetManagerSharedState::new()))
+        last_task_id: 0,
+        text_task_ids: HashSet::new(),
+        read_text_task_ids: VecDeque::new(),
+        shared_state: Arc::new(Mutex::new(NetManagerSharedState::new())),
+        policy: NetPolicy::new(),
+        search_paths: vec![],
+        bundles: vec![],
       },
       is_playing: false,
       is_script_paused: false,
       next_frame: None,
       queue_tx: tx,
       globals: FxHashMap::default(),
-      scopes: Vec::with_capacity(MAX_STACK_SIZE),
+      scopes: vec![],
       bytecode_handler_manager: StaticBytecodeHandlerManager {},
       breakpoint_manager: BreakpointManager::new(),
       current_breakpoint: None,
+      step_history: StepHistory::new(),
       stage_size: (100, 100),
       bitmap_manager: bitmap::manager::BitmapManager::new(),
       cursor: CursorRef::System(0),
-      start_time: chrono::Local::now(), // supposed to be time at which computer started, but we don't have access from browser. this is sufficient for calculating elapsed time.
+      clock: Box::new(RealClock::new()),
+      random: RandomGenerator::new(),
       timeout_manager: TimeoutManager::new(),
       title: "".to_string(),
       bg_color: ColorRef::Rgb(0, 0, 0),
@@ -158,6 +249,13 @@ impl DirPlayer {
       last_mouse_down_time: 0,
       is_double_click: false,
       mouse_down_sprite: 0,
+      click_on: 0,
+      click_loc: (0, 0),
+      mouse_down_script: None,
+      mouse_up_script: None,
+      dont_pass_event: false,
+      dragged_sprite: None,
+      pressed_hyperlink: None,
       subscribed_member_refs: vec![],
       is_subscribed_to_channel_names: false,
       font_manager: FontManager::new(),
@@ -167,53 +265,142 @@ impl DirPlayer {
       float_precision: 4,
       last_handler_result: DatumRef::Void,
       hovered_sprite: None,
+      primary_touch_id: None,
       allocator: DatumAllocator::default(),
       dir_cache: HashMap::new(),
       scope_count: 0,
-      external_params: HashMap::new(),
-    };
-    for i in 0..MAX_STACK_SIZE {
-      result.scopes.push(Scope::default(i));
+      external_params: vec![],
+      frame_hook: None,
+      is_safe_mode: false,
+      allow_do: true,
+      missing_handler_policy: MissingHandlerPolicy::default(),
+      compatibility_profile: None,
+      script_watchdog: ScriptWatchdog::new(),
+      max_stack_size: DEFAULT_MAX_STACK_SIZE,
+      fetch_compatibility_profiles: false,
+      compatibility_baseline: None,
+      coverage_recorder: None,
+      handler_profiler: None,
+      window_manager: WindowManager::new(),
+      sound_manager: SoundManager::new(),
+      smooth_scaling: false,
+      emulate_8bit_stage: false,
+      locale_override: None,
+      text_encoding_override: None,
+      stage_trails: None,
+      transition_pending: false,
+      zoom_box: None,
+      stage_fade: None,
+      search_path_list: None,
+      alert: None,
+      actor_list: None,
+      current_sprite_num: None,
     }
-    result
   }
 
   pub async fn load_movie_from_file(&mut self, path: &str) {
-    let task_id = self.net_manager.preload_net_thing(path.to_owned());
-    self.net_manager.await_task(task_id).await;
+    if self.movie.file.is_some() {
+      // Loading over a running movie unloads it first
+      self.reset();
+    }
+    let task_id = self.net_manager.stream_net_thing(path.to_owned());
+    // Afterburned movies start once everything but the media of their members is in
+    let data_bytes = loop {
+      if self.net_manager.is_task_done(Some(task_id)) {
+        break self.net_manager.get_task_result(Some(task_id)).unwrap().unwrap();
+      }
+      let initial_data = self.net_manager.with_streamed_data(task_id, |data| {
+        get_initial_load_size(data).is_some_and(|size| data.len() >= size).then(|| data.to_vec())
+      }).flatten();
+      if let Some(initial_data) = initial_data {
+        break initial_data;
+      }
+      self.net_manager.await_task_progress(task_id).await;
+    };
     let task = self.net_manager.get_task(task_id).unwrap();
-    let data_bytes = self.net_manager.get_task_result(Some(task_id)).unwrap().unwrap();
 
     let movie_file = read_director_file_bytes(
       &data_bytes, 
       &get_basename_no_extension(task.resolved_url.path()), 
       &get_base_url(&task.resolved_url).to_string(),
+      self.text_encoding_override,
     ).unwrap();
+    if self.fetch_compatibility_profiles && self.compatibility_profile.is_none() {
+      let profile_url = task.resolved_url.join(&format!("{}.compat.json", get_basename_no_extension(task.resolved_url.path())));
+      if let Ok(profile_url) = profile_url {
+        self.fetch_compatibility_profile(profile_url.to_string()).await;
+      }
+    }
     self.load_movie_from_dir(movie_file).await;
+    if !self.net_manager.is_task_done(Some(task_id)) {
+      self.movie.stream_task_id = Some(task_id);
+      async_std::task::spawn_local(player_stream_movie_media(task_id));
+    }
+  }
+
+  async fn fetch_compatibility_profile(&mut self, url: String) {
+    let task_id = self.net_manager.preload_net_thing(url.clone());
+    self.net_manager.await_task(task_id).await;
+    // Most movies don't have one, so a failed fetch is expected
+    if let Some(Ok(data)) = self.net_manager.get_task_result(Some(task_id)) {
+      match CompatibilityProfile::from_json(&String::from_utf8_lossy(&data)) {
+        Ok(profile) => self.compatibility_profile = Some(profile),
+        Err(err) => warn!("Ignoring compatibility profile {}: {}", url, err),
+      }
+    }
   }
 
   async fn load_movie_from_dir(&mut self, dir: DirectorFile) {
     self.movie.load_from_file(dir, &mut self.net_manager, &mut self.bitmap_manager, &mut self.dir_cache).await;
+    if let Some(locale) = self.locale_override {
+      self.movie.set_locale(locale);
+    }
     let (r, g, b) = self.movie.stage_color;
     self.bg_color = ColorRef::Rgb(r, g, b);
     JsApi::dispatch_movie_loaded(self.movie.file.as_ref().unwrap());
+    for usage in find_unsupported_opcodes(&self.movie.cast_manager) {
+      let message = format!(
+        "Unsupported opcode {} used {} time(s), first in {}",
+        get_opcode_name(usage.opcode), usage.count, usage.first_location
+      );
+      warn!("{}", message);
+      JsApi::dispatch_debug_message(&message);
+    }
+    for usage in find_missing_handlers(&self.movie.cast_manager) {
+      let message = format!(
+        "Handler {} is not implemented, called {} time(s), first in {}",
+        usage.name, usage.count, usage.first_location
+      );
+      warn!("{}", message);
+      JsApi::dispatch_debug_message(&message);
+    }
+    player_apply_compatibility_profile(self);
+    if self.is_safe_mode {
+      // Show the first frame's sprites without attaching behaviors
+      self.movie.score.begin_sprites(self.movie.current_frame);
+    }
   }
 
   pub fn play(&mut self) {
     if self.is_playing {
       return;
     }
+    if self.is_safe_mode {
+      warn!("Scripts are disabled in safe mode, not playing the movie");
+      return;
+    }
     self.is_playing = true;
     self.is_script_paused = false;
     // TODO runVM()
     async_std::task::spawn_local(async move {
+      let scope_count = reserve_player_ref(|player| player.scope_count);
       if let Err(err) = player_invoke_global_event(&"prepareMovie".to_string(), &vec![]).await {
-        reserve_player_mut(|player| player.on_script_error(&err));
-        return;
+        player_handle_script_error(&err, scope_count).await;
+        if !player_is_playing().await {
+          return;
+        }
       }
-      reserve_player_mut(|player| {
-        player.movie.score.begin_sprites(player.movie.current_frame);
-      });
+      player_begin_sprites().await;
       run_frame_loop().await;
     });
   }
@@ -234,18 +421,108 @@ impl DirPlayer {
     }
   }
 
+  pub fn dismiss_alert(&mut self) {
+    if let Some(alert) = self.alert.take() {
+      spawn_local(alert.completer.complete(()));
+    }
+  }
+
+  /// Runs the bytecode the debugger is paused at and pauses again at the next one.
+  pub fn step_bytecode(&mut self) {
+    if self.current_breakpoint.is_some() {
+      self.breakpoint_manager.step_requested = true;
+      self.resume_breakpoint();
+    }
+  }
+
+  /// Rewinds the paused handler to the state it had before its previous bytecode ran.
+  pub fn step_back_bytecode(&mut self) {
+    let invocation_id = match &self.current_breakpoint {
+      Some(breakpoint) => breakpoint.invocation_id,
+      None => return,
+    };
+    let snapshot = match self.step_history.pop(invocation_id) {
+      Some(snapshot) => snapshot,
+      None => return,
+    };
+    let scope_ref = self.current_scope_ref();
+    let bytecode_index = snapshot.bytecode_index;
+    snapshot.restore(self.scopes.get_mut(scope_ref).unwrap(), &mut self.globals);
+    self.current_breakpoint.as_mut().unwrap().bytecode_index = bytecode_index;
+    JsApi::dispatch_debug_update(self);
+    update_watch_expressions(self);
+  }
+
   pub fn get_datum(&self, id: &DatumRef) -> &Datum {
     self.allocator.get_datum(id)
   }
 
+  /// Sets a global, letting the step history know what it was before.
+  pub fn set_global(&mut self, name: &str, value_ref: DatumRef) {
+    let previous = self.globals.insert(name.to_owned(), value_ref);
+    if self.step_history.is_recording() {
+      self.step_history.record_global_change(name.to_owned(), previous);
+    }
+  }
+
   pub fn get_datum_mut(&mut self, id: &DatumRef) -> &mut Datum {
     self.allocator.get_datum_mut(id)
   }
 
+  pub fn get_color_depth(&self) -> u16 {
+    let default_depth = if self.emulate_8bit_stage { 8 } else { 32 };
+    self.movie.color_depth.unwrap_or(default_depth)
+  }
+
+  /// Whether the stage is drawn with the 256 colors of its palette.
+  pub fn is_stage_indexed(&self) -> bool {
+    self.emulate_8bit_stage && self.get_color_depth() <= 8
+  }
+
   pub fn get_fps(&self) -> u32 {
+    if let Some(frame_rate) = self.compatibility_profile.as_ref().and_then(|profile| profile.frame_rate) {
+      return frame_rate;
+    }
     if self.movie.puppet_tempo > 0 { self.movie.puppet_tempo } else { self.movie.frame_rate as u32 }
   }
 
+  /// Runs movie time at `scale` times real time, switching to a virtual clock
+  /// the first time it's called. A scale of zero pauses time.
+  pub fn set_time_scale(&mut self, scale: f64) {
+    match self.clock.as_virtual_mut() {
+      Some(clock) => clock.set_time_scale(scale),
+      None => self.clock = Box::new(VirtualClock::new(self.clock.elapsed_ms(), scale)),
+    }
+    let clock = self.clock.as_ref();
+    for timeout in self.timeout_manager.timeouts.values_mut() {
+      if timeout.is_scheduled {
+        timeout.schedule(clock);
+      }
+    }
+  }
+
+  /// Advances movie time by `ms`, pausing it first if it was still running in
+  /// real time. Returns the timeouts that became due, once per elapsed period.
+  pub fn step_virtual_clock(&mut self, ms: i64) -> Vec<TimeoutRef> {
+    if self.clock.as_virtual_mut().is_none() {
+      self.set_time_scale(0.0);
+    }
+    self.clock.as_virtual_mut().unwrap().advance(ms);
+
+    let now = self.clock.elapsed_ms();
+    let mut due_timeouts = vec![];
+    for timeout in self.timeout_manager.timeouts.values() {
+      if !timeout.is_scheduled || timeout.period == 0 {
+        continue;
+      }
+      let due_count = (now - timeout.last_fired_ms) / timeout.period as i64;
+      for _ in 0..due_count {
+        due_timeouts.push(timeout.name.to_owned());
+      }
+    }
+    due_timeouts
+  }
+
   pub fn get_hydrated_globals(&self) -> FxHashMap<String, &Datum> {
     self.globals.iter().map(|(k, v)| (k.to_owned(), self.get_datum(v))).collect()
   }
@@ -273,9 +550,14 @@ impl DirPlayer {
     let next_frame = self.get_next_frame();
     self.next_frame = None;
     self.movie.current_frame = next_frame;
+    if std::mem::take(&mut self.transition_pending) {
+      self.stage_trails = None;
+    }
     if prev_frame != self.movie.current_frame {
       JsApi::dispatch_frame_changed(self.movie.current_frame);
     }
+    self.bitmap_manager.trim_decoded_bitmaps();
+    self.sound_manager.update(&self.movie.cast_manager, self.clock.elapsed_ms());
   }
 
   pub fn stop(&mut self) {
@@ -286,13 +568,26 @@ impl DirPlayer {
     // currentBreakpoint?.completer.completeError(CancelledException());
     // currentBreakpoint = null;
     self.timeout_manager.clear();
+    self.dismiss_alert();
     //notifyListeners();
 
     warn!("Profiler report: {}", get_profiler_report());
   }
 
+  /// Unloads the movie's runtime state. Timeouts are forgotten and xtra instances
+  /// disposed before the allocator is cleared, since both hold references to script
+  /// objects. Datums that still name a disposed xtra instance or a forgotten timeout
+  /// report an error or read as void instead of reaching stale state.
   pub fn reset(&mut self) {
     self.stop();
+    self.sound_manager.stop_all();
+    dispose_xtra_instances();
+    // Window movies hold datums, so they go before the allocator is reset
+    window::dispose_windows(self);
+    search_path::sync_search_paths(self);
+    self.search_path_list = None;
+    self.actor_list = None;
+    self.current_sprite_num = None;
     self.scopes.clear();
     self.globals.clear();
     self.allocator.reset();
@@ -300,8 +595,18 @@ impl DirPlayer {
     // netManager.clear();
     self.movie.score.reset();
     self.movie.current_frame = 1;
+    self.movie.play_stack.clear();
     // TODO cancel breakpoints
     self.current_breakpoint = None;
+    self.breakpoint_manager.step_requested = false;
+    self.step_history.clear();
+    self.stage_trails = None;
+    self.transition_pending = false;
+    self.zoom_box = None;
+    self.stage_fade = None;
+    self.mouse_down_script = None;
+    self.mouse_up_script = None;
+    self.pressed_hyperlink = None;
     // notifyListeners();
 
     JsApi::dispatch_frame_changed(self.movie.current_frame);
@@ -318,16 +623,29 @@ impl DirPlayer {
     match prop {
       "stage" => Ok(Datum::Stage),
       "time" => Ok(Datum::String(chrono::Local::now().format("%H:%M %p").to_string())),
-      "milliSeconds" => Ok(Datum::Int(chrono::Local::now().signed_duration_since(self.start_time).num_milliseconds() as i32)),
+      "milliSeconds" => Ok(Datum::Int(self.clock.elapsed_ms() as i32)),
       "keyboardFocusSprite" => Ok(Datum::Int(self.keyboard_focus_sprite as i32)),
       "frameTempo" => Ok(Datum::Int(self.movie.puppet_tempo as i32)),
       "mouseLoc" => Ok(Datum::IntPoint(self.mouse_loc)),
       "mouseH" => Ok(Datum::Int(self.mouse_loc.0 as i32)),
       "mouseV" => Ok(Datum::Int(self.mouse_loc.1 as i32)),
+      "clickOn" => Ok(Datum::Int(self.click_on as i32)),
+      "clickLoc" => Ok(Datum::IntPoint(self.click_loc)),
+      "mouseDownScript" => Ok(Datum::String(self.mouse_down_script.clone().unwrap_or_default())),
+      "mouseUpScript" => Ok(Datum::String(self.mouse_up_script.clone().unwrap_or_default())),
       "rollover" => {
         let sprite = get_sprite_at(self, self.mouse_loc.0, self.mouse_loc.1, false);
         Ok(Datum::Int(sprite.unwrap_or(0) as i32))
       }
+      "mouseMember" => {
+        let member = get_sprite_at(self, self.mouse_loc.0, self.mouse_loc.1, false)
+          .and_then(|sprite_num| self.movie.score.get_sprite(sprite_num as i16))
+          .and_then(|sprite| sprite.member.clone());
+        Ok(member.map_or(Datum::Void, Datum::CastMember))
+      }
+      "mouseChar" | "mouseWord" | "mouseItem" | "mouseLine" => {
+        Ok(Datum::Int(TextGeometryHandlers::get_mouse_chunk(self, prop)?))
+      }
       "keyCode" => Ok(Datum::Int(self.keyboard_manager.key_code() as i32)),
       "shiftDown" => Ok(datum_bool(self.keyboard_manager.is_shift_down())),
       "optionDown" => Ok(datum_bool(self.keyboard_manager.is_alt_down())), // TODO: return true only on mac
@@ -337,7 +655,16 @@ impl DirPlayer {
       "key" => Ok(Datum::String(self.keyboard_manager.key())),
       "floatPrecision" => Ok(Datum::Int(self.float_precision as i32)),
       "doubleClick" => Ok(datum_bool(self.is_double_click)),
-      "ticks" => Ok(Datum::Int(get_elapsed_ticks(self.start_time))),
+      "ticks" => Ok(Datum::Int(self.clock.elapsed_ticks())),
+      "randomSeed" => Ok(Datum::Int(self.random.seed())),
+      "maxInteger" => Ok(Datum::Int(i32::MAX)),
+      "soundLevel" => Ok(Datum::Int(self.sound_manager.sound_level())),
+      "soundEnabled" => Ok(datum_bool(self.sound_manager.sound_enabled())),
+      "labelList" => {
+        let mut frame_labels = self.movie.score.frame_labels.iter().collect::<Vec<_>>();
+        frame_labels.sort_by_key(|label| label.frame_num);
+        Ok(Datum::String(frame_labels.iter().map(|label| format!("{}\r", label.label)).collect()))
+      },
       "frameLabel" => {
         let frame_label = self.movie.score.frame_labels.iter()
           .filter(|&label| label.frame_num <= self.movie.current_frame as i32)
@@ -346,7 +673,7 @@ impl DirPlayer {
         Ok(Datum::String(frame_label.unwrap_or_else(|| "0".to_string())))
       },
       "currentSpriteNum" => {
-        // TODO: this can also be called by a static script
+        // Movie scripts called during a sprite event see the sprite of the event
         let script_instance_ref = self.scopes
           .get(self.current_scope_ref())
           .and_then(|scope| scope.receiver.clone());
@@ -358,7 +685,7 @@ impl DirPlayer {
             let sprite_num = datum.int_value()?;
             Ok(Datum::Int(sprite_num))
           } else {
-            Ok(Datum::Int(0))
+            Ok(Datum::Int(self.current_sprite_num.unwrap_or(0) as i32))
           }
         })
       },
@@ -370,6 +697,7 @@ impl DirPlayer {
     match prop.as_str() {
       "traceScript" => Ok(self.alloc_datum(datum_bool(false))), // TODO
       "productVersion" => Ok(self.alloc_datum(Datum::String("10.1".to_string()))), // TODO
+      "windowList" => Ok(window::window_list(self)),
       _ => Err(ScriptError::new(format!("Unknown player prop {}", prop)))
     }
   }
@@ -387,8 +715,10 @@ impl DirPlayer {
   fn get_anim_prop(&self, prop_id: u16) -> Result<Datum, ScriptError> {
     let prop_name = get_anim_prop_name(prop_id);
     match prop_name {
-      "colorDepth" => Ok(Datum::Int(32)),
-      "timer" => Ok(Datum::Int(get_elapsed_ticks(self.start_time))),
+      "colorDepth" => Ok(Datum::Int(self.get_color_depth() as i32)),
+      "colorQD" => Ok(datum_bool(true)),
+      "timer" => Ok(Datum::Int(self.clock.elapsed_ticks())),
+      "soundEnabled" | "soundLevel" => self.get_movie_prop(prop_name),
       _ => Err(ScriptError::new(format!("Unknown anim prop {}", prop_name)))
     }
   }
@@ -404,6 +734,41 @@ impl DirPlayer {
 
   fn set_movie_prop(&mut self, prop: &str, value: Datum) -> Result<(), ScriptError> {
     match prop {
+      "randomSeed" => {
+        self.random.set_seed(value.int_value()?);
+        Ok(())
+      },
+      "mouseDownScript" => {
+        let script = value.string_value()?;
+        self.mouse_down_script = Some(script).filter(|script| !script.is_empty());
+        Ok(())
+      },
+      "mouseUpScript" => {
+        let script = value.string_value()?;
+        self.mouse_up_script = Some(script).filter(|script| !script.is_empty());
+        Ok(())
+      },
+      "soundLevel" => {
+        self.sound_manager.set_sound_level(value.int_value()?);
+        Ok(())
+      },
+      "soundEnabled" => {
+        self.sound_manager.set_sound_enabled(value.to_bool()?);
+        Ok(())
+      },
+      "colorDepth" => {
+        // Like on a monitor that can't switch, depths that don't exist are ignored
+        let depth = value.int_value()?;
+        if [1, 2, 4, 8, 16, 24, 32].contains(&depth) {
+          self.movie.color_depth = Some(depth as u16);
+        }
+        Ok(())
+      },
+      "frame" => {
+        // Same as go to frame, the playhead moves once the current frame finishes
+        self.next_frame = Some(value.int_value()? as u32);
+        Ok(())
+      },
       "keyboardFocusSprite" => {
         // TODO switch focus
         self.keyboard_focus_sprite = value.int_value()? as i16;
@@ -425,10 +790,8 @@ impl DirPlayer {
         // TODO
         Ok(())
       },
-      "actorList" => {
-        // TODO
-        Ok(())
-      },
+      "actorList" => actor_list::set_actor_list(self, value),
+      "searchPath" | "searchPaths" => search_path::set_search_paths(self, &value),
       _ => {
         self.movie.set_prop(prop, value, &self.allocator)
       }
@@ -437,6 +800,10 @@ impl DirPlayer {
 
   fn on_script_error(&mut self, err: &ScriptError) {
     warn!("[!!] play failed with error: {}", err.message);
+    if let Some(profiler) = self.handler_profiler.as_mut() {
+      profiler.exit_to_scope(0, performance_now());
+    }
+    window::restore_stage_movie(self);
     self.stop();
 
     JsApi::dispatch_script_error(self, &err);
@@ -448,19 +815,56 @@ impl DirPlayer {
   ) -> &'a Bytecode {
     let scope = self.scopes.get(ctx.scope_ref).unwrap();
     let bytecode_index = scope.bytecode_index;
-    let handler_def = unsafe { &*ctx.handler_def_ptr };
-    handler_def.bytecode_array.get(bytecode_index).unwrap()
+    ctx.handler_def.bytecode_array.get(bytecode_index).unwrap()
   }
 
-  pub fn push_scope(&mut self) -> ScopeRef{
-    if (self.scope_count + 1) as usize >= MAX_STACK_SIZE {
-      panic!("Stack overflow");
+  /// Scopes are allocated as the stack first gets deep enough to need them, and reused
+  /// after that.
+  pub fn push_scope(&mut self) -> Result<ScopeRef, ScriptError> {
+    if self.scope_count as usize >= self.max_stack_size {
+      return Err(ScriptError::new(format!(
+        "Stack overflow: more than {} nested handler calls\n{}",
+        self.max_stack_size,
+        self.format_call_stack(),
+      )));
+    }
+    let scope_ref = self.scope_count as ScopeRef;
+    if scope_ref == self.scopes.len() {
+      self.scopes.push(Scope::default(scope_ref));
     }
-    let scope_ref = self.scope_count;
-    let scope = self.scopes.get_mut(scope_ref as ScopeRef).unwrap();
+    let scope = self.scopes.get_mut(scope_ref).unwrap();
     scope.reset();
     self.scope_count += 1;
-    scope_ref as ScopeRef
+    Ok(scope_ref)
+  }
+
+  /// The running handlers from the innermost out, one per line, with runs of the same
+  /// handler calling itself counted instead of repeated.
+  pub fn format_call_stack(&self) -> String {
+    let mut lines: Vec<(String, usize)> = vec![];
+    for scope in self.scopes.iter().take(self.scope_count as usize).rev() {
+      let line = match self.get_scope_names(scope) {
+        Some((script_name, handler_name)) => format!("{} in {}", handler_name, script_name),
+        None => "(console)".to_owned(),
+      };
+      match lines.last_mut() {
+        Some((last_line, count)) if *last_line == line => *count += 1,
+        _ => lines.push((line, 1)),
+      }
+    }
+    lines.into_iter()
+      .map(|(line, count)| if count > 1 { format!("{} (x{})", line, count) } else { line })
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+
+  /// The names of the script and handler a scope runs, None for scopes that don't
+  /// belong to a script, like the one of the Lingo console.
+  pub fn get_scope_names(&self, scope: &Scope) -> Option<(&str, &str)> {
+    let script = self.movie.cast_manager.get_script_by_ref(&scope.script_ref)?;
+    let handler_name = script::get_lctx_for_script_ref(self, &scope.script_ref)
+      .and_then(|lctx| lctx.names.get(scope.handler_name_id as usize))?;
+    Some((script.name.as_str(), handler_name.as_str()))
   }
 
   pub fn pop_scope(&mut self) {
@@ -608,17 +1012,17 @@ pub async fn player_call_script_handler_raw_args(
   use_raw_arg_list: bool,
 ) -> Result<ScopeResult, ScriptError> {
   let (script_member_ref, handler_name) = &handler_ref;
-  let (scope_ref, handler_ptr, script_ptr) = reserve_player_mut(|player| {
-    let (script_ptr, handler_ptr, handler_name_id, script_type) = {
+  // The frame holds its own references to the script and handler, so recompiling the
+  // script while the handler runs doesn't free them
+  let (scope_ref, handler_def, script) = reserve_player_mut(|player| {
+    let (script, handler_def, handler_name_id, script_type) = {
       let script_rc = player.movie.cast_manager.get_script_by_ref(&script_member_ref).unwrap();
       let script = script_rc.as_ref();
-      let script_ptr = script as *const Script;
       let handler = script.get_own_handler(&handler_name);
 
       if let Some(handler_rc) = handler {
         let handler_name_id = handler_rc.name_id;
-        let handler_ptr: *const HandlerDef = handler_rc.as_ref();
-        Ok((script_ptr, handler_ptr, handler_name_id, script.script_type))
+        Ok((script_rc.clone(), handler_rc.clone(), handler_name_id, script.script_type))
       } else {
         Err(ScriptError::new_code(ScriptErrorCode::HandlerNotFound, format!("Handler {handler_name} not found for script {}", script.name)))
       }
@@ -633,7 +1037,10 @@ pub async fn player_call_script_handler_raw_args(
       None
     };
 
-    let scope_ref = player.push_scope();
+    if player.scope_count == 0 {
+      player.script_watchdog.start();
+    }
+    let scope_ref = player.push_scope()?;
     {
       let scope = player.scopes.get_mut(scope_ref).unwrap();
       scope.script_ref = script_member_ref.clone();
@@ -652,32 +1059,97 @@ pub async fn player_call_script_handler_raw_args(
     let scope = player.scopes.get_mut(scope_ref).unwrap();
     scope.args.extend_from_slice(arg_list);
 
-    Ok((scope_ref, handler_ptr, script_ptr))
+    Ok((scope_ref, handler_def, script))
   })?;
 
   let ctx = BytecodeHandlerContext {
     scope_ref,
-    handler_def_ptr: handler_ptr,
-    script_ptr
+    handler_def,
+    script,
   };
 
   let mut should_return = false;
+  let invocation_id = reserve_player_mut(|player| {
+    if let Some(profiler) = player.handler_profiler.as_mut() {
+      profiler.enter(&handler_ref, &ctx.script.name, scope_ref, performance_now());
+    }
+    player.step_history.begin_invocation()
+  });
 
+  let mut is_long_batch = false;
   loop {
-    let bytecode_index = reserve_player_ref(|player| player.scopes.get(scope_ref).unwrap().bytecode_index);
+    match reserve_player_mut(|player| player.script_watchdog.check(is_long_batch)) {
+      WatchdogEvent::Continue => {}
+      WatchdogEvent::Yield => {
+        // Lets the browser render before carrying on, input waits for the handler
+        player_suspend_handler(timeout(Duration::ZERO, future::pending::<()>())).await.unwrap_err();
+      }
+      WatchdogEvent::Expired(action) => {
+        let bytecode_index = reserve_player_ref(|player| player.scopes.get(scope_ref).unwrap().bytecode_index);
+        player_handle_unresponsive_script(action, &handler_ref, &ctx.script.name, bytecode_index, invocation_id).await?;
+      }
+    }
+
+    // Breakpoints and coverage need to see every opcode, so handlers are stepped one at a time while they're in use
+    let is_stepping = reserve_player_ref(|player| {
+      player.coverage_recorder.is_some()
+        || player.breakpoint_manager.step_requested
+        || player.breakpoint_manager.has_breakpoints_in_handler(&ctx.script.name, &handler_name)
+    });
+    is_long_batch = false;
+    if !is_stepping {
+      match player_execute_sync_bytecodes(&ctx)? {
+        BytecodeBatchResult::Stop => break,
+        BytecodeBatchResult::Yield => {}
+        BytecodeBatchResult::Limit => {
+          is_long_batch = true;
+          continue;
+        }
+      }
+    }
+
+    let bytecode_index = reserve_player_mut(|player| {
+      let bytecode_index = player.scopes.get(scope_ref).unwrap().bytecode_index;
+      if let Some(coverage_recorder) = player.coverage_recorder.as_mut() {
+        let bytecode_pos = ctx.handler_def.bytecode_array[bytecode_index].pos;
+        coverage_recorder.record(&handler_ref, bytecode_pos);
+      }
+      bytecode_index
+    });
     // let profile_token = start_profiling(get_opcode_name(&bytecode.opcode));
-    if let Some(breakpoint) = reserve_player_ref(|player| {
-      player.breakpoint_manager
-        .find_breakpoint_for_bytecode(unsafe { &(&*script_ptr).name }, &handler_name, bytecode_index)
-        .cloned()
+    if let Some(breakpoint) = reserve_player_mut(|player| {
+      let script_name = &ctx.script.name;
+      let breakpoint = player.breakpoint_manager.find_breakpoint_for_bytecode(script_name, &handler_name, bytecode_index).cloned();
+      if player.breakpoint_manager.step_requested {
+        Some(breakpoint.unwrap_or_else(|| Breakpoint {
+          script_name: script_name.to_owned(),
+          handler_name: handler_name.to_owned(),
+          bytecode_index,
+          condition: None,
+        }))
+      } else {
+        breakpoint.filter(|breakpoint| is_breakpoint_condition_met(breakpoint, scope_ref, player))
+      }
     }) {
+      reserve_player_mut(|player| player.breakpoint_manager.step_requested = false);
       player_trigger_breakpoint(
         breakpoint, 
         script_member_ref.to_owned(), 
         handler_ref.to_owned(), 
         bytecode_index,
+        invocation_id,
       ).await;
     }
+    // Stepping back while paused rewinds the scope, so its state is read after the pause
+    let step_snapshot = if is_stepping {
+      reserve_player_mut(|player| {
+        let scope = player.scopes.get(scope_ref).unwrap();
+        let snapshot = StepSnapshot::new(invocation_id, scope);
+        Some((snapshot, player.step_history.begin_step()))
+      })
+    } else {
+      None
+    };
     let result = player_execute_bytecode(&ctx).await?; // TODO catch error
 
     match result {
@@ -694,6 +1166,9 @@ pub async fn player_call_script_handler_raw_args(
       }
       HandlerExecutionResult::Jump => {}
     }
+    if let Some((snapshot, changes_start)) = step_snapshot {
+      reserve_player_mut(|player| player.step_history.push(snapshot, changes_start));
+    }
 
     // end_profiling(profile_token);
 
@@ -703,6 +1178,10 @@ pub async fn player_call_script_handler_raw_args(
   }
 
   let scope = reserve_player_mut(|player| {
+    player.step_history.end_invocation(invocation_id);
+    if let Some(profiler) = player.handler_profiler.as_mut() {
+      profiler.exit_to_scope(scope_ref, performance_now());
+    }
     let result = {
       let scope = player.scopes.get(scope_ref).unwrap();
       player.last_handler_result = scope.return_value.clone();
@@ -719,6 +1198,77 @@ pub async fn player_call_script_handler_raw_args(
   return Ok(scope);
 }
 
+/// Pauses or aborts a handler that has run past the watchdog budget.
+async fn player_handle_unresponsive_script(
+  action: WatchdogAction,
+  handler_ref: &ScriptHandlerRef,
+  script_name: &str,
+  bytecode_index: usize,
+  invocation_id: u32,
+) -> Result<(), ScriptError> {
+  let elapsed_ms = reserve_player_ref(|player| player.script_watchdog.elapsed_ms());
+  let message = format!("Script not responding: {} in {} has run for {:.0} ms", handler_ref.1, script_name, elapsed_ms);
+  match action {
+    WatchdogAction::Abort => Err(ScriptError::new(message)),
+    WatchdogAction::Pause => {
+      warn!("{}", message);
+      JsApi::dispatch_debug_message(&message);
+      let breakpoint = Breakpoint {
+        script_name: script_name.to_owned(),
+        handler_name: handler_ref.1.to_owned(),
+        bytecode_index,
+        condition: None,
+      };
+      player_trigger_breakpoint(breakpoint, handler_ref.0.to_owned(), handler_ref.to_owned(), bytecode_index, invocation_id).await;
+      Ok(())
+    }
+  }
+}
+
+/// Longest real-time wait between clock checks, so that time scale changes and
+/// steps of a paused clock are picked up promptly.
+const CLOCK_POLL_INTERVAL_MS: u64 = 50;
+
+/// Waits until the next frame is due, sending idle to the frame and movie scripts at
+/// least once and then at most every idleHandlerPeriod ticks. Idle is never sent
+/// once the frame is due, so slow idle handlers can't hold the frame back.
+async fn wait_frame_interval(fps: u32, send_idle: bool) {
+  let frame_due_ms = reserve_player_ref(|player| player.clock.elapsed_ms()) + 1000 / fps as i64;
+  let mut next_idle_ms = if send_idle { Some(0) } else { None };
+  loop {
+    let (elapsed_ms, scale) = reserve_player_ref(|player| (player.clock.elapsed_ms(), player.clock.time_scale()));
+    let remaining_ms = frame_due_ms - elapsed_ms;
+    if remaining_ms <= 0 && next_idle_ms != Some(0) {
+      break;
+    }
+    if matches!(next_idle_ms, Some(idle_ms) if elapsed_ms >= idle_ms) {
+      player_unwrap_result(player_invoke_frame_event(&"idle".to_string(), &vec![])).await;
+      player_wait_available().await;
+      let (can_idle, period_ms, elapsed_ms) = reserve_player_ref(|player| {
+        (
+          player.is_playing && !player.is_script_paused,
+          player.movie.idle_handler_period as i64 * 1000 / 60,
+          player.clock.elapsed_ms(),
+        )
+      });
+      next_idle_ms = if can_idle { Some(elapsed_ms + period_ms) } else { None };
+      // Let the browser breathe between idle events sent back to back
+      timeout(Duration::ZERO, future::pending::<()>()).await.unwrap_err();
+      continue;
+    }
+    let until_ms = match next_idle_ms {
+      Some(idle_ms) => remaining_ms.min(idle_ms - elapsed_ms),
+      None => remaining_ms,
+    };
+    let wait_ms = if scale > 0.0 {
+      ((until_ms as f64 / scale).ceil() as u64).min(CLOCK_POLL_INTERVAL_MS)
+    } else {
+      CLOCK_POLL_INTERVAL_MS
+    };
+    timeout(Duration::from_millis(wait_ms), future::pending::<()>()).await.unwrap_err();
+  }
+}
+
 pub async fn run_frame_loop() {
   // let player_arc = &PLAYER_LOCK;
   let mut fps: u32;
@@ -735,14 +1285,14 @@ pub async fn run_frame_loop() {
   while is_playing {
     if !is_script_paused {
       player_wait_available().await;
-      reserve_player_mut(|player| {
-        player.movie.score.begin_sprites(player.movie.current_frame);
-      });
+      player_begin_sprites().await;
+      player_unwrap_result(player_step_actors()).await;
       player_wait_available().await;
-      player_unwrap_result(player_invoke_global_event(&"prepareFrame".to_string(), &vec![]).await);
-      player_unwrap_result(player_invoke_global_event(&"enterFrame".to_string(), &vec![]).await);
+      player_unwrap_result(player_invoke_global_event(&"prepareFrame".to_string(), &vec![])).await;
+      player_unwrap_result(player_invoke_global_event(&"enterFrame".to_string(), &vec![])).await;
     }
-    timeout(Duration::from_millis(1000 / fps as u64), future::pending::<()>()).await.unwrap_err();
+    let can_idle = reserve_player_ref(|player| player.is_playing && !player.is_script_paused);
+    wait_frame_interval(fps, !is_script_paused && can_idle).await;
     player_wait_available().await;
 
     let mut prev_frame = 0;
@@ -767,6 +1317,7 @@ pub async fn run_frame_loop() {
     if new_frame > 1 && prev_frame <= 1 {
       unsafe {
         let player = PLAYER_OPT.as_mut().unwrap();
+        search_path::sync_search_paths(player);
         player.movie.cast_manager.preload_casts(
           CastPreloadReason::AfterFrameOne, 
           &mut player.net_manager, 
@@ -780,12 +1331,15 @@ pub async fn run_frame_loop() {
         player.next_frame.is_some() || !player.is_playing
       });
       if !frame_skipped {
-        player_unwrap_result(player_invoke_global_event(&"exitFrame".to_string(), &vec![]).await);
+        player_unwrap_result(player_invoke_global_event(&"exitFrame".to_string(), &vec![])).await;
       }
       let ended_sprite_nums = reserve_player_mut(|player| {
         let next_frame = player.get_next_frame(); // an exitFrame handler may have changed the next frame
         player.movie.score.end_sprites(prev_frame, next_frame)
       });
+      for sprite_num in ended_sprite_nums.iter() {
+        player_unwrap_result(player_invoke_event_to_sprite(&"endSprite".to_string(), &vec![], *sprite_num as u16)).await;
+      }
       player_wait_available().await;
       reserve_player_mut(|player| {
         for sprite_num in ended_sprite_nums.iter() {
@@ -795,29 +1349,60 @@ pub async fn run_frame_loop() {
       });
       (is_playing, is_script_paused) = reserve_player_mut(|player| {
         player.advance_frame();
+        player_dispatch_frame_hook(player);
         (player.is_playing, player.is_script_paused)
       });
+      if reserve_player_ref(|player| player.allocator.should_collect_garbage()) {
+        player_collect_garbage();
+      }
     };
   }
 }
 
-pub async fn player_trigger_breakpoint(breakpoint: Breakpoint, script_ref: CastMemberRef, handler_ref: ScriptHandlerRef, bytecode_index: usize) {
+/// Enters the sprites of the current frame and sends them beginSprite in channel order,
+/// each handler finishing before the next sprite's starts.
+async fn player_begin_sprites() {
+  let sprite_nums = reserve_player_mut(|player| player.movie.score.begin_sprites(player.movie.current_frame));
+  for sprite_num in sprite_nums {
+    player_unwrap_result(player_apply_property_defaults(sprite_num as i16)).await;
+    player_unwrap_result(player_invoke_event_to_sprite(&"beginSprite".to_string(), &vec![], sprite_num)).await;
+  }
+}
+
+/// Releases datums and script instances that are kept alive only by reference cycles.
+pub fn player_collect_garbage() {
+  let before_counts = reserve_player_ref(|player| (player.allocator.datum_count(), player.allocator.script_instance_count()));
+  let cycles = reserve_player_mut(|player| player.allocator.take_unreachable_cycles());
+  // Dropping outside of the allocator borrow, as each released ref frees its target
+  drop(cycles);
+  reserve_player_mut(|player| {
+    let freed_datums = before_counts.0.saturating_sub(player.allocator.datum_count());
+    let freed_script_instances = before_counts.1.saturating_sub(player.allocator.script_instance_count());
+    player.allocator.on_garbage_collected(freed_datums, freed_script_instances);
+  });
+}
+
+pub async fn player_trigger_breakpoint(breakpoint: Breakpoint, script_ref: CastMemberRef, handler_ref: ScriptHandlerRef, bytecode_index: usize, invocation_id: u32) {
   let (future, completer) = ManualFuture::new();
   let breakpoint_ctx = BreakpointContext {
     breakpoint,
     script_ref,
     handler_ref,
     bytecode_index,
+    invocation_id,
     completer,
   };
   reserve_player_mut(|player| {
     player.current_breakpoint = Some(breakpoint_ctx);
     player.pause_script();
     JsApi::dispatch_scope_list(player);
+    update_watch_expressions(player);
   });
   future.await;
   reserve_player_mut(|player| {
     player.resume_script();
+    // Time spent paused doesn't count towards the watchdog budget
+    player.script_watchdog.start();
   });
 }
 
@@ -844,6 +1429,7 @@ pub fn init_player() {
     PLAYER_TX = Some(tx.clone()); 
     PLAYER_EVENT_TX = Some(event_tx.clone());
     MULTIUSER_XTRA_MANAGER_OPT = Some(MultiuserXtraManager::new());
+    BUDDY_API_XTRA_MANAGER_OPT = Some(BuddyApiXtraManager::new());
   }
 
   unsafe {
@@ -867,7 +1453,7 @@ fn get_active_static_script_refs<'a>(
   movie: &'a Movie, 
   globals: &'a FxHashMap<String, &'a Datum>,
 ) -> Vec<CastMemberRef> {
-  let frame_script = movie.score.get_script_in_frame(movie.current_frame);
+  let frame_script = movie.score.get_static_script_in_frame(movie.current_frame);
   let movie_scripts = movie.cast_manager.get_movie_scripts();
   let movie_scripts = movie_scripts.as_ref().unwrap();
 
@@ -2,9 +2,12 @@ use std::collections::HashMap;
 
 use chrono::Local;
 
-use crate::{director::{file::DirectorFile, lingo::datum::{datum_bool, Datum}}, utils::{PATH_SEPARATOR}};
+use crate::{director::{file::DirectorFile, lingo::datum::{datum_bool, Datum}}, io::text_encoding::TextEncoding, utils::{PATH_SEPARATOR}};
 
-use super::{allocator::DatumAllocator, bitmap::manager::BitmapManager, cast_manager::CastManager, geometry::IntRect, net_manager::NetManager, score::Score, ScriptError, ScriptReceiver};
+use super::{allocator::DatumAllocator, bitmap::manager::BitmapManager, cast_manager::CastManager, date::LingoDate, geometry::IntRect, locale::Locale, net_manager::NetManager, score::Score, ScriptError, ScriptReceiver};
+
+/// `the itemDelimiter` of a movie whose locale doesn't have its own.
+const DEFAULT_ITEM_DELIMITER: char = '.';
 
 pub struct Movie {
   pub rect: IntRect,
@@ -15,15 +18,73 @@ pub struct Movie {
   pub exit_lock: bool,
   pub dir_version: u16,
   pub item_delimiter: char,
+  /// Decimal separator, default itemDelimiter and date format of the movie.
+  pub locale: Locale,
   pub alert_hook: Option<ScriptReceiver>,
   pub base_path: String,
   pub file_name: String,
   pub stage_color: (u8, u8, u8),
   pub frame_rate: u16,
   pub file: Option<DirectorFile>,
+  pub palette_mapping: bool,
+  /// Frames to return to on play done, one per active play excursion. Kept apart
+  /// from go, which never returns.
+  pub play_stack: Vec<u32>,
+  /// Net task of the movie file while its media is still streaming in.
+  pub stream_task_id: Option<u32>,
+  /// Minimum ticks between idle events, with 0 sending them as often as possible.
+  pub idle_handler_period: u32,
+  /// When idle time is used to load cast members. Only kept for scripts to read back.
+  pub idle_load_mode: u32,
+  /// Keeps the stage showing what it last drew while scripts change the score.
+  pub update_lock: bool,
+  /// The colorDepth a script asked for, in bits per pixel.
+  pub color_depth: Option<u16>,
 }
 
 impl Movie {
+  pub fn empty() -> Movie {
+    Movie {
+      rect: IntRect::from(0, 0, 0, 0),
+      cast_manager: CastManager::empty(),
+      score: Score::empty(),
+      current_frame: 1,
+      puppet_tempo: 0,
+      exit_lock: false,
+      dir_version: 0,
+      item_delimiter: DEFAULT_ITEM_DELIMITER,
+      locale: Locale::English,
+      alert_hook: None,
+      base_path: "".to_string(),
+      file_name: "".to_string(),
+      stage_color: (0, 0, 0),
+      frame_rate: 30,
+      file: None,
+      palette_mapping: false,
+      play_stack: vec![],
+      stream_task_id: None,
+      idle_handler_period: 1,
+      idle_load_mode: 0,
+      update_lock: false,
+      color_depth: None,
+    }
+  }
+
+  /// The encoding the movie's text was decoded with, Mac Roman until one is loaded.
+  pub fn text_encoding(&self) -> TextEncoding {
+    self.file.as_ref().map_or(TextEncoding::MacRoman, |file| file.text_encoding)
+  }
+
+  /// Starts a play excursion from the current frame.
+  pub fn push_play_return(&mut self) {
+    self.play_stack.push(self.current_frame);
+  }
+
+  /// Ends the innermost play excursion, returning the frame it was started from.
+  pub fn pop_play_return(&mut self) -> Option<u32> {
+    self.play_stack.pop()
+  }
+
   pub async fn load_from_file(
     &mut self, 
     file: DirectorFile, 
@@ -48,9 +109,16 @@ impl Movie {
     self.score.load_from_dir(&file);
     self.file_name = file.file_name.to_string();
     self.frame_rate = file.config.frame_rate;
+    // Movies don't record the locale they were made in, so they start out English
+    self.set_locale(Locale::English);
     self.file = Some(file);
   }
 
+  pub fn set_locale(&mut self, locale: Locale) {
+    self.locale = locale;
+    self.item_delimiter = locale.item_delimiter().unwrap_or(DEFAULT_ITEM_DELIMITER);
+  }
+
   pub fn get_prop(&self, prop: &str) -> Result<Datum, ScriptError> {
     match prop {
       "alertHook" => {
@@ -61,14 +129,18 @@ impl Movie {
         }
       }
       "exitLock" => Ok(datum_bool(self.exit_lock)),
+      "idleHandlerPeriod" => Ok(Datum::Int(self.idle_handler_period as i32)),
+      "idleLoadMode" => Ok(Datum::Int(self.idle_load_mode as i32)),
+      "updateLock" => Ok(datum_bool(self.update_lock)),
+      "paletteMapping" => Ok(datum_bool(self.palette_mapping)),
       "itemDelimiter" => Ok(Datum::String(self.item_delimiter.into())),
       "runMode" => Ok(Datum::String("Plugin".to_string())), // Plugin / Author
       "date" => {
-        // TODO localize formatting
         let time = Local::now();
-        let formatted = time.format("%m/%d/%Y").to_string();
+        let formatted = time.format(self.locale.date_format()).to_string();
         Ok(Datum::String(formatted))
       }
+      "systemDate" => Ok(Datum::Date(LingoDate::now())),
       "long time" => {
         let time = Local::now();
         let formatted = time.format("%H:%M:%S %p").to_string();
@@ -103,6 +175,18 @@ impl Movie {
       "exitLock" => {
         self.exit_lock = value.int_value()? == 1;
       },
+      "idleHandlerPeriod" => {
+        self.idle_handler_period = value.int_value()?.max(0) as u32;
+      },
+      "idleLoadMode" => {
+        self.idle_load_mode = value.int_value()?.clamp(0, 3) as u32;
+      },
+      "updateLock" => {
+        self.update_lock = value.to_bool()?;
+      },
+      "paletteMapping" => {
+        self.palette_mapping = value.to_bool()?;
+      },
       "itemDelimiter" => {
         self.item_delimiter = (value.string_value()?).as_bytes()[0] as char;
       },
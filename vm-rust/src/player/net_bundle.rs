@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::io::zip::read_zip;
+
+/// Files a host provides up front, such as a zip with a movie, its casts and media,
+/// so that movies whose servers are gone can run offline. Net tasks look here before
+/// going to the network.
+#[derive(Default)]
+pub struct NetBundle {
+  /// Keyed by lowercase path, since Director paths aren't case-sensitive on Windows.
+  files: HashMap<String, Vec<u8>>,
+}
+
+impl NetBundle {
+  pub fn from_zip(data: &[u8]) -> Result<NetBundle, String> {
+    let mut bundle = NetBundle::default();
+    for (name, data) in read_zip(data)? {
+      bundle.files.insert(normalize_bundle_path(&name), data);
+    }
+    Ok(bundle)
+  }
+
+  /// Finds the file for a URL by its host and path, falling back to shorter and shorter
+  /// ends of the path. `http://example.com/game/casts/a.cct` matches a bundle entry
+  /// named `example.com/game/casts/a.cct`, `game/casts/a.cct`, `casts/a.cct` or `a.cct`.
+  pub fn get(&self, url: &Url) -> Option<&Vec<u8>> {
+    let path = percent_decode_str(url.path()).decode_utf8_lossy();
+    let path = normalize_bundle_path(&path);
+    if let Some(host) = url.host_str() {
+      if let Some(data) = self.files.get(&format!("{}/{}", host.to_lowercase(), path)) {
+        return Some(data);
+      }
+    }
+    let mut suffix = path.as_str();
+    loop {
+      if let Some(data) = self.files.get(suffix) {
+        return Some(data);
+      }
+      match suffix.split_once('/') {
+        Some((_, rest)) => suffix = rest,
+        None => return None,
+      }
+    }
+  }
+}
+
+fn normalize_bundle_path(path: &str) -> String {
+  path.replace('\\', "/").trim_start_matches("./").trim_start_matches('/').to_lowercase()
+}
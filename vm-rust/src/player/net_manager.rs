@@ -1,30 +1,90 @@
-use std::{path::Path, collections::HashMap, sync::Arc};
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc};
 
 use async_std::sync::Mutex;
+use chrono::{DateTime, Local};
 use manual_future::{ManualFuture, ManualFutureCompleter};
 use url::Url;
 
-use super::net_task::{NetTask, NetResult, fetch_net_task, NetTaskState};
+use super::net_bundle::NetBundle;
+use super::net_policy::NetPolicy;
+use super::search_path::{get_fallback_urls, normalize_director_path, resolve_path_url};
+use super::net_task::{NetTask, NetResult, NetFetchResult, NetResponseInfo, fetch_net_task, fetch_net_task_revalidated, open_net_stream, read_net_stream, NetTaskState};
+
+/// How long a getNetText response is reused for identical requests before revalidating.
+pub const DEFAULT_NET_CACHE_TTL_MS: i64 = 1000;
+/// The most getNetText response data kept for reuse.
+const MAX_NET_CACHE_BYTES: usize = 8 * 1024 * 1024;
+/// getNetText tasks kept after their result was read, so that a script can still ask
+/// for netError after netTextResult.
+const MAX_READ_TEXT_TASKS: usize = 16;
 
 pub struct NetManager {
   pub base_path: Option<Url>,
   pub tasks: HashMap<u32, NetTask>,
-  pub task_states: HashMap<u32, NetTaskState>,
-  pub shared_state: Arc<Mutex<NetManagerSharedState>>
+  /// Id of the most recently started task, which net functions default to.
+  pub last_task_id: u32,
+  /// Tasks started by getNetText, which are dropped once their result was read.
+  pub text_task_ids: HashSet<u32>,
+  /// The finished getNetText tasks a script has read, oldest first.
+  pub read_text_task_ids: VecDeque<u32>,
+  pub shared_state: Arc<Mutex<NetManagerSharedState>>,
+  pub policy: NetPolicy,
+  /// Folders tried in order when a preloaded file isn't where it was first looked for.
+  pub search_paths: Vec<String>,
+  /// Checked before the network, with the most recently added bundle first.
+  pub bundles: Vec<NetBundle>,
 }
 
 pub struct NetManagerSharedState {
   pub task_states: HashMap<u32, NetTaskState>,
   pub task_completers: HashMap<u32, Vec<ManualFutureCompleter<()>>>,
+  /// Where tasks were found when it wasn't their first URL.
+  pub fallback_hits: HashMap<u32, Url>,
+  /// Data of streamed tasks that are still downloading.
+  pub streams: HashMap<u32, NetStreamState>,
+  /// Completed whenever a streamed task receives data or finishes.
+  pub progress_completers: HashMap<u32, Vec<ManualFutureCompleter<()>>>,
+  pub response_cache: HashMap<String, CachedNetResponse>,
+  pub cache_ttl_ms: i64,
+}
+
+pub struct NetStreamState {
+  pub data: Vec<u8>,
+  pub total: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct CachedNetResponse {
+  pub data: Vec<u8>,
+  pub response: Option<NetResponseInfo>,
+  pub fetched_at: DateTime<Local>,
+}
+
+impl CachedNetResponse {
+  pub fn is_fresh(&self, ttl_ms: i64) -> bool {
+    (Local::now() - self.fetched_at).num_milliseconds() < ttl_ms
+  }
 }
 
 impl NetManagerSharedState {
   pub fn new() -> NetManagerSharedState {
-    return NetManagerSharedState { task_states: HashMap::new(), task_completers: HashMap::new() }
+    return NetManagerSharedState {
+      task_states: HashMap::new(),
+      task_completers: HashMap::new(),
+      fallback_hits: HashMap::new(),
+      streams: HashMap::new(),
+      progress_completers: HashMap::new(),
+      response_cache: HashMap::new(),
+      cache_ttl_ms: DEFAULT_NET_CACHE_TTL_MS,
+    }
   }
 
-  pub async fn fulfill_task(&mut self, id: u32, result: NetResult) {
-    let new_state = NetTaskState { result: Some(result) };
+  /// Completes a task, unless it was already completed by the host.
+  pub async fn fulfill_task(&mut self, id: u32, result: NetResult, response: Option<NetResponseInfo>) {
+    if self.task_states.get(&id).is_some_and(|state| state.is_done()) {
+      return;
+    }
+    let new_state = NetTaskState { result: Some(result), response };
     self.task_states.insert(id, new_state);
 
     let completers_for_task = self.task_completers.get_mut(&id);
@@ -33,6 +93,22 @@ impl NetManagerSharedState {
         completer.complete(()).await;
       }
     }
+    self.notify_progress(id).await;
+  }
+
+  pub async fn append_stream_data(&mut self, id: u32, data: &[u8]) {
+    if let Some(stream) = self.streams.get_mut(&id) {
+      stream.data.extend_from_slice(data);
+    }
+    self.notify_progress(id).await;
+  }
+
+  async fn notify_progress(&mut self, id: u32) {
+    if let Some(completers) = self.progress_completers.get_mut(&id) {
+      while let Some(completer) = completers.pop() {
+        completer.complete(()).await;
+      }
+    }
   }
 
   pub fn add_completer(&mut self, task_id: u32, completer: ManualFutureCompleter<()>) {
@@ -46,6 +122,26 @@ impl NetManagerSharedState {
   pub fn update_task_state(&mut self, task_id: u32, state: NetTaskState) {
     self.task_states.insert(task_id, state);
   }
+
+  /// Remembers a getNetText response, dropping the expired ones and then the oldest
+  /// until the cache fits in MAX_NET_CACHE_BYTES.
+  pub fn cache_response(&mut self, key: String, response: CachedNetResponse) {
+    let ttl_ms = self.cache_ttl_ms;
+    self.response_cache.retain(|_, cached| cached.is_fresh(ttl_ms));
+    if response.data.len() > MAX_NET_CACHE_BYTES {
+      return;
+    }
+    self.response_cache.insert(key, response);
+    let mut cached_bytes: usize = self.response_cache.values().map(|cached| cached.data.len()).sum();
+    while cached_bytes > MAX_NET_CACHE_BYTES {
+      let oldest_key = self.response_cache.iter()
+        .min_by_key(|(_, cached)| cached.fetched_at)
+        .map(|(key, _)| key.to_owned())
+        .unwrap();
+      let oldest = self.response_cache.remove(&oldest_key).unwrap();
+      cached_bytes -= oldest.data.len();
+    }
+  }
 }
 
 impl NetManager {
@@ -58,13 +154,43 @@ impl NetManager {
 
   // TODO findTask
 
+  fn next_task_id(&mut self) -> u32 {
+    self.last_task_id += 1;
+    self.last_task_id
+  }
+
   pub fn get_task_state(&self, task_id: Option<u32>) -> Option<NetTaskState> {
     let shared_state = self.shared_state.try_lock().unwrap();
     let task_states = &shared_state.task_states;
-    let task_id = task_id.unwrap_or(task_states.len() as u32);
+    let task_id = task_id.unwrap_or(self.last_task_id);
     return task_states.get(&task_id).map(|x| x.clone());
   }
 
+  /// Called when a script has seen that a task is done or read its result. Finished
+  /// getNetText tasks are dropped once read, except the last MAX_READ_TEXT_TASKS, since
+  /// polling scripts start a new one for every request.
+  pub fn mark_task_read(&mut self, task_id: Option<u32>) {
+    let task_id = task_id.unwrap_or(self.last_task_id);
+    if !self.text_task_ids.contains(&task_id) || !self.is_task_done(Some(task_id)) || self.read_text_task_ids.contains(&task_id) {
+      return;
+    }
+    self.read_text_task_ids.push_back(task_id);
+    while self.read_text_task_ids.len() > MAX_READ_TEXT_TASKS {
+      let evicted_id = self.read_text_task_ids.pop_front().unwrap();
+      self.remove_task(evicted_id);
+    }
+  }
+
+  fn remove_task(&mut self, task_id: u32) {
+    self.tasks.remove(&task_id);
+    self.text_task_ids.remove(&task_id);
+    let mut shared_state = self.shared_state.try_lock().unwrap();
+    shared_state.task_states.remove(&task_id);
+    shared_state.task_completers.remove(&task_id);
+    shared_state.progress_completers.remove(&task_id);
+    shared_state.fallback_hits.remove(&task_id);
+  }
+
   pub fn is_task_done(&self, task_id: Option<u32>) -> bool {
     return self.get_task_state(task_id).map_or(false, |x| x.result.is_some());
   }
@@ -73,10 +199,61 @@ impl NetManager {
     return self.get_task_state(task_id).and_then(|x| x.result);
   }
 
+  pub fn get_task_response(&self, task_id: Option<u32>) -> Option<NetResponseInfo> {
+    self.get_task_state(task_id).and_then(|x| x.response)
+  }
+
   pub fn get_task(&self, task_id: u32) -> Option<&NetTask> {
     return self.tasks.get(&task_id);
   }
 
+  /// The URL a task was downloaded from, which is one of its fallbacks if it
+  /// wasn't found at its resolved URL.
+  pub fn get_task_url(&self, task_id: u32) -> Option<Url> {
+    let shared_state = self.shared_state.try_lock().unwrap();
+    shared_state.fallback_hits.get(&task_id).cloned()
+      .or_else(|| self.get_task(task_id).map(|task| task.resolved_url.clone()))
+  }
+
+  /// Bytes received so far and the expected total, if known. Tasks that aren't
+  /// streamed only report their size once they're done.
+  pub fn get_task_progress(&self, task_id: u32) -> (usize, Option<usize>) {
+    let shared_state = self.shared_state.try_lock().unwrap();
+    if let Some(stream) = shared_state.streams.get(&task_id) {
+      return (stream.data.len(), stream.total);
+    }
+    match shared_state.task_states.get(&task_id).and_then(|state| state.result.as_ref()) {
+      Some(Ok(data)) => (data.len(), Some(data.len())),
+      _ => (0, None),
+    }
+  }
+
+  /// Runs `f` on the data of a streamed task downloaded so far, or on all of it once
+  /// the task is done. None if the task failed or hasn't started receiving data.
+  pub fn with_streamed_data<T, F>(&self, task_id: u32, f: F) -> Option<T> where F: FnOnce(&[u8]) -> T {
+    let shared_state = self.shared_state.try_lock().unwrap();
+    if let Some(stream) = shared_state.streams.get(&task_id) {
+      return Some(f(&stream.data));
+    }
+    match shared_state.task_states.get(&task_id).and_then(|state| state.result.as_ref()) {
+      Some(Ok(data)) => Some(f(data)),
+      _ => None,
+    }
+  }
+
+  /// Waits until a streamed task receives more data or finishes.
+  pub async fn await_task_progress(&mut self, task_id: u32) {
+    if self.is_task_done(Some(task_id)) {
+      return;
+    }
+    let (future, completer) = ManualFuture::<()>::new();
+    {
+      let mut shared_state = self.shared_state.try_lock().unwrap();
+      shared_state.progress_completers.entry(task_id).or_default().push(completer);
+    }
+    future.await;
+  }
+
   pub fn create_task_future(&mut self, task_id: u32) -> ManualFuture<()> {
     let state = self.get_task_state(Some(task_id));
     if state.is_some() && state.unwrap().result.is_some() {
@@ -115,19 +292,28 @@ impl NetManager {
     
     // If not, construct the task outside of the borrowing scope
     let net_task = {
-      let id = self.tasks.len() + 1;
-      NetTask::new(id as u32, &url, &normalize_task_url(&url, self.base_path.as_ref()))
+      let id = self.next_task_id();
+      let mut task = NetTask::new(id, &url, &self.policy.apply_to_url(normalize_task_url(&url, self.base_path.as_ref())));
+      task.fallback_urls = get_fallback_urls(&normalize_director_path(&url), self.base_path.as_ref(), &self.search_paths)
+        .into_iter()
+        .map(|fallback_url| self.policy.apply_to_url(fallback_url))
+        .filter(|fallback_url| *fallback_url != task.resolved_url)
+        .collect();
+      task
     };
     let task_id = net_task.id;
 
     // Set task initial state
     {
       let mut shared_shared = self.shared_state.try_lock().unwrap();
-      shared_shared.update_task_state(task_id, NetTaskState { result: None });
+      shared_shared.update_task_state(task_id, NetTaskState { result: None, response: None });
     }
 
     // Push the task and execute it
     self.tasks.insert(task_id, net_task.clone());
+    if self.fulfill_task_from_bundle(&net_task) {
+      return task_id;
+    }
 
     let shared_state_arc = Arc::clone(&self.shared_state);
     async_std::task::spawn_local(async move { 
@@ -137,14 +323,177 @@ impl NetManager {
     task_id
   }
 
+  /// Like preload_net_thing, but the data can be read while it's downloading. Used for
+  /// movies, which can start before their media has arrived.
+  pub fn stream_net_thing(&mut self, url: String) -> u32 {
+    if let Some(existing_task) = find_task_with_url(&self.tasks, &url) {
+      return existing_task.id;
+    }
+
+    let net_task = {
+      let id = self.next_task_id();
+      NetTask::new(id, &url, &self.policy.apply_to_url(normalize_task_url(&url, self.base_path.as_ref())))
+    };
+    let task_id = net_task.id;
+
+    {
+      let mut shared_shared = self.shared_state.try_lock().unwrap();
+      shared_shared.update_task_state(task_id, NetTaskState { result: None, response: None });
+    }
+
+    self.tasks.insert(task_id, net_task.clone());
+    if self.fulfill_task_from_bundle(&net_task) {
+      return task_id;
+    }
+
+    let shared_state_arc = Arc::clone(&self.shared_state);
+    async_std::task::spawn_local(async move {
+      Self::execute_streamed_task(task_id, net_task, shared_state_arc).await;
+    });
+
+    task_id
+  }
+
+  /// Starts a new task for every call so that polling scripts see fresh data. Identical
+  /// requests within the cache TTL are answered from memory, and older ones are
+  /// revalidated against the browser's HTTP cache.
+  pub fn get_net_text(&mut self, url: String) -> u32 {
+    let net_task = {
+      let id = self.next_task_id();
+      NetTask::new(id, &url, &self.policy.apply_to_url(normalize_task_url(&url, self.base_path.as_ref())))
+    };
+    let task_id = net_task.id;
+
+    {
+      let mut shared_shared = self.shared_state.try_lock().unwrap();
+      shared_shared.update_task_state(task_id, NetTaskState { result: None, response: None });
+    }
+
+    self.tasks.insert(task_id, net_task.clone());
+    self.text_task_ids.insert(task_id);
+    if self.fulfill_task_from_bundle(&net_task) {
+      return task_id;
+    }
+
+    let shared_state_arc = Arc::clone(&self.shared_state);
+    async_std::task::spawn_local(async move {
+      Self::execute_cached_task(task_id, net_task, shared_state_arc).await;
+    });
+
+    task_id
+  }
+
+  pub fn add_bundle(&mut self, bundle: NetBundle) {
+    self.bundles.insert(0, bundle);
+  }
+
+  /// Completes a task that was just created with a bundled file, trying its resolved
+  /// URL and then its fallbacks. Nothing can be waiting on the task yet, so setting its
+  /// state is enough.
+  fn fulfill_task_from_bundle(&mut self, task: &NetTask) -> bool {
+    let found = std::iter::once(&task.resolved_url)
+      .chain(task.fallback_urls.iter())
+      .find_map(|url| self.bundles.iter().find_map(|bundle| bundle.get(url)).map(|data| (url, data)));
+    let (url, data) = match found {
+      Some(found) => found,
+      None => return false,
+    };
+    let mut shared_state = self.shared_state.try_lock().unwrap();
+    if *url != task.resolved_url {
+      shared_state.fallback_hits.insert(task.id, url.clone());
+    }
+    shared_state.update_task_state(task.id, NetTaskState { result: Some(Ok(data.clone())), response: None });
+    true
+  }
+
+  pub fn set_cache_ttl(&mut self, ttl_ms: i64) {
+    let mut shared_state = self.shared_state.try_lock().unwrap();
+    shared_state.cache_ttl_ms = ttl_ms;
+  }
+
+  async fn execute_cached_task(
+    id: u32,
+    task: NetTask,
+    shared_state_arc: Arc<Mutex<NetManagerSharedState>>,
+  ) {
+    let cache_key = task.resolved_url.to_string();
+    let cached = {
+      let shared_state = shared_state_arc.lock().await;
+      shared_state.response_cache.get(&cache_key)
+        .filter(|x| x.is_fresh(shared_state.cache_ttl_ms))
+        .cloned()
+    };
+    let fetched = match cached {
+      Some(cached) => NetFetchResult { result: Ok(cached.data), response: cached.response },
+      None => fetch_net_task_revalidated(&task).await,
+    };
+
+    let mut shared_state = shared_state_arc.lock().await;
+    if let Ok(data) = &fetched.result {
+      shared_state.cache_response(cache_key, CachedNetResponse {
+        data: data.clone(),
+        response: fetched.response.clone(),
+        fetched_at: Local::now(),
+      });
+    }
+    shared_state.fulfill_task(id, fetched.result, fetched.response).await;
+  }
+
   async fn execute_task(
     id: u32, 
     task: NetTask, 
     shared_state_arc: Arc<Mutex<NetManagerSharedState>>,
   ) {
-    let result = fetch_net_task(&task).await;
+    // When no fallback has the file either, the error is the one from the resolved URL
+    let mut fetched = fetch_net_task(&task).await;
+    let mut fallback_hit = None;
+    if fetched.result.is_err() {
+      for fallback_url in &task.fallback_urls {
+        let fallback_fetched = fetch_net_task(&NetTask::new(id, &task.url, fallback_url)).await;
+        if fallback_fetched.result.is_ok() {
+          fetched = fallback_fetched;
+          fallback_hit = Some(fallback_url.clone());
+          break;
+        }
+      }
+    }
+    let mut shared_state = shared_state_arc.lock().await;
+    if let Some(fallback_url) = fallback_hit {
+      shared_state.fallback_hits.insert(id, fallback_url);
+    }
+    shared_state.fulfill_task(id, fetched.result, fetched.response).await;
+  }
+
+  async fn execute_streamed_task(
+    id: u32,
+    task: NetTask,
+    shared_state_arc: Arc<Mutex<NetManagerSharedState>>,
+  ) {
+    let (result, response) = match open_net_stream(&task).await {
+      Ok((reader, response)) => {
+        let total = response.get_header("content-length").and_then(|len| len.parse().ok());
+        {
+          let mut shared_state = shared_state_arc.lock().await;
+          // The length comes from the server, so a bogus one only reserves up to the cache limit
+          let capacity = total.unwrap_or(0).min(MAX_NET_CACHE_BYTES);
+          shared_state.streams.insert(id, NetStreamState { data: Vec::with_capacity(capacity), total });
+        }
+        let result = loop {
+          match read_net_stream(&reader).await {
+            Ok(Some(data)) => shared_state_arc.lock().await.append_stream_data(id, &data).await,
+            Ok(None) => break Ok(()),
+            Err(err) => break Err(err),
+          }
+        };
+        (result, Some(response))
+      }
+      Err(fetched) => (fetched.result.map(|_| ()), fetched.response),
+    };
+
     let mut shared_state = shared_state_arc.lock().await;
-    shared_state.fulfill_task(id, result).await;
+    let stream = shared_state.streams.remove(&id);
+    let result = result.map(|_| stream.map(|stream| stream.data).unwrap_or_default());
+    shared_state.fulfill_task(id, result, response).await;
   }
 
   // pub fn get_base_path(&self) -> String {
@@ -153,23 +502,7 @@ impl NetManager {
 }
 
 fn normalize_task_url(url: &String, base_path: Option<&Url>) -> Url {
-  let slash_norm = url.replace("\\", "/");
-  let parsed_path = Path::new(slash_norm.as_str());
-  let parsed_url = Url::parse(&slash_norm);
-
-  if let Ok(parsed_url) = parsed_url {
-    if parsed_url.has_host() {
-      return parsed_url;
-    }
-  }
-
-  if parsed_path.is_absolute() {
-    return Url::parse(format!("file:///{slash_norm}").as_str()).unwrap();
-  } else if let Some(base_path) = base_path {
-    return base_path.join(url.as_str()).unwrap();
-  } else {
-    return Url::parse(&slash_norm).unwrap();
-  }
+  resolve_path_url(&normalize_director_path(url), base_path)
 }
 
 pub fn find_task_with_url<'a>(tasks: &'a HashMap<u32, NetTask>, url: &String) -> Option<&'a NetTask> {
@@ -0,0 +1,96 @@
+use url::Url;
+
+use crate::utils::page_is_secure;
+
+/// Maps a host that a movie connects to onto another address. `from` is either
+/// `host` or `host:port`, and `to` is either `host:port` or a full URL, which lets
+/// Multiuser servers sit behind a TLS proxy such as `wss://proxy.example.com/mus`.
+/// When `from` is a URL itself, net task URLs starting with it have that part
+/// replaced by `to`, e.g. `http://old.example.com/game/` to `/mirror/game/`.
+#[derive(Clone)]
+pub struct NetRewriteRule {
+  pub from: String,
+  pub to: String,
+}
+
+/// Decides where net calls and Multiuser connections actually go. Browsers block
+/// plain `http://` and `ws://` connections from secure pages, so those are upgraded
+/// when the page itself was served over TLS.
+pub struct NetPolicy {
+  pub upgrade_insecure: bool,
+  pub rewrite_rules: Vec<NetRewriteRule>,
+}
+
+impl NetPolicy {
+  pub fn new() -> NetPolicy {
+    NetPolicy {
+      upgrade_insecure: page_is_secure(),
+      rewrite_rules: vec![],
+    }
+  }
+
+  pub fn add_rewrite_rule(&mut self, from: String, to: String) {
+    self.rewrite_rules.retain(|rule| !rule.from.eq_ignore_ascii_case(&from));
+    self.rewrite_rules.push(NetRewriteRule { from, to });
+  }
+
+  fn find_rewrite_rule(&self, host: &str, port: Option<u16>) -> Option<&NetRewriteRule> {
+    let host_port = port.map(|port| format!("{}:{}", host, port));
+    // Rules for a specific port take precedence over host-wide ones
+    host_port
+      .and_then(|host_port| self.rewrite_rules.iter().find(|rule| rule.from.eq_ignore_ascii_case(&host_port)))
+      .or_else(|| self.rewrite_rules.iter().find(|rule| rule.from.eq_ignore_ascii_case(host)))
+  }
+
+  /// The URL with the longest matching URL prefix rule replaced, resolved against the
+  /// original URL when the replacement is relative.
+  fn apply_prefix_rules(&self, url: &Url) -> Option<Url> {
+    let url_string = url.to_string();
+    let rule = self.rewrite_rules.iter()
+      .filter(|rule| rule.from.contains("://"))
+      .filter(|rule| url_string.get(..rule.from.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(&rule.from)))
+      .max_by_key(|rule| rule.from.len())?;
+    let rewritten = format!("{}{}", rule.to, &url_string[rule.from.len()..]);
+    url.join(&rewritten).ok()
+  }
+
+  /// Resolves the WebSocket URL used to reach a Multiuser server.
+  pub fn websocket_url(&self, host: &str, port: i32) -> String {
+    let url = match self.find_rewrite_rule(host, Some(port as u16)) {
+      Some(rule) if rule.to.contains("://") => rule.to.clone(),
+      Some(rule) => format!("ws://{}", rule.to),
+      None => format!("ws://{}:{}", host, port),
+    };
+    match url.strip_prefix("ws://") {
+      Some(rest) if self.upgrade_insecure => format!("wss://{}", rest),
+      _ => url,
+    }
+  }
+
+  /// Applies rewrite rules and the HTTPS upgrade to a resolved net task URL. Host
+  /// rules only replace the scheme, host and port; the path is kept.
+  pub fn apply_to_url(&self, url: Url) -> Url {
+    if let Some(rewritten) = self.apply_prefix_rules(&url) {
+      return self.upgrade_url(rewritten);
+    }
+    let mut url = url;
+    let rule = url.host_str().and_then(|host| self.find_rewrite_rule(host, url.port()));
+    if let Some(rule) = rule {
+      let target = if rule.to.contains("://") { rule.to.clone() } else { format!("{}://{}", url.scheme(), rule.to) };
+      if let Ok(target) = Url::parse(&target) {
+        let _ = url.set_scheme(target.scheme());
+        let _ = url.set_host(target.host_str());
+        let _ = url.set_port(target.port());
+      }
+    }
+    self.upgrade_url(url)
+  }
+
+  fn upgrade_url(&self, url: Url) -> Url {
+    let mut url = url;
+    if self.upgrade_insecure && url.scheme() == "http" {
+      let _ = url.set_scheme("https");
+    }
+    url
+  }
+}
@@ -1,17 +1,76 @@
-use itertools::Itertools;
 use js_sys::Uint8Array;
 use url::Url;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
-use web_sys::Response;
+use web_sys::{ReadableStreamDefaultReader, Request, RequestCache, RequestInit, Response};
 
-use crate::utils::log_i;
+use crate::utils::{fetch_with_request, fetch_with_str, log_i};
 
 pub type NetResult = Result<Vec<u8>, i32>;
 
+/// netError codes, as Director reports them.
+pub const NET_ERROR_CONNECTION_FAILED: i32 = 4146;
+pub const NET_ERROR_UNEXPECTED_FORMAT: i32 = 4149;
+pub const NET_ERROR_CONNECTION_CLOSED: i32 = 4150;
+pub const NET_ERROR_TIMEOUT: i32 = 4154;
+pub const NET_ERROR_PROTOCOL: i32 = 4156;
+pub const NET_ERROR_NOT_AUTHENTICATED: i32 = 4157;
+pub const NET_ERROR_INVALID_URL: i32 = 4159;
+pub const NET_ERROR_NOT_FOUND: i32 = 4165;
+
+/// The netError code Director gives for an HTTP error status.
+pub fn get_net_error_for_status(status: u16) -> i32 {
+  match status {
+    401 | 403 | 407 => NET_ERROR_NOT_AUTHENTICATED,
+    404 | 410 => NET_ERROR_NOT_FOUND,
+    408 | 504 => NET_ERROR_TIMEOUT,
+    _ => NET_ERROR_PROTOCOL,
+  }
+}
+
+/// The HTTP response a task got, including failed ones. Tasks answered from a bundle
+/// or by the host without a status don't have one.
+#[derive(Clone, Default)]
+pub struct NetResponseInfo {
+  pub status: u16,
+  pub headers: Vec<(String, String)>,
+}
+
+impl NetResponseInfo {
+  fn from_response(resp: &Response) -> NetResponseInfo {
+    let headers = js_sys::try_iter(&resp.headers()).ok().flatten()
+      .map(|entries| entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+          let entry = js_sys::Array::from(&entry);
+          Some((entry.get(0).as_string()?, entry.get(1).as_string()?))
+        })
+        .collect())
+      .unwrap_or_default();
+    NetResponseInfo { status: resp.status(), headers }
+  }
+
+  pub fn get_header(&self, name: &str) -> Option<&str> {
+    self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+  }
+}
+
+/// What fetching a task produced, along with the response if the server answered.
+pub struct NetFetchResult {
+  pub result: NetResult,
+  pub response: Option<NetResponseInfo>,
+}
+
+impl NetFetchResult {
+  fn failed(code: i32, response: Option<NetResponseInfo>) -> NetFetchResult {
+    NetFetchResult { result: Err(code), response }
+  }
+}
+
 #[derive(Clone)]
 pub struct NetTaskState {
-  pub result: Option<NetResult>
+  pub result: Option<NetResult>,
+  pub response: Option<NetResponseInfo>,
 }
 
 #[derive(Clone)]
@@ -19,6 +78,8 @@ pub struct NetTask {
   pub id: u32,
   pub url: String,
   pub resolved_url: Url,
+  /// Tried in order when the file isn't found at the resolved URL.
+  pub fallback_urls: Vec<Url>,
 }
 
 impl NetTask {
@@ -27,6 +88,7 @@ impl NetTask {
       id: id.clone().to_owned(),
       url: url.clone().to_owned(),
       resolved_url: resolved_url.clone().to_owned(),
+      fallback_urls: vec![],
     };
   }
 }
@@ -37,26 +99,73 @@ impl NetTaskState {
   }
 }
 
-pub async fn fetch_net_task(task: &NetTask) -> NetResult {
+pub async fn fetch_net_task(task: &NetTask) -> NetFetchResult {
   log_i(format_args!("execute_task #{} url: {} resolved: {}", task.id, task.url, task.resolved_url.to_string()).to_string().as_str());
 
-  let task_result: NetResult;
-  let window = web_sys::window().unwrap();
-  let resp_result = JsFuture::from(window.fetch_with_str(&task.resolved_url.to_string())).await;
-  if let Ok(resp_value) = resp_result {
-    assert!(resp_value.is_instance_of::<Response>());
-    let resp: Response = resp_value.dyn_into().unwrap();
-    if resp.status() == 200 {
-      let blob = JsFuture::from(resp.array_buffer().unwrap()).await.unwrap();
-      let blob_buffer: Uint8Array = js_sys::Uint8Array::new(&blob);
-      
-      task_result = Ok(blob_buffer.to_vec().iter().map(|x| *x as u8).collect_vec());
-    } else {
-      task_result = Err(4); // TODO: Error code
-    }
+  read_net_response(JsFuture::from(fetch_with_str(&task.resolved_url.to_string())).await).await
+}
+
+/// Fetches a task that may have been requested before, asking the browser to revalidate
+/// its cached copy. The HTTP cache then sends If-None-Match/If-Modified-Since from the
+/// stored ETag/Last-Modified and hands back the cached body on a 304.
+pub async fn fetch_net_task_revalidated(task: &NetTask) -> NetFetchResult {
+  log_i(format_args!("execute_task #{} url: {} resolved: {} (revalidate)", task.id, task.url, task.resolved_url).to_string().as_str());
+
+  let init = RequestInit::new();
+  init.set_cache(RequestCache::NoCache);
+  let request = Request::new_with_str_and_init(task.resolved_url.as_str(), &init);
+  if let Ok(request) = request {
+    read_net_response(JsFuture::from(fetch_with_request(&request)).await).await
   } else {
-    task_result = Err(4); // TODO: Error code
+    NetFetchResult::failed(NET_ERROR_INVALID_URL, None)
+  }
+}
+
+/// Starts fetching a task without waiting for the body, returning a reader for the
+/// body and the response, whose Content-Length is the size when the server sends it.
+pub async fn open_net_stream(task: &NetTask) -> Result<(ReadableStreamDefaultReader, NetResponseInfo), NetFetchResult> {
+  log_i(format_args!("execute_task #{} url: {} resolved: {} (streamed)", task.id, task.url, task.resolved_url).to_string().as_str());
+
+  let resp_value = JsFuture::from(fetch_with_str(task.resolved_url.as_str())).await
+    .map_err(|_| NetFetchResult::failed(NET_ERROR_CONNECTION_FAILED, None))?;
+  let resp: Response = resp_value.dyn_into().map_err(|_| NetFetchResult::failed(NET_ERROR_CONNECTION_FAILED, None))?;
+  let response = NetResponseInfo::from_response(&resp);
+  if !resp.ok() {
+    return Err(NetFetchResult::failed(get_net_error_for_status(resp.status()), Some(response)));
+  }
+  match resp.body() {
+    Some(body) => Ok((body.get_reader().unchecked_into(), response)),
+    None => Err(NetFetchResult::failed(NET_ERROR_UNEXPECTED_FORMAT, Some(response))),
   }
+}
 
-  return task_result;
+/// Reads the next part of a streamed body, None once the body is complete.
+pub async fn read_net_stream(reader: &ReadableStreamDefaultReader) -> Result<Option<Vec<u8>>, i32> {
+  let result = JsFuture::from(reader.read()).await.map_err(|_| NET_ERROR_CONNECTION_CLOSED)?;
+  let is_done = js_sys::Reflect::get(&result, &"done".into()).map_or(true, |done| done.is_truthy());
+  if is_done {
+    return Ok(None);
+  }
+  let value = js_sys::Reflect::get(&result, &"value".into()).map_err(|_| NET_ERROR_UNEXPECTED_FORMAT)?;
+  Ok(Some(Uint8Array::new(&value).to_vec()))
+}
+
+async fn read_net_response(resp_result: Result<wasm_bindgen::JsValue, wasm_bindgen::JsValue>) -> NetFetchResult {
+  // fetch only rejects when the server couldn't be reached at all
+  let resp: Response = match resp_result.ok().and_then(|resp_value| resp_value.dyn_into().ok()) {
+    Some(resp) => resp,
+    None => return NetFetchResult::failed(NET_ERROR_CONNECTION_FAILED, None),
+  };
+  let response = NetResponseInfo::from_response(&resp);
+  if !resp.ok() {
+    return NetFetchResult::failed(get_net_error_for_status(resp.status()), Some(response));
+  }
+  let body = match resp.array_buffer() {
+    Ok(body) => JsFuture::from(body).await,
+    Err(err) => Err(err),
+  };
+  let result = body
+    .map(|blob| Uint8Array::new(&blob).to_vec())
+    .map_err(|_| NET_ERROR_CONNECTION_CLOSED);
+  NetFetchResult { result, response: Some(response) }
 }
@@ -1,6 +1,11 @@
 use std::{collections::HashMap, sync::{Arc, Mutex, OnceLock}, time::Duration};
 use fxhash::FxHashMap;
 use itertools::Itertools;
+use wasm_bindgen::JsValue;
+
+use crate::js_api::JsUtils;
+
+use super::{scope::ScopeRef, script::ScriptHandlerRef};
 
 pub struct ProfilingToken {
   name: String,
@@ -97,3 +102,185 @@ pub fn get_profiler_report() -> String {
   let profiler = profiler().lock().unwrap();
   profiler.report()
 }
+
+/// Events kept for the flame graph. Totals keep counting after the limit is reached.
+const MAX_PROFILE_EVENTS: usize = 1_000_000;
+
+#[derive(Default)]
+pub struct HandlerStats {
+  pub call_count: u32,
+  /// Time spent in the handler, including the handlers it called.
+  pub inclusive_ms: f64,
+  /// Time spent in the handler itself.
+  pub exclusive_ms: f64,
+}
+
+#[derive(Default)]
+pub struct CallEdgeStats {
+  pub call_count: u32,
+  pub inclusive_ms: f64,
+}
+
+struct ProfiledCall {
+  handler_index: usize,
+  scope_ref: ScopeRef,
+  start_ms: f64,
+  child_ms: f64,
+  is_event_recorded: bool,
+}
+
+struct ProfileEvent {
+  is_open: bool,
+  handler_index: usize,
+  at_ms: f64,
+}
+
+/// Times every Lingo handler call, so users can find the handlers that slow a movie down.
+pub struct HandlerProfiler {
+  start_ms: f64,
+  handler_names: Vec<String>,
+  handler_indices: FxHashMap<ScriptHandlerRef, usize>,
+  handler_stats: Vec<HandlerStats>,
+  /// Calls from one handler to another, None standing for calls made by the player.
+  edges: FxHashMap<(Option<usize>, usize), CallEdgeStats>,
+  call_stack: Vec<ProfiledCall>,
+  events: Vec<ProfileEvent>,
+}
+
+impl HandlerProfiler {
+  pub fn new(now_ms: f64) -> HandlerProfiler {
+    HandlerProfiler {
+      start_ms: now_ms,
+      handler_names: vec![],
+      handler_indices: FxHashMap::default(),
+      handler_stats: vec![],
+      edges: FxHashMap::default(),
+      call_stack: vec![],
+      events: vec![],
+    }
+  }
+
+  pub fn enter(&mut self, handler_ref: &ScriptHandlerRef, script_name: &str, scope_ref: ScopeRef, now_ms: f64) {
+    // Calls left open by a script error are closed once their scope is reused
+    self.exit_to_scope(scope_ref, now_ms);
+    let handler_index = match self.handler_indices.get(handler_ref) {
+      Some(handler_index) => *handler_index,
+      None => {
+        let handler_index = self.handler_names.len();
+        self.handler_names.push(format!("{} ({})", handler_ref.1, script_name));
+        self.handler_stats.push(HandlerStats::default());
+        self.handler_indices.insert(handler_ref.clone(), handler_index);
+        handler_index
+      }
+    };
+    let is_event_recorded = self.events.len() < MAX_PROFILE_EVENTS;
+    if is_event_recorded {
+      self.events.push(ProfileEvent { is_open: true, handler_index, at_ms: now_ms - self.start_ms });
+    }
+    self.call_stack.push(ProfiledCall { handler_index, scope_ref, start_ms: now_ms, child_ms: 0.0, is_event_recorded });
+  }
+
+  /// Closes the calls running in the given scope and the ones above it.
+  pub fn exit_to_scope(&mut self, scope_ref: ScopeRef, now_ms: f64) {
+    while self.call_stack.last().is_some_and(|call| call.scope_ref >= scope_ref) {
+      let call = self.call_stack.pop().unwrap();
+      let elapsed_ms = now_ms - call.start_ms;
+      let stats = &mut self.handler_stats[call.handler_index];
+      stats.call_count += 1;
+      stats.inclusive_ms += elapsed_ms;
+      stats.exclusive_ms += elapsed_ms - call.child_ms;
+
+      let caller = self.call_stack.last_mut();
+      let caller_index = caller.as_ref().map(|caller| caller.handler_index);
+      if let Some(caller) = caller {
+        caller.child_ms += elapsed_ms;
+      }
+      let edge = self.edges.entry((caller_index, call.handler_index)).or_default();
+      edge.call_count += 1;
+      edge.inclusive_ms += elapsed_ms;
+
+      if call.is_event_recorded {
+        self.events.push(ProfileEvent { is_open: false, handler_index: call.handler_index, at_ms: now_ms - self.start_ms });
+      }
+    }
+  }
+
+  /// Totals per handler sorted by exclusive time, and the edges of the call tree.
+  pub fn summary_json(&self) -> String {
+    let handlers = js_sys::Array::new();
+    for handler_index in (0..self.handler_stats.len()).sorted_by(|a, b| {
+      self.handler_stats[*b].exclusive_ms.total_cmp(&self.handler_stats[*a].exclusive_ms)
+    }) {
+      let stats = &self.handler_stats[handler_index];
+      let entry = js_sys::Map::new();
+      entry.str_set("handler", &JsValue::from_str(&self.handler_names[handler_index]));
+      entry.str_set("callCount", &JsValue::from_f64(stats.call_count as f64));
+      entry.str_set("inclusiveMs", &JsValue::from_f64(stats.inclusive_ms));
+      entry.str_set("exclusiveMs", &JsValue::from_f64(stats.exclusive_ms));
+      handlers.push(&js_sys::Object::from_entries(&entry).unwrap());
+    }
+    let edges = js_sys::Array::new();
+    for ((caller_index, callee_index), stats) in &self.edges {
+      let entry = js_sys::Map::new();
+      let caller = caller_index.map_or(JsValue::NULL, |caller_index| JsValue::from_str(&self.handler_names[caller_index]));
+      entry.str_set("caller", &caller);
+      entry.str_set("callee", &JsValue::from_str(&self.handler_names[*callee_index]));
+      entry.str_set("callCount", &JsValue::from_f64(stats.call_count as f64));
+      entry.str_set("inclusiveMs", &JsValue::from_f64(stats.inclusive_ms));
+      edges.push(&js_sys::Object::from_entries(&entry).unwrap());
+    }
+    let summary = js_sys::Map::new();
+    summary.str_set("handlers", &handlers);
+    summary.str_set("edges", &edges);
+    stringify(&js_sys::Object::from_entries(&summary).unwrap())
+  }
+
+  /// The recorded calls as an evented profile in the speedscope file format
+  /// (https://www.speedscope.app/file-format-schema.json). Calls still running are
+  /// shown as ending now.
+  pub fn speedscope_json(&self, now_ms: f64) -> String {
+    let frames = js_sys::Array::new();
+    for name in &self.handler_names {
+      let frame = js_sys::Map::new();
+      frame.str_set("name", &JsValue::from_str(name));
+      frames.push(&js_sys::Object::from_entries(&frame).unwrap());
+    }
+    let end_ms = now_ms - self.start_ms;
+    let events = js_sys::Array::new();
+    let open_events = self.call_stack.iter()
+      .rev()
+      .filter(|call| call.is_event_recorded)
+      .map(|call| ProfileEvent { is_open: false, handler_index: call.handler_index, at_ms: end_ms })
+      .collect_vec();
+    for event in self.events.iter().chain(open_events.iter()) {
+      let event_map = js_sys::Map::new();
+      event_map.str_set("type", &JsValue::from_str(if event.is_open { "O" } else { "C" }));
+      event_map.str_set("frame", &JsValue::from_f64(event.handler_index as f64));
+      event_map.str_set("at", &JsValue::from_f64(event.at_ms));
+      events.push(&js_sys::Object::from_entries(&event_map).unwrap());
+    }
+
+    let profile = js_sys::Map::new();
+    profile.str_set("type", &JsValue::from_str("evented"));
+    profile.str_set("name", &JsValue::from_str("Lingo handlers"));
+    profile.str_set("unit", &JsValue::from_str("milliseconds"));
+    profile.str_set("startValue", &JsValue::from_f64(0.0));
+    profile.str_set("endValue", &JsValue::from_f64(end_ms));
+    profile.str_set("events", &events);
+    let profiles = js_sys::Array::new();
+    profiles.push(&js_sys::Object::from_entries(&profile).unwrap());
+
+    let shared = js_sys::Map::new();
+    shared.str_set("frames", &frames);
+    let file = js_sys::Map::new();
+    file.str_set("$schema", &JsValue::from_str("https://www.speedscope.app/file-format-schema.json"));
+    file.str_set("exporter", &JsValue::from_str("dirplayer-rs"));
+    file.str_set("shared", &js_sys::Object::from_entries(&shared).unwrap());
+    file.str_set("profiles", &profiles);
+    stringify(&js_sys::Object::from_entries(&file).unwrap())
+  }
+}
+
+fn stringify(value: &js_sys::Object) -> String {
+  js_sys::JSON::stringify(value).map(String::from).unwrap_or_default()
+}
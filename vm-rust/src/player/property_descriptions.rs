@@ -0,0 +1,90 @@
+use crate::director::lingo::datum::Datum;
+
+use super::{
+  allocator::ScriptInstanceAllocatorTrait, cast_lib::CastMemberRef, handlers::datum_handlers::script_instance::ScriptInstanceUtils,
+  player_call_script_handler, reserve_player_mut, reserve_player_ref, script::{script_get_prop_opt, script_set_prop},
+  script_ref::ScriptInstanceRef, DatumRef, DirPlayer, ScriptError,
+};
+
+/// A behavior of a sprite with the parameters it describes in getPropertyDescriptionList.
+pub struct BehaviorDescription {
+  pub instance_ref: ScriptInstanceRef,
+  pub script_ref: CastMemberRef,
+  /// Property names with their description lists, holding #default, #format, #comment
+  /// and optionally #range.
+  pub properties: Vec<(String, DatumRef)>,
+}
+
+/// Reads an entry like #default from a property's description list.
+pub fn get_description_entry(player: &DirPlayer, description_ref: &DatumRef, key: &str) -> Option<DatumRef> {
+  match player.get_datum(description_ref) {
+    Datum::PropList(pairs, _) => pairs
+      .iter()
+      .find(|(key_ref, _)| player.get_datum(key_ref).string_value().is_ok_and(|name| name.eq_ignore_ascii_case(key)))
+      .map(|(_, value_ref)| value_ref.clone()),
+    _ => None,
+  }
+}
+
+/// Calls getPropertyDescriptionList of a behavior. Behaviors without the handler
+/// describe no parameters.
+async fn player_describe_behavior(instance_ref: ScriptInstanceRef) -> Result<BehaviorDescription, ScriptError> {
+  let handler_name = "getPropertyDescriptionList".to_string();
+  let (script_ref, handler_ref) = reserve_player_ref(|player| {
+    let script_ref = player.allocator.get_script_instance(&instance_ref).script.clone();
+    let handler_ref = ScriptInstanceUtils::get_script_instance_handler(&handler_name, &instance_ref, player)?;
+    Ok::<_, ScriptError>((script_ref, handler_ref))
+  })?;
+  let properties = match handler_ref {
+    Some(handler_ref) => {
+      let result = player_call_script_handler(Some(instance_ref.clone()), handler_ref, &vec![]).await?;
+      reserve_player_ref(|player| match player.get_datum(&result.return_value) {
+        Datum::PropList(pairs, _) => pairs
+          .iter()
+          .map(|(key_ref, value_ref)| Ok((player.get_datum(key_ref).string_value()?, value_ref.clone())))
+          .collect::<Result<Vec<_>, ScriptError>>(),
+        _ => Ok(vec![]),
+      })?
+    }
+    None => vec![],
+  };
+  Ok(BehaviorDescription { instance_ref, script_ref, properties })
+}
+
+/// Describes the parameters of each behavior of a sprite, in the order they are attached.
+pub async fn player_describe_sprite_behaviors(sprite_num: i16) -> Result<Vec<BehaviorDescription>, ScriptError> {
+  let instance_refs = reserve_player_ref(|player| {
+    player
+      .movie
+      .score
+      .get_sprite(sprite_num)
+      .map(|sprite| sprite.script_instance_list.clone())
+      .unwrap_or_default()
+  });
+  let mut result = vec![];
+  for instance_ref in instance_refs {
+    result.push(player_describe_behavior(instance_ref).await?);
+  }
+  Ok(result)
+}
+
+/// Gives described parameters the score saved no value for their #default, like
+/// Director does for parameters added to a behavior after it was attached to the sprite.
+pub async fn player_apply_property_defaults(sprite_num: i16) -> Result<DatumRef, ScriptError> {
+  for behavior in player_describe_sprite_behaviors(sprite_num).await? {
+    reserve_player_mut(|player| {
+      for (prop_name, description_ref) in behavior.properties.iter() {
+        let is_saved = script_get_prop_opt(player, &behavior.instance_ref, prop_name)
+          .is_some_and(|value_ref| !matches!(player.get_datum(&value_ref), Datum::Void));
+        if is_saved {
+          continue;
+        }
+        if let Some(default_ref) = get_description_entry(player, description_ref, "default") {
+          script_set_prop(player, &behavior.instance_ref, prop_name, &default_ref, false)?;
+        }
+      }
+      Ok::<_, ScriptError>(())
+    })?;
+  }
+  Ok(DatumRef::Void)
+}
@@ -0,0 +1,38 @@
+/// Generator behind random(). Setting `the randomSeed` restarts the sequence, so
+/// movies and debugging sessions can reproduce the same run.
+#[derive(Clone)]
+pub struct RandomGenerator {
+  seed: i32,
+  state: u32,
+}
+
+impl RandomGenerator {
+  pub fn new() -> RandomGenerator {
+    let mut generator = RandomGenerator { seed: 0, state: 0 };
+    generator.set_seed((js_sys::Math::random() * i32::MAX as f64) as i32);
+    generator
+  }
+
+  pub fn seed(&self) -> i32 {
+    self.seed
+  }
+
+  pub fn set_seed(&mut self, seed: i32) {
+    self.seed = seed;
+    // Xorshift gets stuck on a zero state
+    self.state = (seed as u32) ^ 0x9E37_79B9;
+    if self.state == 0 {
+      self.state = 1;
+    }
+  }
+
+  /// Returns a value in [0, 1).
+  pub fn next_f64(&mut self) -> f64 {
+    let mut x = self.state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    self.state = x;
+    x as f64 / (u32::MAX as f64 + 1.0)
+  }
+}
@@ -0,0 +1,960 @@
+use binary_reader::{BinaryReader, Endian};
+use fxhash::FxHashMap;
+
+use crate::{director::lingo::datum::{Datum, DatumType}, js_api::JsApi};
+
+use super::{
+  allocator::{DatumAllocatorTrait, ScriptInstanceAllocatorTrait},
+  bitmap::{bitmap::{Bitmap, PaletteRef}, manager::BitmapRef},
+  cast_lib::CastMemberRef,
+  date::LingoDate,
+  datum_ref::{DatumId, DatumRef},
+  script::{ScriptInstance, ScriptInstanceId},
+  script_ref::ScriptInstanceRef,
+  sound::{SoundChannelState, SoundFade, SoundPlaylistEntry},
+  sprite::{ColorRef, CursorRef, Sprite},
+  timeout::Timeout,
+  DirPlayer, ScriptError,
+};
+
+// A save state captures the player between frames, when no handler is running:
+//   header | frame position | datum heap | script instances | globals | sprites | timeouts | sound channels | bitmaps
+// Datums and script instances are written once and referred to by their ids, so shared
+// references and cycles survive a round trip. Network connections, xtra instances and
+// cast members edited by scripts are not part of the snapshot.
+const SAVE_STATE_MAGIC: &[u8; 4] = b"DPSS";
+const SAVE_STATE_VERSION: u32 = 8;
+
+const DATUM_TAG_VOID: u8 = 0;
+const DATUM_TAG_NULL: u8 = 1;
+const DATUM_TAG_INT: u8 = 2;
+const DATUM_TAG_FLOAT: u8 = 3;
+const DATUM_TAG_STRING: u8 = 4;
+const DATUM_TAG_SYMBOL: u8 = 5;
+const DATUM_TAG_LIST: u8 = 6;
+const DATUM_TAG_PROP_LIST: u8 = 7;
+const DATUM_TAG_CAST_LIB: u8 = 8;
+const DATUM_TAG_STAGE: u8 = 9;
+const DATUM_TAG_SCRIPT_REF: u8 = 10;
+const DATUM_TAG_SCRIPT_INSTANCE_REF: u8 = 11;
+const DATUM_TAG_CAST_MEMBER: u8 = 12;
+const DATUM_TAG_SPRITE_REF: u8 = 13;
+const DATUM_TAG_INT_RECT: u8 = 14;
+const DATUM_TAG_INT_POINT: u8 = 15;
+const DATUM_TAG_CURSOR_REF: u8 = 16;
+const DATUM_TAG_TIMEOUT_REF: u8 = 17;
+const DATUM_TAG_COLOR_REF: u8 = 18;
+const DATUM_TAG_BITMAP_REF: u8 = 19;
+const DATUM_TAG_PALETTE_REF: u8 = 20;
+const DATUM_TAG_XTRA: u8 = 21;
+const DATUM_TAG_XTRA_INSTANCE: u8 = 22;
+const DATUM_TAG_PLAYER_REF: u8 = 23;
+const DATUM_TAG_MOVIE_REF: u8 = 24;
+const DATUM_TAG_SOUND_REF: u8 = 25;
+const DATUM_TAG_WINDOW_REF: u8 = 26;
+const DATUM_TAG_DATE: u8 = 27;
+
+struct SaveStateWriter {
+  buf: Vec<u8>,
+}
+
+impl SaveStateWriter {
+  fn u8(&mut self, value: u8) {
+    self.buf.push(value);
+  }
+
+  fn bool(&mut self, value: bool) {
+    self.buf.push(value as u8);
+  }
+
+  fn i16(&mut self, value: i16) {
+    self.buf.extend_from_slice(&value.to_le_bytes());
+  }
+
+  fn u16(&mut self, value: u16) {
+    self.buf.extend_from_slice(&value.to_le_bytes());
+  }
+
+  fn i32(&mut self, value: i32) {
+    self.buf.extend_from_slice(&value.to_le_bytes());
+  }
+
+  fn u32(&mut self, value: u32) {
+    self.buf.extend_from_slice(&value.to_le_bytes());
+  }
+
+  fn u64(&mut self, value: u64) {
+    self.buf.extend_from_slice(&value.to_le_bytes());
+  }
+
+  fn i64(&mut self, value: i64) {
+    self.buf.extend_from_slice(&value.to_le_bytes());
+  }
+
+  fn f32(&mut self, value: f32) {
+    self.buf.extend_from_slice(&value.to_le_bytes());
+  }
+
+  fn bytes(&mut self, value: &[u8]) {
+    self.u32(value.len() as u32);
+    self.buf.extend_from_slice(value);
+  }
+
+  fn string(&mut self, value: &str) {
+    self.bytes(value.as_bytes());
+  }
+
+  fn member_ref(&mut self, value: &CastMemberRef) {
+    self.i32(value.cast_lib);
+    self.i32(value.cast_member);
+  }
+
+  fn datum_ref(&mut self, value: &DatumRef) {
+    self.u64(value.unwrap() as u64);
+  }
+
+  fn color_ref(&mut self, value: &ColorRef) {
+    match value {
+      ColorRef::Rgb(r, g, b) => {
+        self.u8(0);
+        self.buf.extend_from_slice(&[*r, *g, *b]);
+      }
+      ColorRef::PaletteIndex(index) => {
+        self.u8(1);
+        self.buf.extend_from_slice(&[*index, 0, 0]);
+      }
+    }
+  }
+
+  fn cursor_ref(&mut self, value: &CursorRef) {
+    match value {
+      CursorRef::System(id) => {
+        self.u8(0);
+        self.i32(*id);
+      }
+      CursorRef::Member(members, hot_spot) => {
+        self.u8(1);
+        self.u32(members.len() as u32);
+        for member in members {
+          self.i32(*member);
+        }
+        self.bool(hot_spot.is_some());
+        let (hot_x, hot_y) = hot_spot.unwrap_or_default();
+        self.i32(hot_x);
+        self.i32(hot_y);
+      }
+    }
+  }
+
+  fn palette_ref(&mut self, value: &PaletteRef) {
+    match value {
+      PaletteRef::BuiltIn(palette) => {
+        self.u8(0);
+        self.i16(*palette as i16);
+      }
+      PaletteRef::Member(member_ref) => {
+        self.u8(1);
+        self.member_ref(member_ref);
+      }
+    }
+  }
+
+  fn datum(&mut self, datum: &Datum) {
+    match datum {
+      Datum::Int(value) => {
+        self.u8(DATUM_TAG_INT);
+        self.i32(*value);
+      }
+      Datum::Float(value) => {
+        self.u8(DATUM_TAG_FLOAT);
+        self.f32(*value);
+      }
+      Datum::String(value) | Datum::StringChunk(.., value) => {
+        self.u8(DATUM_TAG_STRING);
+        self.string(value);
+      }
+      Datum::Symbol(value) => {
+        self.u8(DATUM_TAG_SYMBOL);
+        self.string(value);
+      }
+      Datum::List(list_type, items, is_sorted) => {
+        self.u8(DATUM_TAG_LIST);
+        self.u8(match list_type {
+          DatumType::ArgList => 1,
+          DatumType::ArgListNoRet => 2,
+          _ => 0,
+        });
+        self.bool(*is_sorted);
+        self.u32(items.len() as u32);
+        for item in items {
+          self.datum_ref(item);
+        }
+      }
+      Datum::PropList(pairs, is_sorted) => {
+        self.u8(DATUM_TAG_PROP_LIST);
+        self.bool(*is_sorted);
+        self.u32(pairs.len() as u32);
+        for (key, value) in pairs {
+          self.datum_ref(key);
+          self.datum_ref(value);
+        }
+      }
+      Datum::CastLib(number) => {
+        self.u8(DATUM_TAG_CAST_LIB);
+        self.u32(*number);
+      }
+      Datum::Stage => self.u8(DATUM_TAG_STAGE),
+      Datum::ScriptRef(member_ref) => {
+        self.u8(DATUM_TAG_SCRIPT_REF);
+        self.member_ref(member_ref);
+      }
+      Datum::ScriptInstanceRef(instance_ref) => {
+        self.u8(DATUM_TAG_SCRIPT_INSTANCE_REF);
+        self.u32(**instance_ref);
+      }
+      Datum::CastMember(member_ref) => {
+        self.u8(DATUM_TAG_CAST_MEMBER);
+        self.member_ref(member_ref);
+      }
+      Datum::SpriteRef(number) => {
+        self.u8(DATUM_TAG_SPRITE_REF);
+        self.i16(*number);
+      }
+      Datum::IntRect((left, top, right, bottom)) => {
+        self.u8(DATUM_TAG_INT_RECT);
+        for value in [left, top, right, bottom] {
+          self.i32(*value);
+        }
+      }
+      Datum::IntPoint((x, y)) => {
+        self.u8(DATUM_TAG_INT_POINT);
+        self.i32(*x);
+        self.i32(*y);
+      }
+      Datum::CursorRef(cursor_ref) => {
+        self.u8(DATUM_TAG_CURSOR_REF);
+        self.cursor_ref(cursor_ref);
+      }
+      Datum::TimeoutRef(name) => {
+        self.u8(DATUM_TAG_TIMEOUT_REF);
+        self.string(name);
+      }
+      Datum::ColorRef(color_ref) => {
+        self.u8(DATUM_TAG_COLOR_REF);
+        self.color_ref(color_ref);
+      }
+      Datum::BitmapRef(bitmap_ref) => {
+        self.u8(DATUM_TAG_BITMAP_REF);
+        self.u32(*bitmap_ref);
+      }
+      Datum::PaletteRef(palette_ref) => {
+        self.u8(DATUM_TAG_PALETTE_REF);
+        self.palette_ref(palette_ref);
+      }
+      Datum::Xtra(name) => {
+        self.u8(DATUM_TAG_XTRA);
+        self.string(name);
+      }
+      Datum::XtraInstance(name, instance_id) => {
+        self.u8(DATUM_TAG_XTRA_INSTANCE);
+        self.string(name);
+        self.u32(*instance_id);
+      }
+      Datum::PlayerRef => self.u8(DATUM_TAG_PLAYER_REF),
+      Datum::MovieRef => self.u8(DATUM_TAG_MOVIE_REF),
+      Datum::SoundRef(channel) => {
+        self.u8(DATUM_TAG_SOUND_REF);
+        self.u16(*channel);
+      }
+      Datum::WindowRef(name) => {
+        self.u8(DATUM_TAG_WINDOW_REF);
+        self.string(name);
+      }
+      Datum::Date(date) => {
+        self.u8(DATUM_TAG_DATE);
+        self.i32(date.year());
+        self.i32(date.month());
+        self.i32(date.day());
+        self.i32(date.seconds);
+      }
+      Datum::Null => self.u8(DATUM_TAG_NULL),
+      // Masks are rebuilt on demand and var refs only live on the stack
+      Datum::Void | Datum::VarRef(_) | Datum::Matte(_) => self.u8(DATUM_TAG_VOID),
+    }
+  }
+
+  fn sprite(&mut self, sprite: &Sprite) {
+    self.string(&sprite.name);
+    self.bool(sprite.puppet);
+    self.bool(sprite.visible);
+    self.i32(sprite.stretch);
+    self.i32(sprite.loc_h);
+    self.i32(sprite.loc_v);
+    self.i32(sprite.loc_z);
+    self.i32(sprite.width);
+    self.i32(sprite.height);
+    self.i32(sprite.ink);
+    self.i32(sprite.blend);
+    self.f32(sprite.rotation);
+    self.f32(sprite.skew);
+    self.bool(sprite.quad.is_some());
+    for (x, y) in sprite.quad.iter().flatten() {
+      self.f32(*x);
+      self.f32(*y);
+    }
+    self.bool(sprite.flip_h);
+    self.bool(sprite.flip_v);
+    self.bool(sprite.trails);
+    self.i32(sprite.back_color);
+    self.color_ref(&sprite.color);
+    self.color_ref(&sprite.bg_color);
+    self.bool(sprite.member.is_some());
+    if let Some(member_ref) = &sprite.member {
+      self.member_ref(member_ref);
+    }
+    self.u32(sprite.script_instance_list.len() as u32);
+    for instance_ref in &sprite.script_instance_list {
+      self.u32(**instance_ref);
+    }
+    self.bool(sprite.cursor_ref.is_some());
+    if let Some(cursor_ref) = &sprite.cursor_ref {
+      self.cursor_ref(cursor_ref);
+    }
+    self.bool(sprite.editable);
+    self.bool(sprite.moveable);
+    self.i16(sprite.constraint);
+    self.bool(sprite.entered);
+    self.bool(sprite.exited);
+  }
+
+  fn playlist_entry(&mut self, entry: &SoundPlaylistEntry) {
+    self.member_ref(&entry.member);
+    self.i32(entry.loop_count);
+  }
+
+  fn sound_channel(&mut self, state: &SoundChannelState) {
+    self.i32(state.volume);
+    self.i32(state.pan);
+    self.bool(state.fade.is_some());
+    if let Some(fade) = &state.fade {
+      self.i32(fade.from);
+      self.i32(fade.to);
+      self.i64(fade.start_ms);
+      self.i64(fade.duration_ms);
+    }
+    self.bool(state.current.is_some());
+    if let Some(entry) = &state.current {
+      self.playlist_entry(entry);
+    }
+    self.u32(state.playlist.len() as u32);
+    for entry in &state.playlist {
+      self.playlist_entry(entry);
+    }
+    self.i32(state.loops_remaining);
+    self.bool(state.is_paused);
+    self.i64(state.elapsed_ms);
+  }
+}
+
+struct SaveStateReader {
+  reader: BinaryReader,
+}
+
+fn truncated<T>(_: T) -> ScriptError {
+  ScriptError::new("Truncated save state".to_string())
+}
+
+impl SaveStateReader {
+  fn u8(&mut self) -> Result<u8, ScriptError> {
+    self.reader.read_u8().map_err(truncated)
+  }
+
+  fn bool(&mut self) -> Result<bool, ScriptError> {
+    Ok(self.u8()? != 0)
+  }
+
+  fn i16(&mut self) -> Result<i16, ScriptError> {
+    self.reader.read_i16().map_err(truncated)
+  }
+
+  fn u16(&mut self) -> Result<u16, ScriptError> {
+    self.reader.read_u16().map_err(truncated)
+  }
+
+  fn i32(&mut self) -> Result<i32, ScriptError> {
+    self.reader.read_i32().map_err(truncated)
+  }
+
+  fn u32(&mut self) -> Result<u32, ScriptError> {
+    self.reader.read_u32().map_err(truncated)
+  }
+
+  fn u64(&mut self) -> Result<u64, ScriptError> {
+    self.reader.read_u64().map_err(truncated)
+  }
+
+  fn i64(&mut self) -> Result<i64, ScriptError> {
+    self.reader.read_i64().map_err(truncated)
+  }
+
+  fn f32(&mut self) -> Result<f32, ScriptError> {
+    self.reader.read_f32().map_err(truncated)
+  }
+
+  fn bytes(&mut self) -> Result<Vec<u8>, ScriptError> {
+    let length = self.u32()? as usize;
+    Ok(self.reader.read_bytes(length).map_err(truncated)?.to_vec())
+  }
+
+  fn string(&mut self) -> Result<String, ScriptError> {
+    String::from_utf8(self.bytes()?).map_err(|_| ScriptError::new("Invalid string in save state".to_string()))
+  }
+
+  fn member_ref(&mut self) -> Result<CastMemberRef, ScriptError> {
+    Ok(CastMemberRef { cast_lib: self.i32()?, cast_member: self.i32()? })
+  }
+
+  fn datum_ref(&mut self, ids: &SaveStateIdMap) -> Result<DatumRef, ScriptError> {
+    let id = self.u64()? as DatumId;
+    if id == 0 {
+      return Ok(DatumRef::Void);
+    }
+    ids.datums.get(&id).cloned().ok_or_else(|| ScriptError::new(format!("Unknown datum {} in save state", id)))
+  }
+
+  fn script_instance_ref(&mut self, ids: &SaveStateIdMap) -> Result<ScriptInstanceRef, ScriptError> {
+    let id = self.u32()?;
+    ids.script_instances.get(&id).cloned().ok_or_else(|| ScriptError::new(format!("Unknown script instance {} in save state", id)))
+  }
+
+  fn color_ref(&mut self) -> Result<ColorRef, ScriptError> {
+    let kind = self.u8()?;
+    let bytes = self.reader.read_bytes(3).map_err(truncated)?;
+    match kind {
+      0 => Ok(ColorRef::Rgb(bytes[0], bytes[1], bytes[2])),
+      _ => Ok(ColorRef::PaletteIndex(bytes[0])),
+    }
+  }
+
+  fn cursor_ref(&mut self) -> Result<CursorRef, ScriptError> {
+    match self.u8()? {
+      0 => Ok(CursorRef::System(self.i32()?)),
+      _ => {
+        let count = self.u32()?;
+        let mut members = vec![];
+        for _ in 0..count {
+          members.push(self.i32()?);
+        }
+        let has_hot_spot = self.bool()?;
+        let hot_spot = (self.i32()?, self.i32()?);
+        Ok(CursorRef::Member(members, if has_hot_spot { Some(hot_spot) } else { None }))
+      }
+    }
+  }
+
+  fn palette_ref(&mut self) -> Result<PaletteRef, ScriptError> {
+    match self.u8()? {
+      0 => {
+        let id = self.i16()?;
+        num::FromPrimitive::from_i16(id)
+          .map(PaletteRef::BuiltIn)
+          .ok_or_else(|| ScriptError::new(format!("Invalid palette {} in save state", id)))
+      }
+      _ => Ok(PaletteRef::Member(self.member_ref()?)),
+    }
+  }
+
+  fn datum(&mut self, ids: &SaveStateIdMap) -> Result<Datum, ScriptError> {
+    let tag = self.u8()?;
+    let datum = match tag {
+      DATUM_TAG_VOID => Datum::Void,
+      DATUM_TAG_NULL => Datum::Null,
+      DATUM_TAG_INT => Datum::Int(self.i32()?),
+      DATUM_TAG_FLOAT => Datum::Float(self.f32()?),
+      DATUM_TAG_STRING => Datum::String(self.string()?),
+      DATUM_TAG_SYMBOL => Datum::Symbol(self.string()?),
+      DATUM_TAG_LIST => {
+        let list_type = match self.u8()? {
+          1 => DatumType::ArgList,
+          2 => DatumType::ArgListNoRet,
+          _ => DatumType::List,
+        };
+        let is_sorted = self.bool()?;
+        let count = self.u32()?;
+        let mut items = vec![];
+        for _ in 0..count {
+          items.push(self.datum_ref(ids)?);
+        }
+        Datum::List(list_type, items, is_sorted)
+      }
+      DATUM_TAG_PROP_LIST => {
+        let is_sorted = self.bool()?;
+        let count = self.u32()?;
+        let mut pairs = vec![];
+        for _ in 0..count {
+          let key = self.datum_ref(ids)?;
+          let value = self.datum_ref(ids)?;
+          pairs.push((key, value));
+        }
+        Datum::PropList(pairs, is_sorted)
+      }
+      DATUM_TAG_CAST_LIB => Datum::CastLib(self.u32()?),
+      DATUM_TAG_STAGE => Datum::Stage,
+      DATUM_TAG_SCRIPT_REF => Datum::ScriptRef(self.member_ref()?),
+      DATUM_TAG_SCRIPT_INSTANCE_REF => Datum::ScriptInstanceRef(self.script_instance_ref(ids)?),
+      DATUM_TAG_CAST_MEMBER => Datum::CastMember(self.member_ref()?),
+      DATUM_TAG_SPRITE_REF => Datum::SpriteRef(self.i16()?),
+      DATUM_TAG_INT_RECT => Datum::IntRect((self.i32()?, self.i32()?, self.i32()?, self.i32()?)),
+      DATUM_TAG_INT_POINT => Datum::IntPoint((self.i32()?, self.i32()?)),
+      DATUM_TAG_CURSOR_REF => Datum::CursorRef(self.cursor_ref()?),
+      DATUM_TAG_TIMEOUT_REF => Datum::TimeoutRef(self.string()?),
+      DATUM_TAG_COLOR_REF => Datum::ColorRef(self.color_ref()?),
+      DATUM_TAG_BITMAP_REF => Datum::BitmapRef(self.u32()?),
+      DATUM_TAG_PALETTE_REF => Datum::PaletteRef(self.palette_ref()?),
+      DATUM_TAG_XTRA => Datum::Xtra(self.string()?),
+      DATUM_TAG_XTRA_INSTANCE => Datum::XtraInstance(self.string()?, self.u32()?),
+      DATUM_TAG_PLAYER_REF => Datum::PlayerRef,
+      DATUM_TAG_MOVIE_REF => Datum::MovieRef,
+      DATUM_TAG_SOUND_REF => Datum::SoundRef(self.u16()?),
+      DATUM_TAG_WINDOW_REF => Datum::WindowRef(self.string()?),
+      DATUM_TAG_DATE => {
+        let date = LingoDate::from_ymd(self.i32()?, self.i32()?, self.i32()?)?;
+        Datum::Date(LingoDate { seconds: self.i32()?, ..date })
+      }
+      _ => return Err(ScriptError::new(format!("Invalid datum tag {} in save state", tag))),
+    };
+    Ok(datum)
+  }
+
+  fn sprite(&mut self, sprite: &mut Sprite, ids: &SaveStateIdMap) -> Result<(), ScriptError> {
+    sprite.name = self.string()?;
+    sprite.puppet = self.bool()?;
+    sprite.visible = self.bool()?;
+    sprite.stretch = self.i32()?;
+    sprite.loc_h = self.i32()?;
+    sprite.loc_v = self.i32()?;
+    sprite.loc_z = self.i32()?;
+    sprite.width = self.i32()?;
+    sprite.height = self.i32()?;
+    sprite.ink = self.i32()?;
+    sprite.blend = self.i32()?;
+    sprite.rotation = self.f32()?;
+    sprite.skew = self.f32()?;
+    sprite.quad = if self.bool()? {
+      let mut quad = [(0.0, 0.0); 4];
+      for corner in quad.iter_mut() {
+        *corner = (self.f32()?, self.f32()?);
+      }
+      Some(quad)
+    } else {
+      None
+    };
+    sprite.flip_h = self.bool()?;
+    sprite.flip_v = self.bool()?;
+    sprite.trails = self.bool()?;
+    sprite.back_color = self.i32()?;
+    sprite.color = self.color_ref()?;
+    sprite.bg_color = self.color_ref()?;
+    sprite.member = if self.bool()? { Some(self.member_ref()?) } else { None };
+    let instance_count = self.u32()?;
+    sprite.script_instance_list.clear();
+    for _ in 0..instance_count {
+      sprite.script_instance_list.push(self.script_instance_ref(ids)?);
+    }
+    sprite.cursor_ref = if self.bool()? { Some(self.cursor_ref()?) } else { None };
+    sprite.editable = self.bool()?;
+    sprite.moveable = self.bool()?;
+    sprite.constraint = self.i16()?;
+    sprite.entered = self.bool()?;
+    sprite.exited = self.bool()?;
+    Ok(())
+  }
+
+  fn playlist_entry(&mut self) -> Result<SoundPlaylistEntry, ScriptError> {
+    Ok(SoundPlaylistEntry { member: self.member_ref()?, loop_count: self.i32()? })
+  }
+
+  fn sound_channel(&mut self) -> Result<SoundChannelState, ScriptError> {
+    let volume = self.i32()?;
+    let pan = self.i32()?;
+    let fade = if self.bool()? {
+      Some(SoundFade { from: self.i32()?, to: self.i32()?, start_ms: self.i64()?, duration_ms: self.i64()? })
+    } else {
+      None
+    };
+    let current = if self.bool()? { Some(self.playlist_entry()?) } else { None };
+    let playlist_len = self.u32()?;
+    let playlist = (0..playlist_len).map(|_| self.playlist_entry()).collect::<Result<Vec<_>, _>>()?;
+    Ok(SoundChannelState {
+      volume,
+      pan,
+      fade,
+      current,
+      playlist,
+      loops_remaining: self.i32()?,
+      is_paused: self.bool()?,
+      elapsed_ms: self.i64()?,
+    })
+  }
+}
+
+/// Maps the ids stored in a save state to the datums and script instances allocated
+/// for them while loading.
+struct SaveStateIdMap {
+  datums: FxHashMap<DatumId, DatumRef>,
+  script_instances: FxHashMap<ScriptInstanceId, ScriptInstanceRef>,
+}
+
+fn ensure_between_frames(player: &DirPlayer) -> Result<(), ScriptError> {
+  if player.scope_count > 0 {
+    return Err(ScriptError::new("Save states can only be taken between frames, while no handler is running".to_string()));
+  }
+  Ok(())
+}
+
+pub fn save_player_state(player: &DirPlayer) -> Result<Vec<u8>, ScriptError> {
+  ensure_between_frames(player)?;
+  let mut writer = SaveStateWriter { buf: vec![] };
+  writer.buf.extend_from_slice(SAVE_STATE_MAGIC);
+  writer.u32(SAVE_STATE_VERSION);
+  writer.string(&player.movie.file_name);
+
+  writer.u32(player.movie.current_frame);
+  writer.bool(player.next_frame.is_some());
+  writer.u32(player.next_frame.unwrap_or(0));
+  writer.u32(player.movie.puppet_tempo);
+  writer.u32(player.movie.play_stack.len() as u32);
+  for frame in &player.movie.play_stack {
+    writer.u32(*frame);
+  }
+  writer.bool(player.movie.exit_lock);
+  writer.u32(player.movie.item_delimiter as u32);
+  writer.u8(player.float_precision);
+  writer.i16(player.keyboard_focus_sprite);
+
+  // Ids come before contents, so that loading can allocate everything before
+  // resolving references
+  let datums = &player.allocator.datums;
+  let script_instances = &player.allocator.script_instances;
+  writer.u32(datums.len() as u32);
+  for id in datums.keys() {
+    writer.u64(*id as u64);
+  }
+  writer.u32(script_instances.len() as u32);
+  for (id, entry) in script_instances {
+    writer.u32(*id);
+    writer.member_ref(&entry.script_instance.script);
+  }
+  for entry in datums.values() {
+    writer.datum(&entry.datum);
+  }
+  for entry in script_instances.values() {
+    let instance = &entry.script_instance;
+    writer.bool(instance.ancestor.is_some());
+    writer.u32(instance.ancestor.as_ref().map_or(0, |ancestor| **ancestor));
+    let properties: Vec<_> = (0..instance.property_names.len()).filter_map(|index| instance.get_own_prop_at(index)).collect();
+    writer.u32(properties.len() as u32);
+    for (name, value_ref) in properties {
+      writer.string(name);
+      writer.datum_ref(value_ref);
+    }
+  }
+
+  writer.u32(player.globals.len() as u32);
+  for (name, value_ref) in &player.globals {
+    writer.string(name);
+    writer.datum_ref(value_ref);
+  }
+
+  writer.u32(player.movie.score.channels.len() as u32);
+  for channel in &player.movie.score.channels {
+    writer.sprite(&channel.sprite);
+  }
+
+  writer.u32(player.timeout_manager.timeouts.len() as u32);
+  for timeout_name in player.timeout_manager.timeout_names() {
+    let timeout = player.timeout_manager.get_timeout(timeout_name).unwrap();
+    writer.string(&timeout.name);
+    writer.u32(timeout.period);
+    writer.string(&timeout.handler);
+    writer.datum_ref(&timeout.target_ref);
+    writer.bool(timeout.is_scheduled);
+    writer.bool(timeout.persistent);
+  }
+
+  let now_ms = player.clock.elapsed_ms();
+  writer.u32(player.sound_manager.channels.len() as u32);
+  for channel in &player.sound_manager.channels {
+    writer.sound_channel(&channel.get_state(now_ms));
+  }
+
+  // Images created by scripts are only referenced from datums
+  let mut bitmap_refs = player.bitmap_manager.get_modified_bitmap_refs();
+  for entry in datums.values() {
+    if let Datum::BitmapRef(bitmap_ref) = &entry.datum {
+      if !bitmap_refs.contains(bitmap_ref) && player.bitmap_manager.get_bitmap(*bitmap_ref).is_some() {
+        bitmap_refs.push(*bitmap_ref);
+      }
+    }
+  }
+  writer.u32(bitmap_refs.len() as u32);
+  for bitmap_ref in bitmap_refs {
+    let bitmap = player.bitmap_manager.get_bitmap(bitmap_ref).unwrap();
+    writer.u32(bitmap_ref);
+    writer.u16(bitmap.width);
+    writer.u16(bitmap.height);
+    writer.u8(bitmap.bit_depth);
+    writer.palette_ref(&bitmap.palette_ref);
+    writer.bytes(&bitmap.data);
+  }
+
+  Ok(writer.buf)
+}
+
+/// Replaces the running movie's state with a snapshot taken by `save_player_state`.
+/// The snapshot must come from the same movie. The whole snapshot is read before any
+/// of the running state is replaced, so a snapshot that fails to load leaves the movie
+/// as it was.
+pub fn load_player_state(player: &mut DirPlayer, data: &[u8]) -> Result<(), ScriptError> {
+  ensure_between_frames(player)?;
+  if data.len() < SAVE_STATE_MAGIC.len() || &data[0..SAVE_STATE_MAGIC.len()] != SAVE_STATE_MAGIC {
+    return Err(ScriptError::new("Invalid save state".to_string()));
+  }
+  let mut reader = BinaryReader::from_u8(&data[SAVE_STATE_MAGIC.len()..]);
+  reader.set_endian(Endian::Little);
+  let mut reader = SaveStateReader { reader };
+  let version = reader.u32()?;
+  if version != SAVE_STATE_VERSION {
+    return Err(ScriptError::new(format!("Unsupported save state version {}", version)));
+  }
+  let file_name = reader.string()?;
+  if file_name != player.movie.file_name {
+    return Err(ScriptError::new(format!("Save state is for movie {}, not {}", file_name, player.movie.file_name)));
+  }
+
+  let current_frame = reader.u32()?;
+  let has_next_frame = reader.bool()?;
+  let next_frame = reader.u32()?;
+  let puppet_tempo = reader.u32()?;
+  let play_stack_len = reader.u32()?;
+  let play_stack = (0..play_stack_len).map(|_| reader.u32()).collect::<Result<Vec<_>, _>>()?;
+  let exit_lock = reader.bool()?;
+  let item_delimiter = char::from_u32(reader.u32()?).unwrap_or('.');
+  let float_precision = reader.u8()?;
+  let keyboard_focus_sprite = reader.i16()?;
+
+  // Allocate every datum and script instance up front so references between them
+  // can be resolved regardless of order. Until the state is applied nothing else
+  // refers to them, so they are freed again if the snapshot turns out to be broken.
+  let mut ids = SaveStateIdMap {
+    datums: FxHashMap::default(),
+    script_instances: FxHashMap::default(),
+  };
+  let datum_count = reader.u32()?;
+  let mut datum_ids = vec![];
+  for _ in 0..datum_count {
+    let id = reader.u64()? as DatumId;
+    datum_ids.push(id);
+    ids.datums.insert(id, player.alloc_datum(Datum::Int(0)));
+  }
+
+  let instance_count = reader.u32()?;
+  let mut instance_ids = vec![];
+  for _ in 0..instance_count {
+    let id = reader.u32()?;
+    let script = reader.member_ref()?;
+    let instance = ScriptInstance {
+      instance_id: player.allocator.get_free_script_instance_id(),
+      script,
+      ancestor: None,
+      properties: FxHashMap::default(),
+      property_names: vec![],
+    };
+    instance_ids.push(id);
+    ids.script_instances.insert(id, player.allocator.alloc_script_instance(instance));
+  }
+
+  for id in &datum_ids {
+    let datum = reader.datum(&ids)?;
+    *player.allocator.get_datum_mut(&ids.datums[id]) = datum;
+  }
+  for id in &instance_ids {
+    let has_ancestor = reader.bool()?;
+    let ancestor_id = reader.u32()?;
+    let ancestor = if has_ancestor {
+      Some(ids.script_instances.get(&ancestor_id).cloned().ok_or_else(|| ScriptError::new(format!("Unknown script instance {} in save state", ancestor_id)))?)
+    } else {
+      None
+    };
+    let property_count = reader.u32()?;
+    let mut properties = vec![];
+    for _ in 0..property_count {
+      let name = reader.string()?;
+      properties.push((name, reader.datum_ref(&ids)?));
+    }
+    player.allocator.set_script_instance_ancestor(&ids.script_instances[id], ancestor)?;
+    let instance = player.allocator.get_script_instance_mut(&ids.script_instances[id]);
+    instance.properties.clear();
+    instance.property_names.clear();
+    for (name, value_ref) in properties {
+      instance.set_own_prop(&name, value_ref);
+    }
+  }
+
+  let global_count = reader.u32()?;
+  let mut globals = vec![];
+  for _ in 0..global_count {
+    let name = reader.string()?;
+    let value_ref = reader.datum_ref(&ids)?;
+    globals.push((name, value_ref));
+  }
+
+  let channel_count = reader.u32()? as usize;
+  if channel_count != player.movie.score.channels.len() {
+    return Err(ScriptError::new(format!("Save state has {} channels, the movie has {}", channel_count, player.movie.score.channels.len())));
+  }
+  let mut sprites = vec![];
+  for channel in &player.movie.score.channels {
+    let mut sprite = Sprite::new(channel.sprite.number);
+    reader.sprite(&mut sprite, &ids)?;
+    sprites.push(sprite);
+  }
+
+  let timeout_count = reader.u32()?;
+  let mut timeouts = vec![];
+  for _ in 0..timeout_count {
+    let mut timeout = Timeout {
+      name: reader.string()?,
+      period: reader.u32()?,
+      handler: reader.string()?,
+      target_ref: reader.datum_ref(&ids)?,
+      is_scheduled: false,
+      persistent: false,
+      last_fired_ms: 0,
+    };
+    let is_scheduled = reader.bool()?;
+    timeout.persistent = reader.bool()?;
+    timeouts.push((timeout, is_scheduled));
+  }
+
+  let sound_channel_count = reader.u32()? as usize;
+  if sound_channel_count != player.sound_manager.channels.len() {
+    return Err(ScriptError::new(format!("Save state has {} sound channels, the player has {}", sound_channel_count, player.sound_manager.channels.len())));
+  }
+  let sound_channels = (0..sound_channel_count).map(|_| reader.sound_channel()).collect::<Result<Vec<_>, _>>()?;
+
+  let bitmap_count = reader.u32()?;
+  let mut bitmaps = vec![];
+  for _ in 0..bitmap_count {
+    let bitmap_ref: BitmapRef = reader.u32()?;
+    let width = reader.u16()?;
+    let height = reader.u16()?;
+    let bit_depth = reader.u8()?;
+    let palette_ref = reader.palette_ref()?;
+    let data = reader.bytes()?;
+    bitmaps.push((bitmap_ref, Bitmap {
+      width,
+      height,
+      bit_depth,
+      data,
+      palette_ref,
+      matte: None,
+    }));
+  }
+
+  // Everything was read, the running state can be replaced
+  player.movie.puppet_tempo = puppet_tempo;
+  player.movie.play_stack = play_stack;
+  player.movie.exit_lock = exit_lock;
+  player.movie.item_delimiter = item_delimiter;
+  player.float_precision = float_precision;
+  player.keyboard_focus_sprite = keyboard_focus_sprite;
+  player.last_handler_result = DatumRef::Void;
+  player.globals.clear();
+  player.globals.extend(globals);
+  for (channel, sprite) in player.movie.score.channels.iter_mut().zip(sprites) {
+    channel.sprite = sprite;
+  }
+  player.timeout_manager.clear();
+  for (mut timeout, is_scheduled) in timeouts {
+    if is_scheduled {
+      timeout.schedule(player.clock.as_ref());
+    }
+    player.timeout_manager.add_timeout(timeout);
+  }
+  let now_ms = player.clock.elapsed_ms();
+  for (channel, state) in player.sound_manager.channels.iter_mut().zip(sound_channels) {
+    channel.restore_state(state, &player.movie.cast_manager, now_ms);
+  }
+  for (bitmap_ref, bitmap) in bitmaps {
+    player.bitmap_manager.restore_bitmap(bitmap_ref, bitmap);
+  }
+  player.movie.current_frame = current_frame;
+  player.next_frame = if has_next_frame { Some(next_frame) } else { None };
+
+  // Datums only referenced by the id map are unreachable in the restored state
+  drop(ids);
+
+  JsApi::dispatch_frame_changed(player.movie.current_frame);
+  JsApi::dispatch_score_changed();
+  JsApi::dispatch_global_list(player);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use wasm_bindgen_test::*;
+
+  use crate::{player::{datum_formatting::format_datum, eval::eval_lingo, reserve_player_mut}, test_utils::init_player};
+
+  use super::*;
+
+  fn set_global(player: &mut DirPlayer, name: &str, source: &str) {
+    let value_ref = eval_lingo(source.to_string(), player).unwrap();
+    player.globals.insert(name.to_string(), value_ref);
+  }
+
+  fn format_global(player: &DirPlayer, name: &str) -> String {
+    format_datum(&player.globals[name], player)
+  }
+
+  #[wasm_bindgen_test]
+  fn save_state_round_trips_globals() {
+    init_player();
+    reserve_player_mut(|player| {
+      set_global(player, "gSavedList", "[#name: \"before\", #items: [1, 2, #three]]");
+      let state = save_player_state(player).unwrap();
+      set_global(player, "gSavedList", "0");
+      load_player_state(player, &state).unwrap();
+      assert_eq!(format_global(player, "gSavedList"), "[#name: \"before\", #items: [1, 2, #three]]");
+    });
+  }
+
+  #[wasm_bindgen_test]
+  fn save_state_round_trips_shared_references() {
+    init_player();
+    reserve_player_mut(|player| {
+      set_global(player, "gSharedA", "[1]");
+      let shared_ref = player.globals["gSharedA"].clone();
+      player.globals.insert("gSharedB".to_string(), shared_ref);
+      let state = save_player_state(player).unwrap();
+      load_player_state(player, &state).unwrap();
+      let item_ref = player.alloc_datum(Datum::Int(2));
+      let list_ref = player.globals["gSharedA"].clone();
+      player.get_datum_mut(&list_ref).to_list_mut().unwrap().1.push(item_ref);
+      assert_eq!(format_global(player, "gSharedB"), "[1, 2]");
+    });
+  }
+
+  #[wasm_bindgen_test]
+  fn broken_save_state_leaves_the_movie_as_it_was() {
+    init_player();
+    reserve_player_mut(|player| {
+      set_global(player, "gKept", "\"saved\"");
+      let state = save_player_state(player).unwrap();
+      set_global(player, "gKept", "\"changed\"");
+      assert!(load_player_state(player, &state[..state.len() - 1]).is_err());
+      assert_eq!(format_global(player, "gKept"), "\"changed\"");
+    });
+  }
+}
@@ -1,10 +1,15 @@
-use std::cmp::max;
+use std::{borrow::Cow, cmp::max, iter::FromIterator};
 
 use itertools::Itertools;
+use log::warn;
+use num::FromPrimitive;
 
-use crate::{director::{chunks::score::{FrameLabel, ScoreFrameChannelData}, file::DirectorFile, lingo::datum::{datum_bool, Datum, DatumType}}, js_api::JsApi, utils::log_i};
+use crate::{director::{chunks::score::{FrameLabel, ScoreFrameChannelData}, file::DirectorFile, lingo::datum::{datum_bool, Datum, DatumType}}, js_api::{JsApi, JsSerializable, JsUtils}, utils::log_i};
+use wasm_bindgen::JsValue;
 
-use super::{allocator::ScriptInstanceAllocatorTrait, cast_lib::{cast_member_ref, CastMemberRef, NULL_CAST_MEMBER_REF}, cast_member::CastMemberType, datum_ref::DatumRef, events::{player_dispatch_event_to_sprite, player_dispatch_targeted_event}, geometry::{IntRect, IntRectTuple}, handlers::datum_handlers::{cast_member_ref::CastMemberRefHandlers, color::ColorDatumHandlers, script::{self, ScriptDatumHandlers}}, reserve_player_mut, script::{script_get_prop_opt, script_set_prop}, script_ref::ScriptInstanceRef, sprite::{ColorRef, CursorRef, Sprite}, DirPlayer, ScriptError};
+use super::{allocator::ScriptInstanceAllocatorTrait, bitmap::{bitmap::{resolve_color_ref, BuiltInPalette, PaletteRef}, mask::BitmapMask}, cast_lib::{cast_member_ref, CastMemberRef, NULL_CAST_MEMBER_REF}, cast_member::CastMemberType, datum_ref::DatumRef, datum_serialization::deserialize_datum, eval::eval_lingo, events::player_dispatch_targeted_event, font::layout::TextLayout, geometry::{get_registered_rect, map_member_to_rect, IntRect, IntRectTuple, SpriteTransform}, handlers::datum_handlers::{cast_member_ref::CastMemberRefHandlers, color::ColorDatumHandlers, script::{self, ScriptDatumHandlers}}, reserve_player_mut, reserve_player_ref, script::{script_get_prop_opt, script_set_prop}, script_ref::ScriptInstanceRef, cursor::cursor_ref_from_datum, sprite::{ColorRef, Sprite}, DirPlayer, ScriptError};
+
+const PALETTE_CHANNEL_INDEX: u16 = 1;
 
 #[allow(dead_code)]
 pub struct SpriteChannel {
@@ -29,6 +34,7 @@ impl SpriteChannel {
 pub struct ScoreBehaviorReference {
   pub cast_lib: u16,
   pub cast_member: u16,
+  pub initializer_data: Option<Vec<u8>>,
 }
 
 #[derive(Clone)]
@@ -52,11 +58,11 @@ fn get_sprite_rect(player: &DirPlayer, sprite_id: i16) -> IntRectTuple {
     Some(sprite) => sprite,
     None => return (0, 0, 0, 0),
   };
-  let rect = get_concrete_sprite_rect(player, sprite);
+  let rect = get_sprite_bounds(player, sprite);
   return (rect.left, rect.top, rect.right, rect.bottom);
 }
 
-fn get_channel_number_from_index(index: u32) -> u32 {
+pub fn get_channel_number_from_index(index: u32) -> u32 {
   match index {
     0 => 0,
     index => index - 5,
@@ -79,6 +85,22 @@ impl Score {
       .and_then(|span| span.scripts.first().cloned())
   }
 
+  /// The frame script when it has to be called as a static script, which is when it
+  /// wasn't instantiated as a behavior, like in safe mode.
+  pub fn get_static_script_in_frame(&self, frame: u32) -> Option<ScoreBehaviorReference> {
+    if self.get_frame_script_instance_list().is_empty() {
+      self.get_script_in_frame(frame)
+    } else {
+      None
+    }
+  }
+
+  /// The behaviors in the script channel, which get beginSprite and endSprite like
+  /// sprites do, and the frame events with their instance as `me`.
+  pub fn get_frame_script_instance_list(&self) -> Vec<ScriptInstanceRef> {
+    self.channels.first().map_or(vec![], |channel| channel.sprite.script_instance_list.clone())
+  }
+
   fn create_behavior(cast_lib: i32, cast_member: i32) -> (ScriptInstanceRef, DatumRef) {
     let script_ref = CastMemberRef { cast_lib, cast_member };
     reserve_player_mut(|player| {
@@ -88,11 +110,54 @@ impl Score {
     (script_instance_ref.clone(), datum_ref.clone())
   }
 
+  /// Sets the behavior parameters chosen in the score. They are stored as a serialized
+  /// property list, or as its Lingo source in files saved by older authoring tools.
+  fn apply_behavior_initializer(player: &mut DirPlayer, script_instance_ref: &ScriptInstanceRef, data: &[u8]) -> Result<(), ScriptError> {
+    let initializer = if data.first() == Some(&b'[') {
+      let text = data.iter().take_while(|b| **b != 0).map(|b| *b as char).collect::<String>();
+      let datum_ref = eval_lingo(text, player)?;
+      player.get_datum(&datum_ref).clone()
+    } else {
+      deserialize_datum(player, data)?
+    };
+    let pairs = match initializer {
+      Datum::PropList(pairs, _) => pairs,
+      Datum::Void => return Ok(()),
+      initializer => return Err(ScriptError::new(format!("Expected a property list initializer, got {}", initializer.type_str()))),
+    };
+    for (key_ref, value_ref) in pairs {
+      let prop_name = player.get_datum(&key_ref).string_value()?;
+      script_set_prop(player, script_instance_ref, &prop_name, &value_ref, false)?;
+    }
+    Ok(())
+  }
+
+  /// The palette set in the score's palette channel, which stays active until another
+  /// frame changes it.
+  pub fn get_frame_palette(&self, frame_num: u32) -> Option<PaletteRef> {
+    let (_, _, data) = self.channel_initialization_data
+      .iter()
+      .filter(|(frame_index, channel_index, _)| *channel_index == PALETTE_CHANNEL_INDEX && *frame_index < frame_num)
+      .max_by_key(|(frame_index, ..)| *frame_index)?;
+    // The palette channel starts with the palette's cast lib and member numbers
+    let cast_lib = i16::from_be_bytes([data.sprite_type, data.ink]);
+    let cast_member = i16::from_be_bytes([data.fore_color, data.back_color]);
+    if cast_member < 0 {
+      BuiltInPalette::from_i16(cast_member).map(PaletteRef::BuiltIn)
+    } else if cast_member > 0 {
+      Some(PaletteRef::Member(CastMemberRef { cast_lib: cast_lib as i32, cast_member: cast_member as i32 }))
+    } else {
+      None
+    }
+  }
+
   fn is_span_in_frame(span: &ScoreSpriteSpan, frame_num: u32) -> bool {
     span.start_frame <= frame_num && span.end_frame >= frame_num
   }
 
-  pub fn begin_sprites(&mut self, frame_num: u32) {
+  /// Enters the sprites that start at the given frame and creates their behaviors.
+  /// Returns the sprites to send beginSprite to, in channel order.
+  pub fn begin_sprites(&mut self, frame_num: u32) -> Vec<u16> {
 
     // clean up behaviors from previous frame
     let sprites_to_finish = reserve_player_mut(|player| {
@@ -119,6 +184,7 @@ impl Score {
         })
       })
       .cloned()
+      .sorted_by_key(|span| span.channel_number)
       .collect();
 
     let span_init_data: Vec<_> = spans_to_enter.iter()
@@ -133,10 +199,14 @@ impl Score {
       })
       .collect();
 
+    // The script channel has no initialization data, and its span is still entered
+    // once, so that its behaviors last for the whole span
+    for span in spans_to_enter.iter() {
+      self.get_sprite_mut(span.channel_number as i16).entered = true;
+    }
     for (span, data) in span_init_data.iter() {
       let sprite_num = span.channel_number as i16;
       let sprite: &mut Sprite = self.get_sprite_mut(sprite_num);
-      sprite.entered = true;
       let is_sprite = span.channel_number > 0;
       if is_sprite {
         let member = CastMemberRef {
@@ -144,26 +214,46 @@ impl Score {
           cast_member: data.cast_member as i32,
         };
         let _ = sprite_set_prop(sprite_num, "member", Datum::CastMember(member));
-        sprite.ink = data.ink as i32;
+        // The ink byte also holds the trails and stretch flags
+        sprite.ink = (data.ink & 0x3F) as i32;
+        sprite.trails = data.ink & 0x40 != 0;
         sprite.loc_h = data.pos_x as i32;
         sprite.loc_v = data.pos_y as i32;
         sprite.width = data.width as i32;
         sprite.height = data.height as i32;
         sprite.color = ColorRef::PaletteIndex(data.fore_color);
         sprite.bg_color = ColorRef::PaletteIndex(data.back_color);
+        sprite.moveable = data.color_code & 0x80 != 0;
+        sprite.blend = data.blend();
       }
     }
   
-    for span in spans_to_enter.iter() {
-      if let Some(behavior_ref) = span.scripts.first() {
-        let (_, datum_ref) = Self::create_behavior(behavior_ref.cast_lib as i32, behavior_ref.cast_member as i32);
-        let scripts = Datum::List(DatumType::List, vec![datum_ref], false);
-        let _ = sprite_set_prop(span.channel_number as i16, "scriptInstanceList", scripts);
-        player_dispatch_event_to_sprite(&"beginSprite".to_owned(), &vec![], span.channel_number as u16);
+    if reserve_player_ref(|player| player.is_safe_mode) {
+      return vec![];
+    }
+    let mut sprite_nums = vec![];
+    for span in spans_to_enter.iter().filter(|span| !span.scripts.is_empty()) {
+      let mut behaviors = vec![];
+      for behavior_ref in span.scripts.iter() {
+        let (script_instance_ref, datum_ref) = Self::create_behavior(behavior_ref.cast_lib as i32, behavior_ref.cast_member as i32);
+        if let Some(initializer_data) = &behavior_ref.initializer_data {
+          reserve_player_mut(|player| {
+            if let Err(err) = Self::apply_behavior_initializer(player, &script_instance_ref, initializer_data) {
+              warn!("Failed to apply initializer of sprite {} behavior: {}", span.channel_number, err.message);
+            }
+          });
+        }
+        behaviors.push(datum_ref);
       }
+      let scripts = Datum::List(DatumType::List, behaviors, false);
+      let _ = sprite_set_prop(span.channel_number as i16, "scriptInstanceList", scripts);
+      sprite_nums.push(span.channel_number as u16);
     }
+    sprite_nums
   }
 
+  /// The sprites that leave the stage when going from `prev_frame` to `next_frame`, in
+  /// channel order. They are sent endSprite before the next frame's members are set.
   pub fn end_sprites(&mut self, prev_frame: u32, next_frame: u32) -> Vec<u32> {
     let channels_to_end: Vec<u32> = self.sprite_spans
       .iter()
@@ -171,16 +261,165 @@ impl Score {
         Self::is_span_in_frame(span, prev_frame) && !Self::is_span_in_frame(span, next_frame)
       })
       .map(|span| span.channel_number)
+      .sorted()
+      .dedup()
       .collect_vec();
 
-    for channel_num in channels_to_end.iter() {
-      player_dispatch_event_to_sprite(&"endSprite".to_owned(), &vec![], channel_num.clone() as u16);
-    }
+    reserve_player_mut(|player| {
+      // A sprite that leaves the stage is dropped even if the mouse is still down
+      if player.dragged_sprite.is_some_and(|(sprite_num, _)| channels_to_end.contains(&(sprite_num as u32))) {
+        player.dragged_sprite = None;
+      }
+    });
     channels_to_end
   }
 
+  /// Finds the frame of a label, ignoring case like Director does.
+  pub fn find_label_frame(&self, label: &str) -> Option<u32> {
+    self.frame_labels.iter()
+      .find(|frame_label| frame_label.label.eq_ignore_ascii_case(label))
+      .map(|frame_label| frame_label.frame_num as u32)
+  }
+
+  /// Resolves marker(offset) relative to `current_frame`: 0 is the marker at or
+  /// before the current frame, 1 the next one and -1 the one before the current
+  /// marker. Offsets past the last marker clamp to it, and offsets before the
+  /// first marker resolve to frame 1.
+  pub fn get_marker_frame(&self, current_frame: u32, offset: i32) -> u32 {
+    let mut marker_frames = self.frame_labels.iter()
+      .map(|frame_label| frame_label.frame_num as u32)
+      .collect_vec();
+    marker_frames.sort();
+    marker_frames.dedup();
+    if marker_frames.is_empty() {
+      return 1;
+    }
+    let current_index = marker_frames.iter()
+      .rposition(|frame| *frame <= current_frame)
+      .map_or(-1, |index| index as i32);
+    let target_index = current_index + offset;
+    if target_index < 0 {
+      1
+    } else {
+      marker_frames[(target_index as usize).min(marker_frames.len() - 1)]
+    }
+  }
+
+  /// The last frame that has a sprite or frame script on it.
+  pub fn get_last_frame(&self) -> u32 {
+    self.sprite_spans.iter().map(|span| span.end_frame).max().unwrap_or(1)
+  }
+
+  /// Members placed on sprites anywhere from `first_frame` through `last_frame`.
+  pub fn get_members_in_frames(&self, first_frame: u32, last_frame: u32) -> Vec<CastMemberRef> {
+    let mut members: Vec<CastMemberRef> = vec![];
+    let spans = self.sprite_spans.iter()
+      .filter(|span| span.channel_number > 0 && span.start_frame <= last_frame && span.end_frame >= first_frame);
+    for span in spans {
+      let init_data = self.channel_initialization_data.iter()
+        .find(|(frame_index, channel_index, _data)| {
+          get_channel_number_from_index(*channel_index as u32) == span.channel_number && frame_index + 1 == span.start_frame
+        });
+      if let Some((_, _, data)) = init_data {
+        let member = CastMemberRef {
+          cast_lib: data.cast_lib as i32,
+          cast_member: data.cast_member as i32,
+        };
+        if member.cast_member > 0 && !members.contains(&member) {
+          members.push(member);
+        }
+      }
+    }
+    members
+  }
+
+  /// The parsed score as JSON, with its labels, sprite spans and their behaviors, and
+  /// the channel data of every frame, for tools that visualize or archive movies.
+  pub fn to_json(&self) -> String {
+    let frame_count = self.sprite_spans.iter().map(|span| span.end_frame)
+      .chain(self.channel_initialization_data.iter().map(|(frame_index, ..)| frame_index + 1))
+      .max()
+      .unwrap_or(0);
+
+    let labels = js_sys::Array::from_iter(self.frame_labels.iter().map(|label| {
+      let label_map = js_sys::Map::new();
+      label_map.str_set("frame", &JsValue::from_f64(label.frame_num as f64));
+      label_map.str_set("label", &JsValue::from_str(&label.label));
+      label_map.to_js_object()
+    }));
+
+    let spans = js_sys::Array::from_iter(self.sprite_spans.iter().map(|span| {
+      let behaviors = js_sys::Array::from_iter(span.scripts.iter().map(|behavior| {
+        let behavior_map = js_sys::Map::new();
+        behavior_map.str_set("castLib", &JsValue::from_f64(behavior.cast_lib as f64));
+        behavior_map.str_set("castMember", &JsValue::from_f64(behavior.cast_member as f64));
+        // Older files keep the initializer as Lingo source, newer ones serialized
+        let initializer = match &behavior.initializer_data {
+          Some(data) if data.first() == Some(&b'[') => {
+            JsValue::from_str(&data.iter().take_while(|b| **b != 0).map(|b| *b as char).collect::<String>())
+          }
+          Some(_) => JsValue::TRUE,
+          None => JsValue::NULL,
+        };
+        behavior_map.str_set("initializer", &initializer);
+        behavior_map.to_js_object()
+      }));
+      let span_map = js_sys::Map::new();
+      span_map.str_set("channel", &JsValue::from_f64(span.channel_number as f64));
+      span_map.str_set("startFrame", &JsValue::from_f64(span.start_frame as f64));
+      span_map.str_set("endFrame", &JsValue::from_f64(span.end_frame as f64));
+      span_map.str_set("behaviors", &behaviors);
+      span_map.to_js_object()
+    }));
+
+    let frames = js_sys::Array::from_iter(
+      self.channel_initialization_data.iter()
+        .sorted_by_key(|(frame_index, channel_index, _)| (*frame_index, *channel_index))
+        .group_by(|(frame_index, ..)| *frame_index)
+        .into_iter()
+        .map(|(frame_index, channels)| {
+          let channels = js_sys::Array::from_iter(channels.map(|(_, channel_index, data)| {
+            let channel_map = js_sys::Map::new();
+            channel_map.str_set("channelIndex", &JsValue::from_f64(*channel_index as f64));
+            // The first channels are the script, palette, transition, sound and tempo ones
+            if *channel_index > 5 {
+              channel_map.str_set("channel", &JsValue::from_f64(get_channel_number_from_index(*channel_index as u32) as f64));
+            }
+            channel_map.str_set("spriteType", &JsValue::from_f64(data.sprite_type as f64));
+            channel_map.str_set("castLib", &JsValue::from_f64(data.cast_lib as f64));
+            channel_map.str_set("castMember", &JsValue::from_f64(data.cast_member as f64));
+            channel_map.str_set("ink", &JsValue::from_f64((data.ink & 0x3F) as f64));
+            channel_map.str_set("trails", &JsValue::from_bool(data.ink & 0x40 != 0));
+            channel_map.str_set("foreColor", &JsValue::from_f64(data.fore_color as f64));
+            channel_map.str_set("backColor", &JsValue::from_f64(data.back_color as f64));
+            channel_map.str_set("locH", &JsValue::from_f64(data.pos_x as i16 as f64));
+            channel_map.str_set("locV", &JsValue::from_f64(data.pos_y as i16 as f64));
+            channel_map.str_set("width", &JsValue::from_f64(data.width as f64));
+            channel_map.str_set("height", &JsValue::from_f64(data.height as f64));
+            channel_map.str_set("moveable", &JsValue::from_bool(data.color_code & 0x80 != 0));
+            channel_map.str_set("editable", &JsValue::from_bool(data.color_code & 0x40 != 0));
+            channel_map.str_set("blend", &JsValue::from_f64(data.blend() as f64));
+            channel_map.to_js_object()
+          }));
+          let frame_map = js_sys::Map::new();
+          frame_map.str_set("frame", &JsValue::from_f64((frame_index + 1) as f64));
+          frame_map.str_set("channels", &channels);
+          frame_map.to_js_object()
+        })
+    );
+
+    let score = js_sys::Map::new();
+    score.str_set("frameCount", &JsValue::from_f64(frame_count as f64));
+    score.str_set("channelCount", &JsValue::from_f64(self.get_channel_count() as f64));
+    score.str_set("frameLabels", &labels);
+    score.str_set("spriteSpans", &spans);
+    score.str_set("frames", &frames);
+    js_sys::JSON::stringify(&score.to_js_object()).map(String::from).unwrap_or_default()
+  }
+
   pub fn get_channel_count(&self) -> usize {
-    return self.channels.len() - 1;
+    // A score with no movie loaded has no channels at all
+    return self.channels.len().saturating_sub(1);
   }
 
   pub fn set_channel_count(&mut self, new_count: usize) {
@@ -231,6 +470,7 @@ impl Score {
     for i in 0..score_chunk.frame_interval_primaries.len() {
       let primary = &score_chunk.frame_interval_primaries[i];
       let secondary = &score_chunk.frame_interval_secondaries[i];
+      let tertiary = &score_chunk.frame_interval_tertiaries[i];
 
       let is_frame_script_or_sprite_script = primary.channel_index == 0 || primary.channel_index > 5;
       if is_frame_script_or_sprite_script {
@@ -239,15 +479,15 @@ impl Score {
           channel_number: get_channel_number_from_index(primary.channel_index),
           start_frame: primary.start_frame,
           end_frame: primary.end_frame,
-          scripts: match secondary {
-            None => Vec::new(),
-            Some(sec) => vec![
-              ScoreBehaviorReference {
-                cast_lib: sec.cast_lib,
-                cast_member: sec.cast_member,
-              }
-            ],
-          },
+          // The saved behavior parameters are stored for the first behavior of the span
+          scripts: secondary.iter()
+            .enumerate()
+            .map(|(index, sec)| ScoreBehaviorReference {
+              cast_lib: sec.cast_lib,
+              cast_member: sec.cast_member,
+              initializer_data: if index == 0 { tertiary.clone() } else { None },
+            })
+            .collect(),
         };
         self.sprite_spans.push(sprite_span);
       }
@@ -281,13 +521,16 @@ impl Score {
       .collect_vec();
   }
 
+  /// The behaviors of the sprites in channel order followed by the frame behavior,
+  /// which is the order Director sends events in.
   pub fn get_active_script_instance_list(&self) -> Vec<ScriptInstanceRef> {
     let mut instance_list = vec![];
-    for channel in &self.channels {
+    for channel in self.channels.iter().skip(1) {
       for instance_ref in &channel.sprite.script_instance_list {
         instance_list.push(instance_ref.clone());
       }
     }
+    instance_list.extend(self.get_frame_script_instance_list());
     return instance_list;
   }
 }
@@ -305,6 +548,12 @@ pub fn sprite_get_prop(
     "width" => Ok(Datum::Int(sprite.map_or(0, |sprite| sprite.width) as i32)),
     "height" => Ok(Datum::Int(sprite.map_or(0, |sprite| sprite.height) as i32)),
     "blend" => Ok(Datum::Int(sprite.map_or(0, |sprite| sprite.blend) as i32)),
+    // The volume of a sprite is the volume of the sound channel with the same number
+    "volume" => {
+      let now_ms = player.clock.elapsed_ms();
+      let channel = player.sound_manager.get_channel(sprite_id as u16);
+      Ok(Datum::Int(channel.map_or(0, |channel| channel.volume(now_ms))))
+    }
     "ink" => Ok(Datum::Int(sprite.map_or(0, |sprite| sprite.ink) as i32)),
     "left" => {
       let rect = get_sprite_rect(player, sprite_id);
@@ -329,6 +578,9 @@ pub fn sprite_get_prop(
     "bgColor" => Ok(Datum::ColorRef(sprite.map_or(ColorRef::PaletteIndex(0), |sprite| sprite.bg_color.clone()))),
     "skew" => Ok(Datum::Float(sprite.map_or(0.0, |sprite| sprite.skew))),
     "locH" => Ok(Datum::Int(sprite.map_or(0, |sprite| sprite.loc_h) as i32)),
+    "moveableSprite" => Ok(datum_bool(sprite.map_or(false, |sprite| sprite.moveable))),
+    "constraint" => Ok(Datum::Int(sprite.map_or(0, |sprite| sprite.constraint) as i32)),
+    "trails" => Ok(datum_bool(sprite.is_some_and(|sprite| sprite.trails))),
     "locV" => Ok(Datum::Int(sprite.map_or(0, |sprite| sprite.loc_v) as i32)),
     "locZ" => Ok(Datum::Int(sprite.map_or(0, |sprite| sprite.loc_z) as i32)),
     "member" => Ok(Datum::CastMember(
@@ -340,6 +592,11 @@ pub fn sprite_get_prop(
     "flipH" => Ok(datum_bool(sprite.map_or(false, |sprite| sprite.flip_h))),
     "flipV" => Ok(datum_bool(sprite.map_or(false, |sprite| sprite.flip_v))),
     "rotation" => Ok(Datum::Float(sprite.map_or(0.0, |sprite| sprite.rotation))),
+    "quad" => {
+      let quad = sprite.map_or([(0.0, 0.0); 4], |sprite| get_sprite_quad(player, sprite));
+      let corners = quad.iter().map(|(x, y)| player.alloc_datum(Datum::IntPoint((x.round() as i32, y.round() as i32)))).collect();
+      Ok(Datum::List(DatumType::List, corners, false))
+    },
     "scriptInstanceList" => {
       let instance_ids = sprite.map_or(vec![], |x| x.script_instance_list.clone());
       let instance_ids = instance_ids.iter().map(|x| player.alloc_datum(Datum::ScriptInstanceRef(x.clone()))).collect();
@@ -419,17 +676,23 @@ pub fn sprite_set_prop(
     ),
     "locH" => borrow_sprite_mut(
       sprite_id, 
-      |player| value.int_value(),
+      |player| value.int_value().map(|loc_h| {
+        let loc_v = player.movie.score.get_sprite(sprite_id).map_or(0, |sprite| sprite.loc_v);
+        constrain_sprite_loc(player, sprite_id, (loc_h, loc_v)).0
+      }),
       |sprite, value| {
-        sprite.loc_h = value?;
+        sprite.move_to(value?, sprite.loc_v);
         Ok(())
       }
     ),
     "locV" => borrow_sprite_mut(
       sprite_id, 
-      |player| value.int_value(),
+      |player| value.int_value().map(|loc_v| {
+        let loc_h = player.movie.score.get_sprite(sprite_id).map_or(0, |sprite| sprite.loc_h);
+        constrain_sprite_loc(player, sprite_id, (loc_h, loc_v)).1
+      }),
       |sprite, value| {
-        sprite.loc_v = value?;
+        sprite.move_to(sprite.loc_h, value?);
         Ok(())
       }
     ),
@@ -446,6 +709,7 @@ pub fn sprite_set_prop(
       |player| value.int_value(),
       |sprite, value| {
         sprite.width = value?;
+        sprite.quad = None;
         Ok(())
       }
     ),
@@ -454,6 +718,7 @@ pub fn sprite_set_prop(
       |player| value.int_value(),
       |sprite, value| {
         sprite.height = value?;
+        sprite.quad = None;
         Ok(())
       }
     ),
@@ -482,6 +747,7 @@ pub fn sprite_set_prop(
         } else {
           sprite.rotation = 0.0;
         }
+        sprite.quad = None;
         Ok(())
       }
     ),
@@ -494,6 +760,7 @@ pub fn sprite_set_prop(
         } else {
           sprite.skew = 0.0;
         }
+        sprite.quad = None;
         Ok(())
       }
     ),
@@ -622,19 +889,7 @@ pub fn sprite_set_prop(
     ),
     "cursor" => borrow_sprite_mut(
       sprite_id,
-      |player| {
-        if value.is_int() {
-          Ok(CursorRef::System(value.int_value()?))
-        } else if value.is_list() {
-          let mut cursor_ids = vec![];
-          for cursor_id in value.to_list()? {
-            cursor_ids.push(player.get_datum(cursor_id).int_value()?);
-          }
-          Ok(CursorRef::Member(cursor_ids))
-        } else {
-          Err(ScriptError::new("cursor must be a number or a list".to_string()))
-        }
-      },
+      |player| cursor_ref_from_datum(player, &value),
       |sprite, cursor_ref| {
         sprite.cursor_ref = Some(cursor_ref?);
         Ok(())
@@ -642,12 +897,15 @@ pub fn sprite_set_prop(
     ),
     "loc" => borrow_sprite_mut(
       sprite_id, 
-      |_| {},
-      |sprite, _| {
+      |player| match value {
+        Datum::IntPoint(loc) => Some(constrain_sprite_loc(player, sprite_id, loc)),
+        _ => None,
+      },
+      |sprite, constrained_loc| {
         match value {
-          Datum::IntPoint((x, y)) => {
-            sprite.loc_h = x;
-            sprite.loc_v = y;
+          Datum::IntPoint(_) => {
+            let (x, y) = constrained_loc.unwrap();
+            sprite.move_to(x, y);
             Ok(())
           },
           Datum::Void => Ok(()),
@@ -655,6 +913,26 @@ pub fn sprite_set_prop(
         }
       }
     ),
+    "quad" => borrow_sprite_mut(
+      sprite_id,
+      |player| {
+        let corners = value.to_list()?.iter()
+          .map(|corner| player.get_datum(corner).to_int_point())
+          .collect::<Result<Vec<_>, _>>()?;
+        if corners.len() != 4 {
+          return Err(ScriptError::new("quad must be a list of 4 points".to_string()));
+        }
+        let mut quad = [(0.0, 0.0); 4];
+        for (point, (x, y)) in quad.iter_mut().zip(corners) {
+          *point = (x as f32, y as f32);
+        }
+        Ok(quad)
+      },
+      |sprite, quad| {
+        sprite.quad = Some(quad?);
+        Ok(())
+      }
+    ),
     "rect" => borrow_sprite_mut(
       sprite_id, 
       |player| {
@@ -679,6 +957,7 @@ pub fn sprite_set_prop(
             sprite.loc_v = top + reg_point.1 as i32;
             sprite.width = right - left;
             sprite.height = bottom - top;
+            sprite.quad = None;
             Ok(())
           },
           _ => Err(ScriptError::new("rect must be a rect".to_string())),
@@ -732,6 +1011,39 @@ pub fn sprite_set_prop(
         Ok(())
       }
     ),
+    "moveableSprite" => borrow_sprite_mut(
+      sprite_id, 
+      |_| {},
+      |sprite, _| {
+        sprite.moveable = value.to_bool()?;
+        Ok(())
+      }
+    ),
+    "trails" => borrow_sprite_mut(
+      sprite_id,
+      |_| {},
+      |sprite, _| {
+        sprite.trails = value.to_bool()?;
+        Ok(())
+      }
+    ),
+    "constraint" => borrow_sprite_mut(
+      sprite_id, 
+      |_| {},
+      |sprite, _| {
+        sprite.constraint = value.int_value()? as i16;
+        Ok(())
+      }
+    ),
+    "volume" => reserve_player_mut(|player| {
+      match player.sound_manager.get_channel_mut(sprite_id as u16) {
+        Some(channel) => {
+          channel.set_volume(value.int_value()?);
+          Ok(())
+        }
+        None => Err(ScriptError::new(format!("No sound channel {} for the volume of sprite", sprite_id))),
+      }
+    }),
     prop_name => borrow_sprite_mut(
       sprite_id,
       |_| {},
@@ -755,6 +1067,8 @@ pub fn sprite_set_prop(
   result
 }
 
+/// Matte and background transparent sprites are only hit on the pixels they draw,
+/// other sprites anywhere in their rect.
 pub fn concrete_sprite_hit_test(
   player: &DirPlayer,
   sprite: &Sprite,
@@ -762,11 +1076,302 @@ pub fn concrete_sprite_hit_test(
   y: i32,
 ) -> bool {
   let rect = get_concrete_sprite_rect(player, sprite);
+  let transform = get_sprite_transform(sprite, &rect, 0);
+  let (x, y) = if transform.is_identity() {
+    (x, y)
+  } else {
+    let (x, y) = transform.invert((x as f32 + 0.5, y as f32 + 0.5));
+    (x.floor() as i32, y.floor() as i32)
+  };
   let left = rect.left;
   let top = rect.top;
   let right = rect.right;
   let bottom = rect.bottom;
-  return x >= left && x < right && y >= top && y < bottom;
+  if x < left || x >= right || y < top || y >= bottom {
+    return false;
+  }
+  is_sprite_opaque_at(player, sprite, &rect, x, y)
+}
+
+/// The rotation and skew of a sprite around its loc, or the projection of its rect onto
+/// its quad. `offset` moves it on the stage, like the overscan of the stage bitmap does.
+pub fn get_sprite_transform(sprite: &Sprite, rect: &IntRect, offset: i32) -> SpriteTransform {
+  match &sprite.quad {
+    Some(quad) => SpriteTransform::from_quad(
+      &rect.offset(offset, offset),
+      &quad.map(|(x, y)| (x + offset as f32, y + offset as f32)),
+    ),
+    None => SpriteTransform::new((sprite.loc_h + offset, sprite.loc_v + offset), sprite.rotation, sprite.skew),
+  }
+}
+
+/// The stage points the corners of a sprite are drawn at, from the top left clockwise.
+pub fn get_sprite_quad(player: &DirPlayer, sprite: &Sprite) -> [(f32, f32); 4] {
+  if let Some(quad) = sprite.quad {
+    return quad;
+  }
+  let rect = get_concrete_sprite_rect(player, sprite);
+  let transform = get_sprite_transform(sprite, &rect, 0);
+  [
+    (rect.left, rect.top),
+    (rect.right, rect.top),
+    (rect.right, rect.bottom),
+    (rect.left, rect.bottom),
+  ].map(|(x, y)| transform.apply((x as f32, y as f32)))
+}
+
+/// The size of the member of a sprite, which is stretched over the rect of the sprite.
+fn get_sprite_member_size(player: &DirPlayer, sprite: &Sprite, rect: &IntRect) -> (i32, i32) {
+  let member = sprite.member.as_ref().and_then(|member_ref| player.movie.cast_manager.find_member_by_ref(member_ref));
+  let bitmap = match member.map(|member| &member.member_type) {
+    Some(CastMemberType::Bitmap(bitmap_member)) => player.bitmap_manager.get_bitmap(bitmap_member.image_ref),
+    _ => None,
+  };
+  match bitmap {
+    Some(bitmap) => (bitmap.width as i32, bitmap.height as i32),
+    None => (rect.width(), rect.height()),
+  }
+}
+
+/// `sprite.mapStageToMember(point)`, the point of the member drawn at a stage point, or
+/// None when the sprite isn't drawn there.
+pub fn map_stage_to_member(player: &DirPlayer, sprite: &Sprite, point: (i32, i32)) -> Option<(i32, i32)> {
+  let rect = get_concrete_sprite_rect(player, sprite);
+  if rect.width() <= 0 || rect.height() <= 0 {
+    return None;
+  }
+  let (x, y) = get_sprite_transform(sprite, &rect, 0).invert((point.0 as f32, point.1 as f32));
+  if x < rect.left as f32 || x >= rect.right as f32 || y < rect.top as f32 || y >= rect.bottom as f32 {
+    return None;
+  }
+  let (member_width, member_height) = get_sprite_member_size(player, sprite, &rect);
+  let mut member_x = ((x - rect.left as f32) * member_width as f32 / rect.width() as f32) as i32;
+  let mut member_y = ((y - rect.top as f32) * member_height as f32 / rect.height() as f32) as i32;
+  if sprite.flip_h {
+    member_x = member_width - 1 - member_x;
+  }
+  if sprite.flip_v {
+    member_y = member_height - 1 - member_y;
+  }
+  Some((member_x, member_y))
+}
+
+/// `sprite.mapMemberToStage(point)`, the stage point a point of the member is drawn at.
+pub fn map_member_to_stage(player: &DirPlayer, sprite: &Sprite, point: (i32, i32)) -> (i32, i32) {
+  let rect = get_concrete_sprite_rect(player, sprite);
+  let (member_width, member_height) = get_sprite_member_size(player, sprite, &rect);
+  let point = map_member_to_rect(&rect, (member_width, member_height), (sprite.flip_h, sprite.flip_v), (point.0 as f32, point.1 as f32));
+  let (x, y) = get_sprite_transform(sprite, &rect, 0).apply(point);
+  (x.round() as i32, y.round() as i32)
+}
+
+/// The rect of a sprite on the stage, enclosing it once rotated, skewed or projected
+/// onto its quad.
+pub fn get_sprite_bounds(player: &DirPlayer, sprite: &Sprite) -> IntRect {
+  let rect = get_concrete_sprite_rect(player, sprite);
+  let transform = get_sprite_transform(sprite, &rect, 0);
+  if transform.is_identity() {
+    rect
+  } else {
+    transform.bounds(&rect)
+  }
+}
+
+fn has_pixel_outline(sprite: &Sprite) -> bool {
+  sprite.ink == 8 || sprite.ink == 36
+}
+
+/// Samples the bitmap of a matte or background transparent sprite at a stage point
+/// inside its rect, accounting for stretching and flipping.
+fn is_sprite_opaque_at(player: &DirPlayer, sprite: &Sprite, rect: &IntRect, x: i32, y: i32) -> bool {
+  if !has_pixel_outline(sprite) || rect.width() <= 0 || rect.height() <= 0 {
+    return true;
+  }
+  let member = sprite.member.as_ref().and_then(|member_ref| player.movie.cast_manager.find_member_by_ref(member_ref));
+  let bitmap = match member.map(|member| &member.member_type) {
+    Some(CastMemberType::Bitmap(bitmap_member)) => player.bitmap_manager.get_bitmap(bitmap_member.image_ref),
+    _ => None,
+  };
+  let bitmap = match bitmap {
+    Some(bitmap) if bitmap.width > 0 && bitmap.height > 0 => bitmap,
+    _ => return true,
+  };
+  let mut bitmap_x = (x - rect.left) * bitmap.width as i32 / rect.width();
+  let mut bitmap_y = (y - rect.top) * bitmap.height as i32 / rect.height();
+  if sprite.flip_h {
+    bitmap_x = bitmap.width as i32 - 1 - bitmap_x;
+  }
+  if sprite.flip_v {
+    bitmap_y = bitmap.height as i32 - 1 - bitmap_y;
+  }
+  let (bitmap_x, bitmap_y) = (bitmap_x as u16, bitmap_y as u16);
+  match (sprite.ink, &bitmap.matte) {
+    (8, Some(matte)) => matte.get_bit(bitmap_x, bitmap_y),
+    // The matte is made the first time the sprite is drawn, until then only the
+    // background color is left out
+    (8, None) => bitmap.get_pixel_color_ref(bitmap_x, bitmap_y) != bitmap.get_bg_color_ref(),
+    _ => {
+      let palettes = player.movie.cast_manager.palettes();
+      let bg_color = resolve_color_ref(&palettes, &sprite.bg_color, &bitmap.palette_ref);
+      bitmap.get_pixel_color(&palettes, bitmap_x, bitmap_y) != bg_color
+    }
+  }
+}
+
+/// The outline of a sprite, looked up once so it can be tested at every point of a
+/// region. Matches concrete_sprite_hit_test.
+pub struct SpriteOutline<'a> {
+  rect: IntRect,
+  transform: SpriteTransform,
+  flip_h: bool,
+  flip_v: bool,
+  /// The opaque pixels of the bitmap, or None when the whole rect is opaque.
+  mask: Option<Cow<'a, BitmapMask>>,
+}
+
+impl<'a> SpriteOutline<'a> {
+  pub fn new(player: &'a DirPlayer, sprite: &Sprite) -> SpriteOutline<'a> {
+    let rect = get_concrete_sprite_rect(player, sprite);
+    let transform = get_sprite_transform(sprite, &rect, 0);
+    let member = sprite.member.as_ref().and_then(|member_ref| player.movie.cast_manager.find_member_by_ref(member_ref));
+    let bitmap = match member.map(|member| &member.member_type) {
+      Some(CastMemberType::Bitmap(bitmap_member)) => player.bitmap_manager.get_bitmap(bitmap_member.image_ref),
+      _ => None,
+    };
+    let mask = bitmap
+      .filter(|bitmap| has_pixel_outline(sprite) && bitmap.width > 0 && bitmap.height > 0 && rect.width() > 0 && rect.height() > 0)
+      .map(|bitmap| match (sprite.ink, &bitmap.matte) {
+        (8, Some(matte)) => Cow::Borrowed(matte.as_ref()),
+        // The matte is made the first time the sprite is drawn, until then only the
+        // background color is left out
+        (8, None) => Cow::Owned(bitmap.get_mask(&player.movie.cast_manager.palettes(), &bitmap.get_bg_color_ref())),
+        _ => {
+          let palettes = player.movie.cast_manager.palettes();
+          let bg_color = resolve_color_ref(&palettes, &sprite.bg_color, &bitmap.palette_ref);
+          let mut mask = BitmapMask::new(bitmap.width, bitmap.height, false);
+          for y in 0..bitmap.height {
+            for x in 0..bitmap.width {
+              mask.set_bit(x, y, bitmap.get_pixel_color(&palettes, x, y) != bg_color);
+            }
+          }
+          Cow::Owned(mask)
+        }
+      });
+    SpriteOutline { rect, transform, flip_h: sprite.flip_h, flip_v: sprite.flip_v, mask }
+  }
+
+  pub fn contains(&self, x: i32, y: i32) -> bool {
+    let (x, y) = if self.transform.is_identity() {
+      (x, y)
+    } else {
+      let (x, y) = self.transform.invert((x as f32 + 0.5, y as f32 + 0.5));
+      (x.floor() as i32, y.floor() as i32)
+    };
+    let rect = &self.rect;
+    if x < rect.left || x >= rect.right || y < rect.top || y >= rect.bottom {
+      return false;
+    }
+    let mask = match &self.mask {
+      Some(mask) => mask,
+      None => return true,
+    };
+    let mut mask_x = (x - rect.left) * mask.width as i32 / rect.width();
+    let mut mask_y = (y - rect.top) * mask.height as i32 / rect.height();
+    if self.flip_h {
+      mask_x = mask.width as i32 - 1 - mask_x;
+    }
+    if self.flip_v {
+      mask_y = mask.height as i32 - 1 - mask_y;
+    }
+    mask.get_bit(mask_x as u16, mask_y as u16)
+  }
+}
+
+/// `sprite a intersects b`. The outlines of two matte sprites are compared, otherwise
+/// their rects.
+pub fn sprites_intersect(player: &DirPlayer, first: &Sprite, second: &Sprite) -> bool {
+  let first_rect = get_sprite_bounds(player, first);
+  let second_rect = get_sprite_bounds(player, second);
+  if !first_rect.intersects(&second_rect) {
+    return false;
+  }
+  if first.ink != 8 || second.ink != 8 {
+    return true;
+  }
+  let first_outline = SpriteOutline::new(player, first);
+  let second_outline = SpriteOutline::new(player, second);
+  let overlap = first_rect.intersect(&second_rect);
+  (overlap.top..overlap.bottom).any(|y| {
+    (overlap.left..overlap.right).any(|x| first_outline.contains(x, y) && second_outline.contains(x, y))
+  })
+}
+
+/// `sprite a within b`, comparing outlines the same way as sprites_intersect.
+pub fn sprite_within(player: &DirPlayer, first: &Sprite, second: &Sprite) -> bool {
+  let first_rect = get_sprite_bounds(player, first);
+  let second_rect = get_sprite_bounds(player, second);
+  if first.ink != 8 || second.ink != 8 {
+    return first_rect.left >= second_rect.left
+      && first_rect.top >= second_rect.top
+      && first_rect.right <= second_rect.right
+      && first_rect.bottom <= second_rect.bottom;
+  }
+  let first_outline = SpriteOutline::new(player, first);
+  // Apart from the bounds of the second sprite there's nothing of it to be within
+  let outside_is_empty = |rect: &IntRect| {
+    (rect.top..rect.bottom).all(|y| (rect.left..rect.right).all(|x| !first_outline.contains(x, y)))
+  };
+  if !first_rect.intersects(&second_rect) {
+    return outside_is_empty(&first_rect);
+  }
+  let overlap = first_rect.intersect(&second_rect);
+  let outside = [
+    IntRect::from(first_rect.left, first_rect.top, first_rect.right, overlap.top),
+    IntRect::from(first_rect.left, overlap.bottom, first_rect.right, first_rect.bottom),
+    IntRect::from(first_rect.left, overlap.top, overlap.left, overlap.bottom),
+    IntRect::from(overlap.right, overlap.top, first_rect.right, overlap.bottom),
+  ];
+  if !outside.iter().all(outside_is_empty) {
+    return false;
+  }
+  let second_outline = SpriteOutline::new(player, second);
+  (overlap.top..overlap.bottom).all(|y| {
+    (overlap.left..overlap.right).all(|x| !first_outline.contains(x, y) || second_outline.contains(x, y))
+  })
+}
+
+/// Keeps a loc inside the rect of the constraint sprite of a sprite, if it has one.
+pub fn constrain_sprite_loc(player: &DirPlayer, sprite_id: i16, loc: (i32, i32)) -> (i32, i32) {
+  let constraint = player.movie.score.get_sprite(sprite_id).map_or(0, |sprite| sprite.constraint);
+  if constraint <= 0 || constraint == sprite_id {
+    return loc;
+  }
+  let (left, top, right, bottom) = get_sprite_rect(player, constraint);
+  (loc.0.clamp(left, right.max(left)), loc.1.clamp(top, bottom.max(top)))
+}
+
+/// `constrainH(sprite, h)` and `constrainV(sprite, v)` clamp a coordinate to the rect
+/// of a sprite.
+pub fn constrain_to_sprite(player: &DirPlayer, sprite_id: i16, value: i32, is_horizontal: bool) -> i32 {
+  let (left, top, right, bottom) = get_sprite_rect(player, sprite_id);
+  if is_horizontal {
+    value.clamp(left, right.max(left))
+  } else {
+    value.clamp(top, bottom.max(top))
+  }
+}
+
+pub fn compare_sprites(
+  player: &DirPlayer,
+  first_num: i16,
+  second_num: i16,
+  compare: fn(&DirPlayer, &Sprite, &Sprite) -> bool,
+) -> Result<bool, ScriptError> {
+  let get_sprite = |sprite_num: i16| {
+    player.movie.score.get_sprite(sprite_num)
+      .ok_or_else(|| ScriptError::new(format!("Sprite {} does not exist", sprite_num)))
+  };
+  Ok(compare(player, get_sprite(first_num)?, get_sprite(second_num)?))
 }
 
 pub fn get_sprite_at(player: &DirPlayer, x: i32, y: i32, scripted: bool) -> Option<u32> {
@@ -798,16 +1403,13 @@ pub fn get_concrete_sprite_rect(player: &DirPlayer, sprite: &Sprite) -> IntRect
           return IntRect::from(sprite.loc_h, sprite.loc_v, sprite.width, sprite.height);
         }
         let src_bitmap = sprite_bitmap.unwrap();
-        let reg_x = if sprite.flip_h { src_bitmap.width as i16 - bitmap_member.reg_point.0 } else { bitmap_member.reg_point.0 };
-        let reg_y = if sprite.flip_v { src_bitmap.height as i16 - bitmap_member.reg_point.1 } else { bitmap_member.reg_point.1 };
-
-        let dst_rect = IntRect::from(
-            sprite.loc_h - reg_x as i32,
-            sprite.loc_v - reg_y as i32, 
-            sprite.loc_h - reg_x as i32 + sprite.width, 
-            sprite.loc_v - reg_y as i32 + sprite.height
-        );
-        dst_rect
+        get_registered_rect(
+            (sprite.loc_h, sprite.loc_v),
+            (sprite.width, sprite.height),
+            (src_bitmap.width as i32, src_bitmap.height as i32),
+            (bitmap_member.reg_point.0 as i32, bitmap_member.reg_point.1 as i32),
+            (sprite.flip_h, sprite.flip_v),
+        )
     }
     CastMemberType::Shape(shape_member) => {
         let reg_x = shape_member.shape_info.reg_point.0;
@@ -819,7 +1421,11 @@ pub fn get_concrete_sprite_rect(player: &DirPlayer, sprite: &Sprite) -> IntRect
           sprite.height + sprite.loc_v - reg_y as i32,
         )
     }
-    CastMemberType::Field(field_member) => IntRect::from_size(sprite.loc_h, sprite.loc_v, field_member.width as i32, 12), // TODO
+    CastMemberType::Field(field_member) => {
+      let height = TextLayout::for_member(&player.font_manager, &member.member_type)
+        .map_or(12, |layout| layout.get_field_box_height(field_member));
+      IntRect::from_size(sprite.loc_h, sprite.loc_v, field_member.width as i32, height)
+    }
     CastMemberType::Text(text_member) => IntRect::from_size(sprite.loc_h, sprite.loc_v, text_member.width as i32, 12), // TODO
     _ => IntRect::from_size(sprite.loc_h, sprite.loc_v, sprite.width, sprite.height)
   }
@@ -4,11 +4,11 @@ use fxhash::FxHashMap;
 use itertools::Itertools;
 
 use crate::director::{
-    chunks::{handler::HandlerDef, script::ScriptChunk}, enums::ScriptType, file::get_variable_multiplier, lingo::{datum::Datum, script::ScriptContext}
+    chunks::{handler::HandlerDef, script::ScriptChunk}, enums::ScriptType, file::get_variable_multiplier, lingo::{datum::{Datum, StringChunkSource}, script::ScriptContext}
 };
 
 use super::{
-    allocator::{DatumAllocatorTrait, ScriptInstanceAllocatorTrait}, bytecode::handler_manager::BytecodeHandlerContext, cast_lib::{player_cast_lib_set_prop, CastMemberRef}, datum_formatting::{format_concrete_datum, format_datum}, handlers::{datum_handlers::{bitmap::BitmapDatumHandlers, cast_member_ref::CastMemberRefHandlers, color::ColorDatumHandlers, int::IntDatumHandlers, list_handlers::ListDatumUtils, point::PointDatumHandlers, prop_list::PropListUtils, rect::RectDatumHandlers, sound::SoundDatumHandlers, string::StringDatumUtils, string_chunk::StringChunkHandlers, symbol::SymbolDatumHandlers, timeout::TimeoutDatumHandlers, void::VoidDatumHandlers}, types::TypeUtils}, reserve_player_mut, reserve_player_ref, scope::Scope, score::{sprite_get_prop, sprite_set_prop}, script_ref::ScriptInstanceRef, stage::{get_stage_prop, set_stage_prop}, DatumRef, DirPlayer, ScriptError
+    allocator::{DatumAllocatorTrait, ScriptInstanceAllocatorTrait}, bytecode::handler_manager::BytecodeHandlerContext, cast_lib::{player_cast_lib_set_prop, CastMemberRef}, datum_formatting::{format_concrete_datum, format_datum}, handlers::{datum_handlers::{bitmap::BitmapDatumHandlers, cast_member::text::TextMemberHandlers, cast_member_ref::CastMemberRefHandlers, color::ColorDatumHandlers, date::DateDatumHandlers, int::IntDatumHandlers, list_handlers::ListDatumUtils, point::PointDatumHandlers, prop_list::PropListUtils, rect::RectDatumHandlers, sound::SoundDatumHandlers, string::StringDatumUtils, string_chunk::StringChunkHandlers, symbol::SymbolDatumHandlers, timeout::TimeoutDatumHandlers, void::VoidDatumHandlers, window::WindowDatumHandlers}, types::TypeUtils}, reserve_player_mut, reserve_player_ref, scope::Scope, score::{sprite_get_prop, sprite_set_prop}, script_ref::ScriptInstanceRef, stage::{get_stage_prop, set_stage_prop}, DatumRef, DirPlayer, ScriptError
 };
 
 #[derive(Clone)]
@@ -20,6 +20,9 @@ pub struct Script {
     pub handlers: FxHashMap<String, Rc<HandlerDef>>,
     pub handler_names: Vec<String>,
     pub properties: RefCell<FxHashMap<String, DatumRef>>,
+    /// The names of a script compiled apart from its cast, like the statements run by
+    /// `do`, which its bytecode refers to instead of the names of the cast.
+    pub lctx: Option<Rc<ScriptContext>>,
 }
 
 pub type ScriptInstanceId = u32;
@@ -30,25 +33,65 @@ pub struct ScriptInstance {
     pub script: CastMemberRef,
     pub ancestor: Option<ScriptInstanceRef>,
     pub properties: FxHashMap<String, DatumRef>,
+    /// Names of the properties in the order they were declared or added, for getPropAt.
+    pub property_names: Vec<String>,
 }
 
 impl ScriptInstance {
     pub fn new(instance_id: ScriptInstanceId, script_ref: CastMemberRef, script_def: &Script, lctx: &ScriptContext) -> ScriptInstance {
-        let mut properties = FxHashMap::default();
-        for name_id in script_def.chunk.property_name_ids.iter() {
-            let name = lctx.names[*name_id as usize].clone();
-            properties.insert(name, DatumRef::Void);
-        }
-        ScriptInstance {
+        let mut instance = ScriptInstance {
             instance_id,
             script: script_ref,
             ancestor: None,
-            properties,
+            properties: FxHashMap::default(),
+            property_names: vec![],
+        };
+        for name_id in script_def.chunk.property_name_ids.iter() {
+            instance.set_own_prop(&lctx.names[*name_id as usize], DatumRef::Void);
         }
+        instance
+    }
+
+    /// Sets a property of the instance itself, adding it when it's new.
+    pub fn set_own_prop(&mut self, name: &str, value: DatumRef) {
+        if self.properties.insert(name.to_owned(), value).is_none() {
+            self.property_names.push(name.to_owned());
+        }
+    }
+
+    pub fn get_own_prop_at(&self, index: usize) -> Option<(&String, &DatumRef)> {
+        let name = self.property_names.get(index)?;
+        self.properties.get(name).map(|value| (name, value))
     }
 }
 
 impl Script {
+    /// Builds a script from a chunk, looking up the names of its handlers and properties
+    /// in the names of its cast.
+    pub fn new(member_ref: CastMemberRef, name: String, chunk: ScriptChunk, script_type: ScriptType, names: &[String]) -> Script {
+        let mut handler_names = Vec::new();
+        let mut handlers = FxHashMap::default();
+        for handler in &chunk.handlers {
+            let handler_name = &names[handler.name_id as usize];
+            handlers.insert(handler_name.to_lowercase(), Rc::new(handler.clone()));
+            handler_names.push(handler_name.to_owned());
+        }
+        let mut properties = FxHashMap::default();
+        for name_id in &chunk.property_name_ids {
+            properties.insert(names[*name_id as usize].to_owned(), DatumRef::Void);
+        }
+        Script {
+            member_ref,
+            name,
+            chunk,
+            script_type,
+            handlers,
+            handler_names,
+            properties: RefCell::new(properties),
+            lctx: None,
+        }
+    }
+
     pub fn get_own_handler_ref_at(&self, index: usize) -> Option<ScriptHandlerRef> {
         return self.handler_names.get(index).map(|x| (self.member_ref.clone(), x.clone()));
     }
@@ -83,25 +126,17 @@ pub fn script_get_prop_opt(
 ) -> Option<DatumRef> {
     let script_instance = player.allocator.get_script_instance(&script_instance_ref);
     if prop_name == "ancestor" {
-        let script_instance = player.allocator.get_script_instance(&script_instance_ref);
         if let Some(ancestor_id) = &script_instance.ancestor {
             Some(player.alloc_datum(Datum::ScriptInstanceRef(ancestor_id.clone())))
         } else {
             Some(DatumRef::Void)
         }
     } else {
-        // Try to find the property on the current instance
-        let prop_value = script_instance.properties
-            .get(prop_name)
-            .map(|x| x.clone());
-        if let Some(prop) = prop_value {
-            Some(prop)
-        } else if script_instance.ancestor.is_some() {
-            let ancestor_ref = script_instance.ancestor.as_ref().unwrap().clone();
-            script_get_prop_opt(player, &ancestor_ref, prop_name)
-        } else {
-            None
-        }
+        // Take the property from the instance or the nearest ancestor that has it
+        let chain = player.allocator.get_ancestor_chain(**script_instance_ref).ok()?;
+        chain.iter()
+            .filter_map(|id| player.allocator.get_script_instance_by_id(*id))
+            .find_map(|instance| instance.properties.get(prop_name).cloned())
     }
 }
 
@@ -172,37 +207,26 @@ pub fn script_set_prop(
     value_ref: &DatumRef,
     required: bool,
 ) -> Result<(), ScriptError> {
-    // Try to set the property on the current instance
-    let result = {
-        if prop_name == "ancestor" {
-            let ancestor_id = player.allocator.get_datum(value_ref).to_script_instance_ref()?.clone();
-            let script_instance = player.allocator.get_script_instance_mut(&script_instance_ref);
-            script_instance.ancestor = Some(ancestor_id);
+    let result = if prop_name == "ancestor" {
+        let ancestor_id = player.allocator.get_datum(value_ref).to_script_instance_ref()?.clone();
+        player.allocator.set_script_instance_ancestor(script_instance_ref, Some(ancestor_id))?;
+        Ok(())
+    } else {
+        // Set the property on the instance or the nearest ancestor that has it
+        let chain = player.allocator.get_ancestor_chain(**script_instance_ref)?;
+        let owner_id = chain.iter().copied().find(|id| {
+            player.allocator.get_script_instance_by_id(*id).is_some_and(|instance| instance.properties.contains_key(prop_name))
+        });
+        if let Some(owner_id) = owner_id {
+            let owner = player.allocator.script_instances.get_mut(&owner_id).unwrap();
+            owner.script_instance.properties.insert(prop_name.clone(), value_ref.clone());
             Ok(())
         } else {
-            let script_instance = player.allocator.get_script_instance_mut(&script_instance_ref);
-            if let Some(prop) = script_instance.properties.get_mut(prop_name) {
-                *prop = value_ref.clone();
-                Ok(())
-            } else {
-                Err(ScriptError::new(format!(
-                    "Cannot set property {} found on script instance {}",
-                    prop_name, 
-                    format_concrete_datum(&Datum::ScriptInstanceRef(script_instance_ref.clone()), player)
-                )))
-            }
-        }
-    };
-    // If the property was not found on the current instance, try to set it on the ancestor
-    let result = match result {
-        Ok(_) => Ok(()),
-        Err(_) => {
-            let script_instance = player.allocator.get_script_instance(&script_instance_ref);
-            if let Some(ancestor_id) = &script_instance.ancestor {
-                script_set_prop(player, &ancestor_id.clone(), prop_name, value_ref, true)
-            } else {
-                Err(ScriptError::new("No ancestor found".to_string()))
-            }
+            Err(ScriptError::new(format!(
+                "Cannot set property {} found on script instance {}",
+                prop_name,
+                format_concrete_datum(&Datum::ScriptInstanceRef(script_instance_ref.clone()), player)
+            )))
         }
     };
     let result = match result {
@@ -212,7 +236,7 @@ pub fn script_set_prop(
                 Err(err)
             } else {
                 let script_instance = player.allocator.get_script_instance_mut(&script_instance_ref);
-                script_instance.properties.insert(prop_name.to_owned(), value_ref.clone());
+                script_instance.set_own_prop(prop_name, value_ref.clone());
                 Ok(())
             }
         }
@@ -239,14 +263,14 @@ pub fn get_current_script<'a>(
     player: &'a DirPlayer,
     ctx: &'a BytecodeHandlerContext,
 ) -> Option<&'a Script> {
-    return Some(unsafe { &*ctx.script_ptr });
+    return Some(&ctx.script);
 }
 
 pub fn get_current_handler_def<'a>(
     _: &'a DirPlayer,
     ctx: &'a BytecodeHandlerContext,
 ) -> &'a HandlerDef {
-    return unsafe { &*ctx.handler_def_ptr };
+    return &ctx.handler_def;
 }
 
 pub fn get_current_variable_multiplier(player: &DirPlayer, ctx: &BytecodeHandlerContext) -> u32 {
@@ -277,6 +301,9 @@ pub fn get_lctx_for_script<'a>(
     player: &'a DirPlayer,
     script: &'a Script,
 ) -> Option<&'a ScriptContext> {
+    if let Some(lctx) = &script.lctx {
+        return Some(lctx);
+    }
     let cast = player
         .movie
         .cast_manager
@@ -285,6 +312,17 @@ pub fn get_lctx_for_script<'a>(
     return cast.lctx.as_ref();
 }
 
+/// The names the bytecode of a scope's script refers to.
+pub fn get_lctx_for_script_ref<'a>(
+    player: &'a DirPlayer,
+    script_ref: &CastMemberRef,
+) -> Option<&'a ScriptContext> {
+    match player.movie.cast_manager.get_script_by_ref(script_ref) {
+        Some(script) => get_lctx_for_script(player, script),
+        None => player.movie.cast_manager.get_cast(script_ref.cast_lib as u32).ok()?.lctx.as_ref(),
+    }
+}
+
 pub fn get_name<'a>(
     player: &'a DirPlayer,
     ctx: &'a BytecodeHandlerContext,
@@ -362,6 +400,12 @@ pub async fn player_set_obj_prop(
         Datum::SoundRef(..) => reserve_player_mut(|player| {
             SoundDatumHandlers::set_prop(player, obj_ref, prop_name, value_ref)
         }),
+        Datum::WindowRef(..) => reserve_player_mut(|player| {
+            WindowDatumHandlers::set_prop(player, obj_ref, prop_name, value_ref)
+        }),
+        Datum::Date(..) => reserve_player_mut(|player| {
+            DateDatumHandlers::set_prop(player, obj_ref, prop_name, value_ref)
+        }),
         Datum::ScriptRef(script_ref) => reserve_player_mut(|player| {
             script_set_static_prop(player, &script_ref, prop_name, value_ref, false)
         }),
@@ -418,6 +462,10 @@ pub fn get_obj_prop(
         }
         Datum::BitmapRef(_) => BitmapDatumHandlers::get_prop(player, obj_ref, prop_name),
         Datum::String(s) => Ok(player.alloc_datum(StringDatumUtils::get_built_in_prop(&s, &prop_name)?)),
+        Datum::StringChunk(StringChunkSource::Member(member_ref), chunk_expr, _) if matches!(prop_name.as_str(), "hyperlink" | "hyperlinkRange" | "hyperlinkState") => {
+            let result = TextMemberHandlers::get_chunk_hyperlink_prop(player, &member_ref, &chunk_expr, prop_name)?;
+            Ok(player.alloc_datum(result))
+        }
         Datum::StringChunk(..) => Ok(player.alloc_datum(StringDatumUtils::get_built_in_prop(&obj_clone.string_value()?, &prop_name)?)),
         Datum::TimeoutRef(_) => Ok(TimeoutDatumHandlers::get_prop(player, obj_ref, &prop_name)?),
         Datum::Symbol(_) => SymbolDatumHandlers::get_prop(player, obj_ref, &prop_name),
@@ -425,7 +473,12 @@ pub fn get_obj_prop(
         Datum::Int(_) => IntDatumHandlers::get_prop(player, obj_ref, &prop_name),
         Datum::ColorRef(_) => ColorDatumHandlers::get_prop(player, obj_ref, &prop_name),
         Datum::PlayerRef => player.get_player_prop(prop_name),
-        Datum::SoundRef(_) => Ok(player.alloc_datum(SoundDatumHandlers::get_prop(player, obj_ref, &prop_name)?)),
+        Datum::SoundRef(_) => {
+            let result = SoundDatumHandlers::get_prop(player, obj_ref, &prop_name)?;
+            Ok(player.alloc_datum(result))
+        }
+        Datum::WindowRef(_) => WindowDatumHandlers::get_prop(player, obj_ref, prop_name),
+        Datum::Date(_) => Ok(player.alloc_datum(DateDatumHandlers::get_prop(player, obj_ref, prop_name)?)),
         _ => {
             if prop_name == "ilk" {
                 let ilk = TypeUtils::get_datum_ilk(&obj_clone)?;
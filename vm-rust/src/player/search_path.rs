@@ -0,0 +1,115 @@
+use url::Url;
+
+use crate::director::lingo::datum::{Datum, DatumType};
+
+use super::{DatumRef, DirPlayer, ScriptError};
+
+/// Converts a Director file reference into a URL path. `@` stands for the folder of
+/// the movie, and `:` (Mac) and `\` (Windows) separate folders. Absolute paths point
+/// at the authoring machine, so only their file name is kept.
+pub fn normalize_director_path(path: &str) -> String {
+  if path.contains("://") {
+    return path.to_owned();
+  }
+  if let Some(rest) = path.strip_prefix('@') {
+    // Every separator after the first one goes up a folder, like :: in Mac paths
+    let separator_count = rest.chars().take_while(|c| matches!(c, ':' | '/' | '\\')).count();
+    let relative = rest[separator_count..].replace([':', '\\'], "/");
+    return "../".repeat(separator_count.saturating_sub(1)) + &relative;
+  }
+  let bytes = path.as_bytes();
+  let is_drive_path = bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && matches!(bytes[2], b'\\' | b'/');
+  if is_drive_path || path.starts_with("\\\\") {
+    return get_file_name(path).to_owned();
+  }
+  if path.contains(':') {
+    // Mac paths are relative when they start with a colon, e.g. :sounds:music.swa
+    return match path.strip_prefix(':') {
+      Some(relative) => relative.replace([':', '\\'], "/"),
+      None => get_file_name(path).to_owned(),
+    };
+  }
+  path.replace('\\', "/")
+}
+
+pub fn get_file_name(path: &str) -> &str {
+  path.rsplit([':', '/', '\\']).next().unwrap_or(path)
+}
+
+/// Turns a normalized path into a URL. Paths starting with a slash are local files
+/// when there's no movie folder to resolve them against.
+pub fn resolve_path_url(path: &str, base_path: Option<&Url>) -> Url {
+  if let Ok(url) = Url::parse(path) {
+    if url.has_host() {
+      return url;
+    }
+  }
+  match base_path {
+    Some(base_path) => base_path.join(path).unwrap(),
+    None if path.starts_with('/') => Url::parse(&format!("file://{path}")).unwrap(),
+    None => Url::parse(path).unwrap_or_else(|_| Url::parse(&format!("file:///{path}")).unwrap()),
+  }
+}
+
+/// Places to look for a file that wasn't found where it was first looked for: each
+/// search path followed by the movie folder, in the order Director tries them.
+pub fn get_fallback_urls(path: &str, base_path: Option<&Url>, search_paths: &[String]) -> Vec<Url> {
+  let file_name = get_file_name(path);
+  let mut urls: Vec<Url> = vec![];
+  for search_path in search_paths {
+    let mut folder = normalize_director_path(search_path);
+    if folder.is_empty() {
+      continue;
+    }
+    if !folder.ends_with('/') {
+      folder.push('/');
+    }
+    urls.push(resolve_path_url(&format!("{folder}{file_name}"), base_path));
+  }
+  urls.push(resolve_path_url(file_name, base_path));
+  urls.dedup();
+  urls
+}
+
+/// The list behind `the searchPath`. Lingo appends to it in place, so the same list is
+/// handed out until the search paths are replaced.
+pub fn search_path_list(player: &mut DirPlayer) -> DatumRef {
+  if let Some(list_ref) = &player.search_path_list {
+    return list_ref.clone();
+  }
+  let item_refs = player.net_manager.search_paths.clone()
+    .into_iter()
+    .map(|path| player.alloc_datum(Datum::String(path)))
+    .collect();
+  let list_ref = player.alloc_datum(Datum::List(DatumType::List, item_refs, false));
+  player.search_path_list = Some(list_ref.clone());
+  list_ref
+}
+
+pub fn set_search_paths(player: &mut DirPlayer, value: &Datum) -> Result<(), ScriptError> {
+  let paths = match value {
+    Datum::List(_, item_refs, _) => item_refs.iter()
+      .map(|item_ref| player.get_datum(item_ref).string_value())
+      .collect::<Result<Vec<_>, _>>()?,
+    Datum::String(path) if path.is_empty() => vec![],
+    Datum::String(path) => vec![path.to_owned()],
+    _ => return Err(ScriptError::new("searchPath must be a list".to_string())),
+  };
+  player.net_manager.search_paths = paths;
+  player.search_path_list = None;
+  Ok(())
+}
+
+/// Copies changes made by Lingo to `the searchPath` over to the net manager.
+pub fn sync_search_paths(player: &mut DirPlayer) {
+  let list_ref = match &player.search_path_list {
+    Some(list_ref) => list_ref.clone(),
+    None => return,
+  };
+  if let Datum::List(_, item_refs, _) = player.get_datum(&list_ref) {
+    let paths = item_refs.iter()
+      .filter_map(|item_ref| player.get_datum(item_ref).string_value().ok())
+      .collect();
+    player.net_manager.search_paths = paths;
+  }
+}
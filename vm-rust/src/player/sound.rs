@@ -0,0 +1,380 @@
+use crate::{director::chunks::sound::SoundChunk, js_api::JsApi};
+
+use super::{cast_lib::CastMemberRef, cast_manager::CastManager, cast_member::CastMemberType};
+
+pub const SOUND_CHANNEL_COUNT: usize = 8;
+pub const MAX_SOUND_VOLUME: i32 = 255;
+pub const MAX_SOUND_LEVEL: i32 = 7;
+
+/// The status codes of a sound channel, as read from `sound(n).status`.
+pub const SOUND_STATUS_IDLE: i32 = 0;
+pub const SOUND_STATUS_QUEUED: i32 = 2;
+pub const SOUND_STATUS_PLAYING: i32 = 3;
+pub const SOUND_STATUS_PAUSED: i32 = 4;
+
+#[derive(Clone)]
+pub struct SoundPlaylistEntry {
+  pub member: CastMemberRef,
+  pub loop_count: i32,
+}
+
+/// A volume ramp started by fadeIn, fadeOut or fadeTo.
+#[derive(Clone)]
+pub struct SoundFade {
+  pub from: i32,
+  pub to: i32,
+  pub start_ms: i64,
+  pub duration_ms: i64,
+}
+
+impl SoundFade {
+  fn volume_at(&self, now_ms: i64) -> i32 {
+    if self.duration_ms <= 0 || now_ms >= self.start_ms + self.duration_ms {
+      return self.to;
+    }
+    let progress = (now_ms - self.start_ms).max(0) as f64 / self.duration_ms as f64;
+    self.from + ((self.to - self.from) as f64 * progress).round() as i32
+  }
+}
+
+/// What a save state keeps of a sound channel. Times are relative to when the state
+/// was taken, since the clock starts over when it's loaded.
+pub struct SoundChannelState {
+  pub volume: i32,
+  pub pan: i32,
+  /// The fade, with `start_ms` relative to when the state was taken.
+  pub fade: Option<SoundFade>,
+  pub current: Option<SoundPlaylistEntry>,
+  pub playlist: Vec<SoundPlaylistEntry>,
+  pub loops_remaining: i32,
+  pub is_paused: bool,
+  pub elapsed_ms: i64,
+}
+
+pub struct SoundChannel {
+  pub number: u16,
+  volume: i32,
+  pub pan: i32,
+  pub fade: Option<SoundFade>,
+  pub current: Option<SoundPlaylistEntry>,
+  /// The samples of the current sound, missing when its member has none to play.
+  sound: Option<SoundChunk>,
+  pub playlist: Vec<SoundPlaylistEntry>,
+  pub loops_remaining: i32,
+  pub is_paused: bool,
+  /// Clock time at which the current sound started, moved forward while paused.
+  start_ms: i64,
+  paused_at_ms: i64,
+}
+
+impl SoundChannel {
+  fn new(number: u16) -> SoundChannel {
+    SoundChannel {
+      number,
+      volume: MAX_SOUND_VOLUME,
+      pan: 0,
+      fade: None,
+      current: None,
+      sound: None,
+      playlist: vec![],
+      loops_remaining: 0,
+      is_paused: false,
+      start_ms: 0,
+      paused_at_ms: 0,
+    }
+  }
+
+  pub fn volume(&self, now_ms: i64) -> i32 {
+    self.fade.as_ref().map_or(self.volume, |fade| fade.volume_at(now_ms))
+  }
+
+  pub fn set_volume(&mut self, volume: i32) {
+    self.volume = volume.clamp(0, MAX_SOUND_VOLUME);
+    self.fade = None;
+    self.dispatch_changed(0);
+  }
+
+  pub fn set_pan(&mut self, pan: i32) {
+    self.pan = pan.clamp(-100, 100);
+    self.dispatch_changed(0);
+  }
+
+  /// Ramps the volume from where it is now, keeping the target as the volume once done.
+  pub fn fade_to(&mut self, from: Option<i32>, to: i32, duration_ms: i64, now_ms: i64) {
+    let to = to.clamp(0, MAX_SOUND_VOLUME);
+    let from = from.unwrap_or_else(|| self.volume(now_ms));
+    self.volume = to;
+    self.fade = Some(SoundFade { from, to, start_ms: now_ms, duration_ms });
+    if from != to {
+      JsApi::dispatch_sound_channel_changed(self.number, from, self.pan, 0);
+    }
+    self.dispatch_changed(duration_ms);
+  }
+
+  pub fn status(&self) -> i32 {
+    if self.current.is_some() {
+      if self.is_paused { SOUND_STATUS_PAUSED } else { SOUND_STATUS_PLAYING }
+    } else if !self.playlist.is_empty() {
+      SOUND_STATUS_QUEUED
+    } else {
+      SOUND_STATUS_IDLE
+    }
+  }
+
+  pub fn elapsed_ms(&self, now_ms: i64) -> i64 {
+    match self.current {
+      Some(_) if self.is_paused => self.paused_at_ms - self.start_ms,
+      Some(_) => now_ms - self.start_ms,
+      None => 0,
+    }
+  }
+
+  /// Whether a sound is playing, which stops being the case once its last loop ends.
+  /// `update` has to be called first for that to be seen.
+  pub fn is_busy(&self) -> bool {
+    self.current.is_some() && !self.is_paused
+  }
+
+  pub fn play(&mut self, entry: SoundPlaylistEntry, cast_manager: &CastManager, now_ms: i64) {
+    self.start(entry, cast_manager, now_ms);
+    self.start_output(now_ms);
+  }
+
+  fn start(&mut self, entry: SoundPlaylistEntry, cast_manager: &CastManager, start_ms: i64) {
+    self.sound = get_member_sound(cast_manager, &entry.member);
+    self.loops_remaining = (entry.loop_count - 1).max(0);
+    self.current = Some(entry);
+    self.is_paused = false;
+    self.start_ms = start_ms;
+  }
+
+  /// Plays the first queued sound, or resumes the current one when paused.
+  pub fn play_next(&mut self, cast_manager: &CastManager, now_ms: i64) {
+    if self.current.is_some() && self.is_paused {
+      self.is_paused = false;
+      self.start_ms += now_ms - self.paused_at_ms;
+      self.start_output(now_ms);
+    } else if !self.playlist.is_empty() {
+      let entry = self.playlist.remove(0);
+      self.play(entry, cast_manager, now_ms);
+    }
+  }
+
+  pub fn pause(&mut self, now_ms: i64) {
+    if self.current.is_some() && !self.is_paused {
+      self.is_paused = true;
+      self.paused_at_ms = now_ms;
+      JsApi::dispatch_sound_stop(self.number);
+    }
+  }
+
+  pub fn rewind(&mut self, now_ms: i64) {
+    self.start_ms = now_ms;
+    self.paused_at_ms = now_ms;
+    if self.is_busy() {
+      self.start_output(now_ms);
+    }
+  }
+
+  pub fn stop(&mut self) {
+    if self.current.is_some() {
+      JsApi::dispatch_sound_stop(self.number);
+    }
+    self.current = None;
+    self.sound = None;
+    self.is_paused = false;
+    self.loops_remaining = 0;
+  }
+
+  /// Ends the current sound after the loop that is playing.
+  pub fn break_loop(&mut self) {
+    self.loops_remaining = 0;
+    if self.is_busy() {
+      JsApi::dispatch_sound_break_loop(self.number);
+    }
+  }
+
+  pub fn set_loop_count(&mut self, loop_count: i32, now_ms: i64) {
+    if let Some(entry) = self.current.as_mut() {
+      entry.loop_count = loop_count;
+    }
+    self.loops_remaining = (loop_count - 1).max(0);
+    if self.is_busy() {
+      self.start_output(now_ms);
+    }
+  }
+
+  /// Moves on to the next loop or queued sound once the current one has played to its
+  /// end. A member without samples to play ends right away.
+  pub fn update(&mut self, cast_manager: &CastManager, now_ms: i64) {
+    let mut has_started = false;
+    while self.is_busy() {
+      let duration_ms = self.sound.as_ref().map_or(0, |sound| sound.duration_ms());
+      let elapsed_ms = now_ms - self.start_ms;
+      if elapsed_ms < duration_ms {
+        break;
+      }
+      if self.loops_remaining > 0 && duration_ms > 0 {
+        let loops = (elapsed_ms / duration_ms).min(self.loops_remaining as i64);
+        self.loops_remaining -= loops as i32;
+        self.start_ms += loops * duration_ms;
+      } else if !self.playlist.is_empty() {
+        // The next sound starts where this one ended, so the playlist has no gaps
+        let entry = self.playlist.remove(0);
+        self.start(entry, cast_manager, self.start_ms + duration_ms);
+        has_started = true;
+      } else {
+        self.current = None;
+        self.sound = None;
+      }
+    }
+    if has_started && self.is_busy() {
+      self.start_output(now_ms);
+    }
+  }
+
+  pub fn get_state(&self, now_ms: i64) -> SoundChannelState {
+    SoundChannelState {
+      volume: self.volume,
+      pan: self.pan,
+      fade: self.fade.as_ref().map(|fade| SoundFade { start_ms: fade.start_ms - now_ms, ..fade.clone() }),
+      current: self.current.clone(),
+      playlist: self.playlist.clone(),
+      loops_remaining: self.loops_remaining,
+      is_paused: self.is_paused,
+      elapsed_ms: self.elapsed_ms(now_ms),
+    }
+  }
+
+  /// Replaces the channel with a state from `get_state` and plays from where it was.
+  pub fn restore_state(&mut self, state: SoundChannelState, cast_manager: &CastManager, now_ms: i64) {
+    self.stop();
+    self.volume = state.volume;
+    self.pan = state.pan;
+    self.fade = state.fade.map(|fade| SoundFade { start_ms: fade.start_ms + now_ms, ..fade });
+    self.sound = state.current.as_ref().and_then(|entry| get_member_sound(cast_manager, &entry.member));
+    self.current = state.current;
+    self.playlist = state.playlist;
+    self.loops_remaining = state.loops_remaining;
+    self.is_paused = state.is_paused;
+    self.start_ms = now_ms - state.elapsed_ms;
+    self.paused_at_ms = now_ms;
+
+    JsApi::dispatch_sound_channel_changed(self.number, self.volume(now_ms), self.pan, 0);
+    if let Some(fade) = &self.fade {
+      self.dispatch_changed((fade.start_ms + fade.duration_ms - now_ms).max(0));
+    }
+    if self.is_busy() {
+      self.start_output(now_ms);
+    }
+  }
+
+  /// Plays the current sound on the host page from where it is now.
+  fn start_output(&self, now_ms: i64) {
+    match &self.sound {
+      Some(sound) => JsApi::dispatch_sound_play(self.number, sound, (now_ms - self.start_ms) as i32, self.loops_remaining + 1),
+      None => JsApi::dispatch_sound_stop(self.number),
+    }
+  }
+
+  fn dispatch_changed(&self, ramp_ms: i64) {
+    JsApi::dispatch_sound_channel_changed(self.number, self.volume, self.pan, ramp_ms as i32);
+  }
+}
+
+/// The samples a sound member plays.
+fn get_member_sound(cast_manager: &CastManager, member_ref: &CastMemberRef) -> Option<SoundChunk> {
+  match &cast_manager.find_member_by_ref(member_ref)?.member_type {
+    CastMemberType::Sound(member) => member.sound.clone(),
+    _ => None,
+  }
+}
+
+/// Sound channels as seen by Lingo. Sounds are played by the host page through the
+/// gain and panner nodes of their channel, while the end of each sound is worked out
+/// from its length, so scripts see it end without waiting on the page.
+pub struct SoundManager {
+  pub channels: Vec<SoundChannel>,
+  /// `the soundLevel`, from 0 (silent) to 7.
+  sound_level: i32,
+  /// `the soundEnabled`.
+  sound_enabled: bool,
+  /// Volume set by the host page, from 0 to 1.
+  master_volume: f32,
+  /// Mute set by the host page, independent of the soundEnabled.
+  muted: bool,
+}
+
+impl SoundManager {
+  pub fn new() -> SoundManager {
+    SoundManager {
+      channels: (1..=SOUND_CHANNEL_COUNT as u16).map(SoundChannel::new).collect(),
+      sound_level: MAX_SOUND_LEVEL,
+      sound_enabled: true,
+      master_volume: 1.0,
+      muted: false,
+    }
+  }
+
+  pub fn sound_level(&self) -> i32 {
+    self.sound_level
+  }
+
+  pub fn set_sound_level(&mut self, level: i32) {
+    self.sound_level = level.clamp(0, MAX_SOUND_LEVEL);
+    self.dispatch_master_volume();
+  }
+
+  pub fn sound_enabled(&self) -> bool {
+    self.sound_enabled
+  }
+
+  pub fn set_sound_enabled(&mut self, enabled: bool) {
+    self.sound_enabled = enabled;
+    self.dispatch_master_volume();
+  }
+
+  pub fn set_master_volume(&mut self, volume: f32) {
+    self.master_volume = volume.clamp(0.0, 1.0);
+    self.dispatch_master_volume();
+  }
+
+  pub fn set_muted(&mut self, muted: bool) {
+    self.muted = muted;
+    self.dispatch_master_volume();
+  }
+
+  /// The gain applied to all channels, combining the movie's and the host's settings.
+  pub fn master_gain(&self) -> f32 {
+    if self.muted || !self.sound_enabled {
+      0.0
+    } else {
+      self.master_volume * self.sound_level as f32 / MAX_SOUND_LEVEL as f32
+    }
+  }
+
+  fn dispatch_master_volume(&self) {
+    JsApi::dispatch_sound_master_volume_changed(self.master_gain());
+  }
+
+  pub fn update(&mut self, cast_manager: &CastManager, now_ms: i64) {
+    for channel in self.channels.iter_mut() {
+      channel.update(cast_manager, now_ms);
+    }
+  }
+
+  pub fn stop_all(&mut self) {
+    for channel in self.channels.iter_mut() {
+      channel.stop();
+      channel.playlist.clear();
+    }
+  }
+
+  pub fn get_channel(&self, number: u16) -> Option<&SoundChannel> {
+    self.channels.get((number as usize).wrapping_sub(1))
+  }
+
+  pub fn get_channel_mut(&mut self, number: u16) -> Option<&mut SoundChannel> {
+    self.channels.get_mut((number as usize).wrapping_sub(1))
+  }
+}
@@ -7,16 +7,6 @@ pub enum ColorRef {
   PaletteIndex(u8),
 }
 
-impl ColorRef {
-  pub fn from_hex(hex: &str) -> ColorRef {
-    let hex = hex.trim_start_matches('#');
-    let r = u8::from_str_radix(&hex[0..2], 16).unwrap();
-    let g = u8::from_str_radix(&hex[2..4], 16).unwrap();
-    let b = u8::from_str_radix(&hex[4..6], 16).unwrap();
-    ColorRef::Rgb(r, g, b)
-  }
-}
-
 impl ToString for ColorRef {
   fn to_string(&self) -> String {
     match self {
@@ -26,11 +16,11 @@ impl ToString for ColorRef {
   }
 }
 
-#[allow(dead_code)]
 #[derive(Clone)]
 pub enum CursorRef {
   System(i32),
-  Member(Vec<i32>),
+  /// Slot numbers of the members, with the hot spot when it isn't their reg point.
+  Member(Vec<i32>, Option<(i32, i32)>),
 }
 
 pub struct Sprite {
@@ -48,8 +38,13 @@ pub struct Sprite {
   pub blend: i32,
   pub rotation: f32,
   pub skew: f32,
+  /// The stage points the corners of the sprite are drawn at once its quad is set, from
+  /// the top left corner clockwise.
+  pub quad: Option<[(f32, f32); 4]>,
   pub flip_h: bool,
   pub flip_v: bool,
+  /// Leaves the previous renders of the sprite on the stage.
+  pub trails: bool,
   pub back_color: i32,
   pub color: ColorRef,
   pub bg_color: ColorRef,
@@ -57,6 +52,9 @@ pub struct Sprite {
   pub script_instance_list: Vec<ScriptInstanceRef>,
   pub cursor_ref: Option<CursorRef>,
   pub editable: bool,
+  pub moveable: bool,
+  /// Channel whose rect the loc of the sprite is kept in, 0 for none.
+  pub constraint: i16,
   pub entered: bool,
   pub exited: bool,
 }
@@ -78,8 +76,10 @@ impl Sprite {
       blend: 100,
       rotation: 0.0,
       skew: 0.0,
+      quad: None,
       flip_h: false,
       flip_v: false,
+      trails: false,
       back_color: 0,
       color: ColorRef::PaletteIndex(255),
       bg_color: ColorRef::PaletteIndex(0),
@@ -87,11 +87,26 @@ impl Sprite {
       script_instance_list: vec![],
       cursor_ref: None,
       editable: false,
+      moveable: false,
+      constraint: 0,
       entered: false,
       exited: false,
     }
   }
 
+  /// Moves the sprite to a loc, along with its quad.
+  pub fn move_to(&mut self, loc_h: i32, loc_v: i32) {
+    if let Some(quad) = &mut self.quad {
+      let (dx, dy) = ((loc_h - self.loc_h) as f32, (loc_v - self.loc_v) as f32);
+      for (x, y) in quad.iter_mut() {
+        *x += dx;
+        *y += dy;
+      }
+    }
+    self.loc_h = loc_h;
+    self.loc_v = loc_v;
+  }
+
   pub fn reset(&mut self) {
     self.name = "".to_owned();
     self.puppet = false;
@@ -106,8 +121,10 @@ impl Sprite {
     self.blend = 100;
     self.rotation = 0.0;
     self.skew = 0.0;
+    self.quad = None;
     self.flip_h = false;
     self.flip_v = false;
+    self.trails = false;
     self.back_color = 0;
     self.color = ColorRef::PaletteIndex(255);
     self.bg_color = ColorRef::PaletteIndex(0);
@@ -115,6 +132,8 @@ impl Sprite {
     self.script_instance_list.clear();
     self.cursor_ref = None;
     self.editable = false;
+    self.moveable = false;
+    self.constraint = 0;
     self.entered = false;
     self.exited = false;
   }
@@ -1,9 +1,22 @@
-use log::warn;
+use std::collections::HashSet;
 
-use crate::{director::lingo::datum::Datum, player::bitmap::bitmap::PaletteRef};
+use crate::{director::lingo::datum::Datum, player::bitmap::bitmap::PaletteRef, rendering::{render_stage_to_bitmap, StageTrailsMode}};
 
 use super::{bitmap::bitmap::{get_system_default_palette, Bitmap}, DatumRef, DirPlayer, ScriptError};
 
+/// Composites the stage as it is now into a new 32-bit bitmap, without debug overlays.
+/// Trails are drawn as the stage shows them, but the snapshot doesn't add to them.
+pub fn render_stage_image(player: &mut DirPlayer) -> Bitmap {
+  let mut image = Bitmap::new(
+    player.movie.rect.width() as u16,
+    player.movie.rect.height() as u16,
+    32,
+    PaletteRef::BuiltIn(get_system_default_palette()),
+  );
+  render_stage_to_bitmap(player, &mut image, None, 0, &HashSet::new(), StageTrailsMode::Show, None);
+  image
+}
+
 pub fn get_stage_prop(
   player: &mut DirPlayer,
   prop: &str,
@@ -20,14 +33,8 @@ pub fn get_stage_prop(
       Ok(Datum::ColorRef(player.bg_color.clone()))
     },
     "image" => {
-      warn!("TODO get stage image");
-      let new_bitmap = Bitmap::new(
-        player.movie.rect.width() as u16, 
-        player.movie.rect.height() as u16, 
-        32, 
-        PaletteRef::BuiltIn(get_system_default_palette()),
-      );
-      let bitmap_id = player.bitmap_manager.add_bitmap(new_bitmap);
+      let image = render_stage_image(player);
+      let bitmap_id = player.bitmap_manager.add_bitmap(image);
       Ok(Datum::BitmapRef(bitmap_id))
     },
     _ => {
@@ -0,0 +1,76 @@
+use super::{bitmap::{bitmap::Bitmap, palette_map::PaletteMap}, geometry::IntRect};
+
+/// Outlines drawn by a zoomBox, each one kept up for the delay.
+const ZOOM_BOX_STEPS: i64 = 8;
+
+/// A brightness ramp of the whole stage started by fadeIn or fadeOut, from 0 for black
+/// to 100 for the stage as it is.
+#[derive(Clone)]
+pub struct StageFade {
+  pub from: i32,
+  pub to: i32,
+  pub start_ms: i64,
+  pub duration_ms: i64,
+}
+
+impl StageFade {
+  pub fn brightness_at(&self, now_ms: i64) -> i32 {
+    if self.duration_ms <= 0 || now_ms >= self.start_ms + self.duration_ms {
+      return self.to;
+    }
+    let progress = (now_ms - self.start_ms).max(0) as f64 / self.duration_ms as f64;
+    self.from + ((self.to - self.from) as f64 * progress).round() as i32
+  }
+}
+
+/// A rectangle zooming from one sprite to another, drawn over the stage.
+#[derive(Clone)]
+pub struct ZoomBox {
+  pub from: IntRect,
+  pub to: IntRect,
+  pub start_ms: i64,
+  pub step_ms: i64,
+}
+
+impl ZoomBox {
+  pub fn is_done(&self, now_ms: i64) -> bool {
+    now_ms >= self.start_ms + self.step_ms * ZOOM_BOX_STEPS
+  }
+
+  /// The outline for the step the zoom is at, interpolated between the two rects.
+  fn rect_at(&self, now_ms: i64) -> IntRect {
+    let step = ((now_ms - self.start_ms).max(0) / self.step_ms.max(1)).min(ZOOM_BOX_STEPS - 1);
+    let lerp = |from: i32, to: i32| from + ((to - from) as i64 * (step + 1) / ZOOM_BOX_STEPS) as i32;
+    IntRect::from(
+      lerp(self.from.left, self.to.left),
+      lerp(self.from.top, self.to.top),
+      lerp(self.from.right, self.to.right),
+      lerp(self.from.bottom, self.to.bottom),
+    )
+  }
+}
+
+/// Draws the zoom box and darkens the stage for a fade, on the composited stage.
+pub fn apply_stage_effects(
+  bitmap: &mut Bitmap,
+  zoom_box: Option<&ZoomBox>,
+  fade: Option<&StageFade>,
+  now_ms: i64,
+  overscan: i32,
+  palettes: &PaletteMap,
+) {
+  if let Some(zoom_box) = zoom_box {
+    if !zoom_box.is_done(now_ms) {
+      let rect = zoom_box.rect_at(now_ms).offset(overscan, overscan);
+      bitmap.stroke_rect(rect.left, rect.top, rect.right, rect.bottom, (0, 0, 0), palettes, 1.0);
+    }
+  }
+  let brightness = fade.map_or(100, |fade| fade.brightness_at(now_ms)).clamp(0, 100) as u32;
+  if brightness < 100 && bitmap.bit_depth == 32 {
+    for pixel in bitmap.data.chunks_exact_mut(4) {
+      for channel in &mut pixel[..3] {
+        *channel = (*channel as u32 * brightness / 100) as u8;
+      }
+    }
+  }
+}
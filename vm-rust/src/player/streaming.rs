@@ -0,0 +1,87 @@
+use log::warn;
+
+use crate::{director::file::DirectorFile, js_api::JsApi};
+
+use super::{cast_lib::CastMemberRef, cast_member::CastMember, handlers::datum_handlers::cast_member_ref::CastMemberRefHandlers, reserve_player_mut, reserve_player_ref, DirPlayer, PLAYER_OPT};
+
+/// Cast libs of a movie are numbered in the order of its cast list. Movies without a
+/// cast list have a single cast.
+fn get_cast_lib_number(file: &DirectorFile, cast_id: u32) -> Option<u32> {
+  if file.cast_entries.is_empty() {
+    return file.casts.first().filter(|cast| cast.id == cast_id).map(|_| 1);
+  }
+  file.cast_entries.iter().position(|entry| entry.id == cast_id).map(|index| index as u32 + 1)
+}
+
+fn get_cast_id(file: &DirectorFile, cast_lib: u32) -> Option<u32> {
+  if file.cast_entries.is_empty() {
+    return file.casts.first().filter(|_| cast_lib == 1).map(|cast| cast.id);
+  }
+  file.cast_entries.get((cast_lib as usize).checked_sub(1)?).map(|entry| entry.id)
+}
+
+/// Whether the media of a member has been downloaded. Members of a streamed movie
+/// only exist once it has.
+pub fn is_member_media_ready(player: &DirPlayer, member_ref: &CastMemberRef) -> bool {
+  let file = match &player.movie.file {
+    Some(file) => file,
+    None => return true,
+  };
+  match get_cast_id(file, member_ref.cast_lib as u32) {
+    Some(cast_id) => !file.is_member_pending(cast_id, member_ref.cast_member as u32),
+    None => true,
+  }
+}
+
+/// Adds the members whose media arrived since the last call.
+fn add_streamed_members(player: &mut DirPlayer, task_id: u32) {
+  let file = match player.movie.file.as_mut() {
+    Some(file) => file,
+    None => return,
+  };
+  let members = player.net_manager.with_streamed_data(task_id, |data| file.read_streamed_members(data)).unwrap_or_default();
+  let mut changed_casts = vec![];
+  for (cast_id, member_id, member_def) in members {
+    let cast_number = match get_cast_lib_number(file, cast_id) {
+      Some(cast_number) => cast_number,
+      None => continue,
+    };
+    let cast_lib = player.movie.cast_manager.get_cast_mut(cast_number);
+    let member = CastMember::from(cast_number, member_id, &member_def, &cast_lib.lctx, &mut player.bitmap_manager);
+    cast_lib.insert_member(member_id, member);
+    JsApi::on_cast_member_name_changed(CastMemberRefHandlers::get_cast_slot_number(cast_number, member_id));
+    if !changed_casts.contains(&cast_number) {
+      changed_casts.push(cast_number);
+    }
+  }
+  for cast_number in changed_casts {
+    JsApi::dispatch_cast_member_list_changed(cast_number);
+  }
+}
+
+/// Adds the members of a streamed movie as their media finishes downloading, until
+/// the whole file is in.
+pub async fn player_stream_movie_media(task_id: u32) {
+  loop {
+    let is_done = reserve_player_ref(|player| player.net_manager.is_task_done(Some(task_id)));
+    let is_streaming = reserve_player_mut(|player| {
+      // Another movie is the player's movie while a window movie runs
+      if player.movie.stream_task_id != Some(task_id) {
+        return !is_done;
+      }
+      add_streamed_members(player, task_id);
+      if is_done {
+        player.movie.stream_task_id = None;
+        if player.net_manager.get_task_result(Some(task_id)).is_some_and(|result| result.is_err()) {
+          warn!("Streaming the movie failed, some of its members will stay missing");
+        }
+      }
+      !is_done
+    });
+    if !is_streaming {
+      return;
+    }
+    let player = unsafe { PLAYER_OPT.as_mut().unwrap() };
+    player.net_manager.await_task_progress(task_id).await;
+  }
+}
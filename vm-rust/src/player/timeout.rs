@@ -3,10 +3,12 @@ use std::collections::HashMap;
 
 use crate::{director::lingo::datum::TimeoutRef, js_api::JsApi};
 
-use super::DatumRef;
+use super::{clock::Clock, DatumRef};
 
 pub struct TimeoutManager {
     pub timeouts: HashMap<TimeoutRef, Timeout>,
+    /// Timeout names in creation order, which is the order of the timeoutList.
+    order: Vec<TimeoutRef>,
 }
 
 pub struct Timeout {
@@ -15,25 +17,40 @@ pub struct Timeout {
     pub handler: String,
     pub target_ref: DatumRef,
     pub is_scheduled: bool,
+    /// Persistent timeouts survive movie changes. Kept for scripts that read it back.
+    pub persistent: bool,
+    /// Clock time of the last trigger, used to find due timeouts when stepping a paused clock.
+    pub last_fired_ms: i64,
 }
 
 impl TimeoutManager {
     pub fn new() -> TimeoutManager {
         TimeoutManager {
             timeouts: HashMap::new(),
+            order: vec![],
         }
     }
 
+    /// Adds a timeout, replacing and cancelling any existing timeout with the same name.
     pub fn add_timeout(&mut self, timeout: Timeout) {
-        self.timeouts.insert(timeout.name.to_owned(), timeout);
+        let name = timeout.name.to_owned();
+        if let Some(mut existing) = self.timeouts.insert(name.to_owned(), timeout) {
+            existing.cancel();
+        } else {
+            self.order.push(name);
+        }
     }
 
-    #[allow(dead_code)]
     pub fn forget_timeout(&mut self, timeout_name: &TimeoutRef) {
         let timeout = &mut self.timeouts.remove(timeout_name);
         if let Some(timeout) = timeout {
             timeout.cancel();
         }
+        self.order.retain(|name| name != timeout_name);
+    }
+
+    pub fn timeout_names(&self) -> &Vec<TimeoutRef> {
+        &self.order
     }
 
     #[allow(dead_code)]
@@ -50,6 +67,7 @@ impl TimeoutManager {
             timeout.cancel();
         }
         self.timeouts.clear();
+        self.order.clear();
     }
 }
 
@@ -61,11 +79,33 @@ impl Timeout {
         }
     }
 
-    pub fn schedule(&mut self) {
+    /// Schedules the timeout on the host, stretching its period by the clock's time scale.
+    /// While the clock is paused nothing is scheduled and the timeout only fires when the
+    /// clock is stepped.
+    pub fn schedule(&mut self, clock: &dyn Clock) {
         self.cancel();
 
-        let timeout_name = self.name.to_owned();
-        JsApi::dispatch_schedule_timeout(&timeout_name, self.period);
+        let time_scale = clock.time_scale();
+        if time_scale > 0.0 {
+            let timeout_name = self.name.to_owned();
+            let period = (self.period as f64 / time_scale).round().max(1.0) as u32;
+            JsApi::dispatch_schedule_timeout(&timeout_name, period);
+        }
+        self.last_fired_ms = clock.elapsed_ms();
         self.is_scheduled = true;
     }
+
+    /// Changes the period of a running timeout. The next trigger is a full new
+    /// period from now, as in Director.
+    pub fn set_period(&mut self, period: u32, clock: &dyn Clock) {
+        self.period = period;
+        if self.is_scheduled {
+            self.schedule(clock);
+        }
+    }
+
+    /// The clock time in milliseconds at which the timeout fires next.
+    pub fn next_time_ms(&self) -> i64 {
+        self.last_fired_ms + self.period as i64
+    }
 }
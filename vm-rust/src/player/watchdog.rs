@@ -0,0 +1,75 @@
+use crate::utils::performance_now;
+
+/// How long scripts run before the browser gets a chance to render and handle input.
+const YIELD_INTERVAL_MS: f64 = 100.0;
+/// The clock is read on one check out of this many, since checks happen between opcodes.
+const CHECKS_PER_CLOCK_READ: u32 = 64;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchdogAction {
+  /// Stops at the current opcode as if a breakpoint was hit.
+  Pause,
+  /// Fails the handler with a script error.
+  Abort,
+}
+
+pub enum WatchdogEvent {
+  Continue,
+  Yield,
+  Expired(WatchdogAction),
+}
+
+/// Keeps handlers stuck in a loop from freezing the page. Long-running scripts yield to
+/// the browser every so often, and once they've run for `budget_ms` without returning,
+/// `action` is taken.
+pub struct ScriptWatchdog {
+  pub budget_ms: Option<u32>,
+  pub action: WatchdogAction,
+  started_at: f64,
+  last_yield_at: f64,
+  checks: u32,
+}
+
+impl ScriptWatchdog {
+  pub fn new() -> ScriptWatchdog {
+    ScriptWatchdog {
+      budget_ms: None,
+      action: WatchdogAction::Pause,
+      started_at: 0.0,
+      last_yield_at: 0.0,
+      checks: 0,
+    }
+  }
+
+  /// Starts timing a script that was called from outside of Lingo, or that resumed
+  /// after being paused.
+  pub fn start(&mut self) {
+    let now = performance_now();
+    self.started_at = now;
+    self.last_yield_at = now;
+    self.checks = 0;
+  }
+
+  /// Called between opcodes. `force` reads the clock right away, for when a long run of
+  /// opcodes went by without a check.
+  pub fn check(&mut self, force: bool) -> WatchdogEvent {
+    self.checks += 1;
+    if !force && self.checks < CHECKS_PER_CLOCK_READ {
+      return WatchdogEvent::Continue;
+    }
+    self.checks = 0;
+    let now = performance_now();
+    if self.budget_ms.is_some_and(|budget_ms| now - self.started_at >= budget_ms as f64) {
+      return WatchdogEvent::Expired(self.action);
+    }
+    if now - self.last_yield_at >= YIELD_INTERVAL_MS {
+      self.last_yield_at = now;
+      return WatchdogEvent::Yield;
+    }
+    WatchdogEvent::Continue
+  }
+
+  pub fn elapsed_ms(&self) -> f64 {
+    performance_now() - self.started_at
+  }
+}
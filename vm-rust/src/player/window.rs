@@ -0,0 +1,300 @@
+use std::future::Future;
+
+use async_recursion::async_recursion;
+
+use crate::{director::{file::read_director_file_bytes, lingo::datum::{Datum, DatumType}}, js_api::JsApi, utils::{get_base_url, get_basename_no_extension}};
+
+use super::{geometry::IntRect, property_descriptions::player_apply_property_defaults, movie::Movie, player_call_global_handler, events::{player_invoke_event_to_sprite, player_invoke_global_event}, reserve_player_mut, search_path::sync_search_paths, DatumRef, DirPlayer, ScriptError, PLAYER_OPT};
+
+/// A movie in a window (MIAW). Window movies keep their own cast and score and are
+/// swapped into `DirPlayer::movie` while their scripts run or while they're drawn.
+pub struct MovieWindow {
+  pub name: String,
+  pub title: String,
+  /// Path of the movie to open, defaults to the window name.
+  pub file_name: Option<String>,
+  /// Defaults to the rect of the movie once it's loaded.
+  pub rect: Option<IntRect>,
+  pub visible: bool,
+  pub window_type: i32,
+  pub is_open: bool,
+  /// None until the movie is loaded and while it's the active movie.
+  pub movie: Option<Movie>,
+}
+
+impl MovieWindow {
+  fn new(name: &str) -> MovieWindow {
+    MovieWindow {
+      name: name.to_owned(),
+      title: name.to_owned(),
+      file_name: None,
+      rect: None,
+      visible: true,
+      window_type: -1,
+      is_open: false,
+      movie: None,
+    }
+  }
+
+  pub fn movie_path(&self) -> String {
+    self.file_name.to_owned().unwrap_or(self.name.to_owned())
+  }
+}
+
+pub struct WindowManager {
+  pub windows: Vec<MovieWindow>,
+  /// Window whose movie is in `DirPlayer::movie`, None when it's the stage movie.
+  pub active_window: Option<String>,
+  /// The stage movie while a window movie is active.
+  stage_movie: Option<Movie>,
+  /// Pending go of the stage movie while a window movie is active.
+  stage_next_frame: Option<u32>,
+  /// Movie that was active before each tell, restored by the matching end tell.
+  tell_stack: Vec<Option<String>>,
+}
+
+impl WindowManager {
+  pub fn new() -> WindowManager {
+    WindowManager {
+      windows: vec![],
+      active_window: None,
+      stage_movie: None,
+      stage_next_frame: None,
+      tell_stack: vec![],
+    }
+  }
+
+  /// Window names are case insensitive, like other Lingo names.
+  fn find_index(&self, name: &str) -> Option<usize> {
+    self.windows.iter().position(|window| window.name.eq_ignore_ascii_case(name))
+  }
+
+  pub fn get(&self, name: &str) -> Option<&MovieWindow> {
+    self.find_index(name).map(|index| &self.windows[index])
+  }
+
+  pub fn get_mut(&mut self, name: &str) -> Option<&mut MovieWindow> {
+    self.find_index(name).map(move |index| &mut self.windows[index])
+  }
+
+  /// Referring to a window creates it, the way window "name" does in Director.
+  pub fn get_or_create(&mut self, name: &str) -> &mut MovieWindow {
+    let index = match self.find_index(name) {
+      Some(index) => index,
+      None => {
+        self.windows.push(MovieWindow::new(name));
+        self.windows.len() - 1
+      }
+    };
+    &mut self.windows[index]
+  }
+
+  pub fn window_names(&self) -> Vec<String> {
+    self.windows.iter().map(|window| window.name.to_owned()).collect()
+  }
+
+  pub fn is_active(&self, name: &str) -> bool {
+    self.active_window.as_ref().is_some_and(|active| active.eq_ignore_ascii_case(name))
+  }
+
+  fn take_movie(&mut self, window: &Option<String>) -> Option<Movie> {
+    match window {
+      Some(name) => self.get_mut(name).and_then(|window| window.movie.take()),
+      None => self.stage_movie.take(),
+    }
+  }
+
+  fn put_movie(&mut self, window: &Option<String>, movie: Movie) {
+    match window {
+      Some(name) => self.get_or_create(name).movie = Some(movie),
+      None => self.stage_movie = Some(movie),
+    }
+  }
+}
+
+/// The windowList, in the order the windows were created.
+pub fn window_list(player: &mut DirPlayer) -> DatumRef {
+  let window_refs = player.window_manager.window_names()
+    .into_iter()
+    .map(|name| player.alloc_datum(Datum::WindowRef(name)))
+    .collect();
+  player.alloc_datum(Datum::List(DatumType::List, window_refs, false))
+}
+
+/// Makes the movie of a window, or the stage movie for None, the player's movie.
+pub fn activate_window_movie(player: &mut DirPlayer, target: Option<String>) -> Result<(), ScriptError> {
+  let is_same = match (&player.window_manager.active_window, &target) {
+    (Some(active), Some(target)) => active.eq_ignore_ascii_case(target),
+    (None, None) => true,
+    _ => false,
+  };
+  if is_same {
+    return Ok(());
+  }
+  let movie = match player.window_manager.take_movie(&target) {
+    Some(movie) => movie,
+    None => return Err(ScriptError::new(format!("Window {} has no movie open", target.unwrap_or_default()))),
+  };
+  let previous = player.window_manager.active_window.take();
+  match &previous {
+    // A go is applied by player_apply_window_go before the window movie is left, so
+    // one still pending here was cut short by an error
+    Some(_) => player.next_frame = None,
+    None => player.window_manager.stage_next_frame = player.next_frame.take(),
+  }
+  let previous_movie = std::mem::replace(&mut player.movie, movie);
+  player.window_manager.put_movie(&previous, previous_movie);
+  if target.is_none() {
+    player.next_frame = player.window_manager.stage_next_frame.take();
+  }
+  player.window_manager.active_window = target;
+  Ok(())
+}
+
+/// Applies a go made by the active window movie. Window movies aren't driven by the
+/// frame loop, so it takes effect before another movie becomes the active one.
+pub async fn player_apply_window_go() -> Result<(), ScriptError> {
+  let sprite_nums = reserve_player_mut(|player| {
+    if player.window_manager.active_window.is_none() {
+      return vec![];
+    }
+    match player.next_frame.take() {
+      Some(frame) => {
+        player.movie.current_frame = frame;
+        player.movie.score.begin_sprites(frame)
+      }
+      None => vec![],
+    }
+  });
+  for sprite_num in sprite_nums {
+    player_apply_property_defaults(sprite_num as i16).await?;
+    player_invoke_event_to_sprite(&"beginSprite".to_string(), &vec![], sprite_num).await?;
+  }
+  Ok(())
+}
+
+pub fn begin_tell(player: &mut DirPlayer, target: Option<String>) -> Result<(), ScriptError> {
+  let previous = player.window_manager.active_window.to_owned();
+  activate_window_movie(player, target)?;
+  player.window_manager.tell_stack.push(previous);
+  Ok(())
+}
+
+pub fn end_tell(player: &mut DirPlayer) -> Result<(), ScriptError> {
+  match player.window_manager.tell_stack.pop() {
+    Some(previous) => activate_window_movie(player, previous),
+    None => Ok(()),
+  }
+}
+
+/// Puts the stage movie back, e.g. after a script error interrupted a tell.
+pub fn restore_stage_movie(player: &mut DirPlayer) {
+  player.window_manager.tell_stack.clear();
+  if let Err(err) = activate_window_movie(player, None) {
+    log::warn!("Failed to restore the stage movie: {}", err.message);
+  }
+}
+
+/// Drops all windows along with their movies.
+pub fn dispose_windows(player: &mut DirPlayer) {
+  restore_stage_movie(player);
+  for window in player.window_manager.windows.drain(..) {
+    if window.is_open {
+      JsApi::dispatch_window_closed(&window.name);
+    }
+  }
+}
+
+pub fn open_window(player: &mut DirPlayer, name: &str) {
+  let window = player.window_manager.get_or_create(name);
+  if window.is_open {
+    return;
+  }
+  window.is_open = true;
+  let rect = window.rect.to_owned().or(window.movie.as_ref().map(|movie| movie.rect.to_owned()));
+  let (width, height) = rect.map(|rect| (rect.width(), rect.height())).unwrap_or((0, 0));
+  JsApi::dispatch_window_opened(&window.name, &window.title, width, height);
+}
+
+/// Closing a window hides it but keeps its movie loaded until it's forgotten.
+pub fn close_window(player: &mut DirPlayer, name: &str) {
+  if let Some(window) = player.window_manager.get_mut(name) {
+    if window.is_open {
+      window.is_open = false;
+      JsApi::dispatch_window_closed(&window.name);
+    }
+  }
+}
+
+pub fn forget_window(player: &mut DirPlayer, name: &str) -> Result<(), ScriptError> {
+  if player.window_manager.is_active(name) {
+    return Err(ScriptError::new(format!("Cannot forget window {name} while its movie is running")));
+  }
+  close_window(player, name);
+  if let Some(index) = player.window_manager.find_index(name) {
+    player.window_manager.windows.remove(index);
+  }
+  Ok(())
+}
+
+/// Loads the movie of a window if it isn't loaded yet, and starts it.
+pub async fn player_load_window_movie(name: &str) -> Result<(), ScriptError> {
+  let player = unsafe { PLAYER_OPT.as_mut().unwrap() };
+  if player.window_manager.is_active(name) {
+    return Ok(());
+  }
+  let path = {
+    let window = player.window_manager.get_or_create(name);
+    if window.movie.is_some() {
+      return Ok(());
+    }
+    window.movie_path()
+  };
+  sync_search_paths(player);
+  let task_id = player.net_manager.preload_net_thing(path.to_owned());
+  player.net_manager.await_task(task_id).await;
+  let data_bytes = match player.net_manager.get_task_result(Some(task_id)) {
+    Some(Ok(data_bytes)) => data_bytes,
+    _ => return Err(ScriptError::new(format!("Failed to load movie {path} for window {name}"))),
+  };
+  let resolved_url = player.net_manager.get_task_url(task_id).unwrap();
+  let movie_file = read_director_file_bytes(
+    &data_bytes,
+    &get_basename_no_extension(resolved_url.path()),
+    get_base_url(&resolved_url).as_str(),
+    player.text_encoding_override,
+  ).map_err(|err| ScriptError::new(format!("Failed to read movie {path}: {err}")))?;
+
+  let mut movie = Movie::empty();
+  movie.load_from_file(movie_file, &mut player.net_manager, &mut player.bitmap_manager, &mut player.dir_cache).await;
+  player.window_manager.get_or_create(name).movie = Some(movie);
+
+  with_window_movie(name, async {
+    player_invoke_global_event(&"prepareMovie".to_string(), &vec![]).await?;
+    let sprite_nums = reserve_player_mut(|player| player.movie.score.begin_sprites(player.movie.current_frame));
+    for sprite_num in sprite_nums {
+      player_apply_property_defaults(sprite_num as i16).await?;
+      player_invoke_event_to_sprite(&"beginSprite".to_string(), &vec![], sprite_num).await?;
+    }
+    player_invoke_global_event(&"startMovie".to_string(), &vec![]).await
+  }).await?;
+  Ok(())
+}
+
+/// Runs a handler of a window movie, making it the active movie for the duration of the call.
+#[async_recursion(?Send)]
+pub async fn player_call_window_handler(name: &str, handler_name: &String, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+  with_window_movie(name, player_call_global_handler(handler_name, args)).await
+}
+
+async fn with_window_movie<F>(name: &str, future: F) -> Result<DatumRef, ScriptError>
+where
+  F: Future<Output = Result<DatumRef, ScriptError>>,
+{
+  player_apply_window_go().await?;
+  reserve_player_mut(|player| begin_tell(player, Some(name.to_owned())))?;
+  let result = future.await;
+  let go_result = player_apply_window_go().await;
+  reserve_player_mut(end_tell)?;
+  result.and_then(|result| go_result.map(|_| result))
+}
@@ -0,0 +1,206 @@
+use fxhash::FxHashMap;
+use log::warn;
+
+use crate::{
+    director::lingo::datum::Datum,
+    player::{alert::player_alert, reserve_player_mut, reserve_player_ref, DatumRef, DirPlayer, ScriptError},
+};
+
+/// Global handlers exposed by the BuddyAPI xtra. The browser sandbox can't
+/// touch the registry, file system or other processes, so these return
+/// defaults that make movies take their "not available" code paths.
+const BUDDY_API_HANDLERS: &[&str] = &[
+    "baversion",
+    "bamsgbox",
+    "bafindapp",
+    "bafileexists",
+    "bafolderexists",
+    "basysfolder",
+    "bawinver",
+    "bareadregstring",
+    "bareadregnumber",
+    "bawriteregstring",
+    "bawriteregnumber",
+    "bareadini",
+    "bawriteini",
+    "bashell",
+    "barunprogram",
+    "baencrypttext",
+    "badecrypttext",
+];
+
+pub struct BuddyApiXtraManager {
+    pub instance_counter: u32,
+    /// Values returned by query handlers, keyed by lowercase handler name
+    /// (e.g. `bafindapp`) or by `handler:argument` for a specific query
+    /// (e.g. `bareadregstring:software\\game\\path`).
+    pub defaults: FxHashMap<String, String>,
+}
+
+impl BuddyApiXtraManager {
+    pub fn new() -> BuddyApiXtraManager {
+        BuddyApiXtraManager {
+            instance_counter: 0,
+            defaults: FxHashMap::default(),
+        }
+    }
+
+    pub fn create_instance(&mut self, _: &Vec<DatumRef>) -> u32 {
+        self.instance_counter += 1;
+        self.instance_counter
+    }
+
+    pub fn set_default(&mut self, key: &str, value: &str) {
+        self.defaults.insert(key.to_lowercase(), value.to_string());
+    }
+
+    fn get_default(&self, handler_name: &str, arg: Option<&str>) -> Option<String> {
+        if let Some(arg) = arg {
+            let key = format!("{}:{}", handler_name, arg).to_lowercase();
+            if let Some(value) = self.defaults.get(&key) {
+                return Some(value.clone());
+            }
+        }
+        self.defaults.get(&handler_name.to_lowercase()).cloned()
+    }
+
+    pub fn has_handler(handler_name: &str) -> bool {
+        BUDDY_API_HANDLERS.contains(&handler_name.to_lowercase().as_str())
+    }
+
+    /// baMsgBox waits for its message to be dismissed, so it runs as an async handler.
+    pub fn has_async_handler(handler_name: &str) -> bool {
+        handler_name.eq_ignore_ascii_case("baMsgBox")
+    }
+
+    pub async fn call_async_handler(handler_name: &str, args: &[DatumRef]) -> Result<DatumRef, ScriptError> {
+        if !Self::has_async_handler(handler_name) {
+            return Err(ScriptError::new(format!(
+                "No async handler {} found for BuddyAPI xtra",
+                handler_name
+            )));
+        }
+        // baMsgBox(message, caption, buttons, icon, defaultButton)
+        let arg_strings = reserve_player_ref(|player| get_arg_strings(player, args));
+        let arg = |index: usize| arg_strings.get(index).cloned().unwrap_or_default();
+        let (message, caption) = (arg(0), arg(1));
+        let text = if caption.is_empty() { message } else { format!("{}\r\r{}", caption, message) };
+        // The alert only has an OK button, so the movie gets its default button back
+        player_alert(text).await?;
+        let buttons = match arg(2).to_lowercase().as_str() {
+            "okcancel" => vec!["OK", "Cancel"],
+            "yesno" => vec!["Yes", "No"],
+            "yesnocancel" => vec!["Yes", "No", "Cancel"],
+            "retrycancel" => vec!["Retry", "Cancel"],
+            "abortretryignore" => vec!["Abort", "Retry", "Ignore"],
+            _ => vec!["OK"],
+        };
+        let default_index = arg(4).parse::<usize>().unwrap_or(1).max(1) - 1;
+        let button = buttons.get(default_index).unwrap_or(&buttons[0]);
+        Ok(reserve_player_mut(|player| player.alloc_datum(Datum::String(button.to_string()))))
+    }
+
+    pub fn call_handler(handler_name: &String, args: &Vec<DatumRef>) -> Result<DatumRef, ScriptError> {
+        let handler_key = handler_name.to_lowercase();
+        reserve_player_mut(|player| {
+            let arg_strings = get_arg_strings(player, args);
+            let arg = |index: usize| arg_strings.get(index).cloned().unwrap_or_default();
+            let manager = unsafe { BUDDY_API_XTRA_MANAGER_OPT.as_mut().unwrap() };
+
+            let result = match handler_key.as_str() {
+                "baversion" => Datum::String(
+                    manager.get_default(&handler_key, None).unwrap_or("3.7".to_string()),
+                ),
+                "bafindapp" => Datum::String(
+                    manager.get_default(&handler_key, Some(&arg(0))).unwrap_or_default(),
+                ),
+                "bafileexists" | "bafolderexists" => Datum::Int(
+                    manager
+                        .get_default(&handler_key, Some(&arg(0)))
+                        .and_then(|x| x.parse().ok())
+                        .unwrap_or(0),
+                ),
+                "basysfolder" => {
+                    let folder = arg(0);
+                    let fallback = match folder.to_lowercase().as_str() {
+                        "windows" => "C:\\WINDOWS\\",
+                        "system" => "C:\\WINDOWS\\SYSTEM32\\",
+                        "temp" => "C:\\TEMP\\",
+                        "desktop" => "C:\\WINDOWS\\Desktop\\",
+                        "personal" => "C:\\My Documents\\",
+                        _ => "",
+                    };
+                    Datum::String(
+                        manager
+                            .get_default(&handler_key, Some(&folder))
+                            .unwrap_or(fallback.to_string()),
+                    )
+                }
+                "bawinver" => Datum::String(
+                    manager.get_default(&handler_key, None).unwrap_or("XP".to_string()),
+                ),
+                "bareadregstring" | "bareadini" => {
+                    // baReadRegString(key, value, default, branch)
+                    // baReadIni(section, key, default, file)
+                    let key = format!("{}\\{}", arg(0), arg(1));
+                    Datum::String(manager.get_default(&handler_key, Some(&key)).unwrap_or(arg(2)))
+                }
+                "bareadregnumber" => {
+                    let key = format!("{}\\{}", arg(0), arg(1));
+                    let value = manager.get_default(&handler_key, Some(&key)).unwrap_or(arg(2));
+                    Datum::Int(value.parse().unwrap_or(0))
+                }
+                "bawriteregstring" | "bawriteregnumber" | "bawriteini" => {
+                    // Writes are kept for the session so that subsequent reads see them.
+                    let key = format!("{}\\{}", arg(0), arg(1));
+                    let read_handler = match handler_key.as_str() {
+                        "bawriteregstring" => "bareadregstring",
+                        "bawriteregnumber" => "bareadregnumber",
+                        _ => "bareadini",
+                    };
+                    manager.set_default(&format!("{}:{}", read_handler, key), &arg(2));
+                    Datum::Int(1)
+                }
+                "bashell" | "barunprogram" => {
+                    warn!("BuddyAPI {} is not supported: {}", handler_name, arg(0));
+                    Datum::Int(0)
+                }
+                "baencrypttext" | "badecrypttext" => {
+                    // Text encrypted with a made-up cipher would never match what the
+                    // real xtra wrote, so these fail rather than return the wrong text
+                    return Err(ScriptError::new(format!(
+                        "BuddyAPI {} is not implemented",
+                        handler_name
+                    )));
+                }
+                _ => {
+                    return Err(ScriptError::new(format!(
+                        "No handler {} found for BuddyAPI xtra",
+                        handler_name
+                    )))
+                }
+            };
+            Ok(player.alloc_datum(result))
+        })
+    }
+}
+
+fn get_arg_strings(player: &DirPlayer, args: &[DatumRef]) -> Vec<String> {
+    args.iter()
+        .map(|arg| {
+            let datum = player.get_datum(arg);
+            match datum {
+                Datum::Int(i) => i.to_string(),
+                Datum::Float(f) => f.to_string(),
+                _ => datum.string_value().unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+pub fn borrow_buddy_api_manager_mut<T>(callback: impl FnOnce(&mut BuddyApiXtraManager) -> T) -> T {
+    let manager = unsafe { BUDDY_API_XTRA_MANAGER_OPT.as_mut().unwrap() };
+    callback(manager)
+}
+
+pub static mut BUDDY_API_XTRA_MANAGER_OPT: Option<BuddyApiXtraManager> = None;
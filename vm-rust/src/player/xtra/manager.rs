@@ -1,12 +1,59 @@
 use crate::{
     director::lingo::datum::XtraInstanceId,
-    player::{DatumRef, ScriptError},
+    player::{reserve_player_ref, DatumRef, ScriptError},
 };
 
-use super::multiuser::{borrow_multiuser_manager_mut, MultiuserXtraManager};
+use super::{
+    buddyapi::{borrow_buddy_api_manager_mut, BuddyApiXtraManager},
+    multiuser::{borrow_multiuser_manager_mut, MultiuserXtraManager},
+    wheelhook::{borrow_wheel_hook_manager_mut, is_wheel_hook_xtra, WheelHookXtraManager},
+};
 
 pub fn is_xtra_registered(name: &String) -> bool {
-    return name == "Multiuser";
+    return name == "Multiuser" || is_buddy_api_xtra(name) || is_wheel_hook_xtra(name) || is_stubbed_xtra(name);
+}
+
+/// Xtras the movie's compatibility profile pretends are installed, with every handler
+/// returning VOID.
+fn is_stubbed_xtra(name: &str) -> bool {
+    reserve_player_ref(|player| {
+        player.compatibility_profile.as_ref().is_some_and(|profile| profile.stubs_xtra(name))
+    })
+}
+
+fn is_buddy_api_xtra(name: &str) -> bool {
+    name.eq_ignore_ascii_case("BudAPI") || name.eq_ignore_ascii_case("BuddyAPI")
+}
+
+/// Xtras like BuddyAPI expose their handlers as global functions rather
+/// than through an instance.
+pub fn has_xtra_global_handler(handler_name: &String) -> bool {
+    BuddyApiXtraManager::has_handler(handler_name)
+}
+
+pub fn has_xtra_global_async_handler(handler_name: &str) -> bool {
+    BuddyApiXtraManager::has_async_handler(handler_name)
+}
+
+pub async fn call_xtra_global_async_handler(
+    handler_name: &str,
+    args: &[DatumRef],
+) -> Result<DatumRef, ScriptError> {
+    BuddyApiXtraManager::call_async_handler(handler_name, args).await
+}
+
+pub fn call_xtra_global_handler(
+    handler_name: &String,
+    args: &Vec<DatumRef>,
+) -> Result<DatumRef, ScriptError> {
+    if BuddyApiXtraManager::has_handler(handler_name) {
+        BuddyApiXtraManager::call_handler(handler_name, args)
+    } else {
+        Err(ScriptError::new(format!(
+            "No global xtra handler {} found",
+            handler_name
+        )))
+    }
 }
 
 pub fn call_xtra_instance_handler(
@@ -19,6 +66,11 @@ pub fn call_xtra_instance_handler(
         "Multiuser" => {
             return MultiuserXtraManager::call_instance_handler(handler_name, instance_id, args)
         }
+        _ if is_buddy_api_xtra(xtra_name) => BuddyApiXtraManager::call_handler(handler_name, args),
+        _ if is_wheel_hook_xtra(xtra_name) => {
+            WheelHookXtraManager::call_instance_handler(handler_name, instance_id, args)
+        }
+        _ if is_stubbed_xtra(xtra_name) => Ok(DatumRef::Void),
         _ => Err(ScriptError::new(format!(
             "No handler {} found for xtra {} instance #{}",
             handler_name, xtra_name, instance_id
@@ -41,6 +93,7 @@ pub async fn call_xtra_instance_async_handler(
             )
             .await
         }
+        _ if is_buddy_api_xtra(xtra_name) => BuddyApiXtraManager::call_async_handler(handler_name, args).await,
         _ => Err(ScriptError::new(format!(
             "No async handler {} found for xtra {} instance #{}",
             handler_name, xtra_name, instance_id
@@ -55,6 +108,7 @@ pub fn has_xtra_instance_async_handler(
 ) -> bool {
     match xtra_name.as_str() {
         "Multiuser" => MultiuserXtraManager::has_instance_async_handler(handler_name),
+        _ if is_buddy_api_xtra(xtra_name) => BuddyApiXtraManager::has_async_handler(handler_name),
         _ => false,
     }
 }
@@ -65,6 +119,20 @@ pub fn create_xtra_instance(
 ) -> Result<XtraInstanceId, ScriptError> {
     match xtra_name.as_str() {
         "Multiuser" => Ok(borrow_multiuser_manager_mut(|x| x.create_instance(args))),
+        _ if is_buddy_api_xtra(xtra_name) => {
+            Ok(borrow_buddy_api_manager_mut(|x| x.create_instance(args)))
+        }
+        _ if is_wheel_hook_xtra(xtra_name) => {
+            Ok(borrow_wheel_hook_manager_mut(|x| x.create_instance(args)))
+        }
+        _ if is_stubbed_xtra(xtra_name) => Ok(0),
         _ => Err(ScriptError::new(format!("Xtra {} not found", xtra_name))),
     }
 }
+
+/// Disposes the instances of every xtra when the movie is unloaded. Scripts that kept
+/// an instance in a global get an error when they call it afterwards.
+pub fn dispose_xtra_instances() {
+    borrow_multiuser_manager_mut(|x| x.dispose_instances());
+    borrow_wheel_hook_manager_mut(|x| x.dispose_instances());
+}
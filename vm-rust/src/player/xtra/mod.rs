@@ -1,2 +1,4 @@
+pub mod buddyapi;
 pub mod manager;
 pub mod multiuser;
+pub mod wheelhook;
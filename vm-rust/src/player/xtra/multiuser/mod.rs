@@ -1,10 +1,29 @@
+pub mod protocol;
+
 use async_std::{channel::Sender, task::spawn_local};
 use fxhash::FxHashMap;
 use log::warn;
 use wasm_bindgen::{closure::Closure, JsCast};
 use web_sys::{ErrorEvent, Event, MessageEvent, WebSocket};
 
-use crate::{director::lingo::datum::{Datum, DatumType}, player::{events::player_dispatch_callback_event, reserve_player_mut, reserve_player_ref, DatumRef, ScriptError}};
+use crate::{director::lingo::datum::{Datum, DatumType}, player::{events::player_dispatch_callback_event, reserve_player_mut, reserve_player_ref, DatumRef, DirPlayer, ScriptError}};
+
+use self::protocol::{decode_smus_message, encode_smus_message, take_smus_message};
+
+/// Error code reported to scripts when the server can't be reached or drops the connection.
+const MULTIUSER_ERROR_CONNECTION_FAILED: i32 = -2147216222;
+/// Error code returned by waitForNetConnection, since browsers can't accept incoming connections.
+const MULTIUSER_ERROR_NOT_SUPPORTED: i32 = -2147216217;
+
+/// Text returned by getNetErrorString for the error codes this xtra reports.
+fn net_error_string(error_code: i32) -> &'static str {
+    match error_code {
+        0 => "No error",
+        MULTIUSER_ERROR_CONNECTION_FAILED => "Could not connect to the server, or the connection was lost",
+        MULTIUSER_ERROR_NOT_SUPPORTED => "This function is not supported",
+        _ => "Unknown error",
+    }
+}
 
 
 pub struct MultiuserMessage {
@@ -16,13 +35,93 @@ pub struct MultiuserMessage {
     pub time_stamp: i64,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum MultiuserMode {
+    /// Binary Shockwave Multiuser Server protocol
+    Smus,
+    /// Raw strings, used by #text mode connections
+    Text,
+}
+
 pub struct MultiuserXtraInstance {
     pub net_message_handler: Option<(DatumRef, String)>,
     pub message_queue: Vec<MultiuserMessage>,
-    pub socket_tx: Option<Sender<String>>,
+    pub socket_tx: Option<Sender<Vec<u8>>>,
+    pub socket: Option<WebSocket>,
+    pub mode: MultiuserMode,
+    pub user_id: String,
+    pub is_connected: bool,
+    pub recv_buffer: Vec<u8>,
+    /// The most recent error reported to scripts, described by getNetErrorString.
+    pub last_error: i32,
 }
 
 impl MultiuserXtraInstance {
+    /// Queues a message on the socket. Returns false when the socket isn't open, before
+    /// the connection is made or after it was closed.
+    pub fn send_message(&self, player: &DirPlayer, message: &MultiuserMessage) -> Result<bool, ScriptError> {
+        let data = match self.mode {
+            MultiuserMode::Smus => encode_smus_message(player, message)?,
+            MultiuserMode::Text => message.content.string_value()?.into_bytes(),
+        };
+        let is_open = self.socket.as_ref().is_some_and(|socket| socket.ready_state() == WebSocket::OPEN);
+        match &self.socket_tx {
+            Some(tx) if is_open => Ok(tx.try_send(data).is_ok()),
+            _ => Ok(false),
+        }
+    }
+
+    /// Handles bytes received from the socket, dispatching every message that has fully arrived.
+    pub fn receive_data(&mut self, data: Vec<u8>) {
+        if self.mode == MultiuserMode::Text {
+            self.dispatch_message(MultiuserMessage {
+                error_code: 0,
+                recipients: vec!["*".to_string()],
+                sender_id: "System".to_string(),
+                subject: "String".to_string(),
+                content: Datum::String(String::from_utf8_lossy(&data).to_string()),
+                time_stamp: 0, // TODO timestamp
+            });
+            return;
+        }
+
+        self.recv_buffer.extend(data);
+        while let Some(message_data) = take_smus_message(&mut self.recv_buffer) {
+            match reserve_player_mut(|player| decode_smus_message(player, &message_data)) {
+                Ok(mut message) => {
+                    // The server answers the logon handshake with a Logon message,
+                    // which scripts receive as the result of connectToNetServer.
+                    if message.subject == "Logon" && !self.is_connected {
+                        self.is_connected = message.error_code == 0;
+                        self.last_error = message.error_code;
+                        message.subject = "ConnectToNetServer".to_string();
+                    }
+                    self.dispatch_message(message);
+                }
+                Err(err) => {
+                    warn!("Invalid Multiuser message: {}", err.message);
+                    self.recv_buffer.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    fn dispatch_connection_error(&mut self) {
+        let subject = if self.is_connected { "ConnectionProblem" } else { "ConnectToNetServer" };
+        self.is_connected = false;
+        self.socket_tx = None;
+        self.last_error = MULTIUSER_ERROR_CONNECTION_FAILED;
+        self.dispatch_message(MultiuserMessage {
+            error_code: MULTIUSER_ERROR_CONNECTION_FAILED,
+            recipients: vec![self.user_id.clone()],
+            sender_id: "System".to_string(),
+            subject: subject.to_string(),
+            content: Datum::Void,
+            time_stamp: 0,
+        });
+    }
+
     pub fn dispatch_message_handler(&self) {
         if let Some((handler_obj_ref, handler_symbol)) = &self.net_message_handler {
             let handler_symbol = handler_symbol.clone();
@@ -57,10 +156,41 @@ impl MultiuserXtraManager {
                 net_message_handler: None,
                 message_queue: vec![],
                 socket_tx: None,
+                socket: None,
+                mode: MultiuserMode::Smus,
+                user_id: String::new(),
+                is_connected: false,
+                recv_buffer: vec![],
+                last_error: 0,
             });
         self.instance_counter
     }
 
+    /// Looks up an instance referenced by a script. Ids are never reused, so an id that
+    /// isn't found belongs to an instance disposed when its movie was unloaded.
+    pub fn get_instance_mut(&mut self, instance_id: u32) -> Result<&mut MultiuserXtraInstance, ScriptError> {
+        let instance_counter = self.instance_counter;
+        match self.instances.get_mut(&instance_id) {
+            Some(instance) => Ok(instance),
+            None if instance_id <= instance_counter => Err(ScriptError::new(format!(
+                "Multiuser xtra instance #{} was disposed when its movie was unloaded",
+                instance_id
+            ))),
+            None => Err(ScriptError::new(format!("Multiuser xtra instance #{} not found", instance_id))),
+        }
+    }
+
+    /// Closes every connection and drops the instances along with the script
+    /// references they hold, so no callbacks reach the next movie.
+    pub fn dispose_instances(&mut self) {
+        for (_, mut instance) in self.instances.drain() {
+            instance.socket_tx = None;
+            if let Some(socket) = instance.socket.take() {
+                let _ = socket.close();
+            }
+        }
+    }
+
     pub fn has_instance_async_handler(_name: &String) -> bool {
         false
     }
@@ -85,7 +215,7 @@ impl MultiuserXtraManager {
             "setNetBufferLimits" => Ok(DatumRef::Void),
             "setNetMessageHandler" => {
                 let mut multiusr_manager = unsafe { MULTIUSER_XTRA_MANAGER_OPT.as_mut().unwrap() };
-                let instance = multiusr_manager.instances.get_mut(&instance_id).unwrap();
+                let instance = multiusr_manager.get_instance_mut(instance_id)?;
                 reserve_player_mut(|player| {
                     let handler_symbol = player.get_datum(args.get(0).unwrap());
                     let handler_obj_ref = args.get(1).unwrap().clone();
@@ -103,82 +233,172 @@ impl MultiuserXtraManager {
             }
             "connectToNetServer" => {
                 let mut multiusr_manager = unsafe { MULTIUSER_XTRA_MANAGER_OPT.as_mut().unwrap() };
-                let instance = multiusr_manager.instances.get_mut(&instance_id).unwrap();
-                if let Some((handler_obj_ref, handler_symbol)) = &instance.net_message_handler {
-                    let _handler_symbol = handler_symbol.clone();
-                    let _handler_obj_ref = handler_obj_ref.clone();
-                }
+                let instance = multiusr_manager.get_instance_mut(instance_id)?;
                 // userNameString, passwordString, serverIDString, portNumber, movieIDString {, mode, encryptionKey
-                let (host, port) = reserve_player_ref(|player| {
-                    let host = player.get_datum(args.get(2).unwrap()).string_value()?;
+                let (user_id, password, host, port, movie_id, mode) = reserve_player_ref(|player| {
+                    let arg_string = |index: usize| {
+                        args.get(index).map_or(Ok(String::new()), |x| player.get_datum(x).string_value())
+                    };
+                    let user_id = arg_string(0)?;
+                    let password = arg_string(1)?;
+                    let host = arg_string(2)?;
                     let port = player.get_datum(args.get(3).unwrap()).int_value()?;
+                    let movie_id = arg_string(4)?;
+                    let mode = match args.get(5).map(|x| player.get_datum(x)) {
+                        Some(Datum::Symbol(mode)) if mode.eq_ignore_ascii_case("text") => MultiuserMode::Text,
+                        _ => MultiuserMode::Smus,
+                    };
 
-                    Ok((host, port))
+                    Ok((user_id, password, host, port, movie_id, mode))
                 })?;
-                let ws_url = format!("ws://{}:{}", host, port);
-                let socket = WebSocket::new(&ws_url).unwrap();
+                instance.mode = mode;
+                instance.user_id = user_id.clone();
+                instance.is_connected = false;
+                instance.recv_buffer.clear();
+                instance.last_error = 0;
+                // Connecting again replaces the previous connection
+                instance.socket_tx = None;
+                if let Some(socket) = instance.socket.take() {
+                    let _ = socket.close();
+                }
+
+                let ws_url = reserve_player_ref(|player| player.net_manager.policy.websocket_url(&host, port));
+                let socket = match WebSocket::new(&ws_url) {
+                    Ok(socket) => socket,
+                    Err(err) => {
+                        // Thrown synchronously for malformed URLs or blocked mixed content
+                        warn!("Could not open WebSocket {}: {:?}", ws_url, err);
+                        instance.dispatch_connection_error();
+                        return reserve_player_mut(|player| Ok(player.alloc_datum(Datum::Int(MULTIUSER_ERROR_CONNECTION_FAILED))));
+                    }
+                };
                 socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
                 
                 let socket_clone = socket.clone();
+                let closed_socket = socket.clone();
                 let onmessage_callback = Closure::<dyn FnMut(_)>::new(move |e: MessageEvent| {
-                    // e.data().dyn_into::<js_sys::JsString>()
                     let data = e.data().dyn_into::<js_sys::ArrayBuffer>().unwrap();
                     let array = js_sys::Uint8Array::new(&data);
-                    let vec = array.to_vec();
-                    let string = String::from_utf8_lossy(&vec);
-                    warn!("WebSocket message: {:?}", string);
 
                     let mut multiusr_manager = unsafe { MULTIUSER_XTRA_MANAGER_OPT.as_mut().unwrap() };
-                    let instance = multiusr_manager.instances.get_mut(&instance_id).unwrap();
-                    instance.dispatch_message(MultiuserMessage {
-                        error_code: 0,
-                        recipients: vec!["*".to_string()],
-                        sender_id: "System".to_string(),
-                        subject: "String".to_string(),
-                        content: Datum::String(string.to_string()),
-                        time_stamp: 0, // TODO timestamp
-                    });
+                    let instance = match multiusr_manager.instances.get_mut(&instance_id) {
+                        Some(instance) => instance,
+                        None => return, // Disposed when the movie was unloaded
+                    };
+                    instance.receive_data(array.to_vec());
                 });
                 let onerror_callback = Closure::<dyn FnMut(_)>::new(move |e: ErrorEvent| {
-                    // e.data().dyn_into::<js_sys::JsString>()
                     warn!("WebSocket error: {:?}", e);
                 });
-                let onopen_callback = Closure::<dyn FnMut(_)>::new(move |e: Event| {
+                let onclose_callback = Closure::<dyn FnMut(_)>::new(move |_: Event| {
+                    warn!("WebSocket closed");
+                    let multiusr_manager = unsafe { MULTIUSER_XTRA_MANAGER_OPT.as_mut().unwrap() };
+                    let instance = match multiusr_manager.instances.get_mut(&instance_id) {
+                        Some(instance) => instance,
+                        None => return,
+                    };
+                    // Sockets the player closed itself were already replaced on the instance
+                    if instance.socket.as_ref() == Some(&closed_socket) {
+                        instance.socket = None;
+                        instance.dispatch_connection_error();
+                    }
+                });
+                let onopen_callback = Closure::<dyn FnMut(_)>::new(move |_: Event| {
                     warn!("WebSocket opened");
                     let mut multiusr_manager = unsafe { MULTIUSER_XTRA_MANAGER_OPT.as_mut().unwrap() };
-                    let instance = multiusr_manager.instances.get_mut(&instance_id).unwrap();
-                    instance.dispatch_message(MultiuserMessage {
-                        error_code: 0,
-                        recipients: vec!["*".to_string()],
-                        sender_id: "System".to_string(),
-                        subject: "ConnectToNetServer".to_string(),
-                        content: Datum::Void,
-                        time_stamp: 0, // TODO timestamp
-                    });
+                    let instance = match multiusr_manager.instances.get_mut(&instance_id) {
+                        Some(instance) => instance,
+                        None => return,
+                    };
+                    match instance.mode {
+                        MultiuserMode::Text => {
+                            instance.is_connected = true;
+                            instance.dispatch_message(MultiuserMessage {
+                                error_code: 0,
+                                recipients: vec!["*".to_string()],
+                                sender_id: "System".to_string(),
+                                subject: "ConnectToNetServer".to_string(),
+                                content: Datum::Void,
+                                time_stamp: 0, // TODO timestamp
+                            });
+                        }
+                        MultiuserMode::Smus => {
+                            // TODO: encrypt the logon content when the movie passes an encryption key
+                            let result = reserve_player_mut(|player| {
+                                let logon_props = [("movieID", &movie_id), ("userID", &user_id), ("password", &password)]
+                                    .iter()
+                                    .map(|(key, value)| {
+                                        (
+                                            player.alloc_datum(Datum::Symbol(key.to_string())),
+                                            player.alloc_datum(Datum::String(value.to_string())),
+                                        )
+                                    })
+                                    .collect();
+                                instance.send_message(player, &MultiuserMessage {
+                                    error_code: 0,
+                                    recipients: vec!["System".to_string()],
+                                    sender_id: user_id.clone(),
+                                    subject: "Logon".to_string(),
+                                    content: Datum::PropList(logon_props, false),
+                                    time_stamp: 0,
+                                })
+                            });
+                            match result {
+                                Ok(true) => {}
+                                Ok(false) => warn!("Multiuser logon failed: the connection closed"),
+                                Err(err) => warn!("Multiuser logon failed: {}", err.message),
+                            }
+                        }
+                    }
                 });
                 socket.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
                 socket.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
+                socket.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
                 socket.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
 
-                let (tx, rx) = async_std::channel::unbounded();
+                let (tx, rx) = async_std::channel::unbounded::<Vec<u8>>();
                 instance.socket_tx = Some(tx);
+                instance.socket = Some(socket.clone());
                 spawn_local(async move {
                     while let Ok(message) = rx.recv().await {
-                        warn!("Sending message: {:?}", message);
-                        socket_clone.send_with_u8_array(&message.as_bytes()).unwrap();
+                        if let Err(err) = socket_clone.send_with_u8_array(&message) {
+                            warn!("Could not send Multiuser message: {:?}", err);
+                        }
                     }
                 });
 
                 // Forget the callback to keep it alive
                 onmessage_callback.forget();
                 onerror_callback.forget();
+                onclose_callback.forget();
                 onopen_callback.forget();
 
-                Ok(DatumRef::Void)
+                reserve_player_mut(|player| Ok(player.alloc_datum(Datum::Int(0))))
+            },
+            "waitForNetConnection" => {
+                warn!("waitForNetConnection is not supported, browsers can't accept incoming connections");
+                reserve_player_mut(|player| Ok(player.alloc_datum(Datum::Int(MULTIUSER_ERROR_NOT_SUPPORTED))))
+            },
+            "getNetErrorString" => {
+                let last_error = borrow_multiuser_manager_mut(|manager| manager.get_instance_mut(instance_id).map(|x| x.last_error))?;
+                reserve_player_mut(|player| {
+                    // Without an argument, describes the last error on this connection
+                    let error_code = match args.first() {
+                        Some(error_code) => player.get_datum(error_code).int_value()?,
+                        None => last_error,
+                    };
+                    Ok(player.alloc_datum(Datum::String(net_error_string(error_code).to_string())))
+                })
+            },
+            "getNumberWaitingNetMessages" => {
+                let multiusr_manager = unsafe { MULTIUSER_XTRA_MANAGER_OPT.as_mut().unwrap() };
+                let instance = multiusr_manager.get_instance_mut(instance_id)?;
+                let count = instance.message_queue.len() as i32;
+                reserve_player_mut(|player| Ok(player.alloc_datum(Datum::Int(count))))
             },
             "getNetMessage" => {
                 let mut multiusr_manager = unsafe { MULTIUSER_XTRA_MANAGER_OPT.as_mut().unwrap() };
-                let instance = multiusr_manager.instances.get_mut(&instance_id).unwrap();
+                let instance = multiusr_manager.get_instance_mut(instance_id)?;
                 if let Some(message) = instance.next_message() {
                     reserve_player_mut(|player| {
                         let recipient_refs = message.recipients.iter().map(|recipient| {
@@ -214,15 +434,47 @@ impl MultiuserXtraManager {
             }
             "sendNetMessage" => {
                 let mut multiusr_manager = unsafe { MULTIUSER_XTRA_MANAGER_OPT.as_mut().unwrap() };
-                let instance = multiusr_manager.instances.get_mut(&instance_id).unwrap();
-                reserve_player_ref(|player| {
-                    let msg_string = player.get_datum(args.get(2).unwrap()).string_value()?;
-                    warn!("sendNetMessage: {:?}", msg_string);
-                    if let Some(tx) = &instance.socket_tx {
-                        tx.try_send(msg_string).unwrap();
-                        Ok(DatumRef::Void)
+                let instance = multiusr_manager.get_instance_mut(instance_id)?;
+                reserve_player_mut(|player| {
+                    // sendNetMessage(recipients, subject, content) or
+                    // sendNetMessage([#recipients: ..., #subject: ..., #content: ...])
+                    let (recipients_ref, subject_ref, content_ref) = match player.get_datum(&args[0]) {
+                        Datum::PropList(pairs, _) => {
+                            let find_prop = |name: &str| {
+                                pairs.iter()
+                                    .find(|(key, _)| player.get_datum(key).string_value().is_ok_and(|key| key.eq_ignore_ascii_case(name)))
+                                    .map(|(_, value)| value.clone())
+                            };
+                            (find_prop("recipients"), find_prop("subject"), find_prop("content"))
+                        }
+                        _ => (args.get(0).cloned(), args.get(1).cloned(), args.get(2).cloned()),
+                    };
+                    let recipients = match recipients_ref.as_ref().map(|x| player.get_datum(x)) {
+                        Some(Datum::List(_, items, _)) => items
+                            .iter()
+                            .map(|x| player.get_datum(x).string_value())
+                            .collect::<Result<Vec<_>, _>>()?,
+                        Some(recipient) if !recipient.is_void() => vec![recipient.string_value()?],
+                        _ => vec![],
+                    };
+                    let subject = match subject_ref.as_ref().map(|x| player.get_datum(x)) {
+                        Some(subject) if !subject.is_void() => subject.string_value()?,
+                        _ => String::new(),
+                    };
+                    let content = content_ref.map(|x| player.get_datum(&x).clone()).unwrap_or(Datum::Void);
+                    let is_sent = instance.send_message(player, &MultiuserMessage {
+                        error_code: 0,
+                        recipients,
+                        sender_id: instance.user_id.clone(),
+                        subject,
+                        content,
+                        time_stamp: 0,
+                    })?;
+                    if is_sent {
+                        Ok(player.alloc_datum(Datum::Int(0)))
                     } else {
-                        Err(ScriptError::new("Socket not connected".to_string()))
+                        warn!("sendNetMessage called while the Multiuser connection isn't open");
+                        Ok(player.alloc_datum(Datum::Int(MULTIUSER_ERROR_CONNECTION_FAILED)))
                     }
                 })
             },
@@ -252,3 +504,24 @@ pub fn borrow_multiuser_manager_mut<T>(callback: impl FnOnce(&mut MultiuserXtraM
 // }
 
 pub static mut MULTIUSER_XTRA_MANAGER_OPT: Option<MultiuserXtraManager> = None;
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn disposed_instances_raise_a_script_error() {
+        let mut manager = MultiuserXtraManager::new();
+        let instance_id = manager.create_instance(&vec![]);
+        assert!(manager.get_instance_mut(instance_id).is_ok());
+        manager.dispose_instances();
+        let error = manager.get_instance_mut(instance_id).err().unwrap();
+        assert_eq!(error.message, format!("Multiuser xtra instance #{} was disposed when its movie was unloaded", instance_id));
+        // Ids aren't reused, so instances created afterwards don't answer for the old one
+        let next_id = manager.create_instance(&vec![]);
+        assert_ne!(next_id, instance_id);
+        assert!(manager.get_instance_mut(instance_id).is_err());
+    }
+}
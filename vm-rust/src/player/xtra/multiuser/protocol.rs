@@ -0,0 +1,82 @@
+use binary_reader::{BinaryReader, Endian};
+
+use crate::player::{
+    datum_serialization::{read_serialized_datum, read_serialized_string, write_serialized_datum, write_serialized_string},
+    DirPlayer, ScriptError,
+};
+
+use super::MultiuserMessage;
+
+// Shockwave Multiuser Server messages are big-endian and laid out as:
+//   magic (2 bytes) | length (4) | error code (4) | time stamp (4) |
+//   subject (string) | sender ID (string) | recipients (string list) | content (value)
+// where `length` counts every byte after the length field itself.
+const SMUS_MESSAGE_MAGIC: [u8; 2] = [0x72, 0x00];
+const SMUS_HEADER_SIZE: usize = 6;
+
+fn write_i32(buf: &mut Vec<u8>, value: i32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn encode_smus_message(player: &DirPlayer, message: &MultiuserMessage) -> Result<Vec<u8>, ScriptError> {
+    let mut body = vec![];
+    write_i32(&mut body, message.error_code);
+    write_i32(&mut body, message.time_stamp as i32);
+    write_serialized_string(&mut body, &message.subject);
+    write_serialized_string(&mut body, &message.sender_id);
+    write_i32(&mut body, message.recipients.len() as i32);
+    for recipient in &message.recipients {
+        write_serialized_string(&mut body, recipient);
+    }
+    write_serialized_datum(player, &message.content, &mut body)?;
+
+    let mut result = Vec::with_capacity(SMUS_HEADER_SIZE + body.len());
+    result.extend_from_slice(&SMUS_MESSAGE_MAGIC);
+    write_i32(&mut result, body.len() as i32);
+    result.extend_from_slice(&body);
+    Ok(result)
+}
+
+pub fn decode_smus_message(player: &mut DirPlayer, data: &[u8]) -> Result<MultiuserMessage, ScriptError> {
+    if data.len() < SMUS_HEADER_SIZE || data[0..2] != SMUS_MESSAGE_MAGIC {
+        return Err(ScriptError::new("Invalid Multiuser message header".to_string()));
+    }
+    let mut reader = BinaryReader::from_u8(&data[SMUS_HEADER_SIZE..]);
+    reader.set_endian(Endian::Big);
+
+    let truncated = |_| ScriptError::new("Truncated Multiuser message header".to_string());
+    let error_code = reader.read_i32().map_err(truncated)?;
+    let time_stamp = reader.read_i32().map_err(truncated)?;
+    let subject = read_serialized_string(&mut reader)?;
+    let sender_id = read_serialized_string(&mut reader)?;
+    let recipient_count = reader.read_i32().map_err(truncated)?;
+    let mut recipients = vec![];
+    for _ in 0..recipient_count {
+        recipients.push(read_serialized_string(&mut reader)?);
+    }
+    let content = read_serialized_datum(player, &mut reader)?;
+
+    Ok(MultiuserMessage {
+        error_code,
+        recipients,
+        sender_id,
+        subject,
+        content,
+        time_stamp: time_stamp as i64,
+    })
+}
+
+/// Removes the first complete message from `buffer`, if one has fully arrived.
+/// Socket frames don't necessarily line up with message boundaries.
+pub fn take_smus_message(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    if buffer.len() < SMUS_HEADER_SIZE {
+        return None;
+    }
+    let length = i32::from_be_bytes([buffer[2], buffer[3], buffer[4], buffer[5]]).max(0) as usize;
+    let total_length = SMUS_HEADER_SIZE + length;
+    if buffer.len() < total_length {
+        return None;
+    }
+    let rest = buffer.split_off(total_length);
+    Some(std::mem::replace(buffer, rest))
+}
@@ -0,0 +1,112 @@
+use std::cell::RefCell;
+
+use fxhash::FxHashMap;
+
+use crate::{
+    director::lingo::datum::Datum,
+    player::{events::player_dispatch_callback_event, reserve_player_mut, DatumRef, ScriptError},
+};
+
+pub struct WheelHookXtraInstance {
+    /// Object and handler called with the wheel steps whenever the wheel turns.
+    pub wheel_handler: Option<(DatumRef, String)>,
+    pub is_enabled: bool,
+    /// Steps of the most recent wheel turn, returned by getWheelDelta.
+    pub last_delta: i32,
+}
+
+/// Scroll wheel hook xtras like EMWheelHook, which games use to scroll lists. Each
+/// instance calls back a handler on a script object for every turn of the wheel.
+pub struct WheelHookXtraManager {
+    pub instances: FxHashMap<u32, WheelHookXtraInstance>,
+    pub instance_counter: u32,
+}
+
+impl WheelHookXtraManager {
+    pub fn new() -> WheelHookXtraManager {
+        WheelHookXtraManager {
+            instances: FxHashMap::default(),
+            instance_counter: 0,
+        }
+    }
+
+    pub fn create_instance(&mut self, _: &[DatumRef]) -> u32 {
+        self.instance_counter += 1;
+        self.instances.insert(self.instance_counter, WheelHookXtraInstance {
+            wheel_handler: None,
+            is_enabled: true,
+            last_delta: 0,
+        });
+        self.instance_counter
+    }
+
+    fn get_instance_mut(&mut self, instance_id: u32) -> Result<&mut WheelHookXtraInstance, ScriptError> {
+        self.instances
+            .get_mut(&instance_id)
+            .ok_or_else(|| ScriptError::new(format!("Wheel hook xtra instance #{} not found", instance_id)))
+    }
+
+    pub fn dispose_instances(&mut self) {
+        self.instances.clear();
+    }
+
+    /// Calls the handler of every enabled instance with the steps the wheel turned.
+    pub fn dispatch_wheel(&mut self, steps: i32) {
+        for instance in self.instances.values_mut().filter(|x| x.is_enabled) {
+            instance.last_delta = steps;
+            if let Some((handler_obj_ref, handler_symbol)) = &instance.wheel_handler {
+                let args = vec![reserve_player_mut(|player| player.alloc_datum(Datum::Int(steps)))];
+                player_dispatch_callback_event(handler_obj_ref.clone(), handler_symbol, &args);
+            }
+        }
+    }
+
+    pub fn call_instance_handler(
+        handler_name: &String,
+        instance_id: u32,
+        args: &[DatumRef],
+    ) -> Result<DatumRef, ScriptError> {
+        borrow_wheel_hook_manager_mut(|manager| {
+            let instance = manager.get_instance_mut(instance_id)?;
+            reserve_player_mut(|player| match handler_name.to_lowercase().as_str() {
+                // setCallback(#handler, object), like setNetMessageHandler of the Multiuser xtra
+                "setcallback" | "sethook" => {
+                    let handler_symbol = args.first().map(|x| player.get_datum(x));
+                    instance.wheel_handler = match handler_symbol {
+                        Some(handler_symbol) if !handler_symbol.is_void() => {
+                            let handler_obj_ref = args.get(1).cloned().unwrap_or(DatumRef::Void);
+                            Some((handler_obj_ref, handler_symbol.symbol_value()?))
+                        }
+                        _ => None,
+                    };
+                    Ok(player.alloc_datum(Datum::Int(0)))
+                }
+                "enable" => {
+                    instance.is_enabled = true;
+                    Ok(player.alloc_datum(Datum::Int(0)))
+                }
+                "disable" => {
+                    instance.is_enabled = false;
+                    Ok(player.alloc_datum(Datum::Int(0)))
+                }
+                "getwheeldelta" => Ok(player.alloc_datum(Datum::Int(std::mem::take(&mut instance.last_delta)))),
+                _ => Err(ScriptError::new(format!(
+                    "No handler {} found for wheel hook xtra instance #{}",
+                    handler_name, instance_id
+                ))),
+            })
+        })
+    }
+}
+
+pub fn is_wheel_hook_xtra(name: &str) -> bool {
+    name.eq_ignore_ascii_case("EMWheelHook") || name.eq_ignore_ascii_case("WheelHook")
+}
+
+pub fn borrow_wheel_hook_manager_mut<T>(callback: impl FnOnce(&mut WheelHookXtraManager) -> T) -> T {
+    WHEEL_HOOK_XTRA_MANAGER.with(|manager| callback(&mut manager.borrow_mut()))
+}
+
+thread_local! {
+    static WHEEL_HOOK_XTRA_MANAGER: RefCell<WheelHookXtraManager> = RefCell::new(WheelHookXtraManager::new());
+}
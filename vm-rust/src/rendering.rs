@@ -1,136 +1,868 @@
-use std::{borrow::{Borrow, BorrowMut}, cell::RefCell, collections::HashMap, rc::Rc};
+use std::{borrow::BorrowMut, cell::RefCell, collections::{HashMap, HashSet}, rc::Rc};
 
 use async_std::task::spawn_local;
 use chrono::Local;
+use itertools::Itertools;
 use wasm_bindgen::{prelude::*, Clamped};
 
 use crate::{js_api::JsApi, player::{
-    bitmap::{bitmap::{get_system_default_palette, resolve_color_ref, Bitmap, PaletteRef}, drawing::{should_matte_sprite, CopyPixelsParams}, mask::BitmapMask, palette_map::PaletteMap}, cast_lib::CastMemberRef, cast_member::CastMemberType, geometry::IntRect, score::{get_concrete_sprite_rect, get_sprite_at}, sprite::CursorRef, DirPlayer, PLAYER_OPT
+    alert::get_alert_layout, bitmap::{bitmap::{get_system_default_palette, resolve_color_ref, Bitmap, PaletteRef}, manager::{BitmapRef, INVALID_BITMAP_REF}, drawing::{should_matte_sprite, CopyPixelsParams}, mask::BitmapMask, palette_map::PaletteMap}, cast_lib::CastMemberRef, cast_member::{CastMemberType, FieldMember}, font::{layout::{TextLayout, SCROLLBAR_WIDTH}, truetype::{draw_truetype_text, get_line_height, TrueTypeTextParams}, BitmapTextParams, FontStyle}, geometry::{IntRect, IntRectTuple}, stage_effects::apply_stage_effects, cursor::{get_css_cursor, get_cursor_image, CursorImage}, score::{get_channel_number_from_index, get_concrete_sprite_rect, get_sprite_at, get_sprite_bounds, get_sprite_transform, SpriteOutline}, sprite::{ColorRef, Sprite}, window::{activate_window_movie, restore_stage_movie}, DirPlayer, PLAYER_OPT
 }};
 
 pub struct PlayerCanvasRenderer {
     pub container_element: Option<web_sys::HtmlElement>,
     pub preview_container_element: Option<web_sys::HtmlElement>,
-    pub canvas: web_sys::HtmlCanvasElement,
-    pub ctx2d: web_sys::CanvasRenderingContext2d,
-    pub preview_canvas: web_sys::HtmlCanvasElement,
-    pub preview_ctx2d: web_sys::CanvasRenderingContext2d,
+    pub canvas: StageCanvas,
+    /// Not available when the player runs in a worker.
+    pub preview_canvas: Option<web_sys::HtmlCanvasElement>,
+    pub preview_ctx2d: Option<web_sys::CanvasRenderingContext2d>,
     pub size: (u32, u32),
     pub preview_size: (u32, u32),
     pub preview_member_ref: Option<CastMemberRef>,
     pub debug_selected_channel_num: Option<i16>,
+    pub debug_overscan: i32,
+    pub debug_muted_channels: HashSet<i16>,
+    pub debug_solo_channel_num: Option<i16>,
+    /// Set when debug options change so the stage is redrawn even while paused.
+    pub needs_redraw: bool,
     pub bitmap: Bitmap,
+    pub static_layer: Option<StaticLayerCache>,
+    /// Canvases the host page provided for movies in a window, by window name.
+    pub window_canvases: HashMap<String, WindowCanvas>,
+    pub scale_mode: StageScaleMode,
+    /// Device pixels per CSS pixel. The canvas is backed at this resolution so the
+    /// stage stays crisp on high-DPI displays.
+    pub device_pixel_ratio: f64,
+    /// The movie is drawn here at its own size, then scaled onto the stage canvas.
+    pub movie_canvas: Option<(web_sys::OffscreenCanvas, web_sys::OffscreenCanvasRenderingContext2d)>,
+    /// Tables that reduce the stage to a palette for 8-bit stage emulation, by the
+    /// sorted colors of the palette.
+    pub stage_palette_tables: HashMap<Vec<(u8, u8, u8)>, StagePaletteTable>,
+    /// The member cursor last given to the page, to only encode it again when it changes.
+    pub cursor_image: Option<CursorImage>,
 }
 
-pub fn render_stage_to_bitmap(player: &mut DirPlayer, bitmap: &mut Bitmap, debug_sprite_num: Option<i16>) {
-    let palettes = player.movie.cast_manager.palettes();
+/// How the movie is fitted into the stage canvas.
+#[derive(Clone, Copy, PartialEq)]
+pub enum StageScaleMode {
+    /// At its own size, centered.
+    None,
+    /// As large as it fits while keeping its aspect ratio, letterboxed.
+    Fit,
+    /// Like Fit, but only by whole multiples of device pixels so every pixel has the same size.
+    Integer,
+    /// Fills the whole canvas.
+    Stretch,
+}
+
+impl StageScaleMode {
+    pub fn from_name(name: &str) -> Option<StageScaleMode> {
+        match name {
+            "none" => Some(StageScaleMode::None),
+            "fit" => Some(StageScaleMode::Fit),
+            "integer" => Some(StageScaleMode::Integer),
+            "stretch" => Some(StageScaleMode::Stretch),
+            _ => None,
+        }
+    }
+}
+
+pub struct WindowCanvas {
+    pub canvas: StageCanvas,
+    pub bitmap: Bitmap,
+}
+
+/// The stage is drawn to a canvas element on the page or, when the player runs in a
+/// worker, to an OffscreenCanvas transferred from the page.
+pub enum StageCanvas {
+    Element(web_sys::HtmlCanvasElement, web_sys::CanvasRenderingContext2d),
+    Offscreen(web_sys::OffscreenCanvas, web_sys::OffscreenCanvasRenderingContext2d),
+}
+
+impl StageCanvas {
+    /// Resizing a canvas resets its context, so smoothing is turned off again.
+    pub fn set_size(&self, width: u32, height: u32) {
+        match self {
+            StageCanvas::Element(canvas, ctx) => {
+                canvas.set_width(width);
+                canvas.set_height(height);
+                ctx.set_image_smoothing_enabled(false);
+            }
+            StageCanvas::Offscreen(canvas, ctx) => {
+                canvas.set_width(width);
+                canvas.set_height(height);
+                ctx.set_image_smoothing_enabled(false);
+            }
+        }
+    }
+
+    pub fn draw_canvas(&self, image: &web_sys::OffscreenCanvas, rect: (f64, f64, f64, f64)) {
+        let (left, top, width, height) = rect;
+        let _ = match self {
+            StageCanvas::Element(_, ctx) => ctx.draw_image_with_offscreen_canvas_and_dw_and_dh(image, left, top, width, height),
+            StageCanvas::Offscreen(_, ctx) => ctx.draw_image_with_offscreen_canvas_and_dw_and_dh(image, left, top, width, height),
+        };
+    }
+
+    pub fn clear_rect(&self, width: u32, height: u32) {
+        match self {
+            StageCanvas::Element(_, ctx) => ctx.clear_rect(0.0, 0.0, width as f64, height as f64),
+            StageCanvas::Offscreen(_, ctx) => ctx.clear_rect(0.0, 0.0, width as f64, height as f64),
+        }
+    }
+
+    pub fn put_image_data(&self, image_data: &web_sys::ImageData) {
+        match self {
+            StageCanvas::Element(_, ctx) => ctx.put_image_data(image_data, 0.0, 0.0).unwrap(),
+            StageCanvas::Offscreen(_, ctx) => ctx.put_image_data(image_data, 0.0, 0.0).unwrap(),
+        }
+    }
+}
+
+const OVERSCAN_COLOR: (u8, u8, u8) = (64, 64, 64);
+
+/// Everything that affects how a sprite is composited onto the stage.
+#[derive(Clone, PartialEq)]
+pub struct SpriteRenderKey {
+    pub sprite_num: usize,
+    pub member_ref: CastMemberRef,
+    pub image_ref: BitmapRef,
+    pub image_version: u32,
+    pub rect: IntRectTuple,
+    pub ink: i32,
+    pub blend: i32,
+    pub color: ColorRef,
+    pub bg_color: ColorRef,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub rotation: f32,
+    pub skew: f32,
+    pub quad: Option<[(f32, f32); 4]>,
+}
+
+/// The stage background plus the bottom-most run of sprites that didn't change
+/// since the previous frame, composited once and reused until one of their
+/// inputs changes.
+pub struct StaticLayerCache {
+    pub bitmap: Bitmap,
+    pub bg_color: ColorRef,
+    pub overscan: i32,
+    pub palettes: Rc<PaletteMap>,
+    pub stage_palette: Option<PaletteRef>,
+    pub smooth_scaling: bool,
+    pub sprite_keys: Vec<SpriteRenderKey>,
+    pub last_frame_keys: Vec<Option<SpriteRenderKey>>,
+}
+
+/// Most palettes the stage keeps reduction tables for. Movies rarely use more, and
+/// the tables are dropped all at once when they do.
+const MAX_STAGE_PALETTE_TABLES: usize = 16;
+
+/// The nearest palette color for every 15-bit color, which reduces the stage to the
+/// 256 colors an 8-bit monitor could show.
+pub struct StagePaletteTable {
+    nearest: Vec<(u8, u8, u8)>,
+}
+
+impl StagePaletteTable {
+    fn new(colors: &[(u8, u8, u8)]) -> StagePaletteTable {
+        let nearest = (0..0x8000u32)
+            .map(|key| {
+                let channel = |shift: u32| (((key >> shift) & 0x1F) << 3 | 4) as i32;
+                let (r, g, b) = (channel(10), channel(5), channel(0));
+                colors
+                    .iter()
+                    .copied()
+                    .min_by_key(|color| {
+                        let (dr, dg, db) = (color.0 as i32 - r, color.1 as i32 - g, color.2 as i32 - b);
+                        dr * dr + dg * dg + db * db
+                    })
+                    .unwrap_or((0, 0, 0))
+            })
+            .collect();
+        StagePaletteTable { nearest }
+    }
+
+    /// Maps each pixel to the nearest palette color.
+    fn reduce(&self, bitmap: &mut Bitmap) {
+        for pixel in bitmap.data.chunks_exact_mut(4) {
+            let key = (pixel[0] as usize >> 3) << 10 | (pixel[1] as usize >> 3) << 5 | pixel[2] as usize >> 3;
+            let (r, g, b) = self.nearest[key];
+            pixel[0] = r;
+            pixel[1] = g;
+            pixel[2] = b;
+        }
+    }
+}
+
+/// How a stage render treats what sprites with trails left on the stage.
+#[derive(Clone, Copy, PartialEq)]
+pub enum StageTrailsMode {
+    Ignore,
+    /// Draws the trails without adding to them, for snapshots of the stage.
+    Show,
+    /// Draws the trails and adds the sprites with trails to them, once per frame drawn.
+    Record,
+}
+
+/// What sprites with trails left on the stage, in stage coordinates. Only the pixels
+/// set in `mask` were drawn to.
+pub struct StageTrails {
+    pub bitmap: Bitmap,
+    pub mask: BitmapMask,
+}
+
+impl StageTrails {
+    fn new(width: u16, height: u16) -> StageTrails {
+        StageTrails {
+            bitmap: Bitmap::new(width, height, 32, PaletteRef::BuiltIn(get_system_default_palette())),
+            mask: BitmapMask::new(width, height, false),
+        }
+    }
+
+    /// Keeps the pixels of `stage` covered by the sprite as it was just drawn.
+    fn record_sprite(&mut self, player: &DirPlayer, sprite: &Sprite, stage: &Bitmap, palettes: &PaletteMap, overscan: i32) {
+        let bounds = get_sprite_bounds(player, sprite)
+            .intersect(&IntRect::from(0, 0, self.bitmap.width as i32, self.bitmap.height as i32));
+        let outline = SpriteOutline::new(player, sprite);
+        for y in bounds.top..bounds.bottom {
+            for x in bounds.left..bounds.right {
+                if outline.contains(x, y) {
+                    let color = stage.get_pixel_color(palettes, (x + overscan) as u16, (y + overscan) as u16);
+                    self.bitmap.set_pixel(x, y, color, palettes);
+                    self.mask.set_bit(x as u16, y as u16, true);
+                }
+            }
+        }
+    }
+}
+
+fn get_sprite_render_key(player: &DirPlayer, sprite: &Sprite, sprite_rect: &IntRect) -> Option<SpriteRenderKey> {
+    if sprite.trails {
+        return None;
+    }
+    let member_ref = sprite.member.as_ref()?;
+    let member = player.movie.cast_manager.find_member_by_ref(member_ref)?;
+    let (image_ref, image_version) = match &member.member_type {
+        CastMemberType::Bitmap(bitmap_member) => (
+            bitmap_member.image_ref,
+            player.bitmap_manager.get_bitmap_version(bitmap_member.image_ref),
+        ),
+        CastMemberType::Shape(_) => (INVALID_BITMAP_REF, 0),
+        // Fields depend on keyboard focus and text state, always redraw them
+        _ => return None,
+    };
+    Some(SpriteRenderKey {
+        sprite_num: sprite.number,
+        member_ref: member_ref.clone(),
+        image_ref,
+        image_version,
+        rect: (sprite_rect.left, sprite_rect.top, sprite_rect.right, sprite_rect.bottom),
+        // The ink it's drawn with, after the compatibility profile's substitutions
+        ink: get_sprite_draw_ink(player, sprite) as i32,
+        blend: sprite.blend,
+        color: sprite.color.clone(),
+        bg_color: sprite.bg_color.clone(),
+        flip_h: sprite.flip_h,
+        flip_v: sprite.flip_v,
+        rotation: sprite.rotation,
+        skew: sprite.skew,
+        quad: sprite.quad,
+    })
+}
+
+fn get_sprite_draw_ink(player: &DirPlayer, sprite: &Sprite) -> u32 {
+    match &player.compatibility_profile {
+        Some(profile) => profile.map_ink(sprite.ink as u32),
+        None => sprite.ink as u32,
+    }
+}
+
+fn draw_stage_background(player: &DirPlayer, bitmap: &mut Bitmap, palettes: &PaletteMap, overscan: i32) {
+    let stage_rect = IntRect::from_size(overscan, overscan, player.movie.rect.width(), player.movie.rect.height());
+    if overscan > 0 {
+        bitmap.clear_rect(0, 0, bitmap.width as i32, bitmap.height as i32, OVERSCAN_COLOR, palettes);
+    }
     bitmap.clear_rect(
-        0,
-        0,
-        player.movie.rect.width(),
-        player.movie.rect.height(),
+        stage_rect.left,
+        stage_rect.top,
+        stage_rect.right,
+        stage_rect.bottom,
         resolve_color_ref(
-            &palettes,
+            palettes,
             &player.bg_color,
             &PaletteRef::BuiltIn(get_system_default_palette()),
         ),
-        &palettes,
+        palettes,
     );
+}
 
-    let sorted_sprites = player
+fn draw_sprite(
+    player: &mut DirPlayer,
+    bitmap: &mut Bitmap,
+    sprite_num: usize,
+    palettes: &PaletteMap,
+    stage_palette: Option<&PaletteRef>,
+    overscan: i32,
+) {
+    let sprite = player.movie.score.get_sprite(sprite_num as i16).unwrap();
+    let sprite_rect = get_concrete_sprite_rect(player, sprite).offset(overscan, overscan);
+    let member_ref = sprite.member.as_ref().unwrap();
+    let member = player
         .movie
-        .score
-        .get_sorted_channels();
-
-    for channel in sorted_sprites {
-        let sprite = &channel.sprite;
-        let sprite_rect = get_concrete_sprite_rect(player, sprite);
-        let member_ref = sprite.member.as_ref().unwrap();
-        let member = player
-            .movie
-            .cast_manager
-            .find_member_by_ref(member_ref);
-        if member.is_none() {
-            continue;
-        }
-        let member = member.unwrap();
-        match &member.member_type {
-            CastMemberType::Bitmap(bitmap_member) => {
-                let sprite_bitmap = player.bitmap_manager.get_bitmap_mut(bitmap_member.image_ref);
-                if sprite_bitmap.is_none() {
-                    continue;
-                }
-                let src_bitmap = sprite_bitmap.unwrap();
-                let mask = if should_matte_sprite(sprite.ink as u32) {
-                    if src_bitmap.matte.is_none() {
-                        src_bitmap.create_matte(&palettes);
-                    }
-                    Some(src_bitmap.matte.as_ref().unwrap())
-                } else {
-                    None
-                };
-                let src_rect = IntRect::from(0, 0, sprite.width as i32, sprite.height as i32);
-                let dst_rect = sprite_rect;
-                let dst_rect = IntRect::from(
-                    if sprite.flip_h { dst_rect.right } else { dst_rect.left },
-                    if sprite.flip_v { dst_rect.bottom } else { dst_rect.top },
-                    if sprite.flip_h { dst_rect.left } else { dst_rect.right },
-                    if sprite.flip_v { dst_rect.top } else { dst_rect.bottom },
-                );
+        .cast_manager
+        .find_member_by_ref(member_ref);
+    if member.is_none() {
+        return;
+    }
+    let member = member.unwrap();
+    let ink = get_sprite_draw_ink(player, sprite);
+    match &member.member_type {
+        CastMemberType::Bitmap(bitmap_member) => {
+            let matte = if should_matte_sprite(ink) {
+                player.bitmap_manager.get_or_create_matte(bitmap_member.image_ref, palettes)
+            } else {
+                None
+            };
+            let sprite_bitmap = player.bitmap_manager.get_bitmap(bitmap_member.image_ref);
+            if sprite_bitmap.is_none() {
+                return;
+            }
+            let src_bitmap = sprite_bitmap.unwrap();
+            let src_rect = IntRect::from(0, 0, sprite.width as i32, sprite.height as i32);
+            let dst_rect = sprite_rect;
+            let dst_rect = IntRect::from(
+                if sprite.flip_h { dst_rect.right } else { dst_rect.left },
+                if sprite.flip_v { dst_rect.bottom } else { dst_rect.top },
+                if sprite.flip_h { dst_rect.left } else { dst_rect.right },
+                if sprite.flip_v { dst_rect.top } else { dst_rect.bottom },
+            );
 
-                let mut params = CopyPixelsParams {
-                    blend: sprite.blend as i32,
-                    ink: sprite.ink as u32,
-                    color: sprite.color.clone(),
-                    bg_color: sprite.bg_color.clone(),
-                    mask_image: None,
-                };
-                if let Some(mask) = mask {
-                    let mask_bitmap: &BitmapMask = mask.borrow();
-                    params.mask_image = Some(mask_bitmap);
-                }
+            let palette_remap = stage_palette
+                .filter(|stage_palette| src_bitmap.has_palette() && src_bitmap.palette_ref != **stage_palette)
+                .map(|stage_palette| player.movie.cast_manager.get_palette_remap_table(&src_bitmap.palette_ref, stage_palette));
+            let params = CopyPixelsParams {
+                blend: sprite.blend as i32,
+                ink,
+                color: sprite.color.clone(),
+                bg_color: sprite.bg_color.clone(),
+                mask_image: matte.as_deref(),
+                palette_remap: palette_remap.as_ref().map(|table| table.as_slice()),
+                smooth: player.smooth_scaling,
+            };
+            let transform = get_sprite_transform(sprite, &get_concrete_sprite_rect(player, sprite), overscan);
+            if transform.is_identity() {
                 bitmap.copy_pixels_with_params(
-                    &palettes, 
+                    palettes, 
                     &src_bitmap, 
                     dst_rect, 
                     src_rect,
                     &params,
                 );
+            } else {
+                bitmap.copy_pixels_transformed(palettes, src_bitmap, dst_rect, src_rect, &transform, &params);
             }
-            CastMemberType::Shape(_) => {
-                let dst_rect = sprite_rect;
-                bitmap.fill_rect(
-                    dst_rect.left, 
-                    dst_rect.top, 
-                    dst_rect.right, 
-                    dst_rect.bottom, 
-                    resolve_color_ref(&palettes, &sprite.color, &PaletteRef::BuiltIn(get_system_default_palette())), 
-                    &palettes, 
-                    sprite.blend as f32 / 100.0,
-                );
+        }
+        CastMemberType::Shape(_) => {
+            let dst_rect = sprite_rect;
+            bitmap.fill_rect(
+                dst_rect.left, 
+                dst_rect.top, 
+                dst_rect.right, 
+                dst_rect.bottom, 
+                resolve_color_ref(palettes, &sprite.color, &PaletteRef::BuiltIn(get_system_default_palette())), 
+                palettes, 
+                sprite.blend as f32 / 100.0,
+            );
+        }
+        CastMemberType::Field(field_member) => {
+            draw_member_text(player, bitmap, sprite, &member.member_type, palettes, overscan);
+            if field_member.box_type == "scroll" {
+                draw_field_scrollbar(player, bitmap, sprite, &member.member_type, field_member, palettes, overscan);
             }
-            CastMemberType::Field(field_member) => {
-                let font = player.font_manager.get_system_font().unwrap(); // TODO
-                let font_bitmap = player.bitmap_manager.get_bitmap(font.bitmap_ref).unwrap();
-
-                bitmap.draw_text(&field_member.text, font, font_bitmap, sprite.loc_h, sprite.loc_v, sprite.ink as u32, sprite.bg_color.clone(), &palettes, field_member.fixed_line_space, field_member.top_spacing);
-
-                if player.keyboard_focus_sprite == sprite.number as i16 {
-                    let cursor_x = sprite.loc_h + (sprite.width / 2);
-                    let cursor_y = sprite.loc_v;
-                    let cursor_width = 1;
-                    let cursor_height = field_member.font_size as i16;
-                    
-                    bitmap.fill_rect(cursor_x, cursor_y, cursor_x + cursor_width, cursor_y + cursor_height as i32, (0, 0, 0), &palettes, 1.0)
+
+            if player.keyboard_focus_sprite == sprite.number as i16 {
+                let cursor_x = sprite.loc_h + overscan + (sprite.width / 2);
+                let cursor_y = sprite.loc_v + overscan;
+                let cursor_width = 1;
+                let cursor_height = field_member.font_size as i16;
+                
+                bitmap.fill_rect(cursor_x, cursor_y, cursor_x + cursor_width, cursor_y + cursor_height as i32, (0, 0, 0), palettes, 1.0)
+            }
+        }
+        CastMemberType::Text(_) => {
+            draw_member_text(player, bitmap, sprite, &member.member_type, palettes, overscan);
+        }
+        _ => {}
+    }
+}
+
+/// Draws the scrollbar of a #scroll field, with a thumb sized to how much of the text is in view.
+fn draw_field_scrollbar(
+    player: &DirPlayer,
+    bitmap: &mut Bitmap,
+    sprite: &Sprite,
+    member_type: &CastMemberType,
+    field: &FieldMember,
+    palettes: &PaletteMap,
+    overscan: i32,
+) {
+    let layout = match TextLayout::for_member(&player.font_manager, member_type) {
+        Some(layout) => layout,
+        None => return,
+    };
+    let box_height = layout.get_field_box_height(field);
+    let left = sprite.loc_h + overscan + field.width as i32 - SCROLLBAR_WIDTH;
+    let top = sprite.loc_v + overscan;
+    let right = left + SCROLLBAR_WIDTH;
+    let bottom = top + box_height;
+    bitmap.fill_rect(left, top, right, bottom, (0, 0, 0), palettes, 1.0);
+    bitmap.fill_rect(left + 1, top + 1, right - 1, bottom - 1, (221, 221, 221), palettes, 1.0);
+    let text_height = layout.get_height();
+    if text_height <= box_height {
+        return;
+    }
+    let track_height = box_height - 2;
+    let thumb_height = (track_height * box_height / text_height).max(8).min(track_height);
+    let thumb_top = top + 1 + (track_height - thumb_height) * (field.scroll_top as i32).min(text_height - box_height) / (text_height - box_height);
+    bitmap.fill_rect(left + 1, thumb_top, right - 1, thumb_top + thumb_height, (136, 136, 136), palettes, 1.0);
+}
+
+/// Draws the text of a text or field sprite. Fonts with a TrueType replacement are
+/// rasterized from their outlines, the others fall back to the system bitmap font.
+/// Fields that don't adjust to their text only show what fits in their box.
+fn draw_member_text(
+    player: &DirPlayer,
+    bitmap: &mut Bitmap,
+    sprite: &Sprite,
+    member_type: &CastMemberType,
+    palettes: &PaletteMap,
+    overscan: i32,
+) {
+    let (text, font_name, font_size, style, anti_alias, fixed_line_space, top_spacing, scroll_top) = match member_type {
+        CastMemberType::Field(field) => (&field.text, &field.font, field.font_size, field.get_font_style(), field.anti_alias, field.fixed_line_space, field.top_spacing, field.scroll_top),
+        CastMemberType::Text(text) => (&text.text, &text.font, text.font_size, text.get_font_style(), text.anti_alias, text.fixed_line_space, text.top_spacing, text.scroll_top),
+        _ => return,
+    };
+    let is_clipped = matches!(member_type, CastMemberType::Field(field) if field.box_type != "adjust");
+    let loc_h = sprite.loc_h + overscan;
+    let mut loc_v = sprite.loc_v + overscan;
+    // Scrolled and clipped text is drawn from the first line in view to the last
+    let mut text = text.as_str();
+    let mut clip_rect = None;
+    if scroll_top > 0 || is_clipped {
+        let layout = match TextLayout::for_member(&player.font_manager, member_type) {
+            Some(layout) => layout,
+            None => return,
+        };
+        let box_height = match member_type {
+            CastMemberType::Field(field) if is_clipped => Some(layout.get_field_box_height(field)),
+            _ => None,
+        };
+        if let (CastMemberType::Field(field), Some(box_height)) = (member_type, box_height) {
+            let text_width = if field.box_type == "scroll" { field.width as i32 - SCROLLBAR_WIDTH } else { field.width as i32 };
+            clip_rect = Some(IntRect::from(loc_h, loc_v, loc_h + text_width, loc_v + box_height));
+        }
+        match layout.get_visible_range(scroll_top as i32, box_height) {
+            Some((range, offset)) => {
+                let byte_index = |char_index| text.char_indices().nth(char_index).map_or(text.len(), |(index, _)| index);
+                text = &text[byte_index(range.start)..byte_index(range.end)];
+                loc_v += offset;
+            }
+            None => return,
+        }
+    }
+    let draw_text = |bitmap: &mut Bitmap| {
+        if let Some(font) = player.font_manager.get_truetype_font(font_name) {
+            let params = TrueTypeTextParams {
+                size: font_size as f32,
+                color: resolve_color_ref(palettes, &sprite.color, &PaletteRef::BuiltIn(get_system_default_palette())),
+                anti_alias,
+                fixed_line_space,
+                top_spacing,
+                char_spacing: style.char_spacing as f32,
+            };
+            draw_truetype_text(bitmap, font, text, loc_h, loc_v, &params, palettes);
+        } else if let Some(font) = player.font_manager.get_system_font() {
+            let font_bitmap = player.bitmap_manager.get_bitmap(font.bitmap_ref).unwrap();
+            let params = BitmapTextParams {
+                ink: sprite.ink as u32,
+                bg_color: sprite.bg_color.clone(),
+                line_spacing: fixed_line_space,
+                style,
+            };
+            bitmap.draw_text(text, font, font_bitmap, (loc_h, loc_v), palettes, &params);
+        } else {
+            return;
+        }
+        draw_text_hyperlinks(player, bitmap, sprite, member_type, palettes, overscan);
+    };
+    match clip_rect {
+        Some(clip_rect) => draw_clipped_horizontally(bitmap, &clip_rect, draw_text),
+        None => draw_text(bitmap),
+    }
+}
+
+/// Draws, then puts back the pixels left and right of a rect in the rows it spans, so
+/// that lines too wide for a field box don't show past its sides.
+fn draw_clipped_horizontally(bitmap: &mut Bitmap, rect: &IntRect, draw: impl FnOnce(&mut Bitmap)) {
+    let bytes_per_pixel = bitmap.bit_depth as usize / 8;
+    if bytes_per_pixel == 0 {
+        draw(bitmap);
+        return;
+    }
+    let row_len = bitmap.width as usize * bytes_per_pixel;
+    let top = rect.top.clamp(0, bitmap.height as i32) as usize;
+    let bottom = rect.bottom.clamp(0, bitmap.height as i32) as usize;
+    let left = rect.left.clamp(0, bitmap.width as i32);
+    let right = rect.right.clamp(left, bitmap.width as i32) as usize * bytes_per_pixel;
+    let left = left as usize * bytes_per_pixel;
+    let saved_rows = bitmap.data[top * row_len..bottom * row_len].to_vec();
+    draw(bitmap);
+    for (row, saved_row) in saved_rows.chunks(row_len).enumerate() {
+        let row_start = (top + row) * row_len;
+        bitmap.data[row_start..row_start + left].copy_from_slice(&saved_row[..left]);
+        bitmap.data[row_start + right..row_start + row_len].copy_from_slice(&saved_row[right..]);
+    }
+}
+
+/// Draws the hyperlinks of a text sprite over its text, underlined and in the color of
+/// their state. Text in the system font keeps its color and only gets the underline.
+fn draw_text_hyperlinks(
+    player: &DirPlayer,
+    bitmap: &mut Bitmap,
+    sprite: &Sprite,
+    member_type: &CastMemberType,
+    palettes: &PaletteMap,
+    overscan: i32,
+) {
+    let text_member = match member_type {
+        CastMemberType::Text(text_member) if !text_member.hyperlinks.is_empty() => text_member,
+        _ => return,
+    };
+    let layout = match TextLayout::for_member(&player.font_manager, member_type) {
+        Some(layout) => layout,
+        None => return,
+    };
+    let font = player.font_manager.get_truetype_font(&text_member.font);
+    let chars = text_member.text.chars().collect::<Vec<_>>();
+    let scroll_top = text_member.scroll_top as i32;
+    let first_top = layout.lines.first().map_or(0, |line| line.top);
+    for link in &text_member.hyperlinks {
+        let color = link.color();
+        for line in layout.lines.iter().filter(|line| line.top - first_top >= scroll_top) {
+            let line_end = line.start + line.char_x.len() - 1;
+            let start = link.range.0.saturating_sub(1).max(line.start);
+            let end = link.range.1.min(line_end).min(chars.len());
+            if start >= end {
+                continue;
+            }
+            let left = sprite.loc_h + overscan + line.char_x[start - line.start];
+            let right = sprite.loc_h + overscan + line.char_x[end - line.start];
+            let top = sprite.loc_v + overscan + line.top - scroll_top;
+            let underline_y = match font {
+                Some(font) => {
+                    let params = TrueTypeTextParams {
+                        size: text_member.font_size as f32,
+                        color,
+                        anti_alias: text_member.anti_alias,
+                        fixed_line_space: text_member.fixed_line_space,
+                        top_spacing: text_member.top_spacing,
+                        char_spacing: text_member.char_spacing as f32,
+                    };
+                    let segment = chars[start..end].iter().collect::<String>();
+                    draw_truetype_text(bitmap, font, &segment, left, top - text_member.top_spacing as i32, &params, palettes);
+                    top + get_line_height(font, &params).0.round() as i32 + 1
                 }
+                None => top + line.height - 2,
+            };
+            bitmap.fill_rect(left, underline_y, right, underline_y + 1, color, palettes, 1.0);
+        }
+    }
+}
+
+/// Draws a cast member on its own, at its own size and over white, the way the cast
+/// window previews it. Film loops show the sprites of their first frame.
+pub fn render_member_image(player: &DirPlayer, member_ref: &CastMemberRef) -> Option<Bitmap> {
+    let member = player.movie.cast_manager.find_member_by_ref(member_ref)?;
+    let palettes = player.movie.cast_manager.palettes();
+    match &member.member_type {
+        CastMemberType::Bitmap(bitmap_member) => {
+            let src_bitmap = player.bitmap_manager.get_bitmap(bitmap_member.image_ref)?;
+            let mut image = new_member_image(src_bitmap.width as i32, src_bitmap.height as i32, &palettes);
+            let rect = IntRect::from(0, 0, src_bitmap.width as i32, src_bitmap.height as i32);
+            let params = CopyPixelsParams::default(&image);
+            image.copy_pixels_with_params(&palettes, src_bitmap, rect.clone(), rect, &params);
+            Some(image)
+        }
+        CastMemberType::Shape(shape_member) => {
+            let mut image = new_member_image(shape_member.shape_info.width as i32, shape_member.shape_info.height as i32, &palettes);
+            let color = resolve_color_ref(
+                &palettes,
+                &ColorRef::PaletteIndex(shape_member.shape_info.color),
+                &PaletteRef::BuiltIn(get_system_default_palette()),
+            );
+            image.fill_rect(0, 0, image.width as i32, image.height as i32, color, &palettes, 1.0);
+            Some(image)
+        }
+        CastMemberType::Field(_) | CastMemberType::Text(_) => {
+            let layout = TextLayout::for_member(&player.font_manager, &member.member_type)?;
+            let (width, height) = match &member.member_type {
+                CastMemberType::Field(field) if field.box_type != "adjust" => (field.width, layout.get_field_box_height(field)),
+                CastMemberType::Field(field) => (field.width, layout.get_height()),
+                CastMemberType::Text(text) => (text.width, layout.get_height()),
+                _ => return None,
+            };
+            let mut image = new_member_image(width as i32, height, &palettes);
+            let sprite = Sprite::new(0);
+            draw_member_text(player, &mut image, &sprite, &member.member_type, &palettes, 0);
+            Some(image)
+        }
+        CastMemberType::FilmLoop(film_loop) => {
+            let sprites = film_loop.score.frame_data.frame_channel_data
+                .iter()
+                .filter(|(frame_index, channel_index, data)| {
+                    *frame_index == 0 && get_channel_number_from_index(*channel_index as u32) > 0 && data.cast_member > 0
+                })
+                .filter_map(|(_, _, data)| {
+                    // Members of the film loop's own cast are stored without a cast lib
+                    let sprite_member_ref = CastMemberRef {
+                        cast_lib: if data.cast_lib == 0 { member_ref.cast_lib } else { data.cast_lib as i32 },
+                        cast_member: data.cast_member as i32,
+                    };
+                    let sprite_member = player.movie.cast_manager.find_member_by_ref(&sprite_member_ref)?;
+                    let reg_point = match &sprite_member.member_type {
+                        CastMemberType::Bitmap(bitmap_member) => bitmap_member.reg_point,
+                        CastMemberType::Shape(shape_member) => shape_member.shape_info.reg_point,
+                        CastMemberType::Field(_) | CastMemberType::Text(_) => (0, 0),
+                        _ => return None,
+                    };
+                    let sprite_image = render_member_image(player, &sprite_member_ref)?;
+                    let left = data.pos_x as i16 as i32 - reg_point.0 as i32;
+                    let top = data.pos_y as i16 as i32 - reg_point.1 as i32;
+                    let rect = IntRect::from_size(left, top, data.width as i32, data.height as i32);
+                    Some((rect, (data.ink & 0x3F) as u32, sprite_image))
+                })
+                .collect_vec();
+            let bounds = sprites.iter().map(|(rect, ..)| rect).fold(None, |bounds: Option<IntRect>, rect| match bounds {
+                Some(bounds) => Some(IntRect::from(
+                    bounds.left.min(rect.left),
+                    bounds.top.min(rect.top),
+                    bounds.right.max(rect.right),
+                    bounds.bottom.max(rect.bottom),
+                )),
+                None => Some(rect.clone()),
+            })?;
+            let mut image = new_member_image(bounds.width(), bounds.height(), &palettes);
+            for (rect, ink, sprite_image) in sprites {
+                let params = CopyPixelsParams { ink, ..CopyPixelsParams::default(&sprite_image) };
+                image.copy_pixels_with_params(
+                    &palettes,
+                    &sprite_image,
+                    IntRect::from(rect.left - bounds.left, rect.top - bounds.top, rect.right - bounds.left, rect.bottom - bounds.top),
+                    IntRect::from(0, 0, sprite_image.width as i32, sprite_image.height as i32),
+                    &params,
+                );
             }
-            _ => {}
+            Some(image)
+        }
+        _ => None,
+    }
+}
+
+/// Renders a cast member shrunk to fit in `width` by `height`, centered over white.
+/// Members smaller than that keep their size.
+pub fn render_member_thumbnail(player: &DirPlayer, member_ref: &CastMemberRef, width: u16, height: u16) -> Option<Bitmap> {
+    let member_image = render_member_image(player, member_ref)?;
+    let palettes = player.movie.cast_manager.palettes();
+    let mut thumbnail = new_member_image(width as i32, height as i32, &palettes);
+    let scale = (thumbnail.width as f32 / member_image.width as f32)
+        .min(thumbnail.height as f32 / member_image.height as f32)
+        .min(1.0);
+    let scaled_width = ((member_image.width as f32 * scale).round() as i32).max(1);
+    let scaled_height = ((member_image.height as f32 * scale).round() as i32).max(1);
+    let left = (thumbnail.width as i32 - scaled_width) / 2;
+    let top = (thumbnail.height as i32 - scaled_height) / 2;
+    let params = CopyPixelsParams { smooth: scale < 1.0, ..CopyPixelsParams::default(&thumbnail) };
+    thumbnail.copy_pixels_with_params(
+        &palettes,
+        &member_image,
+        IntRect::from_size(left, top, scaled_width, scaled_height),
+        IntRect::from(0, 0, member_image.width as i32, member_image.height as i32),
+        &params,
+    );
+    Some(thumbnail)
+}
+
+fn new_member_image(width: i32, height: i32, palettes: &PaletteMap) -> Bitmap {
+    let mut image = Bitmap::new(
+        width.clamp(1, u16::MAX as i32) as u16,
+        height.clamp(1, u16::MAX as i32) as u16,
+        32,
+        PaletteRef::BuiltIn(get_system_default_palette()),
+    );
+    image.clear_rect(0, 0, image.width as i32, image.height as i32, (255, 255, 255), palettes);
+    image
+}
+
+/// Renders the stage into `bitmap`. When `overscan` is greater than zero, the stage is
+/// drawn inset by that many pixels so that sprites positioned off-stage remain visible.
+///
+/// Sprites in `hidden_channels` are skipped, which the debugger uses to mute or solo channels.
+///
+/// If `layer_cache` is given, sprites at the bottom of the stack that didn't change
+/// since the previous frame are composited from the cached static layer instead of
+/// being redrawn.
+///
+/// Unless `trails_mode` ignores them, what sprites with trails left on the stage in
+/// earlier frames is drawn over the background. Recording adds their new renders to it.
+pub fn render_stage_to_bitmap(
+    player: &mut DirPlayer,
+    bitmap: &mut Bitmap,
+    debug_sprite_num: Option<i16>,
+    overscan: i32,
+    hidden_channels: &HashSet<i16>,
+    trails_mode: StageTrailsMode,
+    layer_cache: Option<&mut Option<StaticLayerCache>>,
+) {
+    let palettes = player.movie.cast_manager.palettes();
+    // Indexed members that don't share the stage palette are remapped to it when paletteMapping is on
+    let stage_palette = if player.movie.palette_mapping {
+        player.movie.score.get_frame_palette(player.movie.current_frame)
+    } else {
+        None
+    };
+    let stage_rect = IntRect::from_size(overscan, overscan, player.movie.rect.width(), player.movie.rect.height());
+    let clip_rect = IntRect::from(0, 0, bitmap.width as i32, bitmap.height as i32);
+
+    let sprite_keys = player
+        .movie
+        .score
+        .get_sorted_channels()
+        .iter()
+        .filter_map(|channel| {
+            let sprite = &channel.sprite;
+            if hidden_channels.contains(&(sprite.number as i16)) {
+                return None;
+            }
+            let sprite_rect = get_sprite_bounds(player, sprite).offset(overscan, overscan);
+            if sprite_rect.intersects(&clip_rect) {
+                Some((sprite.number, get_sprite_render_key(player, sprite, &sprite_rect)))
+            } else {
+                None
+            }
+        })
+        .collect_vec();
+
+    let movie_width = player.movie.rect.width() as u16;
+    let movie_height = player.movie.rect.height() as u16;
+    let mut trails = if trails_mode != StageTrailsMode::Ignore {
+        player.stage_trails.take().filter(|trails| trails.bitmap.width == movie_width && trails.bitmap.height == movie_height)
+    } else {
+        None
+    };
+    // The static layer can't hold trails, which sit between the background and the sprites
+    let layer_cache = if trails.is_some() { None } else { layer_cache };
+
+    let mut first_sprite_to_draw = 0;
+    match layer_cache {
+        Some(layer_cache) => {
+            let cache = layer_cache.take();
+            let last_frame_keys = cache.as_ref().map(|x| x.last_frame_keys.as_slice()).unwrap_or_default();
+            let stable_len = sprite_keys
+                .iter()
+                .zip(last_frame_keys.iter())
+                .take_while(|((_, key), last_key)| key.is_some() && key == *last_key)
+                .count();
+            let is_cache_valid = cache.as_ref().is_some_and(|cache| {
+                cache.bitmap.width == bitmap.width
+                    && cache.bitmap.height == bitmap.height
+                    && cache.overscan == overscan
+                    && cache.bg_color == player.bg_color
+                    && Rc::ptr_eq(&cache.palettes, &palettes)
+                    && cache.stage_palette == stage_palette
+                    && cache.smooth_scaling == player.smooth_scaling
+                    && cache.sprite_keys.len() == stable_len
+                    && cache
+                        .sprite_keys
+                        .iter()
+                        .zip(sprite_keys.iter())
+                        .all(|(cached, (_, key))| Some(cached) == key.as_ref())
+            });
+            let mut cache = if is_cache_valid {
+                cache.unwrap()
+            } else {
+                let mut cache_bitmap = Bitmap::new(
+                    bitmap.width,
+                    bitmap.height,
+                    32,
+                    PaletteRef::BuiltIn(get_system_default_palette()),
+                );
+                draw_stage_background(player, &mut cache_bitmap, &palettes, overscan);
+                for (sprite_num, _) in &sprite_keys[..stable_len] {
+                    draw_sprite(player, &mut cache_bitmap, *sprite_num, &palettes, stage_palette.as_ref(), overscan);
+                }
+                StaticLayerCache {
+                    bitmap: cache_bitmap,
+                    bg_color: player.bg_color.clone(),
+                    overscan,
+                    palettes: palettes.clone(),
+                    stage_palette: stage_palette.clone(),
+                    smooth_scaling: player.smooth_scaling,
+                    sprite_keys: sprite_keys[..stable_len].iter().map(|(_, key)| key.clone().unwrap()).collect(),
+                    last_frame_keys: vec![],
+                }
+            };
+            bitmap.data.copy_from_slice(&cache.bitmap.data);
+            first_sprite_to_draw = stable_len;
+            cache.last_frame_keys = sprite_keys.iter().map(|(_, key)| key.clone()).collect();
+            *layer_cache = Some(cache);
         }
+        None => draw_stage_background(player, bitmap, &palettes, overscan),
+    }
+
+    if let Some(trails) = &trails {
+        let params = CopyPixelsParams {
+            mask_image: Some(&trails.mask),
+            ..CopyPixelsParams::default(&trails.bitmap)
+        };
+        bitmap.copy_pixels_with_params(
+            &palettes,
+            &trails.bitmap,
+            IntRect::from_size(overscan, overscan, movie_width as i32, movie_height as i32),
+            IntRect::from_size(0, 0, movie_width as i32, movie_height as i32),
+            &params,
+        );
+    }
+
+    for (sprite_num, _) in &sprite_keys[first_sprite_to_draw..] {
+        draw_sprite(player, bitmap, *sprite_num, &palettes, stage_palette.as_ref(), overscan);
+        let sprite = player.movie.score.get_sprite(*sprite_num as i16);
+        if let Some(sprite) = sprite.filter(|sprite| trails_mode == StageTrailsMode::Record && sprite.trails) {
+            trails
+                .get_or_insert_with(|| StageTrails::new(movie_width, movie_height))
+                .record_sprite(player, sprite, bitmap, &palettes, overscan);
+        }
+    }
+    if trails_mode != StageTrailsMode::Ignore {
+        player.stage_trails = trails;
+    }
+
+    if overscan > 0 {
+        bitmap.stroke_rect(
+            stage_rect.left - 1,
+            stage_rect.top - 1,
+            stage_rect.right + 1,
+            stage_rect.bottom + 1,
+            (255, 255, 0),
+            &palettes,
+            1.0
+        );
     }
 
     // Draw debug rect
     if let Some(sprite) = debug_sprite_num.and_then(|x| player.movie.score.get_sprite(x)) {
-        let sprite_rect = get_concrete_sprite_rect(player, sprite);
+        let sprite_rect = get_concrete_sprite_rect(player, sprite).offset(overscan, overscan);
         bitmap.stroke_rect(
             sprite_rect.left, 
             sprite_rect.top, 
@@ -140,7 +872,7 @@ pub fn render_stage_to_bitmap(player: &mut DirPlayer, bitmap: &mut Bitmap, debug
             &palettes, 
             1.0
         );
-        bitmap.set_pixel(sprite.loc_h, sprite.loc_v, (0, 255, 0), &palettes);
+        bitmap.set_pixel(sprite.loc_h + overscan, sprite.loc_v + overscan, (0, 255, 0), &palettes);
     }
 
     // Draw pick rect
@@ -149,7 +881,7 @@ pub fn render_stage_to_bitmap(player: &mut DirPlayer, bitmap: &mut Bitmap, debug
         let hovered_sprite = get_sprite_at(player, player.mouse_loc.0, player.mouse_loc.1, false);
         if let Some(hovered_sprite) = hovered_sprite {
             let sprite = player.movie.score.get_sprite(hovered_sprite as i16).unwrap();
-            let sprite_rect = get_concrete_sprite_rect(player, sprite);
+            let sprite_rect = get_concrete_sprite_rect(player, sprite).offset(overscan, overscan);
             bitmap.stroke_rect(
                 sprite_rect.left, 
                 sprite_rect.top, 
@@ -161,104 +893,198 @@ pub fn render_stage_to_bitmap(player: &mut DirPlayer, bitmap: &mut Bitmap, debug
             );
         }
     }
-    draw_cursor(player, bitmap, &palettes);
+    draw_alert(player, bitmap, &palettes, overscan);
+    draw_cursor(player, bitmap, &palettes, overscan);
 }
 
-fn draw_cursor(player: &DirPlayer, bitmap: &mut Bitmap, palettes: &PaletteMap) {
-    let hovered_sprite = get_sprite_at(player, player.mouse_loc.0, player.mouse_loc.1, false);
-    let cursor_ref = if let Some(hovered_sprite) = hovered_sprite {
-        let hovered_sprite = player.movie.score.get_sprite(hovered_sprite as i16).unwrap();
-        hovered_sprite.cursor_ref.as_ref()
-    } else {
-        None
+fn draw_alert(player: &DirPlayer, bitmap: &mut Bitmap, palettes: &PaletteMap, overscan: i32) {
+    let alert = match &player.alert {
+        Some(alert) => alert,
+        None => return,
     };
-    let cursor_ref = cursor_ref.or(Some(&player.cursor));
-    let cursor_list = cursor_ref
-        .and_then(|x| {
-            match x {
-                CursorRef::Member(x) => Some(x),
-                _ => None,
-            }
-        });
-    let cursor_bitmap_member = cursor_list
-        .and_then(|x| x.first().map(|x| *x)) // TODO: what to do with other values? maybe animate?
-        .and_then(|x| player.movie.cast_manager.find_member_by_slot_number(x as u32))
-        .and_then(|x| x.member_type.as_bitmap());
-
-    let cursor_bitmap_ref = cursor_bitmap_member
-        .and_then(|x| Some(x.image_ref));
-
-    let cursor_mask_bitmap_ref = cursor_list
-        .and_then(|x| x.get(1).map(|x| *x)) // TODO: what to do with other values? maybe animate?
-        .and_then(|x| player.movie.cast_manager.find_member_by_slot_number(x as u32))
-        .and_then(|x| x.member_type.as_bitmap())
-        .and_then(|x| Some(x.image_ref));
-
-    if let Some(cursor_bitmap_ref) = cursor_bitmap_ref {
-        let cursor_bitmap = player.bitmap_manager.get_bitmap(cursor_bitmap_ref).unwrap();
-        let mask = if let Some(cursor_mask_bitmap_ref) = cursor_mask_bitmap_ref {
-            let cursor_mask_bitmap = player.bitmap_manager.get_bitmap(cursor_mask_bitmap_ref).unwrap();
-            let mask = cursor_mask_bitmap.to_mask();
-            Some(mask)
-        } else {
-            None
-        };
-        let cursor_bitmap_member = cursor_bitmap_member.unwrap();
-        bitmap.copy_pixels_with_params(
-            &palettes, 
-            cursor_bitmap, 
-            IntRect::from_size(
-                player.mouse_loc.0 - cursor_bitmap_member.reg_point.0 as i32,
-                player.mouse_loc.1 - cursor_bitmap_member.reg_point.1 as i32, 
-                cursor_bitmap.width as i32, 
-                cursor_bitmap.height as i32
-            ), 
-            IntRect::from_size(0, 0, cursor_bitmap.width as i32, cursor_bitmap.height as i32),
-            &CopyPixelsParams {
-                blend: 100,
-                ink: 41,
-                bg_color: bitmap.get_bg_color_ref(),
-                color: bitmap.get_fg_color_ref(),
-                mask_image: mask.as_ref(),
-            }
-        );
+    let layout = get_alert_layout(player, &alert.message);
+    let box_rect = layout.box_rect.offset(overscan, overscan);
+    let button_rect = layout.button_rect.offset(overscan, overscan);
+    bitmap.fill_rect(box_rect.left, box_rect.top, box_rect.right, box_rect.bottom, (255, 255, 255), palettes, 1.0);
+    bitmap.stroke_rect(box_rect.left, box_rect.top, box_rect.right, box_rect.bottom, (0, 0, 0), palettes, 1.0);
+    bitmap.stroke_rect(button_rect.left, button_rect.top, button_rect.right, button_rect.bottom, (0, 0, 0), palettes, 1.0);
+
+    let font = match player.font_manager.get_system_font() {
+        Some(font) => font,
+        None => return,
+    };
+    let font_bitmap = player.bitmap_manager.get_bitmap(font.bitmap_ref).unwrap();
+    let params = BitmapTextParams {
+        ink: 36,
+        bg_color: bitmap.get_bg_color_ref(),
+        line_spacing: 0,
+        style: FontStyle::default(),
+    };
+    for (i, line) in layout.lines.iter().enumerate() {
+        let y = box_rect.top + 12 + i as i32 * layout.line_height;
+        bitmap.draw_text(line, font, font_bitmap, (box_rect.left + 12, y), palettes, &params);
     }
+    let ok_width = 2 * (font.char_width as i32 + 1);
+    let ok_x = button_rect.left + (button_rect.width() - ok_width) / 2;
+    let ok_y = button_rect.top + (button_rect.height() - font.char_height as i32) / 2;
+    bitmap.draw_text("OK", font, font_bitmap, (ok_x, ok_y), palettes, &params);
+}
+
+fn draw_cursor(player: &DirPlayer, bitmap: &mut Bitmap, palettes: &PaletteMap, overscan: i32) {
+    let cursor_image = match get_cursor_image(player, player.clock.elapsed_ms()) {
+        Some(cursor_image) => cursor_image,
+        None => return,
+    };
+    // Cursors small enough for the browser are shown as CSS cursors instead
+    if cursor_image.fits_css_cursor(player) {
+        return;
+    }
+    let cursor_bitmap = player.bitmap_manager.get_bitmap(cursor_image.image_ref).unwrap();
+    let mask = cursor_image.mask_ref
+        .and_then(|mask_ref| player.bitmap_manager.get_bitmap(mask_ref))
+        .map(|mask_bitmap| mask_bitmap.to_mask());
+    bitmap.copy_pixels_with_params(
+        &palettes, 
+        cursor_bitmap, 
+        IntRect::from_size(
+            player.mouse_loc.0 + overscan - cursor_image.hot_spot.0,
+            player.mouse_loc.1 + overscan - cursor_image.hot_spot.1, 
+            cursor_bitmap.width as i32, 
+            cursor_bitmap.height as i32
+        ), 
+        IntRect::from_size(0, 0, cursor_bitmap.width as i32, cursor_bitmap.height as i32),
+        &CopyPixelsParams {
+            blend: 100,
+            ink: 41,
+            bg_color: bitmap.get_bg_color_ref(),
+            color: bitmap.get_fg_color_ref(),
+            mask_image: mask.as_ref(),
+            palette_remap: None,
+            smooth: false,
+        }
+    );
 }
 
 impl PlayerCanvasRenderer {
-    #[allow(dead_code)]
+    fn get_debug_hidden_channels(&self, player: &DirPlayer) -> HashSet<i16> {
+        if let Some(solo_channel_num) = self.debug_solo_channel_num {
+            (0..player.movie.score.channels.len() as i16)
+                .filter(|x| *x != solo_channel_num)
+                .collect()
+        } else {
+            self.debug_muted_channels.clone()
+        }
+    }
+
+    /// Sets the size of the stage in CSS pixels.
     pub fn set_size(&mut self, width: u32, height: u32) {
         self.size = (width, height);
-        self.canvas.set_width(width);
-        self.canvas.set_height(height);
+        self.update_backing_size();
+    }
+
+    pub fn set_device_pixel_ratio(&mut self, ratio: f64) {
+        self.device_pixel_ratio = if ratio > 0.0 { ratio } else { 1.0 };
+        self.update_backing_size();
+    }
+
+    fn get_backing_size(&self) -> (u32, u32) {
+        (
+            (self.size.0 as f64 * self.device_pixel_ratio).round() as u32,
+            (self.size.1 as f64 * self.device_pixel_ratio).round() as u32,
+        )
+    }
+
+    fn update_backing_size(&mut self) {
+        let (width, height) = self.get_backing_size();
+        self.canvas.set_size(width, height);
+        self.needs_redraw = true;
+    }
+
+    /// Where the movie goes on the stage canvas, in device pixels.
+    fn get_movie_dest_rect(&self) -> (f64, f64, f64, f64) {
+        let movie_width = self.bitmap.width as f64;
+        let movie_height = self.bitmap.height as f64;
+        let (view_width, view_height) = self.get_backing_size();
+        let (view_width, view_height) = (view_width as f64, view_height as f64);
+        let fit_scale = (view_width / movie_width).min(view_height / movie_height);
+        let (scale_x, scale_y) = match self.scale_mode {
+            StageScaleMode::None => (self.device_pixel_ratio, self.device_pixel_ratio),
+            StageScaleMode::Fit => (fit_scale, fit_scale),
+            StageScaleMode::Integer => (fit_scale.floor().max(1.0), fit_scale.floor().max(1.0)),
+            StageScaleMode::Stretch => (view_width / movie_width, view_height / movie_height),
+        };
+        let width = movie_width * scale_x;
+        let height = movie_height * scale_y;
+        (((view_width - width) / 2.0).floor(), ((view_height - height) / 2.0).floor(), width, height)
+    }
+
+    /// Maps a point on the stage element, in CSS pixels, to a point in the movie.
+    pub fn to_movie_point(&self, x: f64, y: f64) -> (i32, i32) {
+        let (left, top, width, height) = self.get_movie_dest_rect();
+        let movie_x = (x * self.device_pixel_ratio - left) * self.bitmap.width as f64 / width;
+        let movie_y = (y * self.device_pixel_ratio - top) * self.bitmap.height as f64 / height;
+        let overscan = self.debug_overscan.max(0);
+        (movie_x.floor() as i32 - overscan, movie_y.floor() as i32 - overscan)
+    }
+
+    fn present_frame(&mut self, image_data: &web_sys::ImageData) {
+        let (width, height) = (self.bitmap.width as u32, self.bitmap.height as u32);
+        if self.movie_canvas.is_none() {
+            self.movie_canvas = web_sys::OffscreenCanvas::new(width, height).ok()
+                .and_then(|canvas| {
+                    let ctx = canvas.get_context("2d").ok()??.dyn_into::<web_sys::OffscreenCanvasRenderingContext2d>().ok()?;
+                    Some((canvas, ctx))
+                });
+        }
+        let dest_rect = self.get_movie_dest_rect();
+        let (backing_width, backing_height) = self.get_backing_size();
+        let (movie_canvas, movie_ctx) = match &self.movie_canvas {
+            Some(movie_canvas) => movie_canvas,
+            None => return,
+        };
+        if movie_canvas.width() != width || movie_canvas.height() != height {
+            movie_canvas.set_width(width);
+            movie_canvas.set_height(height);
+        }
+        let _ = movie_ctx.put_image_data(image_data, 0.0, 0.0);
+        self.canvas.clear_rect(backing_width, backing_height);
+        self.canvas.draw_canvas(movie_canvas, dest_rect);
     }
 
     pub fn set_preview_size(&mut self, width: u32, height: u32) {
         self.preview_size = (width, height);
-        self.preview_canvas.set_width(width);
-        self.preview_canvas.set_height(height);
+        if let Some(preview_canvas) = &self.preview_canvas {
+            preview_canvas.set_width(width);
+            preview_canvas.set_height(height);
+        }
     }
 
     pub fn set_container_element(&mut self, container_element: web_sys::HtmlElement) {
-        if self.canvas.parent_node().is_some() {
-            self.canvas.remove();
+        if let StageCanvas::Element(canvas, _) = &self.canvas {
+            if canvas.parent_node().is_some() {
+                canvas.remove();
+            }
+            container_element.append_child(canvas).unwrap();
+            self.container_element = Some(container_element);
         }
-        container_element.append_child(&self.canvas).unwrap();
-        self.container_element = Some(container_element);
     }
 
     pub fn set_preview_container_element(&mut self, container_element: Option<web_sys::HtmlElement>) {
-        if self.preview_canvas.parent_node().is_some() {
-            self.preview_canvas.remove();
+        let preview_canvas = match &self.preview_canvas {
+            Some(preview_canvas) => preview_canvas,
+            None => return,
+        };
+        if preview_canvas.parent_node().is_some() {
+            preview_canvas.remove();
         }
         if let Some(container_element) = container_element {
-            container_element.append_child(&self.preview_canvas).unwrap();
+            container_element.append_child(preview_canvas).unwrap();
             self.preview_container_element = Some(container_element);
         }
     }
 
     pub fn draw_preview_frame(&mut self, player: &DirPlayer) {
-        if self.preview_member_ref.is_none() || self.preview_container_element.is_none() || self.preview_ctx2d.is_null() || self.preview_ctx2d.is_undefined() {
+        if self.preview_member_ref.is_none() || self.preview_container_element.is_none() || self.preview_ctx2d.is_none() {
             return;
         }
 
@@ -318,10 +1144,11 @@ impl PlayerCanvasRenderer {
                     bitmap.width.into(),
                     bitmap.height.into(),
                 );
-                self.preview_ctx2d.set_fill_style(&JsValue::from_str("white"));
+                let preview_ctx2d = self.preview_ctx2d.as_ref().unwrap();
+                preview_ctx2d.set_fill_style(&JsValue::from_str("white"));
                 match image_data {
                     Ok(image_data) => {
-                        self.preview_ctx2d.put_image_data(&image_data, 0.0, 0.0).unwrap();
+                        preview_ctx2d.put_image_data(&image_data, 0.0, 0.0).unwrap();
                     }
                     _ => {}
                 }
@@ -330,6 +1157,22 @@ impl PlayerCanvasRenderer {
         }
     }
 
+    /// Shows member cursors that fit the browser limits as CSS cursors on the stage, and
+    /// hides the system cursor while larger ones are drawn on the stage.
+    fn update_css_cursor(&mut self, player: &DirPlayer, now_ms: i64) {
+        let cursor_image = get_cursor_image(player, now_ms);
+        if cursor_image == self.cursor_image {
+            return;
+        }
+        let css_cursor = match &cursor_image {
+            Some(image) if image.fits_css_cursor(player) => get_css_cursor(player, image),
+            Some(_) => "none".to_string(),
+            None => String::new(),
+        };
+        JsApi::dispatch_stage_cursor_changed(&css_cursor);
+        self.cursor_image = cursor_image;
+    }
+
     pub fn draw_frame(&mut self, player: &mut DirPlayer) {
         // let time = chrono::Local::now().timestamp_millis() as i64;
         // let time_seconds = time as f64 / 1000.0;
@@ -354,8 +1197,9 @@ impl PlayerCanvasRenderer {
         //     )
         //     .unwrap();
 
-        let movie_width = player.movie.rect.width();
-        let movie_height = player.movie.rect.height();
+        let overscan = self.debug_overscan.max(0);
+        let movie_width = player.movie.rect.width() + overscan * 2;
+        let movie_height = player.movie.rect.height() + overscan * 2;
 
         if self.bitmap.width != movie_width as u16 || self.bitmap.height != movie_height as u16 {
             self.bitmap = Bitmap::new(
@@ -365,23 +1209,73 @@ impl PlayerCanvasRenderer {
                 PaletteRef::BuiltIn(get_system_default_palette()),
             );
         }
+        self.update_css_cursor(player, player.clock.elapsed_ms());
+        let hidden_channels = self.get_debug_hidden_channels(player);
         let bitmap = &mut self.bitmap;
-        render_stage_to_bitmap(player, bitmap, self.debug_selected_channel_num);
+        render_stage_to_bitmap(player, bitmap, self.debug_selected_channel_num, overscan, &hidden_channels, StageTrailsMode::Record, Some(&mut self.static_layer));
+        self.needs_redraw = false;
+
+        if player.is_stage_indexed() {
+            let palette_ref = player
+                .movie
+                .score
+                .get_frame_palette(player.movie.current_frame)
+                .unwrap_or(PaletteRef::BuiltIn(get_system_default_palette()));
+            let palettes = player.movie.cast_manager.palettes();
+            // Only the set of colors matters, so a cycling palette reuses one table
+            let colors = (0..=255u8)
+                .map(|index| resolve_color_ref(&palettes, &ColorRef::PaletteIndex(index), &palette_ref))
+                .sorted()
+                .dedup()
+                .collect_vec();
+            if !self.stage_palette_tables.contains_key(&colors)
+                && self.stage_palette_tables.len() >= MAX_STAGE_PALETTE_TABLES
+            {
+                self.stage_palette_tables.clear();
+            }
+            self.stage_palette_tables
+                .entry(colors)
+                .or_insert_with_key(|colors| StagePaletteTable::new(colors))
+                .reduce(bitmap);
+        }
+
+        let now_ms = player.clock.elapsed_ms();
+        if player.zoom_box.as_ref().is_some_and(|zoom_box| zoom_box.is_done(now_ms)) {
+            player.zoom_box = None;
+        }
+        apply_stage_effects(
+            bitmap,
+            player.zoom_box.as_ref(),
+            player.stage_fade.as_ref(),
+            now_ms,
+            overscan,
+            &player.movie.cast_manager.palettes(),
+        );
 
         if let Some(font) = player.font_manager.get_system_font() {
             let font_bitmap = player.bitmap_manager.get_bitmap(font.bitmap_ref).unwrap();
-            let txt = format!("Datum count: {}\nScript count: {}", player.allocator.datum_count(), player.allocator.script_instance_count());
+            let gc_stats = &player.allocator.gc_stats;
+            let txt = format!(
+                "Datum count: {}\nScript count: {}\nGC runs: {} (last freed {} datums, {} scripts)",
+                player.allocator.datum_count(),
+                player.allocator.script_instance_count(),
+                gc_stats.collection_count,
+                gc_stats.last_freed_datums,
+                gc_stats.last_freed_script_instances,
+            );
+            let params = BitmapTextParams {
+                ink: 36,
+                bg_color: bitmap.get_bg_color_ref(),
+                line_spacing: 0,
+                style: FontStyle::default(),
+            };
             bitmap.draw_text(
                 txt.as_str(),
                 font, 
                 font_bitmap, 
-                0, 
-                0, 
-                36, 
-                bitmap.get_bg_color_ref(),
+                (0, 0),
                 &player.movie.cast_manager.palettes(), 
-                0, 
-                0
+                &params,
             );
         }
         let slice_data = Clamped(bitmap.data.as_slice());
@@ -390,13 +1284,51 @@ impl PlayerCanvasRenderer {
             bitmap.width.into(),
             bitmap.height.into(),
         );
-        self.ctx2d.set_fill_style(&JsValue::from_str("white"));
         match image_data {
             Ok(image_data) => {
-                self.ctx2d.put_image_data(&image_data, 0.0, 0.0).unwrap();
+                self.present_frame(&image_data);
             }
             _ => {}
         }
+        self.draw_windows(player);
+    }
+
+    /// Window movies are swapped into the player one at a time to be drawn like the stage.
+    fn draw_windows(&mut self, player: &mut DirPlayer) {
+        for (name, window_canvas) in self.window_canvases.iter_mut() {
+            let is_shown = player.window_manager.get(name).is_some_and(|window| {
+                window.is_open && window.visible && window.movie.is_some()
+            });
+            if !is_shown || activate_window_movie(player, Some(name.to_owned())).is_err() {
+                continue;
+            }
+            let width = player.movie.rect.width();
+            let height = player.movie.rect.height();
+            if window_canvas.bitmap.width != width as u16 || window_canvas.bitmap.height != height as u16 {
+                window_canvas.bitmap = Bitmap::new(
+                    width as u16,
+                    height as u16,
+                    32,
+                    PaletteRef::BuiltIn(get_system_default_palette()),
+                );
+                window_canvas.canvas.set_size(width as u32, height as u32);
+            }
+            let (r, g, b) = player.movie.stage_color;
+            let stage_bg_color = std::mem::replace(&mut player.bg_color, ColorRef::Rgb(r, g, b));
+            render_stage_to_bitmap(player, &mut window_canvas.bitmap, None, 0, &HashSet::new(), StageTrailsMode::Ignore, None);
+            player.bg_color = stage_bg_color;
+            restore_stage_movie(player);
+
+            let bitmap = &window_canvas.bitmap;
+            let image_data = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
+                Clamped(bitmap.data.as_slice()),
+                bitmap.width.into(),
+                bitmap.height.into(),
+            );
+            if let Ok(image_data) = image_data {
+                window_canvas.canvas.put_image_data(&image_data);
+            }
+        }
     }
 }
 
@@ -433,15 +1365,60 @@ pub fn player_set_preview_member_ref(cast_lib: i32, cast_num: i32) -> Result<(),
     Ok(())
 }
 
+/// Redraws the stage on the next draw tick, even while paused at a breakpoint.
+pub fn request_stage_redraw() {
+    with_canvas_renderer_mut(|renderer| {
+        if let Some(renderer) = renderer.as_mut() {
+            renderer.needs_redraw = true;
+        }
+    });
+}
+
 #[wasm_bindgen]
 pub fn player_set_debug_selected_channel(channel_num: i16) -> Result<(), JsValue> {
     with_canvas_renderer_mut(|renderer| {
-        renderer.as_mut().unwrap().debug_selected_channel_num = Some(channel_num );
+        let renderer = renderer.as_mut().unwrap();
+        renderer.debug_selected_channel_num = Some(channel_num);
+        renderer.needs_redraw = true;
     });
     JsApi::dispatch_channel_changed(channel_num);
     Ok(())
 }
 
+#[wasm_bindgen]
+pub fn player_set_debug_overscan(overscan: i32) -> Result<(), JsValue> {
+    with_canvas_renderer_mut(|renderer| {
+        let renderer = renderer.as_mut().unwrap();
+        renderer.debug_overscan = overscan.max(0);
+        renderer.needs_redraw = true;
+    });
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn player_set_debug_channel_muted(channel_num: i16, muted: bool) -> Result<(), JsValue> {
+    with_canvas_renderer_mut(|renderer| {
+        let renderer = renderer.as_mut().unwrap();
+        if muted {
+            renderer.debug_muted_channels.insert(channel_num);
+        } else {
+            renderer.debug_muted_channels.remove(&channel_num);
+        }
+        renderer.needs_redraw = true;
+    });
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn player_set_debug_solo_channel(channel_num: Option<i16>) -> Result<(), JsValue> {
+    with_canvas_renderer_mut(|renderer| {
+        let renderer = renderer.as_mut().unwrap();
+        renderer.debug_solo_channel_num = channel_num;
+        renderer.needs_redraw = true;
+    });
+    Ok(())
+}
+
 #[wasm_bindgen]
 pub fn player_set_preview_parent(parent_selector: &str) -> Result<(), JsValue> {
     if parent_selector.is_empty() {
@@ -478,79 +1455,51 @@ pub fn player_create_canvas() -> Result<(), JsValue> {
         .dyn_into::<web_sys::HtmlElement>()?;
 
     // Create renderer if it doesn't exist
-    with_canvas_renderer_mut(|renderer_lock| {
-        if renderer_lock.is_none() {
-            let canvas = web_sys::window()
-                .unwrap()
-                .document()
-                .unwrap()
-                .create_element("canvas")
-                .unwrap()
-                .dyn_into::<web_sys::HtmlCanvasElement>()
-                .unwrap();
-
-            let preview_canvas = web_sys::window()
-                .unwrap()
-                .document()
-                .unwrap()
-                .create_element("canvas")
-                .unwrap()
-                .dyn_into::<web_sys::HtmlCanvasElement>()
-                .unwrap();
-
-            // TODO: Set size from movie
-            let canvas_size = (720, 540);
-            canvas.set_width(canvas_size.0);
-            canvas.set_height(canvas_size.1);
-
-            preview_canvas.set_width(1);
-            preview_canvas.set_height(1);
-
-            canvas.style().set_property("image-rendering", "pixelated").unwrap_or(());
-            canvas.style().set_property("image-rendering", "-moz-crisp-edges").unwrap_or(());
-            canvas.style().set_property("image-rendering", "crisp-edges").unwrap_or(());
-
-            preview_canvas.style().set_property("image-rendering", "pixelated").unwrap_or(());
-            preview_canvas.style().set_property("image-rendering", "-moz-crisp-edges").unwrap_or(());
-            preview_canvas.style().set_property("image-rendering", "crisp-edges").unwrap_or(());
-
-            let ctx = canvas
-                .get_context("2d")
-                .unwrap()
-                .unwrap()
-                .dyn_into::<web_sys::CanvasRenderingContext2d>()
-                .unwrap();
-
-            let preview_ctx = preview_canvas
-                .get_context("2d")
-                .unwrap()
-                .unwrap()
-                .dyn_into::<web_sys::CanvasRenderingContext2d>()
-                .unwrap();
-
-            ctx.set_image_smoothing_enabled(false);
-            preview_ctx.set_image_smoothing_enabled(false);
-
-            let renderer = PlayerCanvasRenderer {
-                container_element: None,
-                preview_container_element: None,
-                canvas,
-                preview_canvas,
-                ctx2d: ctx,
-                preview_ctx2d: preview_ctx,
-                size: canvas_size,
-                preview_size: (1, 1),
-                preview_member_ref: None,
-                debug_selected_channel_num: None,
-                bitmap: Bitmap::new(1, 1, 32, PaletteRef::BuiltIn(get_system_default_palette())),
-            };
+    let is_renderer_created = with_canvas_renderer_mut(|renderer| renderer.is_some());
+    if !is_renderer_created {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let canvas = document
+            .create_element("canvas")
+            .unwrap()
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .unwrap();
 
-            *renderer_lock = Some(renderer);
-            spawn_local(async {
-                run_draw_loop().await;
-            });
-        }
-    });
+        let preview_canvas = document
+            .create_element("canvas")
+            .unwrap()
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .unwrap();
+
+        preview_canvas.set_width(1);
+        preview_canvas.set_height(1);
+
+        canvas.style().set_property("image-rendering", "pixelated").unwrap_or(());
+        canvas.style().set_property("image-rendering", "-moz-crisp-edges").unwrap_or(());
+        canvas.style().set_property("image-rendering", "crisp-edges").unwrap_or(());
+
+        preview_canvas.style().set_property("image-rendering", "pixelated").unwrap_or(());
+        preview_canvas.style().set_property("image-rendering", "-moz-crisp-edges").unwrap_or(());
+        preview_canvas.style().set_property("image-rendering", "crisp-edges").unwrap_or(());
+
+        let ctx = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+            .unwrap();
+
+        let preview_ctx = preview_canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+            .unwrap();
+
+        ctx.set_image_smoothing_enabled(false);
+        preview_ctx.set_image_smoothing_enabled(false);
+
+        init_canvas_renderer(StageCanvas::Element(canvas, ctx), Some((preview_canvas, preview_ctx)));
+    }
 
     with_canvas_renderer_mut(|renderer| {
         renderer
@@ -562,11 +1511,171 @@ pub fn player_create_canvas() -> Result<(), JsValue> {
     Ok(())
 }
 
-fn request_animation_frame(f: &Closure<dyn FnMut()>) {
-    web_sys::window()
+/// Sets the canvas a movie in a window is drawn to, usually in response to onWindowOpened.
+#[wasm_bindgen]
+pub fn player_set_window_canvas(window_name: &str, canvas: web_sys::HtmlCanvasElement) -> Result<(), JsValue> {
+    let ctx = canvas
+        .get_context("2d")?
+        .unwrap()
+        .dyn_into::<web_sys::CanvasRenderingContext2d>()?;
+    ctx.set_image_smoothing_enabled(false);
+    set_window_canvas(window_name, StageCanvas::Element(canvas, ctx));
+    Ok(())
+}
+
+/// Sets the canvas of a window when the player runs in a worker.
+#[wasm_bindgen]
+pub fn player_set_window_offscreen_canvas(window_name: &str, canvas: web_sys::OffscreenCanvas) -> Result<(), JsValue> {
+    let ctx = canvas
+        .get_context("2d")?
         .unwrap()
-        .request_animation_frame(f.as_ref().unchecked_ref())
-        .unwrap();
+        .dyn_into::<web_sys::OffscreenCanvasRenderingContext2d>()?;
+    ctx.set_image_smoothing_enabled(false);
+    set_window_canvas(window_name, StageCanvas::Offscreen(canvas, ctx));
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn player_remove_window_canvas(window_name: &str) -> Result<(), JsValue> {
+    with_canvas_renderer_mut(|renderer| {
+        if let Some(renderer) = renderer.as_mut() {
+            renderer.window_canvases.remove(window_name);
+        }
+    });
+    Ok(())
+}
+
+fn set_window_canvas(window_name: &str, canvas: StageCanvas) {
+    let window_canvas = WindowCanvas {
+        canvas,
+        bitmap: Bitmap::new(1, 1, 32, PaletteRef::BuiltIn(get_system_default_palette())),
+    };
+    with_canvas_renderer_mut(|renderer| {
+        if let Some(renderer) = renderer.as_mut() {
+            renderer.window_canvases.insert(window_name.to_owned(), window_canvas);
+        }
+    });
+}
+
+/// Creates the renderer for a canvas transferred to the worker the player runs in.
+/// The page keeps the canvas element and forwards input to the worker.
+#[wasm_bindgen]
+pub fn player_create_offscreen_canvas(canvas: web_sys::OffscreenCanvas) -> Result<(), JsValue> {
+    if with_canvas_renderer_mut(|renderer| renderer.is_some()) {
+        return Ok(());
+    }
+    let ctx = canvas
+        .get_context("2d")?
+        .unwrap()
+        .dyn_into::<web_sys::OffscreenCanvasRenderingContext2d>()?;
+    ctx.set_image_smoothing_enabled(false);
+    init_canvas_renderer(StageCanvas::Offscreen(canvas, ctx), None);
+    Ok(())
+}
+
+fn init_canvas_renderer(
+    canvas: StageCanvas,
+    preview: Option<(web_sys::HtmlCanvasElement, web_sys::CanvasRenderingContext2d)>,
+) {
+    // TODO: Set size from movie
+    let canvas_size = (720, 540);
+    canvas.set_size(canvas_size.0, canvas_size.1);
+    let (preview_canvas, preview_ctx2d) = preview.unzip();
+
+    let renderer = PlayerCanvasRenderer {
+        container_element: None,
+        preview_container_element: None,
+        canvas,
+        preview_canvas,
+        preview_ctx2d,
+        size: canvas_size,
+        preview_size: (1, 1),
+        preview_member_ref: None,
+        debug_selected_channel_num: None,
+        debug_overscan: 0,
+        debug_muted_channels: HashSet::new(),
+        debug_solo_channel_num: None,
+        needs_redraw: false,
+        static_layer: None,
+        bitmap: Bitmap::new(1, 1, 32, PaletteRef::BuiltIn(get_system_default_palette())),
+        window_canvases: HashMap::new(),
+        scale_mode: StageScaleMode::None,
+        device_pixel_ratio: 1.0,
+        movie_canvas: None,
+        stage_palette_tables: HashMap::new(),
+        cursor_image: None,
+    };
+
+    with_canvas_renderer_mut(|renderer_lock| {
+        *renderer_lock = Some(renderer);
+    });
+    spawn_local(async {
+        run_draw_loop().await;
+    });
+}
+
+#[wasm_bindgen]
+pub fn set_stage_scale_mode(mode: &str) -> Result<(), JsValue> {
+    let scale_mode = StageScaleMode::from_name(mode)
+        .ok_or_else(|| JsValue::from_str(&format!("Unknown stage scale mode {}", mode)))?;
+    with_canvas_renderer_mut(|renderer| {
+        if let Some(renderer) = renderer.as_mut() {
+            renderer.scale_mode = scale_mode;
+            renderer.needs_redraw = true;
+        }
+    });
+    Ok(())
+}
+
+/// Set by the page, since workers don't know the ratio of the display.
+#[wasm_bindgen]
+pub fn set_device_pixel_ratio(ratio: f64) {
+    with_canvas_renderer_mut(|renderer| {
+        if let Some(renderer) = renderer.as_mut() {
+            renderer.set_device_pixel_ratio(ratio);
+        }
+    });
+}
+
+pub fn set_renderer_stage_size(width: u32, height: u32) {
+    with_canvas_renderer_mut(|renderer| {
+        if let Some(renderer) = renderer.as_mut() {
+            renderer.set_size(width, height);
+        }
+    });
+}
+
+/// Maps a point on the stage element to the movie, or keeps it as is without a renderer.
+pub fn to_movie_point(x: f64, y: f64) -> (i32, i32) {
+    with_canvas_renderer_mut(|renderer| match renderer.as_ref() {
+        Some(renderer) => renderer.to_movie_point(x, y),
+        None => (x as i32, y as i32),
+    })
+}
+
+fn request_animation_frame(f: &js_sys::Function) {
+    match web_sys::window() {
+        Some(window) => window.request_animation_frame(f).unwrap(),
+        None => js_sys::global()
+            .unchecked_into::<web_sys::DedicatedWorkerGlobalScope>()
+            .request_animation_frame(f)
+            .unwrap(),
+    };
+}
+
+/// Resolves on the next animation frame, giving the browser a chance to paint.
+pub async fn next_animation_frame() {
+    let promise = js_sys::Promise::new(&mut |resolve, _| request_animation_frame(&resolve));
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+/// Composites and presents the stage right away, rather than waiting for the draw loop.
+pub fn draw_stage_now(player: &mut DirPlayer) {
+    with_canvas_renderer_mut(|renderer| {
+        if let Some(renderer) = renderer.as_mut() {
+            renderer.draw_frame(player);
+        }
+    });
 }
 
 async fn run_draw_loop() {
@@ -580,20 +1689,29 @@ async fn run_draw_loop() {
 
         if Local::now().timestamp_millis() - last_frame_ms >= 1000 / draw_fps as i64 {
             last_frame_ms = Local::now().timestamp_millis();
+            // While paused at a breakpoint the score is in the middle of being updated,
+            // so keep showing the last composited frame unless the debugger asks for a redraw.
+            // A window movie being told to holds the place of the stage movie, so the stage waits too.
+            // With the updateLock on, the stage keeps what it last drew until it's turned off or updateStage is called.
+            let is_paused = player.current_breakpoint.is_some()
+                || player.window_manager.active_window.is_some()
+                || player.movie.update_lock;
             with_canvas_renderer_mut(|renderer| {
                 let renderer = renderer.as_mut().unwrap();
-                renderer.draw_frame(&mut player);
+                if !is_paused || renderer.needs_redraw {
+                    renderer.draw_frame(&mut player);
+                }
                 renderer.draw_preview_frame(&player);
             });
         }
 
         let cb = rc.as_ref().borrow();
-        let cb = cb.as_ref().unwrap();
-        request_animation_frame(&cb);
+        let cb: &Closure<dyn FnMut()> = cb.as_ref().unwrap();
+        request_animation_frame(cb.as_ref().unchecked_ref());
     });
     rc_clone.replace(Some(cb));
 
     let cb = rc_clone.as_ref().borrow();
-    let cb = cb.as_ref().unwrap();
-    request_animation_frame(&cb);
+    let cb: &Closure<dyn FnMut()> = cb.as_ref().unwrap();
+    request_animation_frame(cb.as_ref().unchecked_ref());
 }
@@ -0,0 +1,14 @@
+use wasm_bindgen_test::wasm_bindgen_test_configure;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Starts the player the first time a test needs one.
+pub fn init_player() {
+  static INIT: std::sync::Once = std::sync::Once::new();
+  INIT.call_once(crate::start);
+}
+
+/// Runs a statement through the Lingo console and returns what it prints.
+pub async fn eval(source: &str) -> String {
+  crate::eval_lingo(source.to_string()).await.unwrap()
+}
@@ -1,7 +1,6 @@
-use chrono::{DateTime, Local, TimeDelta};
 use itertools::Itertools;
 use url::Url;
-use wasm_bindgen::JsValue;
+use wasm_bindgen::{JsCast, JsValue};
 
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
@@ -14,6 +13,50 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
+/// The global scope is a `Window` on the page and a `WorkerGlobalScope` when the
+/// player runs in a worker.
+pub fn worker_global_scope() -> web_sys::WorkerGlobalScope {
+    js_sys::global().unchecked_into()
+}
+
+/// Milliseconds with sub-millisecond precision where the browser allows it.
+pub fn performance_now() -> f64 {
+    js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("performance"))
+        .ok()
+        .and_then(|performance| performance.dyn_into::<web_sys::Performance>().ok())
+        .map_or_else(js_sys::Date::now, |performance| performance.now())
+}
+
+/// Whether the page (or the worker's script) was served over TLS.
+pub fn page_is_secure() -> bool {
+    js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("location"))
+        .and_then(|location| js_sys::Reflect::get(&location, &JsValue::from_str("protocol")))
+        .ok()
+        .and_then(|protocol| protocol.as_string())
+        .is_some_and(|protocol| protocol == "https:")
+}
+
+pub fn fetch_with_str(url: &str) -> js_sys::Promise {
+    match web_sys::window() {
+        Some(window) => window.fetch_with_str(url),
+        None => worker_global_scope().fetch_with_str(url),
+    }
+}
+
+pub fn fetch_with_request(request: &web_sys::Request) -> js_sys::Promise {
+    match web_sys::window() {
+        Some(window) => window.fetch_with_request(request),
+        None => worker_global_scope().fetch_with_request(request),
+    }
+}
+
+pub fn create_image_bitmap_with_blob(blob: &web_sys::Blob) -> Result<js_sys::Promise, JsValue> {
+    match web_sys::window() {
+        Some(window) => window.create_image_bitmap_with_blob(blob),
+        None => worker_global_scope().create_image_bitmap_with_blob(blob),
+    }
+}
+
 pub fn log_i(value: &str) {
     web_sys::console::log_1(&JsValue::from_str(value))
 }
@@ -60,14 +103,3 @@ impl ToHexString for Vec<u8> {
             .join(" ")
     }
 }
-
-/// Number of ticks (60 ticks/second) from epoch until endtime
-fn ticks_since_epoch(endtime: DateTime<Local>) -> i64 {
-  endtime.timestamp_nanos_opt().unwrap() / TimeDelta::milliseconds(1000 / 60).num_nanoseconds().unwrap()
-}
-
-pub fn get_elapsed_ticks(start_time: DateTime<chrono::Local>) -> i32 {
-  let current_ticks = ticks_since_epoch(chrono::Local::now());
-  let start_ticks = ticks_since_epoch(start_time);
-  (current_ticks - start_ticks) as i32
-}
\ No newline at end of file
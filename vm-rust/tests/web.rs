@@ -11,3 +11,122 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+/// Starts the player the first time a test needs one.
+fn init_player() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(vm_rust::start);
+}
+
+async fn eval(source: &str) -> String {
+    vm_rust::eval_lingo(source.to_string()).await.unwrap()
+}
+
+#[wasm_bindgen_test]
+async fn console_returns_the_formatted_result() {
+    init_player();
+    assert_eq!(eval("put 7").await, "7");
+    assert_eq!(eval("1 + 2 * 3").await, "7");
+    assert_eq!(eval("7 mod 4 - 8 / 2").await, "-1");
+    eval("gConsole = [#a: [1, 2]]").await;
+    assert_eq!(eval("gConsole.a").await, "[1, 2]");
+    assert_eq!(eval("1 < 2 and 0").await, "0");
+}
+
+#[wasm_bindgen_test]
+async fn save_state_round_trips_sound_channels() {
+    init_player();
+    eval("sound(2).volume = 100").await;
+    eval("sound(2).pan = -50").await;
+    let state = vm_rust::save_state().unwrap();
+    eval("sound(2).volume = 255").await;
+    eval("sound(2).pan = 0").await;
+    vm_rust::load_state(&state).unwrap();
+    assert_eq!(eval("sound(2).volume").await, "100");
+    assert_eq!(eval("sound(2).pan").await, "-50");
+}
+
+#[wasm_bindgen_test]
+async fn set_prop_at_renames_the_property_at_a_position() {
+    init_player();
+    eval("gPropList = [#a: 1, #b: 2]").await;
+    eval("setPropAt(gPropList, 2, #c)").await;
+    assert_eq!(eval("gPropList").await, "[#a: 1, #c: 2]");
+}
+
+#[wasm_bindgen_test]
+async fn set_prop_at_keeps_sorted_lists_in_order() {
+    init_player();
+    eval("gPropList = [#b: 2, #a: 1]").await;
+    eval("sort(gPropList)").await;
+    eval("gPropList.setPropAt(1, #z)").await;
+    assert_eq!(eval("gPropList").await, "[#b: 2, #z: 1]");
+    eval("gPropList.addProp(#c, 3)").await;
+    assert_eq!(eval("gPropList").await, "[#b: 2, #c: 3, #z: 1]");
+}
+
+#[wasm_bindgen_test]
+async fn set_prop_at_reports_positions_out_of_range() {
+    init_player();
+    eval("gPropList = [#a: 1]").await;
+    assert!(eval("setPropAt(gPropList, 2, #b)").await.starts_with("Script error: Index out of range"));
+    assert!(eval("setPropAt()").await.starts_with("Script error: Invalid number of arguments"));
+    assert_eq!(eval("gPropList").await, "[#a: 1]");
+}
+
+#[wasm_bindgen_test]
+async fn get_prop_at_reads_the_property_at_a_position() {
+    init_player();
+    eval("gPropList = [#a: 1, #b: 2]").await;
+    assert_eq!(eval("getPropAt(gPropList, 2)").await, "#b");
+}
+
+#[wasm_bindgen_test]
+async fn prop_list_lookups_take_the_first_duplicate() {
+    init_player();
+    eval("gPropList = [#a: 1, #a: 2]").await;
+    assert_eq!(eval("getaProp(gPropList, #a)").await, "1");
+    assert_eq!(eval("getProp(gPropList, #a)").await, "1");
+    assert_eq!(eval("findPos(gPropList, #a)").await, "1");
+    assert_eq!(eval("getPos(gPropList, 2)").await, "2");
+}
+
+#[wasm_bindgen_test]
+async fn sorted_prop_lists_order_symbol_and_string_keys() {
+    init_player();
+    eval("gPropList = [#b: 1, \"C\": 2, #a: 3]").await;
+    eval("sort(gPropList)").await;
+    assert_eq!(eval("gPropList").await, "[#a: 3, #b: 1, \"C\": 2]");
+    assert_eq!(eval("findPosNear(gPropList, #bb)").await, "3");
+}
+
+#[wasm_bindgen_test]
+async fn prop_list_lookups_ignore_the_case_of_symbols() {
+    init_player();
+    eval("gPropList = [#Foo: 1, #bar: 2]").await;
+    assert_eq!(eval("getaProp(gPropList, #foo)").await, "1");
+    assert_eq!(eval("getProp(gPropList, #foo)").await, "1");
+    assert_eq!(eval("findPos(gPropList, #foo)").await, "1");
+    assert_eq!(eval("findPos(gPropList, #BAR)").await, "2");
+    eval("gPropList = [#foo: 1, #Foo: 2]").await;
+    assert_eq!(eval("getaProp(gPropList, #FOO)").await, "1");
+}
+
+#[wasm_bindgen_test]
+async fn char_codes_round_trip_through_the_movie_encoding() {
+    init_player();
+    // Mac Roman until a movie says otherwise
+    assert_eq!(eval("charToNum(\"é\")").await, "142");
+    assert_eq!(eval("numToChar(142)").await, "\"é\"");
+    assert_eq!(eval("charToNum(numToChar(200))").await, "200");
+    assert_eq!(eval("charToNum(\"A\")").await, "65");
+}
+
+#[wasm_bindgen_test]
+async fn multiplying_strings_uses_the_number_they_hold() {
+    init_player();
+    assert_eq!(eval("\"3\" * 2").await, "6");
+    assert_eq!(eval("\" 3 \" * 2").await, "6");
+    assert_eq!(eval("2 * \"1.5\" = 3.0").await, "1");
+    assert_eq!(eval("\"abc\" * 2").await, "0");
+}